@@ -1,10 +1,12 @@
 use sqlparser::ast::Expr;
+use std::ops::Range;
 
 use super::indexes::*;
 use super::internal_index_page::*;
 use super::leaf_index_page::*;
 use crate::fileio::header::*;
 use crate::fileio::tableio::*;
+use crate::util::dbtype::Value;
 use crate::util::row::*;
 use crate::version_control::diff::{IndexCreateDiff, IndexRemoveDiff};
 
@@ -19,12 +21,15 @@ pub struct BTree {
 impl BTree {
     /// Create an index on one or more columns
     /// It automatically updates the table to include the index
+    /// A unique index fails to be created if the table already has duplicate keys,
+    /// and afterwards the table rejects rows that would duplicate a key.
     pub fn create_btree_index(
         table_dir: &String,
         table_name: &String,
         table_extension: Option<&String>, // Optionally specify a file extension. Defaults to TABLE_FILE_EXTENSION.
         columns: Vec<String>,
         index_name: String,
+        unique: bool,
     ) -> Result<(Self, IndexCreateDiff), String> {
         let mut table: Table = Table::new(table_dir, table_name, table_extension)?;
 
@@ -45,6 +50,21 @@ impl BTree {
         // Sort all the rows using the index key
         table_rows.sort_by(|a, b| compare_rows_using_index_id(&a.row, &b.row, &index_id));
 
+        if unique {
+            // Duplicate keys end up next to each other once the rows are sorted
+            for pair in table_rows.windows(2) {
+                let key: IndexKey = get_index_key_from_row(&pair[0].row, &index_id);
+                let is_null: bool = key.iter().any(|value| matches!(value, Value::Null(_)));
+                if !is_null && key == get_index_key_from_row(&pair[1].row, &index_id) {
+                    return Err(format!(
+                        "Cannot create unique index {}, the key {:?} appears more than once",
+                        index_name, key
+                    ));
+                }
+            }
+            table.unique_indexes.insert(index_id.clone());
+        }
+
         // Get the root internal index page
         let root_page: InternalIndexPage = Self::create_pages_for_btree(
             &mut table,
@@ -64,7 +84,8 @@ impl BTree {
             IndexCreateDiff {
                 table_name: table.name.clone(),
                 schema: table.schema.clone(),
-                indexes: vec![(index_name, index_id)],
+                indexes: vec![(index_name, index_id.clone())],
+                unique_indexes: if unique { vec![index_id] } else { vec![] },
             },
         ))
     }
@@ -91,19 +112,24 @@ impl BTree {
         if let Some(index_id) = index_id {
             // Remove the index from the table
             table.indexes.remove(&index_id);
+            let unique: bool = table.unique_indexes.remove(&index_id);
 
             // Update the header
             let new_header: Header = Header {
                 num_pages: table.max_pages,
                 schema: table.schema.clone(),
                 index_top_level_pages: table.indexes.clone(),
+                unique_indexes: table.unique_indexes.clone(),
+                ttl: table.ttl,
+                version: table.get_version(),
             };
             write_header(&table.path, &new_header)?;
 
             Ok(IndexRemoveDiff {
                 table_name: table.name.clone(),
                 schema: table.schema.clone(),
-                indexes: vec![(index_name.clone(), index_id)],
+                indexes: vec![(index_name.clone(), index_id.clone())],
+                unique_indexes: if unique { vec![index_id] } else { vec![] },
             })
         } else {
             Err(format!("Index {} does not exist", index_name))
@@ -145,6 +171,31 @@ impl BTree {
         self.root_page.get_rows_matching_expr(pred)
     }
 
+    /// Counts the rows stored under the given index key, without reading the rows
+    pub fn count_rows(&self, index_key: &IndexKey) -> Result<usize, String> {
+        Ok(self.root_page.get_row_locations_from_key(index_key)?.len())
+    }
+
+    /// Counts the rows matching the given predicate, without reading the rows
+    pub fn count_rows_matching_expr(&self, pred: &Expr) -> Result<usize, String> {
+        Ok(self.root_page.get_row_locations_matching_expr(pred)?.len())
+    }
+
+    /// Gets every key in the btree, along with the location of the row it points to
+    pub fn get_all_key_locations(&self) -> Result<Vec<(IndexKey, RowLocation)>, String> {
+        Ok(self
+            .root_page
+            .get_all_key_values()?
+            .into_iter()
+            .map(|(key, value)| (key, value.to_row_location()))
+            .collect())
+    }
+
+    /// Counts the pages that the btree is made of, both internal and leaf pages
+    pub fn count_pages(&self) -> Result<usize, String> {
+        self.root_page.count_pages()
+    }
+
     /// Inserts rows into the btree
     pub fn insert_rows(&mut self, rows: &Vec<RowInfo>) -> Result<(), String> {
         for row in rows {
@@ -238,6 +289,9 @@ impl BTree {
                 num_pages: table.max_pages,
                 schema: table.schema.clone(),
                 index_top_level_pages: table.indexes.clone(),
+                unique_indexes: table.unique_indexes.clone(),
+                ttl: table.ttl,
+                version: table.get_version(),
             };
             write_header(&table.path, &new_header)?;
 
@@ -253,9 +307,11 @@ impl BTree {
             LeafIndexPage::get_max_index_pointers_per_page(&index_key_type);
 
         // Calculate how many leaf pages we need to store all the rows
-        // Double it because we want to keep the leaves between 50% and 100% full
+        // Double it because we want to keep the leaves between 50% and 100% full,
+        // but never use more leaf pages than rows, since every leaf page needs a key
         let num_leaf_pages: u32 =
-            ((num_rows as f64 / max_pointers_per_leaf as f64).ceil() as u32) * 2;
+            (((num_rows as f64 / max_pointers_per_leaf as f64).ceil() as u32) * 2)
+                .min(num_rows as u32);
         num_pages_per_level.push(num_leaf_pages);
 
         // Calculate how many pointers we can fit on a single internal page
@@ -265,10 +321,15 @@ impl BTree {
         // Calculate how many internal nodes we need to point to all the leaf pages
         let mut pages_on_level_below: u32 = num_leaf_pages;
         loop {
-            // Divide the max_pointers_per_internal by 2 because we want to keep the internal nodes between 50% and 100% full
-            let num_internal_pages_for_level: u32 = (pages_on_level_below as f64
-                / (max_pointers_per_internal as f64 / 2 as f64))
-                .ceil() as u32;
+            // Divide the max_pointers_per_internal by 2 because we want to keep the internal nodes between 50% and 100% full.
+            // The root can be any amount full, so the pages below go under it once they fit on one page.
+            let num_internal_pages_for_level: u32 =
+                if pages_on_level_below as usize <= max_pointers_per_internal {
+                    1
+                } else {
+                    (pages_on_level_below as f64 / (max_pointers_per_internal as f64 / 2 as f64))
+                        .ceil() as u32
+                };
             num_pages_per_level.push(num_internal_pages_for_level);
             if num_internal_pages_for_level == 1 {
                 break;
@@ -289,22 +350,11 @@ impl BTree {
             leaf_pages.push(leaf_page);
         }
 
-        // Fill the leaf pages with the rows
-        let mut leaf_page_idx: usize = 0;
-        let mut rows_in_leaf_page: usize = 0;
-        for (i, (key, val)) in key_values.iter().enumerate() {
-            leaf_pages[leaf_page_idx].add_pointer_to_leaf_value(key, val.clone())?;
-            rows_in_leaf_page += 1;
-            // Evenly distribute the rows across the leaf pages
-            let num_leaf_pages_unfilled: usize = (leaf_pages.len() - leaf_page_idx) - 1;
-            if rows_in_leaf_page > (num_rows / leaf_pages.len())
-                || i == (num_rows - 1) - num_leaf_pages_unfilled
-            {
-                // Advance the leaf page idx up until the last leaf page
-                if leaf_page_idx < (leaf_pages.len() - 1) {
-                    leaf_page_idx += 1;
-                }
-                rows_in_leaf_page = 0;
+        // Fill the leaf pages with the rows, evenly distributing the rows across the leaf pages
+        for (i, leaf_page) in leaf_pages.iter_mut().enumerate() {
+            let rows_for_page = Self::even_split(num_rows, num_leaf_pages as usize, i);
+            for (key, val) in &key_values[rows_for_page] {
+                leaf_page.add_pointer_to_leaf_value(key, val.clone())?;
             }
         }
 
@@ -329,72 +379,42 @@ impl BTree {
         // Create the internal pages for each level starting from the bottom
         let mut internal_pages: Vec<Vec<InternalIndexPage>> = Vec::new();
         for level in 1..num_pages_per_level.len() {
+            let num_pages_for_level: usize = num_pages_per_level[level] as usize;
             let mut internal_pages_for_level: Vec<InternalIndexPage> = Vec::new();
-            let mut internal_page_idx: usize = 0;
-            let mut num_pointers_in_page: Option<usize> = None;
-            let num_pages_on_level_below: usize = pages_on_level_below.len();
-            // Insert each page on the level below into an internal page within this level
-            for (page_below, page_below_lowest_key) in pages_on_level_below {
-                // Create a new internal page if we need to
-                if num_pointers_in_page.is_none() {
-                    let internal_page: InternalIndexPage = InternalIndexPage::new(
-                        &table,
-                        table.max_pages,
-                        &index_id,
-                        &index_key_type,
+            let mut pages_on_this_level: Vec<(u32, IndexKey)> = Vec::new();
+            // Evenly distribute the pages on the level below across the pages on this level
+            for i in 0..num_pages_for_level {
+                let pages_for_page =
+                    Self::even_split(pages_on_level_below.len(), num_pages_for_level, i);
+                let pages_below: &[(u32, IndexKey)] = &pages_on_level_below[pages_for_page];
+
+                // The first page below is the leftmost pointer, so it doesn't need a key
+                let mut internal_page: InternalIndexPage = InternalIndexPage::new(
+                    &table,
+                    table.max_pages,
+                    &index_id,
+                    &index_key_type,
+                    &InternalIndexValue {
+                        pagenum: pages_below[0].0,
+                    },
+                    level as u8,
+                )?;
+                table.max_pages += 1;
+                for (page_below, page_below_lowest_key) in &pages_below[1..] {
+                    internal_page.add_pointer_to_page(
+                        page_below_lowest_key,
                         &InternalIndexValue {
-                            pagenum: page_below,
+                            pagenum: *page_below,
                         },
-                        level as u8,
                     )?;
-                    table.max_pages += 1;
-                    num_pointers_in_page = Some(1);
-                    internal_pages_for_level.push(internal_page);
                 }
-                // Insert the page on the level below into the current internal page
-                else {
-                    internal_pages_for_level[internal_page_idx].add_pointer_to_page(
-                        &page_below_lowest_key,
-                        &InternalIndexValue {
-                            pagenum: page_below,
-                        },
-                    )?;
-                    num_pointers_in_page = Some(num_pointers_in_page.unwrap() + 1);
-
-                    // If the internal page is bet, advance to the next one
-                    if num_pointers_in_page.unwrap()
-                        > (num_pages_on_level_below / num_pages_per_level[level] as usize)
-                    {
-                        num_pointers_in_page = None;
-                        internal_page_idx += 1;
-                        // Advance the page idx up until the last page
-                        if internal_page_idx < (num_pages_per_level[level] - 1) as usize {
-                            internal_page_idx += 1;
-                        }
-                    }
-                }
-            }
-            // We should have filled part of every page on this level
-            assert_eq!(
-                internal_page_idx,
-                (num_pages_per_level[level] - 1) as usize,
-                "Internal Index Pages on level {} were not all filled",
-                level
-            );
 
-            // Update pages_on_level_below with the pages on this level
-            pages_on_level_below = Vec::new();
-            for internal_page in internal_pages_for_level.clone() {
-                if let Some(smallest_key) = internal_page.get_lowest_index_key() {
-                    pages_on_level_below.push((internal_page.get_pagenum(), smallest_key));
-                } else {
-                    return Err(format!(
-                        "Internal page {} has no index keys",
-                        internal_page.get_pagenum()
-                    ));
-                }
+                // The lowest key under this page is the lowest key under its leftmost pointer
+                pages_on_this_level.push((internal_page.get_pagenum(), pages_below[0].1.clone()));
+                internal_pages_for_level.push(internal_page);
             }
 
+            pages_on_level_below = pages_on_this_level;
             internal_pages.push(internal_pages_for_level);
         }
 
@@ -415,12 +435,26 @@ impl BTree {
             num_pages: table.max_pages,
             schema: table.schema.clone(),
             index_top_level_pages: table.indexes.clone(),
+            unique_indexes: table.unique_indexes.clone(),
+            ttl: table.ttl,
+            version: table.get_version(),
         };
         write_header(&table.path, &new_header)?;
 
         // Get the root internal index page
         Ok(root_page)
     }
+
+    /// Gets the range of items that belong to the given part when `len` items are split into
+    /// `parts` parts whose sizes differ by at most one.
+    fn even_split(len: usize, parts: usize, part: usize) -> Range<usize> {
+        (part * len / parts)..((part + 1) * len / parts)
+    }
+
+    /// Gets the depth of the tree, which is 1 when the root page points directly to the leaves
+    pub fn get_depth(&self) -> u8 {
+        self.root_page.get_page_depth()
+    }
 }
 
 #[cfg(test)]
@@ -491,6 +525,7 @@ mod tests {
             None,
             index_column_names,
             index_name,
+            false,
         )
         .unwrap();
 
@@ -617,6 +652,7 @@ mod tests {
             None,
             index_column_names,
             index_name,
+            false,
         )
         .unwrap();
 
@@ -1003,6 +1039,7 @@ mod tests {
             None,
             vec!["id1".to_string()],
             index_name,
+            false,
         )
         .unwrap();
 
@@ -1068,6 +1105,7 @@ mod tests {
             None,
             vec!["id1".to_string()],
             index_name,
+            false,
         )
         .unwrap();
 
@@ -1144,6 +1182,7 @@ mod tests {
             None,
             index_column_names,
             index_name,
+            false,
         )
         .unwrap();
 
@@ -1213,6 +1252,131 @@ mod tests {
         cleanup_test();
     }
 
+    #[test]
+    #[serial]
+    fn test_small_node_capacity() {
+        let table_dir: String = String::from("./testing");
+        let table_name: String = String::from("small_node_test_table");
+        let table_schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(10)),
+        ];
+        let make_rows = |ids: std::ops::Range<i32>| -> Vec<Row> {
+            ids.map(|id| vec![Value::I32(id), Value::String(format!("n{}", id))])
+                .collect()
+        };
+
+        // Create the table with 30 rows
+        let mut table: Table = create_table_in_dir(&table_name, &table_schema, &table_dir)
+            .unwrap()
+            .0;
+        let insert_diff: InsertDiff = table.insert_rows(make_rows(0..30)).unwrap();
+
+        // Only let 3 pointers fit on each index page, so the tree needs several levels
+        assert!(set_node_capacity(Some(2)).is_err());
+        set_node_capacity(Some(3)).unwrap();
+        assert_eq!(
+            LeafIndexPage::get_max_index_pointers_per_page(&vec![Column::I32]),
+            3
+        );
+
+        let (btree, _): (BTree, IndexCreateDiff) = BTree::create_btree_index(
+            &table_dir,
+            &table_name,
+            None,
+            vec!["id".to_string()],
+            "small_index".to_string(),
+            false,
+        )
+        .unwrap();
+        assert!(btree.get_depth() > 1);
+
+        // Every key should still lead to its row
+        for rowinfo in &insert_diff.rows {
+            let rows: Vec<RowInfo> = btree.get_rows(&vec![rowinfo.row[0].clone()]).unwrap();
+            assert_eq!(rows, vec![rowinfo.clone()]);
+        }
+        assert_eq!(btree.get_rows(&vec![Value::I32(100)]).unwrap().len(), 0);
+
+        // Inserting and removing rows splits and merges the pages
+        let mut table: Table = Table::new(&table_dir, &table_name, None).unwrap();
+        let insert_diff: InsertDiff = table.insert_rows(make_rows(30..60)).unwrap();
+        table
+            .remove_rows(
+                insert_diff
+                    .rows
+                    .iter()
+                    .filter(|rowinfo| rowinfo.row[0] < Value::I32(40))
+                    .map(|rowinfo| rowinfo.get_row_location())
+                    .collect(),
+            )
+            .unwrap();
+
+        // Reload the tree, since its root may have moved
+        let table: Table = Table::new(&table_dir, &table_name, None).unwrap();
+        let btree: BTree = BTree::load_btree_from_root_page(
+            &table,
+            table.indexes.get(&vec![0]).unwrap().0,
+            vec![0],
+            vec![Column::I32],
+            "small_index".to_string(),
+        )
+        .unwrap();
+        assert!(btree.get_depth() > 1);
+        for id in 0..60 {
+            let rows: Vec<RowInfo> = btree.get_rows(&vec![Value::I32(id)]).unwrap();
+            let expected: usize = if (30..40).contains(&id) { 0 } else { 1 };
+            assert_eq!(rows.len(), expected, "wrong rows for key {}", id);
+        }
+
+        // Clean up
+        set_node_capacity(None).unwrap();
+        cleanup_test();
+    }
+
+    #[test]
+    #[serial]
+    fn test_small_node_capacity_levels() {
+        let table_dir: String = String::from("./testing");
+        let table_schema: Schema = vec![("id".to_string(), Column::I32)];
+        set_node_capacity(Some(3)).unwrap();
+
+        // Each table gets twice as many leaves as it needs, and a level of internal pages goes
+        // on top of the one below it until that one fits under a single root
+        for (num_rows, depth) in [(3, 1), (4, 2), (30, 7)] {
+            let table_name: String = format!("levels_test_table_{}", num_rows);
+            let mut table: Table = create_table_in_dir(&table_name, &table_schema, &table_dir)
+                .unwrap()
+                .0;
+            let insert_diff: InsertDiff = table
+                .insert_rows((0..num_rows).map(|id| vec![Value::I32(id)]).collect())
+                .unwrap();
+            let (btree, _): (BTree, IndexCreateDiff) = BTree::create_btree_index(
+                &table_dir,
+                &table_name,
+                None,
+                vec!["id".to_string()],
+                "levels_index".to_string(),
+                false,
+            )
+            .unwrap();
+            assert_eq!(
+                btree.get_depth(),
+                depth,
+                "wrong depth for {} rows",
+                num_rows
+            );
+            for rowinfo in &insert_diff.rows {
+                let rows: Vec<RowInfo> = btree.get_rows(&vec![rowinfo.row[0].clone()]).unwrap();
+                assert_eq!(rows, vec![rowinfo.clone()]);
+            }
+        }
+
+        // Clean up
+        set_node_capacity(None).unwrap();
+        cleanup_test();
+    }
+
     fn setup_test() -> (Table, BTree, InsertDiff) {
         let table_dir: String = String::from("./testing");
         let index_name: String = String::from("test_index");
@@ -1255,6 +1419,7 @@ mod tests {
             None,
             index_column_names,
             index_name,
+            false,
         )
         .unwrap();
 
@@ -1,4 +1,5 @@
 use sqlparser::ast::{BinaryOperator, Expr, UnaryOperator};
+use std::cell::Cell;
 use std::cmp::Ordering;
 use std::mem::size_of;
 
@@ -9,6 +10,14 @@ use crate::fileio::pageio::*;
 use crate::util::dbtype::*;
 use crate::util::row::{Row, RowLocation};
 
+thread_local! {
+    // The most pointers an index page may hold on this thread, see set_node_capacity()
+    static NODE_CAPACITY: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// The smallest node capacity that still lets each level of a btree have fewer pages than the level below it
+pub const MIN_NODE_CAPACITY: usize = 3;
+
 /// The vector of column indices that make up the index
 /// For example, if the index is on columns 1, 3, and 4, then this would be [1, 3, 4]
 pub type IndexID = Vec<u8>;
@@ -68,10 +77,43 @@ impl LeafIndexValue {
     }
 }
 
+/*************************************************************************************************/
+/*                                Set Methods                                                    */
+/*************************************************************************************************/
+
+/// Limits how many pointers an index page may hold on the current thread, instead of however
+/// many fit on a page. It lets a handful of rows build a btree with several levels, so it's
+/// meant for testing how pages split and merge. None goes back to the page-derived capacity.
+pub fn set_node_capacity(capacity: Option<usize>) -> Result<(), String> {
+    if let Some(capacity) = capacity {
+        if capacity < MIN_NODE_CAPACITY {
+            return Err(format!(
+                "Node capacity must be at least {}, but was {}",
+                MIN_NODE_CAPACITY, capacity
+            ));
+        }
+    }
+    NODE_CAPACITY.with(|cell| cell.set(capacity));
+    Ok(())
+}
+
 /*************************************************************************************************/
 /*                                Get Methods                                                    */
 /*************************************************************************************************/
 
+/// Gets the node capacity set by set_node_capacity() on the current thread, if any
+pub fn get_node_capacity() -> Option<usize> {
+    NODE_CAPACITY.with(|cell| cell.get())
+}
+
+/// Caps the number of pointers that fit on a page at the node capacity, if one is set
+pub fn limit_to_node_capacity(fits_on_page: usize) -> usize {
+    match get_node_capacity() {
+        Some(capacity) => fits_on_page.min(capacity),
+        None => fits_on_page,
+    }
+}
+
 /// get_index_key_type_size returns the size of the index key type in bytes
 pub fn get_index_key_type_size(index_key_type: &IndexKeyType) -> usize {
     index_key_type.iter().map(|col| col.size() as usize).sum()
@@ -192,6 +234,7 @@ pub fn get_index_id_from_expr(
             | BinaryOperator::LtEq
             | BinaryOperator::Eq
             | BinaryOperator::NotEq
+            | BinaryOperator::Spaceship
             | BinaryOperator::Plus
             | BinaryOperator::Minus
             | BinaryOperator::Divide
@@ -264,6 +307,16 @@ pub fn get_index_id_from_expr(
             index_refs,
             table_name,
         )?),
+        // Comparisons against a set of values or a function's result, and pattern matches,
+        // can't use an index
+        Expr::AllOp(_)
+        | Expr::AnyOp(_)
+        | Expr::InList { .. }
+        | Expr::Like { .. }
+        | Expr::ILike { .. }
+        | Expr::Function(_)
+        | Expr::Substring { .. }
+        | Expr::Position { .. } => Ok(None),
         _ => Err(format!("Invalid Predicate Clause: {}", expr)),
     }
 }
@@ -145,6 +145,11 @@ impl InternalIndexPage {
         self.pagenum
     }
 
+    /// Gets the depth of this page in the btree, where 1 is just above the leaves.
+    pub fn get_page_depth(&self) -> u8 {
+        self.page_depth
+    }
+
     /// Gets the lowest valued index key in the page.
     pub fn get_lowest_index_key(&self) -> Option<IndexKey> {
         for key in self.index_keys.clone() {
@@ -153,6 +158,34 @@ impl InternalIndexPage {
         None
     }
 
+    /// Counts the pages of the tree below this page, including this page and the leaf pages.
+    pub fn count_pages(&self) -> Result<usize, String> {
+        let mut num_pages: usize = 1;
+        let mut pagenums_on_level: Vec<u32> = self
+            .index_values
+            .iter()
+            .map(|value| value.pagenum)
+            .collect();
+        for _ in 1..self.page_depth {
+            num_pages += pagenums_on_level.len();
+            let mut pagenums_below: Vec<u32> = Vec::new();
+            for pagenum in pagenums_on_level {
+                let i_page: InternalIndexPage = InternalIndexPage::load_from_table(
+                    self.table_path.clone(),
+                    self.table_name.clone(),
+                    self.table_schema.clone(),
+                    pagenum,
+                    &self.index_id,
+                    &self.index_key_type,
+                )?;
+                pagenums_below.extend(i_page.index_values.iter().map(|value| value.pagenum));
+            }
+            pagenums_on_level = pagenums_below;
+        }
+        // The last level is the leaf pages
+        Ok(num_pages + pagenums_on_level.len())
+    }
+
     /// Inserts a row into the leaf page following all the pointers from this page down to the leaves.
     pub fn insert_row(&mut self, rowinfo: &RowInfo, index_name: String) -> Result<(), String> {
         let index_key: IndexKey = get_index_key_from_row(&rowinfo.row, &self.index_id);
@@ -307,6 +340,15 @@ impl InternalIndexPage {
 
     /// Gets the rows that match the specified expression.
     pub fn get_rows_matching_expr(&self, expr: &Expr) -> Result<Vec<RowInfo>, String> {
+        let row_locations: Vec<RowLocation> = self.get_row_locations_matching_expr(expr)?;
+
+        // Read the rows from the row locations
+        Ok(self.read_rowinfos_from_locations(&row_locations)?)
+    }
+
+    /// Gets the locations of the rows that match the specified expression, sorted by page.
+    /// Only the index pages are read, not the rows themselves.
+    pub fn get_row_locations_matching_expr(&self, expr: &Expr) -> Result<Vec<RowLocation>, String> {
         // Get the leaf page numbers we need to search
         let column_aliases: ColumnAliases = gen_column_aliases_from_schema(&vec![(
             self.table_schema.clone(),
@@ -348,13 +390,61 @@ impl InternalIndexPage {
 
         // Sort the row_locations by pagenum so we reduce the number of page reads we need
         row_locations.sort_by(|a, b| a.pagenum.cmp(&b.pagenum));
+        Ok(row_locations)
+    }
 
-        // Read the rows from the row locations
-        Ok(self.read_rowinfos_from_locations(&row_locations)?)
+    /// Gets every key and value in the leaf pages below this page.
+    /// Only the index pages are read, not the rows themselves.
+    pub fn get_all_key_values(&self) -> Result<Vec<(IndexKey, LeafIndexValue)>, String> {
+        // Follow every pointer down to the lowest level internal pages
+        let mut lowest_internal_pages: Vec<InternalIndexPage> = vec![self.clone()];
+        for _ in 1..self.page_depth {
+            let mut pages_below: Vec<InternalIndexPage> = Vec::new();
+            for page in &lowest_internal_pages {
+                for value in &page.index_values {
+                    pages_below.push(InternalIndexPage::load_from_table(
+                        self.table_path.clone(),
+                        self.table_name.clone(),
+                        self.table_schema.clone(),
+                        value.pagenum,
+                        &self.index_id,
+                        &self.index_key_type,
+                    )?);
+                }
+            }
+            lowest_internal_pages = pages_below;
+        }
+
+        // Get the keys and values from the leaf pages below the lowest level internal pages
+        let mut key_values: Vec<(IndexKey, LeafIndexValue)> = Vec::new();
+        for page in lowest_internal_pages {
+            for value in &page.index_values {
+                let leaf_page: LeafIndexPage = LeafIndexPage::load_from_table(
+                    self.table_path.clone(),
+                    value.pagenum,
+                    &self.index_id,
+                    &self.index_key_type,
+                )?;
+                key_values.extend(leaf_page.get_all_key_values());
+            }
+        }
+        Ok(key_values)
     }
 
     /// Gets the rows that are stored from the specific index key
     pub fn get_rows_from_key(&self, index_key: &IndexKey) -> Result<Vec<RowInfo>, String> {
+        let row_locations: Vec<RowLocation> = self.get_row_locations_from_key(index_key)?;
+
+        // Read the rows from the row locations
+        Ok(self.read_rowinfos_from_locations(&row_locations)?)
+    }
+
+    /// Gets the locations of the rows that are stored from the specific index key, sorted by page.
+    /// Only the index pages are read, not the rows themselves.
+    pub fn get_row_locations_from_key(
+        &self,
+        index_key: &IndexKey,
+    ) -> Result<Vec<RowLocation>, String> {
         let leaf_pagenums: HashSet<u32> = self.get_leaf_pagenums_from_key(index_key)?;
 
         // Get all the row locations we need to read the rows from
@@ -373,9 +463,7 @@ impl InternalIndexPage {
 
         // Sort the row_locations by pagenum so we reduce the number of page reads we need
         row_locations.sort_by(|a, b| a.pagenum.cmp(&b.pagenum));
-
-        // Read the rows from the row locations
-        Ok(self.read_rowinfos_from_locations(&row_locations)?)
+        Ok(row_locations)
     }
 
     /***********************************************************************************************/
@@ -390,7 +478,7 @@ impl InternalIndexPage {
         let num_idx_val_pairs: usize =
             (PAGE_SIZE - INTERNAL_PAGE_HEADER_SIZE - InternalIndexValue::size())
                 / idx_and_value_size;
-        num_idx_val_pairs + 1
+        limit_to_node_capacity(num_idx_val_pairs + 1)
     }
 
     /***********************************************************************************************/
@@ -399,6 +487,12 @@ impl InternalIndexPage {
 
     /// Returns true if there is room for another index and value in this page.
     fn has_room(&self) -> bool {
+        if let Some(capacity) = get_node_capacity() {
+            if self.index_values.len() >= capacity {
+                return false;
+            }
+        }
+
         let all_keys_size: usize = self.index_keys.len() * self.key_size as usize;
         let all_values_size: usize = self.index_values.len() * InternalIndexValue::size();
         let combined_size: usize = all_keys_size + all_values_size;
@@ -570,48 +664,11 @@ impl InternalIndexPage {
             }
         }
 
-        // If we don't have any values, we need to check the last value
+        // When no key matches, the matching rows could still be in any of the pages below this
+        // one, like those below every key for `x < 1`, or between two keys for `x > 1 AND x < 2`.
+        // The leaf pages filter the rows themselves, so all of them are searched.
         if values.len() == 0 {
-            // Get the last index key following all the rightmost pointers from index pages below this one
-            let mut lowest_internal_pagenum: u32 = self.pagenum;
-            for _ in 1..self.page_depth {
-                let lowest_page: InternalIndexPage = InternalIndexPage::load_from_table(
-                    self.table_path.clone(),
-                    self.table_name.clone(),
-                    self.table_schema.clone(),
-                    lowest_internal_pagenum,
-                    &self.index_id,
-                    &self.index_key_type,
-                )
-                .unwrap();
-                lowest_internal_pagenum = lowest_page.index_values.last().unwrap().pagenum;
-            }
-
-            // Read the lowest internal page
-            let lowest_internal_page: InternalIndexPage = InternalIndexPage::load_from_table(
-                self.table_path.clone(),
-                self.table_name.clone(),
-                self.table_schema.clone(),
-                lowest_internal_pagenum,
-                &self.index_id,
-                &self.index_key_type,
-            )
-            .unwrap();
-
-            // Get the leaf page from the lowest internal page
-            let leaf_page: LeafIndexPage = LeafIndexPage::load_from_table(
-                self.table_path.clone(),
-                lowest_internal_page.index_values.last().unwrap().pagenum,
-                &self.index_id,
-                &self.index_key_type,
-            )
-            .unwrap();
-
-            let rightmost_index_key: Option<IndexKey> = leaf_page.get_largest_index_key();
-
-            if rightmost_index_key.is_some() && pred_solver(&rightmost_index_key.unwrap())? {
-                values.insert(self.index_values.last().unwrap().clone());
-            }
+            values.extend(self.index_values.iter().cloned());
         }
 
         Ok(values)
@@ -825,7 +882,9 @@ impl InternalIndexPage {
                         Ok(left.ge(&right))
                     })))
                 }
-                BinaryOperator::NotEq => Ok(Some(Box::new(move |_| Ok(true)))),
+                BinaryOperator::NotEq | BinaryOperator::Spaceship => {
+                    Ok(Some(Box::new(move |_| Ok(true))))
+                }
                 // Create functions for the LHS and RHS of the 'and' operation, and then
                 // combine them into a single function that returns true if both functions return true
                 // Note how this would also indirectly handle short-circuiting
@@ -873,25 +932,17 @@ impl InternalIndexPage {
                 }
                 _ => Err(format!("Unsupported binary operator for Predicate: {}", op)),
             },
-            Expr::UnaryOp { op, expr } => match op {
-                UnaryOperator::Not => {
-                    let expr = Self::solve_internal_index_predicate(
-                        expr,
-                        column_aliases,
-                        index_refs,
-                        index_id,
-                    )?;
-                    if expr.is_none() {
-                        return Ok(None);
-                    }
-                    let expr = expr.unwrap();
-                    Ok(Some(Box::new(move |row| Ok(!expr(row)?))))
-                }
+            Expr::UnaryOp { op, .. } => match op {
+                // The inner condition can match more rows than it should, like `x != 1` does,
+                // so its negation would miss rows. It can't be used to narrow down the index.
+                UnaryOperator::Not => Ok(None),
                 _ => Err(format!("Unsupported unary operator for Predicate: {}", op)),
             },
             Expr::Nested(pred) => {
                 Self::solve_internal_index_predicate(pred, column_aliases, index_refs, index_id)
             }
+            // Membership in a set of values, or a pattern match, can't narrow down the index
+            Expr::InList { .. } | Expr::Like { .. } | Expr::ILike { .. } => Ok(None),
             _ => Err(format!("Invalid Predicate Clause: {}", pred)),
         }
     }
@@ -1053,7 +1104,9 @@ impl InternalIndexPage {
                         Ok(left.eq(&right))
                     })))
                 }
-                BinaryOperator::NotEq => Ok(Some(Box::new(move |_| Ok(true)))),
+                BinaryOperator::NotEq | BinaryOperator::Spaceship => {
+                    Ok(Some(Box::new(move |_| Ok(true))))
+                }
                 // Create functions for the LHS and RHS of the 'and' operation, and then
                 // combine them into a single function that returns true if both functions return true
                 // Note how this would also indirectly handle short-circuiting
@@ -1101,25 +1154,17 @@ impl InternalIndexPage {
                 }
                 _ => Err(format!("Unsupported binary operator for Predicate: {}", op)),
             },
-            Expr::UnaryOp { op, expr } => match op {
-                UnaryOperator::Not => {
-                    let expr = Self::solve_leaf_index_predicate(
-                        expr,
-                        column_aliases,
-                        index_refs,
-                        index_id,
-                    )?;
-                    if expr.is_none() {
-                        return Ok(None);
-                    }
-                    let expr = expr.unwrap();
-                    Ok(Some(Box::new(move |row| Ok(!expr(row)?))))
-                }
+            Expr::UnaryOp { op, .. } => match op {
+                // The inner condition can match more rows than it should, like `x != 1` does,
+                // so its negation would miss rows. It can't be used to narrow down the index.
+                UnaryOperator::Not => Ok(None),
                 _ => Err(format!("Unsupported unary operator for Predicate: {}", op)),
             },
             Expr::Nested(pred) => {
                 Self::solve_internal_index_predicate(pred, column_aliases, index_refs, index_id)
             }
+            // Membership in a set of values, or a pattern match, can't narrow down the index
+            Expr::InList { .. } | Expr::Like { .. } | Expr::ILike { .. } => Ok(None),
             _ => Err(format!("Invalid Predicate Clause: {}", pred)),
         }
     }
@@ -1310,7 +1355,8 @@ impl InternalIndexPage {
                 | BinaryOperator::Gt
                 | BinaryOperator::GtEq
                 | BinaryOperator::Eq
-                | BinaryOperator::NotEq => {
+                | BinaryOperator::NotEq
+                | BinaryOperator::Spaceship => {
                     let binary = Self::solve_internal_index_predicate(
                         expr,
                         column_aliases,
@@ -1369,6 +1415,8 @@ impl InternalIndexPage {
                 }
                 _ => Err(format!("Invalid Unary Operator for Value: {}", op)),
             },
+            // A set of values can't be used to narrow down the index
+            Expr::AllOp(_) | Expr::AnyOp(_) => Ok(None),
             _ => Err(format!("Unexpected Value Clause: {}", expr)),
         }
     }
@@ -245,6 +245,12 @@ impl LeafIndexPage {
 
     /// Returns true if there is room for another index and value in this page.
     pub fn has_room(&self) -> bool {
+        if let Some(capacity) = get_node_capacity() {
+            if self.indexes.len() >= capacity {
+                return false;
+            }
+        }
+
         let all_keys_size: usize = self.indexes.len() * self.key_size as usize;
         let all_values_size: usize = self.indexes.len() * LeafIndexValue::size();
         let combined_size: usize = all_keys_size + all_values_size;
@@ -272,7 +278,7 @@ impl LeafIndexPage {
     pub fn get_max_index_pointers_per_page(index_key_type: &IndexKeyType) -> usize {
         let idx_and_value_size: usize =
             get_index_key_type_size(index_key_type) + LeafIndexValue::size();
-        (PAGE_SIZE - LEAF_PAGE_HEADER_SIZE) / idx_and_value_size
+        limit_to_node_capacity((PAGE_SIZE - LEAF_PAGE_HEADER_SIZE) / idx_and_value_size)
     }
 
     /// Converts rowinfos into leaf index key and value pairs.
@@ -2,10 +2,13 @@ use colored::Colorize;
 use rpassword::read_password;
 use std::io::{self, Write};
 use std::string::String;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use tonic::transport::Channel;
 use tonic::Request;
 
 use crate::client::result_parse;
+use crate::parser::parser;
 use crate::server::server::db_connection::database_connection_client::DatabaseConnectionClient;
 use crate::server::server::db_connection::{ConnectResult, LoginRequest, QueryRequest};
 
@@ -21,18 +24,35 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut connection = attempt_connection().await?;
     let mut copy = connection.clone();
 
-    // Ctrl-C handler, using the copy of the connection
+    // Whether a query is currently running on the server
+    let query_running: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let query_running_copy: Arc<AtomicBool> = query_running.clone();
+
+    // Ctrl-C handler, using the copy of the connection.
+    // It cancels the running query if there is one, otherwise it disconnects.
     tokio::spawn(async move {
-        tokio::signal::ctrl_c().await.unwrap();
-        copy.0
-            .disconnect_db(Request::new(copy.1.clone()))
-            .await
-            .unwrap();
-        println!(
-            "{}",
-            format!("\nSuccessfully disconnected from database").green()
-        );
-        std::process::exit(0);
+        loop {
+            tokio::signal::ctrl_c().await.unwrap();
+            if query_running_copy.load(Ordering::SeqCst) {
+                match copy.0.cancel_query(Request::new(copy.1.clone())).await {
+                    Ok(_) => println!("{}", format!("\nCancelling query...").yellow()),
+                    Err(err) => println!(
+                        "{}",
+                        format!("\nUnable to cancel query: {}", err.message()).red()
+                    ),
+                }
+                continue;
+            }
+            copy.0
+                .disconnect_db(Request::new(copy.1.clone()))
+                .await
+                .unwrap();
+            println!(
+                "{}",
+                format!("\nSuccessfully disconnected from database").green()
+            );
+            std::process::exit(0);
+        }
     });
 
     let client: &mut DatabaseConnectionClient<Channel> = &mut connection.0;
@@ -63,8 +83,8 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 break;
             }
 
-            // stop reading if there's a semi colon
-            if last_input.contains(";") {
+            // stop reading if there's a semi colon, that isn't in a comment or a string
+            if parser::is_statement_complete(&command) {
                 break;
             }
 
@@ -72,10 +92,24 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
             io::stdout().flush()?;
         }
 
-        // string manipulation to get rid of \n and ;
-        command = command.replace(";", "");
+        // string manipulation to get rid of comments, \n and ;
+        if command.to_lowercase().starts_with("gql ") {
+            command = command.replace(";", "");
+        } else {
+            // VC commands can have flags like --force, so only SQL has its comments removed
+            command = parser::strip_comments(&command)
+                .trim()
+                .trim_end_matches(";")
+                .to_string();
+        }
         command = command.replace("\n", " ");
 
+        // PREVIEW before an UPDATE or DELETE runs it as a query, which returns the rows it would change
+        let is_preview: bool = command.to_lowercase().starts_with("preview ");
+        if is_preview {
+            command = command["preview ".len()..].trim().to_string();
+        }
+
         let request = QueryRequest {
             id: String::from(&response.id),
             query: String::from(&command),
@@ -102,12 +136,16 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
             } else {
                 println!("{}{}", error, result.unwrap_err().message());
             }
-        } else if command
-            .to_lowercase()
-            .replace("(", "") // Ignore parenthesis while checking for keywords
-            .starts_with("select ")
+        } else if is_preview
+            || command.to_lowercase().starts_with("show ")
+            || command
+                .to_lowercase()
+                .replace("(", "") // Ignore parenthesis while checking for keywords
+                .starts_with("select ")
         {
+            query_running.store(true, Ordering::SeqCst);
             let result = client.run_query(Request::new(request)).await;
+            query_running.store(false, Ordering::SeqCst);
             if result.is_ok() {
                 // parses through the result and prints the table
                 result_parse::result_parse(result.unwrap().into_inner())?;
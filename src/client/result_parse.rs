@@ -1,10 +1,13 @@
 use chrono::NaiveDateTime;
 use prost_types::Timestamp;
-use tabled::{builder::Builder, Style};
+use tabled::{builder::Builder, object::Columns, Alignment, Modify, Style};
 
 use crate::{
     server::server::db_connection::QueryResult,
-    util::{convert::from_row_value, dbtype::Value},
+    util::{
+        convert::{from_column_types, from_row_value},
+        dbtype::{format_time_of_day, Column, Value},
+    },
 };
 
 pub fn result_parse(result_inner: QueryResult) -> Result<(), String> {
@@ -20,10 +23,11 @@ pub fn result_parse(result_inner: QueryResult) -> Result<(), String> {
         let mut row_value: Vec<String> = Vec::new();
         for value in from_row_value(row) {
             match value {
-                Value::String(s) => row_value.push(s),
+                Value::String(s) | Value::Char(s) => row_value.push(s),
                 Value::I32(i) => row_value.push(i.to_string()),
                 Value::Float(f) => row_value.push(f.to_string()),
                 Value::Timestamp(t) => row_value.push(from_timestamp(&t)),
+                Value::Time(t) => row_value.push(format_time_of_day(t)),
                 Value::I64(i) => row_value.push(i.to_string()),
                 Value::Double(d) => row_value.push(d.to_string()),
                 Value::Bool(b) => row_value.push(b.to_string()),
@@ -43,11 +47,29 @@ pub fn result_parse(result_inner: QueryResult) -> Result<(), String> {
     // pretty table
     let mut table = builder.build();
     table.with(Style::rounded());
+
+    // right-align the numeric columns
+    for (i, column_type) in from_column_types(&result_inner.column_types)
+        .iter()
+        .enumerate()
+    {
+        if is_numeric_column(column_type) {
+            table.with(Modify::new(Columns::single(i)).with(Alignment::right()));
+        }
+    }
     // will print the table on the terminal
     println!("{}", table);
     Ok(())
 }
 
+fn is_numeric_column(column_type: &Column) -> bool {
+    match column_type {
+        Column::I32 | Column::I64 | Column::Float | Column::Double => true,
+        Column::Nullable(x) => is_numeric_column(x),
+        _ => false,
+    }
+}
+
 fn from_timestamp(t: &Timestamp) -> String {
     let time =
         NaiveDateTime::from_timestamp_opt(t.seconds, t.nanos as u32).unwrap_or(NaiveDateTime::MAX);
@@ -136,6 +158,13 @@ mod tests {
                 ]),
             ],
             time_taken: 0 as f32,
+            column_types: vec![
+                Column::String(64).encode_type() as u32,
+                Column::I32.encode_type() as u32,
+                Column::Float.encode_type() as u32,
+                Column::Float.encode_type() as u32,
+                Column::String(64).encode_type() as u32,
+            ],
         };
 
         result_parse(result).unwrap();
@@ -147,6 +176,7 @@ mod tests {
             column_names: vec![],
             row_values: vec![],
             time_taken: 0 as f32,
+            column_types: vec![],
         };
 
         assert_eq!(result_parse(result).unwrap(), ());
@@ -158,6 +188,7 @@ mod tests {
             column_names: vec!["Name".to_string()],
             row_values: vec![to_row_value(vec![Value::String("John Adams".to_string())])],
             time_taken: 0 as f32,
+            column_types: vec![],
         };
 
         result_parse(result).unwrap();
@@ -169,6 +200,7 @@ mod tests {
             column_names: vec!["Name".to_string()],
             row_values: vec![],
             time_taken: 0 as f32,
+            column_types: vec![],
         };
 
         result_parse(result).unwrap();
@@ -1,11 +1,21 @@
 use std::collections::HashMap;
 
+use crate::parser::clauses::AggregateClauses;
 use crate::util::dbtype::{Column, Value};
 use crate::util::row::Row;
 
-use sqlparser::ast::{BinaryOperator, Expr, FunctionArgExpr, UnaryOperator};
+use sqlparser::ast::{
+    BinaryOperator, Expr, FunctionArg, FunctionArgExpr, OrderByExpr, UnaryOperator,
+    Value as SqlValue,
+};
 
-use super::predicate::{resolve_reference, solve_value, JointValues};
+use super::functions::{
+    apply_scalar_function, get_function_args, is_scalar_function, like, position, substring,
+};
+use super::predicate::{
+    interval_seconds, offset_by_interval, resolve_pure_value, resolve_reference, solve_predicate,
+    solve_value, JointValues, PredicateSolver, ValueSolver,
+};
 use super::query::ColumnAliases;
 use super::query::IndexRefs;
 
@@ -13,6 +23,7 @@ use super::query::IndexRefs;
 pub fn resolve_aggregates(
     rows: Vec<(Row, Row)>,
     selections: &Vec<Expr>,
+    column_clauses: &Vec<Vec<AggregateClauses>>, // The clauses of the aggregates in each column
     column_aliases: &ColumnAliases,
     index_refs: &IndexRefs,
 ) -> Result<Vec<Row>, String> {
@@ -35,21 +46,176 @@ pub fn resolve_aggregates(
     // Take the first row, and solve the functions for it for the entire group
     let mut new_row = value_rows[0].clone();
     for (i, expr) in functions {
-        new_row[i] = solve_aggregate(&original_rows, expr, column_aliases, index_refs)?;
+        new_row[i] = solve_aggregate(
+            &original_rows,
+            expr,
+            &column_clauses[i],
+            column_aliases,
+            index_refs,
+        )?;
     }
     Ok(vec![new_row])
 }
 
+/// The aggregates of a query with no GROUP BY, worked out as the rows are scanned, so that the
+/// rows don't have to be kept until the end. Only COUNT, SUM, AVG, MIN and MAX can be selected
+/// this way, and they give the same values that solve_aggregate does for the whole table.
+pub struct RunningAggregates {
+    columns: Vec<Option<RunningAggregate>>, // None for a column that has no aggregate in it
+    first_row: Option<Row>,                 // The selected values of the first row, if any
+}
+
+// An aggregate, with what it has worked out from the rows so far
+enum RunningAggregate {
+    Count(Option<ValueSolver>, i32), // COUNT(*) has no solver, as it counts every row
+    Sum(ValueSolver, Option<JointValues>),
+    Avg(ValueSolver, Option<JointValues>, i32),
+    Min(ValueSolver, Option<JointValues>),
+    Max(ValueSolver, Option<JointValues>),
+}
+
+impl RunningAggregates {
+    /// Sets up the aggregates for the selected columns. Returns None if none of them are
+    /// aggregates, or if any can't be worked out a row at a time, like PERCENTILE, an aggregate
+    /// with clauses, or one inside of another expression.
+    pub fn new(
+        selections: &Vec<Expr>,
+        column_clauses: &Vec<Vec<AggregateClauses>>,
+        column_aliases: &ColumnAliases,
+        index_refs: &IndexRefs,
+    ) -> Result<Option<RunningAggregates>, String> {
+        if column_clauses.iter().any(|clauses| !clauses.is_empty()) {
+            return Ok(None);
+        }
+        let mut columns: Vec<Option<RunningAggregate>> = Vec::new();
+        for expr in selections {
+            if !contains_aggregate(expr)? {
+                columns.push(None);
+                continue;
+            }
+            let func = match expr {
+                Expr::Function(func) if !func.distinct && func.over.is_none() => func,
+                _ => return Ok(None),
+            };
+            let arg: Option<&Expr> = match func.args.as_slice() {
+                [FunctionArg::Unnamed(FunctionArgExpr::Expr(arg))] => Some(arg),
+                [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)] => None,
+                _ => return Ok(None),
+            };
+            let solver: Option<ValueSolver> = arg
+                .map(|arg| solve_value(arg, column_aliases, index_refs))
+                .transpose()?;
+            let name: String = func.name.to_string().to_lowercase();
+            columns.push(Some(match (name.as_str(), solver) {
+                ("count", solver) => RunningAggregate::Count(solver, 0),
+                ("sum", Some(solver)) => RunningAggregate::Sum(solver, None),
+                ("avg", Some(solver)) => RunningAggregate::Avg(solver, None, 0),
+                ("min", Some(solver)) => RunningAggregate::Min(solver, None),
+                ("max", Some(solver)) => RunningAggregate::Max(solver, None),
+                _ => return Ok(None),
+            }));
+        }
+        if columns.iter().all(|column| column.is_none()) {
+            return Ok(None);
+        }
+        Ok(Some(RunningAggregates {
+            columns,
+            first_row: None,
+        }))
+    }
+
+    /// Adds a row to the aggregates, given its selected values and the row they came from
+    pub fn add(&mut self, selected: Row, row: &Row) -> Result<(), String> {
+        if self.first_row.is_none() {
+            self.first_row = Some(selected);
+        }
+        for column in self.columns.iter_mut().flatten() {
+            match column {
+                RunningAggregate::Count(solver, count) => {
+                    let is_counted: bool = match solver {
+                        Some(solver) => !solver(row)?.is_null(),
+                        None => true,
+                    };
+                    if is_counted {
+                        *count += 1;
+                    }
+                }
+                RunningAggregate::Sum(solver, sum) => {
+                    let val = solver(row)?;
+                    *sum = Some(match sum.take() {
+                        Some(sum) => sum.add(&val)?,
+                        None => val,
+                    });
+                }
+                RunningAggregate::Avg(solver, sum, count) => {
+                    let val = solver(row)?;
+                    if !val.is_null() {
+                        *count += 1;
+                    }
+                    *sum = Some(match sum.take() {
+                        Some(sum) => sum.add(&val)?,
+                        None => val,
+                    });
+                }
+                RunningAggregate::Min(solver, min) => {
+                    let val = solver(row)?;
+                    *min = match min.take() {
+                        Some(min) if min < val => Some(min),
+                        _ => Some(val),
+                    };
+                }
+                RunningAggregate::Max(solver, max) => {
+                    let val = solver(row)?;
+                    *max = match max.take() {
+                        Some(max) if max > val => Some(max),
+                        _ => Some(val),
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The single row of the selected values, or no rows if none were added,
+    /// as resolve_aggregates gives for an empty group.
+    pub fn finish(self) -> Result<Vec<Row>, String> {
+        let mut new_row: Row = match self.first_row {
+            Some(row) => row,
+            None => return Ok(vec![]),
+        };
+        let value = |value: Option<JointValues>| match value {
+            Some(value) => value.unpack(),
+            None => Ok(Value::Null(Column::I32)),
+        };
+        for (i, column) in self.columns.into_iter().enumerate() {
+            new_row[i] = match column {
+                None => continue,
+                Some(RunningAggregate::Count(_, count)) => Value::I32(count),
+                Some(RunningAggregate::Sum(_, sum)) => value(sum)?,
+                Some(RunningAggregate::Avg(_, sum, count)) => {
+                    let sum = JointValues::DBValue(value(sum)?);
+                    sum.divide(&JointValues::DBValue(Value::I32(count)))?
+                        .unpack()?
+                }
+                Some(RunningAggregate::Min(_, min)) => value(min)?,
+                Some(RunningAggregate::Max(_, max)) => value(max)?,
+            };
+        }
+        Ok(vec![new_row])
+    }
+}
+
 /// Versions of the Solvers that just return the Value directly
 pub fn solve_agg_predicate(
     rows: &Vec<Row>,
     pred: &Expr,
+    clauses: &Vec<AggregateClauses>, // The clauses of the aggregates in the expression
     column_aliases: &ColumnAliases,
     index_refs: &IndexRefs,
 ) -> Result<bool, String> {
     match pred {
         Expr::Identifier(_) => {
-            let value = solve_aggregate(rows, pred, column_aliases, index_refs)?;
+            let value = solve_aggregate(rows, pred, clauses, column_aliases, index_refs)?;
             match value {
                 Value::Bool(x) => Ok(x),
                 _ => Err(format!("Cannot compare value {:?} to bool", value)),
@@ -58,26 +224,30 @@ pub fn solve_agg_predicate(
         Expr::IsFalse(pred) => Ok(!solve_agg_predicate(
             rows,
             pred,
+            clauses,
             column_aliases,
             index_refs,
         )?),
-        Expr::IsNotFalse(pred) => solve_agg_predicate(rows, pred, column_aliases, index_refs),
-        Expr::IsTrue(pred) => solve_agg_predicate(rows, pred, column_aliases, index_refs),
+        Expr::IsNotFalse(pred) => {
+            solve_agg_predicate(rows, pred, clauses, column_aliases, index_refs)
+        }
+        Expr::IsTrue(pred) => solve_agg_predicate(rows, pred, clauses, column_aliases, index_refs),
         Expr::IsNotTrue(pred) => Ok(!solve_agg_predicate(
             rows,
             pred,
+            clauses,
             column_aliases,
             index_refs,
         )?),
         Expr::IsNull(pred) => {
-            let value = solve_aggregate(rows, pred, column_aliases, index_refs)?;
+            let value = solve_aggregate(rows, pred, clauses, column_aliases, index_refs)?;
             match value {
                 Value::Null(_) => Ok(true),
                 _ => Ok(false),
             }
         }
         Expr::IsNotNull(pred) => {
-            let value = solve_aggregate(rows, pred, column_aliases, index_refs)?;
+            let value = solve_aggregate(rows, pred, clauses, column_aliases, index_refs)?;
             match value {
                 Value::Null(_) => Ok(false),
                 _ => Ok(true),
@@ -89,11 +259,22 @@ pub fn solve_agg_predicate(
             | BinaryOperator::GtEq
             | BinaryOperator::LtEq
             | BinaryOperator::Eq
-            | BinaryOperator::NotEq => {
-                let left =
-                    JointValues::DBValue(solve_aggregate(rows, left, column_aliases, index_refs)?);
-                let right =
-                    JointValues::DBValue(solve_aggregate(rows, right, column_aliases, index_refs)?);
+            | BinaryOperator::NotEq
+            | BinaryOperator::Spaceship => {
+                let left = JointValues::DBValue(solve_aggregate(
+                    rows,
+                    left,
+                    clauses,
+                    column_aliases,
+                    index_refs,
+                )?);
+                let right = JointValues::DBValue(solve_aggregate(
+                    rows,
+                    right,
+                    clauses,
+                    column_aliases,
+                    index_refs,
+                )?);
                 match op {
                     BinaryOperator::Gt => Ok(left > right),
                     BinaryOperator::Lt => Ok(left < right),
@@ -101,29 +282,78 @@ pub fn solve_agg_predicate(
                     BinaryOperator::LtEq => Ok(left <= right),
                     BinaryOperator::Eq => Ok(left == right),
                     BinaryOperator::NotEq => Ok(left != right),
+                    BinaryOperator::Spaceship => Ok(left == right),
                     _ => Err(format!("Invalid comparison operator {:?}", op)),
                 }
             }
             BinaryOperator::And => {
-                let left = solve_agg_predicate(rows, left, column_aliases, index_refs)?;
-                let right = solve_agg_predicate(rows, right, column_aliases, index_refs)?;
+                let left = solve_agg_predicate(rows, left, clauses, column_aliases, index_refs)?;
+                let right = solve_agg_predicate(rows, right, clauses, column_aliases, index_refs)?;
                 Ok(left && right)
             }
             BinaryOperator::Or => {
-                let left = solve_agg_predicate(rows, left, column_aliases, index_refs)?;
-                let right = solve_agg_predicate(rows, right, column_aliases, index_refs)?;
+                let left = solve_agg_predicate(rows, left, clauses, column_aliases, index_refs)?;
+                let right = solve_agg_predicate(rows, right, clauses, column_aliases, index_refs)?;
                 Ok(left || right)
             }
             _ => Err(format!("Unsupported binary operator for Predicate: {}", op)),
         },
         Expr::UnaryOp { op, expr } => match op {
             UnaryOperator::Not => {
-                let left = solve_agg_predicate(rows, expr, column_aliases, index_refs)?;
+                let left = solve_agg_predicate(rows, expr, clauses, column_aliases, index_refs)?;
                 Ok(!left)
             }
             _ => Err(format!("Unsupported unary operator for Predicate: {}", op)),
         },
-        Expr::Nested(pred) => solve_agg_predicate(rows, pred, column_aliases, index_refs),
+        Expr::Nested(pred) => solve_agg_predicate(rows, pred, clauses, column_aliases, index_refs),
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let value = JointValues::DBValue(solve_aggregate(
+                rows,
+                expr,
+                clauses,
+                column_aliases,
+                index_refs,
+            )?);
+            let mut found: bool = false;
+            for item in list {
+                let item = JointValues::DBValue(solve_aggregate(
+                    rows,
+                    item,
+                    clauses,
+                    column_aliases,
+                    index_refs,
+                )?);
+                if value == item {
+                    found = true;
+                    break;
+                }
+            }
+            Ok(found != *negated)
+        }
+        Expr::Like {
+            negated,
+            expr,
+            pattern,
+            escape_char,
+        }
+        | Expr::ILike {
+            negated,
+            expr,
+            pattern,
+            escape_char,
+        } => {
+            let value = solve_aggregate(rows, expr, clauses, column_aliases, index_refs)?;
+            let pattern = solve_aggregate(rows, pattern, clauses, column_aliases, index_refs)?;
+            let case_insensitive: bool = matches!(pred, Expr::ILike { .. });
+            match like(value, pattern, *escape_char, case_insensitive)? {
+                Value::Bool(matches) => Ok(matches != *negated),
+                _ => Ok(false),
+            }
+        }
         _ => Err(format!("Invalid Predicate Clause: {}", pred)),
     }
 }
@@ -131,6 +361,7 @@ pub fn solve_agg_predicate(
 pub fn solve_aggregate(
     rows: &Vec<Row>,
     expr: &Expr,
+    clauses: &Vec<AggregateClauses>, // The clauses of the aggregates in the expression
     column_aliases: &ColumnAliases,
     index_refs: &IndexRefs,
 ) -> Result<Value, String> {
@@ -156,18 +387,40 @@ pub fn solve_aggregate(
                 .ok_or(format!("Column {} does not exist in the table", x))?;
             Ok(row[index].clone())
         }
-        Expr::Nested(x) => solve_aggregate(rows, x, column_aliases, index_refs),
+        Expr::Nested(x) => solve_aggregate(rows, x, clauses, column_aliases, index_refs),
         Expr::Value(x) => JointValues::SQLValue(x.clone()).unpack(),
+        // Timestamps are moved by an interval, like `MAX(ts) + INTERVAL '1 day'`
+        Expr::BinaryOp { left, op, right }
+            if matches!(op, BinaryOperator::Plus | BinaryOperator::Minus)
+                && matches!(**right, Expr::Value(SqlValue::Interval { .. })) =>
+        {
+            let seconds = match (op, interval_seconds(right)?) {
+                (BinaryOperator::Minus, seconds) => -seconds,
+                (_, seconds) => seconds,
+            };
+            let left = solve_aggregate(rows, left, clauses, column_aliases, index_refs)?;
+            offset_by_interval(JointValues::DBValue(left), seconds)?.unpack()
+        }
         Expr::BinaryOp { left, op, right } => match op {
             BinaryOperator::Plus
             | BinaryOperator::Minus
             | BinaryOperator::Multiply
             | BinaryOperator::Divide
             | BinaryOperator::Modulo => {
-                let left =
-                    JointValues::DBValue(solve_aggregate(rows, left, column_aliases, index_refs)?);
-                let right =
-                    JointValues::DBValue(solve_aggregate(rows, right, column_aliases, index_refs)?);
+                let left = JointValues::DBValue(solve_aggregate(
+                    rows,
+                    left,
+                    clauses,
+                    column_aliases,
+                    index_refs,
+                )?);
+                let right = JointValues::DBValue(solve_aggregate(
+                    rows,
+                    right,
+                    clauses,
+                    column_aliases,
+                    index_refs,
+                )?);
                 match op {
                     BinaryOperator::Plus => left.add(&right),
                     BinaryOperator::Minus => left.subtract(&right),
@@ -185,33 +438,118 @@ pub fn solve_aggregate(
             | BinaryOperator::Gt
             | BinaryOperator::GtEq
             | BinaryOperator::Eq
-            | BinaryOperator::NotEq => {
-                let binary = solve_agg_predicate(rows, expr, column_aliases, index_refs)?;
+            | BinaryOperator::NotEq
+            | BinaryOperator::Spaceship => {
+                let binary = solve_agg_predicate(rows, expr, clauses, column_aliases, index_refs)?;
                 Ok(Value::Bool(binary))
             }
             _ => Err(format!("Invalid Binary Operator for Value: {}", op)),
         },
         Expr::UnaryOp { op, expr } => match op {
             UnaryOperator::Plus => {
-                let val =
-                    JointValues::DBValue(solve_aggregate(rows, expr, column_aliases, index_refs)?);
+                let val = JointValues::DBValue(solve_aggregate(
+                    rows,
+                    expr,
+                    clauses,
+                    column_aliases,
+                    index_refs,
+                )?);
                 val.unpack()
             }
             UnaryOperator::Minus => {
-                let val =
-                    JointValues::DBValue(solve_aggregate(rows, expr, column_aliases, index_refs)?);
+                let val = JointValues::DBValue(solve_aggregate(
+                    rows,
+                    expr,
+                    clauses,
+                    column_aliases,
+                    index_refs,
+                )?);
                 JointValues::DBValue(Value::I32(0)).subtract(&val)?.unpack()
             }
             UnaryOperator::Not => {
-                let binary = solve_agg_predicate(rows, expr, column_aliases, index_refs)?;
+                let binary = solve_agg_predicate(rows, expr, clauses, column_aliases, index_refs)?;
                 Ok(Value::Bool(binary))
             }
             _ => Err(format!("Invalid Unary Operator for Value: {}", op)),
         },
+        Expr::IsNull(_)
+        | Expr::IsNotNull(_)
+        | Expr::IsTrue(_)
+        | Expr::IsNotTrue(_)
+        | Expr::IsFalse(_)
+        | Expr::IsNotFalse(_)
+        | Expr::InList { .. }
+        | Expr::Like { .. }
+        | Expr::ILike { .. } => Ok(Value::Bool(solve_agg_predicate(
+            rows,
+            expr,
+            clauses,
+            column_aliases,
+            index_refs,
+        )?)),
+        Expr::Function(func) if is_scalar_function(func) => {
+            let args: Vec<Value> = get_function_args(func)?
+                .into_iter()
+                .map(|arg| solve_aggregate(rows, arg, clauses, column_aliases, index_refs))
+                .collect::<Result<Vec<Value>, String>>()?;
+            apply_scalar_function(func, args)
+        }
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => {
+            let solve = |expr: &Option<Box<Expr>>| {
+                expr.as_ref()
+                    .map(|x| solve_aggregate(rows, x, clauses, column_aliases, index_refs))
+                    .transpose()
+            };
+            let from: Option<Value> = solve(substring_from)?;
+            let length: Option<Value> = solve(substring_for)?;
+            substring(
+                solve_aggregate(rows, expr, clauses, column_aliases, index_refs)?,
+                from,
+                length,
+            )
+        }
+        Expr::Position { expr, r#in } => position(
+            solve_aggregate(rows, expr, clauses, column_aliases, index_refs)?,
+            solve_aggregate(rows, r#in, clauses, column_aliases, index_refs)?,
+        ),
         Expr::Function(func) => {
             let name = func.name.to_string().to_lowercase();
-            let args = &func.args;
-            if args.len() != 1 {
+            // The clauses that the parser took out of the call
+            let call_clauses: Option<&AggregateClauses> = clauses
+                .iter()
+                .find(|call_clauses| &call_clauses.call == func);
+            let order_by: Option<&OrderByExpr> =
+                call_clauses.and_then(|call_clauses| call_clauses.order_by.as_ref());
+            let is_ordered: bool = matches!(
+                name.as_str(),
+                "percentile_cont" | "first_value" | "last_value"
+            );
+            if order_by.is_some() && !is_ordered {
+                return Err(format!("{} can't have an ORDER BY", name));
+            }
+
+            // Only the rows that match the FILTER (WHERE ...) are aggregated
+            let filtered_rows: Vec<Row>;
+            let rows: &Vec<Row> =
+                match call_clauses.and_then(|call_clauses| call_clauses.filter.as_ref()) {
+                    Some(filter) => {
+                        filtered_rows = filter_rows(rows, filter, column_aliases, index_refs)?;
+                        &filtered_rows
+                    }
+                    None => rows,
+                };
+            let args: &Vec<FunctionArg> = &func.args;
+            let num_args: usize = match name.as_str() {
+                "percentile" => 2,
+                // Without an ORDER BY, the ordering is given as a second argument
+                "first_value" | "last_value" if order_by.is_none() => 2,
+                _ => 1,
+            };
+            if args.len() != num_args {
                 return Err(format!(
                     "Invalid number of arguments for {}: {}",
                     name,
@@ -227,6 +565,7 @@ pub fn solve_aggregate(
                             FunctionArgExpr::Expr(expr) => {
                                 aggregate_count(rows, &Some(expr), column_aliases, index_refs)
                             }
+                            // COUNT(*) counts every row, whatever its values are
                             _ => aggregate_count(rows, &None, column_aliases, index_refs),
                         },
                         _ => Err(format!("Unsupported arguments {}", args[0])),
@@ -280,6 +619,73 @@ pub fn solve_aggregate(
                     },
                     _ => Err(format!("Unsupported arguments {}", args[0])),
                 },
+                "percentile" => match (&args[0], &args[1]) {
+                    (
+                        sqlparser::ast::FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)),
+                        sqlparser::ast::FunctionArg::Unnamed(FunctionArgExpr::Expr(fraction)),
+                    ) => aggregate_percentile(
+                        rows,
+                        expr,
+                        fraction,
+                        false,
+                        column_aliases,
+                        index_refs,
+                    ),
+                    _ => Err(format!("Unsupported arguments {}, {}", args[0], args[1])),
+                },
+                // The values come from the WITHIN GROUP (ORDER BY ...), and ordering them by
+                // DESC takes the percentile from the other end
+                "percentile_cont" => match (&args[0], order_by) {
+                    (
+                        sqlparser::ast::FunctionArg::Unnamed(FunctionArgExpr::Expr(fraction)),
+                        Some(order_by),
+                    ) => aggregate_percentile(
+                        rows,
+                        &order_by.expr,
+                        fraction,
+                        order_by.asc == Some(false),
+                        column_aliases,
+                        index_refs,
+                    ),
+                    (_, None) => Err(
+                        "PERCENTILE_CONT needs a WITHIN GROUP (ORDER BY <expression>)".to_string(),
+                    ),
+                    _ => Err(format!("Unsupported arguments {}", args[0])),
+                },
+                // Ordering by DESC swaps the first row with the last
+                "first_value" | "last_value" => match (&args[0], args.get(1), order_by) {
+                    (
+                        sqlparser::ast::FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)),
+                        None,
+                        Some(order_by),
+                    ) => aggregate_ordered_value(
+                        rows,
+                        expr,
+                        &order_by.expr,
+                        (name == "last_value") != (order_by.asc == Some(false)),
+                        column_aliases,
+                        index_refs,
+                    ),
+                    (
+                        sqlparser::ast::FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)),
+                        Some(sqlparser::ast::FunctionArg::Unnamed(FunctionArgExpr::Expr(order))),
+                        None,
+                    ) => aggregate_ordered_value(
+                        rows,
+                        expr,
+                        order,
+                        name == "last_value",
+                        column_aliases,
+                        index_refs,
+                    ),
+                    _ => Err(format!(
+                        "Unsupported arguments {}",
+                        args.iter()
+                            .map(|arg| arg.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    )),
+                },
                 _ => Err(format!("Unsupported aggregate function: {}", name)),
             }
         }
@@ -287,6 +693,23 @@ pub fn solve_aggregate(
     }
 }
 
+/// Keeps the rows that match the FILTER (WHERE ...) condition of an aggregate
+fn filter_rows(
+    rows: &Vec<Row>,
+    filter: &Expr,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<Vec<Row>, String> {
+    let pred: PredicateSolver = solve_predicate(filter, column_aliases, index_refs)?;
+    let mut filtered: Vec<Row> = Vec::new();
+    for row in rows {
+        if pred(row)? {
+            filtered.push(row.clone());
+        }
+    }
+    Ok(filtered)
+}
+
 fn aggregate_count(
     rows: &Vec<Row>,
     expr: &Option<&Expr>,
@@ -384,6 +807,94 @@ fn aggregate_max(
     }
 }
 
+/// Gets the value from the row that comes first, or last, when the rows are sorted by `order`.
+/// Rows with a null ordering value are skipped, and ties go to the earliest row.
+fn aggregate_ordered_value(
+    rows: &Vec<Row>,
+    expr: &Expr,
+    order: &Expr,
+    last: bool,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<Value, String> {
+    let solver = solve_value(expr, column_aliases, index_refs)?;
+    let order_solver = solve_value(order, column_aliases, index_refs)?;
+    let mut best: Option<(JointValues, &Row)> = None;
+    for row in rows {
+        let key = order_solver(row)?;
+        if key.is_null() {
+            continue;
+        }
+        best = match best {
+            Some((best_key, _)) if (last && key > best_key) || (!last && key < best_key) => {
+                Some((key, row))
+            }
+            Some(best) => Some(best),
+            None => Some((key, row)),
+        };
+    }
+    match best {
+        Some((_, row)) => solver(row)?.unpack(),
+        None => Ok(Value::Null(Column::I32)),
+    }
+}
+
+/// Computes the value at the given fraction of the way through the sorted non-null values,
+/// interpolating between the two closest values, like `PERCENTILE_CONT`. The values are sorted
+/// from largest to smallest if descending is set.
+fn aggregate_percentile(
+    rows: &Vec<Row>,
+    expr: &Expr,
+    fraction: &Expr,
+    descending: bool,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<Value, String> {
+    let as_double = |val: Value| match val {
+        Value::I32(x) => Ok(Some(x as f64)),
+        Value::I64(x) => Ok(Some(x as f64)),
+        Value::Float(x) => Ok(Some(x as f64)),
+        Value::Double(x) => Ok(Some(x)),
+        Value::Null(_) => Ok(None),
+        _ => Err(format!(
+            "Cannot take the percentile of non-numeric value {:?}",
+            val
+        )),
+    };
+
+    // The fraction can't refer to any columns, so it's solved without a row
+    let fraction: f64 = match as_double(resolve_pure_value(fraction)?) {
+        Ok(Some(x)) if (0.0..=1.0).contains(&x) && descending => 1.0 - x,
+        Ok(Some(x)) if (0.0..=1.0).contains(&x) => x,
+        _ => {
+            return Err(format!(
+                "Percentile fraction {} must be between 0 and 1",
+                fraction
+            ))
+        }
+    };
+
+    let solver = solve_value(expr, column_aliases, index_refs)?;
+    let mut values: Vec<f64> = Vec::with_capacity(rows.len());
+    for row in rows {
+        if let Some(val) = as_double(solver(row)?.unpack()?)? {
+            values.push(val);
+        }
+    }
+    if values.is_empty() {
+        return Ok(Value::Null(Column::Double));
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+
+    let position: f64 = fraction * (values.len() - 1) as f64;
+    let lower: usize = position.floor() as usize;
+    let upper: usize = position.ceil() as usize;
+    let weight: f64 = position - lower as f64;
+    Ok(Value::Double(
+        values[lower] + (values[upper] - values[lower]) * weight,
+    ))
+}
+
 /// Takes an expression, and returns true if there is a Function expression nested somewhere inside it.
 pub fn contains_aggregate(expr: &Expr) -> Result<bool, String> {
     match expr {
@@ -395,7 +906,45 @@ pub fn contains_aggregate(expr: &Expr) -> Result<bool, String> {
             Ok(contains_aggregate(left)? || contains_aggregate(right)?)
         }
         Expr::UnaryOp { op: _, expr } => contains_aggregate(expr),
+        Expr::IsNull(x)
+        | Expr::IsNotNull(x)
+        | Expr::IsTrue(x)
+        | Expr::IsNotTrue(x)
+        | Expr::IsFalse(x)
+        | Expr::IsNotFalse(x) => contains_aggregate(x),
+        Expr::InList { expr, list, .. } => {
+            for x in list {
+                if contains_aggregate(x)? {
+                    return Ok(true);
+                }
+            }
+            contains_aggregate(expr)
+        }
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => {
+            Ok(contains_aggregate(expr)? || contains_aggregate(pattern)?)
+        }
+        Expr::Function(func) if is_scalar_function(func) => {
+            for arg in get_function_args(func)? {
+                if contains_aggregate(arg)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
         Expr::Function(_) => Ok(true),
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => {
+            for x in [substring_from, substring_for].into_iter().flatten() {
+                if contains_aggregate(x)? {
+                    return Ok(true);
+                }
+            }
+            contains_aggregate(expr)
+        }
+        Expr::Position { expr, r#in } => Ok(contains_aggregate(expr)? || contains_aggregate(r#in)?),
         _ => Err(format!("Unexpected Clause: {}", expr)),
     }
 }
@@ -406,10 +955,12 @@ mod tests {
     use serial_test::serial;
 
     use crate::{
-        executor::query::execute_query,
+        executor::query::{execute_query, execute_update},
+        fileio::databaseio::delete_db_instance,
         parser::parser::parse,
+        user::userdata::User,
         util::{
-            bench::{create_demo_db, create_huge_bench_db},
+            bench::{create_demo_db, create_huge_bench_db, fcreate_db_instance},
             dbtype::{Column, Value},
         },
     };
@@ -589,6 +1140,50 @@ mod tests {
         }
     }
 
+    #[test]
+    #[serial]
+    fn test_group_by_ordinal_and_alias() {
+        let mut user = create_huge_bench_db(312, true);
+
+        let (_, expected) = execute_query(
+            &parse(
+                "select id2 % 4 as bucket, count(*), sum(id2) from huge_table group by id2 % 4 order by id2 % 4",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(expected.len() == 4);
+
+        // Group by the position of the selected column
+        let (_, results) = execute_query(
+            &parse(
+                "select id2 % 4 as bucket, count(*), sum(id2) from huge_table group by 1 order by id2 % 4",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(results == expected);
+
+        // Group by the alias of the selected column
+        let (_, results) = execute_query(
+            &parse(
+                "select id2 % 4 as bucket, count(*), sum(id2) from huge_table group by bucket order by id2 % 4",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(results == expected);
+    }
+
     #[test]
     #[serial]
     fn test_non_group_aggregate() {
@@ -648,6 +1243,382 @@ mod tests {
         assert!(results[0][1].force_int() == 51);
     }
 
+    #[test]
+    #[serial]
+    fn test_non_group_running_aggregates() {
+        fcreate_db_instance("non_group_running_aggregates");
+        let mut user: User = User::new("test_user".to_string());
+        let values: Vec<String> = (0..500)
+            .map(|i| match i % 7 {
+                0 => format!("({}, NULL, 0)", i),
+                _ => format!("({}, {}, 0)", i, (i * 37) % 101),
+            })
+            .collect();
+        execute_update(
+            &parse(
+                &format!(
+                    "CREATE TABLE t (id INT, x INT NULL, g INT); INSERT INTO t VALUES {}",
+                    values.join(", ")
+                ),
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let mut query = |query: &str| {
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string())
+                .unwrap()
+                .1
+        };
+
+        // Every row has the same g, so grouping by it gives the one group that's worked out
+        // from all of the rows, rather than a row at a time
+        let selections: Vec<&str> = vec![
+            "count(*), count(x), sum(id), avg(id), min(id), max(id)",
+            "min(x), max(x), count(x), sum(id), avg(id), id",
+            "sum(id) + 1, count(*) filter (where id > 10)",
+        ];
+        for selection in selections {
+            for filter in ["", "where id % 3 = 1", "where id > 1000"] {
+                let running = query(&format!("select {} from t {}", selection, filter));
+                let grouped = query(&format!(
+                    "select {} from t {} group by g",
+                    selection, filter
+                ));
+                assert_eq!(running, grouped, "{} {}", selection, filter);
+            }
+        }
+
+        let results = query("select count(*), count(x), min(id), max(id) from t");
+        assert_eq!(
+            results,
+            vec![vec![
+                Value::I32(500),
+                Value::I32(428),
+                Value::I64(0),
+                Value::I64(499)
+            ]]
+        );
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_percentile_aggregate() {
+        let mut user = create_huge_bench_db(300, true);
+        let mut query = |query: &str| {
+            execute_query(&parse(query, false)?, &mut user, &"".to_string()).map(|(_, rows)| rows)
+        };
+
+        // id1 runs from 0 to 299, so the median is halfway between 149 and 150
+        let results =
+            query("select percentile(id1, 0.5), percentile(id1, 0.95) from huge_table").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0][0], Value::Double(149.5));
+        match results[0][1] {
+            Value::Double(x) => assert!((x - 284.05).abs() < 1e-9),
+            _ => panic!("Expected a double"),
+        }
+
+        let results = query(
+            "select percentile_cont(0.5) within group (order by id1), \
+             percentile_cont(0.95) within group (order by id1 desc) from huge_table",
+        )
+        .unwrap();
+        assert_eq!(results[0][0], Value::Double(149.5));
+        match results[0][1] {
+            Value::Double(x) => assert!((x - 14.95).abs() < 1e-9),
+            _ => panic!("Expected a double"),
+        }
+
+        // Ordering by the alias of a percentile sorts by its value. Each id2 has the ids that
+        // are 52 apart, so the median of id2 = 0 is 130, and the others are higher.
+        let results = query(
+            "select id2, percentile_cont(0.5) within group (order by id1) as p from huge_table \
+             group by id2 order by p limit 1",
+        )
+        .unwrap();
+        assert_eq!(results, vec![vec![Value::I32(0), Value::Double(130.0)]]);
+
+        assert!(query("select percentile(id1, 1.5) from huge_table").is_err());
+        assert!(query("select percentile(id1, -0.1) from huge_table").is_err());
+        assert!(query("select percentile_cont(0.5) from huge_table").is_err());
+        // The same call can't be used both with and without a WITHIN GROUP in one column
+        assert!(query(
+            "select percentile_cont(0.5) within group (order by id1) - percentile_cont(0.5) \
+             from huge_table"
+        )
+        .is_err());
+        // Only the selected columns of the outermost SELECT can have one
+        assert!(query(
+            "select id1 from huge_table \
+             where id1 > (select percentile_cont(0.5) within group (order by id1) from huge_table)"
+        )
+        .is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_count_column_skips_nulls() {
+        fcreate_db_instance("count_column_skips_nulls");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse(
+                "CREATE TABLE people (team INT, nickname VARCHAR(10) NULL, score INT NULL); \
+                 INSERT INTO people VALUES \
+                 (1, 'ace', 10), (1, NULL, 20), (1, NULL, NULL), (2, 'bee', NULL), (2, 'cat', 5)",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        // COUNT(*) counts every row, while COUNT(col) only counts the rows where col isn't null
+        let (_, results) = execute_query(
+            &parse(
+                "select count(*), count(nickname), count(score) from people",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![vec![Value::I32(5), Value::I32(3), Value::I32(3)]]
+        );
+
+        let (_, results) = execute_query(
+            &parse(
+                "select team, count(*), count(nickname), count(score) from people \
+                 group by team order by team",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I32(1), Value::I32(3), Value::I32(1), Value::I32(2)],
+                vec![Value::I32(2), Value::I32(2), Value::I32(2), Value::I32(1)],
+            ]
+        );
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_aggregate_filter() {
+        fcreate_db_instance("aggregate_filter");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse(
+                "CREATE TABLE ledger (account INT, type VARCHAR(10), amount INT); \
+                 INSERT INTO ledger VALUES \
+                 (1, 'credit', 10), (1, 'debit', 4), (1, 'credit', 5), \
+                 (2, 'debit', 7), (2, 'debit', 1)",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        // The filtered aggregates only see the matching rows of each group
+        let (_, results) = execute_query(
+            &parse(
+                "select account, sum(amount), sum(amount) filter (where type = 'credit'), \
+                 count(*) FILTER (WHERE type = 'debit' AND amount > 1) from ledger \
+                 group by account order by account",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I32(1), Value::I64(19), Value::I64(15), Value::I32(1)],
+                vec![
+                    Value::I32(2),
+                    Value::I64(8),
+                    Value::Null(Column::I32),
+                    Value::I32(1)
+                ],
+            ]
+        );
+
+        // Without a group, the filter applies to the whole table
+        let (_, results) = execute_query(
+            &parse(
+                "select max(amount) filter (where type = 'debit'), avg(amount) from ledger",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results, vec![vec![Value::I64(7), Value::I64(5)]]);
+
+        // Counting with a filter only counts the matching rows
+        let (_, results) = execute_query(
+            &parse(
+                "select count(*) filter (where type = 'debit') from ledger",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results, vec![vec![Value::I32(3)]]);
+
+        // Ordering by the alias of a filtered aggregate orders by its filtered value
+        let (_, results) = execute_query(
+            &parse(
+                "select account, sum(amount) filter (where type = 'debit') as debits \
+                 from ledger group by account order by debits desc",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I32(2), Value::I64(8)],
+                vec![Value::I32(1), Value::I64(4)]
+            ]
+        );
+
+        // The clause needs a WHERE
+        assert!(parse(
+            "select sum(amount) filter (type = 'credit') from ledger",
+            false
+        )
+        .is_err());
+        // Only the selected columns of the outermost SELECT can have one
+        assert!(parse(
+            "select account from ledger where amount in \
+             (select max(amount) filter (where type = 'debit') from ledger)",
+            false
+        )
+        .is_err());
+        // The condition can't be passed as an argument instead
+        assert!(execute_query(
+            &parse(
+                "select sum(amount, filter => (type = 'credit')) from ledger",
+                false
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_first_and_last_value() {
+        fcreate_db_instance("first_and_last_value");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse(
+                "CREATE TABLE events (entity INT, status VARCHAR(10), ts TIMESTAMP); \
+                 INSERT INTO events VALUES \
+                 (1, 'new', '2022-01-01 00:00:00'), \
+                 (2, 'new', '2022-01-02 00:00:00'), \
+                 (1, 'closed', '2022-01-05 00:00:00'), \
+                 (1, 'open', '2022-01-03 00:00:00'), \
+                 (2, 'open', '2022-01-04 00:00:00')",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        let status = |s: &str| Value::String(s.to_string());
+        let (_, results) = execute_query(
+            &parse(
+                "select entity, last_value(status order by ts), first_value(status order by ts), \
+                 first_value(status order by ts desc) from events group by entity order by entity",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![
+                    Value::I32(1),
+                    status("closed"),
+                    status("new"),
+                    status("closed")
+                ],
+                vec![Value::I32(2), status("open"), status("new"), status("open")],
+            ]
+        );
+
+        // The ordering can also be given as a second argument
+        let (_, results) = execute_query(
+            &parse("select last_value(status, ts) from events", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results, vec![vec![status("closed")]]);
+
+        // Without either, there's nothing to order the rows by
+        assert!(execute_query(
+            &parse("select last_value(status) from events", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
+        // Only the selected columns of the outermost SELECT can have an ORDER BY in a call
+        assert!(parse(
+            "select entity from events where status = \
+             (select last_value(status order by ts) from events)",
+            false
+        )
+        .is_err());
+        // A subquery's ORDER BY isn't taken out of it
+        let (_, results) = execute_query(
+            &parse(
+                "select entity from events where ts in \
+                 (select ts from events order by ts desc limit 1)",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results, vec![vec![Value::I32(1)]]);
+
+        delete_db_instance().unwrap();
+    }
+
     #[test]
     #[serial]
     fn test_group_by_dry() {
@@ -0,0 +1,361 @@
+use chrono::NaiveDateTime;
+use parking_lot::{const_mutex, Mutex};
+use sqlparser::ast::{CopyLegacyOption, CopyOption};
+use sqlparser::keywords::ALL_KEYWORDS;
+
+use super::ttl::{get_ttl, is_ttl_column};
+use crate::fileio::{
+    databaseio::Database,
+    storage::{get_storage_backend, resolve_path_within_dir},
+    tableio::Table,
+};
+use crate::user::{
+    usercreds::UserPermissions::{Admin, Read},
+    userdata::User,
+};
+use crate::util::dbtype::{format_time_of_day, Column, Value};
+use crate::util::error::Error;
+
+// The number of rows written into each INSERT statement of the export
+const ROWS_PER_INSERT: usize = 100;
+
+// The directory that users other than admins export files to, unless the server sets another
+pub const DEFAULT_EXPORT_DIR: &str = "exports";
+
+// The directory set with --export-dir, or None to use the default one
+static EXPORT_DIR: Mutex<Option<String>> = const_mutex(None);
+
+/// Sets the directory that users other than admins export files to.
+pub fn set_export_dir(dir: &String) {
+    *EXPORT_DIR.lock() = Some(dir.clone());
+}
+
+/// Returns the path of the file to export to. Admins can write any file, while users who can
+/// write tables can only write the files in the export directory, so their path is taken to be
+/// within it. Users who can only read tables can't export them at all.
+pub fn get_export_path(file_path: &String, user: &User) -> Result<String, Error> {
+    match user.get_permissions() {
+        Admin => Ok(file_path.clone()),
+        Read => Err(Error::permission_denied(
+            "You do not have permission to export tables",
+        )),
+        _ => {
+            let export_dir: String = EXPORT_DIR
+                .lock()
+                .clone()
+                .unwrap_or(DEFAULT_EXPORT_DIR.to_string());
+            Ok(resolve_path_within_dir(&export_dir, file_path)?)
+        }
+    }
+}
+
+/// The options used when exporting a table as a script of SQL statements
+#[derive(Clone, Debug)]
+pub struct SqlExportOptions {
+    pub identifier_quote: char, // The character that identifiers are wrapped in when they need quoting
+}
+
+impl Default for SqlExportOptions {
+    fn default() -> Self {
+        Self {
+            identifier_quote: '"',
+        }
+    }
+}
+
+impl SqlExportOptions {
+    /// Builds the export options from the options given to a `COPY ... TO` statement.
+    /// Only `FORMAT sql` can be exported, and `QUOTE` picks the identifier quote, which can
+    /// be `"` (the default, which GQL reads back) or a backtick for MySQL-style scripts.
+    pub fn from_copy_options(
+        options: &Vec<CopyOption>,
+        legacy_options: &Vec<CopyLegacyOption>,
+    ) -> Result<SqlExportOptions, String> {
+        let mut export_options: SqlExportOptions = SqlExportOptions::default();
+        let mut is_sql: bool = false;
+        for option in options {
+            match option {
+                CopyOption::Format(format) => {
+                    if format.value.to_lowercase() != "sql" {
+                        return Err(format!("Unsupported COPY TO format: {}", format));
+                    }
+                    is_sql = true;
+                }
+                CopyOption::Quote(quote) => {
+                    if *quote != '"' && *quote != '`' {
+                        return Err(format!("Unsupported identifier quote: {}", quote));
+                    }
+                    export_options.identifier_quote = *quote;
+                }
+                _ => return Err(format!("Unsupported COPY option: {}", option)),
+            }
+        }
+        if let Some(option) = legacy_options.first() {
+            return Err(format!("Unsupported COPY option: {}", option));
+        }
+        if !is_sql {
+            return Err("COPY TO can only export with FORMAT sql".to_string());
+        }
+        Ok(export_options)
+    }
+}
+
+/// Exports a table into a file, as a script that recreates the table and its rows.
+/// The script can be run again with GQL, so strings, identifiers and timestamps are all
+/// written in a form that parses back into the same values.
+/// The file is found the same way as with `get_export_path`.
+pub fn export_sql(
+    table_name: &String,
+    file_path: &String,
+    options: &SqlExportOptions,
+    database: &Database,
+    user: &mut User,
+) -> Result<String, Error> {
+    let export_path: String = get_export_path(file_path, user)?;
+    let (script, num_rows) = table_to_sql(table_name, options, database, user)?;
+    get_storage_backend(&export_path).write_file(&export_path, script.as_bytes())?;
+    Ok(format!(
+        "{} rows were exported from {}.",
+        num_rows, table_name
+    ))
+}
+
+/// Writes the CREATE TABLE and INSERT statements for a table, along with the number of rows.
+fn table_to_sql(
+    table_name: &String,
+    options: &SqlExportOptions,
+    database: &Database,
+    user: &mut User,
+) -> Result<(String, usize), String> {
+    let table: Table = Table::from_user(user, database, table_name, None)?;
+    let quote = |name: &str| quote_identifier(name, options.identifier_quote);
+
+    // The hidden TTL column isn't written out, it's recreated by the table's TTL option
+    let visible: Vec<usize> = (0..table.schema.len())
+        .filter(|i| !is_ttl_column(&table.schema[*i].0))
+        .collect();
+    let columns: Vec<String> = visible
+        .iter()
+        .map(|i| {
+            let (name, column) = &table.schema[*i];
+            format!("{} {}", quote(name), column_type_sql(column))
+        })
+        .collect();
+    let mut script: String = format!(
+        "CREATE TABLE {} ({})",
+        quote(table_name),
+        columns.join(", ")
+    );
+    if let Some((_, ttl_seconds)) = get_ttl(&table) {
+        script.push_str(&format!(" WITH (ttl = {})", ttl_seconds));
+    }
+    script.push_str(";\n");
+
+    let mut values: Vec<String> = Vec::new();
+    let mut num_rows: usize = 0;
+    for row_info in table {
+        let row: Vec<String> = visible
+            .iter()
+            .map(|i| value_to_sql(&row_info.row[*i]))
+            .collect::<Result<Vec<String>, String>>()?;
+        values.push(format!("({})", row.join(", ")));
+        num_rows += 1;
+        if values.len() == ROWS_PER_INSERT {
+            script.push_str(&insert_sql(&quote(table_name), &values));
+            values.clear();
+        }
+    }
+    if !values.is_empty() {
+        script.push_str(&insert_sql(&quote(table_name), &values));
+    }
+    Ok((script, num_rows))
+}
+
+// A single INSERT statement for the rows, which are already written as tuples of literals
+fn insert_sql(table_name: &str, values: &[String]) -> String {
+    format!("INSERT INTO {} VALUES {};\n", table_name, values.join(", "))
+}
+
+/// Quotes an identifier if it's a keyword, or has characters that can't go in a bare identifier.
+/// Quotes inside the identifier are doubled, so `a"b` becomes `"a""b"`.
+fn quote_identifier(name: &str, quote: char) -> String {
+    let starts_bare: bool =
+        matches!(name.chars().next(), Some(c) if c.is_ascii_alphabetic() || c == '_');
+    let is_bare: bool = starts_bare
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !ALL_KEYWORDS.contains(&name.to_uppercase().as_str());
+    if is_bare {
+        return name.to_string();
+    }
+    let escaped: String = name.replace(quote, &format!("{}{}", quote, quote));
+    format!("{}{}{}", quote, escaped, quote)
+}
+
+/// Writes a value as a SQL literal. Strings have their single quotes doubled, and
+/// timestamps are written in the same format that they're parsed from.
+fn value_to_sql(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(x) | Value::Char(x) => Ok(format!("'{}'", x.replace('\'', "''"))),
+        Value::Timestamp(x) => {
+            let time = NaiveDateTime::from_timestamp_opt(x.seconds, x.nanos as u32)
+                .ok_or(format!("Cannot export timestamp {:?}", x))?;
+            Ok(format!("'{}'", time.format("%Y-%m-%d %H:%M:%S")))
+        }
+        Value::Time(x) => Ok(format!("'{}'", format_time_of_day(*x))),
+        Value::Float(x) if !x.is_finite() => Err(format!("Cannot export number {}", x)),
+        Value::Double(x) if !x.is_finite() => Err(format!("Cannot export number {}", x)),
+        Value::Float(x) => Ok(x.to_string()),
+        Value::Double(x) => Ok(x.to_string()),
+        Value::I32(x) => Ok(x.to_string()),
+        Value::I64(x) => Ok(x.to_string()),
+        Value::Bool(x) => Ok(x.to_string().to_uppercase()),
+        Value::Null(_) => Ok("NULL".to_string()),
+    }
+}
+
+// The SQL type that creates a column of the given type
+fn column_type_sql(column: &Column) -> String {
+    match column {
+        Column::I32 => "SMALLINT".to_string(),
+        Column::I64 => "INT".to_string(),
+        Column::Float => "FLOAT".to_string(),
+        Column::Double => "DOUBLE".to_string(),
+        Column::Bool => "BOOLEAN".to_string(),
+        Column::Timestamp => "TIMESTAMP".to_string(),
+        Column::Time => "TIME".to_string(),
+        Column::String(size) => format!("VARCHAR({})", size),
+        Column::Char(size) => format!("CHAR({})", size),
+        Column::Nullable(column) => format!("{} NULL", column_type_sql(column)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::query::{execute_query, execute_update},
+        fileio::databaseio::{delete_db_instance, get_db_instance},
+        parser::parser::parse,
+        util::{bench::fcreate_db_instance, row::Row},
+    };
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_copy_to_sql() {
+        let sql_name: String = "test_copy_to_sql.sql".to_string();
+        fcreate_db_instance("test_copy_to_sql");
+        let mut user: User = User::new("test_user".to_string());
+        let sql_path: String = get_export_path(&sql_name, &user).unwrap();
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE notes (id INT, \"order\" INT, \"user\" VARCHAR(20), \
+            \"first name\" VARCHAR(20) NULL, created TIMESTAMP, score DOUBLE NULL)",
+            "INSERT INTO notes VALUES (1, 10, 'It''s', 'O''Brien', '2023-01-02 03:04:05', 2.5), \
+            (2, 20, 'a; b', NULL, '1999-12-31 23:59:59', NULL)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let select = |user: &mut User| -> Vec<Row> {
+            execute_query(
+                &parse("SELECT * FROM notes ORDER BY id", false).unwrap(),
+                user,
+                &"".to_string(),
+            )
+            .unwrap()
+            .1
+        };
+        let rows: Vec<Row> = select(&mut user);
+
+        let message: String = execute_update(
+            &parse(
+                &format!("COPY notes TO '{}' WITH (FORMAT sql)", sql_name),
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(message, "2 rows were exported from notes.".to_string());
+        let script: String = std::fs::read_to_string(&sql_path).unwrap();
+        assert!(script.starts_with(
+            "CREATE TABLE notes (id INT, \"order\" INT, \"user\" VARCHAR(20), \
+            \"first name\" VARCHAR(20) NULL, created TIMESTAMP, score DOUBLE NULL);\n"
+        ));
+        assert!(script.contains("(1, 10, 'It''s', 'O''Brien', '2023-01-02 03:04:05', 2.5)"));
+        delete_db_instance().unwrap();
+
+        // Running the script in a new database recreates the same rows
+        fcreate_db_instance("test_copy_to_sql_restored");
+        execute_update(&parse(&script, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(select(&mut user), rows);
+
+        // Only SQL can be exported, and only with a quote that identifiers can be wrapped in
+        let copy = |options: &str, user: &mut User| {
+            execute_update(
+                &parse(&format!("COPY notes TO '{}' {}", sql_name, options), true).unwrap(),
+                user,
+                &"".to_string(),
+            )
+        };
+        assert!(copy("WITH (FORMAT csv)", &mut user).is_err());
+        assert!(copy("WITH (FORMAT sql, QUOTE '''')", &mut user).is_err());
+        copy("WITH (FORMAT sql, QUOTE '`')", &mut user).unwrap();
+        assert!(std::fs::read_to_string(&sql_path)
+            .unwrap()
+            .contains("`first name` VARCHAR(20) NULL"));
+
+        std::fs::remove_file(&sql_path).unwrap();
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_copy_to_sql_restricted() {
+        fcreate_db_instance("test_copy_to_sql_restricted");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse("CREATE TABLE numbers (id INT)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let options: SqlExportOptions = SqlExportOptions::default();
+        let export = |file_path: &str, user: &mut User| {
+            export_sql(
+                &"numbers".to_string(),
+                &file_path.to_string(),
+                &options,
+                get_db_instance().unwrap(),
+                user,
+            )
+        };
+
+        // Users other than admins can't write the files outside of the export directory
+        for file_path in [
+            "",
+            "/tmp/test_copy_to_sql_restricted.sql",
+            "../test_copy_to_sql_restricted.sql",
+            "nested/../../test_copy_to_sql_restricted.sql",
+        ] {
+            assert!(export(file_path, &mut user).is_err());
+        }
+
+        // And users who can only read can't export at all
+        user.set_permissions(&Read);
+        assert!(export("test_copy_to_sql_restricted.sql", &mut user).is_err());
+        assert!(!std::path::Path::new(DEFAULT_EXPORT_DIR)
+            .join("test_copy_to_sql_restricted.sql")
+            .exists());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("name", '"'), "name");
+        assert_eq!(quote_identifier("select", '"'), "\"select\"");
+        assert_eq!(quote_identifier("a\"b", '"'), "\"a\"\"b\"");
+        assert_eq!(quote_identifier("1st", '`'), "`1st`");
+    }
+}
@@ -0,0 +1,841 @@
+use std::cmp::Ordering;
+
+use sqlparser::ast::{Expr, Function, FunctionArg, FunctionArgExpr};
+
+use super::predicate::JointValues;
+use crate::user::userdata::{get_selected_session, next_selected_random};
+use crate::util::dbtype::{Column, Value};
+
+/// Whether the function is worked out from the values of a single row, like `GREATEST(a, b)`,
+/// rather than from a group of rows like an aggregate.
+pub fn is_scalar_function(func: &Function) -> bool {
+    matches!(
+        func.name.to_string().to_lowercase().as_str(),
+        "greatest"
+            | "least"
+            | "mod"
+            | "pow"
+            | "power"
+            | "left"
+            | "right"
+            | "ltrim"
+            | "rtrim"
+            | "replace"
+            | "current_user"
+            | "current_branch"
+            | "random"
+    )
+}
+
+/// Gets the expressions passed to a scalar function, in order
+pub fn get_function_args(func: &Function) -> Result<Vec<&Expr>, String> {
+    func.args
+        .iter()
+        .map(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Ok(expr),
+            _ => Err(format!("Unsupported arguments {} for {}", arg, func.name)),
+        })
+        .collect()
+}
+
+/// Applies a scalar function to the values of its arguments
+pub fn apply_scalar_function(func: &Function, args: Vec<Value>) -> Result<Value, String> {
+    match func.name.to_string().to_lowercase().as_str() {
+        "greatest" => extreme_value(func, args, Ordering::Greater),
+        "least" => extreme_value(func, args, Ordering::Less),
+        "mod" => modulo(func, args),
+        "pow" | "power" => power(func, args),
+        "left" => keep_characters(func, args, true),
+        "right" => keep_characters(func, args, false),
+        "ltrim" => trim_spaces(func, args, true),
+        "rtrim" => trim_spaces(func, args, false),
+        "replace" => replace(func, args),
+        "current_user" => session_value(func, args, |(user_id, _)| user_id),
+        "current_branch" => session_value(func, args, |(_, branch_name)| branch_name),
+        "random" => random(func, args),
+        _ => Err(format!("Unsupported function: {}", func.name)),
+    }
+}
+
+// CURRENT_USER() and CURRENT_BRANCH() give the id of the user running the statement,
+// and the branch they're on
+fn session_value(
+    func: &Function,
+    args: Vec<Value>,
+    get: fn((String, String)) -> String,
+) -> Result<Value, String> {
+    if !args.is_empty() {
+        return Err(format!(
+            "{} expects no arguments, but was given {}",
+            func.name,
+            args.len()
+        ));
+    }
+    let session: (String, String) =
+        get_selected_session().ok_or(format!("{} needs a session to run in", func.name))?;
+    Ok(Value::String(get(session)))
+}
+
+// RANDOM() gives a number from 0 up to 1, which is different for each row it's worked out for.
+// It's seeded with the session's sample_seed, so `ORDER BY RANDOM() LIMIT n` can be repeated.
+fn random(func: &Function, args: Vec<Value>) -> Result<Value, String> {
+    if !args.is_empty() {
+        return Err(format!(
+            "{} expects no arguments, but was given {}",
+            func.name,
+            args.len()
+        ));
+    }
+    let value: f32 =
+        next_selected_random().ok_or(format!("{} needs a session to run in", func.name))?;
+    Ok(Value::Float(value))
+}
+
+// Finds the greatest or least of the values, promoting numbers to the widest type among them.
+// As in the SQL standard, the result is null if any of the values are null.
+fn extreme_value(func: &Function, args: Vec<Value>, keep: Ordering) -> Result<Value, String> {
+    if args.is_empty() {
+        return Err(format!("{} needs at least one argument", func.name));
+    }
+    let (nulls, values): (Vec<&Value>, Vec<&Value>) = args.iter().partition(|arg| arg.is_null());
+    let mut coltype: Option<Column> = None;
+    for arg in values {
+        coltype = Some(match coltype {
+            None => arg.get_coltype(),
+            Some(coltype) => promote_types(&coltype, &arg.get_coltype()).ok_or(format!(
+                "Cannot compare {:?} and {:?} in {}",
+                coltype,
+                arg.get_coltype(),
+                func.name
+            ))?,
+        });
+    }
+    // Nulls from a column keep its type, but a null literal is always an I32,
+    // so the type of a null is only used when it can be compared with the other values
+    for arg in nulls {
+        if let Value::Null(null_type) = arg {
+            coltype = match coltype {
+                None => Some(null_type.clone()),
+                Some(coltype) => promote_types(&coltype, null_type).or(Some(coltype)),
+            };
+        }
+    }
+    let coltype: Column = coltype.unwrap_or(Column::I32);
+    if args.iter().any(|arg| arg.is_null()) {
+        return Ok(Value::Null(coltype));
+    }
+
+    let mut result: Value = args[0].clone();
+    for arg in args.into_iter().skip(1) {
+        if arg.partial_cmp(&result) == Some(keep) {
+            result = arg;
+        }
+    }
+    coltype.coerce_type(result)
+}
+
+// MOD(a, b) is the same as `a % b`, so the remainder has the sign of `a`.
+// The result is null if either value is null.
+fn modulo(func: &Function, args: Vec<Value>) -> Result<Value, String> {
+    let (left, right) = get_numeric_pair(func, args)?;
+    if left.is_null() || right.is_null() {
+        let coltype: Column = promote_types(&get_type(&left), &get_type(&right)).ok_or(format!(
+            "Cannot apply {} to {:?} and {:?}",
+            func.name, left, right
+        ))?;
+        return Ok(Value::Null(coltype));
+    }
+    JointValues::DBValue(left)
+        .modulo(&JointValues::DBValue(right))?
+        .unpack()
+}
+
+// POW(base, exp) raises the base to the power of the exponent, which can be fractional.
+// The result is always a float, and is null if either value is null.
+fn power(func: &Function, args: Vec<Value>) -> Result<Value, String> {
+    let (base, exponent) = match get_numeric_pair(func, args)? {
+        (base, exponent) if base.is_null() || exponent.is_null() => {
+            return Ok(Value::Null(Column::Float))
+        }
+        (base, exponent) => (to_f64(&base), to_f64(&exponent)),
+    };
+    let result: f64 = base.powf(exponent);
+    if result.is_nan() {
+        return Err(format!(
+            "{} of {} to the power of {} is not a number",
+            func.name, base, exponent
+        ));
+    }
+    Ok(Value::Float(result as f32))
+}
+
+// LEFT(s, n) and RIGHT(s, n) keep the first or last n characters of the string,
+// or all of it if it's shorter. The result is null if either value is null.
+fn keep_characters(func: &Function, args: Vec<Value>, from_start: bool) -> Result<Value, String> {
+    let name: String = func.name.to_string();
+    let (value, count) = match <[Value; 2]>::try_from(args) {
+        Ok([value, count]) => (value, count),
+        Err(args) => {
+            return Err(format!(
+                "{} expects two arguments, but was given {}",
+                name,
+                args.len()
+            ))
+        }
+    };
+    let (string, count) = match (get_string(&name, value)?, get_integer(&name, count)?) {
+        (Some(string), Some(count)) => (string, count),
+        _ => return Ok(Value::Null(Column::String(0))),
+    };
+    if count < 0 {
+        return Err(format!("{} length cannot be negative: {}", name, count));
+    }
+    let length: usize = string.chars().count();
+    let count: usize = (count as usize).min(length);
+    let result: String = match from_start {
+        true => string.chars().take(count).collect(),
+        false => string.chars().skip(length - count).collect(),
+    };
+    Ok(Value::String(result))
+}
+
+// LTRIM(s) and RTRIM(s) remove the spaces from the start or end of the string.
+// The result is null if the value is null.
+fn trim_spaces(func: &Function, args: Vec<Value>, from_start: bool) -> Result<Value, String> {
+    let name: String = func.name.to_string();
+    let value: Value = match <[Value; 1]>::try_from(args) {
+        Ok([value]) => value,
+        Err(args) => {
+            return Err(format!(
+                "{} expects one argument, but was given {}",
+                name,
+                args.len()
+            ))
+        }
+    };
+    Ok(match get_string(&name, value)? {
+        Some(string) if from_start => Value::String(string.trim_start_matches(' ').to_string()),
+        Some(string) => Value::String(string.trim_end_matches(' ').to_string()),
+        None => Value::Null(Column::String(0)),
+    })
+}
+
+// REPLACE(s, from, to) replaces every occurrence of `from` in the string with `to`, going from
+// left to right so occurrences don't overlap. An empty `from` leaves the string as it is.
+// The result is null if any value is null.
+fn replace(func: &Function, args: Vec<Value>) -> Result<Value, String> {
+    let name: String = func.name.to_string();
+    let strings: Vec<Option<String>> = match args.len() {
+        3 => args
+            .into_iter()
+            .map(|arg| get_string(&name, arg))
+            .collect::<Result<Vec<Option<String>>, String>>()?,
+        _ => {
+            return Err(format!(
+                "{} expects three arguments, but was given {}",
+                name,
+                args.len()
+            ))
+        }
+    };
+    Ok(match strings.as_slice() {
+        [Some(string), Some(from), Some(_)] if from.is_empty() => Value::String(string.clone()),
+        [Some(string), Some(from), Some(to)] => Value::String(string.replace(from, to)),
+        _ => Value::Null(Column::String(0)),
+    })
+}
+
+/// Takes the part of a string that starts at the 1-based position `from`, and is at most `length`
+/// characters long, like `SUBSTRING(s FROM 2 FOR 3)`. As in the SQL standard, a start before the
+/// first character still counts towards the length. The result is null if any value is null.
+pub fn substring(
+    value: Value,
+    from: Option<Value>,
+    length: Option<Value>,
+) -> Result<Value, String> {
+    let from: Option<i64> = match from {
+        Some(from) => get_integer("SUBSTRING", from)?,
+        None => Some(1),
+    };
+    let length: Option<Option<i64>> = length
+        .map(|length| get_integer("SUBSTRING", length))
+        .transpose()?;
+    let (string, from) = match (get_string("SUBSTRING", value)?, from) {
+        (Some(string), Some(from)) if length != Some(None) => (string, from),
+        _ => return Ok(Value::Null(Column::String(0))),
+    };
+
+    let start: i64 = from.max(1);
+    let chars = string.chars().skip((start - 1) as usize);
+    let result: String = match length.flatten() {
+        Some(length) if length < 0 => {
+            return Err(format!("SUBSTRING length cannot be negative: {}", length))
+        }
+        Some(length) => chars
+            .take((from + length - start).max(0) as usize)
+            .collect(),
+        None => chars.collect(),
+    };
+    Ok(Value::String(result))
+}
+
+/// Finds the 1-based position of the first place `sub` appears in `string`, like
+/// `POSITION('b' IN 'abc')`, or 0 if it doesn't appear. The result is null if either value is null.
+pub fn position(sub: Value, string: Value) -> Result<Value, String> {
+    match (
+        get_string("POSITION", sub)?,
+        get_string("POSITION", string)?,
+    ) {
+        (Some(sub), Some(string)) => Ok(Value::I64(match string.find(&sub) {
+            Some(index) => string[..index].chars().count() as i64 + 1,
+            None => 0,
+        })),
+        _ => Ok(Value::Null(Column::I64)),
+    }
+}
+
+/// Whether `value` matches a LIKE `pattern`, where `%` matches any run of characters and `_`
+/// matches exactly one. A character after the escape character is matched as it is. Both are
+/// lowercased first when `case_insensitive` is set, as for ILIKE.
+/// The result is null if either value is null.
+pub fn like(
+    value: Value,
+    pattern: Value,
+    escape_char: Option<char>,
+    case_insensitive: bool,
+) -> Result<Value, String> {
+    let (value, pattern) = match (get_string("LIKE", value)?, get_string("LIKE", pattern)?) {
+        (Some(value), Some(pattern)) => (value, pattern),
+        _ => return Ok(Value::Null(Column::Bool)),
+    };
+    let mut tokens: Vec<LikeToken> = parse_like_pattern(&pattern, escape_char)?;
+    let mut value: Vec<char> = value.chars().collect();
+    if case_insensitive {
+        tokens = tokens
+            .into_iter()
+            .flat_map(|token| match token {
+                LikeToken::Char(c) => c.to_lowercase().map(LikeToken::Char).collect(),
+                token => vec![token],
+            })
+            .collect();
+        value = value.into_iter().flat_map(char::to_lowercase).collect();
+    }
+    Ok(Value::Bool(like_matches(&value, &tokens)))
+}
+
+// A part of a LIKE pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LikeToken {
+    Char(char), // Matches just this character
+    One,        // `_`, which matches any one character
+    Any,        // `%`, which matches any number of characters
+}
+
+fn parse_like_pattern(pattern: &str, escape_char: Option<char>) -> Result<Vec<LikeToken>, String> {
+    let mut tokens: Vec<LikeToken> = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            c if Some(c) == escape_char => match chars.next() {
+                Some(escaped) => LikeToken::Char(escaped),
+                None => {
+                    return Err(format!(
+                        "LIKE pattern {} cannot end with its escape character",
+                        pattern
+                    ))
+                }
+            },
+            '%' => LikeToken::Any,
+            '_' => LikeToken::One,
+            c => LikeToken::Char(c),
+        });
+    }
+    Ok(tokens)
+}
+
+// Matches from left to right, going back to the last `%` to have it take one more character
+// whenever the rest of the pattern doesn't match, so no character is looked at too many times
+fn like_matches(value: &[char], pattern: &[LikeToken]) -> bool {
+    let (mut v, mut p): (usize, usize) = (0, 0);
+    // Where the pattern continues after the last `%`, and where its match in the value ends
+    let mut last_any: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some(LikeToken::Any) => {
+                last_any = Some((p + 1, v));
+                p += 1;
+            }
+            Some(LikeToken::One) => {
+                v += 1;
+                p += 1;
+            }
+            Some(LikeToken::Char(c)) if *c == value[v] => {
+                v += 1;
+                p += 1;
+            }
+            _ => match last_any {
+                Some((any_p, any_v)) => {
+                    last_any = Some((any_p, any_v + 1));
+                    p = any_p;
+                    v = any_v + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|token| *token == LikeToken::Any)
+}
+
+// The text of a string without the padding a CHAR keeps, or None if it is null
+fn get_string(name: &str, value: Value) -> Result<Option<String>, String> {
+    match value {
+        Value::String(x) => Ok(Some(x)),
+        Value::Char(x) => Ok(Some(x.trim_end_matches(' ').to_string())),
+        Value::Null(_) => Ok(None),
+        _ => Err(format!(
+            "{} expects a string, but was given {:?}",
+            name, value
+        )),
+    }
+}
+
+// The value of a whole number, or None if it is null
+fn get_integer(name: &str, value: Value) -> Result<Option<i64>, String> {
+    match value {
+        Value::I32(x) => Ok(Some(x as i64)),
+        Value::I64(x) => Ok(Some(x)),
+        Value::Null(_) => Ok(None),
+        _ => Err(format!(
+            "{} expects a whole number, but was given {:?}",
+            name, value
+        )),
+    }
+}
+
+// Checks that a function was given exactly two numbers, which may be null
+fn get_numeric_pair(func: &Function, args: Vec<Value>) -> Result<(Value, Value), String> {
+    let is_numeric = |value: &Value| {
+        matches!(
+            get_type(value),
+            Column::I32 | Column::I64 | Column::Float | Column::Double
+        )
+    };
+    match <[Value; 2]>::try_from(args) {
+        Ok([left, right]) if is_numeric(&left) && is_numeric(&right) => Ok((left, right)),
+        Ok([left, right]) => Err(format!(
+            "{} expects two numbers, but was given {:?} and {:?}",
+            func.name, left, right
+        )),
+        Err(args) => Err(format!(
+            "{} expects two arguments, but was given {}",
+            func.name,
+            args.len()
+        )),
+    }
+}
+
+// The type of a value, which for a null is the type of the column it came from
+fn get_type(value: &Value) -> Column {
+    match value {
+        Value::Null(coltype) => coltype.clone(),
+        _ => value.get_coltype(),
+    }
+}
+
+// The value of a number as a double. Only called on values that are numbers.
+fn to_f64(value: &Value) -> f64 {
+    match value {
+        Value::I32(x) => *x as f64,
+        Value::I64(x) => *x as f64,
+        Value::Float(x) => *x as f64,
+        Value::Double(x) => *x,
+        _ => f64::NAN,
+    }
+}
+
+// The type that values of both types can be compared as, following the same promotions as
+// arithmetic does. Returns None if the two types can't be compared.
+pub fn promote_types(left: &Column, right: &Column) -> Option<Column> {
+    match (left, right) {
+        (Column::String(_) | Column::Char(_), Column::String(_) | Column::Char(_)) => {
+            Some(Column::String(0))
+        }
+        (Column::I32, Column::I64) | (Column::I64, Column::I32) => Some(Column::I64),
+        (Column::I32, Column::Float) | (Column::Float, Column::I32) => Some(Column::Float),
+        (Column::I64, Column::Float) | (Column::Float, Column::I64) => Some(Column::Double),
+        (Column::Double, Column::I32 | Column::I64 | Column::Float)
+        | (Column::I32 | Column::I64 | Column::Float, Column::Double) => Some(Column::Double),
+        _ if left == right => Some(left.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use crate::{
+        executor::query::{execute_query, execute_update},
+        fileio::databaseio::{delete_db_instance, get_db_instance},
+        parser::parser::{parse, parse_vc_cmd},
+        user::userdata::User,
+        util::{
+            bench::fcreate_db_instance,
+            dbtype::{Column, Value},
+            row::Row,
+        },
+    };
+
+    #[test]
+    #[serial]
+    fn test_greatest_and_least() {
+        fcreate_db_instance("greatest_and_least");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, a INT NULL, b SMALLINT, c DOUBLE, s VARCHAR(10))",
+            "INSERT INTO t VALUES (1, 5, 7, 2.5, 'x')",
+            "INSERT INTO t VALUES (2, NULL, 3, 9.5, 'y')",
+            "INSERT INTO t VALUES (3, 4, 6, 5.0, 'z')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
+
+        // The result is promoted to the widest of the three types, and is null if any are null
+        assert_eq!(
+            run("SELECT GREATEST(a, b, c), LEAST(a, b, c) FROM t", &mut user).unwrap(),
+            vec![
+                vec![Value::Double(7.0), Value::Double(2.5)],
+                vec![Value::Null(Column::Double), Value::Null(Column::Double)],
+                vec![Value::Double(6.0), Value::Double(4.0)],
+            ]
+        );
+        assert_eq!(
+            run("SELECT GREATEST(b, 4), LEAST(s, 'y') FROM t", &mut user).unwrap(),
+            vec![
+                vec![Value::I64(7), Value::String("x".to_string())],
+                vec![Value::I64(4), Value::String("y".to_string())],
+                vec![Value::I64(6), Value::String("y".to_string())],
+            ]
+        );
+
+        // They can be used anywhere a value can, including around aggregates
+        assert_eq!(
+            run("SELECT id FROM t WHERE GREATEST(b, c) > 6.5", &mut user).unwrap(),
+            vec![vec![Value::I64(1)], vec![Value::I64(2)]]
+        );
+        assert_eq!(
+            run(
+                "SELECT GREATEST(MAX(b), 10), MIN(LEAST(b, c)) FROM t",
+                &mut user
+            )
+            .unwrap(),
+            vec![vec![Value::I64(10), Value::Double(2.5)]]
+        );
+
+        assert!(run("SELECT GREATEST(s, b) FROM t", &mut user).is_err());
+        assert!(run("SELECT LEAST() FROM t", &mut user).is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_mod_and_pow() {
+        fcreate_db_instance("mod_and_pow");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, a INT NULL, b SMALLINT, s VARCHAR(10))",
+            "INSERT INTO t VALUES (1, -7, 3, 'x')",
+            "INSERT INTO t VALUES (2, 7, -3, 'y')",
+            "INSERT INTO t VALUES (3, NULL, 4, 'z')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
+
+        // The remainder has the sign of the dividend, the same as with %
+        assert_eq!(
+            run("SELECT MOD(a, b), MOD(b, 2) FROM t", &mut user).unwrap(),
+            vec![
+                vec![Value::I64(-1), Value::I64(1)],
+                vec![Value::I64(1), Value::I64(-1)],
+                vec![Value::Null(Column::I64), Value::I64(0)],
+            ]
+        );
+        assert_eq!(
+            run("SELECT MOD(a, b) = a % b FROM t WHERE id < 3", &mut user).unwrap(),
+            vec![vec![Value::Bool(true)], vec![Value::Bool(true)]]
+        );
+        // A negative number has no real square root
+        assert!(run("SELECT POW(b, 0.5) FROM t WHERE id = 2", &mut user).is_err());
+
+        assert!(run("SELECT MOD(s, 2) FROM t", &mut user).is_err());
+        assert!(run("SELECT POW(2, s) FROM t", &mut user).is_err());
+        assert!(run("SELECT MOD(a) FROM t", &mut user).is_err());
+        assert!(run("SELECT MOD(a, 0) FROM t WHERE id = 1", &mut user).is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_substring_and_position() {
+        fcreate_db_instance("substring_and_position");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, s VARCHAR(20) NULL)",
+            "INSERT INTO t VALUES (1, 'hello world')",
+            "INSERT INTO t VALUES (2, 'abc')",
+            "INSERT INTO t VALUES (3, NULL)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
+        let string = |x: &str| Value::String(x.to_string());
+
+        // Positions start at 1, and the length is cut short at the end of the string
+        assert_eq!(
+            run(
+                "SELECT SUBSTRING(s FROM 2 FOR 3), SUBSTRING(s FROM 7) FROM t",
+                &mut user
+            )
+            .unwrap(),
+            vec![
+                vec![string("ell"), string("world")],
+                vec![string("bc"), string("")],
+                vec![
+                    Value::Null(Column::String(0)),
+                    Value::Null(Column::String(0))
+                ],
+            ]
+        );
+        // A start before the first character still uses up the length
+        assert_eq!(
+            run(
+                "SELECT SUBSTRING(s FROM -1 FOR 3) FROM t WHERE id = 2",
+                &mut user
+            )
+            .unwrap(),
+            vec![vec![string("a")]]
+        );
+
+        // POSITION gives the 1-based index of the first match, or 0 when there isn't one
+        assert_eq!(
+            run(
+                "SELECT POSITION('o' IN s), POSITION('xyz' IN s) FROM t",
+                &mut user
+            )
+            .unwrap(),
+            vec![
+                vec![Value::I64(5), Value::I64(0)],
+                vec![Value::I64(0), Value::I64(0)],
+                vec![Value::Null(Column::I64), Value::Null(Column::I64)],
+            ]
+        );
+        assert_eq!(
+            run("SELECT id FROM t WHERE POSITION('bc' IN s) > 0", &mut user).unwrap(),
+            vec![vec![Value::I64(2)]]
+        );
+
+        assert!(run("SELECT SUBSTRING(s FROM 1 FOR -1) FROM t", &mut user).is_err());
+        assert!(run("SELECT SUBSTRING(id FROM 1) FROM t", &mut user).is_err());
+        assert!(run("SELECT POSITION(1 IN s) FROM t", &mut user).is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_left_right_and_trims() {
+        fcreate_db_instance("left_right_and_trims");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, s VARCHAR(20) NULL, n INT NULL)",
+            "INSERT INTO t VALUES (1, '  hello  ', 4)",
+            "INSERT INTO t VALUES (2, 'abc', 10)",
+            "INSERT INTO t VALUES (3, NULL, 2)",
+            "INSERT INTO t VALUES (4, 'xyz', NULL)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
+        let string = |x: &str| Value::String(x.to_string());
+        let null: Value = Value::Null(Column::String(0));
+
+        // A length longer than the string keeps all of it, and a null value or length gives null
+        assert_eq!(
+            run("SELECT LEFT(s, n), RIGHT(s, n) FROM t", &mut user).unwrap(),
+            vec![
+                vec![string("  he"), string("lo  ")],
+                vec![string("abc"), string("abc")],
+                vec![null.clone(), null.clone()],
+                vec![null.clone(), null.clone()],
+            ]
+        );
+        assert_eq!(
+            run(
+                "SELECT LEFT(s, 0), RIGHT(s, 100) FROM t WHERE id = 2",
+                &mut user
+            )
+            .unwrap(),
+            vec![vec![string(""), string("abc")]]
+        );
+
+        // Only the spaces on one side are removed
+        assert_eq!(
+            run("SELECT LTRIM(s), RTRIM(s) FROM t", &mut user).unwrap(),
+            vec![
+                vec![string("hello  "), string("  hello")],
+                vec![string("abc"), string("abc")],
+                vec![null.clone(), null.clone()],
+                vec![string("xyz"), string("xyz")],
+            ]
+        );
+        assert_eq!(
+            run(
+                "SELECT id FROM t WHERE RTRIM(LTRIM(s)) = 'hello'",
+                &mut user
+            )
+            .unwrap(),
+            vec![vec![Value::I64(1)]]
+        );
+
+        assert!(run("SELECT LEFT(s, -1) FROM t", &mut user).is_err());
+        assert!(run("SELECT RIGHT(n, 1) FROM t", &mut user).is_err());
+        assert!(run("SELECT LEFT(s) FROM t", &mut user).is_err());
+        assert!(run("SELECT LTRIM(s, n) FROM t", &mut user).is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_replace() {
+        fcreate_db_instance("replace");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, s VARCHAR(20) NULL)",
+            "INSERT INTO t VALUES (1, 'banana')",
+            "INSERT INTO t VALUES (2, 'aaaa')",
+            "INSERT INTO t VALUES (3, NULL)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
+        let string = |x: &str| Value::String(x.to_string());
+        let null: Value = Value::Null(Column::String(0));
+
+        // Every occurrence is replaced, without the replacements overlapping
+        assert_eq!(
+            run(
+                "SELECT REPLACE(s, 'an', 'AN'), REPLACE(s, 'aa', 'b') FROM t",
+                &mut user
+            )
+            .unwrap(),
+            vec![
+                vec![string("bANANa"), string("banana")],
+                vec![string("aaaa"), string("bb")],
+                vec![null.clone(), null.clone()],
+            ]
+        );
+        assert_eq!(
+            run("SELECT REPLACE(s, 'a', '') FROM t WHERE id = 1", &mut user).unwrap(),
+            vec![vec![string("bnn")]]
+        );
+
+        // The string is returned as it is when there's nothing to replace
+        assert_eq!(
+            run(
+                "SELECT REPLACE(s, 'xyz', 'q'), REPLACE(s, '', 'q') FROM t WHERE id = 1",
+                &mut user
+            )
+            .unwrap(),
+            vec![vec![string("banana"), string("banana")]]
+        );
+        assert_eq!(
+            run(
+                "SELECT REPLACE(s, 'a', NULL), REPLACE('a', s, 'b') FROM t WHERE id = 1",
+                &mut user
+            )
+            .unwrap(),
+            vec![vec![null.clone(), string("a")]]
+        );
+
+        assert!(run("SELECT REPLACE(s, 'a') FROM t", &mut user).is_err());
+        assert!(run("SELECT REPLACE(id, 'a', 'b') FROM t", &mut user).is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_current_user_and_branch() {
+        fcreate_db_instance("current_user_and_branch");
+        let mut user: User = User::new("test_user".to_string());
+        let main_branch: String = get_db_instance().unwrap().get_main_branch_name();
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE audit (id INT, who VARCHAR(20), branch VARCHAR(20))",
+            "INSERT INTO audit VALUES (1, CURRENT_USER(), CURRENT_BRANCH())",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
+        let string = |x: &str| Value::String(x.to_string());
+
+        // They can be selected on their own, and CURRENT_USER can be written without parentheses
+        assert_eq!(
+            run("SELECT CURRENT_USER(), CURRENT_BRANCH()", &mut user).unwrap(),
+            vec![vec![string("test_user"), string(&main_branch)]]
+        );
+        assert_eq!(
+            run("SELECT CURRENT_USER AS who", &mut user).unwrap(),
+            vec![vec![string("test_user")]]
+        );
+
+        // The branch follows the user when they switch to another one
+        parse_vc_cmd("gql commit -m audit", &mut user, Vec::new()).unwrap();
+        parse_vc_cmd("gql branch audit_branch", &mut user, Vec::new()).unwrap();
+        execute_update(
+            &parse(
+                "INSERT INTO audit VALUES (2, CURRENT_USER(), CURRENT_BRANCH())",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            run("SELECT who, branch FROM audit ORDER BY id", &mut user).unwrap(),
+            vec![
+                vec![string("test_user"), string(&main_branch)],
+                vec![string("test_user"), string("audit_branch")],
+            ]
+        );
+        assert_eq!(
+            run(
+                "SELECT id FROM audit WHERE branch = CURRENT_BRANCH()",
+                &mut user
+            )
+            .unwrap(),
+            vec![vec![Value::I64(2)]]
+        );
+
+        assert!(run("SELECT CURRENT_BRANCH(1)", &mut user).is_err());
+        delete_db_instance().unwrap();
+    }
+}
@@ -0,0 +1,524 @@
+use parking_lot::{const_mutex, Mutex};
+use sqlparser::ast::{CopyLegacyCsvOption, CopyLegacyOption, CopyOption};
+
+use super::query::insert;
+use super::ttl::is_ttl_column;
+use crate::fileio::{
+    databaseio::Database,
+    header::Schema,
+    storage::{get_storage_backend, resolve_path_within_dir},
+    tableio::Table,
+};
+use crate::user::{usercreds::UserPermissions::Admin, userdata::User};
+use crate::util::{
+    dbtype::{Column, Value},
+    row::Row,
+};
+
+// The directory that users other than admins import files from, unless the server sets another
+pub const DEFAULT_IMPORT_DIR: &str = "imports";
+
+// The directory set with --import-dir, or None to use the default one
+static IMPORT_DIR: Mutex<Option<String>> = const_mutex(None);
+
+/// Sets the directory that users other than admins import files from.
+pub fn set_import_dir(dir: &String) {
+    *IMPORT_DIR.lock() = Some(dir.clone());
+}
+
+/// Returns the path of the file to import. Admins can import any file, while everyone else
+/// can only import the files in the import directory, so their path is taken to be within it.
+pub fn get_import_path(file_path: &String, user: &User) -> Result<String, String> {
+    if user.get_permissions() == Admin {
+        return Ok(file_path.clone());
+    }
+    let import_dir: String = IMPORT_DIR
+        .lock()
+        .clone()
+        .unwrap_or(DEFAULT_IMPORT_DIR.to_string());
+    resolve_path_within_dir(&import_dir, file_path)
+}
+
+/// The options used when importing a CSV file into a table
+#[derive(Clone, Debug)]
+pub struct CsvImportOptions {
+    pub delimiter: char,   // The character that separates the fields of a line
+    pub header: bool,      // Whether the first line holds the column names, and should be skipped
+    pub skip_errors: bool, // Whether lines that can't be imported are skipped instead of stopping the import
+    pub null: String, // The unquoted field that stands for a null, which is an empty field by default
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            header: false,
+            skip_errors: false,
+            null: String::new(),
+        }
+    }
+}
+
+impl CsvImportOptions {
+    /// Builds the import options from the options given to a `COPY ... FROM` statement.
+    pub fn from_copy_options(
+        options: &Vec<CopyOption>,
+        legacy_options: &Vec<CopyLegacyOption>,
+    ) -> Result<CsvImportOptions, String> {
+        let mut import_options: CsvImportOptions = CsvImportOptions::default();
+        for option in options {
+            match option {
+                CopyOption::Format(format) => {
+                    if format.value.to_lowercase() != "csv" {
+                        return Err(format!("Unsupported COPY format: {}", format));
+                    }
+                }
+                CopyOption::Delimiter(delimiter) => import_options.delimiter = *delimiter,
+                CopyOption::Header(header) => import_options.header = *header,
+                CopyOption::Null(null) => import_options.null = null.clone(),
+                _ => return Err(format!("Unsupported COPY option: {}", option)),
+            }
+        }
+        for option in legacy_options {
+            match option {
+                CopyLegacyOption::Delimiter(delimiter) => import_options.delimiter = *delimiter,
+                CopyLegacyOption::Null(null) => import_options.null = null.clone(),
+                CopyLegacyOption::Csv(csv_options) => {
+                    for csv_option in csv_options {
+                        match csv_option {
+                            CopyLegacyCsvOption::Header => import_options.header = true,
+                            _ => return Err(format!("Unsupported COPY option: {}", csv_option)),
+                        }
+                    }
+                }
+                _ => return Err(format!("Unsupported COPY option: {}", option)),
+            }
+        }
+        Ok(import_options)
+    }
+}
+
+/// Imports the rows of a CSV file into the given table, using a single batched insert.
+/// Each field is parsed into the type of its column. Lines that can't be imported
+/// either stop the import, or are skipped if `skip_errors` is set. Either way, the
+/// errors are reported with the line number they happened on, but not the line itself.
+/// The file is found with get_import_path, so only admins can import from anywhere.
+pub fn import_csv(
+    table_name: &String,
+    file_path: &String,
+    options: &CsvImportOptions,
+    database: &Database,
+    user: &mut User,
+) -> Result<String, String> {
+    // The hidden TTL column is filled in when the rows are inserted
+    let schema: Schema = Table::from_user(user, database, table_name, None)?
+        .schema
+        .into_iter()
+        .filter(|(name, _)| !is_ttl_column(name))
+        .collect();
+    let import_path: String = get_import_path(file_path, user)?;
+    let bytes: Vec<u8> = get_storage_backend(&import_path).read_file(&import_path)?;
+    let contents: String = String::from_utf8(bytes)
+        .map_err(|_| format!("Error importing {}: The file is not valid UTF-8", file_path))?;
+
+    let mut rows: Vec<Row> = Vec::new();
+    let mut skipped_lines: Vec<String> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line_num: usize = i + 1;
+        if (options.header && line_num == 1) || line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_csv_row(line, options, &schema) {
+            Ok(row) => rows.push(row),
+            Err(err) => {
+                let err: String = format!("Line {}: {}", line_num, err);
+                if !options.skip_errors {
+                    return Err(format!("Error importing {}: {}", file_path, err));
+                }
+                skipped_lines.push(err);
+            }
+        }
+    }
+
+    let num_rows: usize = rows.len();
+    if num_rows > 0 {
+        insert(rows, table_name.clone(), database, user)?;
+    }
+
+    let mut message: String = format!("{} rows were imported into {}.", num_rows, table_name);
+    if skipped_lines.len() > 0 {
+        message.push_str(&format!(
+            "\n{} lines were skipped:\n{}",
+            skipped_lines.len(),
+            skipped_lines.join("\n")
+        ));
+    }
+    Ok(message)
+}
+
+/// Parses a single line of a CSV file into a row matching the given schema.
+/// An unquoted field that matches the null token is a null in a nullable column,
+/// so a quoted field can always hold a value that reads the same as the token.
+fn parse_csv_row(line: &str, options: &CsvImportOptions, schema: &Schema) -> Result<Row, String> {
+    let fields: Vec<(String, bool)> = split_csv_line(line, options.delimiter)?;
+    if fields.len() != schema.len() {
+        return Err(format!(
+            "Number of values ({}) does not match the number of columns in the table ({})",
+            fields.len(),
+            schema.len()
+        ));
+    }
+    fields
+        .iter()
+        .zip(schema.iter())
+        .map(|((field, quoted), (name, column))| {
+            let value: Result<Value, String> = match column {
+                Column::Nullable(x) if !quoted && *field == options.null => {
+                    Ok(Value::Null(*x.clone()))
+                }
+                Column::Nullable(x) => x.parse(field),
+                _ => column.parse(field),
+            };
+            // The parse error would hold the value itself, so only the column is named
+            value.map_err(|_| format!("Could not parse the value of column {}", name))
+        })
+        .collect::<Result<Row, String>>()
+}
+
+/// Splits a line of a CSV file into its fields, along with whether each one was quoted.
+/// Fields can be wrapped in double quotes to hold the delimiter, and a doubled quote
+/// inside a quoted field stands for a single quote.
+fn split_csv_line(line: &str, delimiter: char) -> Result<Vec<(String, bool)>, String> {
+    let mut fields: Vec<(String, bool)> = Vec::new();
+    let mut field: String = String::new();
+    let mut in_quotes: bool = false;
+    let mut quoted: bool = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == '"' {
+            in_quotes = true;
+            quoted = true;
+        } else if c == delimiter {
+            fields.push((field, quoted));
+            field = String::new();
+            quoted = false;
+        } else {
+            field.push(c);
+        }
+    }
+    if in_quotes {
+        return Err("Unterminated quoted value".to_string());
+    }
+    fields.push((field, quoted));
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::query::{execute_query, execute_update},
+        fileio::{
+            databaseio::{delete_db_instance, get_db_instance},
+            tableio::ExportFormat,
+        },
+        parser::parser::parse,
+        util::{
+            bench::fcreate_db_instance,
+            dbtype::{Column, Value},
+        },
+    };
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_copy_from_csv() {
+        let csv_path: String = "test_copy_from_csv.csv".to_string();
+        std::fs::write(
+            &csv_path,
+            "id,name,score\n1,Alice,3.5\n2,\"Smith, Bob\",\n3,\"Say \"\"hi\"\"\",10\n",
+        )
+        .unwrap();
+
+        fcreate_db_instance("test_copy_from_csv");
+        let mut user: User = User::new("test_user".to_string());
+        // Admins can import files from anywhere
+        user.set_permissions(&Admin);
+        execute_update(
+            &parse(
+                "CREATE TABLE people (id INT, name VARCHAR(20), score DOUBLE NULL)",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        let message: String = execute_update(
+            &parse(
+                &format!("COPY people FROM '{}' WITH (FORMAT csv, HEADER)", csv_path),
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(message, "3 rows were imported into people.".to_string());
+
+        let (_, results) = execute_query(
+            &parse("SELECT id, name, score FROM people ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![
+                    Value::I64(1),
+                    Value::String("Alice".to_string()),
+                    Value::Double(3.5)
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Smith, Bob".to_string()),
+                    Value::Null(Column::Double)
+                ],
+                vec![
+                    Value::I64(3),
+                    Value::String("Say \"hi\"".to_string()),
+                    Value::Double(10.0)
+                ],
+            ]
+        );
+
+        std::fs::remove_file(&csv_path).unwrap();
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_import_csv_errors() {
+        let csv_path: String = "test_import_csv_errors.csv".to_string();
+        std::fs::write(&csv_path, "1;one\ntwo;two\n3;three;extra\n4;four\n").unwrap();
+
+        fcreate_db_instance("test_import_csv_errors");
+        let mut user: User = User::new("test_user".to_string());
+        user.set_permissions(&Admin);
+        execute_update(
+            &parse("CREATE TABLE numbers (id INT, name VARCHAR(20))", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        // By default, the import stops at the first bad line and inserts nothing
+        let mut options: CsvImportOptions = CsvImportOptions {
+            delimiter: ';',
+            ..CsvImportOptions::default()
+        };
+        let result = import_csv(
+            &"numbers".to_string(),
+            &csv_path,
+            &options,
+            get_db_instance().unwrap(),
+            &mut user,
+        );
+        let err: String = result.unwrap_err();
+        assert!(err.contains("Line 2:"));
+        // The contents of the file aren't echoed back
+        assert!(!err.contains("two"));
+        assert_eq!(user.get_diffs().len(), 1);
+
+        // When skipping errors, the good lines are imported and the bad ones are reported
+        options.skip_errors = true;
+        let message: String = import_csv(
+            &"numbers".to_string(),
+            &csv_path,
+            &options,
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
+        assert!(message.starts_with("2 rows were imported into numbers."));
+        assert!(message.contains("Line 2:"));
+        assert!(message.contains("Line 3:"));
+
+        let (_, results) = execute_query(
+            &parse("SELECT id FROM numbers ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results, vec![vec![Value::I64(1)], vec![Value::I64(4)]]);
+
+        std::fs::remove_file(&csv_path).unwrap();
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_csv_null_token() {
+        let csv_path: String = "test_csv_null_token.csv".to_string();
+        fcreate_db_instance("test_csv_null_token");
+        let mut user: User = User::new("test_user".to_string());
+        user.set_permissions(&Admin);
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE notes (id INT, note VARCHAR(20) NULL, score INT NULL)",
+            "CREATE TABLE copies (id INT, note VARCHAR(20) NULL, score INT NULL)",
+            "INSERT INTO notes VALUES (1, '', 5), (2, NULL, NULL), (3, '\\N', 7), (4, 'NULL', 8)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let select = |table_name: &str, user: &mut User| {
+            let query: String = format!("SELECT * FROM {} ORDER BY id", table_name);
+            execute_query(&parse(&query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let rows = select("notes", &mut user);
+
+        // Under any token, the empty string and the null both come back as they were
+        for null in ["", "\\N", "NULL"] {
+            let table: Table = Table::from_user(
+                &user,
+                get_db_instance().unwrap(),
+                &"notes".to_string(),
+                None,
+            )
+            .unwrap();
+            let mut file = std::fs::File::create(&csv_path).unwrap();
+            table
+                .export_stream(
+                    &mut file,
+                    ExportFormat::Csv {
+                        null: null.to_string(),
+                    },
+                )
+                .unwrap();
+
+            execute_update(
+                &parse("DELETE FROM copies", true).unwrap(),
+                &mut user,
+                &"".to_string(),
+            )
+            .unwrap();
+            execute_update(
+                &parse(
+                    &format!(
+                        "COPY copies FROM '{}' WITH (FORMAT csv, NULL '{}')",
+                        csv_path, null
+                    ),
+                    true,
+                )
+                .unwrap(),
+                &mut user,
+                &"".to_string(),
+            )
+            .unwrap();
+            assert_eq!(select("copies", &mut user), rows, "{}", null);
+        }
+
+        // A file from another tool can use its own token, while an empty field is an empty string
+        let options: CsvImportOptions = CsvImportOptions {
+            null: "\\N".to_string(),
+            ..CsvImportOptions::default()
+        };
+        let import = |contents: &str, user: &mut User| {
+            std::fs::write(&csv_path, contents).unwrap();
+            import_csv(
+                &"copies".to_string(),
+                &csv_path,
+                &options,
+                get_db_instance().unwrap(),
+                user,
+            )
+        };
+        import("5,,\\N\n6,\\N,9\n", &mut user).unwrap();
+        let (_, results) = execute_query(
+            &parse(
+                "SELECT note, score FROM copies WHERE id > 4 ORDER BY id",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::String("".to_string()), Value::Null(Column::I64)],
+                vec![Value::Null(Column::String(20)), Value::I64(9)],
+            ]
+        );
+
+        // A quoted field is never null, so a quoted empty field can't go in a number column
+        assert!(import("7,\\N,\"\"\n", &mut user).is_err());
+
+        std::fs::remove_file(&csv_path).unwrap();
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_import_csv_restricted() {
+        fcreate_db_instance("test_import_csv_restricted");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse("CREATE TABLE numbers (id INT)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        // Users other than admins import the files in the import directory, by their name
+        let csv_name: String = "test_import_csv_restricted.csv".to_string();
+        let csv_path: String = get_import_path(&csv_name, &user).unwrap();
+        std::fs::create_dir_all(DEFAULT_IMPORT_DIR).unwrap();
+        std::fs::write(&csv_path, "1\n2\n").unwrap();
+        let message: String = execute_update(
+            &parse(&format!("COPY numbers FROM '{}'", csv_name), true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(message, "2 rows were imported into numbers.".to_string());
+
+        // They can't reach the files outside of it
+        let options: CsvImportOptions = CsvImportOptions::default();
+        for file_path in [
+            "",
+            "/etc/passwd",
+            "../Cargo.toml",
+            "nested/../../Cargo.toml",
+        ] {
+            assert!(import_csv(
+                &"numbers".to_string(),
+                &file_path.to_string(),
+                &options,
+                get_db_instance().unwrap(),
+                &mut user,
+            )
+            .is_err());
+        }
+
+        std::fs::remove_file(&csv_path).unwrap();
+        delete_db_instance().unwrap();
+    }
+}
@@ -1,4 +1,9 @@
 mod aggregate;
+pub mod export;
+mod functions;
+pub mod import;
 pub mod predicate;
 pub mod query;
+pub mod system;
 mod table_iterator;
+pub mod ttl;
@@ -1,13 +1,16 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::util::dbtype::{Column, Value};
+use crate::util::dbtype::{parse_time, Collation, Column, Value};
 use crate::util::row::Row;
 use prost_types::Timestamp;
 use sqlparser::ast::{BinaryOperator, Expr, UnaryOperator};
 use sqlparser::ast::{OrderByExpr, Value as SqlValue};
 
 use super::aggregate::contains_aggregate;
+use super::functions::{
+    apply_scalar_function, get_function_args, like, position, promote_types, substring,
+};
 use super::query::ColumnAliases;
 use super::query::IndexRefs;
 
@@ -21,6 +24,9 @@ pub type PredicateSolver = Box<dyn Fn(&Row) -> Result<bool, String>>;
 /// Think of both of these functions as a 'solver' given a row, it will reduce the row to a value,
 /// as defined by the expression in the query.
 pub type ValueSolver = Box<dyn Fn(&Row) -> Result<JointValues, String>>;
+/// The SolveTruth Function Type takes a row and returns the truth of a condition under SQL's
+/// three-valued logic, where None means that it's unknown, such as `x = 1` when x is null.
+pub type TruthSolver = Box<dyn Fn(&Row) -> Result<Option<bool>, String>>;
 
 // We could encounter cases with two different types of values, so we need to be able to handle both
 #[derive(Debug)]
@@ -42,19 +48,21 @@ pub fn resolve_value(solver: &ValueSolver, row: &Row) -> Result<Value, String> {
     solver(row)?.unpack()
 }
 
-/// Given a ComparisonSolver and two rows, return an Ordering or an error
+/// Given a ComparisonSolver and two rows, return an Ordering or an error.
+/// Strings are compared using the given collation.
 pub fn resolve_comparison(
     row1: &Row,
     row2: &Row,
     index: usize,
     order_bys: &Vec<OrderByExpr>,
+    collation: &Collation,
 ) -> Ordering {
     // Compare the two rows, from values at the given index onwards
     let mut i = index;
     while i < row1.len() {
         let val1 = &row1[i];
         let val2 = &row2[i];
-        match val1.cmp(val2) {
+        match collation.compare(val1, val2) {
             Ordering::Equal => i += 1,
             other => {
                 let j = i - index;
@@ -126,6 +134,12 @@ pub fn solve_predicate(
                 _ => Ok(true),
             }))
         }
+        // Comparisons against a set of values, like `x > ALL (...)` or `x = ANY (...)`
+        Expr::BinaryOp { left, op, right }
+            if matches!(**right, Expr::AllOp(_) | Expr::AnyOp(_)) =>
+        {
+            solve_quantified_comparison(left, op, right, column_aliases, index_refs)
+        }
         Expr::BinaryOp { left, op, right } => match op {
             // Resolve values from the two sides of the expression, and then perform
             // the comparison on the two values
@@ -165,12 +179,16 @@ pub fn solve_predicate(
                     Ok(left.le(&right))
                 }))
             }
+            // Comparing anything to a null with = or != is unknown, so the row doesn't match
             BinaryOperator::Eq => {
                 let left = solve_value(left, column_aliases, index_refs)?;
                 let right = solve_value(right, column_aliases, index_refs)?;
                 Ok(Box::new(move |row| {
                     let left = left(row)?;
                     let right = right(row)?;
+                    if left.is_null() || right.is_null() {
+                        return Ok(false);
+                    }
                     Ok(left.eq(&right))
                 }))
             }
@@ -180,9 +198,26 @@ pub fn solve_predicate(
                 Ok(Box::new(move |row| {
                     let left = left(row)?;
                     let right = right(row)?;
+                    if left.is_null() || right.is_null() {
+                        return Ok(false);
+                    }
                     Ok(left.ne(&right))
                 }))
             }
+            // Null-safe equality, where two nulls are equal and a null never equals a non-null
+            BinaryOperator::Spaceship => {
+                let left = solve_value(left, column_aliases, index_refs)?;
+                let right = solve_value(right, column_aliases, index_refs)?;
+                Ok(Box::new(move |row| {
+                    let left = left(row)?;
+                    let right = right(row)?;
+                    match (left.is_null(), right.is_null()) {
+                        (true, true) => Ok(true),
+                        (false, false) => Ok(left.eq(&right)),
+                        _ => Ok(false),
+                    }
+                }))
+            }
             // Create functions for the LHS and RHS of the 'and' operation, and then
             // combine them into a single function that returns true if both functions return true
             // Note how this would also indirectly handle short-circuiting
@@ -199,17 +234,353 @@ pub fn solve_predicate(
             _ => Err(format!("Unsupported binary operator for Predicate: {}", op)),
         },
         Expr::UnaryOp { op, expr } => match op {
+            // The negation of an unknown condition is still unknown, so the row doesn't match
             UnaryOperator::Not => {
-                let expr = solve_predicate(expr, column_aliases, index_refs)?;
-                Ok(Box::new(move |row| Ok(!expr(row)?)))
+                let expr = solve_truth(expr, column_aliases, index_refs)?;
+                Ok(Box::new(move |row| Ok(expr(row)? == Some(false))))
             }
             _ => Err(format!("Unsupported unary operator for Predicate: {}", op)),
         },
         Expr::Nested(pred) => solve_predicate(pred, column_aliases, index_refs),
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => solve_in_list(expr, list, *negated, column_aliases, index_refs),
+        Expr::InSubquery { .. } => Err(format!("Subquery was not evaluated: {}", pred)),
+        // A null value or pattern never matches, whether or not the match is negated
+        Expr::Like { .. } | Expr::ILike { .. } => {
+            let matches = solve_like(pred, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| Ok(matches(row)?.unwrap_or(false))))
+        }
+        // Conditions that are already known, such as an EXISTS that was evaluated up front
+        Expr::Value(SqlValue::Boolean(x)) => {
+            let x: bool = *x;
+            Ok(Box::new(move |_| Ok(x)))
+        }
         _ => Err(format!("Invalid Predicate Clause: {}", pred)),
     }
 }
 
+/// Solves a condition with three-valued logic, so that negating it can tell false apart from
+/// unknown. Comparisons with a null are unknown, AND is false if either side is false, and OR
+/// is true if either side is true. Otherwise, any unknown side makes the whole unknown.
+pub fn solve_truth(
+    pred: &Expr,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<TruthSolver, String> {
+    match pred {
+        Expr::Nested(pred) => solve_truth(pred, column_aliases, index_refs),
+        Expr::UnaryOp {
+            op: UnaryOperator::Not,
+            expr,
+        } => {
+            let expr = solve_truth(expr, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| Ok(expr(row)?.map(|x| !x))))
+        }
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            let left = solve_truth(left, column_aliases, index_refs)?;
+            let right = solve_truth(right, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| match left(row)? {
+                Some(false) => Ok(Some(false)),
+                Some(true) => right(row),
+                None => Ok(if right(row)? == Some(false) {
+                    Some(false)
+                } else {
+                    None
+                }),
+            }))
+        }
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::Or,
+            right,
+        } => {
+            let left = solve_truth(left, column_aliases, index_refs)?;
+            let right = solve_truth(right, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| match left(row)? {
+                Some(true) => Ok(Some(true)),
+                Some(false) => right(row),
+                None => Ok(if right(row)? == Some(true) {
+                    Some(true)
+                } else {
+                    None
+                }),
+            }))
+        }
+        Expr::BinaryOp { left, op, right }
+            if matches!(
+                op,
+                BinaryOperator::Gt
+                    | BinaryOperator::Lt
+                    | BinaryOperator::GtEq
+                    | BinaryOperator::LtEq
+                    | BinaryOperator::Eq
+                    | BinaryOperator::NotEq
+            ) && !matches!(**right, Expr::AllOp(_) | Expr::AnyOp(_)) =>
+        {
+            let op: BinaryOperator = op.clone();
+            let left = solve_value(left, column_aliases, index_refs)?;
+            let right = solve_value(right, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| {
+                let left = left(row)?;
+                let right = right(row)?;
+                if left.is_null() || right.is_null() {
+                    return Ok(None);
+                }
+                Ok(Some(match op {
+                    BinaryOperator::Gt => left.gt(&right),
+                    BinaryOperator::Lt => left.lt(&right),
+                    BinaryOperator::GtEq => left.ge(&right),
+                    BinaryOperator::LtEq => left.le(&right),
+                    BinaryOperator::Eq => left.eq(&right),
+                    _ => left.ne(&right),
+                }))
+            }))
+        }
+        // IN is true if it matches, false if NOT IN matches, and unknown otherwise
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let matches = solve_in_list(expr, list, *negated, column_aliases, index_refs)?;
+            let opposite = solve_in_list(expr, list, !*negated, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| {
+                if matches(row)? {
+                    Ok(Some(true))
+                } else if opposite(row)? {
+                    Ok(Some(false))
+                } else {
+                    Ok(None)
+                }
+            }))
+        }
+        Expr::Like { .. } | Expr::ILike { .. } => solve_like(pred, column_aliases, index_refs),
+        // A null boolean column is unknown
+        Expr::Identifier(_) => {
+            let value = solve_value(pred, column_aliases, index_refs)?;
+            let pred = solve_predicate(pred, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| match value(row)?.is_null() {
+                true => Ok(None),
+                false => Ok(Some(pred(row)?)),
+            }))
+        }
+        // Everything else, like IS NULL, is never unknown
+        _ => {
+            let pred = solve_predicate(pred, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| Ok(Some(pred(row)?))))
+        }
+    }
+}
+
+/// Solves a pattern match, such as `name LIKE 'J%'`, or `name NOT ILIKE 'j%'` to ignore case.
+/// The match is unknown if the value or the pattern is null.
+fn solve_like(
+    pred: &Expr,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<TruthSolver, String> {
+    let (negated, expr, pattern, escape_char, case_insensitive) = match pred {
+        Expr::Like {
+            negated,
+            expr,
+            pattern,
+            escape_char,
+        } => (*negated, expr, pattern, *escape_char, false),
+        Expr::ILike {
+            negated,
+            expr,
+            pattern,
+            escape_char,
+        } => (*negated, expr, pattern, *escape_char, true),
+        _ => return Err(format!("Invalid Pattern Match: {}", pred)),
+    };
+    let value = solve_value(expr, column_aliases, index_refs)?;
+    let pattern = solve_value(pattern, column_aliases, index_refs)?;
+    Ok(Box::new(move |row| {
+        match like(
+            resolve_value(&value, row)?,
+            resolve_value(&pattern, row)?,
+            escape_char,
+            case_insensitive,
+        )? {
+            Value::Bool(matches) => Ok(Some(matches != negated)),
+            _ => Ok(None),
+        }
+    }))
+}
+
+/// Solves a quantified comparison, such as `x > ALL (...)` or `x = ANY (...)`.
+/// The set of values must already be a tuple of values, as subqueries are evaluated
+/// before the predicate is built. ALL holds if the comparison holds for every value in
+/// the set, so it's true for an empty set. ANY holds if the comparison holds for at least
+/// one value in the set, so it's false for an empty set.
+fn solve_quantified_comparison(
+    left: &Expr,
+    op: &BinaryOperator,
+    right: &Expr,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<PredicateSolver, String> {
+    let (is_all, set) = match right {
+        Expr::AllOp(set) => (true, set),
+        Expr::AnyOp(set) => (false, set),
+        _ => return Err(format!("Invalid Predicate Clause: {}", right)),
+    };
+    let set: Vec<Expr> = match set.as_ref() {
+        Expr::Tuple(values) => values.clone(),
+        Expr::Subquery(_) => return Err(format!("Subquery was not evaluated: {}", set)),
+        value => vec![value.clone()],
+    };
+    match op {
+        BinaryOperator::Gt
+        | BinaryOperator::Lt
+        | BinaryOperator::GtEq
+        | BinaryOperator::LtEq
+        | BinaryOperator::Eq
+        | BinaryOperator::NotEq
+        | BinaryOperator::Spaceship => {}
+        _ => return Err(format!("Unsupported binary operator for {}: {}", right, op)),
+    }
+
+    let op: BinaryOperator = op.clone();
+    let left: ValueSolver = solve_value(left, column_aliases, index_refs)?;
+    let set: Vec<ValueSolver> = set
+        .iter()
+        .map(|value| solve_value(value, column_aliases, index_refs))
+        .collect::<Result<Vec<ValueSolver>, String>>()?;
+    Ok(Box::new(move |row| {
+        let left = left(row)?;
+        for value in set.iter() {
+            let right = value(row)?;
+            let matches: bool = match op {
+                BinaryOperator::Gt => left.gt(&right),
+                BinaryOperator::Lt => left.lt(&right),
+                BinaryOperator::GtEq => left.ge(&right),
+                BinaryOperator::LtEq => left.le(&right),
+                BinaryOperator::Eq => !left.is_null() && !right.is_null() && left.eq(&right),
+                BinaryOperator::NotEq => !left.is_null() && !right.is_null() && left.ne(&right),
+                _ => match (left.is_null(), right.is_null()) {
+                    (true, true) => true,
+                    (false, false) => left.eq(&right),
+                    _ => false,
+                },
+            };
+            // We can stop as soon as we know the answer for the whole set
+            if matches != is_all {
+                return Ok(matches);
+            }
+        }
+        Ok(is_all)
+    }))
+}
+
+/// Solves a membership test, such as `x IN (1, 2, 3)` or `x NOT IN (...)`, where subqueries
+/// have already been evaluated into a list of values. Constant values are put into a hash set,
+/// so large lists are checked in constant time per row. Following SQL, a null on the left never
+/// matches, and NOT IN never matches if the list contains a null.
+fn solve_in_list(
+    expr: &Expr,
+    list: &[Expr],
+    negated: bool,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<PredicateSolver, String> {
+    // When checking a column, the values in the list are compared as the column's type
+    let column_name: Option<String> = match expr {
+        Expr::Identifier(x) => Some(x.value.to_string()),
+        Expr::CompoundIdentifier(list) => Some(
+            list.iter()
+                .map(|x| x.value.to_string())
+                .collect::<Vec<String>>()
+                .join("."),
+        ),
+        _ => None,
+    };
+    let coltype: Option<Column> = match column_name {
+        Some(column_name) => {
+            let column_name: String = resolve_reference(column_name, column_aliases)?;
+            column_aliases
+                .iter()
+                .find(|(name, _, _)| name == &column_name)
+                .map(|(_, coltype, _)| coltype.clone())
+        }
+        None => None,
+    };
+
+    let mut set: HashSet<Value> = HashSet::new();
+    let mut has_null: bool = false;
+    let mut solvers: Vec<ValueSolver> = Vec::new();
+    for item in list {
+        match resolve_pure_value(item) {
+            Ok(value) if value.is_null() => has_null = true,
+            Ok(value) => {
+                let value: Value = match &coltype {
+                    Some(coltype) => coerce_in_list_item(coltype, value)?,
+                    None => value,
+                };
+                set.insert(in_list_key(&value)?);
+            }
+            // Items that depend on the row, such as columns, are checked row by row
+            Err(_) => solvers.push(solve_value(item, column_aliases, index_refs)?),
+        }
+    }
+
+    let expr: ValueSolver = solve_value(expr, column_aliases, index_refs)?;
+    Ok(Box::new(move |row| {
+        let value = expr(row)?;
+        if value.is_null() {
+            return Ok(false);
+        }
+        let mut found: bool = set.contains(&in_list_key(&value.unpack()?)?);
+        let mut row_null: bool = false;
+        for solver in solvers.iter() {
+            if found {
+                break;
+            }
+            let item = solver(row)?;
+            if item.is_null() {
+                row_null = true;
+            } else {
+                found = value.eq(&item);
+            }
+        }
+        match negated {
+            false => Ok(found),
+            true => Ok(!found && !has_null && !row_null),
+        }
+    }))
+}
+
+/// Brings a value in an IN list to the type of the column it's compared with, so that
+/// `id IN (1, '2')` matches an id of 2. Numbers are already compared across their types,
+/// so they're left as they are. A value that can't be compared with the column is an error.
+pub fn coerce_in_list_item(coltype: &Column, item: Value) -> Result<Value, String> {
+    match (coltype, &item) {
+        (Column::Nullable(coltype), _) => coerce_in_list_item(coltype, item),
+        (_, Value::Null(_)) => Ok(item),
+        (Column::String(_) | Column::Char(_), Value::String(_)) => Ok(item),
+        (_, Value::String(x)) => coltype.parse(x),
+        _ if promote_types(coltype, &item.get_coltype()).is_some() => Ok(item),
+        _ => Err(format!(
+            "Cannot compare {:?} with a column of type {:?} in an IN list",
+            item, coltype
+        )),
+    }
+}
+
+// Values are hashed by their type, so they're brought into a common form before being compared,
+// such that 1, 1.0 and an I32 column holding 1 are all the same key.
+fn in_list_key(value: &Value) -> Result<Value, String> {
+    Value::from_sql_value(&value.to_sql_value())
+}
+
 /// Similar to solve_predicate, this is another function that takes a Row and reduces it to the
 /// value described by the expression. In the most simple case, if we have an Expression just
 /// referencing a column name, we just take a row and then apply the index on that row.
@@ -266,6 +637,18 @@ pub fn solve_value(
             // it when we wish to respond with a Value.
             Ok(Box::new(move |_| Ok(JointValues::SQLValue(val.clone()))))
         }
+        // Timestamps are moved by an interval, like `ts + INTERVAL '1 day'`
+        Expr::BinaryOp { left, op, right }
+            if matches!(op, BinaryOperator::Plus | BinaryOperator::Minus)
+                && matches!(**right, Expr::Value(SqlValue::Interval { .. })) =>
+        {
+            let seconds = match (op, interval_seconds(right)?) {
+                (BinaryOperator::Minus, seconds) => -seconds,
+                (_, seconds) => seconds,
+            };
+            let left = solve_value(left, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| offset_by_interval(left(row)?, seconds)))
+        }
         Expr::BinaryOp { left, op, right } => match op {
             BinaryOperator::Plus => {
                 let left = solve_value(left, column_aliases, index_refs)?;
@@ -319,13 +702,8 @@ pub fn solve_value(
             | BinaryOperator::Gt
             | BinaryOperator::GtEq
             | BinaryOperator::Eq
-            | BinaryOperator::NotEq => {
-                let binary = solve_predicate(expr, column_aliases, index_refs)?;
-                Ok(Box::new(move |row| {
-                    let pred = binary(row)?;
-                    Ok(JointValues::DBValue(Value::Bool(pred)))
-                }))
-            }
+            | BinaryOperator::NotEq
+            | BinaryOperator::Spaceship => solve_predicate_value(expr, column_aliases, index_refs),
             _ => Err(format!("Invalid Binary Operator for Value: {}", op)),
         },
         Expr::UnaryOp { op, expr } => match op {
@@ -350,10 +728,81 @@ pub fn solve_value(
             }
             _ => Err(format!("Invalid Unary Operator for Value: {}", op)),
         },
+        Expr::IsNull(_)
+        | Expr::IsNotNull(_)
+        | Expr::IsTrue(_)
+        | Expr::IsNotTrue(_)
+        | Expr::IsFalse(_)
+        | Expr::IsNotFalse(_)
+        | Expr::InList { .. }
+        | Expr::Like { .. }
+        | Expr::ILike { .. } => solve_predicate_value(expr, column_aliases, index_refs),
+        // Aggregates were handled above, so this is a scalar function like GREATEST(a, b)
+        Expr::Function(func) => {
+            let args: Vec<ValueSolver> = get_function_args(func)?
+                .into_iter()
+                .map(|arg| solve_value(arg, column_aliases, index_refs))
+                .collect::<Result<Vec<ValueSolver>, String>>()?;
+            let func = func.clone();
+            Ok(Box::new(move |row| {
+                let values: Vec<Value> = args
+                    .iter()
+                    .map(|arg| resolve_value(arg, row))
+                    .collect::<Result<Vec<Value>, String>>()?;
+                Ok(JointValues::DBValue(apply_scalar_function(&func, values)?))
+            }))
+        }
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => {
+            let value = solve_value(expr, column_aliases, index_refs)?;
+            let from = substring_from
+                .as_ref()
+                .map(|from| solve_value(from, column_aliases, index_refs))
+                .transpose()?;
+            let length = substring_for
+                .as_ref()
+                .map(|length| solve_value(length, column_aliases, index_refs))
+                .transpose()?;
+            Ok(Box::new(move |row| {
+                let from = from.as_ref().map(|x| resolve_value(x, row)).transpose()?;
+                let length = length.as_ref().map(|x| resolve_value(x, row)).transpose()?;
+                Ok(JointValues::DBValue(substring(
+                    resolve_value(&value, row)?,
+                    from,
+                    length,
+                )?))
+            }))
+        }
+        Expr::Position { expr, r#in } => {
+            let sub = solve_value(expr, column_aliases, index_refs)?;
+            let string = solve_value(r#in, column_aliases, index_refs)?;
+            Ok(Box::new(move |row| {
+                Ok(JointValues::DBValue(position(
+                    resolve_value(&sub, row)?,
+                    resolve_value(&string, row)?,
+                )?))
+            }))
+        }
         _ => Err(format!("Unexpected Value Clause: {}", expr)),
     }
 }
 
+/// Solves a predicate as a value, so that it can be selected, like `select age >= 18 from table`.
+/// The value is a bool, which is true for the rows the predicate would keep in a where clause.
+fn solve_predicate_value(
+    expr: &Expr,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<ValueSolver, String> {
+    let pred = solve_predicate(expr, column_aliases, index_refs)?;
+    Ok(Box::new(move |row| {
+        Ok(JointValues::DBValue(Value::Bool(pred(row)?)))
+    }))
+}
+
 // Given a column name, it figures out which table it belongs to and returns the
 // unambiguous column name. For example, if we have a table called "users" with
 // a column called "id", this would return "users.id". If "users" has an alias
@@ -379,16 +828,36 @@ pub fn resolve_reference(
         if matches.len() == 1 {
             Ok(matches[0].clone())
         } else if matches.len() != 0 {
-            Err(format!("Column name {} is ambiguous.", column_name))
+            // More than one of the tables has the column, so it has to be qualified
+            let choices: Vec<String> = matches.iter().map(|x| x.to_string()).collect();
+            Err(format!(
+                "Column name {} is ambiguous, it could be any of {}.",
+                column_name,
+                choices.join(", ")
+            ))
         } else {
             Err(format!("Column name {} does not exist.", column_name))
         }
     }
 }
 
+/// Why applying a function to two values failed, before the error is given the operation and values
+enum ArithmeticError {
+    IntegerOverflow,
+    DivisionByZero,
+    Unsupported, // The two values can't be combined with this function
+}
+
+// Any other error while applying a function means the values can't be combined with it
+impl From<String> for ArithmeticError {
+    fn from(_: String) -> Self {
+        ArithmeticError::Unsupported
+    }
+}
+
 /// When applying some function to two values, we need to know how to treat the
 /// two values.
-type ApplyInt = fn(i64, i64) -> Result<i64, String>;
+type ApplyInt = fn(i64, i64) -> Result<i64, ArithmeticError>;
 type ApplyFloat = fn(f64, f64) -> Result<f64, String>;
 type ApplyString = fn(&String, &String) -> Result<String, String>;
 
@@ -407,24 +876,27 @@ impl JointValues {
         }
     }
 
+    /// Integer arithmetic is checked, and never wraps: two I32s give an I32, and anything
+    /// involving an I64 (including integer literals) gives an I64. If the result doesn't fit
+    /// in that type, this returns an overflow error naming the operation instead.
     pub fn add(&self, other: &Self) -> Result<JointValues, String> {
-        let apply_int = |x: i64, y: i64| Ok::<i64, String>(x + y);
+        let apply_int = |x: i64, y: i64| x.checked_add(y).ok_or(ArithmeticError::IntegerOverflow);
         let apply_float = |x: f64, y: f64| Ok::<f64, String>(x + y);
         let apply_string = |x: &String, y: &String| Ok::<String, String>(x.to_string() + y);
         self.apply(other, apply_int, apply_float, apply_string)
-            .map_err(|_| format!("Cannot add {:?} and {:?}", self, other))
+            .map_err(|e| self.arithmetic_error(other, "add", e))
     }
 
     pub fn subtract(&self, other: &Self) -> Result<JointValues, String> {
-        let apply_int = |x: i64, y: i64| Ok::<i64, String>(x - y);
+        let apply_int = |x: i64, y: i64| x.checked_sub(y).ok_or(ArithmeticError::IntegerOverflow);
         let apply_float = |x: f64, y: f64| Ok::<f64, String>(x - y);
         let apply_string = |x: &String, y: &String| Ok::<String, String>(x.replace(y, ""));
         self.apply(other, apply_int, apply_float, apply_string)
-            .map_err(|_| format!("Cannot subtract {:?} and {:?}", self, other))
+            .map_err(|e| self.arithmetic_error(other, "subtract", e))
     }
 
     pub fn multiply(&self, other: &Self) -> Result<JointValues, String> {
-        let apply_int = |x: i64, y: i64| Ok::<i64, String>(x * y);
+        let apply_int = |x: i64, y: i64| x.checked_mul(y).ok_or(ArithmeticError::IntegerOverflow);
         let apply_float = |x: f64, y: f64| Ok::<f64, String>(x * y);
         let apply_string = |x: &String, y: &String| {
             let mut result = String::new();
@@ -437,28 +909,49 @@ impl JointValues {
             Ok::<String, String>(result)
         };
         self.apply(other, apply_int, apply_float, apply_string)
-            .map_err(|_| format!("Cannot multiply {:?} and {:?}", self, other))
+            .map_err(|e| self.arithmetic_error(other, "multiply", e))
     }
 
     pub fn divide(&self, other: &Self) -> Result<JointValues, String> {
-        let apply_int = |x: i64, y: i64| Ok::<i64, String>(x / y);
+        let apply_int = |x: i64, y: i64| match y {
+            0 => Err(ArithmeticError::DivisionByZero),
+            _ => x.checked_div(y).ok_or(ArithmeticError::IntegerOverflow),
+        };
         let apply_float = |x: f64, y: f64| Ok::<f64, String>(x / y);
         let apply_string = |_: &String, _: &String| {
             Err::<String, String>("Cannot divide string by string".to_string())
         };
         self.apply(other, apply_int, apply_float, apply_string)
-            .map_err(|_| format!("Cannot divide {:?} and {:?}", self, other))
+            .map_err(|e| self.arithmetic_error(other, "divide", e))
     }
 
     pub fn modulo(&self, other: &Self) -> Result<JointValues, String> {
-        let apply_int = |x: i64, y: i64| Ok::<i64, String>(x % y);
+        let apply_int = |x: i64, y: i64| match y {
+            0 => Err(ArithmeticError::DivisionByZero),
+            _ => x.checked_rem(y).ok_or(ArithmeticError::IntegerOverflow),
+        };
         let apply_float =
             |_: f64, _: f64| Err::<f64, String>("Cannot modulus float by float".to_string());
         let apply_string = |_: &String, _: &String| {
             Err::<String, String>("Cannot modulus string by string".to_string())
         };
         self.apply(other, apply_int, apply_float, apply_string)
-            .map_err(|_| format!("Cannot modulus {:?} and {:?}", self, other))
+            .map_err(|e| self.arithmetic_error(other, "modulus", e))
+    }
+
+    // Gives the message for an error from applying the operation to the two values
+    fn arithmetic_error(&self, other: &Self, operation: &str, error: ArithmeticError) -> String {
+        let reason: &str = match error {
+            ArithmeticError::IntegerOverflow => "Integer overflow",
+            ArithmeticError::DivisionByZero => "Division by zero",
+            ArithmeticError::Unsupported => {
+                return format!("Cannot {} {:?} and {:?}", operation, self, other)
+            }
+        };
+        format!(
+            "{} when trying to {} {:?} and {:?}",
+            reason, operation, self, other
+        )
     }
 
     /// This function applies a function to two values of similar types, casting when necessary.
@@ -469,12 +962,22 @@ impl JointValues {
         int_func: ApplyInt,
         float_func: ApplyFloat,
         string_func: ApplyString,
-    ) -> Result<JointValues, String> {
+    ) -> Result<JointValues, ArithmeticError> {
         Ok(match (self, other) {
+            // Fixed length strings lose their padding when used in expressions
+            (Self::DBValue(Value::Char(x)), _) => {
+                let trimmed = Value::String(x.trim_end_matches(' ').to_string());
+                Self::DBValue(trimmed).apply(other, int_func, float_func, string_func)?
+            }
+            (_, Self::DBValue(Value::Char(x))) => {
+                let trimmed = Value::String(x.trim_end_matches(' ').to_string());
+                self.apply(&Self::DBValue(trimmed), int_func, float_func, string_func)?
+            }
             (Self::DBValue(l0), Self::DBValue(r0)) => Self::DBValue(match (l0, r0) {
-                (Value::I32(l), Value::I32(r)) => {
-                    Value::I32(int_func(*l as i64, *r as i64)? as i32)
-                }
+                (Value::I32(l), Value::I32(r)) => Value::I32(
+                    i32::try_from(int_func(*l as i64, *r as i64)?)
+                        .map_err(|_| ArithmeticError::IntegerOverflow)?,
+                ),
                 (Value::Float(l), Value::Float(r)) => {
                     Value::Float(float_func(*l as f64, *r as f64)? as f32)
                 }
@@ -600,6 +1103,80 @@ impl PartialOrd for JointValues {
     }
 }
 
+/// The number of seconds in an interval, like `INTERVAL '1 day'` or `INTERVAL '3' HOUR`.
+/// An interval can have several parts, like `INTERVAL '1 day 2 hours'`.
+/// Months and years don't have a fixed length, so they aren't supported.
+pub fn interval_seconds(expr: &Expr) -> Result<i64, String> {
+    let (value, leading_field, last_field) = match expr {
+        Expr::Value(SqlValue::Interval {
+            value,
+            leading_field,
+            last_field,
+            ..
+        }) => (value, leading_field, last_field),
+        _ => return Err(format!("Expected an interval, found {}", expr)),
+    };
+    let text: String = match value.as_ref() {
+        Expr::Value(SqlValue::SingleQuotedString(x) | SqlValue::Number(x, _)) => x.clone(),
+        _ => return Err(format!("Invalid interval {}", expr)),
+    };
+    let parts: Vec<String> = match (leading_field, last_field) {
+        (Some(field), None) => vec![text, field.to_string()],
+        (None, None) => text.split_whitespace().map(|x| x.to_string()).collect(),
+        _ => return Err(format!("Unsupported interval {}", expr)),
+    };
+    if parts.is_empty() || parts.len() % 2 != 0 {
+        return Err(format!(
+            "Invalid interval {}, expected a number followed by a unit",
+            expr
+        ));
+    }
+
+    let mut seconds: i64 = 0;
+    for part in parts.chunks(2) {
+        let amount: i64 = part[0]
+            .parse()
+            .map_err(|_| format!("Invalid interval {}, {} is not a number", expr, part[0]))?;
+        let unit_seconds: i64 = match part[1].to_lowercase().as_str() {
+            "second" | "seconds" => 1,
+            "minute" | "minutes" => 60,
+            "hour" | "hours" => 60 * 60,
+            "day" | "days" => 24 * 60 * 60,
+            "week" | "weeks" => 7 * 24 * 60 * 60,
+            unit => return Err(format!("Unsupported interval unit {}", unit)),
+        };
+        seconds = amount
+            .checked_mul(unit_seconds)
+            .and_then(|x| x.checked_add(seconds))
+            .ok_or(format!("Interval {} is too large", expr))?;
+    }
+    Ok(seconds)
+}
+
+// Moves a timestamp or a time of day by a number of seconds.
+// Times of day wrap around midnight.
+pub fn offset_by_interval(value: JointValues, seconds: i64) -> Result<JointValues, String> {
+    let timestamp: Timestamp = match value {
+        JointValues::DBValue(Value::Timestamp(x)) => x,
+        JointValues::SQLValue(SqlValue::SingleQuotedString(x)) => parse_time(&x)?,
+        JointValues::DBValue(Value::Time(x)) => {
+            let time: i64 = (x as i64 + seconds).rem_euclid(24 * 60 * 60);
+            return Ok(JointValues::DBValue(Value::Time(time as u32)));
+        }
+        JointValues::DBValue(Value::Null(_)) | JointValues::SQLValue(SqlValue::Null) => {
+            return Ok(JointValues::DBValue(Value::Null(Column::Timestamp)))
+        }
+        other => return Err(format!("Cannot add an interval to {:?}", other)),
+    };
+    Ok(JointValues::DBValue(Value::Timestamp(Timestamp {
+        seconds: timestamp
+            .seconds
+            .checked_add(seconds)
+            .ok_or("Timestamp out of range".to_string())?,
+        nanos: timestamp.nanos,
+    })))
+}
+
 // Where (predicate) tests go here
 #[cfg(test)]
 mod tests {
@@ -609,9 +1186,11 @@ mod tests {
         executor::query::{execute_query, execute_update},
         fileio::databaseio::{delete_db_instance, get_db_instance},
         parser::parser::parse,
+        user::userdata::User,
         util::{
-            bench::create_demo_db,
-            dbtype::{Column, Value},
+            bench::{create_demo_db, fcreate_db_instance},
+            dbtype::{parse_time, Column, Value},
+            row::Row,
         },
     };
 
@@ -668,6 +1247,213 @@ mod tests {
         delete_db_instance().unwrap();
     }
 
+    #[test]
+    #[serial]
+    fn test_null_safe_equality() {
+        let mut user = create_demo_db("null_safe_equality");
+        let (_, null_rows) = execute_query(
+            &parse("select id from personal_info where height is null", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let (_, all_rows) = execute_query(
+            &parse("select id from personal_info", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(null_rows.len() > 0);
+
+        // With =, a null height never matches, not even another null height
+        let (_, results) = execute_query(
+            &parse(
+                "select a.id from personal_info a, personal_info b where a.id = b.id and a.height = b.height",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), all_rows.len() - null_rows.len());
+        for row in null_rows.iter() {
+            assert!(!results.contains(row));
+        }
+
+        // With <=>, two null heights are equal
+        let (_, results) = execute_query(
+            &parse(
+                "select a.id from personal_info a, personal_info b where a.id = b.id and a.height <=> b.height",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), all_rows.len());
+
+        // A null is never equal to a value that isn't null
+        let (_, results) = execute_query(
+            &parse(
+                "select id from personal_info where height <=> 5.5 and height is null",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 0);
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_not_predicate() {
+        fcreate_db_instance("not_predicate");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE pairs (id INT, a INT NULL, b INT NULL)",
+            "INSERT INTO pairs VALUES (1, 1, 2), (2, 1, 3), (3, 2, 2), (4, NULL, 2), \
+            (5, 1, NULL), (6, NULL, NULL), (7, 2, NULL), (8, 2, 3)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let ids = |condition: &str, user: &mut User| -> Vec<i64> {
+            let query: String = format!("SELECT id FROM pairs WHERE {} ORDER BY id", condition);
+            let rows: Vec<Row> =
+                execute_query(&parse(&query, false).unwrap(), user, &"".to_string())
+                    .unwrap()
+                    .1;
+            rows.iter()
+                .map(|row| match row[0] {
+                    Value::I64(x) => x,
+                    _ => panic!("Invalid value type"),
+                })
+                .collect()
+        };
+
+        // NOT gives the complement of the condition, leaving out the rows where it's unknown
+        let conditions: Vec<(&str, Vec<i64>, Vec<i64>)> = vec![
+            ("a = 1 AND b = 2", vec![1], vec![2, 3, 7, 8]),
+            ("a = 1 OR b = 2", vec![1, 2, 3, 4, 5], vec![8]),
+            ("a > 1", vec![3, 7, 8], vec![1, 2, 5]),
+            ("a IN (1, NULL)", vec![1, 2, 5], vec![]),
+            ("NOT (a <> 2)", vec![3, 7, 8], vec![1, 2, 5]),
+            ("a IS NULL", vec![4, 6], vec![1, 2, 3, 5, 7, 8]),
+        ];
+        for (condition, matches, negated) in conditions.iter() {
+            assert_eq!(ids(condition, &mut user), *matches, "{}", condition);
+            let negation: String = format!("NOT ({})", condition);
+            assert_eq!(ids(&negation, &mut user), *negated, "{}", negation);
+        }
+
+        // The rows are the same when an index is scanned for them
+        execute_update(
+            &parse("CREATE INDEX a_idx ON pairs (a)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        user.set_prefer_index_scans(true);
+        for (condition, matches, negated) in conditions {
+            assert_eq!(ids(condition, &mut user), matches, "{}", condition);
+            let negation: String = format!("NOT ({})", condition);
+            assert_eq!(ids(&negation, &mut user), negated, "{}", negation);
+        }
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_quantified_comparisons() {
+        let mut user = create_demo_db("quantified_comparisons");
+        let (_, all_rows) = execute_query(
+            &parse("select id from personal_info", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        // ALL: the comparison has to hold for every value the subquery returns
+        let (_, results) = execute_query(
+            &parse(
+                "select id from personal_info where id > ALL (select id from personal_info where id < 20)",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let expected: Vec<Vec<Value>> = all_rows
+            .iter()
+            .filter(|row| row[0] >= Value::I32(20))
+            .cloned()
+            .collect();
+        assert!(expected.len() > 0);
+        assert_eq!(results, expected);
+
+        // ANY: the comparison has to hold for at least one value the subquery returns
+        let (_, results) = execute_query(
+            &parse(
+                "select id from personal_info where id = ANY (select id from personal_info where id < 3)",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let expected: Vec<Vec<Value>> = all_rows
+            .iter()
+            .filter(|row| row[0] < Value::I32(3))
+            .cloned()
+            .collect();
+        assert!(expected.len() > 0);
+        assert_eq!(results, expected);
+
+        // ALL over an empty set is always true, and ANY over an empty set is always false
+        let (_, results) = execute_query(
+            &parse(
+                "select id from personal_info where id > ALL (select id from personal_info where id < 0)",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results, all_rows);
+        let (_, results) = execute_query(
+            &parse(
+                "select id from personal_info where id = ANY (select id from personal_info where id < 0)",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 0);
+
+        // The subquery has to return a single column
+        assert!(execute_query(
+            &parse(
+                "select id from personal_info where id = ANY (select id, age from personal_info)",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
+        delete_db_instance().unwrap();
+    }
+
     #[test]
     #[serial]
     fn test_equality_predicates() {
@@ -1089,4 +1875,118 @@ mod tests {
 
         delete_db_instance().unwrap();
     }
+
+    #[test]
+    #[serial]
+    fn test_interval_arithmetic() {
+        fcreate_db_instance("interval_arithmetic");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE events (name VARCHAR(20), happened_at TIMESTAMP)",
+            "INSERT INTO events VALUES ('launch', '2024-02-28 22:30:00')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let shift = |interval: &str, user: &mut User| -> Result<Value, String> {
+            let query: String = format!("SELECT happened_at {} FROM events", interval);
+            let (_, rows) = execute_query(&parse(&query, false).unwrap(), user, &"".to_string())?;
+            Ok(rows[0][0].clone())
+        };
+        let timestamp = |x: &str| Value::Timestamp(parse_time(&x.to_string()).unwrap());
+
+        assert_eq!(
+            shift("+ INTERVAL '1 day'", &mut user).unwrap(),
+            timestamp("2024-02-29 22:30:00")
+        );
+        assert_eq!(
+            shift("- INTERVAL '3 hours'", &mut user).unwrap(),
+            timestamp("2024-02-28 19:30:00")
+        );
+        assert_eq!(
+            shift(
+                "+ INTERVAL '2 days 1 hour 30 minutes 15 seconds'",
+                &mut user
+            )
+            .unwrap(),
+            timestamp("2024-03-02 00:00:15")
+        );
+        assert_eq!(
+            shift("- INTERVAL '90' MINUTE", &mut user).unwrap(),
+            timestamp("2024-02-28 21:00:00")
+        );
+
+        // Intervals can be used in a where clause. The interval's value takes in
+        // the rest of the expression, so it needs parentheses before a comparison.
+        let (_, rows) = execute_query(
+            &parse(
+                "SELECT name FROM events \
+                WHERE (happened_at + INTERVAL '2 hours') > '2024-02-29 00:00:00'",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(rows, vec![vec![Value::String("launch".to_string())]]);
+
+        // So can aggregates
+        let (_, rows) = execute_query(
+            &parse(
+                "SELECT MAX(happened_at) + INTERVAL '1 day' FROM events",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(rows, vec![vec![timestamp("2024-02-29 22:30:00")]]);
+
+        assert!(shift("+ INTERVAL '1 month'", &mut user).is_err());
+        assert!(shift("+ INTERVAL 'soon'", &mut user).is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_in_list_coercion() {
+        fcreate_db_instance("in_list_coercion");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(20), joined TIMESTAMP)",
+            "INSERT INTO people VALUES (1, 'alice', '2024-01-01 00:00:00')",
+            "INSERT INTO people VALUES (2, 'bob', '2024-02-01 00:00:00')",
+            "INSERT INTO people VALUES (3, 'carol', '2024-03-01 00:00:00')",
+            "INSERT INTO people VALUES (4, 'dave', '2024-04-01 00:00:00')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let names = |condition: &str, user: &mut User| -> Result<Vec<Value>, String> {
+            let query: String = format!("SELECT name FROM people WHERE {}", condition);
+            let (_, rows) = execute_query(&parse(&query, false).unwrap(), user, &"".to_string())?;
+            Ok(rows.into_iter().map(|row| row[0].clone()).collect())
+        };
+        let string = |x: &str| Value::String(x.to_string());
+
+        // The string is read as an integer, since it's compared with an integer column
+        assert_eq!(
+            names("id IN (1, '2', 3)", &mut user).unwrap(),
+            vec![string("alice"), string("bob"), string("carol")]
+        );
+        assert_eq!(
+            names("id NOT IN (1, '2', 3)", &mut user).unwrap(),
+            vec![string("dave")]
+        );
+        assert_eq!(
+            names("joined IN ('2024-02-01 00:00:00')", &mut user).unwrap(),
+            vec![string("bob")]
+        );
+
+        assert!(names("id IN (1, 'two')", &mut user).is_err());
+        assert!(names("name IN ('bob', 2)", &mut user).is_err());
+        delete_db_instance().unwrap();
+    }
 }
@@ -1,96 +1,544 @@
-use std::collections::HashMap;
-
-use super::aggregate::resolve_aggregates;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Barrier};
+use std::time::{Duration, Instant};
+
+use super::aggregate::{resolve_aggregates, solve_aggregate, RunningAggregates};
+use super::export::{export_sql, SqlExportOptions};
+use super::functions::{is_scalar_function, promote_types};
+use super::import::{import_csv, CsvImportOptions};
 use super::predicate::{
     resolve_comparison, resolve_predicate, resolve_pure_value, resolve_reference, resolve_value,
     solve_predicate, solve_value, PredicateSolver, ValueSolver,
 };
+use super::system::{check_writable_table, is_system_table, load_system_table};
 use super::table_iterator::{RowIterator, TableIterator};
+use super::ttl::{
+    check_column_name, get_ttl, is_expired, is_ttl_column, remove_expired_rows, timestamp_row,
+    ttl_column, visible_column_count,
+};
+use crate::parser::clauses::{
+    AggregateClauses, ConflictAction, OnConflict, RowLimit, SelectClauses,
+};
+use crate::parser::parser::ParsedStatement;
 use crate::user::usercreds::UserPermissions::*;
 use crate::user::userdata::*;
-use crate::util::dbtype::Column;
+use crate::util::dbtype::{Collation, Column, StringOverflowPolicy};
+use crate::util::error::Error;
 use crate::util::row::{Row, RowInfo};
 use crate::version_control::diff::*;
 use crate::{
     btree::{btree::*, indexes::*},
     fileio::{
+        catalog::CatalogEntry,
         databaseio::*,
         header::*,
+        pageio::PAGE_SIZE,
+        snapshot::ReadSnapshot,
+        storage::{get_storage_backend, IN_MEMORY_PATH_PREFIX},
         tableio::{self, *},
+        wal::run_logged,
     },
-    util::row::RowLocation,
 };
 
 use crate::util::dbtype::Value;
 use itertools::{Itertools, MultiProduct};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use sqlparser::ast::{
-    AlterTableOperation, BinaryOperator, ColumnOption, Expr, Ident, OrderByExpr, Query, Select,
-    SelectItem, SetExpr, SetOperator, Statement,
+    AlterTableOperation, BinaryOperator, ColumnOption, CopyTarget, Cte, Expr, FunctionArg,
+    FunctionArgExpr, Ident, OrderByExpr, Query, Select, SelectItem, SetExpr, SetOperator,
+    Statement, TableConstraint, TableFactor, Value as SqlValue,
 };
+use tabled::{builder::Builder, Style};
 
 pub type Tables = Vec<(Table, String)>;
 pub type ColumnAliases = Vec<ColumnAlias>;
 pub type ColumnAlias = (String, Column, String); // Format (<table_alias>.<column_name>, <column_type>, <output_column_name>)
 pub type IndexRefs = HashMap<String, usize>;
 
+thread_local! {
+    // Waited on by the next query on this thread once it starts scanning, see set_scan_barrier()
+    static SCAN_BARRIER: RefCell<Option<Arc<Barrier>>> = const { RefCell::new(None) };
+}
+
+/// Makes the next query on the current thread wait on the barrier twice once it starts scanning
+/// its rows. Another thread that waits on the barrier knows that the scan has started, and can act
+/// on the query, like cancelling it, before it waits again to let the scan go on.
+pub fn set_scan_barrier(barrier: Arc<Barrier>) {
+    SCAN_BARRIER.with(|scan_barrier| *scan_barrier.borrow_mut() = Some(barrier));
+}
+
 /// A parse function, that starts with a string and returns either a table for query commands
 /// or a string for
 pub fn execute_query(
-    ast: &Vec<Statement>,
+    ast: &Vec<ParsedStatement>,
     user: &mut User,
-    _command: &String,
+    command: &String,
 ) -> Result<(Vec<String>, Vec<Row>), String> {
+    let (column_names, _, rows) = execute_query_with_types(ast, user, command)?;
+    Ok((column_names, rows))
+}
+
+/// Same as execute_query, but also returns the type of each output column,
+/// so that clients can format the values of each column.
+pub fn execute_query_with_types(
+    ast: &Vec<ParsedStatement>,
+    user: &mut User,
+    _command: &String,
+) -> Result<(Vec<String>, Vec<Column>, Vec<Row>), Error> {
     if ast.len() == 0 {
-        return Err("Empty AST".to_string());
+        return Err("Empty AST".into());
     }
-    for a in ast.iter() {
-        match a {
+    let _database: DatabaseSelection = select_database(user.get_database_name());
+    let _session: SessionSelection = select_session(user);
+    // Keep reading the branch as it is now, even if another session commits to it meanwhile
+    let _snapshot: Option<ReadSnapshot> = get_db_instance()?.pin_read_snapshot(user);
+    for parsed in ast.iter() {
+        match &parsed.statement {
             Statement::Query(q) => {
-                return parse_query(&q.body, user, q);
+                let result = run_query(q, &parsed.clauses.select, user, None)?;
+                check_result_width(&result.0, user)?;
+                return Ok(result);
+            }
+            Statement::Explain {
+                statement, analyze, ..
+            } => {
+                return explain_query(statement, &parsed.clauses.select, *analyze, user);
+            }
+            Statement::ShowVariable { variable }
+                if variable.len() == 1 && variable[0].value.to_lowercase() == "databases" =>
+            {
+                return Ok(show_databases()?);
+            }
+            // Run as a query, a write is only previewed, so the rows it would change are returned
+            Statement::Update { .. } | Statement::Delete { .. } => {
+                return preview_write(parsed, user);
             }
             _ => print!("Not a query\n"),
         };
     }
-    Err("No query found".to_string())
+    Err("No query found".into())
+}
+
+/// Lists the databases in the catalog, using `SHOW DATABASES`, along with whether the server
+/// has opened them yet. The others are opened once a session uses them.
+fn show_databases() -> Result<(Vec<String>, Vec<Column>, Vec<Row>), String> {
+    let entries: Vec<CatalogEntry> = get_db_instance()?.get_catalog_entries()?;
+    let width = |value: fn(&CatalogEntry) -> &String| -> u16 {
+        entries
+            .iter()
+            .map(|entry| value(entry).len())
+            .max()
+            .unwrap_or(0)
+            .max(1) as u16
+    };
+    let column_types: Vec<Column> = vec![
+        Column::String(width(|entry| &entry.name)),
+        Column::String(width(|entry| &entry.main_branch)),
+        Column::Timestamp,
+        Column::Bool,
+    ];
+    let rows: Vec<Row> = entries
+        .iter()
+        .map(|entry| {
+            vec![
+                Value::String(entry.name.clone()),
+                Value::String(entry.main_branch.clone()),
+                Value::Timestamp(entry.created.clone()),
+                Value::Bool(is_db_instance_open(&entry.name)),
+            ]
+        })
+        .collect();
+    Ok((
+        vec![
+            "name".to_string(),
+            "main_branch".to_string(),
+            "created".to_string(),
+            "open".to_string(),
+        ],
+        column_types,
+        rows,
+    ))
+}
+
+/// Previews an UPDATE or DELETE, returning the rows of the table that it would change,
+/// as they are now. Nothing is written, and the user's diffs are left as they are.
+fn preview_write(
+    parsed: &ParsedStatement,
+    user: &mut User,
+) -> Result<(Vec<String>, Vec<Column>, Vec<Row>), Error> {
+    let (schema, rows): (Schema, Vec<RowInfo>) = match parsed.statement {
+        Statement::Update { .. } => {
+            let (_, diff, _) = run_update_statement(parsed, true, user)?;
+            (diff.schema, diff.old_rows)
+        }
+        _ => {
+            let (_, diff, _) = run_delete_statement(parsed, true, user)?;
+            (diff.schema, diff.rows)
+        }
+    };
+    let (column_names, column_types): (Vec<String>, Vec<Column>) = schema.into_iter().unzip();
+    let rows: Vec<Row> = rows.into_iter().map(|row_info| row_info.row).collect();
+    check_result_width(&column_names, user)?;
+    Ok((column_names, column_types, rows))
+}
+
+/// Rejects a query's result if it has more columns than the user's session allows
+fn check_result_width(column_names: &Vec<String>, user: &User) -> Result<(), String> {
+    match user.get_max_result_columns() {
+        Some(max_columns) if column_names.len() > max_columns => Err(format!(
+            "Query returns {} columns, but at most {} are allowed",
+            column_names.len(),
+            max_columns
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The steps that a query takes, as described by EXPLAIN
+#[derive(Debug, Default)]
+pub struct QueryPlan {
+    pub steps: Vec<String>,
+    // Whether the query is actually run, so each step can say how many rows it produced,
+    // and how long it took
+    pub analyze: bool,
+}
+
+/// Describes how a query would be run. With ANALYZE, the query is run, and each step of
+/// the plan also has the number of rows it produced and the time it took.
+/// Each step of the plan is returned as a row.
+fn explain_query(
+    statement: &Statement,
+    clauses: &SelectClauses,
+    analyze: bool,
+    user: &mut User,
+) -> Result<(Vec<String>, Vec<Column>, Vec<Row>), Error> {
+    let query: &Query = match statement {
+        Statement::Query(query) => query,
+        _ => return Err("Only queries can be explained".into()),
+    };
+    let mut plan: QueryPlan = QueryPlan {
+        steps: Vec::new(),
+        analyze,
+    };
+    let start: Instant = Instant::now();
+    run_query(query, clauses, user, Some(&mut plan))?;
+    if analyze {
+        plan.steps.push(format!(
+            "Execution time: {}",
+            format_elapsed(start.elapsed())
+        ));
+    }
+
+    let width: usize = plan.steps.iter().map(|step| step.len()).max().unwrap_or(0);
+    Ok((
+        vec!["plan".to_string()],
+        vec![Column::String(width as u16)],
+        plan.steps
+            .into_iter()
+            .map(|step| vec![Value::String(step)])
+            .collect(),
+    ))
 }
 
+// A step of an analyzed plan, with the number of rows it produced and how long it took
+fn analyzed_step(step: String, rows: usize, elapsed: Duration) -> String {
+    format!(
+        "{} (rows: {}, time: {})",
+        step,
+        rows,
+        format_elapsed(elapsed)
+    )
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    format!("{:.3} ms", elapsed.as_secs_f64() * 1000.0)
+}
+
+// If a plan is given, the steps of the query are added to it. Unless the plan is being
+// analyzed, the query isn't run.
 fn parse_query(
     set_expr: &SetExpr,
+    clauses: &SelectClauses, // The clauses of the query's outermost SELECT
     user: &mut User,
     query: &Query,
-) -> Result<(Vec<String>, Vec<Row>), String> {
+    mut plan: Option<&mut QueryPlan>,
+) -> Result<(Vec<String>, Vec<Column>, Vec<Row>), Error> {
     match &set_expr {
-        SetExpr::Select(s) => parse_select(&s, user, Some(query)),
+        SetExpr::Select(s) => parse_select(&s, clauses, user, Some(query), plan),
         SetExpr::SetOperation {
             op,
             all: _,
             left,
             right,
         } => {
-            let (left_cols, left_rows) = parse_query(&left, user, query)?;
-            let (right_cols, right_rows) = parse_query(&right, user, query)?;
+            if let Some(plan) = plan.as_deref_mut() {
+                plan.steps.push(format!("{} of:", op));
+            }
+            let (left_cols, left_types, left_rows) =
+                parse_query(&left, clauses, user, query, plan.as_deref_mut())?;
+            let (right_cols, right_types, right_rows) =
+                parse_query(&right, clauses, user, query, plan.as_deref_mut())?;
 
             let row = set_operations(op, left_rows.clone(), right_rows.clone())?;
             if left_rows.is_empty() && !right_rows.is_empty() {
-                return Ok((right_cols, row));
+                return Ok((right_cols, right_types, row));
+            }
+            Ok((left_cols, left_types, row))
+        }
+        SetExpr::Query(q) => run_query(&q, &SelectClauses::default(), user, plan),
+        _ => Err("Not a select\n".into()),
+    }
+}
+
+// The most times the recursive part of a WITH RECURSIVE table is run before giving up,
+// since a recursive term that keeps producing new rows would never finish
+const MAX_RECURSIVE_CTE_ITERATIONS: usize = 1000;
+
+/// Runs a query, along with the tables defined by its WITH clause, which the query
+/// (and the tables defined after them) can read like any other table.
+/// The tables are kept in memory, and are dropped once the query is done.
+fn run_query(
+    query: &Query,
+    clauses: &SelectClauses, // The clauses of the query's outermost SELECT
+    user: &mut User,
+    mut plan: Option<&mut QueryPlan>,
+) -> Result<(Vec<String>, Vec<Column>, Vec<Row>), Error> {
+    let with = match &query.with {
+        Some(with) => with,
+        None => return parse_query(&query.body, clauses, user, query, plan),
+    };
+
+    let outer_tables: Vec<(String, String)> = user.get_cte_tables();
+    let mut result = Ok(());
+    for cte in &with.cte_tables {
+        let start: Instant = Instant::now();
+        let table_name: String = cte.alias.name.value.clone();
+        // Each table gets its own directory, so nested WITH clauses can reuse names
+        let table_dir: String = format!(
+            "{}cte{}{}{}{}{}{}",
+            IN_MEMORY_PATH_PREFIX,
+            std::path::MAIN_SEPARATOR,
+            get_db_instance()?.get_database_name(),
+            std::path::MAIN_SEPARATOR,
+            user.get_user_id(),
+            std::path::MAIN_SEPARATOR,
+            user.get_cte_tables().len()
+        );
+        let mut cte_tables: Vec<(String, String)> = user.get_cte_tables();
+        cte_tables.push((table_name.clone(), table_dir.clone()));
+        user.set_cte_tables(&cte_tables);
+
+        result = materialize_cte(cte, with.recursive, &table_dir, user);
+        if result.is_err() {
+            break;
+        }
+        if let Some(plan) = plan.as_deref_mut() {
+            let step: String = format!("Materialize {}", table_name);
+            if plan.analyze {
+                let rows: usize = Table::new(&table_dir, &table_name, None)?.count();
+                plan.steps.push(analyzed_step(step, rows, start.elapsed()));
+            } else {
+                plan.steps.push(step);
+            }
+        }
+    }
+    let output = result.and_then(|_| parse_query(&query.body, clauses, user, query, plan));
+
+    // Drop the tables, even if the query failed
+    for (table_name, table_dir) in user.get_cte_tables().iter().skip(outer_tables.len()) {
+        let table_path: String = format!(
+            "{}{}{}{}",
+            table_dir,
+            std::path::MAIN_SEPARATOR,
+            table_name,
+            TABLE_FILE_EXTENSION
+        );
+        if get_storage_backend(&table_path).exists(&table_path) {
+            get_storage_backend(&table_path).remove_file(&table_path)?;
+        }
+    }
+    user.set_cte_tables(&outer_tables);
+    output
+}
+
+/// Runs the query of a WITH table and writes its rows to the table.
+/// A recursive table is seeded with the left side of its UNION, after which the right side
+/// is run again and again against the rows found by the previous run, until it
+/// doesn't find any new rows.
+fn materialize_cte(
+    cte: &Cte,
+    recursive: bool,
+    table_dir: &String,
+    user: &mut User,
+) -> Result<(), Error> {
+    let table_name: &String = &cte.alias.name.value;
+    let (seed, recursive_term, all) = match cte.query.body.as_ref() {
+        SetExpr::SetOperation {
+            op: SetOperator::Union,
+            all,
+            left,
+            right,
+        } if recursive => (left.as_ref(), Some(right.as_ref()), *all),
+        body => (body, None, true),
+    };
+
+    let (columns, types, rows) =
+        parse_query(seed, &SelectClauses::default(), user, &cte.query, None)?;
+    let columns: Vec<String> = if cte.alias.columns.is_empty() {
+        columns
+            .iter()
+            .map(|column| column.rsplit('.').next().unwrap_or(column).to_string())
+            .collect()
+    } else if cte.alias.columns.len() == columns.len() {
+        cte.alias.columns.iter().map(|x| x.value.clone()).collect()
+    } else {
+        return Err(format!(
+            "{} has {} columns, but its query returns {}",
+            table_name,
+            cte.alias.columns.len(),
+            columns.len()
+        )
+        .into());
+    };
+
+    let recursive_term: &SetExpr = match recursive_term {
+        Some(term) => term,
+        None => {
+            return Ok(write_cte_table(
+                table_name, &columns, &types, rows, table_dir,
+            )?)
+        }
+    };
+
+    // With UNION, rows that were already found aren't added again
+    let mut seen: HashSet<Row> = HashSet::new();
+    let mut found: Vec<Row> = Vec::new();
+    let mut new_rows: Vec<Row> = Vec::new();
+    for row in rows {
+        if all || seen.insert(row.clone()) {
+            new_rows.push(row);
+        }
+    }
+
+    let mut types: Vec<Column> = types;
+    let mut iterations: usize = 0;
+    while !new_rows.is_empty() {
+        user.check_cancelled()?;
+        if iterations == MAX_RECURSIVE_CTE_ITERATIONS {
+            return Err(format!(
+                "{} didn't stop recursing after {} iterations",
+                table_name, MAX_RECURSIVE_CTE_ITERATIONS
+            )
+            .into());
+        }
+        iterations += 1;
+
+        // The recursive term only sees the rows found by the previous iteration
+        write_cte_table(table_name, &columns, &types, new_rows.clone(), table_dir)?;
+        found.append(&mut new_rows);
+        let (_, term_types, rows) = parse_query(
+            recursive_term,
+            &SelectClauses::default(),
+            user,
+            &cte.query,
+            None,
+        )?;
+        if term_types.len() != columns.len() {
+            return Err(format!(
+                "The recursive part of {} returns {} columns, but {} are expected",
+                table_name,
+                term_types.len(),
+                columns.len()
+            )
+            .into());
+        }
+        types = types
+            .iter()
+            .zip(term_types.iter())
+            .map(|(x, y)| {
+                promote_types(&x.clone().as_non_nullable(), &y.clone().as_non_nullable())
+                    .unwrap_or(x.clone())
+            })
+            .collect();
+        for row in rows {
+            if all || seen.insert(row.clone()) {
+                new_rows.push(row);
             }
-            Ok((left_cols, row))
         }
-        SetExpr::Query(q) => parse_query(&q.body, user, &q),
-        _ => Err("Not a select\n".to_string()),
     }
+    Ok(write_cte_table(
+        table_name, &columns, &types, found, table_dir,
+    )?)
+}
+
+// Creates the table of a WITH clause, holding the given rows.
+// The query's column types don't say how long their strings are, or whether they're
+// nullable, so those are worked out from the rows.
+fn write_cte_table(
+    table_name: &String,
+    columns: &Vec<String>,
+    types: &Vec<Column>,
+    rows: Vec<Row>,
+    table_dir: &String,
+) -> Result<(), String> {
+    let schema: Schema = columns
+        .iter()
+        .zip(types.iter())
+        .enumerate()
+        .map(|(i, (name, coltype))| {
+            let mut coltype: Column = coltype.clone().as_non_nullable();
+            if let Column::String(_) = coltype {
+                let width: usize = rows
+                    .iter()
+                    .map(|row| match &row[i] {
+                        Value::String(x) | Value::Char(x) => x.len(),
+                        _ => 0,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                coltype = Column::String(width.max(1) as u16);
+            }
+            if rows.iter().any(|row| row[i].is_null()) {
+                coltype = coltype.as_nullable();
+            }
+            (name.clone(), coltype)
+        })
+        .collect();
+    let rows: Vec<Row> = rows
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .zip(schema.iter())
+                .map(|(value, (_, coltype))| coltype.coerce_type(value))
+                .collect::<Result<Row, String>>()
+        })
+        .collect::<Result<Vec<Row>, String>>()?;
+
+    let (mut table, _) = create_table_in_dir(table_name, &schema, table_dir)?;
+    table.insert_rows(rows)?;
+    Ok(())
 }
 
 fn parse_select(
     s: &Select,
+    clauses: &SelectClauses,
     user: &mut User,
     query: Option<&Query>,
-) -> Result<(Vec<String>, Vec<Row>), String> {
+    plan: Option<&mut QueryPlan>,
+) -> Result<(Vec<String>, Vec<Column>, Vec<Row>), Error> {
     let mut columns = Vec::new();
     for c in s.projection.iter() {
         columns.push(c.clone());
     }
+    // There are no rows to read without a FROM, so the values are worked out once
+    if s.from.is_empty() {
+        return select_without_from(columns, &s.selection);
+    }
     let mut table_names = Vec::new();
+    // The fraction of rows to sample from each table, if it has a TABLESAMPLE
+    let mut table_samples: Vec<Option<f64>> = Vec::new();
     let where_clause: Option<Expr>;
     // This will be the new 'where' clause resulting from the joins
     let mut join_clause: Vec<Expr> = Vec::new();
@@ -100,23 +548,13 @@ fn parse_select(
     for t in s.from.iter() {
         if t.joins.len() > 0 {
             // Get the table name and alias if present
-            let table_name = t.relation.to_string();
-            let table_name: Vec<&str> = table_name.split(" ").collect();
-            if table_name.len() == 3 {
-                table_names.push((table_name[0].to_string(), table_name[2].to_string()));
-            } else {
-                table_names.push((table_name[0].to_string(), "".to_string()));
-            }
+            table_names.push(get_table_name(&t.relation)?);
+            table_samples.push(get_table_sample(clauses, table_samples.len())?);
 
             for j in t.joins.iter() {
                 // Get the table name and alias if present
-                let table_name = j.relation.to_string();
-                let table_name: Vec<&str> = table_name.split(" ").collect();
-                if table_name.len() == 3 {
-                    table_names.push((table_name[0].to_string(), table_name[2].to_string()));
-                } else {
-                    table_names.push((table_name[0].to_string(), "".to_string()));
-                }
+                table_names.push(get_table_name(&j.relation)?);
+                table_samples.push(get_table_sample(clauses, table_samples.len())?);
 
                 // Get the join condition
                 let join_condition: Expr = match &j.join_operator {
@@ -269,19 +707,20 @@ fn parse_select(
                 join_clause.push(join_condition);
             }
         } else {
-            let table_name = t.to_string();
-            let table_name: Vec<&str> = table_name.split(" ").collect();
-            if table_name.len() == 3 {
-                table_names.push((table_name[0].to_string(), table_name[2].to_string()));
-            } else {
-                table_names.push((table_name[0].to_string(), "".to_string()));
-            }
+            table_names.push(get_table_name(&t.relation)?);
+            table_samples.push(get_table_sample(clauses, table_samples.len())?);
         }
     }
 
+    // Evaluate any subqueries in the WHERE clause up front
+    let selection: Option<Expr> = match &s.selection {
+        Some(selection) => Some(resolve_subqueries(selection, user)?),
+        None => None,
+    };
+
     // If we don't have any joins, just use the existing WHERE clause
     if join_clause.len() == 0 {
-        where_clause = s.selection.clone();
+        where_clause = selection;
     }
     // If we do have joins
     else {
@@ -296,7 +735,7 @@ fn parse_select(
         }
 
         // If we have an existing WHERE clause, add the join clause to it
-        let where_clause_joins: Expr = match &s.selection {
+        let where_clause_joins: Expr = match &selection {
             Some(clause) => Expr::BinaryOp {
                 left: Box::new(clause.clone()),
                 op: sqlparser::ast::BinaryOperator::And,
@@ -308,29 +747,64 @@ fn parse_select(
         where_clause = Some(where_clause_joins);
     }
 
+    // The keys of DISTINCT ON are selected after the columns, and removed once they're used
+    let mut select_columns: Vec<SelectItem> = columns.clone();
+    if let Some(keys) = &clauses.distinct_on {
+        select_columns.extend(keys.iter().cloned().map(SelectItem::UnnamedExpr));
+    }
+
     // Execute the select statement
-    let (res_columns, mut res_rows) = select(
-        columns.clone(),
+    let (mut res_columns, mut res_types, mut res_rows) = select_with_types(
+        select_columns,
         where_clause,
         s.group_by.clone(),
         query.map_or(vec![], |q| q.order_by.clone()),
         &table_names,
+        &table_samples,
+        &clauses.aggregates,
         get_db_instance()?,
         user,
+        plan,
     )?;
 
+    if let Some(keys) = &clauses.distinct_on {
+        let num_columns: usize = res_columns.len() - keys.len();
+        res_rows = first_row_of_each_key(res_rows, num_columns, &user.get_collation());
+        res_columns.truncate(num_columns);
+        res_types.truncate(num_columns);
+    }
+
     if unioned_rows.len() > 0 {
+        // The rows without a match in the joined table are padded with nulls
+        for row in &unioned_rows {
+            for (i, value) in row.iter().enumerate() {
+                if value.is_null() && i < res_types.len() {
+                    res_types[i] = res_types[i].clone().as_nullable();
+                }
+            }
+        }
         res_rows.append(&mut unioned_rows);
     }
 
     // Limit and Offset
     if let Some(query) = query {
-        let limit: Option<usize> = match &query.limit {
+        // FETCH FIRST n ROWS ONLY is the standard way to write LIMIT n
+        let limit_expr: Option<&Expr> = match (&query.limit, &query.fetch) {
+            (Some(_), Some(_)) => return Err("A query cannot have both a LIMIT and a FETCH".into()),
+            (_, Some(fetch)) if fetch.percent || fetch.with_ties => {
+                return Err(format!("Unsupported FETCH: {}", fetch).into())
+            }
+            (_, Some(fetch)) => fetch.quantity.as_ref(),
+            (limit, None) => limit.as_ref(),
+        };
+        let limit: Option<usize> = match limit_expr {
             Some(l) => match resolve_pure_value(l)? {
                 Value::I32(i) => Some(i as usize),
                 Value::I64(i) => Some(i as usize),
                 _ => None,
             },
+            // FETCH FIRST ROW ONLY keeps a single row
+            None if query.fetch.is_some() => Some(1),
             None => None,
         };
         let offset: usize = match &query.offset {
@@ -342,32 +816,100 @@ fn parse_select(
             None => 0,
         };
         if offset >= res_rows.len() {
-            return Ok((res_columns, Vec::new()));
+            return Ok((res_columns, res_types, Vec::new()));
         }
         if let Some(l) = limit {
             res_rows = res_rows[offset..(offset + l).min(res_rows.len())].to_vec();
         }
     }
-    Ok((res_columns, res_rows))
+    Ok((res_columns, res_types, res_rows))
+}
+
+/// Keeps the first of the rows that have the same key, where the key is every value past the
+/// first num_columns values of the row. The key is removed from the rows that are kept.
+/// Like in a GROUP BY, values that the collation treats as the same are the same key.
+fn first_row_of_each_key(rows: Vec<Row>, num_columns: usize, collation: &Collation) -> Vec<Row> {
+    let mut seen: HashSet<Row> = HashSet::new();
+    let mut kept: Vec<Row> = Vec::new();
+    for mut row in rows {
+        let key: Row = row
+            .split_off(num_columns)
+            .into_iter()
+            .map(|value| collation.key(value))
+            .collect();
+        if seen.insert(key) {
+            kept.push(row);
+        }
+    }
+    kept
 }
 
 pub fn execute_update(
-    ast: &Vec<Statement>,
+    ast: &Vec<ParsedStatement>,
     user: &mut User,
     command: &String,
-) -> Result<String, String> {
+) -> Result<String, Error> {
     if ast.len() == 0 {
-        return Err("Empty AST".to_string());
+        return Err("Empty AST".into());
+    }
+
+    // With the write-ahead log on, a crash partway through the statements is rolled back
+    let _database: DatabaseSelection = select_database(user.get_database_name());
+    let database: &Database = get_db_instance()?;
+    if database.is_write_ahead_log_enabled() {
+        let wal_dir: String = database.get_wal_dir_path();
+        return run_logged(&wal_dir, || {
+            // The pages of failed statements are rolled back, so their changes are dropped too
+            let diffs: Vec<Diff> = user.get_diffs();
+            let result: Result<String, Error> = execute_update_unlogged(ast, user, command);
+            if result.is_err() {
+                user.set_diffs(&diffs);
+            }
+            result
+        });
     }
+    execute_update_unlogged(ast, user, command)
+}
+
+fn execute_update_unlogged(
+    ast: &Vec<ParsedStatement>,
+    user: &mut User,
+    command: &String,
+) -> Result<String, Error> {
     let mut results: Vec<String> = Vec::new();
+    // A rolled back command didn't change anything, so it isn't kept with the user's commands
+    let mut is_rolled_back: bool = false;
+
     // Commands: create, insert, select
-    for a in ast.iter() {
+    for parsed in ast.iter() {
+        // A statement before this one may have switched the user to another database
+        let _database: DatabaseSelection = select_database(user.get_database_name());
+        let _session: SessionSelection = select_session(user);
+        let a: &Statement = &parsed.statement;
         match a {
+            Statement::CreateDatabase {
+                db_name,
+                if_not_exists,
+                ..
+            } => {
+                if user.get_permissions() == Read {
+                    return Err(Error::permission_denied(
+                        "You do not have permission to create a database",
+                    ));
+                }
+                let database_name: String = db_name.to_string();
+                if *if_not_exists && open_db_instance(&database_name).is_ok() {
+                    results.push(format!("Database already exists: {}", database_name));
+                } else {
+                    create_named_db_instance(&database_name)?;
+                    results.push(format!("Database created: {}", database_name));
+                }
+            }
             Statement::CreateIndex {
                 name,
                 table_name,
                 columns,
-                unique: _,
+                unique,
                 if_not_exists: _,
             } => {
                 let table_dir: String = get_db_instance()?.get_current_working_branch_path(user);
@@ -382,99 +924,19 @@ pub fn execute_update(
                     None,
                     column_names,
                     index_name,
+                    *unique,
                 )?;
 
                 user.append_diff(&Diff::IndexCreate(idx_new_diff));
                 results.push("Successfully created index".to_string());
             }
-            Statement::Update {
-                table,
-                assignments,
-                from: _,
-                selection,
-            } => {
-                let final_table; // What is the best way to do this?
-                let mut all_data: Vec<(String, Expr)> = Vec::new();
-                let final_alias;
-
-                match table.relation.clone() {
-                    sqlparser::ast::TableFactor::Table {
-                        name: table_name,
-                        alias,
-                        args: _,
-                        with_hints: _,
-                    } => {
-                        // Now you have the table
-                        final_alias = match alias {
-                            Some(x) => x.to_string(),
-                            None => "".to_string(),
-                        };
-                        final_table = table_name.to_string();
-                    }
-                    _ => {
-                        // Not a table inside the TableFactor enum
-                        return Err("Error parsing".to_string());
-                    }
-                }
-
-                // Iterate through and build vector of assignments to pass to update
-                for assignment in assignments {
-                    let column_name;
-                    let insert_value = assignment.value.clone();
-                    column_name = assignment.id[0].value.clone();
-
-                    all_data.push((column_name, insert_value));
-                }
-
-                results.push(
-                    update(
-                        all_data,
-                        final_table,
-                        final_alias,
-                        selection.clone(),
-                        get_db_instance()?,
-                        user,
-                    )?
-                    .0,
-                );
+            Statement::Update { .. } => {
+                let (message, diff, returning) = run_update_statement(parsed, false, user)?;
+                results.push(with_returned_rows(message, returning, &diff.rows)?);
             }
-            Statement::Delete {
-                table_name,
-                using: _,
-                selection,
-            } => {
-                let final_table; // What is the best way to do this?
-                let final_alias;
-                match table_name.clone() {
-                    sqlparser::ast::TableFactor::Table {
-                        name: table_name,
-                        alias,
-                        args: _,
-                        with_hints: _,
-                    } => {
-                        // Now you have the table
-                        final_alias = match alias {
-                            Some(x) => x.to_string(),
-                            None => "".to_string(),
-                        };
-                        final_table = table_name.to_string();
-                    }
-                    _ => {
-                        // Not a table inside the TableFactor enum
-                        return Err("Error parsing".to_string());
-                    }
-                }
-
-                results.push(
-                    delete(
-                        final_table,
-                        final_alias,
-                        selection.clone(),
-                        get_db_instance()?,
-                        user,
-                    )?
-                    .0,
-                );
+            Statement::Delete { .. } => {
+                let (message, diff, returning) = run_delete_statement(parsed, false, user)?;
+                results.push(with_returned_rows(message, returning, &diff.rows)?);
             }
             Statement::Drop {
                 object_type,
@@ -485,31 +947,41 @@ pub fn execute_update(
             } => {
                 match object_type.clone() {
                     sqlparser::ast::ObjectType::Table => {
-                        if names.len() != 1 {
-                            return Err("Can only drop one table at a time".to_string());
+                        // Every table is checked before any are dropped, so a missing table
+                        // doesn't leave the tables before it dropped. With IF EXISTS, tables
+                        // that don't exist on this branch are skipped instead.
+                        let existing_tables: Vec<String> = get_db_instance()?.get_tables(user)?;
+                        let mut table_names: Vec<String> = Vec::new();
+                        for name in names {
+                            let table_name: String = name.to_string();
+                            if !existing_tables.contains(&table_name) {
+                                if !if_exists {
+                                    return Err(Error::not_found(format!(
+                                        "Table {} does not exist",
+                                        table_name
+                                    )));
+                                }
+                                results.push(format!("Table does not exist: {}", table_name));
+                            } else if !table_names.contains(&table_name) {
+                                table_names.push(table_name);
+                            }
                         }
 
-                        let table_name: String = names[0].to_string();
-
-                        // If the table doesn't exist on this branch, return an error
-                        if (!if_exists)
-                            && (!get_db_instance()?.get_tables(user)?.contains(&table_name))
-                        {
-                            return Err(format!("Table {} does not exist", table_name));
+                        // Each table gets its own diff, so they can be restored on their own
+                        for table_name in table_names {
+                            let result: TableRemoveDiff =
+                                drop_table(&table_name, get_db_instance()?, user)?;
+                            results.push(format!("Table dropped: {}", result.table_name));
                         }
-
-                        let result: TableRemoveDiff =
-                            drop_table(&table_name, get_db_instance()?, user)?;
-                        results.push(format!("Table dropped: {}", result.table_name));
                     }
                     sqlparser::ast::ObjectType::Index => {
                         if names.len() != 1 {
-                            return Err("Can only drop one index at a time".to_string());
+                            return Err("Can only drop one index at a time".into());
                         }
 
                         let idents: Vec<Ident> = names[0].0.clone();
                         if idents.len() != 2 {
-                            return Err("Must specify one index and table to drop {table_name}.{index_name}".to_string());
+                            return Err("Must specify one index and table to drop {table_name}.{index_name}".into());
                         }
 
                         let table_name: &String = &idents[0].value;
@@ -525,53 +997,184 @@ pub fn execute_update(
                         results.push(format!("Index dropped: {}", index_name));
                     }
                     _ => {
-                        return Err("Can only drop tables and indexes".to_string());
+                        return Err("Can only drop tables and indexes".into());
                     }
                 }
             }
-            Statement::CreateTable { name, columns, .. } => {
+            Statement::CreateTable {
+                name,
+                columns,
+                with_options,
+                ..
+            } => {
                 let table_name = name.0[0].value.to_string();
                 let mut schema = Schema::new();
 
                 for c in columns.iter() {
+                    check_column_name(&c.name.value)?;
                     schema.push((c.name.value.clone(), Column::from_col_def(c)?));
                 }
-                let _result = create_table(&table_name, &schema, get_db_instance()?, user)?;
+                let mut ttl: Option<u64> = None;
+                for option in with_options {
+                    if option.name.value.to_lowercase() != "ttl" {
+                        return Err(format!("Unsupported table option: {}", option.name).into());
+                    }
+                    let ttl_seconds: u64 = option.value.to_string().parse().map_err(|_| {
+                        format!(
+                            "The TTL must be a whole number of seconds: {}",
+                            option.value
+                        )
+                    })?;
+                    schema.push(ttl_column());
+                    ttl = Some(ttl_seconds);
+                }
+                let _result =
+                    create_table_with_ttl(&table_name, &schema, ttl, get_db_instance()?, user)?;
                 results.push(format!("Table created: {}", table_name));
             }
             Statement::Insert {
                 table_name,
-                columns: _,
+                columns,
                 source,
                 ..
             } => {
                 let table_name = table_name.0[0].value.to_string();
+                let returning: Option<ReturnedColumns> =
+                    prepare_returning(&parsed.clauses.returning, &table_name, user)?;
                 // Keeping all_data as a vector of rows allows us to also easily integrate select later on
                 let mut all_data: Vec<Row> = Vec::new();
                 match *source.body.clone() {
                     SetExpr::Values(values) => {
                         let values_list = values.0;
+                        let schema: Schema =
+                            Table::from_user(user, get_db_instance()?, &table_name, None)?.schema;
+
+                        // Make sure every row has a value for each column before anything is written
+                        let num_columns: usize = if columns.is_empty() {
+                            visible_column_count(&schema)
+                        } else {
+                            columns.len()
+                        };
+                        for (i, row) in values_list.iter().enumerate() {
+                            if row.len() != num_columns {
+                                return Err(format!(
+                                    "Row {} of VALUES has {} values, but {} columns were expected",
+                                    i + 1,
+                                    row.len(),
+                                    num_columns
+                                )
+                                .into());
+                            }
+                        }
+
                         for row in values_list {
                             let mut data = Vec::new();
-                            for k in row {
+                            for (i, k) in row.iter().enumerate() {
+                                if is_default_keyword(k) {
+                                    data.push(get_column_default(&schema, i)?);
+                                    continue;
+                                }
                                 data.push(
                                     // We don't need any additional information to solve this, hence the empty vectors and maps
                                     // Here, we effectively convert the Expr's into our Value types
-                                    resolve_pure_value(&k)?,
+                                    resolve_pure_value(k)?,
                                 );
                             }
                             all_data.push(data);
                         }
                     }
                     SetExpr::Select(v) => {
-                        let (_, rows) = parse_select(&v, user, None)?;
+                        let (_, _, rows) =
+                            parse_select(&v, &SelectClauses::default(), user, None, None)?;
                         all_data = rows;
                     }
                     _ => {
-                        return Err("Expected a Values statement".to_string());
+                        return Err("Expected a Values statement".into());
+                    }
+                }
+                let (message, rows) = match &parsed.clauses.on_conflict {
+                    Some(on_conflict) => insert_on_conflict(
+                        all_data,
+                        table_name,
+                        on_conflict,
+                        get_db_instance()?,
+                        user,
+                    )?,
+                    None => {
+                        let (message, diff) =
+                            insert(all_data, table_name, get_db_instance()?, user)?;
+                        (message, diff.rows)
                     }
+                };
+                results.push(with_returned_rows(message, returning, &rows)?);
+            }
+            Statement::Copy {
+                table_name,
+                columns,
+                to,
+                target,
+                options,
+                legacy_options,
+                ..
+            } => {
+                if !columns.is_empty() {
+                    return Err("COPY with a column list is not supported".into());
+                }
+                let file_path: String = match target {
+                    CopyTarget::File { filename } => filename.clone(),
+                    _ if *to => return Err("COPY can only export to a file".into()),
+                    _ => return Err("COPY can only import from a file".into()),
+                };
+                let table_name: String = table_name.0[0].value.to_string();
+                if *to {
+                    let export_options: SqlExportOptions =
+                        SqlExportOptions::from_copy_options(options, legacy_options)?;
+                    results.push(export_sql(
+                        &table_name,
+                        &file_path,
+                        &export_options,
+                        get_db_instance()?,
+                        user,
+                    )?);
+                } else {
+                    let import_options: CsvImportOptions =
+                        CsvImportOptions::from_copy_options(options, legacy_options)?;
+                    results.push(import_csv(
+                        &table_name,
+                        &file_path,
+                        &import_options,
+                        get_db_instance()?,
+                        user,
+                    )?);
+                }
+            }
+            Statement::SetVariable {
+                variable, value, ..
+            } => {
+                results.push(set_variable(&variable.to_string(), value, user)?);
+            }
+            Statement::StartTransaction { .. } => {
+                if user.get_transaction_start().is_some() {
+                    return Err("A transaction is already open".into());
+                }
+                user.set_transaction_start(Some((
+                    user.get_diffs().len(),
+                    user.get_commands().len(),
+                )));
+                results.push("Transaction started".to_string());
+            }
+            Statement::Commit { .. } => {
+                if user.get_transaction_start().is_none() {
+                    return Err("There is no transaction to commit".into());
                 }
-                results.push(insert(all_data, table_name, get_db_instance()?, user)?.0);
+                // The changes are already in the user's diffs, so they only have to be kept
+                user.set_transaction_start(None);
+                results.push("Transaction committed".to_string());
+            }
+            Statement::Rollback { .. } => {
+                rollback_transaction(user)?;
+                is_rolled_back = true;
+                results.push("Transaction rolled back".to_string());
             }
             Statement::AlterTable { name, operation } => {
                 let instance = get_db_instance()?;
@@ -579,36 +1182,42 @@ pub fn execute_update(
 
                 let all_tables = instance.get_tables(user)?;
                 if !all_tables.clone().contains(&table_name) {
-                    return Err(format!("Table {} does not exist", table_name));
+                    return Err(Error::not_found(format!(
+                        "Table {} does not exist",
+                        table_name
+                    )));
                 }
 
                 let table = Table::from_user(user, &instance, &table_name, None)?;
 
                 let mut schemas = table.schema.clone();
+                let ttl: Option<u64> = table.ttl;
+                let mut indexes: Vec<NamedIndex> = get_named_indexes(&table);
                 let mut rows = table.into_iter().collect::<Vec<RowInfo>>();
 
                 match operation {
                     AlterTableOperation::AddColumn { column_def } => {
                         let column_name = column_def.name.value.to_string();
+                        check_column_name(&column_name)?;
                         if schemas.iter().any(|x| x.0 == column_name) {
-                            return Err(format!("Column name {} already exists", column_name));
+                            return Err(
+                                format!("Column name {} already exists", column_name).into()
+                            );
                         }
                         let column = Column::from_col_def(&column_def)?;
                         let column_nullable = column.clone().as_nullable();
                         let added_schema = (column_name.clone(), column_nullable.clone());
                         schemas.push(added_schema);
-                        for r in rows.iter_mut() {
-                            r.row.push(Value::Null(column.clone()));
+                        if user.get_alter_dry_run() {
+                            results.push(format!(
+                                "Dry run: column {}({:?}) can be added to Table {}",
+                                column_name, column, table_name
+                            ));
+                            continue;
                         }
 
-                        // drop the old table
-                        drop_table(&table_name, instance, user)?;
-
-                        // create the new table with the new schema and insert values
-                        create_table(&table_name, &schemas, instance, user)?;
-                        for r in rows.iter() {
-                            insert(vec![r.row.clone()], table_name.clone(), instance, user)?;
-                        }
+                        // The table is changed in place, so its indexes are kept
+                        change_table_schema(&table_name, &schemas, instance, user)?;
 
                         results.push(format!(
                             "Column added {}({:?}) to Table {}",
@@ -618,12 +1227,32 @@ pub fn execute_update(
                     AlterTableOperation::DropColumn {
                         column_name,
                         if_exists: _,
-                        cascade: _,
+                        cascade,
                     } => {
                         let column_name = column_name.to_string();
 
                         if !schemas.iter().any(|x| x.0 == column_name) {
-                            return Err(format!("Column name {} does not exist", column_name));
+                            return Err(Error::not_found(format!(
+                                "Column name {} does not exist",
+                                column_name
+                            )));
+                        }
+
+                        // The indexes that include the column can't be kept without it
+                        let dependent_indexes: Vec<String> = indexes
+                            .iter()
+                            .filter(|(_, columns, _)| columns.contains(&column_name))
+                            .map(|(index_name, _, _)| index_name.clone())
+                            .collect();
+                        if !dependent_indexes.is_empty()
+                            && user.get_restrict_column_drops()
+                            && !*cascade
+                        {
+                            return Err(format!(
+                                "Column {} is used by the indexes {}, so it can only be dropped with CASCADE",
+                                column_name,
+                                dependent_indexes.join(", ")
+                            ).into());
                         }
 
                         // find the index of the column to drop
@@ -634,23 +1263,31 @@ pub fn execute_update(
 
                         // drop the index in the vector
                         schemas.remove(column_index);
-                        for r in rows.iter_mut() {
-                            r.row.remove(column_index);
+                        if user.get_alter_dry_run() {
+                            results.push(format!(
+                                "Dry run: column {} can be dropped from Table {}",
+                                column_name, table_name
+                            ));
+                            for index_name in &dependent_indexes {
+                                results.push(format!(
+                                    "Dry run: index {} would be dropped",
+                                    index_name
+                                ));
+                            }
+                            continue;
                         }
 
-                        // drop the old table
-                        drop_table(&table_name, instance, user)?;
-
-                        // create the new table with the new schema and insert values
-                        create_table(&table_name, &schemas, instance, user)?;
-                        for r in rows.iter() {
-                            insert(vec![r.row.clone()], table_name.clone(), instance, user)?;
-                        }
+                        // The table is changed in place, so the indexes on other columns are kept,
+                        // and the ones that include the column are dropped
+                        change_table_schema(&table_name, &schemas, instance, user)?;
 
                         results.push(format!(
                             "Column {} dropped in Table {}",
                             column_name, table_name
                         ));
+                        for index_name in &dependent_indexes {
+                            results.push(format!("Index dropped: {}", index_name));
+                        }
                     }
                     AlterTableOperation::ChangeColumn {
                         old_name,
@@ -660,6 +1297,8 @@ pub fn execute_update(
                     } => {
                         let old_name = old_name.to_string();
                         let new_name = new_name.to_string();
+                        check_column_name(&old_name)?;
+                        check_column_name(&new_name)?;
                         let mut column = Column::from_datatype_def(data_type)?;
 
                         if options.len() > 0 {
@@ -672,14 +1311,17 @@ pub fn execute_update(
                         }
 
                         if !schemas.iter().any(|x| x.0 == old_name) {
-                            return Err(format!("Column name {} does not exist", old_name));
+                            return Err(Error::not_found(format!(
+                                "Column name {} does not exist",
+                                old_name
+                            )));
                         }
 
                         if schemas
                             .iter()
                             .any(|x| x.0 == old_name && x.1.is_nullable() && !column.is_nullable())
                         {
-                            return Err(format!("Cannot change Nullable to not Nullable"));
+                            return Err(format!("Cannot change Nullable to not Nullable").into());
                         }
 
                         // find the index of the column to drop
@@ -691,43 +1333,168 @@ pub fn execute_update(
                         // drop the replace in the vector
                         schemas[column_index] = (new_name.clone(), column.clone());
 
-                        for r in rows.iter_mut() {
-                            r.row[column_index] =
-                                column.clone().coerce_type(r.row[column_index].clone())?;
+                        // Every row is checked before anything is changed, so the rows that
+                        // don't fit the new type can all be reported
+                        let values: Vec<Value> = check_column_change(
+                            &rows,
+                            column_index,
+                            &column,
+                            &old_name,
+                            &user.get_string_overflow_policy(),
+                        )?;
+                        for (r, value) in rows.iter_mut().zip(values) {
+                            r.row[column_index] = value;
+                        }
+                        rename_index_column(&mut indexes, &old_name, &new_name);
+                        if user.get_alter_dry_run() {
+                            results.push(format!(
+                                "Dry run: column {} can be changed to {}({:?}) in Table {}",
+                                old_name, new_name, column, table_name
+                            ));
+                            continue;
                         }
 
                         // drop the old table
                         drop_table(&table_name, instance, user)?;
 
                         // create the new table with the new schema and insert values
-                        create_table(&table_name, &schemas, instance, user)?;
+                        create_table_with_ttl(&table_name, &schemas, ttl, instance, user)?;
                         for r in rows.iter() {
                             insert(vec![r.row.clone()], table_name.clone(), instance, user)?;
                         }
+                        recreate_indexes(&table_name, &indexes, instance, user)?;
 
                         results.push(format!(
                             "Column {} changed to {}({:?}) in Table {}",
                             old_name, new_name, column, table_name
                         ));
                     }
-                    _ => {
-                        return Err("Can only add, drop, or change columns".to_string());
-                    }
-                }
-            }
-            _ => {
-                return Err(format!("Not a valid command: {0}", a));
-            }
-        }
+                    AlterTableOperation::RenameColumn {
+                        old_column_name,
+                        new_column_name,
+                    } => {
+                        let old_name: String = old_column_name.value.to_string();
+                        let new_name: String = new_column_name.value.to_string();
+                        check_column_name(&old_name)?;
+                        check_column_name(&new_name)?;
+                        let column_index: usize = schemas
+                            .iter()
+                            .position(|(name, _)| name == &old_name)
+                            .ok_or(Error::not_found(format!(
+                                "Column name {} does not exist",
+                                old_name
+                            )))?;
+                        if schemas.iter().any(|(name, _)| name == &new_name) {
+                            return Err(format!("Column name {} already exists", new_name).into());
+                        }
+                        schemas[column_index].0 = new_name.clone();
+                        rename_index_column(&mut indexes, &old_name, &new_name);
+                        if user.get_alter_dry_run() {
+                            results.push(format!(
+                                "Dry run: column {} can be renamed to {} in Table {}",
+                                old_name, new_name, table_name
+                            ));
+                            continue;
+                        }
+
+                        // The indexes refer to their columns by name, so they're recreated
+                        // with the new name once the table is rebuilt
+                        drop_table(&table_name, instance, user)?;
+                        create_table_with_ttl(&table_name, &schemas, ttl, instance, user)?;
+                        let rows: Vec<Row> = rows.into_iter().map(|r| r.row).collect();
+                        if !rows.is_empty() {
+                            insert(rows, table_name.clone(), instance, user)?;
+                        }
+                        recreate_indexes(&table_name, &indexes, instance, user)?;
+
+                        results.push(format!(
+                            "Column {} renamed to {} in Table {}",
+                            old_name, new_name, table_name
+                        ));
+                    }
+                    AlterTableOperation::AddConstraint(TableConstraint::Unique {
+                        name,
+                        columns,
+                        is_primary: false,
+                    }) => {
+                        let column_names: Vec<String> =
+                            columns.iter().map(|c| c.value.clone()).collect();
+                        // Index names are limited to 20 characters in the table header
+                        let index_name: String = match name {
+                            Some(name) => name.value.clone(),
+                            None => format!("{}_key", column_names.join("_"))
+                                .chars()
+                                .take(20)
+                                .collect(),
+                        };
+                        if user.get_alter_dry_run() {
+                            check_unique_columns(&rows, &schemas, &column_names)?;
+                            results.push(format!(
+                                "Dry run: unique index {} can be added to Table {}",
+                                index_name, table_name
+                            ));
+                            continue;
+                        }
+
+                        let table_dir: String = instance.get_current_working_branch_path(user);
+                        let (_, idx_new_diff): (_, IndexCreateDiff) = BTree::create_btree_index(
+                            &table_dir,
+                            &table_name,
+                            None,
+                            column_names,
+                            index_name.clone(),
+                            true,
+                        )?;
+
+                        user.append_diff(&Diff::IndexCreate(idx_new_diff));
+                        results.push(format!(
+                            "Unique index {} added to Table {}",
+                            index_name, table_name
+                        ));
+                    }
+                    _ => {
+                        return Err(
+                            "Can only add, drop, change, or rename columns, or add unique indexes"
+                                .to_string()
+                                .into(),
+                        );
+                    }
+                }
+            }
+            _ => {
+                return Err(format!("Not a valid command: {0}", a).into());
+            }
+        }
     }
     if results.len() == 0 {
-        Err("No command found".to_string())
+        Err("No command found".into())
     } else {
-        user.append_command(command);
+        if !is_rolled_back {
+            user.append_command(command);
+        }
         Ok(results.join("\n"))
     }
 }
 
+/// Undoes the changes made since the user's open transaction began, and closes it.
+/// The diffs and commands from the transaction are taken off of the user as well.
+pub fn rollback_transaction(user: &mut User) -> Result<(), String> {
+    let (num_diffs, num_commands): (usize, usize) = user
+        .get_transaction_start()
+        .ok_or("There is no transaction to roll back".to_string())?;
+    let diffs: Vec<Diff> = user.get_diffs();
+    let commands: Vec<String> = user.get_commands();
+    let num_diffs: usize = num_diffs.min(diffs.len());
+    let num_commands: usize = num_commands.min(commands.len());
+
+    let table_dir: String = get_db_instance()?.get_current_working_branch_path(user);
+    revert_tables_from_diffs(&table_dir, &diffs[num_diffs..].to_vec())?;
+    user.set_diffs(&diffs[..num_diffs].to_vec());
+    user.set_commands(&commands[..num_commands].to_vec());
+    user.set_transaction_start(None);
+    Ok(())
+}
+
 /// Creates a new table within the given database named <table_name><TABLE_FILE_EXTENSION>
 /// with the given schema.
 /// It appends the diff to the user passed in
@@ -736,15 +1503,30 @@ pub fn create_table(
     schema: &Schema,
     database: &Database,
     user: &mut User,
-) -> Result<(Table, TableCreateDiff), String> {
+) -> Result<(Table, TableCreateDiff), Error> {
+    create_table_with_ttl(table_name, schema, None, database, user)
+}
+
+/// Creates a new table like create_table() does, whose rows expire once they are older than
+/// the TTL in seconds. The schema must include the hidden column made by ttl_column().
+pub fn create_table_with_ttl(
+    table_name: &String,
+    schema: &Schema,
+    ttl: Option<u64>,
+    database: &Database,
+    user: &mut User,
+) -> Result<(Table, TableCreateDiff), Error> {
     if user.get_permissions() == Read {
-        return Err("You do not have permission to create a table".to_string());
+        return Err(Error::permission_denied(
+            "You do not have permission to create a table",
+        ));
     }
+    check_writable_table(table_name)?;
 
     let table_dir: String = database.get_current_working_branch_path(&user);
 
     // Create a table file and return it
-    let results = tableio::create_table(table_name, schema, &table_dir)?;
+    let results = tableio::create_table(table_name, schema, ttl, &table_dir)?;
     user.append_diff(&Diff::TableCreate(results.1.clone()));
     Ok(results)
 }
@@ -755,10 +1537,13 @@ pub fn drop_table(
     table_name: &String,
     database: &Database,
     user: &mut User,
-) -> Result<TableRemoveDiff, String> {
+) -> Result<TableRemoveDiff, Error> {
     if user.get_permissions() == Read {
-        return Err("You do not have permission to drop a table".to_string());
+        return Err(Error::permission_denied(
+            "You do not have permission to drop a table",
+        ));
     }
+    check_writable_table(table_name)?;
 
     let table_dir: String = database.get_current_working_branch_path(user);
 
@@ -768,6 +1553,348 @@ pub fn drop_table(
     Ok(results)
 }
 
+/// Changes the columns of a table in place, such as to add or drop a column, rather than
+/// recreating it. The rows keep their locations, except for those that no longer fit on their
+/// page, which are moved. The indexes on the columns that are kept stay as they are, and the
+/// others are dropped. It appends the diffs to the user passed in
+pub fn change_table_schema(
+    table_name: &String,
+    schema: &Schema,
+    database: &Database,
+    user: &mut User,
+) -> Result<(), Error> {
+    if user.get_permissions() == Read {
+        return Err(Error::permission_denied(
+            "You do not have permission to alter a table",
+        ));
+    }
+    check_writable_table(table_name)?;
+
+    let table_dir: String = database.get_current_working_branch_path(user);
+    let table: Table = Table::new(&table_dir, table_name, None)?;
+
+    // Every row is converted before anything is changed, so a row that doesn't fit the new
+    // schema leaves the table as it was
+    let moved: Vec<RowInfo> = table.get_rows_outside(schema);
+    for row_info in table.clone() {
+        convert_row(&row_info.row, &table.schema, schema)?;
+    }
+
+    // If a step still fails, the steps before it are undone
+    let mut diffs: Vec<Diff> = Vec::new();
+    if let Err(e) = apply_schema_change(&table, schema, moved, &table_dir, &mut diffs) {
+        revert_tables_from_diffs(&table_dir, &diffs)?;
+        return Err(e.into());
+    }
+    for diff in diffs.iter() {
+        user.append_diff(diff);
+    }
+    Ok(())
+}
+
+// Makes the changes for change_table_schema(), adding each diff as soon as its change is made
+fn apply_schema_change(
+    table: &Table,
+    schema: &Schema,
+    moved: Vec<RowInfo>,
+    table_dir: &String,
+    diffs: &mut Vec<Diff>,
+) -> Result<(), String> {
+    for (index_id, (_, index_name)) in &table.indexes {
+        let is_kept: bool = index_id
+            .iter()
+            .all(|i| schema.contains(&table.schema[*i as usize]));
+        if !is_kept {
+            let diff: IndexRemoveDiff =
+                BTree::drop_btree_index(table_dir, &table.name, None, index_name)?;
+            diffs.push(Diff::IndexRemove(diff));
+        }
+    }
+
+    // Wider rows fit fewer times on a page, so the rows past the end are moved to free slots
+    let mut table: Table = Table::new(table_dir, &table.name, None)?;
+    let remove_diff: RemoveDiff =
+        table.remove_rows(moved.iter().map(|r| r.get_row_location()).collect())?;
+    if !remove_diff.rows.is_empty() {
+        diffs.push(Diff::Remove(remove_diff));
+    }
+    let old_schema: Schema = table.schema.clone();
+    diffs.push(Diff::SchemaChange(table.change_schema(schema)?));
+    let moved_rows: Vec<Row> = moved
+        .iter()
+        .map(|r| convert_row(&r.row, &old_schema, schema))
+        .collect::<Result<Vec<Row>, String>>()?;
+    let insert_diff: InsertDiff = table.insert_rows(moved_rows)?;
+    if !insert_diff.rows.is_empty() {
+        diffs.push(Diff::Insert(insert_diff));
+    }
+    Ok(())
+}
+
+/// An index of a table, given by its name, the names of its columns, and whether it's unique.
+pub type NamedIndex = (String, Vec<String>, bool);
+
+/// Gets the indexes of a table, with their columns given by name rather than by position,
+/// so they can be recreated after the table's columns are moved or renamed.
+pub fn get_named_indexes(table: &Table) -> Vec<NamedIndex> {
+    let mut indexes: Vec<NamedIndex> = table
+        .indexes
+        .iter()
+        .map(|(index_id, (_, index_name))| {
+            let columns: Vec<String> = index_id
+                .iter()
+                .map(|i| table.schema[*i as usize].0.clone())
+                .collect();
+            (
+                index_name.clone(),
+                columns,
+                table.unique_indexes.contains(index_id),
+            )
+        })
+        .collect();
+    indexes.sort();
+    indexes
+}
+
+// Points the indexes on a column at its new name
+fn rename_index_column(indexes: &mut [NamedIndex], old_name: &str, new_name: &str) {
+    for (_, columns, _) in indexes.iter_mut() {
+        for column in columns.iter_mut().filter(|column| *column == old_name) {
+            *column = new_name.to_string();
+        }
+    }
+}
+
+/// Checks that every row of a table can have one of its columns changed to the given type,
+/// before anything is changed. The values of the column in the new type are returned.
+/// Otherwise, the error lists each row whose value would fail to convert, be cut short,
+/// or lose its value, along with the reason.
+pub fn check_column_change(
+    rows: &Vec<RowInfo>,
+    column_index: usize,
+    column: &Column,
+    column_name: &String,
+    overflow_policy: &StringOverflowPolicy,
+) -> Result<Vec<Value>, String> {
+    let mut values: Vec<Value> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    for row_info in rows {
+        let value: &Value = &row_info.row[column_index];
+        match change_value_type(value, column, column_name, overflow_policy) {
+            Ok(value) => values.push(value),
+            Err(err) => errors.push(format!(
+                "{}, in row ({})",
+                err,
+                row_info.row.iter().map(|x| x.to_string()).join(", ")
+            )),
+        }
+    }
+    if errors.is_empty() {
+        return Ok(values);
+    }
+    errors.push(format!(
+        "{} rows can't be changed to type {:?}, so column {} was left as it is",
+        errors.len(),
+        column,
+        column_name
+    ));
+    Err(errors.join("\n"))
+}
+
+// Converts a value to a column's type, failing if that would change the value
+fn change_value_type(
+    value: &Value,
+    column: &Column,
+    column_name: &String,
+    overflow_policy: &StringOverflowPolicy,
+) -> Result<Value, String> {
+    let new_value: Value = column.coerce_type(value.clone())?;
+    let new_value: Value = column.enforce_length(new_value, column_name, overflow_policy)?;
+    let is_changed: bool = match (&new_value, value) {
+        // Integers wrap around when they're too big, and lose the fraction of a float
+        (Value::I32(_) | Value::I64(_), _) => new_value != *value,
+        (Value::Float(x), Value::Double(y)) => x.is_infinite() && y.is_finite(),
+        _ => false,
+    };
+    if is_changed {
+        return Err(format!(
+            "Value {} can't be stored as type {:?} without changing it",
+            value.to_string(),
+            column
+        ));
+    }
+    Ok(new_value)
+}
+
+// Checks that no two rows have the same values in the given columns, as a unique index on them
+// would need. Keys with a null in them are never duplicates. The error lists the rows that are.
+fn check_unique_columns(
+    rows: &Vec<RowInfo>,
+    schema: &Schema,
+    column_names: &Vec<String>,
+) -> Result<(), String> {
+    let index_id: IndexID = column_names
+        .iter()
+        .map(|name| {
+            schema
+                .iter()
+                .position(|(column, _)| column == name)
+                .map(|i| i as u8)
+                .ok_or(format!("Column name {} does not exist", name))
+        })
+        .collect::<Result<IndexID, String>>()?;
+
+    // Duplicate keys end up next to each other once the rows are sorted
+    let mut rows: Vec<&RowInfo> = rows.iter().collect();
+    rows.sort_by(|a, b| compare_rows_using_index_id(&a.row, &b.row, &index_id));
+    let mut errors: Vec<String> = Vec::new();
+    for pair in rows.windows(2) {
+        let key: IndexKey = get_index_key_from_row(&pair[0].row, &index_id);
+        let is_null: bool = key.iter().any(|value| matches!(value, Value::Null(_)));
+        if !is_null && key == get_index_key_from_row(&pair[1].row, &index_id) {
+            errors.push(format!(
+                "Duplicate key {:?}, in row ({})",
+                key,
+                pair[1].row.iter().map(|x| x.to_string()).join(", ")
+            ));
+        }
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.push(format!(
+        "{} rows have the same ({}) as another row",
+        errors.len(),
+        column_names.join(", ")
+    ));
+    Err(errors.join("\n"))
+}
+
+/// Creates the given indexes on a table that was rebuilt, such as by ALTER TABLE.
+/// Each index gets its own diff, as if it were created with CREATE INDEX.
+pub fn recreate_indexes(
+    table_name: &String,
+    indexes: &Vec<NamedIndex>,
+    database: &Database,
+    user: &mut User,
+) -> Result<(), String> {
+    let table_dir: String = database.get_current_working_branch_path(user);
+    for (index_name, columns, unique) in indexes {
+        let (_, diff): (_, IndexCreateDiff) = BTree::create_btree_index(
+            &table_dir,
+            table_name,
+            None,
+            columns.clone(),
+            index_name.clone(),
+            *unique,
+        )?;
+        user.append_diff(&Diff::IndexCreate(diff));
+    }
+    Ok(())
+}
+
+/// Rewrites a table so that its rows are packed together, without the free slots left by
+/// deleted rows. Like ALTER TABLE, the table is rebuilt, so the rewrite is recorded in diffs.
+pub fn vacuum_table(
+    table_name: &String,
+    database: &Database,
+    user: &mut User,
+) -> Result<String, Error> {
+    if !database.get_tables(user)?.contains(table_name) {
+        return Err(Error::not_found(format!(
+            "Table {} does not exist",
+            table_name
+        )));
+    }
+    let table: Table = Table::from_user(user, database, table_name, None)?;
+    let schema: Schema = table.schema.clone();
+    let ttl: Option<u64> = table.ttl;
+    let indexes: Vec<NamedIndex> = get_named_indexes(&table);
+    let rows: Vec<Row> = table.into_iter().map(|row_info| row_info.row).collect();
+    let num_rows: usize = rows.len();
+
+    drop_table(table_name, database, user)?;
+    create_table_with_ttl(table_name, &schema, ttl, database, user)?;
+    if !rows.is_empty() {
+        insert(rows, table_name.clone(), database, user)?;
+    }
+    recreate_indexes(table_name, &indexes, database, user)?;
+    Ok(format!(
+        "Table {} vacuumed: {} rows kept",
+        table_name, num_rows
+    ))
+}
+
+/// Counts the pages that the indexes of a table are made of
+pub fn count_index_pages(table: &Table) -> Result<usize, String> {
+    let mut num_pages: usize = 0;
+    for (index_id, (pagenum, index_name)) in &table.indexes {
+        let index_key_type: IndexKeyType = index_id
+            .iter()
+            .map(|x| table.schema[*x as usize].1.clone())
+            .collect();
+        let btree: BTree = BTree::load_btree_from_root_page(
+            table,
+            *pagenum,
+            index_id.clone(),
+            index_key_type,
+            index_name.clone(),
+        )?;
+        num_pages += btree.count_pages()?;
+    }
+    Ok(num_pages)
+}
+
+/// Rebuilds every index of a table from its rows, so that their pages are evenly filled again
+/// after inserts and deletes have left them uneven. With vacuum, the whole table is rewritten,
+/// which rebuilds the indexes along with it. Like vacuuming, the rebuild is recorded in diffs.
+pub fn optimize_table(
+    table_name: &String,
+    vacuum: bool,
+    database: &Database,
+    user: &mut User,
+) -> Result<String, Error> {
+    if user.get_permissions() == Read {
+        return Err(Error::permission_denied(
+            "You do not have permission to alter a table",
+        ));
+    }
+    check_writable_table(table_name)?;
+    if !database.get_tables(user)?.contains(table_name) {
+        return Err(Error::not_found(format!(
+            "Table {} does not exist",
+            table_name
+        )));
+    }
+    let table: Table = Table::from_user(user, database, table_name, None)?;
+    let pages_before: usize = count_index_pages(&table)?;
+
+    let mut results: Vec<String> = Vec::new();
+    if vacuum {
+        results.push(vacuum_table(table_name, database, user)?);
+    } else {
+        let table_dir: String = database.get_current_working_branch_path(user);
+        let indexes: Vec<NamedIndex> = get_named_indexes(&table);
+        for (index_name, _, _) in &indexes {
+            let diff: IndexRemoveDiff =
+                BTree::drop_btree_index(&table_dir, table_name, None, index_name)?;
+            user.append_diff(&Diff::IndexRemove(diff));
+        }
+        recreate_indexes(table_name, &indexes, database, user)?;
+    }
+
+    let table: Table = Table::from_user(user, database, table_name, None)?;
+    let pages_after: usize = count_index_pages(&table)?;
+    results.push(format!(
+        "Table {} optimized: {} indexes rebuilt, index pages went from {} to {}",
+        table_name,
+        table.indexes.len(),
+        pages_before,
+        pages_after
+    ));
+    Ok(results.join("\n"))
+}
+
 /// This method implements the SQL Select statement. It takes in the column and table names where table_names
 /// is an array of tuples where the first element is the table name and the second element is the alias.
 /// It returns a tuple containing the schema and the rows of the resulting table.
@@ -779,27 +1906,170 @@ pub fn select(
     table_names: &Vec<(String, String)>,
     database: &Database,
     user: &User, // If a user is present, query that user's branch. Otherwise, query main branch
-) -> Result<(Vec<String>, Vec<Row>), String> {
+) -> Result<(Vec<String>, Vec<Row>), Error> {
+    let (column_names, _, rows) = select_with_types(
+        columns,
+        where_expr,
+        group_by,
+        order_by,
+        table_names,
+        &vec![None; table_names.len()],
+        &Vec::new(),
+        database,
+        user,
+        None,
+    )?;
+    Ok((column_names, rows))
+}
+
+/// Works out the values of a select without a FROM, like `SELECT CURRENT_USER()`, as a single row
+fn select_without_from(
+    columns: Vec<SelectItem>,
+    selection: &Option<Expr>,
+) -> Result<(Vec<String>, Vec<Column>, Vec<Row>), Error> {
+    if selection.is_some() {
+        return Err("A SELECT without a FROM cannot have a WHERE clause".into());
+    }
+    let mut column_names: Vec<String> = Vec::new();
+    let mut row: Row = Vec::new();
+    for item in columns {
+        let expr: Expr = match item {
+            SelectItem::ExprWithAlias { expr, alias } => {
+                column_names.push(alias.value);
+                expr
+            }
+            SelectItem::UnnamedExpr(expr) => {
+                column_names.push(expr.to_string());
+                expr
+            }
+            _ => return Err(format!("Cannot select {} without a FROM", item).into()),
+        };
+        row.push(resolve_pure_value(&expr)?);
+    }
+    let column_types: Vec<Column> = row.iter().map(|value| value.get_coltype()).collect();
+    Ok((column_names, column_types, vec![row]))
+}
+
+/// Same as select, but also returns the type of each selected column.
+/// Each table can be given a fraction of its rows to randomly sample, in which case the
+/// query runs as if the sampled rows were the only rows in that table.
+/// If a plan is given, the steps the query takes are added to it. Unless the plan is being
+/// analyzed, no rows are read.
+pub fn select_with_types(
+    columns: Vec<SelectItem>,
+    where_expr: Option<Expr>,
+    group_by: Vec<Expr>,        // Empty if no group by
+    order_by: Vec<OrderByExpr>, // Empty if no order by
+    table_names: &Vec<(String, String)>,
+    table_samples: &Vec<Option<f64>>, // The fraction of rows to sample from each table
+    aggregates: &Vec<AggregateClauses>, // The clauses of the aggregates in the selected items
+    database: &Database,
+    user: &User, // If a user is present, query that user's branch. Otherwise, query main branch
+    mut plan: Option<&mut QueryPlan>,
+) -> Result<(Vec<String>, Vec<Column>, Vec<Row>), Error> {
     if table_names.len() == 0 || columns.len() == 0 {
-        return Err("Malformed SELECT Command".to_string());
+        return Err("Malformed SELECT Command".into());
     }
 
     if user.get_permissions() == Write {
-        return Err("You do not have the permission to read tables".to_string());
+        return Err(Error::permission_denied(
+            "You do not have the permission to read tables",
+        ));
+    }
+
+    // The other functions are solved a row at a time, so they can't have any clauses
+    if let Some(call_clauses) = aggregates
+        .iter()
+        .find(|call_clauses| is_scalar_function(&call_clauses.call))
+    {
+        return Err(format!(
+            "{} is not an aggregate, so it can't have clauses",
+            call_clauses.call
+        )
+        .into());
     }
 
     // The schema that would be returned from the select statement
     let mut column_names: Vec<String> = Vec::new();
 
     let tables: Tables = load_aliased_tables(database, user, &table_names)?;
+    // Rows that outlived their table's TTL are skipped, until a write removes them
+    let table_ttls: Vec<Option<(usize, u64)>> =
+        tables.iter().map(|(table, _)| get_ttl(table)).collect();
+    let now_seconds: i64 = super::ttl::now().seconds;
 
     // This is where the fun begins... ;)
     let table_aliases: ColumnAliases = gen_column_aliases(&tables);
     let index_refs = get_index_refs(&table_aliases);
 
+    // Keep track of the aliases given to the selected items, so the group by and order by can refer to them
+    let projection_aliases: Vec<(String, usize)> = get_projection_aliases(&columns);
+
     // Pass through columns with no aliases used to provide an alias if unambiguous
-    let mut column_exprs: Vec<Expr> =
-        resolve_columns(columns, &mut column_names, &tables, &table_aliases)?;
+    let mut column_items: Vec<usize> = Vec::new();
+    let mut column_exprs: Vec<Expr> = resolve_columns(
+        columns,
+        &mut column_names,
+        &mut column_items,
+        &tables,
+        &table_aliases,
+    )?;
+    // Each column's aggregates have the clauses of the ones in the item it came from
+    let mut column_clauses: Vec<Vec<AggregateClauses>> = column_items
+        .iter()
+        .map(|item| {
+            aggregates
+                .iter()
+                .filter(|call_clauses| call_clauses.item == *item)
+                .cloned()
+                .collect()
+        })
+        .collect();
+    let column_types: Vec<Column> =
+        resolve_column_types(&column_exprs, &column_clauses, &table_aliases)?;
+
+    // Resolve group by and order by positions and aliases into the selected column they refer to
+    let group_by: Vec<Expr> = group_by
+        .into_iter()
+        .map(|expr| {
+            let column: Option<usize> =
+                get_projection_reference(&expr, &column_items, &projection_aliases)?;
+            Ok(column.map_or(expr, |column| column_exprs[column].clone()))
+        })
+        .collect::<Result<Vec<Expr>, String>>()?;
+    // An order by that refers to a selected column has the clauses of its aggregates too
+    let mut order_clauses: Vec<Vec<AggregateClauses>> = Vec::new();
+    let order_by: Vec<OrderByExpr> = order_by
+        .into_iter()
+        .map(|mut order_exp| {
+            let column: Option<usize> =
+                get_projection_reference(&order_exp.expr, &column_items, &projection_aliases)?;
+            if let Some(column) = column {
+                order_exp.expr = column_exprs[column].clone();
+            }
+            order_clauses.push(column.map_or(Vec::new(), |column| column_clauses[column].clone()));
+            Ok(order_exp)
+        })
+        .collect::<Result<Vec<OrderByExpr>, String>>()?;
+
+    // Correlated EXISTS subqueries are checked separately, as they need to look at other tables
+    let (where_expr, exists_subqueries) = match where_expr {
+        Some(expr) => split_exists_subqueries(expr)?,
+        None => (None, Vec::new()),
+    };
+    let exists_filters: Vec<ExistsFilter> = exists_subqueries
+        .iter()
+        .map(|(subquery, negated)| {
+            solve_exists(
+                subquery,
+                *negated,
+                &table_aliases,
+                &index_refs,
+                database,
+                user,
+            )
+        })
+        .collect::<Result<Vec<ExistsFilter>, String>>()?;
 
     // Convert the where expression into a predicate solver
     let where_pred: Option<PredicateSolver> = match &where_expr {
@@ -807,20 +2077,84 @@ pub fn select(
         None => None,
     };
 
+    // Counting the rows that an index finds doesn't need the rows to be read
+    let is_plain_count: bool = plan.is_none()
+        && group_by.is_empty()
+        && order_by.is_empty()
+        && exists_filters.is_empty()
+        && table_samples.iter().all(|sample| sample.is_none())
+        && aggregates.is_empty()
+        && table_ttls.iter().all(|ttl| ttl.is_none());
+    if is_plain_count {
+        let count: Option<usize> = count_with_index(
+            &column_exprs,
+            &where_expr,
+            &tables,
+            &table_aliases,
+            &index_refs,
+        )?;
+        if let Some(count) = count {
+            // As with a scan, there's no group to count when no rows match
+            let rows: Vec<Row> = match count {
+                0 => Vec::new(),
+                _ => vec![vec![Value::I32(count as i32)]],
+            };
+            return Ok((column_names, column_types, rows));
+        }
+    }
+
+    // The steps of the query, in case it's being explained
+    let mut steps: Vec<String> = Vec::new();
+    let explain_only: bool = plan.as_ref().is_some_and(|plan| !plan.analyze);
+    let analyze: bool = plan.as_ref().is_some_and(|plan| plan.analyze);
+    // The estimated (rows read, rows kept) of each table, when the query is only explained
+    let mut estimated_rows: Vec<(usize, usize)> = Vec::new();
+
     // Construct the iterators for each table
     let mut table_iters: Vec<TableIterator> = Vec::new();
-    for (table, alias) in tables {
+    for ((table, alias), sample) in tables.into_iter().zip(table_samples.iter()) {
+        // Sampled tables are scanned once, and only the sampled rows are kept
+        if let Some(fraction) = sample {
+            let step: String = format!(
+                "Sample {}% of table {} as {}",
+                fraction * 100.0,
+                table.name,
+                alias
+            );
+            if explain_only {
+                let table_rows: usize = estimate_table_rows(&table);
+                estimated_rows.push((table_rows, (table_rows as f64 * fraction) as usize));
+                steps.push(step);
+                continue;
+            }
+            let start: Instant = Instant::now();
+            let mut rng: StdRng = match user.get_sample_seed() {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+            let mut sampled_rows: Vec<RowInfo> = Vec::new();
+            for row_info in table {
+                user.check_cancelled()?;
+                if rng.gen_bool(*fraction) {
+                    sampled_rows.push(row_info);
+                }
+            }
+            steps.push(analyzed_step(step, sampled_rows.len(), start.elapsed()));
+            table_iters.push(TableIterator::RowIter(RowIterator::new(sampled_rows)));
+            continue;
+        }
+
         // If we are using a where predicate, check if we can use an index
         let mut used_index: bool = false;
         if where_pred.is_some() {
             let expr: Expr = where_expr.clone().unwrap();
 
-            // Get the index id for this specific table for this specific query
-            let index_id: Option<IndexID> =
-                get_index_id_from_expr(&expr, &table_aliases, &index_refs, &alias)?;
+            // Get the index for this specific table for this specific query
+            let index_choice: Option<(IndexID, Expr)> =
+                choose_index(&expr, &table_aliases, &index_refs, &alias, &table, user)?;
 
             // If we can use an index (i.e. the where clause references only one table)
-            if let Some(index_id) = index_id {
+            if let Some((index_id, index_expr)) = index_choice {
                 // Check if this table has this index
                 if let Some(idx_val) = table.indexes.get(&index_id) {
                     // We can use the index, so we can use the index to get the rows
@@ -831,6 +2165,12 @@ pub fn select(
                         .map(|x| table.schema[*x as usize].1.clone())
                         .collect();
 
+                    used_index = true;
+                    let step: String = format!(
+                        "Index scan on table {} as {} using index {}",
+                        table.name, alias, index_name
+                    );
+                    let start: Instant = Instant::now();
                     let btree: BTree = BTree::load_btree_from_root_page(
                         &table,
                         btree_pagenum,
@@ -838,26 +2178,75 @@ pub fn select(
                         index_key_type,
                         index_name,
                     )?;
+                    if explain_only {
+                        // The index is read to see how many rows it finds, but the rows aren't
+                        let index_rows: usize = btree.count_rows_matching_expr(&index_expr)?;
+                        estimated_rows.push((index_rows, index_rows));
+                        steps.push(step);
+                        continue;
+                    }
 
-                    let res_rows: Vec<RowInfo> = btree.get_rows_matching_expr(&expr)?;
+                    let res_rows: Vec<RowInfo> = btree.get_rows_matching_expr(&index_expr)?;
+                    steps.push(analyzed_step(step, res_rows.len(), start.elapsed()));
 
                     // Load the result rows into a row iterator
                     table_iters.push(TableIterator::RowIter(RowIterator::new(res_rows)));
-                    used_index = true;
                 }
             }
         }
 
         if !used_index {
-            table_iters.push(TableIterator::TableIter(table));
+            let step: String = format!("Scan table {} as {}", table.name, alias);
+            if explain_only {
+                let table_rows: usize = estimate_table_rows(&table);
+                estimated_rows.push((table_rows, table_rows));
+            }
+            if analyze {
+                // The table is read up front, so that the scan can be counted and timed
+                let start: Instant = Instant::now();
+                let rows: Vec<RowInfo> = table.into_iter().collect();
+                steps.push(analyzed_step(step, rows.len(), start.elapsed()));
+                table_iters.push(TableIterator::RowIter(RowIterator::new(rows)));
+            } else {
+                steps.push(step);
+                table_iters.push(TableIterator::TableIter(table));
+            }
+        }
+    }
+
+    if let Some(plan) = plan.as_deref_mut().filter(|_| explain_only) {
+        if table_names.len() > 1 {
+            steps.push(format!("Join {} tables", table_names.len()));
+        }
+        if let Some(expr) = &where_expr {
+            steps.push(format!("Filter: {}", expr));
         }
+        steps.extend(
+            exists_filters
+                .iter()
+                .map(|filter| filter.description.clone()),
+        );
+        if !group_by.is_empty() {
+            steps.push(format!("Group by: {}", group_by.iter().join(", ")));
+        }
+        if !order_by.is_empty() {
+            steps.push(format!("Sort by: {}", order_by.iter().join(", ")));
+        }
+        steps.push(estimate_cost(
+            &estimated_rows,
+            !group_by.is_empty(),
+            !order_by.is_empty(),
+        ));
+        plan.steps.append(&mut steps);
+        return Ok((column_names, column_types, Vec::new()));
     }
 
     // Create an iterator of table iterators using the cartesion product of the tables
     let table_iterator: MultiProduct<TableIterator> =
         table_iters.into_iter().multi_cartesian_product();
 
-    // Add order by cases to the column expressions (and track when to discard them later)
+    // Add order by cases to the column expressions (and track when to discard them later).
+    // They're solved along with the selected columns, so in a grouped query they can be aggregates.
     let order_start: usize = column_exprs.len();
     column_exprs.append(
         &mut order_by
@@ -865,25 +2254,76 @@ pub fn select(
             .map(|order_exp| order_exp.expr.clone())
             .collect(),
     );
+    column_clauses.append(&mut order_clauses);
 
     // Instead of directly adding rows to a Vector, we add them to a HashMap from the group_by columns to the rows in that group
     let mut grouped_rows: HashMap<Row, Vec<(Row, Row)>> = HashMap::new();
 
     let column_solver: Vec<ValueSolver> = solve_row(&column_exprs, &table_aliases, &index_refs)?;
     let group_solver: Vec<ValueSolver> = solve_row(&group_by, &table_aliases, &index_refs)?;
+    // Without a group by, simple aggregates are added up during the scan, so no rows are kept
+    let mut running_aggregates: Option<RunningAggregates> = match group_by.is_empty() {
+        true => {
+            RunningAggregates::new(&column_exprs, &column_clauses, &table_aliases, &index_refs)?
+        }
+        false => None,
+    };
+    let collation: Collation = user.get_collation();
     // let order_solver: ComparisonSolver = solve_comparison(&order_by, &table_aliases, &index_refs)?;
 
+    // What each step after the scans did, in case the query is being analyzed
+    let scan_start: Instant = Instant::now();
+    let mut joined_count: usize = 0;
+    let mut matched_count: usize = 0;
+    let mut filter_time: Duration = Duration::ZERO;
+
     // The table_iterator returns a vector of rows where each row is a vector of cells on each iteration
+    let mut scan_barrier: Option<Arc<Barrier>> =
+        SCAN_BARRIER.with(|scan_barrier| scan_barrier.borrow_mut().take());
     for table_rows in table_iterator {
+        if let Some(barrier) = scan_barrier.take() {
+            barrier.wait();
+            barrier.wait();
+        }
+        // Stop scanning if the user cancelled the query
+        user.check_cancelled()?;
+
+        let is_row_expired: bool = table_rows
+            .iter()
+            .zip(&table_ttls)
+            .any(|(row_info, ttl)| is_expired(ttl, &row_info.row, now_seconds));
+        if is_row_expired {
+            continue;
+        }
+
         // Flatten the entire output row, but it includes all columns from all tables
         let mut output_row: Row = Vec::new();
         for row_info in table_rows {
             output_row.extend(row_info.row);
         }
-        if resolve_predicate(&where_pred, &output_row)? {
+        joined_count += 1;
+        let filter_start: Instant = Instant::now();
+        let mut matches: bool = resolve_predicate(&where_pred, &output_row)?;
+        for filter in &exists_filters {
+            if !matches {
+                break;
+            }
+            matches = (filter.solver)(&output_row)?;
+        }
+        filter_time += filter_start.elapsed();
+        if matches {
+            matched_count += 1;
             // Iterate through the output row and apply the column functions to each row
             let selected_cells: Row = resolve_row(&column_solver, &output_row)?;
-            let group_row: Row = resolve_row(&group_solver, &output_row)?;
+            if let Some(running_aggregates) = &mut running_aggregates {
+                running_aggregates.add(selected_cells, &output_row)?;
+                continue;
+            }
+            // Values that the collation treats as the same are in the same group
+            let group_row: Row = resolve_row(&group_solver, &output_row)?
+                .into_iter()
+                .map(|value| collation.key(value))
+                .collect();
             // Append the selected_cells row to our result
             grouped_rows
                 .entry(group_row)
@@ -892,16 +2332,73 @@ pub fn select(
         }
     }
 
+    let scan_time: Duration = scan_start.elapsed();
+
     // Solve aggregate functions and create the selected rows that are now ready to be returned
-    let mut resolved_groups: Vec<Row> = grouped_rows
-        .into_values()
-        .map(|rows| resolve_aggregates(rows, &column_exprs, &table_aliases, &index_refs))
-        .flatten_ok()
-        .collect::<Result<Vec<Row>, String>>()?;
+    let group_start: Instant = Instant::now();
+    let group_count: usize = grouped_rows.len();
+    let mut resolved_groups: Vec<Row> = match running_aggregates {
+        Some(running_aggregates) => running_aggregates.finish()?,
+        None => grouped_rows
+            .into_values()
+            .map(|rows| {
+                resolve_aggregates(
+                    rows,
+                    &column_exprs,
+                    &column_clauses,
+                    &table_aliases,
+                    &index_refs,
+                )
+            })
+            .flatten_ok()
+            .collect::<Result<Vec<Row>, String>>()?,
+    };
+
+    let group_time: Duration = group_start.elapsed();
 
     // Sort the remaining rows using the order by clause
-    resolved_groups
-        .sort_unstable_by(|row1, row2| resolve_comparison(row1, row2, order_start, &order_by));
+    let sort_start: Instant = Instant::now();
+    resolved_groups.sort_unstable_by(|row1, row2| {
+        resolve_comparison(row1, row2, order_start, &order_by, &collation)
+    });
+    let sort_time: Duration = sort_start.elapsed();
+
+    if let Some(plan) = plan {
+        if table_names.len() > 1 {
+            steps.push(analyzed_step(
+                format!("Join {} tables", table_names.len()),
+                joined_count,
+                scan_time.saturating_sub(filter_time),
+            ));
+        }
+        if let Some(expr) = &where_expr {
+            steps.push(analyzed_step(
+                format!("Filter: {}", expr),
+                matched_count,
+                filter_time,
+            ));
+        }
+        steps.extend(
+            exists_filters
+                .iter()
+                .map(|filter| filter.description.clone()),
+        );
+        if !group_by.is_empty() {
+            steps.push(analyzed_step(
+                format!("Group by: {}", group_by.iter().join(", ")),
+                group_count,
+                group_time,
+            ));
+        }
+        if !order_by.is_empty() {
+            steps.push(analyzed_step(
+                format!("Sort by: {}", order_by.iter().join(", ")),
+                resolved_groups.len(),
+                sort_time,
+            ));
+        }
+        plan.steps.append(&mut steps);
+    }
 
     // Drop the order by columns now
     let selected_rows: Vec<Row> = resolved_groups
@@ -909,49 +2406,557 @@ pub fn select(
         .map(|row| row[0..order_start].to_vec())
         .collect();
 
-    Ok((column_names, selected_rows))
+    Ok((column_names, column_types, selected_rows))
 }
 
-fn solve_row(
-    group_by: &Vec<Expr>,
-    table_aliases: &ColumnAliases,
-    index_refs: &IndexRefs,
-) -> Result<Vec<ValueSolver>, String> {
-    let group_solver = group_by
+/// An estimate of the number of rows in a table, from the number of pages in its file.
+/// Every page is taken to be full of rows, so it's high for tables with deleted rows or indexes.
+fn estimate_table_rows(table: &Table) -> usize {
+    table.max_pages as usize * (PAGE_SIZE / table.schema_size)
+}
+
+/// Describes the estimated cost of a query, given the estimated (rows read, rows kept) of each
+/// of its tables. Each row read costs 1, as does each row made by joining the tables, or put
+/// into a group. Sorting n rows costs n log n. The estimate is only meant for comparing ways to
+/// write a query, such as whether it can use an index.
+fn estimate_cost(estimated_rows: &Vec<(usize, usize)>, hash: bool, sort: bool) -> String {
+    let mut rows_examined: usize = estimated_rows.iter().map(|(read, _)| read).sum();
+    let joined_rows: usize = estimated_rows
         .iter()
-        .map(|item| solve_value(item, &table_aliases, &index_refs))
-        .collect::<Result<Vec<ValueSolver>, String>>()?;
-    Ok(group_solver)
+        .fold(1, |joined: usize, (_, kept)| joined.saturating_mul(*kept));
+    if estimated_rows.len() > 1 {
+        rows_examined = rows_examined.saturating_add(joined_rows);
+    }
+    let mut cost: usize = rows_examined;
+    if hash {
+        cost = cost.saturating_add(joined_rows);
+    }
+    if sort {
+        let sort_cost: f64 = joined_rows as f64 * (joined_rows.max(2) as f64).log2();
+        cost = cost.saturating_add(sort_cost.ceil() as usize);
+    }
+    let yes_no = |needed: bool| if needed { "yes" } else { "no" };
+    format!(
+        "Estimated cost: {} (rows examined: {}, hash: {}, sort: {})",
+        cost,
+        rows_examined,
+        yes_no(hash),
+        yes_no(sort)
+    )
 }
 
-fn resolve_row(column_funcs: &Vec<ValueSolver>, output_row: &Row) -> Result<Row, String> {
-    let selected_cells: Row = column_funcs
+/// Works out the type of each selected column. Columns that are selected directly keep the
+/// type they were declared with. Any other expression is typed from its structure, see
+/// infer_expr_type, so its type follows the same coercions that are used when the query runs.
+fn resolve_column_types(
+    column_exprs: &Vec<Expr>,
+    column_clauses: &Vec<Vec<AggregateClauses>>,
+    table_aliases: &ColumnAliases,
+) -> Result<Vec<Column>, String> {
+    column_exprs
         .iter()
-        .map(|f| resolve_value(f, &output_row))
-        .collect::<Result<Vec<_>, _>>()?;
-    Ok(selected_cells)
+        .zip(column_clauses)
+        .map(|(expr, clauses)| infer_expr_type(expr, clauses, table_aliases))
+        .collect()
 }
 
-/// This method implements the SQL update statement
-pub fn update(
-    values: Vec<(String, Expr)>,
-    table_name: String,
-    alias: String,
-    where_expr: Option<Expr>,
-    database: &Database,
-    user: &mut User,
-) -> Result<(String, UpdateDiff), String> {
-    if user.get_permissions() == Read {
-        return Err("You do not have permission to write to this table".to_string());
+// The name of the table that holds the sample values standing in for an expression's operands
+const SAMPLE_TABLE: &str = "#sample";
+
+/// The type of an expression, worked out from its structure. Column references have the type
+/// they were declared with, and literals the type of their value. Anything else is solved with
+/// each of its operands replaced by a sample value of the operand's type, so an expression that
+/// would fail on some row, like dividing by a column that's zero there, can still be typed.
+/// One that fails on the sample values too, like dividing by a literal zero, gives the error
+/// that the query would give on its rows. Aggregates are solved on the sample row as they are,
+/// along with their clauses.
+fn infer_expr_type(
+    expr: &Expr,
+    clauses: &Vec<AggregateClauses>,
+    table_aliases: &ColumnAliases,
+) -> Result<Column, String> {
+    let column_name: Option<String> = match expr {
+        Expr::Identifier(x) => Some(x.value.to_string()),
+        Expr::CompoundIdentifier(list) => Some(
+            list.iter()
+                .map(|x| x.value.to_string())
+                .collect::<Vec<String>>()
+                .join("."),
+        ),
+        _ => None,
+    };
+    if let Some(column_name) = column_name {
+        let column_name: String = resolve_reference(column_name, table_aliases)?;
+        return table_aliases
+            .iter()
+            .find(|(name, _, _)| name == &column_name)
+            .map(|(_, coltype, _)| coltype.clone())
+            .ok_or(format!(
+                "Column {} does not exist in the table",
+                column_name
+            ));
     }
 
-    database.get_table_path(&table_name, user)?;
-    let table: Table = Table::from_user(user, database, &table_name, None)?;
-    let mut selected_rows: Vec<RowInfo> = Vec::new();
-    let tables: Tables =
-        load_aliased_tables(database, user, &vec![(table_name.clone(), alias.clone())])?;
+    // Each operand becomes a column of the sample table, holding a sample value of its type
+    let mut sample_aliases: ColumnAliases = table_aliases.clone();
+    let sample_expr: Expr = replace_operands(expr, &mut |operand: &Expr| {
+        let coltype: Column = infer_expr_type(operand, clauses, table_aliases)?;
+        let name: String = (sample_aliases.len() - table_aliases.len()).to_string();
+        sample_aliases.push((format!("{}.{}", SAMPLE_TABLE, name), coltype, name.clone()));
+        Ok(Expr::CompoundIdentifier(vec![
+            Ident::new(SAMPLE_TABLE),
+            Ident::new(name),
+        ]))
+    })?;
+    let sample_row: Row = sample_aliases
+        .iter()
+        .map(|(_, coltype, _)| get_sample_value(coltype))
+        .collect();
+    // The sample row might not match the FILTER of an aggregate, which would leave it no rows
+    let unfiltered_clauses: Vec<AggregateClauses> = clauses
+        .iter()
+        .map(|call_clauses| AggregateClauses {
+            filter: None,
+            ..call_clauses.clone()
+        })
+        .collect();
+    solve_aggregate(
+        &vec![sample_row],
+        &sample_expr,
+        &unfiltered_clauses,
+        &sample_aliases,
+        &get_index_refs(&sample_aliases),
+    )
+    .map(|value| value.get_coltype())
+}
+
+// Rebuilds the expression with each of its direct operands passed through replace. Literals
+// are kept, as are the lists of ANY and ALL, which are only ever compared against.
+fn replace_operands(
+    expr: &Expr,
+    replace: &mut dyn FnMut(&Expr) -> Result<Expr, String>,
+) -> Result<Expr, String> {
+    let mut operand = |expr: &Expr| -> Result<Box<Expr>, String> {
+        match expr {
+            Expr::Value(_) | Expr::AnyOp(_) | Expr::AllOp(_) => Ok(Box::new(expr.clone())),
+            _ => Ok(Box::new(replace(expr)?)),
+        }
+    };
+    Ok(match expr {
+        Expr::Nested(x) => Expr::Nested(operand(x)?),
+        Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+            op: op.clone(),
+            expr: operand(expr)?,
+        },
+        Expr::BinaryOp { left, op, right } => Expr::BinaryOp {
+            left: operand(left)?,
+            op: op.clone(),
+            right: operand(right)?,
+        },
+        Expr::IsNull(x) => Expr::IsNull(operand(x)?),
+        Expr::IsNotNull(x) => Expr::IsNotNull(operand(x)?),
+        Expr::IsTrue(x) => Expr::IsTrue(operand(x)?),
+        Expr::IsNotTrue(x) => Expr::IsNotTrue(operand(x)?),
+        Expr::IsFalse(x) => Expr::IsFalse(operand(x)?),
+        Expr::IsNotFalse(x) => Expr::IsNotFalse(operand(x)?),
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => Expr::InList {
+            expr: operand(expr)?,
+            list: list
+                .iter()
+                .map(|x| operand(x).map(|x| *x))
+                .collect::<Result<Vec<Expr>, String>>()?,
+            negated: *negated,
+        },
+        Expr::Between {
+            expr,
+            negated,
+            low,
+            high,
+        } => Expr::Between {
+            expr: operand(expr)?,
+            negated: *negated,
+            low: operand(low)?,
+            high: operand(high)?,
+        },
+        Expr::Like {
+            negated,
+            expr,
+            pattern,
+            escape_char,
+        } => Expr::Like {
+            negated: *negated,
+            expr: operand(expr)?,
+            pattern: operand(pattern)?,
+            escape_char: *escape_char,
+        },
+        Expr::ILike {
+            negated,
+            expr,
+            pattern,
+            escape_char,
+        } => Expr::ILike {
+            negated: *negated,
+            expr: operand(expr)?,
+            pattern: operand(pattern)?,
+            escape_char: *escape_char,
+        },
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => Expr::Substring {
+            expr: operand(expr)?,
+            substring_from: substring_from.as_deref().map(&mut operand).transpose()?,
+            substring_for: substring_for.as_deref().map(&mut operand).transpose()?,
+        },
+        Expr::Position { expr, r#in } => Expr::Position {
+            expr: operand(expr)?,
+            r#in: operand(r#in)?,
+        },
+        Expr::Function(func) if is_scalar_function(func) => {
+            let mut func = func.clone();
+            for arg in func.args.iter_mut() {
+                if let FunctionArg::Unnamed(FunctionArgExpr::Expr(x)) = arg {
+                    *x = *operand(x)?;
+                }
+            }
+            Expr::Function(func)
+        }
+        _ => expr.clone(),
+    })
+}
+
+// A value of the given column type, used to work out the types of expressions.
+// It isn't zero, so that dividing by it doesn't fail.
+fn get_sample_value(coltype: &Column) -> Value {
+    match coltype {
+        Column::I32 => Value::I32(1),
+        Column::I64 => Value::I64(1),
+        Column::Float => Value::Float(1.0),
+        Column::Double => Value::Double(1.0),
+        Column::String(_) => Value::String("1".to_string()),
+        Column::Nullable(x) => get_sample_value(x),
+        _ => coltype.get_default_value(),
+    }
+}
+
+fn solve_row(
+    group_by: &Vec<Expr>,
+    table_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<Vec<ValueSolver>, String> {
+    let group_solver = group_by
+        .iter()
+        .map(|item| solve_value(item, &table_aliases, &index_refs))
+        .collect::<Result<Vec<ValueSolver>, String>>()?;
+    Ok(group_solver)
+}
+
+fn resolve_row(column_funcs: &Vec<ValueSolver>, output_row: &Row) -> Result<Row, String> {
+    let selected_cells: Row = column_funcs
+        .iter()
+        .map(|f| resolve_value(f, &output_row))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(selected_cells)
+}
+
+// Keeps the rows a DELETE or UPDATE matched that are within its row limit, in order.
+// Each row comes with the row its ORDER BY is solved on, which includes any joined tables.
+fn limit_rows(
+    matched_rows: Vec<(RowInfo, Row)>,
+    row_limit: &RowLimit,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+    collation: &Collation,
+) -> Result<Vec<(RowInfo, Row)>, String> {
+    if row_limit == &RowLimit::default() {
+        return Ok(matched_rows);
+    }
+    let order_exprs: Vec<Expr> = row_limit
+        .order_by
+        .iter()
+        .map(|order_exp| order_exp.expr.clone())
+        .collect();
+    let order_solver: Vec<ValueSolver> = solve_row(&order_exprs, column_aliases, index_refs)?;
+    let mut ordered_rows: Vec<(Row, (RowInfo, Row))> = matched_rows
+        .into_iter()
+        .map(|(row_info, row)| Ok((resolve_row(&order_solver, &row)?, (row_info, row))))
+        .collect::<Result<Vec<(Row, (RowInfo, Row))>, String>>()?;
+    ordered_rows.sort_by(|(keys1, (row1, _)), (keys2, (row2, _))| {
+        resolve_comparison(keys1, keys2, 0, &row_limit.order_by, collation)
+            .then((row1.pagenum, row1.rownum).cmp(&(row2.pagenum, row2.rownum)))
+    });
+    if let Some(limit) = row_limit.limit {
+        ordered_rows.truncate(limit);
+    }
+    Ok(ordered_rows.into_iter().map(|(_, row)| row).collect())
+}
+
+/// Returns an error if the user is in safe update mode and the write has no where clause,
+/// so a whole table can't be updated or deleted by accident
+fn check_safe_updates(where_expr: &Option<Expr>, write: &str, user: &User) -> Result<(), String> {
+    if user.get_safe_updates() && where_expr.is_none() {
+        return Err(format!(
+            "Cannot {} without a WHERE clause in safe update mode",
+            write
+        ));
+    }
+    Ok(())
+}
+
+/// Runs an UPDATE statement, returning its message and diff, along with the columns that it
+/// returns. With preview, the rows that it would change are found, but nothing is written.
+fn run_update_statement(
+    parsed: &ParsedStatement,
+    preview: bool,
+    user: &mut User,
+) -> Result<(String, UpdateDiff, Option<ReturnedColumns>), Error> {
+    let (table, assignments, from, selection) = match &parsed.statement {
+        Statement::Update {
+            table,
+            assignments,
+            from,
+            selection,
+        } => (table, assignments, from, selection),
+        _ => return Err("Not an update".into()),
+    };
+    let final_table; // What is the best way to do this?
+    let mut all_data: Vec<(String, Expr)> = Vec::new();
+    let final_alias;
+
+    match table.relation.clone() {
+        sqlparser::ast::TableFactor::Table {
+            name: table_name,
+            alias,
+            args: _,
+            with_hints: _,
+        } => {
+            // Now you have the table
+            final_alias = match alias {
+                Some(x) => x.to_string(),
+                None => "".to_string(),
+            };
+            final_table = table_name.to_string();
+        }
+        _ => {
+            // Not a table inside the TableFactor enum
+            return Err("Error parsing".into());
+        }
+    }
+
+    // Iterate through and build vector of assignments to pass to update
+    for assignment in assignments {
+        let column_name;
+        let insert_value = assignment.value.clone();
+        // The column can be qualified with the table, like SET a.x = ...
+        // As in a SELECT, the qualifier is the table's alias if it has one.
+        let table_ref: &String = match final_alias.is_empty() {
+            true => &final_table,
+            false => &final_alias,
+        };
+        if assignment.id.len() > 1 && assignment.id[assignment.id.len() - 2].value != *table_ref {
+            return Err(format!(
+                "Cannot assign to {}, as it is not a column of {}",
+                assignment.id.iter().join("."),
+                table_ref
+            )
+            .into());
+        }
+        column_name = assignment.id[assignment.id.len() - 1].value.clone();
+
+        all_data.push((column_name, insert_value));
+    }
+
+    let mut selection: Option<Expr> = match selection {
+        Some(selection) => Some(resolve_subqueries(selection, user)?),
+        None => None,
+    };
+
+    // The tables joined in the FROM clause, which the assignments can use the columns of
+    let mut source_tables: Vec<(String, String)> = Vec::new();
+    if let Some(from) = from {
+        let mut relations: Vec<&TableFactor> = vec![&from.relation];
+        for join in from.joins.iter() {
+            let join_condition: Expr = match &join.join_operator {
+                sqlparser::ast::JoinOperator::Inner(sqlparser::ast::JoinConstraint::On(on)) => {
+                    on.clone()
+                }
+                _ => Err("Only inner joins are supported in an UPDATE".to_string())?,
+            };
+            relations.push(&join.relation);
+
+            // The join conditions become part of the WHERE clause
+            selection = Some(match selection {
+                Some(clause) => Expr::BinaryOp {
+                    left: Box::new(clause),
+                    op: BinaryOperator::And,
+                    right: Box::new(join_condition),
+                },
+                None => join_condition,
+            });
+        }
+        for relation in relations {
+            let source_table: (String, String) = get_table_name(relation)?;
+            // The FROM clause can list the updated table itself, to join it with the others
+            if source_table != (final_table.clone(), final_alias.clone()) {
+                source_tables.push(source_table);
+            }
+        }
+    }
+
+    let returning: Option<ReturnedColumns> =
+        prepare_returning(&parsed.clauses.returning, &final_table, user)?;
+    let (message, diff) = update_with_limit(
+        all_data,
+        final_table,
+        final_alias,
+        source_tables,
+        selection,
+        &parsed.clauses.row_limit,
+        preview,
+        get_db_instance()?,
+        user,
+    )?;
+    Ok((message, diff, returning))
+}
+
+/// Runs a DELETE statement, returning its message and diff, along with the columns that it
+/// returns. With preview, the rows that it would delete are found, but nothing is removed.
+fn run_delete_statement(
+    parsed: &ParsedStatement,
+    preview: bool,
+    user: &mut User,
+) -> Result<(String, RemoveDiff, Option<ReturnedColumns>), Error> {
+    let (table_name, selection) = match &parsed.statement {
+        Statement::Delete {
+            table_name,
+            using: _,
+            selection,
+        } => (table_name, selection),
+        _ => return Err("Not a delete".into()),
+    };
+    let final_table; // What is the best way to do this?
+    let final_alias;
+    match table_name.clone() {
+        sqlparser::ast::TableFactor::Table {
+            name: table_name,
+            alias,
+            args: _,
+            with_hints: _,
+        } => {
+            // Now you have the table
+            final_alias = match alias {
+                Some(x) => x.to_string(),
+                None => "".to_string(),
+            };
+            final_table = table_name.to_string();
+        }
+        _ => {
+            // Not a table inside the TableFactor enum
+            return Err("Error parsing".into());
+        }
+    }
+
+    let selection: Option<Expr> = match selection {
+        Some(selection) => Some(resolve_subqueries(selection, user)?),
+        None => None,
+    };
+
+    let returning: Option<ReturnedColumns> =
+        prepare_returning(&parsed.clauses.returning, &final_table, user)?;
+    let (message, diff) = delete_with_limit(
+        final_table,
+        final_alias,
+        selection,
+        &parsed.clauses.row_limit,
+        preview,
+        get_db_instance()?,
+        user,
+    )?;
+    Ok((message, diff, returning))
+}
+
+/// This method implements the SQL update statement
+/// The source tables are joined with the updated table, so the new values can use their columns.
+/// Each row that is updated uses the first combination of source rows that matches the WHERE clause.
+/// The new values are computed from the row as it was before the update, so `x = x + 1`
+/// increments every matched row once, and `a = b, b = a` swaps the two columns.
+/// With preview, the rows that would change are put in the diff, with their current values as
+/// the old rows, but the table isn't changed and the diff isn't kept.
+pub fn update(
+    values: Vec<(String, Expr)>,
+    table_name: String,
+    alias: String,
+    source_tables: Vec<(String, String)>, // Empty if the update doesn't join any tables
+    where_expr: Option<Expr>,
+    preview: bool,
+    database: &Database,
+    user: &mut User,
+) -> Result<(String, UpdateDiff), Error> {
+    update_with_limit(
+        values,
+        table_name,
+        alias,
+        source_tables,
+        where_expr,
+        &RowLimit::default(),
+        preview,
+        database,
+        user,
+    )
+}
+
+/// Same as update, but only the first rows that match are updated, as given by the row limit
+pub fn update_with_limit(
+    values: Vec<(String, Expr)>,
+    table_name: String,
+    alias: String,
+    source_tables: Vec<(String, String)>, // Empty if the update doesn't join any tables
+    where_expr: Option<Expr>,
+    row_limit: &RowLimit,
+    preview: bool,
+    database: &Database,
+    user: &mut User,
+) -> Result<(String, UpdateDiff), Error> {
+    if user.get_permissions() == Read {
+        return Err(Error::permission_denied(
+            "You do not have permission to write to this table",
+        ));
+    }
+    check_writable_table(&table_name)?;
+    check_safe_updates(&where_expr, "update", user)?;
+
+    database.get_table_path(&table_name, user)?;
+    // Rows that outlived their table's TTL are removed before they can be updated
+    remove_expired_rows(database, &table_name, user)?;
+    let table: Table = Table::from_user(user, database, &table_name, None)?;
+    let mut selected_rows: Vec<RowInfo> = Vec::new();
+    let mut old_rows: Vec<RowInfo> = Vec::new();
+    let overflow_policy: StringOverflowPolicy = user.get_string_overflow_policy();
+
+    // The updated table comes first, so its columns are at the start of the joined rows
+    let mut table_names: Vec<(String, String)> = vec![(table_name.clone(), alias.clone())];
+    table_names.extend(source_tables.clone());
+    let tables: Tables = load_aliased_tables(database, user, &table_names)?;
     let column_aliases: ColumnAliases = gen_column_aliases(&tables);
     let index_refs: IndexRefs = get_index_refs(&column_aliases);
+    // Only the columns of the updated table can be assigned to
+    let target_aliases: ColumnAliases = gen_column_aliases(&tables[0..1].to_vec());
+
+    // Every combination of the rows from the source tables
+    let source_rows: Vec<Row> = match source_tables.len() {
+        0 => vec![vec![]],
+        _ => tables[1..]
+            .iter()
+            .map(|(table, _)| table.clone())
+            .multi_cartesian_product()
+            .map(|rows| rows.into_iter().flat_map(|row_info| row_info.row).collect())
+            .collect(),
+    };
 
     let values: Vec<(String, ValueSolver)> = values
         .into_iter()
@@ -959,7 +2964,6 @@ pub fn update(
         .collect::<Result<Vec<(String, ValueSolver)>, String>>()?;
 
     // Convert the where expression into a predicate solver
-    let table_names: Vec<(String, String)> = vec![(table_name.clone(), alias.clone())];
     let selection: Option<PredicateSolver> = match &where_expr {
         Some(pred) => Some(where_clause(pred, &table_names, get_db_instance()?, user)?),
         None => None,
@@ -969,16 +2973,17 @@ pub fn update(
 
     // Construct the iterators for each table
     // If we are using a where predicate, check if we can use an index
+    // When joining, the where clause refers to the other tables too, so the index can't be used
     let mut used_index: bool = false;
-    if where_expr.is_some() {
+    if where_expr.is_some() && source_tables.is_empty() {
         let expr: Expr = where_expr.clone().unwrap();
 
-        // Get the index id for this specific table for this specific query
-        let index_id: Option<IndexID> =
-            get_index_id_from_expr(&expr, &column_aliases, &index_refs, &alias)?;
+        // Get the index for this specific table for this specific query
+        let index_choice: Option<(IndexID, Expr)> =
+            choose_index(&expr, &column_aliases, &index_refs, &alias, &table, user)?;
 
         // If we can use an index (i.e. the where clause references only one table)
-        if let Some(index_id) = index_id {
+        if let Some((index_id, index_expr)) = index_choice {
             // Check if this table has this index
             if let Some(idx_val) = table.indexes.get(&index_id) {
                 // We can use the index, so we can use the index to get the rows
@@ -997,7 +3002,7 @@ pub fn update(
                     index_name,
                 )?;
 
-                let res_rows: Vec<RowInfo> = btree.get_rows_matching_expr(&expr)?;
+                let res_rows: Vec<RowInfo> = btree.get_rows_matching_expr(&index_expr)?;
 
                 // Load the result rows into a row iterator
                 iterator = Some(TableIterator::RowIter(RowIterator::new(res_rows)));
@@ -1010,42 +3015,114 @@ pub fn update(
         iterator = Some(TableIterator::TableIter(table.clone()));
     }
 
+    let mut matched_rows: Vec<(RowInfo, Row)> = Vec::new();
     for row_info in iterator.unwrap() {
-        if resolve_predicate(&selection, &row_info.row)? {
-            // Append the selected_cells row to our result
-            let mut row_info = row_info.clone();
-            for (name, value) in values.iter() {
-                let value = resolve_value(&value, &row_info.row)?;
-                let column_name = resolve_reference(name.clone(), &column_aliases)?;
-                let index = *index_refs.get(&column_name).ok_or(format!(
-                    "Column name {} not found in table {}",
-                    column_name, &table_name
-                ))?;
-                row_info.row[index] = value;
+        // Find the first source rows that this row matches with
+        for source_row in source_rows.iter() {
+            let mut row: Row = row_info.row.clone();
+            row.extend(source_row.clone());
+            if resolve_predicate(&selection, &row)? {
+                matched_rows.push((row_info.clone(), row));
+                break;
             }
+        }
+    }
+
+    let matched_rows: Vec<(RowInfo, Row)> = limit_rows(
+        matched_rows,
+        row_limit,
+        &column_aliases,
+        &index_refs,
+        &user.get_collation(),
+    )?;
+    for (row_info, joined_row) in matched_rows {
+        // Append the selected_cells row to our result
+        let old_row: &Row = &row_info.row;
+        let mut row_info = row_info.clone();
+        // The values are resolved against the joined row, which keeps the row's old values
+        for (name, value) in values.iter() {
+            let value = resolve_value(&value, &joined_row)?;
+            let column_name = resolve_reference(name.clone(), &target_aliases)?;
+            let index = *index_refs.get(&column_name).ok_or(format!(
+                "Column name {} not found in table {}",
+                column_name, &table_name
+            ))?;
+            let (schema_name, column) = &table.schema[index];
+            row_info.row[index] = column.enforce_length(value, schema_name, &overflow_policy)?;
+        }
+
+        // Rows that the assignments leave as they were aren't rewritten, or added to the diff
+        let is_changed: bool = table.schema.iter().enumerate().any(|(i, (_, column))| {
+            column.coerce_type(row_info.row[i].clone()).as_ref() != Ok(&old_row[i])
+        });
+        if is_changed {
+            old_rows.push(RowInfo {
+                row: old_row.clone(),
+                ..row_info.clone()
+            });
             selected_rows.push(row_info);
         }
     }
 
     let len: usize = selected_rows.len();
+    if preview {
+        let diff: UpdateDiff = UpdateDiff {
+            table_name: table.name.clone(),
+            schema: table.schema.clone(),
+            rows: selected_rows,
+            old_rows,
+        };
+        return Ok((format!("{} rows would be updated.", len), diff));
+    }
     let diff: UpdateDiff = table.rewrite_rows(selected_rows)?;
     user.append_diff(&Diff::Update(diff.clone()));
     Ok((format!("{} rows were successfully updated.", len), diff))
 }
 
+/// This method implements the SQL delete statement
+/// With preview, the rows that would be deleted are put in the diff, but the table isn't changed
+/// and the diff isn't kept.
 pub fn delete(
     table_name: String,
     alias: String,
     where_expr: Option<Expr>,
+    preview: bool,
+    database: &Database,
+    user: &mut User,
+) -> Result<(String, RemoveDiff), Error> {
+    delete_with_limit(
+        table_name,
+        alias,
+        where_expr,
+        &RowLimit::default(),
+        preview,
+        database,
+        user,
+    )
+}
+
+/// Same as delete, but only the first rows that match are deleted, as given by the row limit
+pub fn delete_with_limit(
+    table_name: String,
+    alias: String,
+    where_expr: Option<Expr>,
+    row_limit: &RowLimit,
+    preview: bool,
     database: &Database,
     user: &mut User,
-) -> Result<(String, RemoveDiff), String> {
+) -> Result<(String, RemoveDiff), Error> {
     if user.get_permissions() == Read {
-        return Err("You do not have permission to write to this table".to_string());
+        return Err(Error::permission_denied(
+            "You do not have permission to write to this table",
+        ));
     }
+    check_writable_table(&table_name)?;
+    check_safe_updates(&where_expr, "delete", user)?;
 
+    // Rows that outlived their table's TTL are removed before the rows to delete are chosen
+    remove_expired_rows(database, &table_name, user)?;
     let table = Table::from_user(user, database, &table_name, None)?;
-    let mut selected_rows: Vec<RowLocation> = Vec::new();
+    let mut selected_rows: Vec<RowInfo> = Vec::new();
     let tables: Tables =
         load_aliased_tables(database, user, &vec![(table_name.clone(), alias.clone())])?;
     let column_aliases: ColumnAliases = gen_column_aliases(&tables);
@@ -1066,12 +3143,12 @@ pub fn delete(
     if where_expr.is_some() {
         let expr: Expr = where_expr.clone().unwrap();
 
-        // Get the index id for this specific table for this specific query
-        let index_id: Option<IndexID> =
-            get_index_id_from_expr(&expr, &column_aliases, &index_refs, &alias)?;
+        // Get the index for this specific table for this specific query
+        let index_choice: Option<(IndexID, Expr)> =
+            choose_index(&expr, &column_aliases, &index_refs, &alias, &table, user)?;
 
         // If we can use an index (i.e. the where clause references only one table)
-        if let Some(index_id) = index_id {
+        if let Some((index_id, index_expr)) = index_choice {
             // Check if this table has this index
             if let Some(idx_val) = table.indexes.get(&index_id) {
                 // We can use the index, so we can use the index to get the rows
@@ -1090,7 +3167,7 @@ pub fn delete(
                     index_name,
                 )?;
 
-                let res_rows: Vec<RowInfo> = btree.get_rows_matching_expr(&expr)?;
+                let res_rows: Vec<RowInfo> = btree.get_rows_matching_expr(&index_expr)?;
 
                 // Load the result rows into a row iterator
                 iterator = Some(TableIterator::RowIter(RowIterator::new(res_rows)));
@@ -1103,15 +3180,40 @@ pub fn delete(
         iterator = Some(TableIterator::TableIter(table.clone()));
     }
 
+    let mut matched_rows: Vec<(RowInfo, Row)> = Vec::new();
     for row_info in iterator.unwrap() {
         if resolve_predicate(&selection, &row_info.row)? {
-            // Append the selected_cells row to our result
-            selected_rows.push(row_info.get_row_location());
+            let row: Row = row_info.row.clone();
+            matched_rows.push((row_info, row));
         }
     }
+    let collation: Collation = user.get_collation();
+    for (row_info, _) in limit_rows(
+        matched_rows,
+        row_limit,
+        &column_aliases,
+        &index_refs,
+        &collation,
+    )? {
+        // Append the selected_cells row to our result
+        selected_rows.push(row_info);
+    }
 
     let len: usize = selected_rows.len();
-    let diff: RemoveDiff = table.remove_rows(selected_rows)?;
+    if preview {
+        let diff: RemoveDiff = RemoveDiff {
+            table_name: table.name.clone(),
+            schema: table.schema.clone(),
+            rows: selected_rows,
+        };
+        return Ok((format!("{} rows would be deleted.", len), diff));
+    }
+    let diff: RemoveDiff = table.remove_rows(
+        selected_rows
+            .iter()
+            .map(|row_info| row_info.get_row_location())
+            .collect(),
+    )?;
     user.append_diff(&Diff::Remove(diff.clone()));
 
     Ok((format!("{} rows were deleted.", len), diff))
@@ -1128,1917 +3230,5957 @@ pub fn insert(
     table_name: String,
     database: &Database,
     user: &mut User,
-) -> Result<(String, InsertDiff), String> {
+) -> Result<(String, InsertDiff), Error> {
+    check_writable_table(&table_name)?;
     database.get_table_path(&table_name, user)?;
-    let mut table = Table::from_user(user, database, &table_name, None)?;
-
     if user.get_permissions() == Read {
-        return Err("You do not have permission to write to this table.".to_string());
+        return Err(Error::permission_denied(
+            "You do not have permission to write to this table.",
+        ));
     }
 
+    // Rows that outlived their table's TTL are removed before the new rows are checked against them
+    remove_expired_rows(database, &table_name, user)?;
+    let mut table = Table::from_user(user, database, &table_name, None)?;
+
+    let values: Vec<Row> = prepare_inserted_rows(values, &table, user)?;
+    // Actually insert the values into the table
+    let len: usize = values.len();
+    let diff: InsertDiff = table.insert_rows(values)?;
+    user.append_diff(&Diff::Insert(diff.clone()));
+    Ok((format!("{} rows were successfully inserted.", len), diff))
+}
+
+/// Converts the values to be inserted into the types of the table's columns
+fn prepare_inserted_rows(values: Vec<Row>, table: &Table, user: &User) -> Result<Vec<Row>, String> {
     // Ensure that the number of values to be inserted matches the number of columns in the table
-    let values = values
+    let overflow_policy: StringOverflowPolicy = user.get_string_overflow_policy();
+    values
         .into_iter()
-        .map(|x| {
+        .map(|x| (x.len(), timestamp_row(&table.schema, x)))
+        .map(|(num_values, x)| {
             if x.len() != table.schema.len() {
                 Err(format!(
                     "Number of values ({}) to be inserted does not match the number of columns in the table ({})"
-                , x.len(), table.schema.len()))
+                , num_values, visible_column_count(&table.schema)))
             } else {
                 Ok(x
                     .into_iter()
                     .zip(table.schema.iter())
-                    .map(|(val, (_, col))| {
-                        col.coerce_type(val).map_err(|e| format!("Error parsing value: {}", e))
+                    .map(|(val, (name, col))| {
+                        let val: Value = col
+                            .coerce_type(val)
+                            .map_err(|e| format!("Error parsing value: {}", e))?;
+                        col.enforce_length(val, name, &overflow_policy)
                     })
                     .collect::<Result<Row, String>>()?)
             }
         })
-        .collect::<Result<Vec<Row>, _>>().map_err(|x| x.to_string())?;
-    // Actually insert the values into the table
-    let len: usize = values.len();
-    let diff: InsertDiff = table.insert_rows(values)?;
-    user.append_diff(&Diff::Insert(diff.clone()));
-    Ok((format!("{} rows were successfully inserted.", len), diff))
+        .collect::<Result<Vec<Row>, _>>().map_err(|x| x.to_string())
 }
 
-// This method implements the SQL Where clause. It takes in an expression, and generates
-// a function that takes in a row and returns a boolean. The function returns an error if
-// the expression is invalid.
-pub fn where_clause(
-    pred: &Expr,
-    table_names: &Vec<(String, String)>,
+/// Inserts the rows like insert() does, except for the rows that have the same key as a row in
+/// the table, in the unique index on the conflict columns. With DO NOTHING those rows are skipped,
+/// and with DO UPDATE the row in the table is updated instead. The updated values can use the
+/// columns of the row in the table, and the columns of the row that was skipped as `excluded.<column>`.
+/// Returns the rows that were inserted and updated.
+pub fn insert_on_conflict(
+    values: Vec<Row>,
+    table_name: String,
+    on_conflict: &OnConflict,
     database: &Database,
-    user: &User,
-) -> Result<PredicateSolver, String> {
-    let tables = load_aliased_tables(database, user, &table_names)?;
-    let column_aliases = gen_column_aliases(&tables);
-    let index_refs = get_index_refs(&column_aliases);
-    solve_predicate(pred, &column_aliases, &index_refs)
-}
+    user: &mut User,
+) -> Result<(String, Vec<RowInfo>), Error> {
+    check_writable_table(&table_name)?;
+    database.get_table_path(&table_name, user)?;
+    if user.get_permissions() == Read {
+        return Err(Error::permission_denied(
+            "You do not have permission to write to this table.",
+        ));
+    }
 
-// Generating tables with aliases from a list of table names,
-// and creating new aliases where necessary
-fn load_aliased_tables(
-    database: &Database,
-    user: &User,
-    table_names: &Vec<(String, String)>,
-) -> Result<Tables, String> {
-    let tables: Vec<(Table, String)> = table_names
-        .iter()
-        .map(|(table_name, alias)| {
-            let table = Table::from_user(user, database, table_name, None)?;
-            if alias.is_empty() {
-                // If no alias is provided, use the table name as the alias
-                let alias = table_name.clone();
-                Ok((table, alias))
-            } else {
-                Ok((table, alias.to_string()))
-            }
-        })
-        .collect::<Result<Tables, String>>()?;
-    Ok(tables)
-}
+    // Rows that outlived their table's TTL are removed before the new rows are checked against them
+    remove_expired_rows(database, &table_name, user)?;
+    let mut table = Table::from_user(user, database, &table_name, None)?;
+    let values: Vec<Row> = prepare_inserted_rows(values, &table, user)?;
 
-// Get the names of all the columns in the tables along with their aliases in
-// the format <alias>.<column_name> and store them in a vector of tuples
-// alongside their column types and new column name when output.
-// It will be a vector of tuples where each tuple is of the form:
-// (<table_alias>.<column_name>, <column_type>, <output_column_name>)
-pub fn gen_column_aliases(tables: &Tables) -> ColumnAliases {
-    tables
-        .iter()
-        .map(|(table, alias): &(Table, String)| {
-            table
-                .schema
+    // The unique indexes that the rows are checked against
+    let index_ids: Vec<IndexID> = match on_conflict.columns.as_slice() {
+        [] => table.unique_indexes.iter().cloned().collect(),
+        columns => {
+            let mut conflict_id: IndexID = columns
                 .iter()
-                .map(|(name, coltype)| {
-                    (
-                        format!("{}.{}", alias, name.clone()),
-                        coltype.clone(),
-                        name.clone(),
-                    )
+                .map(|column| {
+                    table
+                        .schema
+                        .iter()
+                        .position(|(name, _)| name == column)
+                        .map(|i| i as u8)
+                        .ok_or(format!(
+                            "Column {} not found in table {}",
+                            column, table_name
+                        ))
                 })
-                .collect::<ColumnAliases>()
-        })
-        .flatten()
-        .collect::<ColumnAliases>()
-}
+                .collect::<Result<IndexID, String>>()?;
+            conflict_id.sort();
+            let index_id: &IndexID = table
+                .unique_indexes
+                .iter()
+                .find(|index_id| {
+                    let mut index_id: IndexID = (*index_id).clone();
+                    index_id.sort();
+                    index_id == conflict_id
+                })
+                .ok_or(format!(
+                    "There is no unique index on ({}) of table {} for ON CONFLICT",
+                    columns.join(", "),
+                    table_name
+                ))?;
+            vec![index_id.clone()]
+        }
+    };
 
-// Get the names of all the columns in the tables along with their aliases in
-// the format <alias>.<column_name> and store them in a vector of tuples
-// alongside their column types and new column name when output.
-// It will be a vector of tuples where each tuple is of the form:
-// (<table_alias>.<column_name>, <column_type>, <output_column_name>)
-pub fn gen_column_aliases_from_schema(tables: &Vec<(Schema, String)>) -> ColumnAliases {
-    tables
-        .iter()
-        .map(|(schema, alias): &(Schema, String)| {
-            schema
+    // The row in the table comes first in the rows the new values are solved on, and it's the one
+    // that unqualified columns refer to. The row that wasn't inserted follows it.
+    let mut column_aliases: ColumnAliases =
+        gen_column_aliases_from_schema(&vec![(table.schema.clone(), table_name.clone())]);
+    let target_aliases: ColumnAliases = column_aliases.clone();
+    column_aliases.extend(table.schema.iter().map(|(name, column)| {
+        let excluded: String = format!("excluded.{}", name);
+        (excluded.clone(), column.clone(), excluded)
+    }));
+    let index_refs: IndexRefs = get_index_refs(&column_aliases);
+    let assignments: Vec<(usize, ValueSolver)> = match &on_conflict.action {
+        ConflictAction::DoNothing => Vec::new(),
+        ConflictAction::DoUpdate(assignments) => assignments
+            .iter()
+            .map(|assignment| {
+                // The column can be qualified with the table, like SET t.x = ...
+                if assignment.id.len() > 1
+                    && assignment.id[assignment.id.len() - 2].value != table_name
+                {
+                    return Err(format!(
+                        "Cannot assign to {}, as it is not a column of {}",
+                        assignment.id.iter().join("."),
+                        table_name
+                    ));
+                }
+                let name: String = assignment.id[assignment.id.len() - 1].value.clone();
+                let column_name: String = resolve_reference(name, &target_aliases)?;
+                let index: usize = *index_refs.get(&column_name).ok_or(format!(
+                    "Column name {} not found in table {}",
+                    column_name, &table_name
+                ))?;
+                Ok((
+                    index,
+                    solve_value(&assignment.value, &column_aliases, &index_refs)?,
+                ))
+            })
+            .collect::<Result<Vec<(usize, ValueSolver)>, String>>()?,
+    };
+
+    let overflow_policy: StringOverflowPolicy = user.get_string_overflow_policy();
+    let mut inserted_rows: Vec<Row> = Vec::new();
+    let mut updated_rows: Vec<RowInfo> = Vec::new();
+    'rows: for row in values {
+        for index_id in &index_ids {
+            let key: IndexKey = get_index_key_from_row(&row, index_id);
+            // Keys with a null in them never conflict
+            if key.iter().any(|value| matches!(value, Value::Null(_))) {
+                continue;
+            }
+            let inserted_conflict: bool = inserted_rows
                 .iter()
-                .map(|(name, coltype)| {
-                    (
-                        format!("{}.{}", alias, name.clone()),
-                        coltype.clone(),
-                        name.clone(),
+                .any(|inserted| get_index_key_from_row(inserted, index_id) == key);
+            let conflict: Option<RowInfo> = table.get_rows_with_key(index_id, &key)?.pop();
+            match (&on_conflict.action, conflict) {
+                (ConflictAction::DoNothing, conflict)
+                    if inserted_conflict || conflict.is_some() =>
+                {
+                    continue 'rows
+                }
+                (ConflictAction::DoUpdate(_), _) if inserted_conflict => {
+                    return Err(format!(
+                        "ON CONFLICT DO UPDATE cannot change the row with key {:?} a second time",
+                        key
                     )
-                })
-                .collect::<ColumnAliases>()
-        })
-        .flatten()
-        .collect::<ColumnAliases>()
-}
+                    .into())
+                }
+                (ConflictAction::DoUpdate(_), Some(conflict)) => {
+                    if updated_rows
+                        .iter()
+                        .any(|updated| updated.get_row_location() == conflict.get_row_location())
+                    {
+                        return Err(format!(
+                            "ON CONFLICT DO UPDATE cannot change the row with key {:?} a second time",
+                            key
+                        ).into());
+                    }
+                    // The values are solved against the row as it was before the update
+                    let mut joined_row: Row = conflict.row.clone();
+                    joined_row.extend_from_slice(&row);
+                    let mut updated: RowInfo = conflict;
+                    for (index, solver) in assignments.iter() {
+                        let value: Value = resolve_value(solver, &joined_row)?;
+                        let (name, column) = &table.schema[*index];
+                        let value: Value = column
+                            .coerce_type(value)
+                            .map_err(|e| format!("Error parsing value: {}", e))?;
+                        updated.row[*index] =
+                            column.enforce_length(value, name, &overflow_policy)?;
+                    }
+                    updated_rows.push(updated);
+                    continue 'rows;
+                }
+                _ => (),
+            }
+        }
+        inserted_rows.push(row);
+    }
 
-/// Hashmap from column names to index in the row
-pub fn get_index_refs(column_aliases: &ColumnAliases) -> IndexRefs {
-    column_aliases
-        .iter()
-        .enumerate()
-        .map(|(i, (name, _, _))| (name.clone(), i))
-        .collect::<IndexRefs>()
+    let mut rows: Vec<RowInfo> = Vec::new();
+    let (num_inserted, num_updated) = (inserted_rows.len(), updated_rows.len());
+    if !updated_rows.is_empty() {
+        let diff: UpdateDiff = table.rewrite_rows(updated_rows)?;
+        user.append_diff(&Diff::Update(diff.clone()));
+        rows.extend(diff.rows);
+    }
+    if !inserted_rows.is_empty() {
+        let diff: InsertDiff = table.insert_rows(inserted_rows)?;
+        user.append_diff(&Diff::Insert(diff.clone()));
+        rows.extend(diff.rows);
+    }
+    Ok((
+        format!(
+            "{} rows were successfully inserted, and {} rows were updated.",
+            num_inserted, num_updated
+        ),
+        rows,
+    ))
 }
 
-/// Given a set of Columns, this creates a vector to reference these columns and apply relevant operations
-fn resolve_columns(
-    columns: Vec<SelectItem>,
-    column_names: &mut Vec<String>,
-    tables: &Tables,
-    column_aliases: &ColumnAliases,
-) -> Result<Vec<Expr>, String> {
-    columns
-        .into_iter()
-        .map(|item| resolve_selects(item, column_names, &tables, column_aliases))
-        .flatten_ok()
-        .collect::<Result<Vec<Expr>, String>>()
+/// Switches the user's session to another database, starting on that database's main branch
+fn use_database(database_name: &String, user: &mut User) -> Result<(), String> {
+    if user.get_diffs().len() > 0 {
+        return Err("Cannot use another database with uncommitted changes".to_string());
+    }
+    let selected: Option<String> = open_db_instance(database_name)?;
+
+    // The user's temporary copy of their branch has no changes in it, so it can go
+    if user.is_on_temp_commit() {
+        get_db_instance()?.delete_temp_branch_directory(user)?;
+    }
+    let _database: DatabaseSelection = select_database(selected.clone());
+    user.set_database_name(selected);
+    user.set_current_branch_name(&get_db_instance()?.get_main_branch_name());
+    Ok(())
 }
 
-/// Given a specific SelectItem, this will resolve the column name and create a function to resolve the value
-fn resolve_selects(
-    item: SelectItem,
-    column_names: &mut Vec<String>,
-    tables: &Tables,
-    column_aliases: &ColumnAliases,
-) -> Result<Vec<Expr>, String> {
-    Ok(match item {
-        SelectItem::ExprWithAlias { expr, alias: _ } => {
-            column_names.push(expr.to_string());
-            vec![expr]
+/// Changes a setting of the user's session, using `SET <setting> = <value>`.
+/// The settings are `string_overflow` (`error` or `truncate`), `sample_seed` (a number or `none`),
+/// `collation` (`binary` or `case_insensitive`), `prefer_index_scans` (`on` or `off`),
+/// `safe_updates` (`on` or `off`), `alter_dry_run` (`on` or `off`),
+/// `restrict_column_drops` (`on` or `off`), `max_result_columns` (a number or `none`),
+/// `max_merge_conflicts` (a number above 0 or `none`), `branch` (the name of a branch) and
+/// `database` (the name of a database, which starts the user on its main branch).
+fn set_variable(variable: &String, value: &Vec<Expr>, user: &mut User) -> Result<String, String> {
+    // Branch and database names are case sensitive, so they're set before the value is lowercased
+    if matches!(variable.to_lowercase().as_str(), "branch" | "database") {
+        let name: String = match value.as_slice() {
+            [Expr::Identifier(x)] => x.value.clone(),
+            [Expr::Value(sqlparser::ast::Value::SingleQuotedString(x))] => x.clone(),
+            _ => return Err(format!("Invalid value for setting {}", variable)),
+        };
+        if variable.to_lowercase() == "branch" {
+            get_db_instance()?.use_branch(&name, user)?;
+            return Ok(format!("Using branch {}", name));
         }
-        SelectItem::UnnamedExpr(expr) => {
-            column_names.push(expr.to_string());
-            vec![expr]
+        use_database(&name, user)?;
+        return Ok(format!("Using database {}", name));
+    }
+
+    let setting: String = match value.as_slice() {
+        [Expr::Identifier(x)] => x.value.to_lowercase(),
+        [Expr::Value(sqlparser::ast::Value::SingleQuotedString(x))] => x.to_lowercase(),
+        [Expr::Value(sqlparser::ast::Value::Number(x, _))] => x.clone(),
+        _ => return Err(format!("Invalid value for setting {}", variable)),
+    };
+    match variable.to_lowercase().as_str() {
+        "string_overflow" => {
+            let policy: StringOverflowPolicy = match setting.as_str() {
+                "error" => StringOverflowPolicy::Error,
+                "truncate" => StringOverflowPolicy::Truncate,
+                _ => {
+                    return Err(format!(
+                        "Invalid value for setting {}: {}, expected error or truncate",
+                        variable, setting
+                    ))
+                }
+            };
+            user.set_string_overflow_policy(&policy);
         }
-        // Pick out all the columns
-        SelectItem::Wildcard => {
-            let names: Vec<Expr> = column_aliases
-                .iter()
-                .map(|(x, _, _)| to_ident(x.clone()))
-                .collect();
-            column_names.append(
-                column_aliases
-                    .iter()
-                    .map(|(_, _, z)| z.clone())
-                    .collect::<Vec<String>>()
-                    .as_mut(),
-            );
-            names
+        "sample_seed" => {
+            let seed: Option<u64> = match setting.as_str() {
+                "none" => None,
+                _ => Some(setting.parse::<u64>().map_err(|_| {
+                    format!(
+                        "Invalid value for setting {}: {}, expected a number or none",
+                        variable, setting
+                    )
+                })?),
+            };
+            user.set_sample_seed(seed);
         }
-        // Pick out all the columns from tha table, aliased
-        SelectItem::QualifiedWildcard(idents) => {
-            let name = idents
-                .0
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>()
-                .join(".");
-            let index = tables
-                .iter()
-                .position(|(t, alias)| t.name == name || alias == &name);
-            if let Some(index) = index {
-                let (table, alias) = tables.get(index).unwrap();
-                table
-                    .schema
-                    .iter()
-                    .map(|(colname, _)| {
-                        column_names.push(colname.clone());
-                        to_ident(format!("{}.{}", alias, colname))
-                    })
-                    .collect()
-            } else {
-                return Err(format!("Table {} not found.", name));
-            }
+        "collation" => {
+            let collation: Collation = match setting.as_str() {
+                "binary" => Collation::Binary,
+                "case_insensitive" => Collation::CaseInsensitive,
+                _ => {
+                    return Err(format!(
+                        "Invalid value for setting {}: {}, expected binary or case_insensitive",
+                        variable, setting
+                    ))
+                }
+            };
+            user.set_collation(&collation);
         }
-    })
+        "prefer_index_scans" => {
+            let prefer: bool = match setting.as_str() {
+                "on" | "true" => true,
+                "off" | "false" => false,
+                _ => {
+                    return Err(format!(
+                        "Invalid value for setting {}: {}, expected on or off",
+                        variable, setting
+                    ))
+                }
+            };
+            user.set_prefer_index_scans(prefer);
+        }
+        "safe_updates" => {
+            let safe: bool = match setting.as_str() {
+                "on" | "true" => true,
+                "off" | "false" => false,
+                _ => {
+                    return Err(format!(
+                        "Invalid value for setting {}: {}, expected on or off",
+                        variable, setting
+                    ))
+                }
+            };
+            user.set_safe_updates(safe);
+        }
+        "alter_dry_run" => {
+            let dry_run: bool = match setting.as_str() {
+                "on" | "true" => true,
+                "off" | "false" => false,
+                _ => {
+                    return Err(format!(
+                        "Invalid value for setting {}: {}, expected on or off",
+                        variable, setting
+                    ))
+                }
+            };
+            user.set_alter_dry_run(dry_run);
+        }
+        "restrict_column_drops" => {
+            let restrict: bool = match setting.as_str() {
+                "on" | "true" => true,
+                "off" | "false" => false,
+                _ => {
+                    return Err(format!(
+                        "Invalid value for setting {}: {}, expected on or off",
+                        variable, setting
+                    ))
+                }
+            };
+            user.set_restrict_column_drops(restrict);
+        }
+        "max_result_columns" => {
+            let max_columns: Option<usize> = match setting.as_str() {
+                "none" => None,
+                _ => Some(setting.parse::<usize>().map_err(|_| {
+                    format!(
+                        "Invalid value for setting {}: {}, expected a number or none",
+                        variable, setting
+                    )
+                })?),
+            };
+            user.set_max_result_columns(max_columns);
+        }
+        "max_merge_conflicts" => {
+            let max_conflicts: Option<usize> = match setting.as_str() {
+                "none" => None,
+                _ => Some(
+                    setting
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|max_conflicts| *max_conflicts > 0)
+                        .ok_or(format!(
+                            "Invalid value for setting {}: {}, expected a number above 0 or none",
+                            variable, setting
+                        ))?,
+                ),
+            };
+            user.set_max_merge_conflicts(max_conflicts);
+        }
+        _ => return Err(format!("Unknown setting: {}", variable)),
+    }
+    Ok(format!("Set {} to {}", variable, setting))
 }
 
-pub fn to_ident(s: String) -> Expr {
-    Expr::Identifier(Ident {
-        value: s.to_string(),
-        quote_style: None,
-    })
+// The names of the columns that a write returns, and how to work them out from a written row
+type ReturnedColumns = (Vec<String>, Vec<ValueSolver>);
+
+/// Solves the `RETURNING` expressions of a write to the given table, before anything is written,
+/// so that a write with invalid expressions doesn't change the table.
+fn prepare_returning(
+    returning: &Option<Vec<SelectItem>>,
+    table_name: &String,
+    user: &User,
+) -> Result<Option<ReturnedColumns>, String> {
+    match returning {
+        Some(returning) => {
+            let table: Table = Table::from_user(user, get_db_instance()?, table_name, None)?;
+            Ok(Some(solve_returning(
+                &returning,
+                table_name,
+                &table.schema,
+            )?))
+        }
+        None => Ok(None),
+    }
 }
 
-pub fn set_operations(
-    op: &SetOperator,
-    left_rows: Vec<Row>,
-    right_rows: Vec<Row>,
-) -> Result<Vec<Row>, String> {
-    // checking if the columns match
-    if left_rows.is_empty() || right_rows.is_empty() {
-        match op {
-            SetOperator::Union => {
-                if left_rows.is_empty() {
-                    return Ok(right_rows);
-                }
-                return Ok(left_rows);
+/// Solves the `RETURNING` expressions of a write to the given table.
+/// Returns the names of the returned columns, and how to work out their values from a written row.
+fn solve_returning(
+    returning: &Vec<SelectItem>,
+    table_name: &String,
+    schema: &Schema,
+) -> Result<ReturnedColumns, String> {
+    let column_aliases: ColumnAliases =
+        gen_column_aliases_from_schema(&vec![(schema.clone(), table_name.clone())]);
+    let index_refs: IndexRefs = get_index_refs(&column_aliases);
+
+    // A wildcard returns every column that can be selected, and an alias names its column
+    let column_name = |expr: &Expr| match expr {
+        Expr::Identifier(x) => x.value.clone(),
+        Expr::CompoundIdentifier(x) => x.last().map_or(expr.to_string(), |x| x.value.clone()),
+        _ => expr.to_string(),
+    };
+    let mut exprs: Vec<Expr> = Vec::new();
+    let mut columns: Vec<String> = Vec::new();
+    for item in returning {
+        match item {
+            SelectItem::UnnamedExpr(expr) => {
+                columns.push(column_name(expr));
+                exprs.push(expr.clone());
             }
-            SetOperator::Except => {
-                return Ok(left_rows);
+            SelectItem::ExprWithAlias { expr, alias } => {
+                columns.push(alias.value.clone());
+                exprs.push(expr.clone());
             }
-            SetOperator::Intersect => {
-                return Ok(vec![]);
+            SelectItem::Wildcard | SelectItem::QualifiedWildcard(_) => {
+                for (name, _) in schema.iter().filter(|(name, _)| !is_ttl_column(name)) {
+                    columns.push(name.clone());
+                    exprs.push(Expr::Identifier(Ident::new(name)));
+                }
             }
         }
     }
-
-    let left_columns: Vec<Column> = left_rows[0]
+    let solvers: Vec<ValueSolver> = exprs
         .iter()
-        .map(|v| v.get_coltype().as_nullable())
-        .collect();
-    let right_columns: Vec<Column> = right_rows[0]
+        .map(|expr| solve_value(expr, &column_aliases, &index_refs))
+        .collect::<Result<Vec<ValueSolver>, String>>()?;
+    Ok((columns, solvers))
+}
+
+// Adds the returned values of each row that a write wrote to its message, as a table.
+// The rows are the new rows of an INSERT or UPDATE, or the removed rows of a DELETE.
+fn with_returned_rows(
+    message: String,
+    returning: Option<ReturnedColumns>,
+    rows: &Vec<RowInfo>,
+) -> Result<String, String> {
+    let (columns, solvers) = match returning {
+        Some(returning) => returning,
+        None => return Ok(message),
+    };
+    let rows: Vec<Row> = rows
         .iter()
-        .map(|v| v.get_coltype().as_nullable())
-        .collect();
+        .map(|row_info| {
+            solvers
+                .iter()
+                .map(|solver| resolve_value(solver, &row_info.row))
+                .collect::<Result<Row, String>>()
+        })
+        .collect::<Result<Vec<Row>, String>>()?;
+    Ok(format!("{}\n{}", message, format_rows(columns, &rows)))
+}
 
-    // Checking if the columns match
-    if left_columns.len() != right_columns.len() {
-        return Err("Incompatible types in set operation".to_string());
+// Formats rows as a table, so that they can be returned along with a write's message
+fn format_rows(columns: Vec<String>, rows: &Vec<Row>) -> String {
+    let mut builder = Builder::default();
+    builder.set_columns(columns);
+    for row in rows {
+        builder.add_record(row.iter().map(|value| value.to_sql_value().to_string()));
     }
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    table.to_string()
+}
 
-    let schemas_match = left_columns
-        .iter()
-        // For each table 1, join it with it's column in table 2
-        .zip(right_columns.iter())
-        // Check if any do not match the condition that their types *should* match.
-        .any(|(l, r)| l.match_type(r));
+/// Whether the given expression is the DEFAULT keyword used in place of a value.
+/// sqlparser has no special expression for it, so it is parsed as an identifier.
+fn is_default_keyword(expr: &Expr) -> bool {
+    match expr {
+        Expr::Identifier(ident) => {
+            ident.quote_style.is_none() && ident.value.to_uppercase() == "DEFAULT"
+        }
+        _ => false,
+    }
+}
 
-    if !schemas_match {
-        return Err("Columns don't match".to_string());
+/// Gets the value that DEFAULT stands for in the column at the given position.
+/// Columns don't have configured defaults, so nullable columns default to NULL,
+/// and using DEFAULT for any other column is an error.
+fn get_column_default(schema: &Schema, index: usize) -> Result<Value, String> {
+    match schema.get(index) {
+        Some((_, Column::Nullable(col))) => Ok(Value::Null(*col.clone())),
+        Some((name, _)) => Err(format!("Column {} does not have a default value", name)),
+        None => Err(format!(
+            "Number of values ({}) to be inserted does not match the number of columns in the table ({})",
+            index + 1,
+            schema.len()
+        )),
     }
+}
 
-    let mut new_left: Vec<Row> = left_rows;
-    // Create right rows by converting them to the same schema as the left rows
-    let new_right: Vec<Row> = right_rows
-        .into_iter()
-        // For each row
-        .map(|row| {
-            // For each value
-            row.into_iter()
-                // Combine each value with it's supposed type
-                .zip(left_columns.iter())
-                // Convert the value to the correct type
-                .map(|(val, col_type)| col_type.coerce_type(val))
-                // Collect the values into a row
-                .collect::<Result<Row, String>>()
-        })
-        .collect::<Result<Vec<Row>, String>>()?;
+// This method implements the SQL Where clause. It takes in an expression, and generates
+// a function that takes in a row and returns a boolean. The function returns an error if
+// the expression is invalid.
+pub fn where_clause(
+    pred: &Expr,
+    table_names: &Vec<(String, String)>,
+    database: &Database,
+    user: &User,
+) -> Result<PredicateSolver, String> {
+    let tables = load_aliased_tables(database, user, &table_names)?;
+    let column_aliases = gen_column_aliases(&tables);
+    let index_refs = get_index_refs(&column_aliases);
+    solve_predicate(pred, &column_aliases, &index_refs)
+}
 
-    match op {
-        SetOperator::Union => {
-            for row in new_right {
-                if !new_left.contains(&row) {
-                    new_left.push(row);
-                }
-            }
-            Ok(new_left)
-        }
-        SetOperator::Except => {
-            let mut rows: Vec<Row> = Vec::new();
-            for row in new_left {
-                if !new_right.contains(&row) {
-                    rows.push(row);
-                }
+/// Evaluates the subqueries used as sets of values, such as in `x > ALL (SELECT ...)`, and
+/// replaces them with the tuple of values they return. This way, the predicate doesn't need
+/// to run the subquery again for every row it checks.
+pub fn resolve_subqueries(expr: &Expr, user: &mut User) -> Result<Expr, String> {
+    match expr {
+        Expr::AllOp(set) => Ok(Expr::AllOp(Box::new(resolve_subquery_values(set, user)?))),
+        Expr::AnyOp(set) => Ok(Expr::AnyOp(Box::new(resolve_subquery_values(set, user)?))),
+        Expr::BinaryOp { left, op, right } => Ok(Expr::BinaryOp {
+            left: Box::new(resolve_subqueries(left, user)?),
+            op: op.clone(),
+            right: Box::new(resolve_subqueries(right, user)?),
+        }),
+        Expr::UnaryOp { op, expr } => Ok(Expr::UnaryOp {
+            op: op.clone(),
+            expr: Box::new(resolve_subqueries(expr, user)?),
+        }),
+        Expr::Nested(expr) => Ok(Expr::Nested(Box::new(resolve_subqueries(expr, user)?))),
+        // `x IN (SELECT ...)` is evaluated once, and then checked like `x IN (1, 2, ...)`
+        Expr::InSubquery {
+            expr,
+            subquery,
+            negated,
+        } => match resolve_subquery_values(&Expr::Subquery(subquery.clone()), user)? {
+            Expr::Tuple(list) => Ok(Expr::InList {
+                expr: Box::new(resolve_subqueries(expr, user)?),
+                list,
+                negated: *negated,
+            }),
+            set => Err(format!("Subquery was not evaluated: {}", set)),
+        },
+        // A correlated EXISTS depends on the outer row, so it's left for the select to check
+        Expr::Exists { subquery, negated } => {
+            if get_exists_correlation(subquery, get_db_instance()?, user)?.is_some() {
+                return Ok(expr.clone());
             }
-            Ok(rows)
+            let (_, _, rows) = run_query(subquery, &SelectClauses::default(), user, None)?;
+            Ok(Expr::Value(sqlparser::ast::Value::Boolean(
+                rows.is_empty() == *negated,
+            )))
         }
-        SetOperator::Intersect => {
-            let mut rows: Vec<Row> = Vec::new();
-            for row in new_right {
-                if new_left.contains(&row) {
-                    rows.push(row);
-                }
+        _ => Ok(expr.clone()),
+    }
+}
+
+// Runs a subquery that returns a single column, and turns its rows into a tuple of values
+fn resolve_subquery_values(set: &Expr, user: &mut User) -> Result<Expr, String> {
+    match set {
+        Expr::Subquery(query) => {
+            let (columns, _, rows) = run_query(query, &SelectClauses::default(), user, None)?;
+            if columns.len() != 1 {
+                return Err(format!(
+                    "Subquery must return a single column, but it returns {}",
+                    columns.len()
+                ));
             }
-            Ok(rows)
+            Ok(Expr::Tuple(
+                rows.iter()
+                    .map(|row| Expr::Value(row[0].to_sql_value()))
+                    .collect(),
+            ))
         }
+        _ => Ok(set.clone()),
     }
 }
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
-    use crate::{
-        parser::parser::parse,
-        util::{
-            self,
-            bench::create_demo_db,
-            dbtype::{Column, Value},
-        },
+/// A correlated EXISTS subquery, reduced to the parts needed to check it for an outer row
+struct ExistsCorrelation {
+    table_name: (String, String), // The name and alias of the subquery's table
+    inner_column: Expr,           // The column of the subquery's table that is compared
+    outer_value: Expr,            // The value from the outer query that it's compared to
+    inner_pred: Option<Expr>,     // The rest of the subquery's WHERE clause
+}
+
+/// A correlated EXISTS, which can be checked for each row of the outer query
+struct ExistsFilter {
+    description: String, // Describes how the subquery is checked, for EXPLAIN
+    solver: PredicateSolver,
+}
+
+// Splits an expression into the expressions that are ANDed together
+fn split_conjuncts(expr: Expr, conjuncts: &mut Vec<Expr>) {
+    match expr {
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            split_conjuncts(*left, conjuncts);
+            split_conjuncts(*right, conjuncts);
+        }
+        Expr::Nested(expr) if matches!(*expr, Expr::BinaryOp { .. }) => {
+            split_conjuncts(*expr, conjuncts)
+        }
+        expr => conjuncts.push(expr),
+    }
+}
+
+// Removes any parentheses around an expression, so `((x))` is looked at as `x`
+fn unwrap_nested(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Nested(expr) => unwrap_nested(expr),
+        expr => expr,
+    }
+}
+
+// Joins expressions back together with AND
+fn join_conjuncts(conjuncts: Vec<Expr>) -> Option<Expr> {
+    conjuncts.into_iter().reduce(|left, right| Expr::BinaryOp {
+        left: Box::new(left),
+        op: BinaryOperator::And,
+        right: Box::new(right),
+    })
+}
+
+/// Picks the index to scan for a where clause on a single table, along with the expression to
+/// scan it with. Normally an index is only used when it's on exactly the columns of the clause.
+/// If the user prefers index scans, an index on the columns of any one of the ANDed conditions
+/// is used instead. Either way, the whole clause is still checked on the rows that are found.
+fn choose_index(
+    expr: &Expr,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+    alias: &String,
+    table: &Table,
+    user: &User,
+) -> Result<Option<(IndexID, Expr)>, String> {
+    if let Some(index_id) = get_index_id_from_expr(expr, column_aliases, index_refs, alias)? {
+        if table.indexes.contains_key(&index_id) {
+            return Ok(Some((index_id, expr.clone())));
+        }
+    }
+    if !user.get_prefer_index_scans() {
+        return Ok(None);
+    }
+
+    let mut conjuncts: Vec<Expr> = Vec::new();
+    split_conjuncts(expr.clone(), &mut conjuncts);
+    for conjunct in conjuncts {
+        // Conditions the index can't handle are left for the full check
+        let index_id: Option<IndexID> =
+            get_index_id_from_expr(&conjunct, column_aliases, index_refs, alias)
+                .ok()
+                .flatten();
+        if let Some(index_id) = index_id {
+            if !index_id.is_empty() && table.indexes.contains_key(&index_id) {
+                return Ok(Some((index_id, conjunct)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Counts the rows for `SELECT COUNT(*) FROM t WHERE ...` from the entries of an index, without
+/// reading the rows of the table. This is only done when the where clause is on exactly the
+/// columns of an index, and the index finds exactly the rows that match it. Otherwise, None is
+/// returned and the rows have to be scanned.
+fn count_with_index(
+    column_exprs: &Vec<Expr>,
+    where_expr: &Option<Expr>,
+    tables: &Tables,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+) -> Result<Option<usize>, String> {
+    let is_count_all: bool = match column_exprs.as_slice() {
+        [Expr::Function(func)] => {
+            func.name.to_string().to_lowercase() == "count"
+                && !func.distinct
+                && func.over.is_none()
+                && matches!(
+                    func.args.as_slice(),
+                    [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)]
+                )
+        }
+        _ => false,
     };
-    use serial_test::serial;
+    let (expr, table, alias) = match (where_expr, tables.as_slice()) {
+        (Some(expr), [(table, alias)]) if is_count_all => (expr, table, alias),
+        _ => return Ok(None),
+    };
+    if !is_exact_index_condition(expr, column_aliases) {
+        return Ok(None);
+    }
+    let index_id: IndexID = match get_index_id_from_expr(expr, column_aliases, index_refs, alias)? {
+        Some(index_id) => index_id,
+        None => return Ok(None),
+    };
+    let (btree_pagenum, index_name) = match table.indexes.get(&index_id) {
+        Some(index) => index.clone(),
+        None => return Ok(None),
+    };
+    let index_key_type: IndexKeyType = index_id
+        .iter()
+        .map(|x| table.schema[*x as usize].1.clone())
+        .collect();
+    let btree: BTree = BTree::load_btree_from_root_page(
+        table,
+        btree_pagenum,
+        index_id,
+        index_key_type,
+        index_name,
+    )?;
+    Ok(Some(btree.count_rows_matching_expr(expr)?))
+}
+
+// Whether an index finds exactly the rows that match the condition, rather than some extra rows
+// that are filtered out afterwards. That's the case for comparisons of a column with a literal of
+// its own type, and ANDs of them. Nulls are ordered before every value, so a range on a nullable
+// column would find its nulls too, and only an equality can be used on it.
+fn is_exact_index_condition(expr: &Expr, column_aliases: &ColumnAliases) -> bool {
+    match expr {
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            is_exact_index_condition(left, column_aliases)
+                && is_exact_index_condition(right, column_aliases)
+        }
+        Expr::BinaryOp { left, op, right } => {
+            let (column_name, value) = match (left.as_ref(), right.as_ref()) {
+                (Expr::Identifier(x), Expr::Value(value))
+                | (Expr::Value(value), Expr::Identifier(x)) => (x.value.to_string(), value),
+                (Expr::CompoundIdentifier(list), Expr::Value(value))
+                | (Expr::Value(value), Expr::CompoundIdentifier(list)) => {
+                    (list.iter().map(|x| x.value.to_string()).join("."), value)
+                }
+                _ => return false,
+            };
+            let coltype: Column = match resolve_reference(column_name, column_aliases) {
+                Ok(column_name) => match column_aliases
+                    .iter()
+                    .find(|(name, _, _)| name == &column_name)
+                {
+                    Some((_, coltype, _)) => coltype.clone(),
+                    None => return false,
+                },
+                Err(_) => return false,
+            };
+            let is_nullable: bool = coltype.is_nullable();
+            let inner_type: Column = match coltype {
+                Column::Nullable(inner) => *inner,
+                coltype => coltype,
+            };
+            let is_same_type: bool = match (value, inner_type) {
+                (SqlValue::Number(..), Column::I32 | Column::I64) => true,
+                (SqlValue::SingleQuotedString(_), Column::String(_)) => true,
+                _ => false,
+            };
+            match op {
+                BinaryOperator::Eq => is_same_type,
+                BinaryOperator::Lt
+                | BinaryOperator::LtEq
+                | BinaryOperator::Gt
+                | BinaryOperator::GtEq => is_same_type && !is_nullable,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+// Returns true if the expression has an EXISTS anywhere in it
+fn contains_exists(expr: &Expr) -> bool {
+    match expr {
+        Expr::Exists { .. } => true,
+        Expr::BinaryOp { left, right, .. } => contains_exists(left) || contains_exists(right),
+        Expr::UnaryOp { expr, .. } | Expr::Nested(expr) => contains_exists(expr),
+        _ => false,
+    }
+}
+
+/// Takes the EXISTS subqueries that are ANDed with the rest of the WHERE clause out of it.
+/// Returns what's left of the WHERE clause, and each subquery with whether it's a NOT EXISTS.
+fn split_exists_subqueries(expr: Expr) -> Result<(Option<Expr>, Vec<(Query, bool)>), String> {
+    let mut conjuncts: Vec<Expr> = Vec::new();
+    split_conjuncts(expr, &mut conjuncts);
+
+    let mut subqueries: Vec<(Query, bool)> = Vec::new();
+    let mut remaining: Vec<Expr> = Vec::new();
+    for conjunct in conjuncts {
+        match conjunct {
+            Expr::Exists { subquery, negated } => subqueries.push((*subquery, negated)),
+            conjunct if contains_exists(&conjunct) => {
+                return Err(format!(
+                    "A correlated EXISTS can only be combined with the rest of the WHERE clause using AND: {}",
+                    conjunct
+                ))
+            }
+            conjunct => remaining.push(conjunct),
+        }
+    }
+    Ok((join_conjuncts(remaining), subqueries))
+}
+
+/// Works out if an EXISTS subquery refers to the outer query. Only subqueries on a single
+/// table that compare one of its columns to a value from the outer query with `=` can be
+/// correlated, and the rest of their WHERE clause can only use the subquery's table.
+/// Returns None if the subquery doesn't refer to the outer query.
+fn get_exists_correlation(
+    subquery: &Query,
+    database: &Database,
+    user: &User,
+) -> Result<Option<ExistsCorrelation>, String> {
+    let select: &Select = match subquery.body.as_ref() {
+        SetExpr::Select(select) => select,
+        _ => return Ok(None),
+    };
+    let selection: Expr = match (&select.selection, select.from.as_slice()) {
+        (Some(selection), [table]) if table.joins.is_empty() => selection.clone(),
+        _ => return Ok(None),
+    };
+    let table_name: (String, String) = get_table_name(&select.from[0].relation)?;
+    let tables: Tables = load_aliased_tables(database, user, &vec![table_name.clone()])?;
+    let column_aliases: ColumnAliases = gen_column_aliases(&tables);
+    let index_refs: IndexRefs = get_index_refs(&column_aliases);
+    let is_inner = |expr: &Expr| solve_value(expr, &column_aliases, &index_refs).is_ok();
+
+    let mut conjuncts: Vec<Expr> = Vec::new();
+    split_conjuncts(selection, &mut conjuncts);
+    let mut correlation: Option<(Expr, Expr)> = None;
+    let mut inner_conjuncts: Vec<Expr> = Vec::new();
+    for conjunct in conjuncts {
+        if solve_predicate(&conjunct, &column_aliases, &index_refs).is_ok() {
+            inner_conjuncts.push(conjunct);
+            continue;
+        }
+        let (inner_column, outer_value) = match &conjunct {
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::Eq,
+                right,
+            } => match (unwrap_nested(left), unwrap_nested(right)) {
+                (inner @ (Expr::Identifier(_) | Expr::CompoundIdentifier(_)), outer)
+                    if is_inner(inner) =>
+                {
+                    (inner.clone(), outer.clone())
+                }
+                (outer, inner @ (Expr::Identifier(_) | Expr::CompoundIdentifier(_)))
+                    if is_inner(inner) =>
+                {
+                    (inner.clone(), outer.clone())
+                }
+                _ => return Err(format!("Unsupported condition in EXISTS: {}", conjunct)),
+            },
+            _ => return Err(format!("Unsupported condition in EXISTS: {}", conjunct)),
+        };
+        if correlation.is_some() {
+            return Err(
+                "A correlated EXISTS can only compare one column to the outer query".to_string(),
+            );
+        }
+        correlation = Some((inner_column, outer_value));
+    }
+
+    Ok(
+        correlation.map(|(inner_column, outer_value)| ExistsCorrelation {
+            table_name,
+            inner_column,
+            outer_value,
+            inner_pred: join_conjuncts(inner_conjuncts),
+        }),
+    )
+}
+
+/// Creates the filter for a correlated EXISTS. For each outer row, it looks for a row of the
+/// subquery's table with a matching column, which also satisfies the rest of the subquery's
+/// WHERE clause. If the column has an index, it's probed with the value from the outer row,
+/// which is a semi-join. Otherwise, the whole table is scanned for every outer row.
+fn solve_exists(
+    subquery: &Query,
+    negated: bool,
+    column_aliases: &ColumnAliases,
+    index_refs: &IndexRefs,
+    database: &Database,
+    user: &User,
+) -> Result<ExistsFilter, String> {
+    let correlation: ExistsCorrelation = get_exists_correlation(subquery, database, user)?
+        .ok_or("EXISTS subquery was not evaluated".to_string())?;
+    let outer_value: ValueSolver =
+        solve_value(&correlation.outer_value, column_aliases, index_refs)?;
+
+    let (table, alias) =
+        load_aliased_tables(database, user, &vec![correlation.table_name])?.remove(0);
+    let inner_aliases: ColumnAliases = gen_column_aliases(&vec![(table.clone(), alias.clone())]);
+    let inner_refs: IndexRefs = get_index_refs(&inner_aliases);
+    let inner_pred: Option<PredicateSolver> = match &correlation.inner_pred {
+        Some(pred) => Some(solve_predicate(pred, &inner_aliases, &inner_refs)?),
+        None => None,
+    };
+    let index_id: IndexID = get_index_id_from_expr(
+        &correlation.inner_column,
+        &inner_aliases,
+        &inner_refs,
+        &alias,
+    )?
+    .ok_or(format!(
+        "Invalid column in EXISTS: {}",
+        correlation.inner_column
+    ))?;
+    let column_index: usize = index_id[0] as usize;
+    let column_type: Column = table.schema[column_index].1.clone();
+
+    // Finds the value that the inner column has to be equal to, if there can be one
+    let get_key = move |row: &Row| -> Result<Option<Value>, String> {
+        let value: Value = resolve_value(&outer_value, row)?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(column_type.coerce_type(value)?))
+    };
+
+    if let Some((btree_pagenum, index_name)) = table.indexes.get(&index_id).cloned() {
+        let btree: BTree = BTree::load_btree_from_root_page(
+            &table,
+            btree_pagenum,
+            index_id,
+            vec![table.schema[column_index].1.clone()],
+            index_name.clone(),
+        )?;
+        return Ok(ExistsFilter {
+            description: format!(
+                "Semi-join on table {} as {} using index {}",
+                table.name, alias, index_name
+            ),
+            solver: Box::new(move |row| {
+                if let Some(key) = get_key(row)? {
+                    for row_info in btree.get_rows(&vec![key])? {
+                        if resolve_predicate(&inner_pred, &row_info.row)? {
+                            return Ok(!negated);
+                        }
+                    }
+                }
+                Ok(negated)
+            }),
+        });
+    }
+
+    Ok(ExistsFilter {
+        description: format!(
+            "Semi-join on table {} as {} by scanning it for each row",
+            table.name, alias
+        ),
+        solver: Box::new(move |row| {
+            if let Some(key) = get_key(row)? {
+                for row_info in table.clone() {
+                    if row_info.row[column_index] == key
+                        && resolve_predicate(&inner_pred, &row_info.row)?
+                    {
+                        return Ok(!negated);
+                    }
+                }
+            }
+            Ok(negated)
+        }),
+    })
+}
+
+// Gets the name and alias of a table in the FROM clause
+fn get_table_name(relation: &TableFactor) -> Result<(String, String), String> {
+    if let TableFactor::Table { with_hints, .. } = relation {
+        if let Some(hint) = with_hints.first() {
+            return Err(format!("Unsupported table hint: {}", hint));
+        }
+    }
+
+    let table_name = relation.to_string();
+    let table_name: Vec<&str> = table_name.split(" ").collect();
+    if table_name.len() == 3 {
+        Ok((table_name[0].to_string(), table_name[2].to_string()))
+    } else {
+        Ok((table_name[0].to_string(), "".to_string()))
+    }
+}
+
+// Gets the fraction of the rows to sample from the table at the given position in the FROM
+// clause, if it has a TABLESAMPLE.
+fn get_table_sample(clauses: &SelectClauses, table: usize) -> Result<Option<f64>, String> {
+    let sample = match clauses
+        .table_samples
+        .iter()
+        .find(|sample| sample.table == table)
+    {
+        Some(sample) => sample,
+        None => return Ok(None),
+    };
+    if !(0.0..=100.0).contains(&sample.percent) {
+        return Err(format!(
+            "TABLESAMPLE percent must be between 0 and 100, but it is {}",
+            sample.percent
+        ));
+    }
+    Ok(Some(sample.percent / 100.0))
+}
+
+// Generating tables with aliases from a list of table names,
+// and creating new aliases where necessary
+fn load_aliased_tables(
+    database: &Database,
+    user: &User,
+    table_names: &Vec<(String, String)>,
+) -> Result<Tables, Error> {
+    let tables: Vec<(Table, String)> = table_names
+        .iter()
+        .map(|(table_name, alias)| {
+            let table = if let Some(table_dir) = user.get_cte_table_dir(table_name) {
+                Table::new(&table_dir, table_name, None)?
+            } else if is_system_table(table_name) {
+                load_system_table(table_name, user)?
+            } else if let Some((branch_name, name)) = table_name.split_once('.') {
+                load_branch_table(database, user, &branch_name.to_string(), &name.to_string())?
+            } else {
+                Table::from_user(user, database, table_name, None)?
+            };
+            if alias.is_empty() {
+                // If no alias is provided, use the table name as the alias, without its branch
+                let alias = match table_name.split_once('.') {
+                    Some((_, name)) => name.to_string(),
+                    None => table_name.clone(),
+                };
+                Ok((table, alias))
+            } else {
+                Ok((table, alias.to_string()))
+            }
+        })
+        .collect::<Result<Tables, String>>()?;
+    Ok(tables)
+}
+
+/// Loads a table written as `<branch>.<table>` from the named branch, so one statement can
+/// read the same table on several branches. The user's own branch is read from their working
+/// copy, so their uncommitted changes are seen, like any other table they read.
+fn load_branch_table(
+    database: &Database,
+    user: &User,
+    branch_name: &String,
+    table_name: &String,
+) -> Result<Table, Error> {
+    if user.get_permissions() == Write {
+        return Err(Error::permission_denied(format!(
+            "You do not have the permission to read tables on branch {}",
+            branch_name
+        )));
+    }
+    if branch_name == &user.get_current_branch_name() {
+        return Ok(Table::from_user(user, database, table_name, None)?);
+    }
+
+    // Checking if the branch exists. It will return an error if it doesn't exist.
+    get_db_instance()?
+        .get_branch_heads_file_mut()
+        .get_branch_head(branch_name)?;
+    let branch_path: String = database.get_branch_path_from_name(branch_name);
+    if !get_storage_backend(&branch_path).exists(&branch_path) {
+        return Err(format!(
+            "The tables for branch {} have not been built, use GQL switch_branch first",
+            branch_name
+        )
+        .into());
+    }
+    Ok(Table::new(&branch_path, table_name, None)?)
+}
+
+// Get the names of all the columns in the tables along with their aliases in
+// the format <alias>.<column_name> and store them in a vector of tuples
+// alongside their column types and new column name when output.
+// It will be a vector of tuples where each tuple is of the form:
+// (<table_alias>.<column_name>, <column_type>, <output_column_name>)
+pub fn gen_column_aliases(tables: &Tables) -> ColumnAliases {
+    tables
+        .iter()
+        .map(|(table, alias): &(Table, String)| {
+            table
+                .schema
+                .iter()
+                .map(|(name, coltype)| {
+                    (
+                        format!("{}.{}", alias, name.clone()),
+                        coltype.clone(),
+                        name.clone(),
+                    )
+                })
+                .collect::<ColumnAliases>()
+        })
+        .flatten()
+        .collect::<ColumnAliases>()
+}
+
+// Get the names of all the columns in the tables along with their aliases in
+// the format <alias>.<column_name> and store them in a vector of tuples
+// alongside their column types and new column name when output.
+// It will be a vector of tuples where each tuple is of the form:
+// (<table_alias>.<column_name>, <column_type>, <output_column_name>)
+pub fn gen_column_aliases_from_schema(tables: &Vec<(Schema, String)>) -> ColumnAliases {
+    tables
+        .iter()
+        .map(|(schema, alias): &(Schema, String)| {
+            schema
+                .iter()
+                .map(|(name, coltype)| {
+                    (
+                        format!("{}.{}", alias, name.clone()),
+                        coltype.clone(),
+                        name.clone(),
+                    )
+                })
+                .collect::<ColumnAliases>()
+        })
+        .flatten()
+        .collect::<ColumnAliases>()
+}
+
+/// Hashmap from column names to index in the row
+pub fn get_index_refs(column_aliases: &ColumnAliases) -> IndexRefs {
+    column_aliases
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _, _))| (name.clone(), i))
+        .collect::<IndexRefs>()
+}
+
+/// Given a set of Columns, this creates a vector to reference these columns and apply relevant operations.
+/// The position of the item that each column came from is added to column_items.
+fn resolve_columns(
+    columns: Vec<SelectItem>,
+    column_names: &mut Vec<String>,
+    column_items: &mut Vec<usize>,
+    tables: &Tables,
+    column_aliases: &ColumnAliases,
+) -> Result<Vec<Expr>, String> {
+    let mut column_exprs: Vec<Expr> = Vec::new();
+    for (i, item) in columns.into_iter().enumerate() {
+        let exprs: Vec<Expr> = resolve_selects(item, column_names, &tables, column_aliases)?;
+        column_items.extend(exprs.iter().map(|_| i));
+        column_exprs.extend(exprs);
+    }
+    Ok(column_exprs)
+}
+
+/// Given a specific SelectItem, this will resolve the column name and create a function to resolve the value
+fn resolve_selects(
+    item: SelectItem,
+    column_names: &mut Vec<String>,
+    tables: &Tables,
+    column_aliases: &ColumnAliases,
+) -> Result<Vec<Expr>, String> {
+    Ok(match item {
+        SelectItem::ExprWithAlias { expr, alias } => {
+            column_names.push(alias.value.clone());
+            vec![expr]
+        }
+        SelectItem::UnnamedExpr(expr) => {
+            column_names.push(expr.to_string());
+            vec![expr]
+        }
+        // Pick out all the columns
+        SelectItem::Wildcard => {
+            // The hidden TTL columns aren't selected
+            let visible_aliases: Vec<&ColumnAlias> = column_aliases
+                .iter()
+                .filter(|(x, _, _)| !is_ttl_column(x.split('.').last().unwrap_or(x)))
+                .collect();
+            let names: Vec<Expr> = visible_aliases
+                .iter()
+                .map(|(x, _, _)| to_ident(x.clone()))
+                .collect();
+            column_names.append(
+                visible_aliases
+                    .iter()
+                    .map(|(_, _, z)| z.clone())
+                    .collect::<Vec<String>>()
+                    .as_mut(),
+            );
+            names
+        }
+        // Pick out all the columns from tha table, aliased
+        SelectItem::QualifiedWildcard(idents) => {
+            let name = idents
+                .0
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join(".");
+            let index = tables
+                .iter()
+                .position(|(t, alias)| t.name == name || alias == &name);
+            if let Some(index) = index {
+                let (table, alias) = tables.get(index).unwrap();
+                table
+                    .schema
+                    .iter()
+                    .filter(|(colname, _)| !is_ttl_column(colname))
+                    .map(|(colname, _)| {
+                        column_names.push(colname.clone());
+                        to_ident(format!("{}.{}", alias, colname))
+                    })
+                    .collect()
+            } else {
+                return Err(format!("Table {} not found.", name));
+            }
+        }
+    })
+}
+
+/// Returns the (alias, item position) pairs of all the aliased items in the select
+fn get_projection_aliases(columns: &Vec<SelectItem>) -> Vec<(String, usize)> {
+    columns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| match item {
+            SelectItem::ExprWithAlias { alias, .. } => Some((alias.value.clone(), i)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Gets the position of the selected column that a group by or order by expression refers to,
+/// either by its 1-based position (ORDER BY 2) or by its alias (GROUP BY total). Returns None
+/// for any other expression. An alias can be in parentheses, but a position can't, as
+/// `ORDER BY (2)` orders by the number 2.
+fn get_projection_reference(
+    expr: &Expr,
+    column_items: &Vec<usize>, // The position of the item that each column came from
+    projection_aliases: &Vec<(String, usize)>,
+) -> Result<Option<usize>, String> {
+    match expr {
+        Expr::Nested(inner) if matches!(unwrap_nested(inner), Expr::Identifier(_)) => {
+            get_projection_reference(unwrap_nested(inner), column_items, projection_aliases)
+        }
+        Expr::Value(sqlparser::ast::Value::Number(n, _)) => {
+            let position: usize = n
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid column position: {}", n))?;
+            if position == 0 || position > column_items.len() {
+                return Err(format!("Column position {} is not in the select list", n));
+            }
+            Ok(Some(position - 1))
+        }
+        // An aliased item is a single column
+        Expr::Identifier(ident) => Ok(projection_aliases
+            .iter()
+            .find(|(alias, _)| alias == &ident.value)
+            .and_then(|(_, item)| column_items.iter().position(|i| i == item))),
+        _ => Ok(None),
+    }
+}
+
+pub fn to_ident(s: String) -> Expr {
+    Expr::Identifier(Ident {
+        value: s.to_string(),
+        quote_style: None,
+    })
+}
+
+pub fn set_operations(
+    op: &SetOperator,
+    left_rows: Vec<Row>,
+    right_rows: Vec<Row>,
+) -> Result<Vec<Row>, String> {
+    // checking if the columns match
+    if left_rows.is_empty() || right_rows.is_empty() {
+        match op {
+            SetOperator::Union => {
+                if left_rows.is_empty() {
+                    return Ok(right_rows);
+                }
+                return Ok(left_rows);
+            }
+            SetOperator::Except => {
+                return Ok(left_rows);
+            }
+            SetOperator::Intersect => {
+                return Ok(vec![]);
+            }
+        }
+    }
+
+    let left_columns: Vec<Column> = left_rows[0]
+        .iter()
+        .map(|v| v.get_coltype().as_nullable())
+        .collect();
+    let right_columns: Vec<Column> = right_rows[0]
+        .iter()
+        .map(|v| v.get_coltype().as_nullable())
+        .collect();
+
+    // Checking if the columns match
+    if left_columns.len() != right_columns.len() {
+        return Err("Incompatible types in set operation".to_string());
+    }
+
+    let schemas_match = left_columns
+        .iter()
+        // For each table 1, join it with it's column in table 2
+        .zip(right_columns.iter())
+        // Check if any do not match the condition that their types *should* match.
+        .any(|(l, r)| l.match_type(r));
+
+    if !schemas_match {
+        return Err("Columns don't match".to_string());
+    }
+
+    let mut new_left: Vec<Row> = left_rows;
+    // Create right rows by converting them to the same schema as the left rows
+    let new_right: Vec<Row> = right_rows
+        .into_iter()
+        // For each row
+        .map(|row| {
+            // For each value
+            row.into_iter()
+                // Combine each value with it's supposed type
+                .zip(left_columns.iter())
+                // Convert the value to the correct type
+                .map(|(val, col_type)| col_type.coerce_type(val))
+                // Collect the values into a row
+                .collect::<Result<Row, String>>()
+        })
+        .collect::<Result<Vec<Row>, String>>()?;
+
+    match op {
+        SetOperator::Union => {
+            for row in new_right {
+                if !new_left.contains(&row) {
+                    new_left.push(row);
+                }
+            }
+            Ok(new_left)
+        }
+        SetOperator::Except => {
+            let mut rows: Vec<Row> = Vec::new();
+            for row in new_left {
+                if !new_right.contains(&row) {
+                    rows.push(row);
+                }
+            }
+            Ok(rows)
+        }
+        SetOperator::Intersect => {
+            let mut rows: Vec<Row> = Vec::new();
+            for row in new_right {
+                if new_left.contains(&row) {
+                    rows.push(row);
+                }
+            }
+            Ok(rows)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        parser::parser::parse,
+        util::{
+            self,
+            bench::{create_demo_db, create_huge_bench_db, fcreate_db_instance},
+            dbtype::{format_time_of_day, Column, Value},
+        },
+    };
+    use serial_test::serial;
+    use std::collections::HashSet;
+
+    pub fn to_selectitems(names: Vec<String>) -> Vec<SelectItem> {
+        names
+            .into_iter()
+            .map(|name| SelectItem::UnnamedExpr(to_ident(name)))
+            .collect()
+    }
+
+    #[test]
+    #[serial]
+    fn test_select_single_table_star() {
+        // This tests
+        // SELECT * FROM select_test_db.test_table1
+        let columns = vec![SelectItem::Wildcard];
+        let tables = vec![("test_table1".to_string(), "".to_string())]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+
+        insert(
+            [
+                vec![
+                    Value::I64(1),
+                    Value::String("Iron Man".to_string()),
+                    Value::I64(40),
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Spiderman".to_string()),
+                    Value::I64(20),
+                ],
+            ]
+            .to_vec(),
+            "test_table1".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap();
+
+        assert_eq!(result.0[0], "id".to_string());
+        assert_eq!(result.0[1], "name".to_string());
+        assert_eq!(result.0[2], "age".to_string());
+
+        // Assert that 2 rows were returned
+        assert_eq!(result.1.iter().len(), 2);
+
+        // Assert that the first row is correct
+        assert_eq!(result.1[0][0], Value::I32(1));
+        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+        assert_eq!(result.1[0][2], Value::I32(40));
+
+        // Assert that the second row is correct
+        assert_eq!(result.1[1][0], Value::I32(2));
+        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+        assert_eq!(result.1[1][2], Value::I32(20));
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_select_multi_table_star() {
+        // This tests:
+        // SELECT * FROM select_test_db.test_table1, select_test_db.test_table2
+        let columns = vec![SelectItem::Wildcard];
+        let tables = vec![
+            ("test_table1".to_string(), "T1".to_string()),
+            ("test_table2".to_string(), "T2".to_string()),
+        ]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+
+        let schema1: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+        let schema2: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("country".to_string(), Column::String(50)),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
+        create_table(&"test_table2".to_string(), &schema2, &new_db, &mut user).unwrap();
+
+        // Write rows to first table
+        insert(
+            [
+                vec![
+                    Value::I64(1),
+                    Value::String("Robert Downey Jr.".to_string()),
+                    Value::I64(40),
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Tom Holland".to_string()),
+                    Value::I64(20),
+                ],
+            ]
+            .to_vec(),
+            "test_table1".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        // Write rows to second table
+        insert(
+            [
+                vec![Value::I64(1), Value::String("United States".to_string())],
+                vec![Value::I64(2), Value::String("Britain".to_string())],
+            ]
+            .to_vec(),
+            "test_table2".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        // Run the SELECT query
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap();
+
+        // Check that the schema is correct
+        assert_eq!(result.0[0], "id".to_string());
+        assert_eq!(result.0[1], "name".to_string());
+        assert_eq!(result.0[2], "age".to_string());
+        assert_eq!(result.0[3], "id".to_string());
+        assert_eq!(result.0[4], "country".to_string());
+
+        // Check that we returned 4 rows
+        assert_eq!(result.1.iter().len(), 4);
+
+        // Check that the first row is correct
+        assert_eq!(result.1[0][0], Value::I32(1));
+        assert_eq!(
+            result.1[0][1],
+            Value::String("Robert Downey Jr.".to_string())
+        );
+        assert_eq!(result.1[0][2], Value::I32(40));
+        assert_eq!(result.1[0][3], Value::I32(1));
+        assert_eq!(result.1[0][4], Value::String("United States".to_string()));
+
+        // Check that the second row is correct
+        assert_eq!(result.1[1][0], Value::I32(1));
+        assert_eq!(
+            result.1[1][1],
+            Value::String("Robert Downey Jr.".to_string())
+        );
+        assert_eq!(result.1[1][2], Value::I32(40));
+        assert_eq!(result.1[1][3], Value::I32(2));
+        assert_eq!(result.1[1][4], Value::String("Britain".to_string()));
+
+        // Check that the third row is correct
+        assert_eq!(result.1[2][0], Value::I32(2));
+        assert_eq!(result.1[2][1], Value::String("Tom Holland".to_string()));
+        assert_eq!(result.1[2][2], Value::I32(20));
+        assert_eq!(result.1[2][3], Value::I32(1));
+        assert_eq!(result.1[2][4], Value::String("United States".to_string()));
+
+        // Check that the fourth row is correct
+        assert_eq!(result.1[3][0], Value::I32(2));
+        assert_eq!(result.1[3][1], Value::String("Tom Holland".to_string()));
+        assert_eq!(result.1[3][2], Value::I32(20));
+        assert_eq!(result.1[3][3], Value::I32(2));
+        assert_eq!(result.1[3][4], Value::String("Britain".to_string()));
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_select_single_table_specific_columns() {
+        // This tests
+        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
+        let columns = to_selectitems(vec!["T.id".to_string(), "name".to_string()]);
+        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        insert(
+            vec![
+                vec![
+                    Value::I64(1),
+                    Value::String("Iron Man".to_string()),
+                    Value::I64(40),
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Spiderman".to_string()),
+                    Value::I64(20),
+                ],
+                vec![
+                    Value::I64(3),
+                    Value::String("Doctor Strange".to_string()),
+                    Value::I64(35),
+                ],
+            ],
+            "test_table1".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        // Run the SELECT query
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap();
+
+        assert_eq!(result.0[0], "T.id".to_string());
+        assert_eq!(result.0[1], "name".to_string());
+
+        // Assert that 3 rows were returned
+        assert_eq!(result.1.iter().len(), 3);
+
+        // Assert that each row only has 2 columns
+        for row in result.1.clone() {
+            assert_eq!(row.len(), 2);
+        }
+
+        // Assert that the first row is correct
+        assert_eq!(result.1[0][0], Value::I32(1));
+        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+
+        // Assert that the second row is correct
+        assert_eq!(result.1[1][0], Value::I32(2));
+        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+
+        // Assert that the third row is correct
+        assert_eq!(result.1[2][0], Value::I32(3));
+        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_unaliased_select() {
+        // This tests
+        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
+        let columns = to_selectitems(vec!["id".to_string(), "name".to_string()]);
+        let tables = vec![("test_table1".to_string(), "".to_string())]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        insert(
+            vec![
+                vec![
+                    Value::I64(1),
+                    Value::String("Iron Man".to_string()),
+                    Value::I64(40),
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Spiderman".to_string()),
+                    Value::I64(20),
+                ],
+                vec![
+                    Value::I64(3),
+                    Value::String("Doctor Strange".to_string()),
+                    Value::I64(35),
+                ],
+            ],
+            "test_table1".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        // Run the SELECT query
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap();
+
+        assert_eq!(result.0[0], "id".to_string());
+        assert_eq!(result.0[1], "name".to_string());
+
+        // Assert that 3 rows were returned
+        assert_eq!(result.1.iter().len(), 3);
+
+        // Assert that each row only has 2 columns
+        for row in result.1.clone() {
+            assert_eq!(row.len(), 2);
+        }
+
+        // Assert that the first row is correct
+        assert_eq!(result.1[0][0], Value::I32(1));
+        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+
+        // Assert that the second row is correct
+        assert_eq!(result.1[1][0], Value::I32(2));
+        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+
+        // Assert that the third row is correct
+        assert_eq!(result.1[2][0], Value::I32(3));
+        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_select_multiple_tables_specific_columns() {
+        // This tests
+        // SELECT T1.id, country FROM select_test_db.test_table1 T1, select_test_db.test_table2;
+        let columns = to_selectitems(vec!["T1.id".to_string(), "country".to_string()]);
+        let tables = vec![
+            ("test_table1".to_string(), "T1".to_string()),
+            ("test_table2".to_string(), "".to_string()),
+        ]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+
+        let schema1: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
+
+        insert(
+            [
+                vec![
+                    Value::I64(1),
+                    Value::String("Robert Downey Jr".to_string()),
+                    Value::I64(40),
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Tom Holland".to_string()),
+                    Value::I64(20),
+                ],
+            ]
+            .to_vec(),
+            "test_table1".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        let schema2: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("country".to_string(), Column::String(50)),
+        ];
+
+        create_table(&"test_table2".to_string(), &schema2, &new_db, &mut user).unwrap();
+
+        insert(
+            [
+                vec![Value::I64(1), Value::String("United States".to_string())],
+                vec![Value::I64(2), Value::String("Britain".to_string())],
+            ]
+            .to_vec(),
+            "test_table2".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        // Run the SELECT query
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap();
+
+        assert_eq!(result.0[0], "T1.id".to_string());
+        assert_eq!(result.0[1], "country".to_string());
+
+        // Assert that 4 rows were returned
+        assert_eq!(result.1.iter().len(), 4);
+
+        // Assert that each row only has 2 columns
+        for row in result.1.clone() {
+            assert_eq!(row.len(), 2);
+        }
+
+        // Assert that the first row is correct
+        assert_eq!(result.1[0][0], Value::I32(1));
+        assert_eq!(result.1[0][1], Value::String("United States".to_string()));
+
+        // Assert that the second row is correct
+        assert_eq!(result.1[1][0], Value::I32(1));
+        assert_eq!(result.1[1][1], Value::String("Britain".to_string()));
+
+        // Assert that the third row is correct
+        assert_eq!(result.1[2][0], Value::I32(2));
+        assert_eq!(result.1[2][1], Value::String("United States".to_string()));
+
+        // Assert that the fourth row is correct
+        assert_eq!(result.1[3][0], Value::I32(2));
+        assert_eq!(result.1[3][1], Value::String("Britain".to_string()));
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_ambigous_select() {
+        // This tests
+        // SELECT id, country FROM select_test_db.test_table1, select_test_db.test_table2;
+        let columns = to_selectitems(vec!["id".to_string(), "country".to_string()]);
+        let tables = vec![
+            ("test_table1".to_string(), "".to_string()),
+            ("test_table2".to_string(), "".to_string()),
+        ]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+
+        let schema1: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
+
+        insert(
+            [
+                vec![
+                    Value::I64(1),
+                    Value::String("Robert Downey Jr.".to_string()),
+                    Value::I64(40),
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Tom Holland".to_string()),
+                    Value::I64(20),
+                ],
+            ]
+            .to_vec(),
+            "test_table1".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        let schema2: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("country".to_string(), Column::String(50)),
+        ];
+
+        create_table(&"test_table2".to_string(), &schema2, &new_db, &mut user).unwrap();
+
+        insert(
+            [
+                vec![Value::I64(5), Value::String("United States".to_string())],
+                vec![Value::I64(6), Value::String("Britain".to_string())],
+            ]
+            .to_vec(),
+            "test_table2".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        // Run the SELECT query
+        let user: User = User::new("test_user".to_string());
+        let _ = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap_err();
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_invalid_column_select() {
+        // This tests
+        // SELECT id, name, age, invalid_column FROM select_test_db.test_table1;
+
+        let columns = to_selectitems(vec![
+            "id".to_string(),
+            "name".to_string(),
+            "age".to_string(),
+            "invalid_column".to_string(),
+        ]);
+        let tables = vec![("test_table1".to_string(), "".to_string())]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+
+        let schema1: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
+
+        insert(
+            [
+                vec![
+                    Value::I64(1),
+                    Value::String("Robert Downey Jr.".to_string()),
+                    Value::I64(40),
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Tom Holland".to_string()),
+                    Value::I64(20),
+                ],
+            ]
+            .to_vec(),
+            "test_table1".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        // Run the SELECT query
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        );
+
+        // Verify that SELECT failed
+        assert!(result.is_err());
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_invalid_table_select() {
+        // This tests
+        // SELECT id, name, age FROM select_test_db.test_table1, select_test_db.test_table2;
+
+        let columns = to_selectitems(vec![
+            "id".to_string(),
+            "name".to_string(),
+            "age".to_string(),
+        ]);
+        let tables = vec![
+            ("test_table1".to_string(), "".to_string()),
+            ("test_table2".to_string(), "".to_string()),
+        ]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+
+        let schema1: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
+
+        insert(
+            [
+                vec![
+                    Value::I64(1),
+                    Value::String("Robert Downey Jr.".to_string()),
+                    Value::I64(40),
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Tom Holland".to_string()),
+                    Value::I64(20),
+                ],
+            ]
+            .to_vec(),
+            "test_table1".to_string(),
+            &new_db,
+            &mut user,
+        )
+        .unwrap();
+
+        // Run the SELECT query
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        );
+
+        // Verify that SELECT failed
+        assert!(result.is_err());
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_insert_columns() {
+        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
+        let columns = to_selectitems(vec!["T.id".to_string(), "T.name".to_string()]);
+        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
+
+        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        let rows = vec![
+            vec![
+                Value::I32(1),
+                Value::String("Iron Man".to_string()),
+                Value::I32(40),
+            ],
+            vec![
+                Value::I32(2),
+                Value::String("Spiderman".to_string()),
+                Value::I32(20),
+            ],
+            vec![
+                Value::I32(3),
+                Value::String("Doctor Strange".to_string()),
+                Value::I32(35),
+            ],
+            vec![
+                Value::I32(4),
+                Value::String("Captain America".to_string()),
+                Value::I32(100),
+            ],
+            vec![
+                Value::I32(5),
+                Value::String("Thor".to_string()),
+                Value::I32(1000),
+            ],
+        ];
+        let (_, diff) =
+            insert(rows.clone(), "test_table1".to_string(), &new_db, &mut user).unwrap();
+
+        // Verify that the insert was successful by looking at the diff first
+        assert_eq!(diff.rows.len(), 5);
+        assert_eq!(diff.schema, schema);
+        assert_eq!(diff.table_name, "test_table1".to_string());
+        assert_eq!(diff.rows[0].row, rows[0]);
+        assert_eq!(diff.rows[1].row, rows[1]);
+        assert_eq!(diff.rows[2].row, rows[2]);
+        assert_eq!(diff.rows[3].row, rows[3]);
+        assert_eq!(diff.rows[4].row, rows[4]);
+
+        // Run the SELECT query and ensure that the result is correct
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap();
+
+        assert_eq!(result.0[0], "T.id".to_string());
+        assert_eq!(result.0[1], "T.name".to_string());
+
+        // Assert that 3 rows were returned
+        assert_eq!(result.1.iter().len(), 5);
+
+        // Assert that each row only has 2 columns
+        for row in result.1.clone() {
+            assert_eq!(row.len(), 2);
+        }
+
+        // Assert that the first row is correct
+        assert_eq!(result.1[0][0], Value::I32(1));
+        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+
+        // Assert that the second row is correct
+        assert_eq!(result.1[1][0], Value::I32(2));
+        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+
+        // Assert that the third row is correct
+        assert_eq!(result.1[2][0], Value::I32(3));
+        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+
+        // Assert that the fourth row is correct
+        assert_eq!(result.1[3][0], Value::I32(4));
+        assert_eq!(result.1[3][1], Value::String("Captain America".to_string()));
+
+        // Assert that the fifth row is correct
+        assert_eq!(result.1[4][0], Value::I32(5));
+        assert_eq!(result.1[4][1], Value::String("Thor".to_string()));
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_invalid_insert() {
+        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+
+        let newrows = vec![
+            vec![
+                Value::I64(1),
+                Value::String("Iron Man".to_string()),
+                Value::I64(40),
+            ],
+            vec![
+                Value::I64(2),
+                Value::String("Spiderman".to_string()),
+                Value::I64(20),
+            ],
+            vec![Value::I64(3), Value::I64(35)],
+            vec![
+                Value::I64(4),
+                Value::String("Captain America".to_string()),
+                Value::I64(35),
+            ],
+        ];
+
+        assert!(insert(newrows, "test_table1".to_string(), &new_db, &mut user).is_err());
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_read_permissions() {
+        let new_db: Database = Database::new("read_perm_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+
+        // Setting user permissions to Read
+        user.set_permissions(&Read);
+
+        let rows = vec![
+            vec![
+                Value::I32(100), // Can only insert I32
+                Value::String("Iron Man".to_string()),
+                Value::Double(3.456),
+            ],
+            vec![
+                Value::I32(2),
+                Value::String("Spiderman".to_string()),
+                Value::Double(3.43456),
+            ],
+        ];
+
+        assert!(insert(rows, "test_table1".to_string(), &new_db, &mut user).is_err());
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_permission() {
+        let columns = to_selectitems(vec!["T.id".to_string(), "T.name".to_string()]);
+        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
+        let new_db: Database = Database::new("read_perm_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        // Setting user permissions to Read
+        user.set_permissions(&Write);
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        );
+
+        assert!(result.is_err());
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_read_and_write_permissions() {
+        let columns = to_selectitems(vec!["T.id".to_string(), "T.name".to_string()]);
+        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
+        let new_db: Database = Database::new("read_perm_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I32),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+        // Setting user permissions to Read
+        user.set_permissions(&ReadAndWrite);
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        );
+
+        assert!(result.is_ok());
+
+        let rows = vec![
+            vec![
+                Value::I32(100), // Can only insert I32
+                Value::String("Iron Man".to_string()),
+                Value::Double(3.456),
+            ],
+            vec![
+                Value::I32(2),
+                Value::String("Spiderman".to_string()),
+                Value::Double(3.43456),
+            ],
+        ];
+
+        assert!(insert(rows, "test_table1".to_string(), &new_db, &mut user).is_ok());
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that insert can cast values to the correct type if possible
+    fn test_insert_casts() {
+        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
+        let columns = to_selectitems(vec!["T.id".to_string(), "T.name".to_string()]);
+        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::Double),
+        ];
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        let rows = vec![
+            vec![
+                Value::I32(100), // Can only insert I32
+                Value::String("Iron Man".to_string()),
+                Value::Double(3.456),
+            ],
+            vec![
+                Value::I32(2),
+                Value::String("Spiderman".to_string()),
+                Value::Double(3.43456),
+            ],
+            vec![
+                Value::I32(3),
+                Value::String("Doctor Strange".to_string()),
+                Value::Double(322.456),
+            ],
+            vec![
+                Value::I32(4),
+                Value::String("Captain America".to_string()),
+                Value::Double(12.456),
+            ],
+        ];
+
+        let (_, diff) =
+            insert(rows.clone(), "test_table1".to_string(), &new_db, &mut user).unwrap();
+
+        // Verify that the insert was successful by looking at the diff first
+        assert_eq!(diff.rows.len(), 4);
+        assert_eq!(diff.schema, schema);
+        assert_eq!(diff.table_name, "test_table1".to_string());
+        assert_eq!(diff.rows[0].row, rows[0]);
+        assert_eq!(diff.rows[1].row, rows[1]);
+        assert_eq!(diff.rows[2].row, rows[2]);
+        assert_eq!(diff.rows[3].row, rows[3]);
+
+        // Run the SELECT query and ensure that the result is correct
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap();
+
+        assert_eq!(result.0[0], "T.id".to_string());
+        assert_eq!(result.0[1], "T.name".to_string());
+
+        // Assert that 3 rows were returned
+        assert_eq!(result.1.iter().len(), 4);
+
+        // Assert that each row only has 2 columns
+        for row in result.1.clone() {
+            assert_eq!(row.len(), 2);
+        }
+
+        // Assert that the first row is correct
+        assert_eq!(result.1[0][0], Value::I32(100)); // Casted from I64
+        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+
+        // Assert that the second row is correct
+        assert_eq!(result.1[1][0], Value::I32(2));
+        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+
+        // Assert that the third row is correct
+        assert_eq!(result.1[2][0], Value::I32(3));
+        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+
+        // Assert that the fourth row is correct
+        assert_eq!(result.1[3][0], Value::I32(4));
+        assert_eq!(result.1[3][1], Value::String("Captain America".to_string()));
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that insert exits if a value cannot be casted
+    fn test_insert_invalid_casts() {
+        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::Double),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        let rows = vec![
+            vec![
+                Value::I32(100), // Can only insert I32
+                Value::String("Iron Man".to_string()),
+                Value::String("Robert Downey".to_string()),
+            ],
+            vec![
+                Value::I32(2),
+                Value::String("Spiderman".to_string()),
+                Value::Double(3.43456),
+            ],
+            vec![
+                Value::I32(3),
+                Value::String("Doctor Strange".to_string()),
+                Value::Double(322.456),
+            ],
+            vec![
+                Value::I64(4),
+                Value::String("Captain America".to_string()),
+                Value::Float(12.456),
+            ],
+        ];
+
+        assert!(insert(rows, "test_table1".to_string(), &new_db, &mut user).is_err());
+
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that insert can cast values to the correct type if possible
+    fn test_insert_nulls() {
+        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
+        let columns = to_selectitems(vec!["id".to_string(), "name".to_string()]);
+        let tables = vec![("test_table1".to_string(), "".to_string())]; // [(table_name, alias)]
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::Nullable(Box::new(Column::I32))),
+            ("name".to_string(), Column::String(50)),
+            (
+                "age".to_string(),
+                Column::Nullable(Box::new(Column::Double)),
+            ),
+        ];
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        let rows = vec![
+            vec![
+                Value::I32(100), // Can only insert I32
+                Value::String("Iron Man".to_string()),
+                Value::Double(3.456),
+            ],
+            vec![
+                Value::Null(Column::I32),
+                Value::String("Spiderman".to_string()),
+                Value::Double(3.43456),
+            ],
+            vec![
+                Value::I32(3),
+                Value::String("Doctor Strange".to_string()),
+                Value::Null(Column::Double),
+            ],
+            vec![
+                Value::Null(Column::I32),
+                Value::String("Captain America".to_string()),
+                Value::Null(Column::Double),
+            ],
+        ];
+        let new_rows = vec![
+            vec![
+                Value::I32(100),
+                Value::String("Iron Man".to_string()),
+                Value::Double(3.456),
+            ],
+            vec![
+                Value::Null(Column::I32),
+                Value::String("Spiderman".to_string()),
+                Value::Double(3.43456),
+            ],
+            vec![
+                Value::I32(3),
+                Value::String("Doctor Strange".to_string()),
+                Value::Null(Column::Double),
+            ],
+            vec![
+                Value::Null(Column::I32),
+                Value::String("Captain America".to_string()),
+                Value::Null(Column::Double),
+            ],
+        ];
+
+        let (_, diff) = insert(new_rows, "test_table1".to_string(), &new_db, &mut user).unwrap();
+
+        // Verify that the insert was successful by looking at the diff first
+        assert_eq!(diff.rows.len(), 4);
+        assert_eq!(diff.schema, schema);
+        assert_eq!(diff.table_name, "test_table1".to_string());
+        assert_eq!(diff.rows[0].row, rows[0]);
+        assert_eq!(diff.rows[1].row, rows[1]);
+        assert_eq!(diff.rows[2].row, rows[2]);
+        assert_eq!(diff.rows[3].row, rows[3]);
+
+        // Run the SELECT query and ensure that the result is correct
+        let user: User = User::new("test_user".to_string());
+        let result = select(
+            columns.to_owned(),
+            None,
+            vec![],
+            vec![],
+            &tables,
+            &new_db,
+            &user,
+        )
+        .unwrap();
+
+        assert_eq!(result.0[0], "id".to_string());
+        assert_eq!(result.0[1], "name".to_string());
+
+        // Assert that 3 rows were returned
+        assert_eq!(result.1.iter().len(), 4);
+
+        // Assert that each row only has 2 columns
+        for row in result.1.clone() {
+            assert_eq!(row.len(), 2);
+        }
+
+        // Assert that the first row is correct
+        assert_eq!(result.1[0][0], Value::I32(100)); // Casted from I64
+        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+
+        // Assert that the second row is correct
+        assert_eq!(result.1[1][0], Value::Null(Column::I32));
+        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+
+        // Assert that the third row is correct
+        assert_eq!(result.1[2][0], Value::I32(3));
+        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+
+        // Assert that the fourth row is correct
+        assert_eq!(result.1[3][0], Value::Null(Column::I32));
+        assert_eq!(result.1[3][1], Value::String("Captain America".to_string()));
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that DEFAULT inserts the column's default value
+    fn test_insert_default() {
+        let mut user = create_demo_db("insert_default");
+        execute_update(
+            &parse(
+                "CREATE TABLE default_table (id INT, name VARCHAR(20) NULL)",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        // DEFAULT in a nullable column inserts NULL
+        execute_update(
+            &parse(
+                "INSERT INTO default_table VALUES (1, DEFAULT), (2, 'x')",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let (_, results) = execute_query(
+            &parse("SELECT id, name FROM default_table ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I64(1), Value::Null(Column::String(20))],
+                vec![Value::I64(2), Value::String("x".to_string())],
+            ]
+        );
+
+        // A column that isn't nullable has no default
+        let result = execute_update(
+            &parse("INSERT INTO default_table VALUES (DEFAULT, 'y')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert!(result.is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that a VALUES list with a row of the wrong width is rejected before anything is inserted
+    fn test_insert_mismatched_row_width() {
+        fcreate_db_instance("insert_mismatched_row_width");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse("CREATE TABLE pairs (id INT, name VARCHAR(20))", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        let result = execute_update(
+            &parse("INSERT INTO pairs VALUES (1, 'a'), (2), (3, 'c')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert_eq!(
+            result.unwrap_err().message,
+            "Row 2 of VALUES has 1 values, but 2 columns were expected".to_string()
+        );
+
+        // The width is checked against the column list when one is given
+        let result = execute_update(
+            &parse("INSERT INTO pairs (id) VALUES (1, 'a')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert!(result.unwrap_err().message.starts_with("Row 1 of VALUES"));
+
+        let (_, results) = execute_query(
+            &parse("SELECT * FROM pairs", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(results.is_empty());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that ALTER TABLE can add indexes, and that a unique index rejects duplicate keys
+    fn test_alter_table_add_unique_index() {
+        fcreate_db_instance("alter_table_add_unique_index");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse("CREATE TABLE people (id INT, name VARCHAR(20))", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        execute_update(
+            &parse(
+                "INSERT INTO people VALUES (1, 'a'), (2, 'b'), (2, 'c')",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        // The existing rows already have a duplicate id
+        let result = execute_update(
+            &parse("ALTER TABLE people ADD UNIQUE (id)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert!(result
+            .unwrap_err()
+            .message
+            .starts_with("Cannot create unique index"));
+
+        execute_update(
+            &parse("ALTER TABLE people ADD INDEX people_id (id)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        execute_update(
+            &parse(
+                "ALTER TABLE people ADD UNIQUE INDEX people_name (name)",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let table: Table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"people".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(table.indexes.len(), 2);
+        assert_eq!(table.unique_indexes, HashSet::from([vec![1u8]]));
+
+        // A duplicate against the table, or within the inserted rows, is rejected
+        let result = execute_update(
+            &parse("INSERT INTO people VALUES (3, 'a')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert!(result.unwrap_err().message.starts_with("Duplicate key"));
+        let result = execute_update(
+            &parse("INSERT INTO people VALUES (3, 'd'), (4, 'd')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert!(result.unwrap_err().message.starts_with("Duplicate key"));
+        let result = execute_update(
+            &parse("UPDATE people SET name = 'a' WHERE id = 2", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert!(result.unwrap_err().message.starts_with("Duplicate key"));
+
+        // Rewriting a row with its own key is fine, as is a new key
+        execute_update(
+            &parse("UPDATE people SET id = 5 WHERE name = 'a'", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        execute_update(
+            &parse("INSERT INTO people VALUES (3, 'd')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        let (_, results) = execute_query(
+            &parse("SELECT id FROM people ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I32(2)],
+                vec![Value::I32(2)],
+                vec![Value::I32(3)],
+                vec![Value::I32(5)],
+            ]
+        );
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that a read which started before another session's commit keeps seeing the old rows
+    fn test_read_snapshot_during_commit() {
+        fcreate_db_instance("read_snapshot");
+        let mut writer: User = User::new("writer".to_string());
+        for update in [
+            "CREATE TABLE people (id INT)",
+            "INSERT INTO people VALUES (1), (2)",
+        ] {
+            execute_update(&parse(update, true).unwrap(), &mut writer, &"".to_string()).unwrap();
+        }
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Initial".to_string(), &"".to_string(), &mut writer, None)
+            .unwrap();
+
+        let mut reader: User = User::new("reader".to_string());
+        let run = |user: &mut User| -> Vec<Row> {
+            let query: &str = "SELECT id FROM people ORDER BY id";
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let ids =
+            |ids: &[i64]| -> Vec<Row> { ids.iter().map(|id| vec![Value::I64(*id)]).collect() };
+
+        // Start a read the way a query does, so it stays open while the other session commits
+        let snapshot: Option<ReadSnapshot> = get_db_instance().unwrap().pin_read_snapshot(&reader);
+        assert!(snapshot.is_some());
+        assert_eq!(run(&mut reader), ids(&[1, 2]));
+
+        let commit_thread = std::thread::spawn(move || {
+            for update in [
+                "UPDATE people SET id = 10 WHERE id = 1",
+                "INSERT INTO people VALUES (3)",
+            ] {
+                execute_update(&parse(update, true).unwrap(), &mut writer, &"".to_string())
+                    .unwrap();
+            }
+            get_db_instance()
+                .unwrap()
+                .create_commit_on_head(&"Change".to_string(), &"".to_string(), &mut writer, None)
+                .unwrap();
+        });
+        commit_thread.join().unwrap();
+
+        // The open read still sees the branch as it was when it started
+        assert_eq!(run(&mut reader), ids(&[1, 2]));
+        let branch_path: String = get_db_instance().unwrap().get_current_branch_path(&reader);
+        let snapshot_files = || -> Vec<String> {
+            let db_dir: String = std::path::Path::new(&branch_path)
+                .parent()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            get_storage_backend(&db_dir)
+                .read_dir(&db_dir)
+                .unwrap()
+                .into_iter()
+                .filter(|file| file.contains(crate::fileio::snapshot::SNAPSHOT_FILE_SUFFIX))
+                .collect()
+        };
+        // Only the table that the commit changed was copied
+        assert_eq!(snapshot_files().len(), 1);
+
+        // Once it's done, new reads see the commit, and the copy of the table is removed
+        drop(snapshot);
+        assert_eq!(run(&mut reader), ids(&[2, 3, 10]));
+        assert_eq!(snapshot_files().len(), 0);
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that a commit only copies the tables it changes for the reads that are open
+    fn test_read_snapshot_copies_changed_tables() {
+        fcreate_db_instance("read_snapshot_copies");
+        let mut writer: User = User::new("writer".to_string());
+        for update in [
+            "CREATE TABLE people (id INT)",
+            "CREATE TABLE pets (id INT)",
+            "CREATE TABLE places (id INT)",
+            "INSERT INTO people VALUES (1)",
+            "INSERT INTO pets VALUES (1)",
+            "INSERT INTO places VALUES (1)",
+        ] {
+            execute_update(&parse(update, true).unwrap(), &mut writer, &"".to_string()).unwrap();
+        }
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Initial".to_string(), &"".to_string(), &mut writer, None)
+            .unwrap();
 
-    pub fn to_selectitems(names: Vec<String>) -> Vec<SelectItem> {
-        names
+        let mut reader: User = User::new("reader".to_string());
+        let run = |table: &str, user: &mut User| -> Vec<Row> {
+            let query: String = format!("SELECT id FROM {} ORDER BY id", table);
+            execute_query(&parse(&query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let ids =
+            |ids: &[i64]| -> Vec<Row> { ids.iter().map(|id| vec![Value::I64(*id)]).collect() };
+        let read_table = |table: &str, user: &User| -> Vec<u8> {
+            let path: String = get_db_instance()
+                .unwrap()
+                .get_table_path(&table.to_string(), user)
+                .unwrap();
+            get_storage_backend(&path).read_file(&path).unwrap()
+        };
+        let old_tables: Vec<Vec<u8>> =
+            vec![read_table("pets", &reader), read_table("places", &reader)];
+
+        let snapshot: Option<ReadSnapshot> = get_db_instance().unwrap().pin_read_snapshot(&reader);
+        assert!(snapshot.is_some());
+        let commit_thread = std::thread::spawn(move || {
+            for update in [
+                "INSERT INTO pets VALUES (2)",
+                "DELETE FROM places WHERE id = 1",
+            ] {
+                execute_update(&parse(update, true).unwrap(), &mut writer, &"".to_string())
+                    .unwrap();
+            }
+            get_db_instance()
+                .unwrap()
+                .create_commit_on_head(&"Change".to_string(), &"".to_string(), &mut writer, None)
+                .unwrap();
+        });
+        commit_thread.join().unwrap();
+
+        // The two tables that the commit changed were copied as they were, and the other one wasn't
+        let branch_path: String = get_db_instance().unwrap().get_current_branch_path(&reader);
+        let db_dir: String = std::path::Path::new(&branch_path)
+            .parent()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let mut copies: Vec<Vec<u8>> = get_storage_backend(&db_dir)
+            .read_dir(&db_dir)
+            .unwrap()
             .into_iter()
-            .map(|name| SelectItem::UnnamedExpr(to_ident(name)))
-            .collect()
+            .filter(|file| file.contains(crate::fileio::snapshot::SNAPSHOT_FILE_SUFFIX))
+            .map(|file| get_storage_backend(&file).read_file(&file).unwrap())
+            .collect();
+        copies.sort();
+        let mut expected: Vec<Vec<u8>> = old_tables;
+        expected.sort();
+        assert_eq!(copies, expected);
+
+        // The open read sees the changed tables through their copies
+        assert_eq!(run("people", &mut reader), ids(&[1]));
+        assert_eq!(run("pets", &mut reader), ids(&[1]));
+        assert_eq!(run("places", &mut reader), ids(&[1]));
+        drop(snapshot);
+        assert_eq!(run("pets", &mut reader), ids(&[1, 2]));
+        assert_eq!(run("places", &mut reader), ids(&[]));
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_select_single_table_star() {
-        // This tests
-        // SELECT * FROM select_test_db.test_table1
-        let columns = vec![SelectItem::Wildcard];
-        let tables = vec![("test_table1".to_string(), "".to_string())]; // [(table_name, alias)]
+    // Ensures that an UPDATE or DELETE run as a query only returns the rows it would change
+    fn test_preview_write() {
+        fcreate_db_instance("preview_write");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, name VARCHAR(10))",
+            "INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'b')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let preview = |query: &str, user: &mut User| -> (Vec<String>, Vec<Row>) {
+            let (names, _, rows) =
+                execute_query_with_types(&parse(query, false).unwrap(), user, &"".to_string())
+                    .unwrap();
+            (names, rows)
+        };
+        let row =
+            |id: i64, name: &str| -> Row { vec![Value::I64(id), Value::String(name.to_string())] };
+        let all_rows =
+            |user: &mut User| -> Vec<Row> { preview("SELECT * FROM t ORDER BY id", user).1 };
+        let old_rows: Vec<Row> = all_rows(&mut user);
+        let num_diffs: usize = user.get_diffs().len();
+
+        // The rows are returned as they are now
+        let (names, rows) = preview("UPDATE t SET name = 'z' WHERE name = 'b'", &mut user);
+        assert_eq!(names, vec!["id".to_string(), "name".to_string()]);
+        assert_eq!(rows, vec![row(2, "b"), row(4, "b")]);
+
+        // Rows that the update wouldn't change aren't returned
+        let (_, rows) = preview("UPDATE t SET name = 'a' WHERE id <= 2", &mut user);
+        assert_eq!(rows, vec![row(2, "b")]);
+
+        let (_, rows) = preview("DELETE FROM t WHERE id > 2", &mut user);
+        assert_eq!(rows, vec![row(3, "c"), row(4, "b")]);
+
+        // Nothing was changed, or added to the user's diffs
+        assert_eq!(all_rows(&mut user), old_rows);
+        assert_eq!(user.get_diffs().len(), num_diffs);
+
+        // The same writes still change the table when they're run as updates
+        execute_update(
+            &parse("DELETE FROM t WHERE id > 2", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(all_rows(&mut user), vec![row(1, "a"), row(2, "b")]);
+        delete_db_instance().unwrap();
+    }
 
-        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+    #[test]
+    #[serial]
+    // Ensures that USE BRANCH points a session's reads at another branch
+    fn test_use_branch() {
+        fcreate_db_instance("use_branch");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse("CREATE TABLE people (id INT)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        execute_update(
+            &parse("INSERT INTO people VALUES (1)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Initial".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_branch(&"feature-1".to_string(), &None, &mut user)
+            .unwrap();
+        execute_update(
+            &parse("INSERT INTO people VALUES (2)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Feature".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
 
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
+        // A new session starts out reading main
+        let mut analyst: User = User::new("analyst".to_string());
+        analyst.set_permissions(&Read);
+        let query: &str = "SELECT id FROM people ORDER BY id";
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut analyst, &"".to_string()).unwrap();
+        assert_eq!(results, vec![vec![Value::I32(1)]]);
 
-        // Create a new user on the main branch
+        let result = execute_update(
+            &parse("USE BRANCH missing", true).unwrap(),
+            &mut analyst,
+            &"".to_string(),
+        );
+        assert!(result.is_err());
+
+        execute_update(
+            &parse("USE BRANCH feature-1", true).unwrap(),
+            &mut analyst,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(analyst.get_current_branch_name(), "feature-1".to_string());
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut analyst, &"".to_string()).unwrap();
+        assert_eq!(results, vec![vec![Value::I32(1)], vec![Value::I32(2)]]);
+
+        // Using a branch doesn't let a read only session write to it
+        let result = execute_update(
+            &parse("INSERT INTO people VALUES (3)", true).unwrap(),
+            &mut analyst,
+            &"".to_string(),
+        );
+        assert!(result.is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that a table can be read from another branch by qualifying it with the branch name
+    fn test_query_across_branches() {
+        fcreate_db_instance("query_across_branches");
         let mut user: User = User::new("test_user".to_string());
+        let update = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string())
+        };
+        let select = |query: &str, user: &mut User| {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+        };
+        update("CREATE TABLE people (id INT, name VARCHAR(10))", &mut user).unwrap();
+        update(
+            "INSERT INTO people VALUES (1, 'Ann'), (2, 'Bob')",
+            &mut user,
+        )
+        .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Initial".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_branch(&"feature".to_string(), &None, &mut user)
+            .unwrap();
+        update("INSERT INTO people VALUES (3, 'Cal')", &mut user).unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Feature".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
 
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        // A session on main can read the feature branch's copy of the table
+        let mut analyst: User = User::new("analyst".to_string());
+        analyst.set_permissions(&Read);
+        let query: &str = "SELECT * FROM main.people UNION SELECT * FROM feature.people";
+        let (_, mut results) = select(query, &mut analyst).unwrap();
+        results.sort_by_key(|row| format!("{:?}", row));
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I32(1), Value::String("Ann".to_string())],
+                vec![Value::I32(2), Value::String("Bob".to_string())],
+                vec![Value::I32(3), Value::String("Cal".to_string())],
+            ]
+        );
 
-        insert(
-            [
-                vec![
-                    Value::I64(1),
-                    Value::String("Iron Man".to_string()),
-                    Value::I64(40),
-                ],
-                vec![
-                    Value::I64(2),
-                    Value::String("Spiderman".to_string()),
-                    Value::I64(20),
-                ],
+        // Without an alias, the columns are qualified by the table name alone
+        let query: &str = "SELECT people.name FROM feature.people WHERE people.id > 2";
+        let (_, results) = select(query, &mut analyst).unwrap();
+        assert_eq!(results, vec![vec![Value::String("Cal".to_string())]]);
+
+        assert!(select("SELECT * FROM missing.people", &mut analyst).is_err());
+        assert!(select("SELECT * FROM feature.missing", &mut analyst).is_err());
+
+        // A session that can only write can't read the other branch either
+        analyst.set_permissions(&Write);
+        assert!(select("SELECT * FROM feature.people", &mut analyst).is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that tables in one database aren't visible from another
+    fn test_use_database() {
+        fcreate_db_instance("use_database_default");
+        let mut user: User = User::new("test_user".to_string());
+        let mut other_user: User = User::new("other_user".to_string());
+        let update = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string())
+        };
+        let select = |query: &str, user: &mut User| {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+        };
+        update("CREATE TABLE people (id INT)", &mut user).unwrap();
+        update("INSERT INTO people VALUES (1)", &mut user).unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Initial".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
+
+        assert_eq!(
+            update("CREATE DATABASE use_database_other", &mut user).unwrap(),
+            "Database created: use_database_other".to_string()
+        );
+        assert!(update("CREATE DATABASE use_database_other", &mut user).is_err());
+        assert!(update(
+            "CREATE DATABASE IF NOT EXISTS use_database_other",
+            &mut user
+        )
+        .is_ok());
+        assert!(update("USE DATABASE missing", &mut user).is_err());
+        update("USE DATABASE use_database_other", &mut user).unwrap();
+        assert_eq!(
+            user.get_database_name(),
+            Some("use_database_other".to_string())
+        );
+
+        // The tables of the default database can't be seen from the other one, and the other way around
+        assert!(select("SELECT id FROM people", &mut user).is_err());
+        update("CREATE TABLE orders (id INT)", &mut user).unwrap();
+        update("INSERT INTO orders VALUES (7)", &mut user).unwrap();
+        let (_, results) = select("SELECT id FROM orders", &mut user).unwrap();
+        assert_eq!(results, vec![vec![Value::I64(7)]]);
+        let (_, results) = select("SELECT id FROM people", &mut other_user).unwrap();
+        assert_eq!(results, vec![vec![Value::I64(1)]]);
+        assert!(select("SELECT id FROM orders", &mut other_user).is_err());
+
+        // Uncommitted changes are kept in the database they were made in
+        assert!(update("USE DATABASE use_database_default", &mut user).is_err());
+        {
+            let _database: DatabaseSelection = select_database(user.get_database_name());
+            get_db_instance()
+                .unwrap()
+                .create_commit_on_head(&"Orders".to_string(), &"".to_string(), &mut user, None)
+                .unwrap();
+        }
+        update("USE DATABASE use_database_default", &mut user).unwrap();
+        assert_eq!(user.get_database_name(), None);
+        let (_, results) = select("SELECT id FROM people", &mut user).unwrap();
+        assert_eq!(results, vec![vec![Value::I64(1)]]);
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that SHOW DATABASES lists every database in the catalog, opened or not
+    fn test_show_databases() {
+        fcreate_db_instance("show_databases_default");
+        let mut user: User = User::new("test_user".to_string());
+        let update = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string())
+        };
+        // The name and whether it's open, for each database in the catalog
+        let show = |user: &mut User| -> Vec<(String, bool)> {
+            let (columns, rows) = execute_query(
+                &parse("SHOW DATABASES", false).unwrap(),
+                user,
+                &"".to_string(),
+            )
+            .unwrap();
+            assert_eq!(columns, vec!["name", "main_branch", "created", "open"]);
+            rows.into_iter()
+                .filter_map(|row| match (&row[0], &row[3]) {
+                    (Value::String(name), Value::Bool(open))
+                        if name.starts_with("show_databases") =>
+                    {
+                        Some((name.clone(), *open))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        update("CREATE DATABASE show_databases_other", &mut user).unwrap();
+        // Created without being opened by the server, like one left from an earlier run
+        Database::new_with_main_branch("show_databases_closed".to_string(), "trunk".to_string())
+            .unwrap();
+        assert_eq!(
+            show(&mut user),
+            vec![
+                ("show_databases_closed".to_string(), false),
+                ("show_databases_default".to_string(), true),
+                ("show_databases_other".to_string(), true),
             ]
-            .to_vec(),
-            "test_table1".to_string(),
-            &new_db,
+        );
+        let (_, rows) = execute_query(
+            &parse("SHOW DATABASES", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let closed: &Row = rows
+            .iter()
+            .find(|row| row[0] == Value::String("show_databases_closed".to_string()))
+            .unwrap();
+        assert_eq!(closed[1], Value::String("trunk".to_string()));
+
+        // Using a database opens it, and it lists the same databases
+        update("USE DATABASE show_databases_closed", &mut user).unwrap();
+        assert_eq!(user.get_current_branch_name(), "trunk".to_string());
+        assert_eq!(
+            show(&mut user),
+            vec![
+                ("show_databases_closed".to_string(), true),
+                ("show_databases_default".to_string(), true),
+                ("show_databases_other".to_string(), true),
+            ]
+        );
+
+        // Deleted databases are taken out of the catalog
+        delete_db_instance().unwrap();
+        fcreate_db_instance("show_databases_default");
+        let mut user: User = User::new("test_user".to_string());
+        assert_eq!(
+            show(&mut user),
+            vec![("show_databases_default".to_string(), true)]
+        );
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that preferring index scans uses an index that only fits part of the where clause
+    fn test_prefer_index_scans() {
+        fcreate_db_instance("prefer_index_scans");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(20))",
+            "INSERT INTO people VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Cal'), (2, 'Dee')",
+            "CREATE INDEX people_id ON people (id)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let query: &str = "SELECT name FROM people WHERE id = 2 AND name != 'Bob'";
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let scan = vec![Value::String("Scan table people as people".to_string())];
+        let index_scan = vec![Value::String(
+            "Index scan on table people as people using index people_id".to_string(),
+        )];
+
+        // The where clause is on more columns than the index, so it isn't used by default
+        let expected: Vec<Row> = vec![vec![Value::String("Dee".to_string())]];
+        assert_eq!(run(query, &mut user), expected);
+        let plan: Vec<Row> = run(&format!("EXPLAIN {}", query), &mut user);
+        assert!(plan.contains(&scan) && !plan.contains(&index_scan));
+
+        execute_update(
+            &parse("SET prefer_index_scans = on", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(run(query, &mut user), expected);
+        let plan: Vec<Row> = run(&format!("EXPLAIN {}", query), &mut user);
+        assert!(plan.contains(&index_scan) && !plan.contains(&scan));
+
+        // Updates and deletes still check the whole where clause on the rows the index finds
+        execute_update(
+            &parse(
+                "UPDATE people SET name = 'Eve' WHERE id = 2 AND name = 'Dee'",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        execute_update(
+            &parse("DELETE FROM people WHERE id = 2 AND name = 'Bob'", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            run("SELECT id, name FROM people ORDER BY id", &mut user),
+            vec![
+                vec![Value::I32(1), Value::String("Ann".to_string())],
+                vec![Value::I32(2), Value::String("Eve".to_string())],
+                vec![Value::I32(3), Value::String("Cal".to_string())],
+            ]
+        );
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that parentheses around expressions and conditions don't change how they resolve
+    fn test_parenthesized_expressions() {
+        fcreate_db_instance("parenthesized_expressions");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (a INT, b INT, c INT)",
+            "CREATE TABLE u (a INT, d INT)",
+            "INSERT INTO t VALUES (1, 2, 3), (2, 3, 4), (3, 4, (5))",
+            "INSERT INTO u VALUES (1, 10), (3, 30)",
+            "UPDATE t SET c = (c + 1) * 2 WHERE (a = 3)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let column = |values: Vec<i64>| -> Vec<Row> {
+            values.into_iter().map(|x| vec![Value::I64(x)]).collect()
+        };
+
+        // Nested arithmetic
+        assert_eq!(
+            run("SELECT (a + b) * c FROM t ORDER BY a", &mut user),
+            column(vec![9, 20, 84])
+        );
+        assert_eq!(
+            run("SELECT ((a)) - (b - (c)) FROM t ORDER BY a", &mut user),
+            column(vec![2, 3, 11])
+        );
+
+        // Nested conditions, in WHERE, joins and correlated subqueries
+        let conditions: Vec<(&str, Vec<i64>)> = vec![
+            ("SELECT a FROM t WHERE (a = 1)", vec![1]),
+            (
+                "SELECT a FROM t WHERE ((a = 1) OR (b = 4)) AND (c > 0) ORDER BY a",
+                vec![1, 3],
+            ),
+            (
+                "SELECT a FROM t WHERE ((a) IN (1, 3)) ORDER BY a",
+                vec![1, 3],
+            ),
+            (
+                "SELECT t.a FROM t JOIN u ON (t.a = u.a) ORDER BY t.a",
+                vec![1, 3],
+            ),
+            (
+                "SELECT t.a FROM t WHERE EXISTS \
+                (SELECT * FROM u WHERE ((u.a) = (t.a)) AND (u.d > 20))",
+                vec![3],
+            ),
+        ];
+        for (query, expected) in conditions.iter() {
+            assert_eq!(run(query, &mut user), column(expected.clone()), "{}", query);
+        }
+
+        // Aliases in parentheses still refer to the selected column
+        assert_eq!(
+            run("SELECT (a + 1) AS z FROM t ORDER BY ((z)) DESC", &mut user),
+            column(vec![4, 3, 2])
+        );
+
+        // The same rows are found when the conditions can use an index
+        execute_update(
+            &parse("CREATE INDEX a_idx ON t (a)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        user.set_prefer_index_scans(true);
+        for (query, expected) in conditions {
+            assert_eq!(run(query, &mut user), column(expected), "{}", query);
+        }
+        assert_eq!(
+            run(
+                "SELECT a FROM t WHERE ((a > 1) AND (a < (1 + 2)))",
+                &mut user
+            ),
+            column(vec![2])
+        );
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that negative literals and negated expressions work in inserts, filters and selects
+    fn test_negative_literals() {
+        fcreate_db_instance("negative_literals");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE readings (x INT, y DOUBLE)",
+            "INSERT INTO readings VALUES (-5, -2.5), (3, +1.5), (-1, -(1.5 + 1)), (0, 4)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let query: &str = "SELECT x, -y FROM readings WHERE x < -1 AND -x > 4 ORDER BY x";
+        let expected: Vec<Row> = vec![vec![Value::I64(-5), Value::Double(2.5)]];
+        assert_eq!(
+            run("SELECT x, -y FROM readings WHERE x < -1", &mut user),
+            expected
+        );
+        assert_eq!(
+            run("SELECT -(x + 1) FROM readings WHERE y = +1.5", &mut user),
+            vec![vec![Value::I64(-4)]]
+        );
+        assert_eq!(run(query, &mut user), expected);
+
+        // The same rows are found when the filter can use an index
+        execute_update(
+            &parse("CREATE INDEX x_idx ON readings (x)", true).unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(
+            run("SELECT x, -y FROM readings WHERE x < -1", &mut user),
+            expected
+        );
+        assert_eq!(
+            run("SELECT y FROM readings WHERE x = -1", &mut user),
+            vec![vec![Value::Double(-2.5)]]
+        );
+        delete_db_instance().unwrap();
+    }
 
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+    #[test]
+    #[serial]
+    // Ensures that LIKE and ILIKE match patterns, with ILIKE ignoring case
+    fn test_ilike() {
+        fcreate_db_instance("ilike");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(20) NULL)",
+            "INSERT INTO people VALUES (1, 'John'), (2, 'JOANNE'), (3, 'Bob'), (4, 'jo_x'), (5, NULL)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let ids = |query: &str, user: &mut User| -> Vec<i64> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+                .into_iter()
+                .map(|row| match row[0] {
+                    Value::I64(id) => id,
+                    _ => panic!("Expected an id"),
+                })
+                .collect()
+        };
+        let conditions: Vec<(&str, Vec<i64>)> = vec![
+            ("name ILIKE 'jo%'", vec![1, 2, 4]),
+            ("name LIKE 'Jo%'", vec![1]),
+            ("name ILIKE 'J_HN'", vec![1]),
+            ("name ILIKE '%o%n%'", vec![1, 2]),
+            ("name LIKE 'jo!_%' ESCAPE '!'", vec![4]),
+            // Nulls don't match either way
+            ("name NOT ILIKE 'jo%'", vec![3]),
+            ("NOT name ILIKE 'jo%'", vec![3]),
+        ];
+        for (condition, expected) in conditions {
+            let query: String = format!("SELECT id FROM people WHERE {} ORDER BY id", condition);
+            assert_eq!(ids(&query, &mut user), expected, "{}", query);
+        }
+
+        // A pattern match can be selected as a value
+        let (_, rows) = execute_query(
+            &parse(
+                "SELECT name ILIKE 'b%' FROM people WHERE id < 4 ORDER BY id",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
         )
         .unwrap();
-
-        assert_eq!(result.0[0], "id".to_string());
-        assert_eq!(result.0[1], "name".to_string());
-        assert_eq!(result.0[2], "age".to_string());
-
-        // Assert that 2 rows were returned
-        assert_eq!(result.1.iter().len(), 2);
-
-        // Assert that the first row is correct
-        assert_eq!(result.1[0][0], Value::I32(1));
-        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
-        assert_eq!(result.1[0][2], Value::I32(40));
-
-        // Assert that the second row is correct
-        assert_eq!(result.1[1][0], Value::I32(2));
-        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
-        assert_eq!(result.1[1][2], Value::I32(20));
-
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Bool(false)],
+                vec![Value::Bool(false)],
+                vec![Value::Bool(true)]
+            ]
+        );
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_select_multi_table_star() {
-        // This tests:
-        // SELECT * FROM select_test_db.test_table1, select_test_db.test_table2
-        let columns = vec![SelectItem::Wildcard];
-        let tables = vec![
-            ("test_table1".to_string(), "T1".to_string()),
-            ("test_table2".to_string(), "T2".to_string()),
-        ]; // [(table_name, alias)]
-
-        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
-
-        let schema1: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
+    // Ensures that a column name in more than one joined table has to be qualified
+    fn test_ambiguous_column() {
+        fcreate_db_instance("ambiguous_column");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t1 (id INT, name VARCHAR(20))",
+            "CREATE TABLE t2 (id INT, t1_id INT)",
+            "INSERT INTO t1 VALUES (1, 'Ann'), (2, 'Bob')",
+            "INSERT INTO t2 VALUES (10, 1), (20, 2)",
         ];
-        let schema2: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("country".to_string(), Column::String(50)),
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+        };
+        let join: &str = "FROM t1 JOIN t2 ON t1.id = t2.t1_id";
+
+        let ambiguous: Vec<String> = vec![
+            format!("SELECT id {}", join),
+            format!("SELECT t1.name {} WHERE id = 1", join),
+            format!("SELECT t1.name {} ORDER BY id", join),
+            format!("SELECT COUNT(*) {} GROUP BY id", join),
+            "SELECT id FROM t1, t2".to_string(),
         ];
+        for query in ambiguous {
+            let result = run(&query, &mut user);
+            assert_eq!(
+                result,
+                Err("Column name id is ambiguous, it could be any of t1.id, t2.id.".to_string()),
+                "{}",
+                query
+            );
+        }
 
-        // Create a new user on the main branch
+        // Qualified names, and names that only one of the tables has, are fine
+        let (_, rows) = run(&format!("SELECT t1.id {} ORDER BY t1.id", join), &mut user).unwrap();
+        assert_eq!(rows, vec![vec![Value::I64(1)], vec![Value::I64(2)]]);
+        let (_, rows) = run(&format!("SELECT name {} WHERE t2.id = 20", join), &mut user).unwrap();
+        assert_eq!(rows, vec![vec![Value::String("Bob".to_string())]]);
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Ensures that a correlated EXISTS gives the same rows whether or not it can probe an index
+    fn test_exists_semi_join() {
+        fcreate_db_instance("exists_semi_join");
         let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE customers (id INT, name VARCHAR(20))",
+            "CREATE TABLE orders (id INT, customer_id INT, amount INT)",
+            "INSERT INTO customers VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Cal'), (4, 'Dee')",
+            "INSERT INTO orders VALUES (1, 1, 5), (2, 1, 50), (3, 2, 5), (4, 4, 20), (5, 4, 30)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let exists_query: &str = "SELECT c.id FROM customers c WHERE c.id > 0 AND EXISTS \
+            (SELECT * FROM orders o WHERE o.customer_id = c.id AND o.amount > 10) ORDER BY c.id";
+        let not_exists_query: &str = "SELECT c.name FROM customers c WHERE NOT EXISTS \
+            (SELECT * FROM orders WHERE customer_id = c.id) ORDER BY c.name";
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
 
-        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
-        create_table(&"test_table2".to_string(), &schema2, &new_db, &mut user).unwrap();
+        // Without an index, the orders are scanned for every customer
+        let naive_exists: Vec<Row> = run(exists_query, &mut user);
+        let naive_not_exists: Vec<Row> = run(not_exists_query, &mut user);
+        assert_eq!(naive_exists, vec![vec![Value::I64(1)], vec![Value::I64(4)]]);
+        assert_eq!(
+            naive_not_exists,
+            vec![vec![Value::String("Cal".to_string())]]
+        );
+        let plan: Vec<Row> = run(&format!("EXPLAIN {}", exists_query), &mut user);
+        assert!(plan.contains(&vec![Value::String(
+            "Semi-join on table orders as o by scanning it for each row".to_string()
+        )]));
 
-        // Write rows to first table
-        insert(
-            [
-                vec![
-                    Value::I64(1),
-                    Value::String("Robert Downey Jr.".to_string()),
-                    Value::I64(40),
-                ],
-                vec![
-                    Value::I64(2),
-                    Value::String("Tom Holland".to_string()),
-                    Value::I64(20),
-                ],
-            ]
-            .to_vec(),
-            "test_table1".to_string(),
-            &new_db,
+        // With an index, it's probed instead, and the results are the same
+        execute_update(
+            &parse("CREATE INDEX customer_idx ON orders (customer_id)", true).unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
-
-        // Write rows to second table
-        insert(
-            [
-                vec![Value::I64(1), Value::String("United States".to_string())],
-                vec![Value::I64(2), Value::String("Britain".to_string())],
+        assert_eq!(run(exists_query, &mut user), naive_exists);
+        assert_eq!(run(not_exists_query, &mut user), naive_not_exists);
+        let plan: Vec<Row> = run(&format!("EXPLAIN {}", exists_query), &mut user);
+        assert_eq!(
+            plan[..plan.len() - 1].to_vec(),
+            vec![
+                vec![Value::String("Scan table customers as c".to_string())],
+                vec![Value::String("Filter: c.id > 0".to_string())],
+                vec![Value::String(
+                    "Semi-join on table orders as o using index customer_idx".to_string()
+                )],
+                vec![Value::String("Sort by: c.id".to_string())],
             ]
-            .to_vec(),
-            "test_table2".to_string(),
-            &new_db,
-            &mut user,
-        )
-        .unwrap();
+        );
 
-        // Run the SELECT query
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+        // An EXISTS that doesn't refer to the outer query is only run once
+        assert_eq!(
+            run(
+                "SELECT id FROM customers WHERE EXISTS (SELECT * FROM orders WHERE amount > 40)",
+                &mut user
+            )
+            .len(),
+            4
+        );
+        assert!(run(
+            "SELECT id FROM customers WHERE EXISTS (SELECT * FROM orders WHERE amount > 100)",
+            &mut user
         )
-        .unwrap();
-
-        // Check that the schema is correct
-        assert_eq!(result.0[0], "id".to_string());
-        assert_eq!(result.0[1], "name".to_string());
-        assert_eq!(result.0[2], "age".to_string());
-        assert_eq!(result.0[3], "id".to_string());
-        assert_eq!(result.0[4], "country".to_string());
+        .is_empty());
+        delete_db_instance().unwrap();
+    }
 
-        // Check that we returned 4 rows
-        assert_eq!(result.1.iter().len(), 4);
+    #[test]
+    #[serial]
+    // Ensures that IN and NOT IN with a subquery check membership, following SQL's null rules
+    fn test_in_subquery() {
+        fcreate_db_instance("in_subquery");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE customers (id INT, name VARCHAR(20))",
+            "CREATE TABLE orders (id INT, customer_id INT NULL, amount INT)",
+            "INSERT INTO customers VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Cal'), (4, 'Dee')",
+            "INSERT INTO orders VALUES (1, 1, 5), (2, 1, 50), (3, 2, 5), (4, 4, 20)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let names = |names: Vec<&str>| -> Vec<Row> {
+            names
+                .iter()
+                .map(|name| vec![Value::String(name.to_string())])
+                .collect()
+        };
 
-        // Check that the first row is correct
-        assert_eq!(result.1[0][0], Value::I32(1));
         assert_eq!(
-            result.1[0][1],
-            Value::String("Robert Downey Jr.".to_string())
+            run(
+                "SELECT name FROM customers WHERE id IN (SELECT customer_id FROM orders) ORDER BY name",
+                &mut user
+            ),
+            names(vec!["Ann", "Bob", "Dee"])
         );
-        assert_eq!(result.1[0][2], Value::I32(40));
-        assert_eq!(result.1[0][3], Value::I32(1));
-        assert_eq!(result.1[0][4], Value::String("United States".to_string()));
-
-        // Check that the second row is correct
-        assert_eq!(result.1[1][0], Value::I32(1));
         assert_eq!(
-            result.1[1][1],
-            Value::String("Robert Downey Jr.".to_string())
+            run(
+                "SELECT name FROM customers WHERE id NOT IN \
+                (SELECT customer_id FROM orders WHERE amount > 10) ORDER BY name",
+                &mut user
+            ),
+            names(vec!["Bob", "Cal"])
         );
-        assert_eq!(result.1[1][2], Value::I32(40));
-        assert_eq!(result.1[1][3], Value::I32(2));
-        assert_eq!(result.1[1][4], Value::String("Britain".to_string()));
-
-        // Check that the third row is correct
-        assert_eq!(result.1[2][0], Value::I32(2));
-        assert_eq!(result.1[2][1], Value::String("Tom Holland".to_string()));
-        assert_eq!(result.1[2][2], Value::I32(20));
-        assert_eq!(result.1[2][3], Value::I32(1));
-        assert_eq!(result.1[2][4], Value::String("United States".to_string()));
-
-        // Check that the fourth row is correct
-        assert_eq!(result.1[3][0], Value::I32(2));
-        assert_eq!(result.1[3][1], Value::String("Tom Holland".to_string()));
-        assert_eq!(result.1[3][2], Value::I32(20));
-        assert_eq!(result.1[3][3], Value::I32(2));
-        assert_eq!(result.1[3][4], Value::String("Britain".to_string()));
+        assert!(run(
+            "SELECT name FROM customers WHERE id IN (SELECT customer_id FROM orders WHERE amount > 100)",
+            &mut user
+        )
+        .is_empty());
+        assert!(execute_query(
+            &parse(
+                "SELECT name FROM customers WHERE id IN (SELECT customer_id, amount FROM orders)",
+                false
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string()
+        )
+        .is_err());
 
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        // Once the subquery returns a null, NOT IN can't be sure that any row is missing from it
+        execute_update(
+            &parse("INSERT INTO orders VALUES (5, NULL, 30)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(run(
+            "SELECT name FROM customers WHERE id NOT IN (SELECT customer_id FROM orders)",
+            &mut user
+        )
+        .is_empty());
+        assert_eq!(
+            run(
+                "SELECT name FROM customers WHERE id IN (SELECT customer_id FROM orders) ORDER BY name",
+                &mut user
+            ),
+            names(vec!["Ann", "Bob", "Dee"])
+        );
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_select_single_table_specific_columns() {
-        // This tests
-        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
-        let columns = to_selectitems(vec!["T.id".to_string(), "name".to_string()]);
-        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
-
-        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
-
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
-
-        // Create a new user on the main branch
+    // Ensures that TABLESAMPLE keeps roughly the given fraction of rows, and that a seed repeats the sample
+    fn test_table_sample() {
+        fcreate_db_instance("table_sample");
         let mut user: User = User::new("test_user".to_string());
-
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-        insert(
-            vec![
-                vec![
-                    Value::I64(1),
-                    Value::String("Iron Man".to_string()),
-                    Value::I64(40),
-                ],
-                vec![
-                    Value::I64(2),
-                    Value::String("Spiderman".to_string()),
-                    Value::I64(20),
-                ],
-                vec![
-                    Value::I64(3),
-                    Value::String("Doctor Strange".to_string()),
-                    Value::I64(35),
-                ],
-            ],
-            "test_table1".to_string(),
-            &new_db,
+        execute_update(
+            &parse("CREATE TABLE numbers (id INT)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let values: Vec<String> = (0..1000).map(|i| format!("({})", i)).collect();
+        execute_update(
+            &parse(
+                &format!("INSERT INTO numbers VALUES {}", values.join(", ")),
+                true,
+            )
+            .unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
-
-        // Run the SELECT query
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+        execute_update(
+            &parse("SET sample_seed = 42", true).unwrap(),
+            &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(user.get_sample_seed(), Some(42));
 
-        assert_eq!(result.0[0], "T.id".to_string());
-        assert_eq!(result.0[1], "name".to_string());
-
-        // Assert that 3 rows were returned
-        assert_eq!(result.1.iter().len(), 3);
-
-        // Assert that each row only has 2 columns
-        for row in result.1.clone() {
-            assert_eq!(row.len(), 2);
-        }
+        let query: &str = "SELECT id FROM numbers N TABLESAMPLE (10 PERCENT) ORDER BY N.id";
+        let (_, sampled) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert!(sampled.len() > 50 && sampled.len() < 150);
 
-        // Assert that the first row is correct
-        assert_eq!(result.1[0][0], Value::I32(1));
-        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+        // The same seed gives the same rows
+        let (_, resampled) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(sampled, resampled);
 
-        // Assert that the second row is correct
-        assert_eq!(result.1[1][0], Value::I32(2));
-        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+        // The WHERE clause and aggregates only see the sampled rows
+        let (_, results) = execute_query(
+            &parse(
+                "SELECT COUNT(*) FROM numbers TABLESAMPLE BERNOULLI (10) WHERE id < 500",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let expected: usize = sampled
+            .iter()
+            .filter(|row| row[0] < Value::I32(500))
+            .count();
+        assert_eq!(results, vec![vec![Value::I32(expected as i32)]]);
 
-        // Assert that the third row is correct
-        assert_eq!(result.1[2][0], Value::I32(3));
-        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+        let (_, results) = execute_query(
+            &parse("SELECT id FROM numbers TABLESAMPLE (0)", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 0);
+        let (_, results) = execute_query(
+            &parse("SELECT id FROM numbers TABLESAMPLE (100 PERCENT)", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 1000);
+        assert!(execute_query(
+            &parse("SELECT id FROM numbers TABLESAMPLE (150 PERCENT)", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
 
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        // A table hint is not a way to write a sample
+        assert!(execute_query(
+            &parse("SELECT id FROM numbers WITH (TABLESAMPLE(10))", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
+        // Only the tables of the outermost SELECT can be sampled
+        assert!(parse(
+            "SELECT id FROM numbers WHERE id IN (SELECT id FROM numbers TABLESAMPLE (10))",
+            false
+        )
+        .is_err());
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_unaliased_select() {
-        // This tests
-        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
-        let columns = to_selectitems(vec!["id".to_string(), "name".to_string()]);
-        let tables = vec![("test_table1".to_string(), "".to_string())]; // [(table_name, alias)]
-
-        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
-
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
-
-        // Create a new user on the main branch
+    // Ordering by RANDOM() with a LIMIT picks a random sample, which a seed makes repeatable
+    fn test_order_by_random() {
+        fcreate_db_instance("order_by_random");
         let mut user: User = User::new("test_user".to_string());
+        let values: Vec<String> = (0..100).map(|i| format!("({})", i)).collect();
+        let queries: Vec<String> = vec![
+            "CREATE TABLE numbers (id INT)".to_string(),
+            format!("INSERT INTO numbers VALUES {}", values.join(", ")),
+            "SET sample_seed = 7".to_string(),
+        ];
+        for query in queries {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let query = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
 
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-        insert(
-            vec![
-                vec![
-                    Value::I64(1),
-                    Value::String("Iron Man".to_string()),
-                    Value::I64(40),
-                ],
-                vec![
-                    Value::I64(2),
-                    Value::String("Spiderman".to_string()),
-                    Value::I64(20),
-                ],
-                vec![
-                    Value::I64(3),
-                    Value::String("Doctor Strange".to_string()),
-                    Value::I64(35),
-                ],
-            ],
-            "test_table1".to_string(),
-            &new_db,
+        let sample: Vec<Row> = query(
+            "SELECT id FROM numbers ORDER BY RANDOM() LIMIT 10",
             &mut user,
-        )
-        .unwrap();
+        );
+        assert_eq!(sample.len(), 10);
+        assert_eq!(sample.iter().unique().count(), 10);
+        assert_ne!(
+            sample,
+            query("SELECT id FROM numbers ORDER BY id LIMIT 10", &mut user)
+        );
 
-        // Run the SELECT query
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+        // The same seed gives the same sample, and another seed gives another one
+        assert_eq!(
+            sample,
+            query(
+                "SELECT id FROM numbers ORDER BY RANDOM() LIMIT 10",
+                &mut user
+            )
+        );
+        execute_update(
+            &parse("SET sample_seed = 8", true).unwrap(),
+            &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        assert_ne!(
+            sample,
+            query(
+                "SELECT id FROM numbers ORDER BY RANDOM() LIMIT 10",
+                &mut user
+            )
+        );
 
-        assert_eq!(result.0[0], "id".to_string());
-        assert_eq!(result.0[1], "name".to_string());
-
-        // Assert that 3 rows were returned
-        assert_eq!(result.1.iter().len(), 3);
-
-        // Assert that each row only has 2 columns
-        for row in result.1.clone() {
-            assert_eq!(row.len(), 2);
+        // It can be selected too, and it's different for each row
+        let numbers: Vec<Row> = query("SELECT RANDOM() FROM numbers LIMIT 5", &mut user);
+        assert_eq!(numbers.len(), 5);
+        assert_eq!(numbers.iter().unique().count(), 5);
+        for row in numbers {
+            assert!(matches!(row[..], [Value::Float(x)] if (0.0..1.0).contains(&x)));
         }
+        assert!(execute_query(
+            &parse("SELECT RANDOM(1) FROM numbers", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
+        delete_db_instance().unwrap();
+    }
 
-        // Assert that the first row is correct
-        assert_eq!(result.1[0][0], Value::I32(1));
-        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+    #[test]
+    #[serial]
+    // Ensures that strings longer than their column either error or are truncated
+    fn test_string_overflow_policy() {
+        let mut user = create_demo_db("string_overflow_policy");
+        execute_update(
+            &parse("CREATE TABLE short_strings (id INT, name VARCHAR(5))", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        // Assert that the second row is correct
-        assert_eq!(result.1[1][0], Value::I32(2));
-        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+        // By default, the insert fails with the column name and lengths
+        let result = execute_update(
+            &parse("INSERT INTO short_strings VALUES (1, 'abcdefgh')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert_eq!(
+            result.unwrap_err().message,
+            "Value for column name is too long (8 bytes), the maximum is 5 bytes".to_string()
+        );
+        execute_update(
+            &parse("INSERT INTO short_strings VALUES (1, 'abc')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let result = execute_update(
+            &parse(
+                "UPDATE short_strings SET name = 'abcdefgh' WHERE id = 1",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert!(result.is_err());
 
-        // Assert that the third row is correct
-        assert_eq!(result.1[2][0], Value::I32(3));
-        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+        // With the truncate policy, the strings are cut down to the width of the column
+        execute_update(
+            &parse("SET string_overflow = 'truncate'", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            user.get_string_overflow_policy(),
+            StringOverflowPolicy::Truncate
+        );
+        execute_update(
+            &parse(
+                "INSERT INTO short_strings VALUES (2, 'abcdefgh'), (3, 'ééé')",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        execute_update(
+            &parse(
+                "UPDATE short_strings SET name = 'zyxwvut' WHERE id = 1",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let (_, results) = execute_query(
+            &parse("SELECT id, name FROM short_strings ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I64(1), Value::String("zyxwv".to_string())],
+                vec![Value::I64(2), Value::String("abcde".to_string())],
+                // Multi-byte characters are never split
+                vec![Value::I64(3), Value::String("éé".to_string())],
+            ]
+        );
 
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        assert!(execute_update(
+            &parse("SET string_overflow = 'ignore'", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_select_multiple_tables_specific_columns() {
-        // This tests
-        // SELECT T1.id, country FROM select_test_db.test_table1 T1, select_test_db.test_table2;
-        let columns = to_selectitems(vec!["T1.id".to_string(), "country".to_string()]);
-        let tables = vec![
-            ("test_table1".to_string(), "T1".to_string()),
-            ("test_table2".to_string(), "".to_string()),
-        ]; // [(table_name, alias)]
-
-        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
-
-        let schema1: Schema = vec![
+    // Ensures that insert exits if a value is of the wrong type and is inserted as null
+    fn test_insert_invalid_nulls() {
+        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
+        let schema: Schema = vec![
             ("id".to_string(), Column::I32),
             ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
+            (
+                "age".to_string(),
+                Column::Nullable(Box::new(Column::Double)),
+            ),
+        ];
+
+        // Create a new user on the main branch
+        let mut user: User = User::new("test_user".to_string());
+
+        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        let rows = vec![
+            vec![
+                Value::Null(Column::I32), // Nulled
+                Value::String("Iron Man".to_string()),
+                Value::String("Robert Downey".to_string()),
+            ],
+            vec![
+                Value::I64(2),
+                Value::String("Spiderman".to_string()),
+                Value::String("".to_string()),
+            ],
+            vec![
+                Value::I64(3),
+                Value::Null(Column::String(50)),
+                Value::Float(322.456),
+            ],
+            vec![
+                Value::I64(4),
+                Value::String("Captain America".to_string()),
+                Value::Null(Column::Double),
+            ],
         ];
 
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
+        assert!(insert(rows, "test_table1".to_string(), &new_db, &mut user).is_err());
 
-        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
+        // Delete the test database
+        new_db.delete_database().unwrap();
+    }
 
-        insert(
-            [
-                vec![
-                    Value::I64(1),
-                    Value::String("Robert Downey Jr".to_string()),
-                    Value::I64(40),
-                ],
-                vec![
-                    Value::I64(2),
-                    Value::String("Tom Holland".to_string()),
-                    Value::I64(20),
-                ],
-            ]
-            .to_vec(),
-            "test_table1".to_string(),
-            &new_db,
+    #[test]
+    #[serial]
+    // Test update row with one specific row
+    fn test_update_single_row() {
+        let mut user = create_demo_db("personal_info");
+        let _res = execute_update(
+            &parse("UPDATE personal_info SET age = 50 WHERE id = 1", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        let (_, results) = execute_query(
+            &parse("SELECT * from personal_info", false).unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        for row in results {
+            if let Value::I64(x) = row[3] {
+                if let Value::I32(y) = row[0] {
+                    if y == 1 {
+                        assert!(x == 50);
+                    }
+                }
+            } else {
+                panic!("Invalid value type");
+            }
+        }
+        delete_db_instance().unwrap();
+    }
 
-        let schema2: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("country".to_string(), Column::String(50)),
-        ];
-
-        create_table(&"test_table2".to_string(), &schema2, &new_db, &mut user).unwrap();
-
-        insert(
-            [
-                vec![Value::I64(1), Value::String("United States".to_string())],
-                vec![Value::I64(2), Value::String("Britain".to_string())],
-            ]
-            .to_vec(),
-            "test_table2".to_string(),
-            &new_db,
+    #[test]
+    #[serial]
+    // Test update row with multiple rows
+    fn test_update_multiple_row() {
+        let mut user = create_demo_db("personal_info");
+        let _res = execute_update(
+            &parse("UPDATE personal_info SET age = 55", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        let (_, results) = execute_query(
+            &parse("SELECT * from personal_info", false).unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        for row in results {
+            if let Value::I64(x) = row[3] {
+                assert!(x == 55);
+            } else {
+                panic!("Invalid value type");
+            }
+        }
+        delete_db_instance().unwrap();
+    }
 
-        // Run the SELECT query
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+    #[test]
+    #[serial]
+    // Test update row with multiple rows using more complex logic
+    fn test_update_row_predicate() {
+        let mut user = create_demo_db("personal_info");
+        let _res = execute_update(
+            &parse("UPDATE personal_info SET age = 55 WHERE id < 10", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        let (_, results) = execute_query(
+            &parse("SELECT * from personal_info", false).unwrap(),
+            &mut user,
+            &"".to_string(),
         )
         .unwrap();
-
-        assert_eq!(result.0[0], "T1.id".to_string());
-        assert_eq!(result.0[1], "country".to_string());
-
-        // Assert that 4 rows were returned
-        assert_eq!(result.1.iter().len(), 4);
-
-        // Assert that each row only has 2 columns
-        for row in result.1.clone() {
-            assert_eq!(row.len(), 2);
+        for row in results {
+            if let Value::I64(x) = row[3] {
+                if let Value::I32(y) = row[0] {
+                    if y < 10 {
+                        assert!(x == 55);
+                    }
+                }
+            } else {
+                panic!("Invalid value type");
+            }
         }
+        delete_db_instance().unwrap();
+    }
+    #[test]
+    #[serial]
+    // Ensures that assignments which refer to the updated columns use the rows' old values
+    fn test_update_self_referencing_assignments() {
+        fcreate_db_instance("update_self_referencing");
+        let mut user: User = User::new("test_user".to_string());
+        let update = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string()).unwrap();
+        };
+        update(
+            "CREATE TABLE pages (id INT, visits INT, likes INT)",
+            &mut user,
+        );
+        update(
+            "INSERT INTO pages VALUES (1, 0, 5), (2, 10, 6), (3, 20, 7), (4, 30, 8)",
+            &mut user,
+        );
+        let rows = |user: &mut User| -> Vec<Row> {
+            execute_query(
+                &parse("SELECT visits, likes FROM pages ORDER BY id", false).unwrap(),
+                user,
+                &"".to_string(),
+            )
+            .unwrap()
+            .1
+        };
+        let row = |visits: i64, likes: i64| -> Row { vec![Value::I64(visits), Value::I64(likes)] };
 
-        // Assert that the first row is correct
-        assert_eq!(result.1[0][0], Value::I32(1));
-        assert_eq!(result.1[0][1], Value::String("United States".to_string()));
-
-        // Assert that the second row is correct
-        assert_eq!(result.1[1][0], Value::I32(1));
-        assert_eq!(result.1[1][1], Value::String("Britain".to_string()));
-
-        // Assert that the third row is correct
-        assert_eq!(result.1[2][0], Value::I32(2));
-        assert_eq!(result.1[2][1], Value::String("United States".to_string()));
+        // Every matched row is incremented once, from its own value
+        update(
+            "UPDATE pages SET visits = visits + 1 WHERE id > 1",
+            &mut user,
+        );
+        update(
+            "UPDATE pages SET visits = visits + 1 WHERE id > 2",
+            &mut user,
+        );
+        assert_eq!(
+            rows(&mut user),
+            vec![row(0, 5), row(11, 6), row(22, 7), row(32, 8)]
+        );
 
-        // Assert that the fourth row is correct
-        assert_eq!(result.1[3][0], Value::I32(2));
-        assert_eq!(result.1[3][1], Value::String("Britain".to_string()));
+        // Both assignments see the old values, so the columns are swapped
+        update(
+            "UPDATE pages SET visits = likes, likes = visits WHERE id <= 2",
+            &mut user,
+        );
+        assert_eq!(
+            rows(&mut user),
+            vec![row(5, 0), row(6, 11), row(22, 7), row(32, 8)]
+        );
 
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        // A later assignment doesn't see the value given by an earlier one
+        update(
+            "UPDATE pages SET visits = visits * 2, likes = visits + 1",
+            &mut user,
+        );
+        assert_eq!(
+            rows(&mut user),
+            vec![row(10, 6), row(12, 7), row(44, 23), row(64, 33)]
+        );
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_ambigous_select() {
-        // This tests
-        // SELECT id, country FROM select_test_db.test_table1, select_test_db.test_table2;
-        let columns = to_selectitems(vec!["id".to_string(), "country".to_string()]);
-        let tables = vec![
-            ("test_table1".to_string(), "".to_string()),
-            ("test_table2".to_string(), "".to_string()),
-        ]; // [(table_name, alias)]
-
-        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
-
-        let schema1: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
-
-        // Create a new user on the main branch
+    // Test updating a table with the values of the rows it is joined with
+    fn test_update_from_join() {
+        fcreate_db_instance("update_from_join");
         let mut user: User = User::new("test_user".to_string());
+        for query in [
+            "CREATE TABLE prices (id INT, price INT)",
+            "CREATE TABLE new_prices (id INT, price INT)",
+            "INSERT INTO prices VALUES (1, 10), (2, 20), (3, 30)",
+            "INSERT INTO new_prices VALUES (1, 11), (3, 33), (4, 44)",
+        ] {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
 
-        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
-
-        insert(
-            [
-                vec![
-                    Value::I64(1),
-                    Value::String("Robert Downey Jr.".to_string()),
-                    Value::I64(40),
-                ],
-                vec![
-                    Value::I64(2),
-                    Value::String("Tom Holland".to_string()),
-                    Value::I64(20),
-                ],
-            ]
-            .to_vec(),
-            "test_table1".to_string(),
-            &new_db,
+        // Only the rows with a match in the joined table are updated
+        let message: String = execute_update(
+            &parse(
+                "UPDATE prices SET prices.price = n.price FROM prices JOIN new_prices n ON prices.id = n.id",
+                true,
+            )
+            .unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(message, "2 rows were successfully updated.".to_string());
+        let (_, results) = execute_query(
+            &parse("SELECT id, price FROM prices ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I32(1), Value::I32(11)],
+                vec![Value::I32(2), Value::I32(20)],
+                vec![Value::I32(3), Value::I32(33)],
+            ]
+        );
 
-        let schema2: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("country".to_string(), Column::String(50)),
-        ];
-
-        create_table(&"test_table2".to_string(), &schema2, &new_db, &mut user).unwrap();
+        // The joined table can also be listed on its own, with the join in the WHERE clause
+        execute_update(
+            &parse(
+                "UPDATE prices P SET price = n.price + P.price FROM new_prices n WHERE P.id = n.id AND n.price > 20",
+                true,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let (_, results) = execute_query(
+            &parse("SELECT id, price FROM prices ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I32(1), Value::I32(11)],
+                vec![Value::I32(2), Value::I32(20)],
+                vec![Value::I32(3), Value::I32(66)],
+            ]
+        );
+        delete_db_instance().unwrap();
+    }
+    #[test]
+    #[serial]
+    // Ensures that rows an UPDATE leaves unchanged aren't counted, or included in the diff
+    fn test_update_skips_unchanged_rows() {
+        fcreate_db_instance("update_skips_unchanged_rows");
+        let mut user: User = User::new("test_user".to_string());
+        for query in [
+            "CREATE TABLE t (id INT, v INT, name VARCHAR(10))",
+            "INSERT INTO t VALUES (1, 10, 'a'), (2, 20, 'b'), (3, 30, 'c')",
+        ] {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
 
-        insert(
-            [
-                vec![Value::I64(5), Value::String("United States".to_string())],
-                vec![Value::I64(6), Value::String("Britain".to_string())],
-            ]
-            .to_vec(),
-            "test_table2".to_string(),
-            &new_db,
+        // Only the third row ends up with a different value
+        let num_diffs: usize = user.get_diffs().len();
+        let message: String = execute_update(
+            &parse("UPDATE t SET v = 30, name = name WHERE id >= 1", true).unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
-
-        // Run the SELECT query
-        let user: User = User::new("test_user".to_string());
-        let _ = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+        assert_eq!(message, "2 rows were successfully updated.".to_string());
+        let message: String = execute_update(
+            &parse("UPDATE t SET v = 30 WHERE id = 3", true).unwrap(),
+            &mut user,
+            &"".to_string(),
         )
-        .unwrap_err();
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        .unwrap();
+        assert_eq!(message, "0 rows were successfully updated.".to_string());
+
+        let diffs: Vec<Diff> = user.get_diffs();
+        match &diffs[num_diffs] {
+            Diff::Update(diff) => {
+                let ids: Vec<Value> = diff.rows.iter().map(|row| row.row[0].clone()).collect();
+                assert_eq!(ids, vec![Value::I32(1), Value::I32(2)]);
+            }
+            _ => panic!("Expected an update diff"),
+        }
+        assert!(diffs[num_diffs + 1..].iter().all(|diff| diff.is_empty()));
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_invalid_column_select() {
-        // This tests
-        // SELECT id, name, age, invalid_column FROM select_test_db.test_table1;
+    // Ensures that several tables can be dropped at once, each with its own diff
+    fn test_drop_multiple_tables() {
+        fcreate_db_instance("drop_multiple_tables");
+        let mut user: User = User::new("test_user".to_string());
+        for query in [
+            "CREATE TABLE a (id INT)",
+            "CREATE TABLE b (id INT)",
+            "CREATE TABLE c (id INT)",
+            "CREATE TABLE d (id INT)",
+        ] {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let num_diffs: usize = user.get_diffs().len();
+        let drop = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string())
+        };
 
-        let columns = to_selectitems(vec![
-            "id".to_string(),
-            "name".to_string(),
-            "age".to_string(),
-            "invalid_column".to_string(),
-        ]);
-        let tables = vec![("test_table1".to_string(), "".to_string())]; // [(table_name, alias)]
+        let message: String = drop("DROP TABLE a, b, c", &mut user).unwrap();
+        assert_eq!(
+            message,
+            "Table dropped: a\nTable dropped: b\nTable dropped: c".to_string()
+        );
+        let dropped: Vec<String> = user.get_diffs()[num_diffs..]
+            .iter()
+            .filter_map(|diff| match diff {
+                Diff::TableRemove(diff) => Some(diff.table_name.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(dropped, vec!["a", "b", "c"]);
 
-        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+        // Nothing is dropped if one of the tables doesn't exist
+        assert!(drop("DROP TABLE d, a", &mut user).is_err());
+        assert_eq!(
+            get_db_instance().unwrap().get_tables(&user).unwrap(),
+            vec!["d".to_string()]
+        );
 
-        let schema1: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
+        // Unless IF EXISTS is given, in which case the missing tables are skipped
+        let message: String = drop("DROP TABLE IF EXISTS a, d", &mut user).unwrap();
+        assert_eq!(
+            message,
+            "Table does not exist: a\nTable dropped: d".to_string()
+        );
+        assert!(get_db_instance()
+            .unwrap()
+            .get_tables(&user)
+            .unwrap()
+            .is_empty());
+        delete_db_instance().unwrap();
+    }
 
-        // Create a new user on the main branch
+    #[test]
+    #[serial]
+    // Ensures that renaming an indexed column keeps the index, under the column's new name
+    fn test_rename_indexed_column() {
+        fcreate_db_instance("rename_indexed_column");
         let mut user: User = User::new("test_user".to_string());
+        for query in [
+            "CREATE TABLE people (id INT, name VARCHAR(20), age INT)",
+            "INSERT INTO people VALUES (1, 'Ann', 30), (2, 'Bob', 40), (3, 'Cal', 50)",
+            "CREATE INDEX people_id ON people (id)",
+            "ALTER TABLE people ADD CONSTRAINT name_key UNIQUE (name)",
+            "ALTER TABLE people RENAME COLUMN id TO person_id",
+        ] {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
 
-        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
+        let query: &str = "SELECT name FROM people WHERE person_id = 2";
+        assert_eq!(
+            run(query, &mut user),
+            vec![vec![Value::String("Bob".to_string())]]
+        );
+        assert!(
+            run(&format!("EXPLAIN {}", query), &mut user).contains(&vec![Value::String(
+                "Index scan on table people as people using index people_id".to_string()
+            )])
+        );
 
-        insert(
-            [
-                vec![
-                    Value::I64(1),
-                    Value::String("Robert Downey Jr.".to_string()),
-                    Value::I64(40),
-                ],
-                vec![
-                    Value::I64(2),
-                    Value::String("Tom Holland".to_string()),
-                    Value::I64(20),
-                ],
-            ]
-            .to_vec(),
-            "test_table1".to_string(),
-            &new_db,
+        // The unique index on the other column still rejects duplicates
+        assert!(execute_update(
+            &parse("INSERT INTO people VALUES (4, 'Ann', 60)", true).unwrap(),
             &mut user,
+            &"".to_string()
         )
-        .unwrap();
-
-        // Run the SELECT query
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
-        );
-
-        // Verify that SELECT failed
-        assert!(result.is_err());
+        .is_err());
 
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        // The old name is gone, and a column can't be renamed onto another one
+        assert!(execute_query(
+            &parse("SELECT name FROM people WHERE id = 2", false).unwrap(),
+            &mut user,
+            &"".to_string()
+        )
+        .is_err());
+        assert!(execute_update(
+            &parse("ALTER TABLE people RENAME COLUMN age TO name", true).unwrap(),
+            &mut user,
+            &"".to_string()
+        )
+        .is_err());
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_invalid_table_select() {
-        // This tests
-        // SELECT id, name, age FROM select_test_db.test_table1, select_test_db.test_table2;
+    // Ensures that UPDATE and DELETE can refer to columns through the table's alias, like SELECT
+    fn test_update_and_delete_with_alias() {
+        fcreate_db_instance("update_and_delete_with_alias");
+        let mut user: User = User::new("test_user".to_string());
+        let mut run = |query: &str| {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap()
+        };
+        run("CREATE TABLE t (id INT, v INT, w INT)");
+        run("INSERT INTO t VALUES (1, 10, 100), (2, 20, 200), (3, 30, 300), (4, 40, 400)");
+        run("CREATE INDEX t_id ON t (id)");
 
-        let columns = to_selectitems(vec![
-            "id".to_string(),
-            "name".to_string(),
-            "age".to_string(),
-        ]);
-        let tables = vec![
-            ("test_table1".to_string(), "".to_string()),
-            ("test_table2".to_string(), "".to_string()),
-        ]; // [(table_name, alias)]
+        assert_eq!(
+            run("UPDATE t AS x SET v = x.v + 1 WHERE x.id = 2"),
+            "1 rows were successfully updated.".to_string()
+        );
+        run("UPDATE t x SET x.w = w + x.v WHERE id = 3 AND x.v = 30");
+        run("UPDATE t SET v = 0 WHERE t.id = 4");
+        run("DELETE FROM t AS x WHERE x.id = 1");
 
-        let new_db: Database = Database::new("select_test_db".to_string()).unwrap();
+        let (_, results) = execute_query(
+            &parse("SELECT * FROM t ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::I32(2), Value::I32(21), Value::I32(200)],
+                vec![Value::I32(3), Value::I32(30), Value::I32(330)],
+                vec![Value::I32(4), Value::I32(0), Value::I32(400)],
+            ]
+        );
 
-        let schema1: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
+        // An alias hides the table's own name, as it does in a SELECT
+        for query in [
+            "DELETE FROM t AS x WHERE t.id = 2",
+            "UPDATE t AS x SET t.v = 1",
+            "UPDATE t AS x SET y.v = 1",
+        ] {
+            assert!(
+                execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).is_err()
+            );
+        }
+        delete_db_instance().unwrap();
+    }
+    #[test]
+    #[serial]
+    // Test delete a single row from the database
+    fn test_delete_single_row() {
+        let mut user = create_demo_db("personal_info");
+        let _res = execute_update(
+            &parse("DELETE FROM personal_info WHERE id = 27", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        let (_, results) = execute_query(
+            &parse("SELECT * from personal_info", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(results.len() == 24);
+        for row in results {
+            if let Value::I32(x) = row[0] {
+                assert!(x != 27);
+            } else {
+                panic!("Invalid value type");
+            }
+        }
+        delete_db_instance().unwrap();
+    }
+    #[test]
+    #[serial]
+    // Test deleting multiple rows on the database
+    fn test_delete_multiple_rows() {
+        let mut user = create_demo_db("personal_info");
+        let _res = execute_update(
+            &parse("DELETE FROM personal_info WHERE id < 25", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        let (_, results) = execute_query(
+            &parse("SELECT * from personal_info", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(results.len() == 16);
+        delete_db_instance().unwrap();
+    }
+    #[test]
+    #[serial]
+    // Test order by command ASC
+    fn test_order_by_asc() {
+        let mut user = create_demo_db("personal_info");
+        let (_, results) = execute_query(
+            &parse("SELECT * from personal_info ORDER BY id ASC", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let mut temp = 0;
+        for row in results {
+            if let Value::I32(x) = row[0] {
+                assert!(x >= temp);
+                temp = x;
+            } else {
+                panic!("Invalid value type");
+            }
+        }
+    }
 
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
+    #[test]
+    #[serial]
+    // Test order by command DESC
+    fn test_order_by_desc() {
+        let mut user = create_demo_db("personal_info");
+        let (_, results) = execute_query(
+            &parse("SELECT * from personal_info ORDER BY id DESC", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let mut temp = 100;
+        for row in results {
+            if let Value::I32(x) = row[0] {
+                assert!(x <= temp);
+                temp = x;
+            } else {
+                panic!("Invalid value type");
+            }
+        }
+    }
 
-        create_table(&"test_table1".to_string(), &schema1, &new_db, &mut user).unwrap();
+    #[test]
+    #[serial]
+    // Test order by command DESC
+    fn test_order_by_select() {
+        let mut user = create_demo_db("personal_info");
+        let (_, results) = execute_query(
+            &parse("SELECT age, id from personal_info ORDER BY age DESC", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let mut temp = 200;
+        for row in results {
+            if let Value::I64(x) = row[0] {
+                assert!(x <= temp);
+                temp = x;
+            } else {
+                panic!("Invalid value type");
+            }
+        }
+    }
 
-        insert(
-            [
-                vec![
-                    Value::I64(1),
-                    Value::String("Robert Downey Jr.".to_string()),
-                    Value::I64(40),
-                ],
-                vec![
-                    Value::I64(2),
-                    Value::String("Tom Holland".to_string()),
-                    Value::I64(20),
-                ],
-            ]
-            .to_vec(),
-            "test_table1".to_string(),
-            &new_db,
+    #[test]
+    #[serial]
+    // Test order by the position of a selected column
+    fn test_order_by_ordinal() {
+        let mut user = create_demo_db("personal_info");
+        let (_, results) = execute_query(
+            &parse("SELECT id, age from personal_info ORDER BY 2 DESC", false).unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        let mut temp = 200;
+        for row in results {
+            if let Value::I64(x) = row[1] {
+                assert!(x <= temp);
+                temp = x;
+            } else {
+                panic!("Invalid value type");
+            }
+        }
 
-        // Run the SELECT query
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+        let result = execute_query(
+            &parse("SELECT id, age from personal_info ORDER BY 3", false).unwrap(),
+            &mut user,
+            &"".to_string(),
         );
-
-        // Verify that SELECT failed
         assert!(result.is_err());
-
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_insert_columns() {
-        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
-        let columns = to_selectitems(vec!["T.id".to_string(), "T.name".to_string()]);
-        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
-
-        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
-
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
-
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-        let rows = vec![
-            vec![
-                Value::I32(1),
-                Value::String("Iron Man".to_string()),
-                Value::I32(40),
-            ],
-            vec![
-                Value::I32(2),
-                Value::String("Spiderman".to_string()),
-                Value::I32(20),
-            ],
-            vec![
-                Value::I32(3),
-                Value::String("Doctor Strange".to_string()),
-                Value::I32(35),
-            ],
-            vec![
-                Value::I32(4),
-                Value::String("Captain America".to_string()),
-                Value::I32(100),
-            ],
-            vec![
-                Value::I32(5),
-                Value::String("Thor".to_string()),
-                Value::I32(1000),
-            ],
-        ];
-        let (_, diff) =
-            insert(rows.clone(), "test_table1".to_string(), &new_db, &mut user).unwrap();
-
-        // Verify that the insert was successful by looking at the diff first
-        assert_eq!(diff.rows.len(), 5);
-        assert_eq!(diff.schema, schema);
-        assert_eq!(diff.table_name, "test_table1".to_string());
-        assert_eq!(diff.rows[0].row, rows[0]);
-        assert_eq!(diff.rows[1].row, rows[1]);
-        assert_eq!(diff.rows[2].row, rows[2]);
-        assert_eq!(diff.rows[3].row, rows[3]);
-        assert_eq!(diff.rows[4].row, rows[4]);
+    // Test cancelling a query while it is scanning, and running another query afterwards
+    fn test_cancel_query() {
+        let mut user = create_huge_bench_db(500, true);
+        let query: String = "SELECT a.id1, b.id1 FROM huge_table a, huge_table b".to_string();
+
+        // Cancel the query from another thread once it has started scanning. The scan waits on
+        // the barrier when it starts, and again until the cancel has been requested.
+        let canceller: User = user.clone();
+        let barrier: Arc<Barrier> = Arc::new(Barrier::new(2));
+        set_scan_barrier(barrier.clone());
+        let cancel_thread = std::thread::spawn(move || {
+            barrier.wait();
+            canceller.request_cancel();
+            barrier.wait();
+        });
+        let result = execute_query(&parse(&query, false).unwrap(), &mut user, &query);
+        cancel_thread.join().unwrap();
+        assert_eq!(result.unwrap_err(), "Query cancelled".to_string());
+
+        // The session can still run queries once the cancel is cleared
+        user.clear_cancel();
+        let (_, results) =
+            execute_query(&parse(&query, false).unwrap(), &mut user, &query).unwrap();
+        assert_eq!(results.len(), 500 * 500);
+        delete_db_instance().unwrap();
+    }
 
-        // Run the SELECT query and ensure that the result is correct
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+    #[test]
+    #[serial]
+    // Test order by the alias of a selected column
+    fn test_order_by_alias() {
+        let mut user = create_demo_db("personal_info");
+        let (_, results) = execute_query(
+            &parse(
+                "SELECT id, age * 2 AS total from personal_info ORDER BY total",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
         )
         .unwrap();
-
-        assert_eq!(result.0[0], "T.id".to_string());
-        assert_eq!(result.0[1], "T.name".to_string());
-
-        // Assert that 3 rows were returned
-        assert_eq!(result.1.iter().len(), 5);
-
-        // Assert that each row only has 2 columns
-        for row in result.1.clone() {
-            assert_eq!(row.len(), 2);
+        let mut temp = 0;
+        for row in results {
+            if let Value::I64(x) = row[1] {
+                assert!(x >= temp);
+                temp = x;
+            } else {
+                panic!("Invalid value type");
+            }
         }
-
-        // Assert that the first row is correct
-        assert_eq!(result.1[0][0], Value::I32(1));
-        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
-
-        // Assert that the second row is correct
-        assert_eq!(result.1[1][0], Value::I32(2));
-        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
-
-        // Assert that the third row is correct
-        assert_eq!(result.1[2][0], Value::I32(3));
-        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
-
-        // Assert that the fourth row is correct
-        assert_eq!(result.1[3][0], Value::I32(4));
-        assert_eq!(result.1[3][1], Value::String("Captain America".to_string()));
-
-        // Assert that the fifth row is correct
-        assert_eq!(result.1[4][0], Value::I32(5));
-        assert_eq!(result.1[4][1], Value::String("Thor".to_string()));
-
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_invalid_insert() {
-        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
-
-        // Create a new user on the main branch
+    // Test ordering groups by an aggregate, its alias, or an expression of aggregates
+    fn test_order_by_aggregate() {
+        fcreate_db_instance("order_by_aggregate");
         let mut user: User = User::new("test_user".to_string());
-
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-
-        let newrows = vec![
-            vec![
-                Value::I64(1),
-                Value::String("Iron Man".to_string()),
-                Value::I64(40),
-            ],
-            vec![
-                Value::I64(2),
-                Value::String("Spiderman".to_string()),
-                Value::I64(20),
-            ],
-            vec![Value::I64(3), Value::I64(35)],
-            vec![
-                Value::I64(4),
-                Value::String("Captain America".to_string()),
-                Value::I64(35),
-            ],
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE emp (id INT, dept VARCHAR(10), salary INT)",
+            "INSERT INTO emp VALUES (1, 'a', 10), (2, 'b', 20), (3, 'b', 30)",
+            "INSERT INTO emp VALUES (4, 'c', 5), (5, 'c', 6), (6, 'c', 7)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let counts: Vec<Row> = vec![
+            vec![Value::String("c".to_string()), Value::I32(3)],
+            vec![Value::String("b".to_string()), Value::I32(2)],
+            vec![Value::String("a".to_string()), Value::I32(1)],
         ];
+        assert_eq!(
+            run(
+                "SELECT dept, COUNT(*) FROM emp GROUP BY dept ORDER BY COUNT(*) DESC",
+                &mut user
+            ),
+            counts
+        );
+        assert_eq!(
+            run(
+                "SELECT dept, COUNT(*) AS n FROM emp GROUP BY dept ORDER BY n DESC",
+                &mut user
+            ),
+            counts
+        );
+        assert_eq!(
+            run(
+                "SELECT dept, COUNT(*) FROM emp GROUP BY dept ORDER BY COUNT(*) DESC LIMIT 2",
+                &mut user
+            ),
+            counts[0..2].to_vec()
+        );
 
-        assert!(insert(newrows, "test_table1".to_string(), &new_db, &mut user).is_err());
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        // The aggregate doesn't have to be selected
+        let depts = |values: Vec<&str>| -> Vec<Row> {
+            values
+                .into_iter()
+                .map(|x| vec![Value::String(x.to_string())])
+                .collect()
+        };
+        assert_eq!(
+            run(
+                "SELECT dept FROM emp GROUP BY dept ORDER BY SUM(salary)",
+                &mut user
+            ),
+            depts(vec!["a", "c", "b"])
+        );
+        assert_eq!(
+            run(
+                "SELECT dept FROM emp GROUP BY dept ORDER BY MAX(salary) - MIN(salary) DESC",
+                &mut user
+            ),
+            depts(vec!["b", "c", "a"])
+        );
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_read_permissions() {
-        let new_db: Database = Database::new("read_perm_test_db".to_string()).unwrap();
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
-        ];
-
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
-
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-
-        // Setting user permissions to Read
-        user.set_permissions(&Read);
-
-        let rows = vec![
-            vec![
-                Value::I32(100), // Can only insert I32
-                Value::String("Iron Man".to_string()),
-                Value::Double(3.456),
-            ],
-            vec![
-                Value::I32(2),
-                Value::String("Spiderman".to_string()),
-                Value::Double(3.43456),
-            ],
-        ];
+    // Test set operation with incompatible types
+    fn test_set_operations_incompatible_types() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        assert!(insert(rows, "test_table1".to_string(), &new_db, &mut user).is_err());
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        let results = execute_query(
+            &parse(
+                "SELECT * from personal_info UNION SELECT * from locations",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap_err();
+        assert!(results == "Incompatible types in set operation");
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_write_permission() {
-        let columns = to_selectitems(vec!["T.id".to_string(), "T.name".to_string()]);
-        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
-        let new_db: Database = Database::new("read_perm_test_db".to_string()).unwrap();
+    fn test_set_operations_union0() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
         let schema: Schema = vec![
             ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
+            ("first_name".to_string(), Column::String(50)),
+            ("last_name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I64),
+            ("height".to_string(), Column::Float),
+            ("date_inserted".to_string(), Column::Timestamp),
         ];
+        create_table(
+            &"test_table".to_string(),
+            &schema,
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
 
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
+        let results = execute_query(
+            &parse(
+                "SELECT * from personal_info UNION SELECT * from test_table",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        // Setting user permissions to Read
-        user.set_permissions(&Write);
+        let results2 = execute_query(
+            &parse("SELECT * from personal_info", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
+        assert!(results == results2);
+        delete_db_instance().unwrap();
+    }
 
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
-        );
+    #[test]
+    #[serial]
+    fn test_set_operations_union1() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        assert!(result.is_err());
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        let results = execute_query(
+            &parse(
+                "select * from personal_info where age > 25 union select * from personal_info where age <= 25 ORDER BY id desc;",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        let results2 = execute_query(
+            &parse("SELECT * from personal_info ORDER BY id desc;", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        assert!(results == results2);
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_read_and_write_permissions() {
-        let columns = to_selectitems(vec!["T.id".to_string(), "T.name".to_string()]);
-        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
-        let new_db: Database = Database::new("read_perm_test_db".to_string()).unwrap();
+    fn test_set_operations_except0() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
         let schema: Schema = vec![
             ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I32),
+            ("first_name".to_string(), Column::String(50)),
+            ("last_name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I64),
+            ("height".to_string(), Column::Float),
+            ("date_inserted".to_string(), Column::Timestamp),
         ];
+        create_table(
+            &"test_table".to_string(),
+            &schema,
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
 
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
-        // Setting user permissions to Read
-        user.set_permissions(&ReadAndWrite);
-
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
-        );
-
-        assert!(result.is_ok());
+        let results = execute_query(
+            &parse(
+                "SELECT * from personal_info except SELECT * from test_table",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        let rows = vec![
-            vec![
-                Value::I32(100), // Can only insert I32
-                Value::String("Iron Man".to_string()),
-                Value::Double(3.456),
-            ],
-            vec![
-                Value::I32(2),
-                Value::String("Spiderman".to_string()),
-                Value::Double(3.43456),
-            ],
-        ];
+        let results2 = execute_query(
+            &parse("SELECT * from personal_info", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        assert!(insert(rows, "test_table1".to_string(), &new_db, &mut user).is_ok());
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        assert!(results == results2);
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Ensures that insert can cast values to the correct type if possible
-    fn test_insert_casts() {
-        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
-        let columns = to_selectitems(vec!["T.id".to_string(), "T.name".to_string()]);
-        let tables = vec![("test_table1".to_string(), "T".to_string())]; // [(table_name, alias)]
-
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
+    fn test_set_operations_except1() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::Double),
-        ];
+        let results = execute_query(
+            &parse(
+                "select * from personal_info except select * from personal_info where age <= 25;",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-        let rows = vec![
-            vec![
-                Value::I32(100), // Can only insert I32
-                Value::String("Iron Man".to_string()),
-                Value::Double(3.456),
-            ],
-            vec![
-                Value::I32(2),
-                Value::String("Spiderman".to_string()),
-                Value::Double(3.43456),
-            ],
-            vec![
-                Value::I32(3),
-                Value::String("Doctor Strange".to_string()),
-                Value::Double(322.456),
-            ],
-            vec![
-                Value::I32(4),
-                Value::String("Captain America".to_string()),
-                Value::Double(12.456),
-            ],
-        ];
+        let results2 = execute_query(
+            &parse("select * from personal_info where age > 25;", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        let (_, diff) =
-            insert(rows.clone(), "test_table1".to_string(), &new_db, &mut user).unwrap();
+        assert!(results == results2);
+        delete_db_instance().unwrap();
+    }
 
-        // Verify that the insert was successful by looking at the diff first
-        assert_eq!(diff.rows.len(), 4);
-        assert_eq!(diff.schema, schema);
-        assert_eq!(diff.table_name, "test_table1".to_string());
-        assert_eq!(diff.rows[0].row, rows[0]);
-        assert_eq!(diff.rows[1].row, rows[1]);
-        assert_eq!(diff.rows[2].row, rows[2]);
-        assert_eq!(diff.rows[3].row, rows[3]);
+    #[test]
+    #[serial]
+    fn test_set_operations_intersect() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        // Run the SELECT query and ensure that the result is correct
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
-            None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
-            &user,
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("first_name".to_string(), Column::String(50)),
+            ("last_name".to_string(), Column::String(50)),
+            ("age".to_string(), Column::I64),
+            ("height".to_string(), Column::Float),
+            ("date_inserted".to_string(), Column::Timestamp),
+        ];
+        create_table(
+            &"test_table".to_string(),
+            &schema,
+            get_db_instance().unwrap(),
+            &mut user,
         )
         .unwrap();
 
-        assert_eq!(result.0[0], "T.id".to_string());
-        assert_eq!(result.0[1], "T.name".to_string());
+        let results = execute_query(
+            &parse(
+                "SELECT * from personal_info intersect SELECT * from test_table",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        // Assert that 3 rows were returned
-        assert_eq!(result.1.iter().len(), 4);
+        let results2 = execute_query(
+            &parse("SELECT * from test_table", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        // Assert that each row only has 2 columns
-        for row in result.1.clone() {
-            assert_eq!(row.len(), 2);
-        }
+        assert!(results == results2);
+        delete_db_instance().unwrap();
+    }
 
-        // Assert that the first row is correct
-        assert_eq!(result.1[0][0], Value::I32(100)); // Casted from I64
-        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+    #[test]
+    #[serial]
+    fn test_set_operations_intersect1() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        // Assert that the second row is correct
-        assert_eq!(result.1[1][0], Value::I32(2));
-        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+        let results = execute_query(
+            &parse(
+                "select * from personal_info where age > 27 intersect select * from personal_info where age <= 28;",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        // Assert that the third row is correct
-        assert_eq!(result.1[2][0], Value::I32(3));
-        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+        let results2 = execute_query(
+            &parse("select * from personal_info where age = 28;", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        // Assert that the fourth row is correct
-        assert_eq!(result.1[3][0], Value::I32(4));
-        assert_eq!(result.1[3][1], Value::String("Captain America".to_string()));
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        assert!(results == results2);
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Ensures that insert exits if a value cannot be casted
-    fn test_insert_invalid_casts() {
-        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::Double),
-        ];
-
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
+    fn test_set_operations_nested0() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-        let rows = vec![
-            vec![
-                Value::I32(100), // Can only insert I32
-                Value::String("Iron Man".to_string()),
-                Value::String("Robert Downey".to_string()),
-            ],
-            vec![
-                Value::I32(2),
-                Value::String("Spiderman".to_string()),
-                Value::Double(3.43456),
-            ],
-            vec![
-                Value::I32(3),
-                Value::String("Doctor Strange".to_string()),
-                Value::Double(322.456),
-            ],
-            vec![
-                Value::I64(4),
-                Value::String("Captain America".to_string()),
-                Value::Float(12.456),
-            ],
-        ];
+        let results = execute_query(
+            &parse(
+                "select * from personal_info where age > 27 union select * from personal_info where age <= 28 intersect select * from personal_info where age > 25;",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        assert!(insert(rows, "test_table1".to_string(), &new_db, &mut user).is_err());
+        let results2 = execute_query(
+            &parse("select * from personal_info where age > 25;", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        assert!(results == results2);
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Ensures that insert can cast values to the correct type if possible
-    fn test_insert_nulls() {
-        // SELECT T.id, T.name FROM select_test_db.test_table1 T;
-        let columns = to_selectitems(vec!["id".to_string(), "name".to_string()]);
-        let tables = vec![("test_table1".to_string(), "".to_string())]; // [(table_name, alias)]
+    fn test_set_operations_nested1() {
+        let mut user = create_demo_db("set_op_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
+        let results = execute_query(
+            &parse(
+                "select * from personal_info where age > 28
+                    union select * from personal_info where age > 20 and age <= 34
+                    intersect select * from personal_info where age > 30 ORDER BY id desc;",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
-        let schema: Schema = vec![
-            ("id".to_string(), Column::Nullable(Box::new(Column::I32))),
-            ("name".to_string(), Column::String(50)),
-            (
-                "age".to_string(),
-                Column::Nullable(Box::new(Column::Double)),
-            ),
-        ];
+        let results2 = execute_query(
+            &parse(
+                "select * from personal_info where age > 28 ORDER BY id desc;",
+                false,
+            )
+            .unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-        let rows = vec![
-            vec![
-                Value::I32(100), // Can only insert I32
-                Value::String("Iron Man".to_string()),
-                Value::Double(3.456),
-            ],
-            vec![
-                Value::Null(Column::I32),
-                Value::String("Spiderman".to_string()),
-                Value::Double(3.43456),
-            ],
-            vec![
-                Value::I32(3),
-                Value::String("Doctor Strange".to_string()),
-                Value::Null(Column::Double),
-            ],
-            vec![
-                Value::Null(Column::I32),
-                Value::String("Captain America".to_string()),
-                Value::Null(Column::Double),
-            ],
-        ];
-        let new_rows = vec![
-            vec![
-                Value::I32(100),
-                Value::String("Iron Man".to_string()),
-                Value::Double(3.456),
-            ],
-            vec![
-                Value::Null(Column::I32),
-                Value::String("Spiderman".to_string()),
-                Value::Double(3.43456),
-            ],
-            vec![
-                Value::I32(3),
-                Value::String("Doctor Strange".to_string()),
-                Value::Null(Column::Double),
-            ],
-            vec![
-                Value::Null(Column::I32),
-                Value::String("Captain America".to_string()),
-                Value::Null(Column::Double),
-            ],
-        ];
+        assert!(results == results2);
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_alter_no_table() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let results = execute_update(
+            &parse("alter table missing add test_column int", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap_err();
 
-        let (_, diff) = insert(new_rows, "test_table1".to_string(), &new_db, &mut user).unwrap();
+        assert!(results.message == "Table missing does not exist".to_string());
+        delete_db_instance().unwrap();
+    }
 
-        // Verify that the insert was successful by looking at the diff first
-        assert_eq!(diff.rows.len(), 4);
-        assert_eq!(diff.schema, schema);
-        assert_eq!(diff.table_name, "test_table1".to_string());
-        assert_eq!(diff.rows[0].row, rows[0]);
-        assert_eq!(diff.rows[1].row, rows[1]);
-        assert_eq!(diff.rows[2].row, rows[2]);
-        assert_eq!(diff.rows[3].row, rows[3]);
+    #[test]
+    #[serial]
+    fn test_alter_add() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        // Run the SELECT query and ensure that the result is correct
-        let user: User = User::new("test_user".to_string());
-        let result = select(
-            columns.to_owned(),
+        let old_table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
             None,
-            vec![],
-            vec![],
-            &tables,
-            &new_db,
+        )
+        .unwrap();
+        let old_schemas = old_table.schema.clone();
+
+        execute_update(
+            &parse("alter table personal_info add test_column int", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        let table = Table::from_user(
             &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
         )
         .unwrap();
 
-        assert_eq!(result.0[0], "id".to_string());
-        assert_eq!(result.0[1], "name".to_string());
+        let schemas = table.schema.clone();
+        assert!(old_schemas.len() + 1 == schemas.len());
+        delete_db_instance().unwrap();
+    }
 
-        // Assert that 3 rows were returned
-        assert_eq!(result.1.iter().len(), 4);
+    #[test]
+    #[serial]
+    fn test_alter_add1() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
 
-        // Assert that each row only has 2 columns
-        for row in result.1.clone() {
-            assert_eq!(row.len(), 2);
-        }
+        let old_table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+        let old_schemas = old_table.schema.clone();
 
-        // Assert that the first row is correct
-        assert_eq!(result.1[0][0], Value::I32(100)); // Casted from I64
-        assert_eq!(result.1[0][1], Value::String("Iron Man".to_string()));
+        execute_update(
+            &parse("alter table personal_info add test_column int", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        // Assert that the second row is correct
-        assert_eq!(result.1[1][0], Value::Null(Column::I32));
-        assert_eq!(result.1[1][1], Value::String("Spiderman".to_string()));
+        let table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
 
-        // Assert that the third row is correct
-        assert_eq!(result.1[2][0], Value::I32(3));
-        assert_eq!(result.1[2][1], Value::String("Doctor Strange".to_string()));
+        let rows = table.into_iter().collect::<Vec<RowInfo>>();
+        assert!(
+            rows[0].row[old_schemas.len()] == util::dbtype::Value::Null(util::dbtype::Column::I64)
+        );
+        delete_db_instance().unwrap();
+    }
 
-        // Assert that the fourth row is correct
-        assert_eq!(result.1[3][0], Value::Null(Column::I32));
-        assert_eq!(result.1[3][1], Value::String("Captain America".to_string()));
-        // Delete the test database
-        new_db.delete_database().unwrap();
+    #[test]
+    #[serial]
+    fn test_alter_add2() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let result = execute_update(
+            &parse("alter table personal_info add id int", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap_err();
+
+        assert!(result.message == "Column name id already exists");
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Ensures that insert exits if a value is of the wrong type and is inserted as null
-    fn test_insert_invalid_nulls() {
-        let new_db: Database = Database::new("insert_test_db".to_string()).unwrap();
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("name".to_string(), Column::String(50)),
-            (
-                "age".to_string(),
-                Column::Nullable(Box::new(Column::Double)),
-            ),
+    // Ensures that adding a column keeps the indexes on the other columns as they were
+    fn test_alter_add_keeps_index() {
+        fcreate_db_instance("alter_add_keeps_index");
+        let mut user: User = User::new("test_user".to_string());
+        let values: Vec<String> = (0..300).map(|i| format!("({}, 'name{}')", i, i)).collect();
+        let queries: Vec<String> = vec![
+            "CREATE TABLE t (id INT, name VARCHAR(20))".to_string(),
+            format!("INSERT INTO t VALUES {}", values.join(", ")),
+            "CREATE INDEX id_idx ON t (id)".to_string(),
         ];
+        for query in queries {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let load = |user: &User| {
+            Table::from_user(user, get_db_instance().unwrap(), &"t".to_string(), None).unwrap()
+        };
+        let old_indexes = load(&user).indexes;
+        let num_diffs: usize = user.get_diffs().len();
 
-        // Create a new user on the main branch
-        let mut user: User = User::new("test_user".to_string());
+        execute_update(
+            &parse("ALTER TABLE t ADD note VARCHAR(40)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
 
-        create_table(&"test_table1".to_string(), &schema, &new_db, &mut user).unwrap();
-        let rows = vec![
-            vec![
-                Value::Null(Column::I32), // Nulled
-                Value::String("Iron Man".to_string()),
-                Value::String("Robert Downey".to_string()),
-            ],
-            vec![
-                Value::I64(2),
-                Value::String("Spiderman".to_string()),
-                Value::String("".to_string()),
-            ],
-            vec![
-                Value::I64(3),
-                Value::Null(Column::String(50)),
-                Value::Float(322.456),
-            ],
-            vec![
-                Value::I64(4),
-                Value::String("Captain America".to_string()),
-                Value::Null(Column::Double),
-            ],
-        ];
+        // The index wasn't dropped and rebuilt, so it starts on the same page
+        assert_eq!(load(&user).indexes, old_indexes);
+        let diffs: Vec<Diff> = user.get_diffs()[num_diffs..].to_vec();
+        assert!(diffs
+            .iter()
+            .any(|diff| matches!(diff, Diff::SchemaChange(_))));
+        assert!(!diffs.iter().any(|diff| matches!(
+            diff,
+            Diff::TableCreate(_)
+                | Diff::TableRemove(_)
+                | Diff::IndexCreate(_)
+                | Diff::IndexRemove(_)
+        )));
+
+        // The rows that had to move are still found through the index
+        user.set_prefer_index_scans(true);
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        for id in [0, 150, 299] {
+            let rows: Vec<Row> = run(
+                &format!("SELECT id, name, note FROM t WHERE id = {}", id),
+                &mut user,
+            );
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0][0], Value::I64(id));
+            assert_eq!(rows[0][1], Value::String(format!("name{}", id)));
+            assert!(matches!(rows[0][2], Value::Null(_)));
+        }
+        assert_eq!(
+            run("SELECT id FROM t WHERE id >= 100", &mut user).len(),
+            200
+        );
+        delete_db_instance().unwrap();
+    }
 
-        assert!(insert(rows, "test_table1".to_string(), &new_db, &mut user).is_err());
+    #[test]
+    #[serial]
+    // Ensures that a schema change that fails on any row leaves the table and its indexes as they were
+    fn test_change_table_schema_failure() {
+        fcreate_db_instance("change_table_schema_failure");
+        let mut user: User = User::new("test_user".to_string());
+        let values: Vec<String> = (0..300).map(|i| format!("({}, 'name{}')", i, i)).collect();
+        let queries: Vec<String> = vec![
+            "CREATE TABLE t (id INT, name VARCHAR(20))".to_string(),
+            format!("INSERT INTO t VALUES {}", values.join(", ")),
+            "CREATE INDEX name_idx ON t (name)".to_string(),
+        ];
+        for query in queries {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let load = |user: &User| {
+            Table::from_user(user, get_db_instance().unwrap(), &"t".to_string(), None).unwrap()
+        };
+        let old_table: Table = load(&user);
+        let num_diffs: usize = user.get_diffs().len();
+
+        // The names can't be converted, the index on them would be dropped, and the wider
+        // rows would have to move
+        let mut schema: Schema = old_table.schema.clone();
+        schema[1].1 = Column::I32;
+        schema.push((
+            "note".to_string(),
+            Column::Nullable(Box::new(Column::String(40))),
+        ));
+        assert!(change_table_schema(
+            &"t".to_string(),
+            &schema,
+            get_db_instance().unwrap(),
+            &mut user
+        )
+        .is_err());
 
-        // Delete the test database
-        new_db.delete_database().unwrap();
+        let table: Table = load(&user);
+        assert_eq!(table.schema, old_table.schema);
+        assert_eq!(table.indexes, old_table.indexes);
+        assert_eq!(
+            table.collect::<Vec<RowInfo>>(),
+            old_table.collect::<Vec<RowInfo>>()
+        );
+        assert_eq!(user.get_diffs().len(), num_diffs);
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Test update row with one specific row
-    fn test_update_single_row() {
-        let mut user = create_demo_db("personal_info");
-        let _res = execute_update(
-            &parse("UPDATE personal_info SET age = 50 WHERE id = 1", false).unwrap(),
+    fn test_alter_drop() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let old_table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+        let old_schemas = old_table.schema.clone();
+
+        execute_update(
+            &parse("alter table personal_info drop id", false).unwrap(),
             &mut user,
             &"".to_string(),
-        );
-        let (_, results) = execute_query(
-            &parse("SELECT * from personal_info", false).unwrap(),
+        )
+        .unwrap();
+
+        let table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+
+        let schemas = table.schema.clone();
+        assert!(old_schemas.len() - 1 == schemas.len());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_alter_drop1() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let old_table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+        let old_rows = old_table.into_iter().collect::<Vec<RowInfo>>();
+
+        execute_update(
+            &parse("alter table personal_info drop id", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
-        for row in results {
-            if let Value::I64(x) = row[3] {
-                if let Value::I32(y) = row[0] {
-                    if y == 1 {
-                        assert!(x == 50);
-                    }
-                }
-            } else {
-                panic!("Invalid value type");
-            }
-        }
+
+        let table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+
+        let rows = table.into_iter().collect::<Vec<RowInfo>>();
+        assert!(rows[0].row[0] == old_rows[0].row[1]);
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Test update row with multiple rows
-    fn test_update_multiple_row() {
-        let mut user = create_demo_db("personal_info");
-        let _res = execute_update(
-            &parse("UPDATE personal_info SET age = 55", false).unwrap(),
-            &mut user,
-            &"".to_string(),
-        );
-        let (_, results) = execute_query(
-            &parse("SELECT * from personal_info", false).unwrap(),
+    fn test_alter_drop2() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let result = execute_update(
+            &parse("alter table personal_info drop missing", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
-        .unwrap();
-        for row in results {
-            if let Value::I64(x) = row[3] {
-                assert!(x == 55);
-            } else {
-                panic!("Invalid value type");
-            }
-        }
+        .unwrap_err();
+
+        assert!(result.message == "Column name missing does not exist");
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Test update row with multiple rows using more complex logic
-    fn test_update_row_predicate() {
-        let mut user = create_demo_db("personal_info");
-        let _res = execute_update(
-            &parse("UPDATE personal_info SET age = 55 WHERE id < 10", false).unwrap(),
-            &mut user,
-            &"".to_string(),
-        );
-        let (_, results) = execute_query(
-            &parse("SELECT * from personal_info", false).unwrap(),
+    // Ensures that dropping an indexed column drops the indexes that include it, and that
+    // restrict_column_drops rejects the drop unless it has CASCADE
+    fn test_alter_drop_indexed_column() {
+        fcreate_db_instance("alter_drop_indexed");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, name VARCHAR(20), age INT)",
+            "INSERT INTO t VALUES (1, 'a', 30), (2, 'b', 40), (3, 'c', 50)",
+            "CREATE INDEX name_idx ON t (name)",
+            "CREATE INDEX name_age_idx ON t (name, age)",
+            "CREATE INDEX age_idx ON t (age)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let update = |query: &str, user: &mut User| -> Result<String, String> {
+            Ok(execute_update(
+                &parse(query, false).unwrap(),
+                user,
+                &"".to_string(),
+            )?)
+        };
+        let load = |user: &User| -> Table {
+            Table::from_user(user, get_db_instance().unwrap(), &"t".to_string(), None).unwrap()
+        };
+
+        // With restrict_column_drops on, the indexes have to be dropped with CASCADE
+        update("SET restrict_column_drops = on", &mut user).unwrap();
+        let result: String = update("ALTER TABLE t DROP COLUMN name", &mut user).unwrap_err();
+        assert!(result.contains("name_age_idx"), "{}", result);
+        assert!(result.contains("CASCADE"), "{}", result);
+        assert_eq!(load(&user).schema.len(), 3);
+        assert_eq!(load(&user).indexes.len(), 3);
+
+        let num_diffs: usize = user.get_diffs().len();
+        let result: String = update("ALTER TABLE t DROP COLUMN name CASCADE", &mut user).unwrap();
+        assert!(result.contains("Index dropped: name_idx"), "{}", result);
+        assert!(result.contains("Index dropped: name_age_idx"), "{}", result);
+
+        // Only the index on age is left, and it points at the column's new position
+        let table: Table = load(&user);
+        let index_names: Vec<String> = table.indexes.values().map(|(_, n)| n.clone()).collect();
+        assert_eq!(index_names, vec!["age_idx".to_string()]);
+        assert!(table.indexes.contains_key(&vec![1]));
+        let mut removed: Vec<String> = user.get_diffs()[num_diffs..]
+            .iter()
+            .flat_map(|diff| match diff {
+                Diff::IndexRemove(diff) => diff.indexes.iter().map(|(n, _)| n.clone()).collect(),
+                _ => vec![],
+            })
+            .collect();
+        removed.sort();
+        assert_eq!(removed, vec!["name_age_idx", "name_idx"]);
+
+        // The dropped indexes can't be found anymore, and the kept one still works
+        assert!(update("DROP INDEX t.name_idx", &mut user).is_err());
+        let (_, rows) = execute_query(
+            &parse("SELECT id FROM t WHERE age = 40", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
-        for row in results {
-            if let Value::I64(x) = row[3] {
-                if let Value::I32(y) = row[0] {
-                    if y < 10 {
-                        assert!(x == 55);
-                    }
-                }
-            } else {
-                panic!("Invalid value type");
-            }
-        }
+        assert_eq!(rows, vec![vec![Value::I64(2)]]);
+
+        // Without the setting, the indexes are dropped along with the column
+        update("SET restrict_column_drops = off", &mut user).unwrap();
+        let result: String = update("ALTER TABLE t DROP COLUMN age", &mut user).unwrap();
+        assert!(result.contains("Index dropped: age_idx"), "{}", result);
+        assert_eq!(load(&user).indexes.len(), 0);
         delete_db_instance().unwrap();
     }
+
     #[test]
     #[serial]
-    // Test delete a single row from the database
-    fn test_delete_single_row() {
-        let mut user = create_demo_db("personal_info");
-        let _res = execute_update(
-            &parse("DELETE FROM personal_info WHERE id = 27", false).unwrap(),
-            &mut user,
-            &"".to_string(),
-        );
-        let (_, results) = execute_query(
-            &parse("SELECT * from personal_info", false).unwrap(),
+    fn test_alter_change() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let result = execute_update(
+            &parse(
+                "alter table personal_info change missing something int",
+                false,
+            )
+            .unwrap(),
             &mut user,
             &"".to_string(),
         )
-        .unwrap();
-        assert!(results.len() == 24);
-        for row in results {
-            if let Value::I32(x) = row[0] {
-                assert!(x != 27);
-            } else {
-                panic!("Invalid value type");
-            }
-        }
+        .unwrap_err();
+
+        assert!(result.message == "Column name missing does not exist");
         delete_db_instance().unwrap();
     }
+
     #[test]
     #[serial]
-    // Test deleting multiple rows on the database
-    fn test_delete_multiple_rows() {
-        let mut user = create_demo_db("personal_info");
-        let _res = execute_update(
-            &parse("DELETE FROM personal_info WHERE id < 25", false).unwrap(),
-            &mut user,
-            &"".to_string(),
-        );
-        let (_, results) = execute_query(
-            &parse("SELECT * from personal_info", false).unwrap(),
+    fn test_alter_change1() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let old_table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+        let old_rows = old_table.into_iter().collect::<Vec<RowInfo>>();
+
+        execute_update(
+            &parse("alter table personal_info change id missing int", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
-        assert!(results.len() == 16);
+
+        let table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+        let rows = table.into_iter().collect::<Vec<RowInfo>>();
+
+        assert!(rows[0].row[0] == old_rows[0].row[0]);
         delete_db_instance().unwrap();
     }
+
     #[test]
     #[serial]
-    // Test order by command ASC
-    fn test_order_by_asc() {
-        let mut user = create_demo_db("personal_info");
-        let (_, results) = execute_query(
-            &parse("SELECT * from personal_info ORDER BY id ASC", false).unwrap(),
+    fn test_alter_change2() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let old_table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+        let old_schemas = old_table.schema.clone();
+
+        execute_update(
+            &parse("alter table personal_info change id missing int", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
-        let mut temp = 0;
-        for row in results {
-            if let Value::I32(x) = row[0] {
-                assert!(x >= temp);
-                temp = x;
-            } else {
-                panic!("Invalid value type");
-            }
-        }
+
+        let table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"personal_info".to_string(),
+            None,
+        )
+        .unwrap();
+        let schemas = table.schema.clone();
+        assert!(old_schemas[0] != schemas[0] && schemas[0].0 == "missing");
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Test order by command DESC
-    fn test_order_by_desc() {
-        let mut user = create_demo_db("personal_info");
-        let (_, results) = execute_query(
-            &parse("SELECT * from personal_info ORDER BY id DESC", false).unwrap(),
+    fn test_alter_change3() {
+        let mut user = create_demo_db("alter_test");
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&"main".to_string(), &mut user)
+            .unwrap();
+
+        let result = execute_update(
+            &parse(
+                "alter table personal_info change id missing varchar(50)",
+                false,
+            )
+            .unwrap(),
             &mut user,
             &"".to_string(),
         )
-        .unwrap();
-        let mut temp = 100;
-        for row in results {
-            if let Value::I32(x) = row[0] {
-                assert!(x <= temp);
-                temp = x;
-            } else {
-                panic!("Invalid value type");
-            }
-        }
+        .unwrap_err();
+
+        assert!(result.message.starts_with(&"Unexpected Type".to_string()));
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Test order by command DESC
-    fn test_order_by_select() {
-        let mut user = create_demo_db("personal_info");
-        let (_, results) = execute_query(
-            &parse("SELECT age, id from personal_info ORDER BY age DESC", false).unwrap(),
-            &mut user,
-            &"".to_string(),
-        )
-        .unwrap();
-        let mut temp = 200;
-        for row in results {
-            if let Value::I64(x) = row[0] {
-                assert!(x <= temp);
-                temp = x;
-            } else {
-                panic!("Invalid value type");
-            }
+    // Ensures that a column change is checked against every row before the table is touched,
+    // and that a dry run reports the rows that don't fit without changing anything
+    fn test_alter_change_dry_run() {
+        fcreate_db_instance("alter_change_dry_run");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, name VARCHAR(20))",
+            "INSERT INTO t VALUES (1, 'ab'), (3000000000, 'abcdef'), (2, 'xyz'), (2, 'abcd')",
+            "SET alter_dry_run = on",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
         }
+        let update = |query: &str, user: &mut User| -> Result<String, String> {
+            Ok(execute_update(
+                &parse(query, false).unwrap(),
+                user,
+                &"".to_string(),
+            )?)
+        };
+        let load = |user: &User| -> (Schema, Vec<Row>) {
+            let table: Table =
+                Table::from_user(user, get_db_instance().unwrap(), &"t".to_string(), None).unwrap();
+            (table.schema.clone(), table.map(|r| r.row).collect())
+        };
+        let (old_schema, old_rows) = load(&user);
+
+        // Narrowing the type lists the one row that doesn't fit
+        let result: String = update("ALTER TABLE t CHANGE id id SMALLINT", &mut user).unwrap_err();
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", result);
+        assert!(lines[0].contains("I64(3000000000)"), "{}", result);
+        assert!(lines[0].contains("String(abcdef)"), "{}", result);
+        assert!(
+            lines[1].starts_with("1 rows can't be changed"),
+            "{}",
+            result
+        );
+
+        let result: String =
+            update("ALTER TABLE t CHANGE name name VARCHAR(3)", &mut user).unwrap_err();
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", result);
+        assert!(lines[0].contains("String(abcdef)"), "{}", result);
+        assert!(lines[1].contains("String(abcd)"), "{}", result);
+
+        let result: String = update("ALTER TABLE t ADD UNIQUE (id)", &mut user).unwrap_err();
+        assert!(result.starts_with("Duplicate key [I64(2)]"), "{}", result);
+
+        // A change that fits every row is reported, but not made
+        let result: String =
+            update("ALTER TABLE t CHANGE name label VARCHAR(30)", &mut user).unwrap();
+        assert!(result.starts_with("Dry run"), "{}", result);
+        assert_eq!(load(&user), (old_schema.clone(), old_rows.clone()));
+
+        // Without a dry run, the change is still checked before the table is rebuilt
+        update("SET alter_dry_run = off", &mut user).unwrap();
+        assert!(update("ALTER TABLE t CHANGE id id SMALLINT", &mut user).is_err());
+        assert_eq!(load(&user), (old_schema, old_rows));
+        update("ALTER TABLE t CHANGE name label VARCHAR(30)", &mut user).unwrap();
+        assert_eq!(load(&user).0[1], ("label".to_string(), Column::String(30)));
+        delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    // Test set operation with incompatible types
-    fn test_set_operations_incompatible_types() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    fn test_query_column_types() {
+        let mut user = create_demo_db("query_column_types");
 
-        let results = execute_query(
+        let (columns, types, rows) = execute_query_with_types(
             &parse(
-                "SELECT * from personal_info UNION SELECT * from locations",
+                "SELECT id, first_name, P.height, age * 1.5, id + 1, age + id, COUNT(*), \
+                 date_inserted, id > 1 FROM personal_info P GROUP BY id",
                 false,
             )
             .unwrap(),
             &mut user,
             &"".to_string(),
         )
-        .unwrap_err();
-        assert!(results == "Incompatible types in set operation");
+        .unwrap();
+        assert_eq!(columns.len(), 9);
+        assert_eq!(
+            types,
+            vec![
+                Column::I32,
+                Column::String(256),
+                Column::Nullable(Box::new(Column::Float)),
+                Column::Double,
+                Column::I64,
+                Column::I64,
+                Column::I32,
+                Column::Timestamp,
+                Column::Bool,
+            ]
+        );
+
+        // The types match the values that are returned, other than the width and nullability
+        for row in rows {
+            for (value, coltype) in row.iter().zip(types.iter()) {
+                if !matches!(coltype, Column::String(_) | Column::Nullable(_)) {
+                    assert_eq!(&value.get_coltype(), coltype);
+                }
+            }
+        }
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_set_operations_union0() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // The types are worked out from the expressions, so one that would fail on some row, like
+    // dividing by zero, still works on the rows of the table that it doesn't fail on
+    fn test_query_column_types_sample_fails() {
+        fcreate_db_instance("query_column_types_sample_fails");
+        let mut user: User = User::new("test_user".to_string());
+        for query in [
+            "CREATE TABLE t (id INT)",
+            "INSERT INTO t VALUES (1), (2), (3)",
+        ] {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
 
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("first_name".to_string(), Column::String(50)),
-            ("last_name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I64),
-            ("height".to_string(), Column::Float),
-            ("date_inserted".to_string(), Column::Timestamp),
-        ];
-        create_table(
-            &"test_table".to_string(),
-            &schema,
-            get_db_instance().unwrap(),
+        let (_, types, rows) = execute_query_with_types(
+            &parse("SELECT 100 / (id - 1), id <=> 2 FROM t WHERE id > 1", false).unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(types, vec![Column::I64, Column::Bool]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::I64(100), Value::Bool(true)],
+                vec![Value::I64(50), Value::Bool(false)]
+            ]
+        );
 
-        let results = execute_query(
-            &parse(
-                "SELECT * from personal_info UNION SELECT * from test_table",
-                false,
-            )
-            .unwrap(),
+        // An expression that can't be solved at all is an error, rather than a guessed type
+        assert!(execute_query_with_types(
+            &parse("SELECT id + TRUE FROM t", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
-        .unwrap();
+        .is_err());
+        delete_db_instance().unwrap();
+    }
 
-        let results2 = execute_query(
-            &parse("SELECT * from personal_info", false).unwrap(),
+    #[test]
+    #[serial]
+    fn test_commits_table() {
+        fcreate_db_instance("commits_table");
+        let mut user: User = User::new("test_user".to_string());
+        let mut reviewer: User = User::new("reviewer".to_string());
+
+        // Before the first commit, the table is empty
+        let query: &str = "SELECT hash, author, message FROM gql_commits";
+        let (columns, results) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(columns, vec!["hash", "author", "message"]);
+        assert_eq!(results.len(), 0);
+
+        execute_update(
+            &parse("CREATE TABLE people (id INT)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        execute_update(
+            &parse("INSERT INTO people VALUES (1), (2)", true).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
+        let (_, initial) = get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Initial".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
+        execute_update(
+            &parse("INSERT INTO people VALUES (3)", true).unwrap(),
+            &mut reviewer,
+            &"".to_string(),
+        )
+        .unwrap();
+        let (_, review) = get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Review".to_string(), &"".to_string(), &mut reviewer, None)
+            .unwrap();
 
-        assert!(results == results2);
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(results.len(), 2);
+
+        let query: &str = "SELECT hash, message, diffs, rows_inserted FROM gql_commits \
+            WHERE author = 'test_user'";
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(
+            results,
+            vec![vec![
+                Value::String(initial.hash),
+                Value::String("Initial".to_string()),
+                Value::I64(2),
+                Value::I64(2),
+            ]]
+        );
+
+        let query: &str = "SELECT c.hash FROM gql_commits c WHERE c.author = 'reviewer'";
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(results, vec![vec![Value::String(review.hash)]]);
+
+        // The table is read-only
+        let query: &str = "INSERT INTO gql_commits VALUES ('a', 'b', 'c', NULL, 0, 0, 0, 0)";
+        let result = execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string());
+        assert!(result.is_err());
+        let query: &str = "DELETE FROM gql_commits";
+        let result = execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string());
+        assert!(result.is_err());
+        let query: &str = "CREATE TABLE gql_commits (id INT)";
+        let result = execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string());
+        assert!(result.is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_set_operations_union1() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
-
-        let results = execute_query(
+    fn test_indexes_table() {
+        fcreate_db_instance("indexes_table");
+        let mut user: User = User::new("test_user".to_string());
+        execute_update(
             &parse(
-                "select * from personal_info where age > 25 union select * from personal_info where age <= 25 ORDER BY id desc;",
-                false,
+                "CREATE TABLE people (id INT, name VARCHAR(20), age INT)",
+                true,
             )
             .unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
+        let query: &str = "SELECT table_name, index_name, columns, unique FROM gql_indexes";
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(results.len(), 0);
 
-        let results2 = execute_query(
-            &parse("SELECT * from personal_info ORDER BY id desc;", false).unwrap(),
+        execute_update(
+            &parse("CREATE INDEX people_name_age ON people (name, age)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        execute_update(
+            &parse("CREATE UNIQUE INDEX people_id ON people (id)", true).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![
+                    Value::String("people".to_string()),
+                    Value::String("people_id".to_string()),
+                    Value::String("id".to_string()),
+                    Value::Bool(true),
+                ],
+                vec![
+                    Value::String("people".to_string()),
+                    Value::String("people_name_age".to_string()),
+                    Value::String("name, age".to_string()),
+                    Value::Bool(false),
+                ],
+            ]
+        );
 
-        assert!(results == results2);
+        let query: &str = "SELECT index_name FROM gql_indexes WHERE unique = false";
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(
+            results,
+            vec![vec![Value::String("people_name_age".to_string())]]
+        );
+
+        let query: &str = "DELETE FROM gql_indexes";
+        let result = execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string());
+        assert!(result.is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_set_operations_except0() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
-
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("first_name".to_string(), Column::String(50)),
-            ("last_name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I64),
-            ("height".to_string(), Column::Float),
-            ("date_inserted".to_string(), Column::Timestamp),
+    // Comparisons and other predicates can be selected, giving a boolean column
+    fn test_select_predicate() {
+        fcreate_db_instance("select_predicate");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(20) NULL, age INT)",
+            "INSERT INTO people VALUES (1, 'Ann', 20)",
+            "INSERT INTO people VALUES (2, NULL, 12)",
+            "INSERT INTO people VALUES (3, 'Cat', 18)",
         ];
-        create_table(
-            &"test_table".to_string(),
-            &schema,
-            get_db_instance().unwrap(),
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+
+        let (columns, rows) = execute_query(
+            &parse("SELECT id, age >= 18 AS is_adult FROM people", false).unwrap(),
             &mut user,
+            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(columns, vec!["id", "is_adult"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::I64(1), Value::Bool(true)],
+                vec![Value::I64(2), Value::Bool(false)],
+                vec![Value::I64(3), Value::Bool(true)],
+            ]
+        );
 
-        let results = execute_query(
+        // Predicates that aren't comparisons, and boolean literals
+        let (_, rows) = execute_query(
             &parse(
-                "SELECT * from personal_info except SELECT * from test_table",
+                "SELECT name IS NULL, id IN (1, 2), TRUE FROM people WHERE id < 3",
                 false,
             )
             .unwrap(),
@@ -3046,529 +9188,1219 @@ pub mod tests {
             &"".to_string(),
         )
         .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Bool(false), Value::Bool(true), Value::Bool(true)],
+                vec![Value::Bool(true), Value::Bool(true), Value::Bool(true)],
+            ]
+        );
 
-        let results2 = execute_query(
-            &parse("SELECT * from personal_info", false).unwrap(),
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_time_column() {
+        fcreate_db_instance("time_column");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE schedule (name VARCHAR(20), starts_at TIME)",
+            "INSERT INTO schedule VALUES ('lunch', '12:30:00'), ('standup', '09:15:00'), \
+            ('review', '16:05:30'), ('breakfast', '07:00:00')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<(Vec<Column>, Vec<Row>), String> {
+            let (_, types, rows) =
+                execute_query_with_types(&parse(query, false).unwrap(), user, &"".to_string())?;
+            Ok((types, rows))
+        };
+
+        let (types, rows) = run(
+            "SELECT name, starts_at FROM schedule ORDER BY starts_at",
             &mut user,
-            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(types[1], Column::Time);
+        let times: Vec<(String, String)> = rows
+            .iter()
+            .map(|row| match (&row[0], &row[1]) {
+                (Value::String(name), Value::Time(time)) => {
+                    (name.clone(), format_time_of_day(*time))
+                }
+                row => panic!("Unexpected row {:?}", row),
+            })
+            .collect();
+        assert_eq!(
+            times,
+            vec![
+                ("breakfast".to_string(), "07:00:00".to_string()),
+                ("standup".to_string(), "09:15:00".to_string()),
+                ("lunch".to_string(), "12:30:00".to_string()),
+                ("review".to_string(), "16:05:30".to_string()),
+            ]
+        );
 
-        assert!(results == results2);
+        // Times can be compared with literals
+        let (_, rows) = run(
+            "SELECT name FROM schedule WHERE starts_at >= '12:00:00' ORDER BY starts_at DESC",
+            &mut user,
+        )
+        .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::String("review".to_string())],
+                vec![Value::String("lunch".to_string())]
+            ]
+        );
+
+        let err: String = execute_update(
+            &parse("INSERT INTO schedule VALUES ('late', '25:00:00')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap_err()
+        .message;
+        assert!(
+            err.contains("Could not parse value 25:00:00 into type Time"),
+            "{}",
+            err
+        );
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_set_operations_except1() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // CHAR columns are padded to their length and ignore trailing spaces, unlike VARCHAR
+    fn test_char_trailing_spaces() {
+        fcreate_db_instance("char_trailing_spaces");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, c CHAR(5), v VARCHAR(5))",
+            "INSERT INTO t VALUES (1, 'ab', 'ab ')",
+            "INSERT INTO t VALUES (2, 'ab   ', 'ab')",
+            "INSERT INTO t VALUES (3, 'abc', 'abc')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
 
-        let results = execute_query(
-            &parse(
-                "select * from personal_info except select * from personal_info where age <= 25;",
-                false,
-            )
-            .unwrap(),
+        assert_eq!(
+            run("SELECT c FROM t WHERE id = 1", &mut user).unwrap(),
+            vec![vec![Value::Char("ab   ".to_string())]]
+        );
+        assert_eq!(
+            run("SELECT id FROM t WHERE c = 'ab'", &mut user).unwrap(),
+            vec![vec![Value::I64(1)], vec![Value::I64(2)]]
+        );
+        assert_eq!(
+            run("SELECT id FROM t WHERE c = 'ab '", &mut user).unwrap(),
+            vec![vec![Value::I64(1)], vec![Value::I64(2)]]
+        );
+        assert_eq!(
+            run("SELECT id FROM t WHERE v = 'ab'", &mut user).unwrap(),
+            vec![vec![Value::I64(1)], vec![Value::I64(2)]]
+        );
+        assert_eq!(
+            run("SELECT id FROM t WHERE c = v", &mut user).unwrap(),
+            vec![
+                vec![Value::I64(1)],
+                vec![Value::I64(2)],
+                vec![Value::I64(3)]
+            ]
+        );
+
+        // Values that are equal fall into the same group, whichever string type they have
+        let values: HashSet<Value> = HashSet::from([
+            Value::Char("ab   ".to_string()),
+            Value::String("ab".to_string()),
+            Value::String("ab ".to_string()),
+        ]);
+        assert_eq!(values.len(), 1);
+        let rows: HashSet<Row> = HashSet::from([
+            vec![
+                Value::Char("ab   ".to_string()),
+                Value::String("ab ".to_string()),
+            ],
+            vec![
+                Value::String("ab".to_string()),
+                Value::Char("ab".to_string()),
+            ],
+        ]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            run("SELECT c, COUNT(*) FROM t GROUP BY c ORDER BY c", &mut user).unwrap(),
+            vec![
+                vec![Value::Char("ab   ".to_string()), Value::I64(2)],
+                vec![Value::Char("abc  ".to_string()), Value::I64(1)]
+            ]
+        );
+        assert_eq!(
+            run("SELECT COUNT(*) FROM t GROUP BY v ORDER BY v", &mut user).unwrap(),
+            vec![vec![Value::I64(2)], vec![Value::I64(1)]]
+        );
+
+        // Only the characters before the padding count towards the length
+        execute_update(
+            &parse("INSERT INTO t VALUES (4, 'abcde    ', 'a')", true).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
-
-        let results2 = execute_query(
-            &parse("select * from personal_info where age > 25;", false).unwrap(),
+        assert!(execute_update(
+            &parse("INSERT INTO t VALUES (5, 'abcdef', 'a')", true).unwrap(),
             &mut user,
             &"".to_string(),
         )
-        .unwrap();
-
-        assert!(results == results2);
+        .is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_set_operations_intersect() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // The rows that an insert wrote can be returned, including the values filled in for DEFAULT
+    fn test_insert_returning() {
+        fcreate_db_instance("insert_returning");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| -> Result<String, String> {
+            Ok(execute_update(&parse(query, true)?, user, &"".to_string())?)
+        };
+        run(
+            "CREATE TABLE people (id INT, name VARCHAR(10) NULL, code CHAR(3))",
+            &mut user,
+        )
+        .unwrap();
 
-        let schema: Schema = vec![
-            ("id".to_string(), Column::I32),
-            ("first_name".to_string(), Column::String(50)),
-            ("last_name".to_string(), Column::String(50)),
-            ("age".to_string(), Column::I64),
-            ("height".to_string(), Column::Float),
-            ("date_inserted".to_string(), Column::Timestamp),
-        ];
-        create_table(
-            &"test_table".to_string(),
-            &schema,
-            get_db_instance().unwrap(),
+        let result: String = run(
+            "INSERT INTO people VALUES (1, DEFAULT, 'a'), (2, 'Bo', 'b') RETURNING id, name",
             &mut user,
         )
         .unwrap();
+        assert_eq!(
+            result.lines().collect::<Vec<&str>>(),
+            vec![
+                "2 rows were successfully inserted.",
+                "╭────┬──────╮",
+                "│ id │ name │",
+                "├────┼──────┤",
+                "│ 1  │ NULL │",
+                "│ 2  │ 'Bo' │",
+                "╰────┴──────╯",
+            ]
+        );
 
-        let results = execute_query(
-            &parse(
-                "SELECT * from personal_info intersect SELECT * from test_table",
-                false,
-            )
-            .unwrap(),
+        // Every column, with CHAR padding, and expressions of the inserted values
+        let result: String = run(
+            "INSERT INTO people (id, name, code) VALUES (3, 'Cy', 'c') RETURNING *, id * 10",
             &mut user,
-            &"".to_string(),
         )
         .unwrap();
+        assert!(result.contains("│ id │ name │ code  │ id * 10 │"));
+        assert!(result.contains("│ 3  │ 'Cy' │ 'c  ' │ 30      │"));
 
-        let results2 = execute_query(
-            &parse("SELECT * from test_table", false).unwrap(),
+        // The returned expressions are passed along next to the statement, not in it
+        let parsed: Vec<ParsedStatement> = parse(
+            "INSERT INTO people VALUES (4, 'Di', 'd') RETURNING id AS key",
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed[0].statement.to_string(),
+            "INSERT INTO people VALUES (4, 'Di', 'd')"
+        );
+        assert_eq!(
+            parsed[0].clauses.returning.as_ref().unwrap()[0].to_string(),
+            "id AS key"
+        );
+
+        assert!(run(
+            "INSERT INTO people VALUES (4, 'Di', 'd') RETURNING",
+            &mut user
+        )
+        .is_err());
+        // Nothing is inserted if the returned expressions are invalid
+        assert!(run(
+            "INSERT INTO people VALUES (4, 'Di', 'd') RETURNING age",
+            &mut user
+        )
+        .is_err());
+        let (_, rows) = execute_query(
+            &parse("SELECT id FROM people", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::I64(1)],
+                vec![Value::I64(2)],
+                vec![Value::I64(3)]
+            ]
+        );
 
-        assert!(results == results2);
+        // Without RETURNING, only the message is returned
+        assert_eq!(
+            run("INSERT INTO people VALUES (4, 'Di', 'd')", &mut user).unwrap(),
+            "1 rows were successfully inserted."
+        );
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_set_operations_intersect1() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // Rows with the same key as a row in the table are skipped, along with repeated keys
+    fn test_insert_on_conflict_do_nothing() {
+        fcreate_db_instance("insert_on_conflict_do_nothing");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| -> Result<String, String> {
+            Ok(execute_update(&parse(query, true)?, user, &"".to_string())?)
+        };
+        run("CREATE TABLE people (id INT, name VARCHAR(10))", &mut user).unwrap();
+        run("INSERT INTO people VALUES (1, 'Ann'), (2, 'Bo')", &mut user).unwrap();
 
-        let results = execute_query(
-            &parse(
-                "select * from personal_info where age > 27 intersect select * from personal_info where age <= 28;",
-                false,
+        // The conflict target needs a unique index
+        assert!(run(
+            "INSERT INTO people VALUES (2, 'Cy') ON CONFLICT (id) DO NOTHING",
+            &mut user
+        )
+        .is_err());
+        run("CREATE UNIQUE INDEX people_id ON people (id)", &mut user).unwrap();
+
+        assert_eq!(
+            run(
+                "INSERT INTO people VALUES (2, 'Cy'), (3, 'Di'), (3, 'Ed') ON CONFLICT (id) DO NOTHING",
+                &mut user
             )
             .unwrap(),
+            "1 rows were successfully inserted, and 0 rows were updated."
+        );
+        // Without a target, every unique index is checked
+        let result: String = run(
+            "INSERT INTO people VALUES (1, 'Fay'), (4, 'Gus') ON CONFLICT DO NOTHING RETURNING name",
             &mut user,
-            &"".to_string(),
         )
         .unwrap();
+        assert!(result.contains("'Gus'"));
+        assert!(!result.contains("'Fay'"));
 
-        let results2 = execute_query(
-            &parse("select * from personal_info where age = 28;", false).unwrap(),
+        let (_, rows) = execute_query(
+            &parse("SELECT id, name FROM people ORDER BY id", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::I64(1), Value::String("Ann".to_string())],
+                vec![Value::I64(2), Value::String("Bo".to_string())],
+                vec![Value::I64(3), Value::String("Di".to_string())],
+                vec![Value::I64(4), Value::String("Gus".to_string())],
+            ]
+        );
 
-        assert!(results == results2);
+        // The clause is passed along next to the statement, not in it
+        let parsed: Vec<ParsedStatement> = parse(
+            "INSERT INTO people VALUES (1, 'Hal') ON CONFLICT DO NOTHING",
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed[0].statement.to_string(),
+            "INSERT INTO people VALUES (1, 'Hal')"
+        );
+        assert_eq!(
+            parsed[0].clauses.on_conflict,
+            Some(OnConflict {
+                columns: Vec::new(),
+                action: ConflictAction::DoNothing,
+            })
+        );
+
+        // A plain insert still fails on the duplicate
+        assert!(run("INSERT INTO people VALUES (1, 'Hal')", &mut user).is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_set_operations_nested0() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // Rows with the same key as a row in the table update that row instead of being inserted
+    fn test_insert_on_conflict_do_update() {
+        fcreate_db_instance("insert_on_conflict_do_update");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| -> Result<String, String> {
+            Ok(execute_update(&parse(query, true)?, user, &"".to_string())?)
+        };
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(10), visits INT)",
+            "CREATE UNIQUE INDEX people_id ON people (id)",
+            "INSERT INTO people VALUES (1, 'Ann', 1), (2, 'Bo', 1)",
+        ];
+        for query in queries {
+            run(query, &mut user).unwrap();
+        }
 
-        let results = execute_query(
-            &parse(
-                "select * from personal_info where age > 27 union select * from personal_info where age <= 28 intersect select * from personal_info where age > 25;",
-                false,
-            )
-            .unwrap(),
+        // The new values can use the row in the table, and the row that wasn't inserted
+        let result: String = run(
+            "INSERT INTO people (id, name, visits) VALUES (2, 'Bob', 1), (3, 'Cy', 1) \
+             ON CONFLICT (id) DO UPDATE SET name = excluded.name, visits = people.visits + excluded.visits \
+             RETURNING id, name, visits",
             &mut user,
-            &"".to_string(),
         )
         .unwrap();
+        assert!(result.starts_with("1 rows were successfully inserted, and 1 rows were updated."));
+        assert!(result.contains("│ 2  │ 'Bob' │ 2      │"));
+        assert!(result.contains("│ 3  │ 'Cy'  │ 1      │"));
 
-        let results2 = execute_query(
-            &parse("select * from personal_info where age > 25;", false).unwrap(),
+        run(
+            "INSERT INTO people VALUES (1, 'Al', 5) ON CONFLICT (id) DO UPDATE SET visits = visits * 10",
+            &mut user,
+        )
+        .unwrap();
+        let (_, rows) = execute_query(
+            &parse("SELECT id, name, visits FROM people ORDER BY id", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Value::I64(1),
+                    Value::String("Ann".to_string()),
+                    Value::I64(10)
+                ],
+                vec![
+                    Value::I64(2),
+                    Value::String("Bob".to_string()),
+                    Value::I64(2)
+                ],
+                vec![
+                    Value::I64(3),
+                    Value::String("Cy".to_string()),
+                    Value::I64(1)
+                ],
+            ]
+        );
 
-        assert!(results == results2);
+        // The same row can't be updated twice by one insert
+        assert!(run(
+            "INSERT INTO people VALUES (1, 'A', 1), (1, 'B', 1) ON CONFLICT (id) DO UPDATE SET name = excluded.name",
+            &mut user
+        )
+        .is_err());
+        assert!(run(
+            "INSERT INTO people VALUES (1, 'A', 1) ON CONFLICT (id) DO UPDATE name = 'A'",
+            &mut user
+        )
+        .is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_set_operations_nested1() {
-        let mut user = create_demo_db("set_op_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // Updates return the rows they changed as they are afterwards, and deletes return the rows
+    // they removed as they were before
+    fn test_update_delete_returning() {
+        fcreate_db_instance("update_delete_returning");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| -> Result<String, String> {
+            Ok(execute_update(&parse(query, true)?, user, &"".to_string())?)
+        };
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(10), age INT)",
+            "INSERT INTO people VALUES (1, 'Ann', 20), (2, 'Bo', 30), (3, 'Cy', 40)",
+        ];
+        for query in queries {
+            run(query, &mut user).unwrap();
+        }
 
-        let results = execute_query(
-            &parse(
-                "select * from personal_info where age > 28
-                    union select * from personal_info where age > 20 and age <= 34
-                    intersect select * from personal_info where age > 30 ORDER BY id desc;",
-                false,
-            )
-            .unwrap(),
+        let result: String = run(
+            "UPDATE people SET age = age + 1 WHERE id < 3 RETURNING id, age",
             &mut user,
-            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(
+            result.lines().collect::<Vec<&str>>(),
+            vec![
+                "2 rows were successfully updated.",
+                "╭────┬─────╮",
+                "│ id │ age │",
+                "├────┼─────┤",
+                "│ 1  │ 21  │",
+                "│ 2  │ 31  │",
+                "╰────┴─────╯",
+            ]
+        );
 
-        let results2 = execute_query(
-            &parse(
-                "select * from personal_info where age > 28 ORDER BY id desc;",
-                false,
-            )
-            .unwrap(),
+        let result: String = run(
+            "DELETE FROM people WHERE age > 30 ORDER BY age DESC LIMIT 1 RETURNING *",
             &mut user,
-            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(
+            result.lines().collect::<Vec<&str>>(),
+            vec![
+                "1 rows were deleted.",
+                "╭────┬──────┬─────╮",
+                "│ id │ name │ age │",
+                "├────┼──────┼─────┤",
+                "│ 3  │ 'Cy' │ 40  │",
+                "╰────┴──────┴─────╯",
+            ]
+        );
 
-        assert!(results == results2);
-        delete_db_instance().unwrap();
-    }
-
-    #[test]
-    #[serial]
-    fn test_alter_no_table() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+        // The returned expressions are passed along next to the statement, not as table hints
+        let parsed: Vec<ParsedStatement> =
+            parse("UPDATE people p SET age = 0 RETURNING id, age", true).unwrap();
+        assert_eq!(
+            parsed[0].statement.to_string(),
+            "UPDATE people AS p SET age = 0"
+        );
+        assert_eq!(
+            parsed[0].clauses.returning.as_ref().unwrap()[0].to_string(),
+            "id"
+        );
 
-        let results = execute_update(
-            &parse("alter table missing add test_column int", false).unwrap(),
+        // Nothing is written if the returned expressions are invalid
+        assert!(run("DELETE FROM people RETURNING height", &mut user).is_err());
+        assert!(run("UPDATE people SET age = 0 RETURNING height", &mut user).is_err());
+        assert!(run("DELETE FROM people RETURNING * LIMIT 1", &mut user).is_err());
+        let (_, rows) = execute_query(
+            &parse("SELECT id, age FROM people", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
-        .unwrap_err();
-
-        assert!(results == "Table missing does not exist".to_string());
+        .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::I64(1), Value::I64(21)],
+                vec![Value::I64(2), Value::I64(31)],
+            ]
+        );
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_add() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // Strings sort by their bytes by default, or ignoring case with a case insensitive collation
+    fn test_collation() {
+        fcreate_db_instance("collation");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE fruit (id INT, name VARCHAR(10))",
+            "INSERT INTO fruit VALUES (1, 'banana'), (2, 'Apple'), (3, 'apple')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let names = |names: Vec<&str>| -> Vec<Row> {
+            names
+                .into_iter()
+                .map(|name| vec![Value::String(name.to_string())])
+                .collect()
+        };
 
-        let old_table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
-        )
-        .unwrap();
-        let old_schemas = old_table.schema.clone();
+        let query: &str = "SELECT name FROM fruit WHERE id < 3 ORDER BY name";
+        assert_eq!(run(query, &mut user), names(vec!["Apple", "banana"]));
+        let query: &str = "SELECT name FROM fruit WHERE id > 1 ORDER BY name";
+        assert_eq!(run(query, &mut user), names(vec!["Apple", "apple"]));
 
         execute_update(
-            &parse("alter table personal_info add test_column int", false).unwrap(),
+            &parse("SET collation = 'case_insensitive'", true).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
+        let query: &str = "SELECT name FROM fruit WHERE id > 1 ORDER BY name DESC, id";
+        assert_eq!(run(query, &mut user), names(vec!["Apple", "apple"]));
+        let query: &str = "SELECT name FROM fruit ORDER BY name DESC, id";
+        assert_eq!(
+            run(query, &mut user),
+            names(vec!["banana", "Apple", "apple"])
+        );
 
-        let table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
-        )
-        .unwrap();
+        // Names that only differ in case are in the same group
+        assert_eq!(
+            run(
+                "SELECT COUNT(id) FROM fruit GROUP BY name ORDER BY COUNT(id)",
+                &mut user
+            ),
+            vec![vec![Value::I64(1)], vec![Value::I64(2)]]
+        );
 
-        let schemas = table.schema.clone();
-        assert!(old_schemas.len() + 1 == schemas.len());
+        assert!(execute_update(
+            &parse("SET collation = 'french'", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_add1() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
+    // In safe update mode, updates and deletes need a where clause
+    fn test_safe_updates() {
+        fcreate_db_instance("safe_updates");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, v INT)",
+            "INSERT INTO t VALUES (1, 10), (2, 20), (3, 30)",
+            "SET safe_updates = on",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<String, String> {
+            Ok(execute_update(
+                &parse(query, true).unwrap(),
+                user,
+                &"".to_string(),
+            )?)
+        };
+        let count = |user: &mut User| -> usize {
+            execute_query(
+                &parse("SELECT * FROM t", false).unwrap(),
+                user,
+                &"".to_string(),
+            )
             .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+            .1
+            .len()
+        };
 
-        let old_table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
-        )
-        .unwrap();
-        let old_schemas = old_table.schema.clone();
+        assert!(run("DELETE FROM t", &mut user).is_err());
+        assert!(run("UPDATE t SET v = 0", &mut user).is_err());
+        assert_eq!(count(&mut user), 3);
+        run("DELETE FROM t WHERE id = 1", &mut user).unwrap();
+        run("UPDATE t SET v = 0 WHERE id = 2", &mut user).unwrap();
+        assert_eq!(count(&mut user), 2);
 
-        execute_update(
-            &parse("alter table personal_info add test_column int", false).unwrap(),
+        run("SET safe_updates = off", &mut user).unwrap();
+        run("DELETE FROM t", &mut user).unwrap();
+        assert_eq!(count(&mut user), 0);
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // EXPLAIN ANALYZE runs the query, and says how many rows each step produced
+    fn test_explain_analyze() {
+        fcreate_db_instance("explain_analyze");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(20))",
+            "INSERT INTO people VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Cal'), (2, 'Dee'), (5, 'Eve')",
+            "CREATE INDEX people_id ON people (id)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        // The steps of the plan, along with the number of rows each one produced
+        let analyze = |query: &str, user: &mut User| -> Vec<(String, Option<usize>)> {
+            let query: String = format!("EXPLAIN ANALYZE {}", query);
+            let (_, rows) =
+                execute_query(&parse(&query, false).unwrap(), user, &"".to_string()).unwrap();
+            rows.into_iter()
+                .map(|row| match &row[0] {
+                    Value::String(step) => match step.split_once(" (rows: ") {
+                        Some((step, rest)) => {
+                            let count: &str = rest.split(',').next().unwrap();
+                            (step.to_string(), Some(count.parse::<usize>().unwrap()))
+                        }
+                        None => (step.to_string(), None),
+                    },
+                    value => panic!("Expected a step, found {:?}", value),
+                })
+                .collect()
+        };
+
+        // The index only reads the matching rows, instead of the whole table
+        let plan = analyze("SELECT name FROM people WHERE id = 2", &mut user);
+        assert_eq!(
+            plan[0],
+            (
+                "Index scan on table people as people using index people_id".to_string(),
+                Some(2)
+            )
+        );
+        assert_eq!(plan[1], ("Filter: id = 2".to_string(), Some(2)));
+        assert!(plan[2].0.starts_with("Execution time: "));
+        assert_eq!(plan.len(), 3);
+
+        let plan = analyze(
+            "SELECT id, COUNT(name) FROM people WHERE name <> 'Ann' GROUP BY id ORDER BY id",
             &mut user,
-            &"".to_string(),
-        )
-        .unwrap();
+        );
+        assert_eq!(
+            plan[..4].to_vec(),
+            vec![
+                ("Scan table people as people".to_string(), Some(5)),
+                ("Filter: name <> 'Ann'".to_string(), Some(4)),
+                ("Group by: id".to_string(), Some(3)),
+                ("Sort by: id".to_string(), Some(3)),
+            ]
+        );
 
-        let table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
+        // Without ANALYZE, the steps don't have any counts, but the cost is estimated
+        let (_, rows) = execute_query(
+            &parse("EXPLAIN SELECT name FROM people WHERE id = 2", false).unwrap(),
+            &mut user,
+            &"".to_string(),
         )
         .unwrap();
-
-        let rows = table.into_iter().collect::<Vec<RowInfo>>();
-        assert!(
-            rows[0].row[old_schemas.len()] == util::dbtype::Value::Null(util::dbtype::Column::I64)
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::String(
+                    "Index scan on table people as people using index people_id".to_string()
+                )],
+                vec![Value::String("Filter: id = 2".to_string())],
+                vec![Value::String(
+                    "Estimated cost: 2 (rows examined: 2, hash: no, sort: no)".to_string()
+                )],
+            ]
         );
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_add2() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // Ensures that EXPLAIN estimates a lower cost for the plans that read fewer rows
+    fn test_explain_estimated_cost() {
+        fcreate_db_instance("explain_estimated_cost");
+        let mut user: User = User::new("test_user".to_string());
+        let values: Vec<String> = (0..3000).map(|i| format!("({}, 'name{}')", i, i)).collect();
+        let queries: Vec<String> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(20))".to_string(),
+            format!("INSERT INTO people VALUES {}", values.join(", ")),
+            "CREATE INDEX people_id ON people (id)".to_string(),
+        ];
+        for query in queries {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        // The last step of the plan is the estimated cost
+        let cost = |query: &str, user: &mut User| -> (usize, String) {
+            let query: String = format!("EXPLAIN {}", query);
+            let (_, rows) =
+                execute_query(&parse(&query, false).unwrap(), user, &"".to_string()).unwrap();
+            match &rows.last().unwrap()[0] {
+                Value::String(step) => {
+                    let rest: &str = step.strip_prefix("Estimated cost: ").unwrap();
+                    let (cost, details) = rest.split_once(' ').unwrap();
+                    (cost.parse::<usize>().unwrap(), details.to_string())
+                }
+                value => panic!("Expected a step, found {:?}", value),
+            }
+        };
 
-        let result = execute_update(
-            &parse("alter table personal_info add id int", false).unwrap(),
+        // The index finds the one matching row, while the scan reads every row
+        let (index_cost, details) = cost("SELECT name FROM people WHERE id = 5", &mut user);
+        assert_eq!(index_cost, 1);
+        assert_eq!(details, "(rows examined: 1, hash: no, sort: no)");
+        let (scan_cost, _) = cost("SELECT name FROM people WHERE name = 'name5'", &mut user);
+        assert!(scan_cost >= 3000, "{}", scan_cost);
+        assert!(index_cost * 1000 < scan_cost);
+
+        // Joining the table to itself looks at every pair of rows
+        let (join_cost, _) = cost(
+            "SELECT a.name FROM people AS a, people AS b WHERE a.id = b.id",
             &mut user,
-            &"".to_string(),
-        )
-        .unwrap_err();
+        );
+        assert!(join_cost >= scan_cost * scan_cost);
 
-        assert!(result == "Column name id already exists");
+        // Grouping and sorting add to the cost of the scan
+        let (group_cost, details) = cost(
+            "SELECT name, COUNT(*) FROM people GROUP BY name ORDER BY name",
+            &mut user,
+        );
+        assert!(group_cost > scan_cost * 2);
+        assert!(details.ends_with("hash: yes, sort: yes)"));
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_drop() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
+    // A row inserted after a delete reuses the freed slot, instead of growing the table
+    fn test_insert_reuses_deleted_slots() {
+        fcreate_db_instance("reuse_deleted_slots");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE people (id INT, name VARCHAR(20))",
+            "INSERT INTO people VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Cal')",
+            "CREATE INDEX people_id ON people (id)",
+            "DELETE FROM people WHERE id = 2",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let table_size = |user: &User| -> (u32, u64) {
+            let table: Table = Table::from_user(
+                user,
+                get_db_instance().unwrap(),
+                &"people".to_string(),
+                None,
+            )
             .unwrap();
+            let file_len: u64 = get_storage_backend(&table.path)
+                .file_len(&table.path)
+                .unwrap();
+            (table.max_pages, file_len)
+        };
+        let size: (u32, u64) = table_size(&user);
 
-        let old_table = Table::from_user(
+        execute_update(
+            &parse("INSERT INTO people VALUES (4, 'Dee')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(table_size(&user), size);
+        let table: Table = Table::from_user(
             &user,
             get_db_instance().unwrap(),
-            &"personal_info".to_string(),
+            &"people".to_string(),
             None,
         )
         .unwrap();
-        let old_schemas = old_table.schema.clone();
+        let rows: Vec<RowInfo> = table.collect();
+        assert_eq!(
+            rows[1].row,
+            vec![Value::I32(4), Value::String("Dee".to_string())]
+        );
+        assert_eq!((rows[1].pagenum, rows[1].rownum), (1, 1));
 
-        execute_update(
-            &parse("alter table personal_info drop id", false).unwrap(),
+        // The index points to the reused slot
+        let (_, rows) = execute_query(
+            &parse("SELECT name FROM people WHERE id = 4", false).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
-
-        let table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
+        assert_eq!(rows, vec![vec![Value::String("Dee".to_string())]]);
+        let (_, rows) = execute_query(
+            &parse("EXPLAIN SELECT name FROM people WHERE id = 4", false).unwrap(),
+            &mut user,
+            &"".to_string(),
         )
         .unwrap();
-
-        let schemas = table.schema.clone();
-        assert!(old_schemas.len() - 1 == schemas.len());
+        assert_eq!(
+            rows[0],
+            vec![Value::String(
+                "Index scan on table people as people using index people_id".to_string()
+            )]
+        );
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_drop1() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    fn test_recursive_cte() {
+        fcreate_db_instance("recursive_cte");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE edges (parent INT, child INT)",
+            "INSERT INTO edges VALUES (1, 2), (2, 3), (3, 4), (2, 5), (6, 7)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            let (_, rows) = execute_query(&parse(query, false).unwrap(), user, &"".to_string())?;
+            Ok(rows)
+        };
+        let pairs = |pairs: Vec<(i32, i32)>| -> Vec<Row> {
+            pairs
+                .into_iter()
+                .map(|(x, y)| vec![Value::I32(x), Value::I32(y)])
+                .collect()
+        };
 
-        let old_table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
-        )
-        .unwrap();
-        let old_rows = old_table.into_iter().collect::<Vec<RowInfo>>();
+        // The transitive closure of the edges: every ancestor of every node
+        let closure: &str = "WITH RECURSIVE reach (ancestor, descendant) AS (\
+            SELECT parent, child FROM edges \
+            UNION SELECT r.ancestor, e.child FROM reach r, edges e WHERE r.descendant = e.parent) \
+            SELECT ancestor, descendant FROM reach ORDER BY ancestor, descendant";
+        assert_eq!(
+            run(closure, &mut user).unwrap(),
+            pairs(vec![
+                (1, 2),
+                (1, 3),
+                (1, 4),
+                (1, 5),
+                (2, 3),
+                (2, 4),
+                (2, 5),
+                (3, 4),
+                (6, 7)
+            ])
+        );
+
+        // A plain WITH table can be read like any other table
+        assert_eq!(
+            run(
+                "WITH roots AS (SELECT parent, child FROM edges WHERE parent = 2) \
+                SELECT parent, child FROM roots ORDER BY child",
+                &mut user
+            )
+            .unwrap(),
+            pairs(vec![(2, 3), (2, 5)])
+        );
 
+        // The tables are dropped once the query is done
+        assert!(run("SELECT ancestor FROM reach", &mut user).is_err());
+
+        // A cycle never stops producing rows with UNION ALL, so it runs into the guard
         execute_update(
-            &parse("alter table personal_info drop id", false).unwrap(),
+            &parse("INSERT INTO edges VALUES (4, 1)", true).unwrap(),
             &mut user,
             &"".to_string(),
         )
         .unwrap();
-
-        let table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
+        let err: String = run(
+            &closure.replace("UNION SELECT", "UNION ALL SELECT"),
+            &mut user,
         )
-        .unwrap();
-
-        let rows = table.into_iter().collect::<Vec<RowInfo>>();
-        assert!(rows[0].row[0] == old_rows[0].row[1]);
+        .unwrap_err();
+        assert!(err.contains("didn't stop recursing"), "{}", err);
+        // With UNION, rows that were already found stop the cycle: 1 to 4 each reach 1 to 5
+        assert_eq!(run(closure, &mut user).unwrap().len(), 21);
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_drop2() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // Integer arithmetic that doesn't fit in its type is an error, instead of wrapping around
+    fn test_integer_overflow() {
+        fcreate_db_instance("integer_overflow");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, a SMALLINT, b SMALLINT, c INT)",
+            "INSERT INTO t VALUES (1, 2147483647, 1, 9223372036854775807)",
+            "INSERT INTO t VALUES (2, 2147483646, -2147483648, 0)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
 
-        let result = execute_update(
-            &parse("alter table personal_info drop missing", false).unwrap(),
-            &mut user,
-            &"".to_string(),
-        )
-        .unwrap_err();
+        // Results that fit are still computed as before
+        assert_eq!(
+            run("SELECT a - b FROM t WHERE id = 1", &mut user).unwrap(),
+            vec![vec![Value::I32(2147483646)]]
+        );
+        assert_eq!(
+            run("SELECT a + b FROM t WHERE id = 2", &mut user).unwrap(),
+            vec![vec![Value::I32(-2)]]
+        );
+        // Integer literals are I64s, so mixing one in gives an I64
+        assert_eq!(
+            run("SELECT a * 2 FROM t WHERE id = 1", &mut user).unwrap(),
+            vec![vec![Value::I64(4294967294)]]
+        );
+
+        let overflows: Vec<(&str, &str)> = vec![
+            ("SELECT a + b FROM t WHERE id = 1", "add"),
+            ("SELECT b - a FROM t WHERE id = 2", "subtract"),
+            ("SELECT a * a FROM t WHERE id = 1", "multiply"),
+            ("SELECT c + 1 FROM t WHERE id = 1", "add"),
+            ("SELECT SUM(a) FROM t", "add"),
+        ];
+        for (query, operation) in overflows {
+            let error: String = run(query, &mut user).unwrap_err();
+            assert!(
+                error.starts_with(&format!("Integer overflow when trying to {}", operation)),
+                "{}: {}",
+                query,
+                error
+            );
+        }
 
-        assert!(result == "Column name missing does not exist");
+        // Dividing an integer by zero is an error too, rather than a panic
+        let error: String = run("SELECT a / 0 FROM t", &mut user).unwrap_err();
+        assert!(error.starts_with("Division by zero when trying to divide"));
+        let error: String = run("SELECT a % (b - b) FROM t", &mut user).unwrap_err();
+        assert!(error.starts_with("Division by zero when trying to modulus"));
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_change() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // Test that a DELETE or UPDATE with a LIMIT only writes that many of the matching rows
+    fn test_write_limit() {
+        fcreate_db_instance("write_limit");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE t (id INT, score INT, tag VARCHAR(10))",
+            "INSERT INTO t VALUES (1, 50, 'a'), (2, 10, 'a'), (3, 40, 'a'), (4, 30, 'b')",
+            "INSERT INTO t VALUES (5, 20, 'a'), (6, 60, 'a'), (7, 70, 'b')",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let update = |query: &str, user: &mut User| -> String {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string()).unwrap()
+        };
+        let ids = |user: &mut User| -> Vec<i64> {
+            let query: &str = "SELECT id FROM t ORDER BY id";
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+                .into_iter()
+                .map(|row| match row[0] {
+                    Value::I64(x) => x,
+                    _ => panic!("Invalid value type"),
+                })
+                .collect()
+        };
 
-        let result = execute_update(
-            &parse(
-                "alter table personal_info change missing something int",
-                false,
-            )
-            .unwrap(),
-            &mut user,
-            &"".to_string(),
-        )
-        .unwrap_err();
+        // Without an ORDER BY, the first matching rows in the table are deleted
+        let result: String = update("DELETE FROM t WHERE tag = 'a' LIMIT 2", &mut user);
+        assert_eq!(result, "2 rows were deleted.");
+        assert_eq!(ids(&mut user), vec![3, 4, 5, 6, 7]);
 
-        assert!(result == "Column name missing does not exist");
+        // With an ORDER BY, the rows are deleted in that order
+        let result: String = update("DELETE FROM t ORDER BY score DESC LIMIT 2", &mut user);
+        assert_eq!(result, "2 rows were deleted.");
+        assert_eq!(ids(&mut user), vec![3, 4, 5]);
+
+        // A limit past the number of matching rows deletes all of them
+        let result: String = update("DELETE FROM t WHERE tag = 'b' LIMIT 100", &mut user);
+        assert_eq!(result, "1 rows were deleted.");
+        assert_eq!(ids(&mut user), vec![3, 5]);
+
+        let result: String = update(
+            "UPDATE t SET tag = 'c' WHERE tag = 'a' ORDER BY score LIMIT 1",
+            &mut user,
+        );
+        assert_eq!(result, "1 rows were successfully updated.");
+        let query: &str = "SELECT id FROM t WHERE tag = 'c'";
+        let (_, results) =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()).unwrap();
+        assert_eq!(results, vec![vec![Value::I64(5)]]);
+
+        // The ORDER BY and LIMIT are passed along next to the statement, not in it
+        let parsed: Vec<ParsedStatement> =
+            parse("DELETE FROM t WHERE id > 1 ORDER BY id DESC LIMIT 2", true).unwrap();
+        assert_eq!(parsed[0].clauses.row_limit.limit, Some(2));
+        assert_eq!(parsed[0].clauses.row_limit.order_by[0].asc, Some(false));
+        assert_eq!(
+            parsed[0].statement.to_string(),
+            "DELETE FROM t WHERE id > 1"
+        );
+        assert!(parse("DELETE FROM t LIMIT 1 ORDER BY id", true).is_err());
+        assert!(parse("DELETE FROM t LIMIT x", true).is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_change1() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // Test that DISTINCT ON keeps the first row of each key, in the order of the ORDER BY
+    fn test_distinct_on() {
+        fcreate_db_instance("distinct_on");
+        let mut user: User = User::new("test_user".to_string());
+        let queries: Vec<&str> = vec![
+            "CREATE TABLE events (entity VARCHAR(10), status VARCHAR(10), ts INT)",
+            "INSERT INTO events VALUES ('a', 'new', 1), ('b', 'new', 2), ('a', 'open', 3), \
+             ('c', 'new', 4), ('b', 'closed', 5), ('a', 'closed', 6), ('c', 'open', 7)",
+        ];
+        for query in queries {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<(Vec<String>, Vec<Row>), String> {
+            execute_query(&parse(query, false)?, user, &"".to_string())
+        };
+        let row = |entity: &str, status: &str| -> Row {
+            vec![
+                Value::String(entity.to_string()),
+                Value::String(status.to_string()),
+            ]
+        };
+        let status_at = |status: &str, ts: i64| -> Row {
+            vec![Value::String(status.to_string()), Value::I64(ts)]
+        };
 
-        let old_table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
+        // The latest status of each entity
+        let (columns, rows) = run(
+            "SELECT DISTINCT ON (entity) entity, status FROM events ORDER BY entity, ts DESC",
+            &mut user,
         )
         .unwrap();
-        let old_rows = old_table.into_iter().collect::<Vec<RowInfo>>();
+        assert_eq!(columns, vec!["entity".to_string(), "status".to_string()]);
+        assert_eq!(
+            rows,
+            vec![row("a", "closed"), row("b", "closed"), row("c", "open")]
+        );
 
-        execute_update(
-            &parse("alter table personal_info change id missing int", false).unwrap(),
+        // The key doesn't have to be selected, and the limit applies to the rows that are kept
+        let (_, rows) = run(
+            "SELECT DISTINCT ON (entity) status, ts FROM events ORDER BY entity, ts LIMIT 2",
             &mut user,
-            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(rows, vec![status_at("new", 1), status_at("new", 2)]);
 
-        let table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
+        // Every expression is part of the key
+        let (_, rows) = run(
+            "SELECT DISTINCT ON (status, ts > 4) status, ts FROM events ORDER BY status, ts",
+            &mut user,
         )
         .unwrap();
-        let rows = table.into_iter().collect::<Vec<RowInfo>>();
+        assert_eq!(
+            rows,
+            vec![
+                status_at("closed", 5),
+                status_at("new", 1),
+                status_at("open", 3),
+                status_at("open", 7),
+            ]
+        );
 
-        assert!(rows[0].row[0] == old_rows[0].row[0]);
+        assert!(run("SELECT DISTINCT ON entity FROM events", &mut user).is_err());
+        // Only the outermost SELECT can have one
+        assert!(run(
+            "SELECT * FROM (SELECT DISTINCT ON (entity) status FROM events) e",
+            &mut user
+        )
+        .is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_change2() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
-
-        let old_table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
-        )
-        .unwrap();
-        let old_schemas = old_table.schema.clone();
+    // Test that FETCH FIRST n ROWS ONLY returns the same rows as LIMIT n
+    fn test_fetch_first() {
+        fcreate_db_instance("fetch_first");
+        let mut user: User = User::new("test_user".to_string());
+        let values: String = (0..10).map(|i| format!("({}, {})", i, 100 - i)).join(", ");
+        let queries: Vec<String> = vec![
+            "CREATE TABLE t (id INT, score INT)".to_string(),
+            format!("INSERT INTO t VALUES {}", values),
+        ];
+        for query in queries {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Result<Vec<Row>, String> {
+            Ok(execute_query(&parse(query, false).unwrap(), user, &"".to_string())?.1)
+        };
 
-        execute_update(
-            &parse("alter table personal_info change id missing int", false).unwrap(),
+        let fetched: Vec<Row> = run(
+            "SELECT id FROM t ORDER BY score FETCH FIRST 5 ROWS ONLY",
             &mut user,
-            &"".to_string(),
         )
         .unwrap();
+        assert_eq!(fetched.len(), 5);
+        assert_eq!(
+            fetched,
+            run("SELECT id FROM t ORDER BY score LIMIT 5", &mut user).unwrap()
+        );
 
-        let table = Table::from_user(
-            &user,
-            get_db_instance().unwrap(),
-            &"personal_info".to_string(),
-            None,
+        // The standard OFFSET works with it the same way as with LIMIT
+        assert_eq!(
+            run(
+                "SELECT id FROM t ORDER BY id OFFSET 2 ROWS FETCH NEXT 3 ROWS ONLY",
+                &mut user
+            )
+            .unwrap(),
+            run("SELECT id FROM t ORDER BY id LIMIT 3 OFFSET 2", &mut user).unwrap()
+        );
+        // Without a count, a single row is fetched
+        assert_eq!(
+            run(
+                "SELECT id FROM t ORDER BY id FETCH FIRST ROW ONLY",
+                &mut user
+            )
+            .unwrap(),
+            vec![vec![Value::I64(0)]]
+        );
+
+        assert!(run(
+            "SELECT id FROM t LIMIT 2 FETCH FIRST 3 ROWS ONLY",
+            &mut user
         )
-        .unwrap();
-        let schemas = table.schema.clone();
-        assert!(old_schemas[0] != schemas[0] && schemas[0].0 == "missing");
+        .is_err());
+        assert!(run(
+            "SELECT id FROM t ORDER BY id FETCH FIRST 3 ROWS WITH TIES",
+            &mut user
+        )
+        .is_err());
         delete_db_instance().unwrap();
     }
 
     #[test]
     #[serial]
-    fn test_alter_change3() {
-        let mut user = create_demo_db("alter_test");
-        get_db_instance()
-            .unwrap()
-            .switch_branch(&"main".to_string(), &mut user)
-            .unwrap();
+    // A COUNT(*) with a where clause on an index is counted from the index, without reading rows
+    fn test_count_with_index() {
+        use crate::fileio::pageio::data_pages_read;
+        fcreate_db_instance("count_with_index");
+        let mut user: User = User::new("test_user".to_string());
+        let values: String = (0..300)
+            .map(|i| format!("({}, '{}')", i, ["a", "b", "c"][i % 3]))
+            .join(", ");
+        let queries: Vec<String> = vec![
+            "CREATE TABLE t (id INT, dept VARCHAR(20))".to_string(),
+            format!("INSERT INTO t VALUES {}", values),
+        ];
+        for query in queries {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let run = |query: &str, user: &mut User| -> Vec<Row> {
+            execute_query(&parse(query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let counts: Vec<&str> = vec![
+            "SELECT COUNT(*) FROM t WHERE dept = 'b'",
+            "SELECT COUNT(*) FROM t WHERE id < 100",
+            "SELECT COUNT(*) FROM t WHERE id >= 50 AND id <= 149",
+            "SELECT COUNT(*) FROM t WHERE dept = 'z'",
+        ];
 
-        let result = execute_update(
-            &parse(
-                "alter table personal_info change id missing varchar(50)",
-                false,
-            )
-            .unwrap(),
-            &mut user,
-            &"".to_string(),
-        )
-        .unwrap_err();
+        // Without indexes, the rows are scanned
+        let before: u64 = data_pages_read();
+        let scanned: Vec<Vec<Row>> = counts.iter().map(|query| run(query, &mut user)).collect();
+        assert!(data_pages_read() > before);
+        assert_eq!(scanned[0], vec![vec![Value::I32(100)]]);
+        assert_eq!(scanned[1], vec![vec![Value::I32(100)]]);
+        assert_eq!(scanned[2], vec![vec![Value::I32(100)]]);
+        assert_eq!(scanned[3], Vec::<Row>::new());
+
+        for query in [
+            "CREATE INDEX dept_idx ON t (dept)",
+            "CREATE INDEX id_idx ON t (id)",
+        ] {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        for (query, expected) in counts.iter().zip(scanned) {
+            let before: u64 = data_pages_read();
+            assert_eq!(run(query, &mut user), expected, "{}", query);
+            assert_eq!(data_pages_read(), before, "{}", query);
+        }
 
-        assert!(result.starts_with(&"Unexpected Type".to_string()));
+        // Conditions the index can't answer exactly still read the rows
+        let before: u64 = data_pages_read();
+        assert_eq!(
+            run("SELECT COUNT(*) FROM t WHERE dept <> 'b'", &mut user),
+            vec![vec![Value::I32(200)]]
+        );
+        assert!(data_pages_read() > before);
         delete_db_instance().unwrap();
     }
 }
@@ -0,0 +1,209 @@
+use prost_types::Timestamp;
+
+use crate::fileio::databaseio::get_db_instance;
+use crate::fileio::header::Schema;
+use crate::fileio::storage::IN_MEMORY_PATH_PREFIX;
+use crate::fileio::tableio::{create_table_in_dir, Table};
+use crate::user::userdata::User;
+use crate::util::dbtype::{Column, Value};
+use crate::util::row::Row;
+use crate::version_control::branches::BranchNode;
+use crate::version_control::commit::Commit;
+use crate::version_control::diff::Diff;
+
+/// The system table that holds the commits of the user's current branch
+pub const COMMITS_TABLE_NAME: &str = "gql_commits";
+/// The system table that holds the indexes of every table on the user's current branch
+pub const INDEXES_TABLE_NAME: &str = "gql_indexes";
+
+// The longest commit message that's kept in the commits table, so that a row fits in a page
+const MAX_MESSAGE_LENGTH: usize = 1000;
+
+/// Whether a table name refers to one of the read-only system tables
+pub fn is_system_table(table_name: &str) -> bool {
+    let table_name: String = table_name.to_lowercase();
+    table_name == COMMITS_TABLE_NAME || table_name == INDEXES_TABLE_NAME
+}
+
+/// Returns an error if the table is a system table, which can't be created or written to
+pub fn check_writable_table(table_name: &str) -> Result<(), String> {
+    if is_system_table(table_name) {
+        return Err(format!("{} is a read-only system table", table_name));
+    }
+    Ok(())
+}
+
+/// Builds a system table for the user, with its rows as they are right now.
+/// The table is kept in memory, and is rebuilt every time it's loaded.
+pub fn load_system_table(table_name: &String, user: &User) -> Result<Table, String> {
+    let (schema, rows): (Schema, Vec<Row>) = match table_name.to_lowercase().as_str() {
+        COMMITS_TABLE_NAME => commits_table(user)?,
+        INDEXES_TABLE_NAME => indexes_table(user)?,
+        _ => return Err(format!("{} is not a system table", table_name)),
+    };
+
+    // Each user gets their own copy, since the rows depend on the user's branch
+    let table_dir: String = format!(
+        "{}system{}{}{}{}",
+        IN_MEMORY_PATH_PREFIX,
+        std::path::MAIN_SEPARATOR,
+        get_db_instance()?.get_database_name(),
+        std::path::MAIN_SEPARATOR,
+        user.get_user_id()
+    );
+    let (mut table, _) = create_table_in_dir(table_name, &schema, &table_dir)?;
+    table.insert_rows(rows)?;
+    Table::new(&table_dir, table_name, None)
+}
+
+/// The commits of the user's current branch, from its head back to the first commit,
+/// with the number of diffs and changed rows in each commit.
+fn commits_table(user: &User) -> Result<(Schema, Vec<Row>), String> {
+    let mut commits: Vec<Commit> = Vec::new();
+    // With no commits there are no branch heads, and the table is empty
+    if !get_db_instance()?
+        .get_branch_heads_file_mut()
+        .get_all_branch_heads()?
+        .is_empty()
+    {
+        let branch_node: BranchNode = get_db_instance()?
+            .get_branch_heads_file_mut()
+            .get_branch_node_from_head(
+                &user.get_current_branch_name(),
+                get_db_instance()?.get_branch_file(),
+            )?;
+        let branch_nodes: Vec<BranchNode> = get_db_instance()?
+            .get_branch_file()
+            .traverse_branch_nodes(&branch_node)?;
+        for node in branch_nodes {
+            commits.push(
+                get_db_instance()?
+                    .get_commit_file_mut()
+                    .fetch_commit(&node.commit_hash)?,
+            );
+        }
+    }
+
+    let messages: Vec<String> = commits
+        .iter()
+        .map(|commit| commit.message.chars().take(MAX_MESSAGE_LENGTH).collect())
+        .collect();
+    let schema: Schema = vec![
+        (
+            "hash".to_string(),
+            Column::String(width(commits.iter().map(|c| &c.hash).collect())),
+        ),
+        (
+            "author".to_string(),
+            Column::String(width(commits.iter().map(|c| &c.user_id).collect())),
+        ),
+        (
+            "message".to_string(),
+            Column::String(width(messages.iter().collect())),
+        ),
+        (
+            "timestamp".to_string(),
+            Column::Nullable(Box::new(Column::Timestamp)),
+        ),
+        ("diffs".to_string(), Column::I64),
+        ("rows_inserted".to_string(), Column::I64),
+        ("rows_updated".to_string(), Column::I64),
+        ("rows_removed".to_string(), Column::I64),
+    ];
+
+    let rows: Vec<Row> = commits
+        .into_iter()
+        .zip(messages)
+        .map(|(commit, message)| {
+            let count = |rows: fn(&Diff) -> usize| -> Value {
+                Value::I64(commit.diffs.iter().map(rows).sum::<usize>() as i64)
+            };
+            vec![
+                Value::String(commit.hash.clone()),
+                Value::String(commit.user_id.clone()),
+                Value::String(message),
+                commit_timestamp(&commit),
+                Value::I64(commit.diffs.len() as i64),
+                count(|diff| match diff {
+                    Diff::Insert(diff) => diff.rows.len(),
+                    _ => 0,
+                }),
+                count(|diff| match diff {
+                    Diff::Update(diff) => diff.rows.len(),
+                    _ => 0,
+                }),
+                count(|diff| match diff {
+                    Diff::Remove(diff) => diff.rows.len(),
+                    _ => 0,
+                }),
+            ]
+        })
+        .collect();
+    Ok((schema, rows))
+}
+
+/// The indexes of each table on the user's current branch, with the columns
+/// they're built on in order, and whether they're unique.
+fn indexes_table(user: &User) -> Result<(Schema, Vec<Row>), String> {
+    let mut indexes: Vec<(String, String, String, bool)> = Vec::new();
+    for table_name in get_db_instance()?.get_tables(user)? {
+        let table: Table = Table::from_user(user, get_db_instance()?, &table_name, None)?;
+        for (index_id, (_, index_name)) in &table.indexes {
+            let columns: Vec<String> = index_id
+                .iter()
+                .map(|col| table.schema[*col as usize].0.clone())
+                .collect();
+            indexes.push((
+                table.name.clone(),
+                index_name.clone(),
+                columns.join(", "),
+                table.unique_indexes.contains(index_id),
+            ));
+        }
+    }
+    indexes.sort();
+
+    let schema: Schema = vec![
+        (
+            "table_name".to_string(),
+            Column::String(width(indexes.iter().map(|i| &i.0).collect())),
+        ),
+        (
+            "index_name".to_string(),
+            Column::String(width(indexes.iter().map(|i| &i.1).collect())),
+        ),
+        (
+            "columns".to_string(),
+            Column::String(width(indexes.iter().map(|i| &i.2).collect())),
+        ),
+        ("unique".to_string(), Column::Bool),
+    ];
+    let rows: Vec<Row> = indexes
+        .into_iter()
+        .map(|(table_name, index_name, columns, unique)| {
+            vec![
+                Value::String(table_name),
+                Value::String(index_name),
+                Value::String(columns),
+                Value::Bool(unique),
+            ]
+        })
+        .collect();
+    Ok((schema, rows))
+}
+
+// Strings are stored in fixed size columns, so each column is as wide as its longest value
+fn width(values: Vec<&String>) -> u16 {
+    values.iter().map(|x| x.len()).max().unwrap_or(0).max(1) as u16
+}
+
+// Commits store their time as the number of milliseconds since the epoch
+fn commit_timestamp(commit: &Commit) -> Value {
+    match commit.timestamp.parse::<i64>() {
+        Ok(millis) => Value::Timestamp(Timestamp {
+            seconds: millis.div_euclid(1000),
+            nanos: (millis.rem_euclid(1000) * 1_000_000) as i32,
+        }),
+        Err(_) => Value::Null(Column::Timestamp),
+    }
+}
@@ -0,0 +1,218 @@
+use prost_types::Timestamp;
+
+use crate::fileio::{databaseio::Database, header::*, tableio::Table};
+use crate::user::userdata::User;
+use crate::util::{clock, dbtype::*, row::*};
+use crate::version_control::diff::{Diff, RemoveDiff};
+
+// A table with a TTL has a hidden column with this name, which records when each row was
+// inserted. The TTL itself is kept in the table's header.
+const TTL_COLUMN_NAME: &str = "__ttl_inserted_at";
+
+// Columns whose names start with this are kept for the hidden columns, so users can't add them
+const RESERVED_COLUMN_PREFIX: &str = "__ttl_";
+
+/// Gets the current time that rows are timestamped and expired with
+pub fn now() -> Timestamp {
+    let millis: u128 = clock::now_millis();
+    Timestamp {
+        seconds: (millis / 1000) as i64,
+        nanos: ((millis % 1000) * 1_000_000) as i32,
+    }
+}
+
+/// Creates the hidden column that records when each row of a table with a TTL was inserted
+pub fn ttl_column() -> SchemaCol {
+    (TTL_COLUMN_NAME.to_string(), Column::Timestamp)
+}
+
+/// Whether the column is the hidden column of a table with a TTL
+pub fn is_ttl_column(column_name: &str) -> bool {
+    column_name == TTL_COLUMN_NAME
+}
+
+/// Makes sure that a column a user is adding doesn't take a name kept for the hidden columns
+pub fn check_column_name(column_name: &str) -> Result<(), String> {
+    if column_name.starts_with(RESERVED_COLUMN_PREFIX) {
+        return Err(format!(
+            "Column names starting with {} are reserved: {}",
+            RESERVED_COLUMN_PREFIX, column_name
+        ));
+    }
+    Ok(())
+}
+
+/// Gets the position of the hidden TTL column in the table's schema, along with the TTL in
+/// seconds. Returns None if the table doesn't have a TTL.
+pub fn get_ttl(table: &Table) -> Option<(usize, u64)> {
+    let position: usize = table
+        .schema
+        .iter()
+        .position(|(name, _)| is_ttl_column(name))?;
+    table.ttl.map(|ttl| (position, ttl))
+}
+
+/// The number of columns in the schema that a user can see and insert into
+pub fn visible_column_count(schema: &Schema) -> usize {
+    schema
+        .iter()
+        .filter(|(name, _)| !is_ttl_column(name))
+        .count()
+}
+
+/// Timestamps a row that is about to be inserted into a table with a TTL.
+/// Rows that already have a value for every column, like rows restored from a diff, are left alone.
+pub fn timestamp_row(schema: &Schema, row: Row) -> Row {
+    match schema.iter().position(|(name, _)| is_ttl_column(name)) {
+        Some(position) if row.len() + 1 == schema.len() => {
+            let mut row: Row = row;
+            row.insert(position, Value::Timestamp(now()));
+            row
+        }
+        _ => row,
+    }
+}
+
+/// Whether a row has outlived its table's TTL. Tables without a TTL never expire.
+pub fn is_expired(ttl: &Option<(usize, u64)>, row: &Row, now_seconds: i64) -> bool {
+    match (ttl, ttl.and_then(|(position, _)| row.get(position))) {
+        (Some((_, ttl_seconds)), Some(Value::Timestamp(inserted_at))) => {
+            inserted_at.seconds.saturating_add(*ttl_seconds as i64) <= now_seconds
+        }
+        _ => false,
+    }
+}
+
+/// Removes the rows that have outlived the table's TTL, before a write to the table can see them.
+/// The removal is added to the user's diffs, so it is committed like any other delete.
+pub fn remove_expired_rows(
+    database: &Database,
+    table_name: &String,
+    user: &mut User,
+) -> Result<(), String> {
+    let table: Table = Table::from_user(user, database, table_name, None)?;
+    let ttl: Option<(usize, u64)> = get_ttl(&table);
+    if ttl.is_none() {
+        return Ok(());
+    }
+
+    let now_seconds: i64 = now().seconds;
+    let expired: Vec<RowLocation> = table
+        .clone()
+        .filter(|rowinfo| is_expired(&ttl, &rowinfo.row, now_seconds))
+        .map(|rowinfo| rowinfo.get_row_location())
+        .collect();
+    if !expired.is_empty() {
+        let diff: RemoveDiff = table.remove_rows(expired)?;
+        user.append_diff(&Diff::Remove(diff));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{
+        executor::query::{execute_query, execute_update},
+        fileio::databaseio::{delete_db_instance, get_db_instance},
+        parser::parser::parse,
+        util::{bench::fcreate_db_instance, clock::TestClock},
+    };
+
+    #[test]
+    #[serial]
+    // Ensures that rows are hidden once they outlive the TTL, and removed by the next write
+    fn test_table_ttl() {
+        fcreate_db_instance("table_ttl");
+        let clock: TestClock = TestClock::start_at(1_000_000_000_000);
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string()).unwrap();
+        };
+        let select = |user: &mut User| {
+            execute_query(
+                &parse("SELECT * FROM cache ORDER BY k", false).unwrap(),
+                user,
+                &"".to_string(),
+            )
+            .unwrap()
+        };
+
+        run(
+            "CREATE TABLE cache (k INT, v VARCHAR(10)) WITH TTL 3600",
+            &mut user,
+        );
+        run("INSERT INTO cache VALUES (1, 'a'), (2, 'b')", &mut user);
+
+        // The TTL is kept in the table's header, and the hidden column's name is reserved
+        let table: Table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"cache".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(table.ttl, Some(3600));
+        for query in [
+            "CREATE TABLE other (__ttl_x INT)",
+            "ALTER TABLE cache ADD COLUMN __ttl_inserted_at INT",
+            "ALTER TABLE cache RENAME COLUMN v TO __ttl_v",
+        ] {
+            let result = execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string());
+            assert!(result.unwrap_err().message.contains("reserved"));
+        }
+
+        // The hidden column isn't counted in the number of columns to insert into
+        let result = execute_update(
+            &parse("INSERT INTO cache SELECT k FROM cache", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        );
+        assert!(result.unwrap_err().message.contains("(1)"));
+
+        // The hidden column isn't selected
+        let (columns, rows) = select(&mut user);
+        assert_eq!(columns, vec!["k".to_string(), "v".to_string()]);
+        assert_eq!(rows.len(), 2);
+
+        clock.advance_seconds(1800);
+        run("INSERT INTO cache VALUES (3, 'c')", &mut user);
+        assert_eq!(select(&mut user).1.len(), 3);
+
+        // Only the first two rows are past the TTL
+        clock.advance_seconds(1800);
+        let (_, rows) = select(&mut user);
+        assert_eq!(
+            rows,
+            vec![vec![Value::I32(3), Value::String("c".to_string())]]
+        );
+
+        // Only the writes to the table remove its expired rows
+        let num_diffs: usize = user.get_diffs().len();
+        run("CREATE TABLE other (k INT)", &mut user);
+        run("INSERT INTO other VALUES (1)", &mut user);
+        assert_eq!(user.get_diffs().len(), num_diffs + 2);
+
+        // The next write to the table removes the expired rows, and records their removal
+        let num_diffs: usize = user.get_diffs().len();
+        run("INSERT INTO cache VALUES (4, 'd')", &mut user);
+        let diffs: Vec<Diff> = user.get_diffs();
+        assert_eq!(diffs.len(), num_diffs + 2);
+        match &diffs[num_diffs] {
+            Diff::Remove(remove) => assert_eq!(remove.rows.len(), 2),
+            _ => panic!("Expected the expired rows to be removed"),
+        }
+        let table: Table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"cache".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(table.count(), 2);
+
+        delete_db_instance().unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use parking_lot::{const_mutex, Mutex, MutexGuard};
+use prost_types::Timestamp;
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{
+    header::{write_header, Header},
+    pageio::*,
+    storage::get_storage_backend,
+    tableio::Table,
+};
+use crate::util::{
+    dbtype::{Column, Value},
+    row::{Row, RowInfo},
+};
+
+// Catalog File Constants
+pub const CATALOG_FILE_NAME: &str = "catalog";
+pub const CATALOG_FILE_EXTENSION: &str = ".gql";
+
+// Every database in a databases directory shares its catalog, so it's changed by one at a time
+static CATALOG_LOCK: Mutex<()> = const_mutex(());
+
+/// A database that is listed in the catalog
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatalogEntry {
+    pub name: String,
+    pub main_branch: String,
+    pub created: Timestamp,
+}
+
+/// This lists the databases kept in a databases directory, so they can be found without
+/// opening each of them. Each database is a single (name, main_branch, created) row in the
+/// `catalog.gql` table, which sits next to the database directories.
+pub struct CatalogFile {
+    catalog_table: Table,
+    _lock: MutexGuard<'static, ()>, // The catalog is only read or changed while it's open
+}
+
+impl CatalogFile {
+    /// Opens the catalog of the given databases directory, creating it if it doesn't exist yet.
+    /// Other catalogs can't be opened until this one is dropped.
+    pub fn open(dir_path: &String) -> Result<CatalogFile, String> {
+        let lock: MutexGuard<'static, ()> = CATALOG_LOCK.lock();
+        let filepath: String = format!(
+            "{}{}{}{}",
+            dir_path,
+            std::path::MAIN_SEPARATOR,
+            CATALOG_FILE_NAME,
+            CATALOG_FILE_EXTENSION
+        );
+
+        if !get_storage_backend(&filepath).exists(&filepath) {
+            get_storage_backend(&filepath).create_file(&filepath)?;
+
+            let schema = vec![
+                ("name".to_string(), Column::String(64)),
+                ("main_branch".to_string(), Column::String(64)),
+                ("created".to_string(), Column::Timestamp),
+            ];
+            let header = Header {
+                num_pages: 2,
+                schema,
+                index_top_level_pages: HashMap::new(),
+                unique_indexes: HashSet::new(),
+                ttl: None,
+                version: 0,
+            };
+            write_header(&filepath, &header)?;
+
+            // Write a blank page to the table
+            let page = [0u8; PAGE_SIZE];
+            write_page(1, &filepath, &page, PageType::Data)?;
+        }
+
+        Ok(CatalogFile {
+            catalog_table: Table::new(
+                dir_path,
+                &CATALOG_FILE_NAME.to_string(),
+                Some(&CATALOG_FILE_EXTENSION.to_string()),
+            )?,
+            _lock: lock,
+        })
+    }
+
+    /// Returns every database in the catalog, ordered by name
+    pub fn get_entries(&self) -> Result<Vec<CatalogEntry>, String> {
+        let mut entries: Vec<CatalogEntry> = self
+            .catalog_table
+            .clone()
+            .map(|row_info| Self::to_entry(&row_info.row))
+            .collect::<Result<Vec<CatalogEntry>, String>>()?;
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// Adds a database to the catalog, created now.
+    /// If the database is already listed, it's left as it is.
+    pub fn add_database(&mut self, name: &String, main_branch: &String) -> Result<(), String> {
+        if self.find_row(name)?.is_some() {
+            return Ok(());
+        }
+        let created: Timestamp = Timestamp {
+            seconds: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?
+                .as_secs() as i64,
+            nanos: 0,
+        };
+        let row: Row = vec![
+            Value::String(name.clone()),
+            Value::String(main_branch.clone()),
+            Value::Timestamp(created),
+        ];
+        self.catalog_table.insert_rows(vec![row])?;
+        Ok(())
+    }
+
+    /// Removes a database from the catalog, if it's listed
+    pub fn remove_database(&mut self, name: &String) -> Result<(), String> {
+        if let Some(row_info) = self.find_row(name)? {
+            self.catalog_table
+                .remove_rows(vec![row_info.get_row_location()])?;
+        }
+        Ok(())
+    }
+
+    /// Returns the row of the database with the given name, if there is one
+    fn find_row(&self, name: &String) -> Result<Option<RowInfo>, String> {
+        for row_info in self.catalog_table.clone() {
+            if Self::to_entry(&row_info.row)?.name == *name {
+                return Ok(Some(row_info));
+            }
+        }
+        Ok(None)
+    }
+
+    fn to_entry(row: &Row) -> Result<CatalogEntry, String> {
+        match (row.get(0), row.get(1), row.get(2)) {
+            (
+                Some(Value::String(name)),
+                Some(Value::String(main_branch)),
+                Some(Value::Timestamp(created)),
+            ) => Ok(CatalogEntry {
+                name: name.clone(),
+                main_branch: main_branch.clone(),
+                created: created.clone(),
+            }),
+            _ => Err("Error: Catalog entry is not valid".to_string()),
+        }
+    }
+}
@@ -1,23 +1,40 @@
-use super::header::schema_size;
-use super::pageio::PAGE_SIZE;
+use super::catalog::{CatalogEntry, CatalogFile};
+use super::header::{schema_size, Schema};
+use super::metadata::{
+    MetadataFile, CHECKPOINT_METADATA_KEY_PREFIX, COMMIT_COMPRESSION_METADATA_KEY,
+    MAIN_BRANCH_METADATA_KEY, WRITE_AHEAD_LOG_METADATA_KEY,
+};
+use super::pageio::{read_page, PageType, PAGE_SIZE};
+use super::snapshot::{pin_branch_dir, ReadSnapshot};
+use super::storage::{get_storage_backend, IN_MEMORY_PATH_PREFIX};
 use super::tableio::*;
+use super::wal;
+use crate::btree::btree::BTree;
+use crate::btree::indexes::{
+    cols_id_to_index_key_type, compare_indexes, get_index_key_from_row, IndexKey, KeyComparison,
+};
 use crate::user::usercreds::UserCREDs;
 use crate::user::userdata::*;
-use crate::util::row::{EmptyRowLocation, RowLocation};
+use crate::util::row::{EmptyRowLocation, Row, RowLocation};
 use crate::version_control::command::del_branch;
 use crate::version_control::diff::*;
 use crate::version_control::{
     branch_heads::*, branches::*, commitfile::CommitFile, diff::Diff, merged_branches::*,
 };
 use crate::version_control::{commit::Commit, merge::*};
-use glob::glob;
+use itertools::Itertools;
 use parking_lot::{ReentrantMutex, ReentrantMutexGuard};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::path::Path;
+use std::sync::Arc;
 
 // Branch Constants
 pub const MAIN_BRANCH_NAME: &str = "main";
 pub const DB_NAME_BRANCH_SEPARATOR: char = '-';
+// A branch is checkpointed after this many commits since its last checkpoint
+pub const CHECKPOINT_INTERVAL: usize = 100;
 
 // Deltas File Constants
 pub const DELTAS_FILE_NAME: &str = "deltas";
@@ -43,6 +60,10 @@ pub const MERGED_BRANCHES_FILE_EXTENSION: &str = ".gql";
 pub const USER_CREDS_FILE_NAME: &str = "user_creds";
 pub const USER_CREDS_FILE_EXTENSION: &str = ".gql";
 
+// Metadata File Constants
+pub const METADATA_FILE_NAME: &str = "metadata";
+pub const METADATA_FILE_EXTENSION: &str = ".gql";
+
 // #[derive(Clone)] I'm keeping this commented. We do NOT want the database to be cloneable.
 pub struct Database {
     db_path: String, // This is the full patch to the database directory: <path>/<db_name>
@@ -52,13 +73,82 @@ pub struct Database {
     commit_file: CommitFile, // The CommitFile object for this database
     user_creds: UserCREDs, // The UserCreds object for this database
     merged_branches: MergedBranchesFile, // The MergedBranches object for this database
-    mutex: ReentrantMutex<()>, // This is the mutex that is used to lock the database
-                     // TODO: maybe add permissions here
+    metadata: MetadataFile, // The MetadataFile object for this database
+    main_branch_name: String, // The name of the main branch, chosen when the database is created
+    write_ahead_log: bool, // Whether statements and commits are logged before their pages are written
+    mutex: Arc<ReentrantMutex<()>>, // This is the mutex that is used to lock the database
+                           // TODO: maybe add permissions here
+}
+
+/// The row-level differences of a table between two branches
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableBranchDiff {
+    pub schema: Schema,
+    pub only_in_a: Vec<Row>,      // Rows that are only in the first branch
+    pub only_in_b: Vec<Row>,      // Rows that are only in the second branch
+    pub changed: Vec<(Row, Row)>, // Rows that are in both branches, as (row in a, row in b)
+}
+
+/// How the row slots of a table are used, to see how much space deleted rows have left behind
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableStats {
+    pub num_rows: usize,
+    pub num_pages: u32, // The number of pages that hold rows, not counting the header or indexes
+    pub free_slots: usize, // Empty slots before the last row, left behind by deleted rows
+    pub spare_slots: usize, // Empty slots after the last row, which haven't been used yet
+}
+
+/// An index entry that doesn't match the rows of its table, or a row that the index is missing
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexProblem {
+    pub index_name: String,
+    pub location: RowLocation, // The row that the entry points to, or that has no entry
+    pub key: IndexKey,         // The key of the entry, or of the row if it has no entry
+    pub problem: String,
 }
 
 static mut DATABASE_INSTANCE: Option<Database> = None;
 
+// The other databases that are hosted alongside the default one, by name.
+// They're boxed so that references to them stay valid when more databases are opened.
+static mut NAMED_DATABASE_INSTANCES: Option<HashMap<String, Box<Database>>> = None;
+
+thread_local! {
+    // The named database that this thread's statements run against, or None for the default one
+    static SELECTED_DATABASE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Keeps a database selected on the current thread, until it's dropped
+pub struct DatabaseSelection {
+    previous: Option<String>,
+}
+
+impl Drop for DatabaseSelection {
+    fn drop(&mut self) {
+        let previous: Option<String> = self.previous.take();
+        SELECTED_DATABASE.with(|selected| *selected.borrow_mut() = previous);
+    }
+}
+
+/// Selects the database that get_db_instance() returns on this thread, until the returned
+/// selection is dropped. None selects the default database.
+pub fn select_database(database_name: Option<String>) -> DatabaseSelection {
+    let previous: Option<String> =
+        SELECTED_DATABASE.with(|selected| selected.replace(database_name));
+    DatabaseSelection { previous }
+}
+
 pub fn get_db_instance() -> Result<&'static mut Database, String> {
+    let selected: Option<Result<&'static mut Database, String>> =
+        SELECTED_DATABASE.with(|selected| {
+            selected
+                .borrow()
+                .as_ref()
+                .map(|database_name| get_named_db_instance(database_name))
+        });
+    if let Some(database) = selected {
+        return database;
+    }
     unsafe {
         match DATABASE_INSTANCE {
             Some(ref mut db) => Ok(db),
@@ -74,6 +164,26 @@ pub fn create_db_instance(database_name: &String) -> Result<(), String> {
     Ok(())
 }
 
+pub fn create_db_instance_with_main_branch(
+    database_name: &String,
+    main_branch_name: &String,
+) -> Result<(), String> {
+    unsafe {
+        DATABASE_INSTANCE = Some(Database::new_with_main_branch(
+            database_name.clone(),
+            main_branch_name.clone(),
+        )?);
+    }
+    Ok(())
+}
+
+pub fn create_in_memory_db_instance(database_name: &String) -> Result<(), String> {
+    unsafe {
+        DATABASE_INSTANCE = Some(Database::new_in_memory(database_name.clone())?);
+    }
+    Ok(())
+}
+
 pub fn load_db_instance(database_name: &String) -> Result<(), String> {
     match Database::load_db(database_name.clone()) {
         Ok(db) => unsafe {
@@ -85,8 +195,67 @@ pub fn load_db_instance(database_name: &String) -> Result<(), String> {
     Ok(())
 }
 
+// A database that was created or opened next to the default one
+fn get_named_db_instance(database_name: &String) -> Result<&'static mut Database, String> {
+    unsafe {
+        NAMED_DATABASE_INSTANCES
+            .as_mut()
+            .and_then(|databases| databases.get_mut(database_name))
+            .map(|database| database.as_mut())
+            .ok_or(format!("Database {} is not open", database_name))
+    }
+}
+
+/// Creates a new database next to the default one, which sessions can switch to.
+/// It will return an error if a database with the name already exists.
+pub fn create_named_db_instance(database_name: &String) -> Result<(), String> {
+    unsafe {
+        let is_default: bool =
+            matches!(DATABASE_INSTANCE, Some(ref db) if db.db_name == *database_name);
+        let databases = NAMED_DATABASE_INSTANCES.get_or_insert_with(HashMap::new);
+        if is_default || databases.contains_key(database_name) {
+            return Err(format!("Database {} already exists", database_name));
+        }
+        let database: Database = Database::new(database_name.clone())?;
+        databases.insert(database_name.clone(), Box::new(database));
+    }
+    Ok(())
+}
+
+/// Opens the database with the given name, so it can be selected with select_database().
+/// It returns the name to select, which is None if it's the default database.
+pub fn open_db_instance(database_name: &String) -> Result<Option<String>, String> {
+    unsafe {
+        if matches!(DATABASE_INSTANCE, Some(ref db) if db.db_name == *database_name) {
+            return Ok(None);
+        }
+        let databases = NAMED_DATABASE_INSTANCES.get_or_insert_with(HashMap::new);
+        if !databases.contains_key(database_name) {
+            let database: Database = Database::load_db(database_name.clone())
+                .map_err(|_| format!("Database {} does not exist", database_name))?;
+            databases.insert(database_name.clone(), Box::new(database));
+        }
+    }
+    Ok(Some(database_name.clone()))
+}
+
+/// Returns true if the database with the given name has been opened, either as the default
+/// database or next to it.
+pub fn is_db_instance_open(database_name: &String) -> bool {
+    unsafe {
+        matches!(DATABASE_INSTANCE, Some(ref db) if db.db_name == *database_name)
+            || matches!(NAMED_DATABASE_INSTANCES, Some(ref databases) if databases.contains_key(database_name))
+    }
+}
+
+/// Deletes the default database, along with any other databases that were hosted next to it
 pub fn delete_db_instance() -> Result<(), String> {
     unsafe {
+        if let Some(databases) = NAMED_DATABASE_INSTANCES.take() {
+            for (_, database) in databases {
+                database.delete_database_dir()?;
+            }
+        }
         match DATABASE_INSTANCE {
             Some(ref mut db) => {
                 db.delete_database_dir()?;
@@ -106,10 +275,44 @@ impl Database {
     /// It will return an error if the database already exists.
     pub fn new(database_name: String) -> Result<Database, String> {
         let db_base_path = Database::get_database_base_path()?;
+        Database::new_in_dir(database_name, db_base_path, MAIN_BRANCH_NAME.to_string())
+    }
+
+    /// Creates a new database at the given path, using the given name for its main branch
+    /// instead of the default `main`. The name is stored in the database's metadata file.
+    /// It will return an error if the database already exists.
+    pub fn new_with_main_branch(
+        database_name: String,
+        main_branch_name: String,
+    ) -> Result<Database, String> {
+        if main_branch_name.is_empty() {
+            return Err("Database::new() Error: Main branch name cannot be empty".to_owned());
+        }
+        let db_base_path = Database::get_database_base_path()?;
+        Database::new_in_dir(database_name, db_base_path, main_branch_name)
+    }
+
+    /// Creates a new database that only lives in memory. Nothing is written to disk,
+    /// and the database is gone once the process exits.
+    /// It will return an error if the database already exists.
+    pub fn new_in_memory(database_name: String) -> Result<Database, String> {
+        let db_base_path = Database::get_in_memory_base_path();
+        Database::new_in_dir(database_name, db_base_path, MAIN_BRANCH_NAME.to_string())
+    }
+
+    /// Creates a new database within the given databases directory.
+    /// The storage backend used is decided by the databases directory path.
+    fn new_in_dir(
+        database_name: String,
+        db_base_path: String,
+        main_branch_name: String,
+    ) -> Result<Database, String> {
+        let backend = get_storage_backend(&db_base_path);
         // If the databases base path './databases/' doesn't exist, create it
-        if !Path::new(&db_base_path).exists() {
-            std::fs::create_dir(&db_base_path)
-                .map_err(|e| "Database::new() Error: ".to_owned() + &e.to_string())?;
+        if !backend.exists(&db_base_path) {
+            backend
+                .create_dir_all(&db_base_path)
+                .map_err(|e| "Database::new() Error: ".to_owned() + &e)?;
         }
 
         // Create the database directory './databases/<database_name>'
@@ -117,35 +320,40 @@ impl Database {
         db_path.push(std::path::MAIN_SEPARATOR);
         db_path.push_str(database_name.as_str());
         // If the database already exists, return an error
-        if Path::new(&db_path).exists() {
+        if backend.exists(&db_path) {
             return Err("Database::new() Error: Database already exists".to_owned());
         }
-        std::fs::create_dir(&db_path)
-            .map_err(|e| "Database::new() Error: ".to_owned() + &e.to_string())?;
+        backend
+            .create_dir_all(&db_path)
+            .map_err(|e| "Database::new() Error: ".to_owned() + &e)?;
 
         // Create the deltas file, which holds the deltas for the commits
         // './databases/<database_name>/deltas.gql'
         let deltas_file_path = Database::append_deltas_file_path(db_path.clone());
-        std::fs::File::create(&deltas_file_path)
-            .map_err(|e| "Database::new() Error: ".to_owned() + &e.to_string())?;
+        backend
+            .create_file(&deltas_file_path)
+            .map_err(|e| "Database::new() Error: ".to_owned() + &e)?;
 
         // Create the commit headers file, which holds all the headers for the commits
         // './databases/<database_name>/commitheaders.gql'
         let commit_headers_file_path = Database::append_commit_headers_file_path(db_path.clone());
-        std::fs::File::create(&commit_headers_file_path)
-            .map_err(|e| "Database::new() Error: ".to_owned() + &e.to_string())?;
+        backend
+            .create_file(&commit_headers_file_path)
+            .map_err(|e| "Database::new() Error: ".to_owned() + &e)?;
 
         // Create the branches file, which holds all the branches for the database
         // './databases/<database_name>/branches.gql'
         let branches_file_path = Database::append_branches_file_path(db_path.clone());
-        std::fs::File::create(&branches_file_path)
-            .map_err(|e| "Database::new() Error: ".to_owned() + &e.to_string())?;
+        backend
+            .create_file(&branches_file_path)
+            .map_err(|e| "Database::new() Error: ".to_owned() + &e)?;
 
         // Create the user credentials file, which holds all the user credentials for the database
         // './databases/<database_name>/user_creds.gql'
         let user_creds = Database::append_user_creds_file_path(db_path.clone());
-        std::fs::File::create(&user_creds)
-            .map_err(|e| "Database::new() Error: ".to_owned() + &e.to_string())?;
+        backend
+            .create_file(&user_creds)
+            .map_err(|e| "Database::new() Error: ".to_owned() + &e)?;
 
         // Create the branch_heads file, which holds all the branch HEADs for the database
         // './databases/<database_name>/branch_heads.gql'
@@ -163,16 +371,25 @@ impl Database {
         // Create the user credentials file object
         let user_creds: UserCREDs = UserCREDs::new(&db_path.clone(), true)?;
 
+        // Create the metadata file, and record the name of the main branch in it
+        // './databases/<database_name>/metadata.gql'
+        let mut metadata: MetadataFile = MetadataFile::new(&db_path.clone(), true)?;
+        metadata.set_value(MAIN_BRANCH_METADATA_KEY, &main_branch_name)?;
+
         // Now create the directory for the main branch
         // './databases/<database_name>/<database_name>-<branch_name>/'
         let mut main_branch_path = db_path.clone();
         main_branch_path.push(std::path::MAIN_SEPARATOR);
         main_branch_path.push_str(database_name.as_str());
         main_branch_path.push(DB_NAME_BRANCH_SEPARATOR);
-        main_branch_path.push_str(MAIN_BRANCH_NAME);
+        main_branch_path.push_str(main_branch_name.as_str());
         // Create a directory for the main branch database.
-        std::fs::create_dir(&main_branch_path)
-            .map_err(|e| "Database::new() Error: ".to_owned() + &e.to_string())?;
+        backend
+            .create_dir_all(&main_branch_path)
+            .map_err(|e| "Database::new() Error: ".to_owned() + &e)?;
+
+        // List the database in the catalog of the databases directory
+        CatalogFile::open(&db_base_path)?.add_database(&database_name, &main_branch_name)?;
 
         Ok(Database {
             db_path: db_path,
@@ -182,7 +399,10 @@ impl Database {
             commit_file: commit_file,
             merged_branches: merged_branches,
             user_creds: user_creds,
-            mutex: ReentrantMutex::new(()),
+            metadata: metadata,
+            main_branch_name: main_branch_name,
+            write_ahead_log: false,
+            mutex: Arc::new(ReentrantMutex::new(())),
         })
     }
 
@@ -196,10 +416,13 @@ impl Database {
         db_path.push(std::path::MAIN_SEPARATOR);
         db_path.push_str(db_name.as_str());
         // If the database doesn't already exist, return an error
-        if !Path::new(&db_path.clone()).exists() {
+        if !get_storage_backend(&db_path).exists(&db_path) {
             return Err("Database::load_db() Error: Database does not exist".to_owned());
         }
 
+        // Finish or undo anything that was interrupted before the files are read
+        wal::replay(&Database::append_wal_dir_path(db_path.clone()))?;
+
         // Load the branch_heads.gql file, which holds all the branch HEADs for the database
         let branch_heads: BranchHEADs = BranchHEADs::new(&db_path.clone(), false)?;
 
@@ -207,7 +430,7 @@ impl Database {
         let branches: Branches = Branches::new(&db_path.clone(), false)?;
 
         // Create the commit file object
-        let commit_file: CommitFile = CommitFile::new(&db_path.clone(), false)?;
+        let mut commit_file: CommitFile = CommitFile::new(&db_path.clone(), false)?;
 
         // Create the merged branches file object
         let merged_branches: MergedBranchesFile = MergedBranchesFile::new(&db_path.clone(), false)?;
@@ -215,6 +438,31 @@ impl Database {
         // Create the user credentials file object
         let user_creds: UserCREDs = UserCREDs::new(&db_path.clone(), false)?;
 
+        // Load the metadata file. Databases created before it existed get one with the defaults.
+        let metadata_path: String = Database::append_metadata_file_path(db_path.clone());
+        let mut metadata: MetadataFile =
+            if get_storage_backend(&metadata_path).exists(&metadata_path) {
+                MetadataFile::new(&db_path.clone(), false)?
+            } else {
+                MetadataFile::new(&db_path.clone(), true)?
+            };
+        let main_branch_name: String = match metadata.get_value(MAIN_BRANCH_METADATA_KEY)? {
+            Some(main_branch_name) => main_branch_name,
+            None => {
+                metadata.set_value(MAIN_BRANCH_METADATA_KEY, &MAIN_BRANCH_NAME.to_string())?;
+                MAIN_BRANCH_NAME.to_string()
+            }
+        };
+
+        // Commits are only compressed if it was turned on for this database
+        commit_file.compress_commits =
+            metadata.get_value(COMMIT_COMPRESSION_METADATA_KEY)? == Some("true".to_string());
+        let write_ahead_log: bool =
+            metadata.get_value(WRITE_AHEAD_LOG_METADATA_KEY)? == Some("true".to_string());
+
+        // Databases created before the catalog existed are listed once they're opened
+        CatalogFile::open(&db_base_path)?.add_database(&db_name, &main_branch_name)?;
+
         Ok(Database {
             db_path,
             db_name,
@@ -223,7 +471,10 @@ impl Database {
             commit_file,
             merged_branches,
             user_creds,
-            mutex: ReentrantMutex::new(()),
+            metadata,
+            main_branch_name,
+            write_ahead_log,
+            mutex: Arc::new(ReentrantMutex::new(())),
         })
     }
 
@@ -249,8 +500,34 @@ impl Database {
         new_branch_name: Option<String>, // If this is Some, then a new branch is created
         prev_node: Option<String>, // If this is Some, then a new branch is created from this commit hash, otherwise it is created from the HEAD
     ) -> Result<(BranchNode, Commit), String> {
-        // Make sure to lock the database before doing anything
-        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+        // The commit, branch, and branch HEAD files are written together, or not at all
+        if self.write_ahead_log {
+            let wal_dir: String = self.get_wal_dir_path();
+            return wal::run_logged(&wal_dir, || {
+                self.create_commit_node_unlogged(
+                    commit_msg,
+                    command,
+                    user,
+                    new_branch_name,
+                    prev_node,
+                )
+            });
+        }
+        self.create_commit_node_unlogged(commit_msg, command, user, new_branch_name, prev_node)
+    }
+
+    fn create_commit_node_unlogged(
+        &mut self,
+        commit_msg: &String,
+        command: &String,
+        user: &mut User,
+        new_branch_name: Option<String>,
+        prev_node: Option<String>,
+    ) -> Result<(BranchNode, Commit), String> {
+        // Make sure to lock the database before doing anything.
+        // The lock is held through a clone of the mutex, so self can still be borrowed mutably.
+        let mutex: Arc<ReentrantMutex<()>> = self.mutex.clone();
+        let _lock: ReentrantMutexGuard<()> = mutex.lock();
 
         let (_, is_behind) = user.get_status();
         if is_behind {
@@ -325,9 +602,9 @@ impl Database {
             let temp_branch_dir: String = self.get_current_working_branch_path(user);
 
             // Delete the temp branch directory
-            std::fs::remove_dir_all(&temp_branch_dir).map_err(|e| {
-                "Database::create_commit_and_node() Error: ".to_owned() + &e.to_string()
-            })?;
+            get_storage_backend(&temp_branch_dir)
+                .remove_dir_all(&temp_branch_dir)
+                .map_err(|e| "Database::create_commit_and_node() Error: ".to_owned() + &e)?;
 
             // Set the user back to the non-temp version of the branch
             user.set_is_on_temp_commit(false);
@@ -338,9 +615,89 @@ impl Database {
         // Clear the commands for the user
         user.set_commands(&Vec::new());
 
+        // Checkpoint the branch every so often, so later merges don't read all of its commits
+        self.checkpoint_if_due(&node)?;
+
         Ok((node, commit))
     }
 
+    /// Recreates a branch from the given commits, which are in the order they were made.
+    /// Each commit keeps its hash, author, timestamp and message, and its diffs are applied
+    /// to the branch's tables. The branch must not have any commits yet.
+    /// The commits at the start that the database already has are the history the branch
+    /// shares with another branch, so the restored branch is branched off of the last of them.
+    pub fn restore_branch_commits(
+        &mut self,
+        branch_name: &String,
+        commits: &Vec<Commit>,
+    ) -> Result<(), String> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        if self.branch_heads.does_branch_exist(branch_name.clone())? {
+            return Err(format!("Branch {} already exists", branch_name));
+        }
+        let existing_hashes: Vec<String> = self.commit_file.get_hashes()?;
+        let num_shared: usize = commits
+            .iter()
+            .take_while(|commit| existing_hashes.contains(&commit.hash))
+            .count();
+        if num_shared == commits.len() {
+            return Err(format!(
+                "The database already has every commit of branch {}",
+                branch_name
+            ));
+        }
+        for commit in &commits[num_shared..] {
+            if existing_hashes.contains(&commit.hash) {
+                return Err(format!("Commit {} already exists", commit.hash));
+            }
+        }
+
+        // Find the node of the last shared commit on the branches that have it
+        let mut prev_node: Option<BranchNode> = None;
+        if num_shared > 0 {
+            let parent_hash: &String = &commits[num_shared - 1].hash;
+            for branch_head in self.branch_heads.get_all_branch_heads()? {
+                let head_node: BranchNode = self
+                    .branch_heads
+                    .get_branch_node_from_head(&branch_head.branch_name, &self.branches)?;
+                prev_node = self.branches.traverse_for_commit(&head_node, parent_hash)?;
+                if prev_node.is_some() {
+                    break;
+                }
+            }
+            if prev_node.is_none() {
+                return Err(format!("Commit {} is not on any branch", parent_hash));
+            }
+        }
+
+        // Create the directory for the branch's tables, if it doesn't exist yet
+        let branch_path: String = self.get_branch_path_from_name(branch_name);
+        let backend = get_storage_backend(&branch_path);
+        if !backend.exists(&branch_path) {
+            backend
+                .create_dir_all(&branch_path)
+                .map_err(|e| "Database::restore_branch_commits() Error: ".to_owned() + &e)?;
+        }
+
+        // Replay each commit on top of the last one, only storing the ones that are new
+        for (i, commit) in commits.iter().enumerate() {
+            construct_tables_from_diffs(&branch_path, &commit.diffs)?;
+            if i < num_shared {
+                continue;
+            }
+            self.commit_file.store_commit(commit)?;
+            prev_node = Some(self.branches.create_branch_node(
+                &mut self.branch_heads,
+                prev_node.as_ref(),
+                branch_name,
+                &commit.hash,
+            )?);
+        }
+        Ok(())
+    }
+
     /// Returns all of the tables in the current working branch
     pub fn get_tables(&self, user: &User) -> Result<Vec<String>, String> {
         // Make sure to lock the database before doing anything
@@ -350,12 +707,11 @@ impl Database {
 
         let mut tables: Vec<String> = Vec::new();
 
-        for entry in std::fs::read_dir(&branch_path)
-            .map_err(|e| "Database::get_tables() Error: ".to_owned() + &e.to_string())?
+        for entry in get_storage_backend(&branch_path)
+            .read_dir(&branch_path)
+            .map_err(|e| "Database::get_tables() Error: ".to_owned() + &e)?
         {
-            let entry =
-                entry.map_err(|e| "Database::get_tables() Error: ".to_owned() + &e.to_string())?;
-            let path = entry.path();
+            let path = Path::new(&entry);
             let file_name = path.file_name();
             if file_name.is_some() {
                 let table_name = file_name.unwrap().to_str().unwrap().to_string();
@@ -378,13 +734,11 @@ impl Database {
 
         let mut table_paths: Vec<String> = Vec::new();
 
-        for entry in std::fs::read_dir(&branch_path)
-            .map_err(|e| "Database::get_table_paths() Error: ".to_owned() + &e.to_string())?
+        for entry in get_storage_backend(&branch_path)
+            .read_dir(&branch_path)
+            .map_err(|e| "Database::get_table_paths() Error: ".to_owned() + &e)?
         {
-            let entry = entry
-                .map_err(|e| "Database::get_table_paths() Error: ".to_owned() + &e.to_string())?;
-            let path = entry.path();
-            table_paths.push(path.into_os_string().into_string().unwrap());
+            table_paths.push(entry);
         }
         Ok(table_paths)
     }
@@ -405,6 +759,19 @@ impl Database {
         branch_path
     }
 
+    /// Pins the branch that the user reads from, so a query keeps seeing the branch as it is now,
+    /// even if another user commits to it before the query is done. It returns None if the user
+    /// has uncommitted changes, since nobody else changes those.
+    pub fn pin_read_snapshot(&self, user: &User) -> Option<ReadSnapshot> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        if user.is_on_temp_commit() {
+            return None;
+        }
+        Some(pin_branch_dir(&self.get_current_branch_path(user)))
+    }
+
     /// Returns the database's current branch path for a user: <path>/<db_name>/<db_name>-<branch_name>
     pub fn get_current_branch_path(&self, user: &User) -> String {
         // Make sure to lock the database before doing anything
@@ -515,7 +882,7 @@ impl Database {
         table_path.push(std::path::MAIN_SEPARATOR);
         table_path.push_str(table_name.as_str());
         table_path.push_str(TABLE_FILE_EXTENSION);
-        if Path::new(&table_path.clone()).exists() {
+        if get_storage_backend(&table_path).exists(&table_path) {
             Ok(table_path)
         } else {
             Err("Error: Table does not exist".to_string())
@@ -529,24 +896,15 @@ impl Database {
 
         let mut table_paths: Vec<String> = Vec::new();
         let branch_path: String = self.get_current_branch_path(user);
-        let mut table_path: String = branch_path.clone();
-        table_path.push(std::path::MAIN_SEPARATOR);
-        table_path.push_str("*");
-        table_path.push_str(TABLE_FILE_EXTENSION);
-        for entry in glob(&table_path)
-            .map_err(|e| "Database::get_all_table_paths() Error: ".to_owned() + &e.to_string())?
+        for entry in get_storage_backend(&branch_path)
+            .read_dir(&branch_path)
+            .map_err(|e| "Database::get_all_table_paths() Error: ".to_owned() + &e)?
         {
-            match entry {
-                Ok(path) => {
-                    table_paths.push(path.to_str().unwrap().to_string());
-                }
-                Err(e) => {
-                    return Err(
-                        "Database::get_all_table_paths() Error: ".to_owned() + &e.to_string()
-                    );
-                }
+            if entry.ends_with(TABLE_FILE_EXTENSION) {
+                table_paths.push(entry);
             }
         }
+        table_paths.sort();
         Ok(table_paths)
     }
 
@@ -558,6 +916,55 @@ impl Database {
         self.db_name.clone()
     }
 
+    /// Returns the databases listed in the catalog of the directory that holds this database,
+    /// which includes this database.
+    pub fn get_catalog_entries(&self) -> Result<Vec<CatalogEntry>, String> {
+        CatalogFile::open(&self.get_databases_dir_path())?.get_entries()
+    }
+
+    /// Returns the name of the database's main branch
+    pub fn get_main_branch_name(&self) -> String {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        self.main_branch_name.clone()
+    }
+
+    /// Turns compression of new commits on or off. The setting is stored in the
+    /// database's metadata file. Commits that were already written are left as they are,
+    /// and both kinds of commits can always be read.
+    pub fn set_commit_compression(&mut self, enabled: bool) -> Result<(), String> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        self.metadata
+            .set_value(COMMIT_COMPRESSION_METADATA_KEY, &enabled.to_string())?;
+        self.commit_file.compress_commits = enabled;
+        Ok(())
+    }
+
+    /// Turns the write-ahead log on or off for the database. While it's on, each statement
+    /// and commit is logged before its pages are written, so a crash can't leave it half done.
+    pub fn set_write_ahead_log(&mut self, enabled: bool) -> Result<(), String> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        self.metadata
+            .set_value(WRITE_AHEAD_LOG_METADATA_KEY, &enabled.to_string())?;
+        self.write_ahead_log = enabled;
+        Ok(())
+    }
+
+    /// Returns true if the write-ahead log is turned on for the database
+    pub fn is_write_ahead_log_enabled(&self) -> bool {
+        self.write_ahead_log
+    }
+
+    /// Returns the directory that holds the write-ahead logs: '<path>/<db_name>/wal'
+    pub fn get_wal_dir_path(&self) -> String {
+        Database::append_wal_dir_path(self.db_path.clone())
+    }
+
     /// Returns a list of all branches on the database
     pub fn get_all_branch_names(&mut self) -> Result<Vec<String>, String> {
         // Make sure to lock the database before doing anything
@@ -655,18 +1062,18 @@ impl Database {
 
         // 1. Create a new directory for the branch
         let new_branch_path: String = self.get_current_branch_path(user);
-        std::fs::create_dir_all(&new_branch_path)
-            .map_err(|e| "Database::create_branch() Error: ".to_owned() + &e.to_string())?;
+        let backend = get_storage_backend(&new_branch_path);
+        backend
+            .create_dir_all(&new_branch_path)
+            .map_err(|e| "Database::create_branch() Error: ".to_owned() + &e)?;
 
         // 2. Copy all the tables from the main branch to the new branch directory
-        let mut options = fs_extra::dir::CopyOptions::new();
-        options.content_only = true;
-        fs_extra::dir::copy(
-            self.get_branch_path_from_name(&MAIN_BRANCH_NAME.to_string()),
-            &new_branch_path,
-            &options,
-        )
-        .map_err(|e| "Database::create_branch() Error: ".to_owned() + &e.to_string())?;
+        backend
+            .copy_dir_contents(
+                &self.get_branch_path_from_name(&self.main_branch_name.clone()),
+                &new_branch_path,
+            )
+            .map_err(|e| "Database::create_branch() Error: ".to_owned() + &e)?;
 
         // 3. Find the common ancestor between the main branch and the new branch
         // Get the node for the new branch's HEAD
@@ -677,7 +1084,7 @@ impl Database {
         // Get the node for the main branch's HEAD
         let node1: BranchNode = match self
             .branch_heads
-            .get_branch_node_from_head(&MAIN_BRANCH_NAME.to_string(), &self.branches)
+            .get_branch_node_from_head(&self.main_branch_name, &self.branches)
         {
             Ok(main_branch_head) => {
                 // The main branch does exist
@@ -740,6 +1147,39 @@ impl Database {
         Ok(())
     }
 
+    /// Points a session at the given branch, so its statements use that branch's tables.
+    /// Unlike switch_branch, this never builds the branch's tables, so the branch must already
+    /// have them on disk. Reads and writes on the branch are still checked against the
+    /// user's permissions as usual.
+    pub fn use_branch(&mut self, branch_name: &String, user: &mut User) -> Result<(), String> {
+        // Make sure to lock the database before doing anything
+        {
+            let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+            // Checking if the argument branch exists. It will return an error if it doesn't exist.
+            self.branch_heads.get_branch_head(&branch_name)?;
+
+            if user.get_diffs().len() > 0 {
+                return Err("Cannot use another branch with uncommitted changes".to_string());
+            }
+
+            let branch_path: String = self.get_branch_path_from_name(&branch_name);
+            if !get_storage_backend(&branch_path).exists(&branch_path) {
+                return Err(format!(
+                    "The tables for branch {} have not been built, use GQL switch_branch instead",
+                    branch_name
+                ));
+            }
+        }
+
+        // The user's temporary copy of their old branch has no changes in it, so it can go
+        if user.is_on_temp_commit() {
+            self.delete_temp_branch_directory(user)?;
+        }
+        user.set_current_branch_name(&branch_name);
+        Ok(())
+    }
+
     /// Switches the database to the given branch.
     /// The branch MUST exist already.
     /// It returns true on success, and false on failure.
@@ -761,27 +1201,26 @@ impl Database {
         let new_branch_path: String = self.get_branch_path_from_name(&branch_name);
 
         // If the branch directory already exists, then we can just switch to it without building that branch
-        if Path::new(&new_branch_path).exists() {
+        let backend = get_storage_backend(&new_branch_path);
+        if backend.exists(&new_branch_path) {
             user.set_current_branch_name(&branch_name);
             return Ok(());
         }
 
         // Create the branch directory
-        std::fs::create_dir_all(&new_branch_path).map_err(|e| {
+        backend.create_dir_all(&new_branch_path).map_err(|e| {
             "Database::switch_branch() Error: Failed to create directory for given branch path: "
                 .to_owned()
-                + &e.to_string()
+                + &e
         })?;
 
         // Copy all the tables from the main branch to the new branch directory
-        let mut options = fs_extra::dir::CopyOptions::new();
-        options.content_only = true;
-        fs_extra::dir::copy(
-            self.get_branch_path_from_name(&MAIN_BRANCH_NAME.to_string()),
-            &new_branch_path,
-            &options,
-        )
-        .map_err(|e| "Database::create_branch() Error: ".to_owned() + &e.to_string())?;
+        backend
+            .copy_dir_contents(
+                &self.get_branch_path_from_name(&self.main_branch_name.clone()),
+                &new_branch_path,
+            )
+            .map_err(|e| "Database::create_branch() Error: ".to_owned() + &e)?;
 
         // Grabbing the nodes for the current user branch HEAD and the new branch HEAD
         let new_branch_node = self
@@ -790,7 +1229,7 @@ impl Database {
         // Get the node for the main branch's HEAD
         let main_branch_node: BranchNode = match self
             .branch_heads
-            .get_branch_node_from_head(&MAIN_BRANCH_NAME.to_string(), &self.branches)
+            .get_branch_node_from_head(&self.main_branch_name, &self.branches)
         {
             Ok(main_branch_head) => {
                 // The main branch does exist
@@ -834,6 +1273,93 @@ impl Database {
         Ok(())
     }
 
+    /// Compares the committed state of a table on two branches, without changing either.
+    /// Tables don't have primary keys, so rows are matched by their location in the table,
+    /// which branches share since a branch starts as a copy of the branch it came from.
+    /// A row at the same location that differs was changed. The rows left over on each side
+    /// are then matched by full-row equality, so rows that were only moved aren't reported.
+    /// A table that is missing on one of the branches is treated as empty there.
+    pub fn table_branch_diff(
+        &mut self,
+        branch_a: &String,
+        branch_b: &String,
+        table_name: &String,
+    ) -> Result<TableBranchDiff, String> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        // Load the table on both branches
+        let mut tables: Vec<Option<Table>> = Vec::new();
+        for branch_name in [branch_a, branch_b] {
+            if !self.branch_heads.does_branch_exist(branch_name.clone())? {
+                return Err(format!("Branch {} does not exist", branch_name));
+            }
+            let branch_path: String = self.get_branch_path_from_name(branch_name);
+            tables.push(Table::new(&branch_path, table_name, None).ok());
+        }
+        let schema: Schema = match (&tables[0], &tables[1]) {
+            (Some(table_a), Some(table_b)) => {
+                if table_a.schema != table_b.schema {
+                    return Err(format!(
+                        "Table {} has a different schema on branches {} and {}",
+                        table_name, branch_a, branch_b
+                    ));
+                }
+                table_a.schema.clone()
+            }
+            (Some(table), None) | (None, Some(table)) => table.schema.clone(),
+            (None, None) => {
+                return Err(format!(
+                    "Table {} does not exist on branches {} or {}",
+                    table_name, branch_a, branch_b
+                ))
+            }
+        };
+        let mut rows_b: HashMap<(u32, u16), Row> = HashMap::new();
+        if let Some(table_b) = tables[1].clone() {
+            for row_info in table_b {
+                rows_b.insert((row_info.pagenum, row_info.rownum), row_info.row);
+            }
+        }
+
+        // Match the rows by their location
+        let mut only_in_a: Vec<Row> = Vec::new();
+        let mut changed: Vec<(Row, Row)> = Vec::new();
+        if let Some(table_a) = tables[0].clone() {
+            for row_info in table_a {
+                match rows_b.remove(&(row_info.pagenum, row_info.rownum)) {
+                    Some(row_b) if row_b == row_info.row => {}
+                    Some(row_b) => changed.push((row_info.row, row_b)),
+                    None => only_in_a.push(row_info.row),
+                }
+            }
+        }
+        let mut locations_b: Vec<(u32, u16)> = rows_b.keys().cloned().collect();
+        locations_b.sort();
+        let mut only_in_b: Vec<Row> = locations_b
+            .iter()
+            .filter_map(|location| rows_b.remove(location))
+            .collect();
+
+        // Rows that are on both sides, but at different locations, are the same row
+        only_in_a.retain(
+            |row| match only_in_b.iter().position(|row_b| row_b == row) {
+                Some(i) => {
+                    only_in_b.remove(i);
+                    false
+                }
+                None => true,
+            },
+        );
+
+        Ok(TableBranchDiff {
+            schema,
+            only_in_a,
+            only_in_b,
+            changed,
+        })
+    }
+
     /// Gets all open rows in a table into a vector of empty row locations.
     pub fn get_open_rows_in_table(
         &self,
@@ -883,6 +1409,138 @@ impl Database {
         Ok(open_rows)
     }
 
+    /// Counts the rows of a table on the user's branch, along with the empty row slots.
+    /// Empty slots before the last row were left by deleted rows, and are reused by inserts,
+    /// while the slots after the last row haven't been used yet.
+    pub fn get_table_stats(&self, table_name: &String, user: &User) -> Result<TableStats, String> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        if !self.get_tables(user)?.contains(table_name) {
+            return Err(format!("Table {} does not exist", table_name));
+        }
+        let table_dir: String = self.get_current_working_branch_path(user);
+        let table: Table = Table::new(&table_dir, table_name, None)?;
+        let mut num_pages: u32 = 0;
+        for pagenum in 1..table.max_pages {
+            if read_page(pagenum, &table.path)?.1 == PageType::Data {
+                num_pages += 1;
+            }
+        }
+        let mut num_rows: usize = 0;
+        let mut last_row: Option<(u32, u16)> = None;
+        for row_info in table {
+            num_rows += 1;
+            last_row = Some((row_info.pagenum, row_info.rownum));
+        }
+
+        let mut stats: TableStats = TableStats {
+            num_rows,
+            num_pages,
+            free_slots: 0,
+            spare_slots: 0,
+        };
+        for open_rows in self.get_open_rows_in_table(table_name, &table_dir, 0)? {
+            let location: (u32, u16) = (open_rows.location.pagenum, open_rows.location.rownum);
+            // A run of empty slots never has a row in it, so it's either all before or all after the last row
+            match last_row {
+                Some(last_row) if location < last_row => {
+                    stats.free_slots += open_rows.num_rows_empty as usize
+                }
+                _ => stats.spare_slots += open_rows.num_rows_empty as usize,
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Cross-checks every index of a table on the user's branch against the table's rows.
+    /// Reports the entries that point to a missing row, or to a row with a different key,
+    /// along with the rows that the index has no entry for. Nothing is reported if the
+    /// indexes match the rows.
+    pub fn verify_indexes(
+        &self,
+        table_name: &String,
+        user: &User,
+    ) -> Result<Vec<IndexProblem>, String> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        if !self.get_tables(user)?.contains(table_name) {
+            return Err(format!("Table {} does not exist", table_name));
+        }
+        let table_dir: String = self.get_current_working_branch_path(user);
+        let table: Table = Table::new(&table_dir, table_name, None)?;
+        let rows: HashMap<(u32, u16), Row> = table
+            .clone()
+            .map(|row_info| ((row_info.pagenum, row_info.rownum), row_info.row))
+            .collect();
+
+        let mut problems: Vec<IndexProblem> = Vec::new();
+        for (index_id, (pagenum, index_name)) in table
+            .indexes
+            .iter()
+            .sorted_by(|(_, a), (_, b)| a.1.cmp(&b.1))
+        {
+            let btree: BTree = BTree::load_btree_from_root_page(
+                &table,
+                *pagenum,
+                index_id.clone(),
+                cols_id_to_index_key_type(index_id, &table.schema),
+                index_name.clone(),
+            )?;
+            let mut report = |location: &RowLocation, key: &IndexKey, problem: String| {
+                problems.push(IndexProblem {
+                    index_name: index_name.clone(),
+                    location: location.clone(),
+                    key: key.clone(),
+                    problem,
+                })
+            };
+
+            // The rows that have an entry with the right key
+            let mut indexed: HashSet<(u32, u16)> = HashSet::new();
+            for (key, location) in btree.get_all_key_locations()? {
+                let slot: (u32, u16) = (location.pagenum, location.rownum);
+                match rows.get(&slot) {
+                    None => report(&location, &key, "points to a missing row".to_string()),
+                    Some(row) => {
+                        let row_key: IndexKey = get_index_key_from_row(row, index_id);
+                        if compare_indexes(&key, &row_key) != KeyComparison::Equal {
+                            report(
+                                &location,
+                                &key,
+                                format!(
+                                    "points to a row with the key {}",
+                                    row_key
+                                        .iter()
+                                        .map(|value| value.to_sql_value().to_string())
+                                        .join(", ")
+                                ),
+                            );
+                        } else if !indexed.insert(slot) {
+                            report(
+                                &location,
+                                &key,
+                                "points to a row that's already indexed".to_string(),
+                            );
+                        }
+                    }
+                }
+            }
+            for (slot, row) in rows.iter().sorted_by_key(|(slot, _)| **slot) {
+                if !indexed.contains(slot) {
+                    let location: RowLocation = RowLocation {
+                        pagenum: slot.0,
+                        rownum: slot.1,
+                    };
+                    let key: IndexKey = get_index_key_from_row(row, index_id);
+                    report(&location, &key, "row has no entry".to_string());
+                }
+            }
+        }
+        Ok(problems)
+    }
+
     /// Merges two branches together.
     /// It uses the user's branch as the destination branch, and the given branch as the source branch.
     pub fn merge_branches(
@@ -898,24 +1556,23 @@ impl Database {
         let merged_diffs: Vec<Diff>;
         let branched_commit: String;
 
+        // 0. Ensure user is on the destination branch
+        if user.get_current_branch_name() != dest_branch_name {
+            return Err(
+                "Database::merge_branches() Error: You must be on the destination branch to merge."
+                    .to_owned(),
+            );
+        }
+
+        // 1, 2 and 4. Find the source commits since the common ancestor,
+        // and squash the destination's commits since then
+        let (common_ancestor, src_commits, dest_diffs) =
+            self.get_branch_divergence(src_branch_name, user, true)?;
+
         {
             // Make sure to lock the database before doing anything
             let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
 
-            // Ensure both branches exist
-            // Checking if the argument branch exists. It will return an error if it doesn't exist.
-            self.branch_heads.get_branch_head(&dest_branch_name)?;
-            self.branch_heads.get_branch_head(&src_branch_name)?;
-
-            // 0. Ensure user is on the destination branch
-            if user.get_current_branch_name() != dest_branch_name {
-                return Err("Database::merge_branches() Error: You must be on the destination branch to merge.".to_owned());
-            }
-            // Ensure that the user does not have uncommited changes
-            if user.get_diffs().len() > 0 {
-                return Err("Database::merge_branches() Error: You must commit your changes before merging.".to_owned());
-            }
-
             // Merging two branches follows these steps:
             // 0. Ensure user is on destination branch.
             // 1. Get the nodes for the destination branch HEAD and the source branch HEAD
@@ -929,66 +1586,38 @@ impl Database {
             // 7. Create a new commit on destination branch with the diffs from the merge.
             // 8. Delete source branch (optionally)
 
-            // 1. Get the nodes for the destination branch and the source branch
-            let dest_branch_node: BranchNode = self
-                .branch_heads
-                .get_branch_node_from_head(&dest_branch_name, &self.branches)?;
-            let src_branch_node: BranchNode = self
-                .branch_heads
-                .get_branch_node_from_head(&src_branch_name, &self.branches)?;
-
-            // 2. Find a common ancestor
-            let common_ancestor: BranchNode =
-                self.find_common_ancestor(&dest_branch_node, &src_branch_node)?;
-
             // 3. Squash commits of source branch (common ancestor to branch head) into a single commit object
             //    with the message merge_cmt_msg and the current timestamp
-            let src_commits: Vec<Commit> =
-                self.get_commits_between_nodes(Some(&common_ancestor), &src_branch_node)?;
-
-            // Only squash the commits if we have some to squash
-            let src_diffs: Vec<Diff>;
-            if src_commits.len() > 0 {
-                let src_squashed_cmt: Commit =
-                    self.commit_file
-                        .squash_commits(user.get_user_id(), &src_commits, false)?;
-                src_diffs = src_squashed_cmt.diffs;
-
-                // If we're deleting the branch, we need the branching_commit to be the common_ancestor's hash,
-                // otherwise it's the last src_commits's hash
-                branched_commit = if do_delete_src_branch {
-                    common_ancestor.commit_hash.clone()
-                } else {
-                    src_commits.clone().last().unwrap().hash.clone()
-                };
+            let src_squashed_cmt: Commit =
+                self.commit_file
+                    .squash_commits(user.get_user_id(), &src_commits, false)?;
+            let src_diffs: Vec<Diff> = src_squashed_cmt.diffs;
+
+            // If we're deleting the branch, we need the branching_commit to be the common_ancestor's hash,
+            // otherwise it's the last src_commits's hash
+            branched_commit = if do_delete_src_branch {
+                common_ancestor.commit_hash.clone()
             } else {
-                return Err(
-                    "Merge Branches Error: Must have at least one source commit to merge."
-                        .to_owned(),
-                );
-            }
-
-            // 4. Squash commits of destination branch (common ancestor to branch head) into a single commit object
-            //    with the message merge_cmt_msg and the current timestamp
-            let dest_commits: Vec<Commit> =
-                self.get_commits_between_nodes(Some(&common_ancestor), &dest_branch_node)?;
-
-            // Only squash the commits if we have some to squash
-            let mut dest_diffs: Vec<Diff> = Vec::new();
-            if dest_commits.len() > 0 {
-                let dest_squashed_cmt: Commit =
-                    self.commit_file
-                        .squash_commits(user.get_user_id(), &dest_commits, false)?;
-                dest_diffs = dest_squashed_cmt.diffs;
-            }
+                src_commits.clone().last().unwrap().hash.clone()
+            };
 
             // 5. Merge the two squashes together using the merging algorithm.
-            merged_diffs = create_merge_diffs(
+            //    A clean merge reports as many of its conflicts as the user asked for.
+            merged_diffs = create_merge_diffs_with_insert_map(
                 &src_diffs,
                 &dest_diffs,
                 &self.get_current_working_branch_path(user),
-                conflict_res_algo,
+                conflict_res_algo.clone(),
+                &mut HashMap::new(),
+                user.get_max_merge_conflicts(),
             )?;
+            if VERIFY_MERGES && conflict_res_algo == MergeConflictResolutionAlgo::NoConflicts {
+                verify_merge_diffs(
+                    &src_diffs,
+                    &merged_diffs,
+                    &self.get_current_working_branch_path(user),
+                )?;
+            }
 
             // If we aren't committing the merge, we can just return here
             if !do_commit_merge {
@@ -1018,19 +1647,164 @@ impl Database {
             None,
         )?;
 
-        // 8. Delete source branch (optionally)
-        if do_delete_src_branch {
-            del_branch(user, &src_branch_name, false, vec![user.clone()])?;
+        // 8. Delete source branch (optionally)
+        if do_delete_src_branch {
+            del_branch(user, &src_branch_name, false, vec![user.clone()])?;
+        }
+
+        // We need to store the last commit in the source branch and the merged commit as a link
+        self.merged_branches.insert_merged_branch(
+            &src_branch_name,
+            &branched_commit,
+            &commit.hash,
+        )?;
+
+        Ok(commit)
+    }
+
+    /// Merges the source branch into the user's branch by replaying each of the source branch's
+    /// commits onto it, oldest first, instead of squashing them into a single merge commit.
+    /// Each replayed commit keeps its message, and conflicts with the destination branch are
+    /// resolved with the given algorithm. Returns the new commits, oldest first.
+    pub fn replay_branch(
+        &mut self,
+        src_branch_name: &String, // The source branch to replay the commits of
+        user: &mut User,          // The user, who's branch will be the destination branch
+        conflict_res_algo: MergeConflictResolutionAlgo, // What type of conflict resolution algorithm to use
+        do_delete_src_branch: bool, // Whether we delete the source branch after the merge or not
+    ) -> Result<Vec<Commit>, String> {
+        let dest_branch_name: String = user.get_current_branch_name();
+
+        // A clean replay shouldn't stop partway through, so check the whole merge first
+        if conflict_res_algo == MergeConflictResolutionAlgo::NoConflicts {
+            self.merge_branches(
+                src_branch_name,
+                user,
+                &"Test Merge Conflict".to_string(),
+                false,
+                MergeConflictResolutionAlgo::NoConflicts,
+                false,
+            )?;
+        }
+
+        let (common_ancestor, src_commits, dest_diffs) =
+            self.get_branch_divergence(src_branch_name, user, false)?;
+
+        // Each source commit is merged against the destination's own changes, like a cherry-pick.
+        // Rows are mapped between the branches across all the commits, so that a commit can
+        // update the rows an earlier one inserted at a different location on the destination.
+        let mut insert_map: HashMap<(u32, u16), (u32, u16)> = HashMap::new();
+        let mut commits: Vec<Commit> = Vec::new();
+        for src_commit in &src_commits {
+            let merged_diffs: Vec<Diff> = create_merge_diffs_with_insert_map(
+                &src_commit.diffs,
+                &dest_diffs,
+                &self.get_current_working_branch_path(user),
+                conflict_res_algo.clone(),
+                &mut insert_map,
+                user.get_max_merge_conflicts(),
+            )?;
+            user.set_diffs(&merged_diffs);
+            construct_tables_from_diffs(
+                &self.get_current_working_branch_path(user),
+                &user.get_diffs(),
+            )?;
+            let (_, commit) = self.create_commit_on_head(
+                &src_commit.message,
+                &format!(
+                    "Replayed {} from {} onto {}",
+                    src_commit.hash, src_branch_name, dest_branch_name
+                ),
+                user,
+                None,
+            )?;
+            commits.push(commit);
+        }
+
+        if do_delete_src_branch {
+            del_branch(user, src_branch_name, false, vec![user.clone()])?;
+        }
+
+        // Link the source branch to the last replayed commit, as a squashed merge does
+        let branched_commit: String = if do_delete_src_branch {
+            common_ancestor.commit_hash.clone()
+        } else {
+            src_commits.last().unwrap().hash.clone()
+        };
+        self.merged_branches.insert_merged_branch(
+            src_branch_name,
+            &branched_commit,
+            &commits.last().unwrap().hash,
+        )?;
+
+        Ok(commits)
+    }
+
+    /// Finds where the source branch and the user's branch diverged, for merging the source
+    /// branch into the user's branch. Returns the common ancestor, the source branch's commits
+    /// since then (oldest first), and the destination branch's changes since then, squashed.
+    /// The source commits are only read from checkpoints if they're going to be squashed.
+    fn get_branch_divergence(
+        &mut self,
+        src_branch_name: &String, // The source branch to merge from
+        user: &User,              // The user, who's branch will be the destination branch
+        squash_src_commits: bool, // Whether the caller squashes the source commits together
+    ) -> Result<(BranchNode, Vec<Commit>, Vec<Diff>), String> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        let dest_branch_name: String = user.get_current_branch_name();
+
+        // Ensure both branches exist
+        // Checking if the argument branch exists. It will return an error if it doesn't exist.
+        self.branch_heads.get_branch_head(&dest_branch_name)?;
+        self.branch_heads.get_branch_head(&src_branch_name)?;
+
+        // Ensure that the user does not have uncommited changes
+        if user.get_diffs().len() > 0 {
+            return Err(
+                "Database::merge_branches() Error: You must commit your changes before merging."
+                    .to_owned(),
+            );
         }
 
-        // We need to store the last commit in the source branch and the merged commit as a link
-        self.merged_branches.insert_merged_branch(
-            &src_branch_name,
-            &branched_commit,
-            &commit.hash,
-        )?;
+        // 1. Get the nodes for the destination branch and the source branch
+        let dest_branch_node: BranchNode = self
+            .branch_heads
+            .get_branch_node_from_head(&dest_branch_name, &self.branches)?;
+        let src_branch_node: BranchNode = self
+            .branch_heads
+            .get_branch_node_from_head(&src_branch_name, &self.branches)?;
 
-        Ok(commit)
+        // 2. Find a common ancestor
+        let common_ancestor: BranchNode =
+            self.find_common_ancestor(&dest_branch_node, &src_branch_node)?;
+
+        // 3. Get the commits of source branch (common ancestor to branch head)
+        let src_commits: Vec<Commit> = if squash_src_commits {
+            self.get_checkpointed_commits_between_nodes(Some(&common_ancestor), &src_branch_node)?
+        } else {
+            self.get_commits_between_nodes(Some(&common_ancestor), &src_branch_node)?
+        };
+        if src_commits.len() == 0 {
+            return Err(
+                "Merge Branches Error: Must have at least one source commit to merge.".to_owned(),
+            );
+        }
+
+        // 4. Squash commits of destination branch (common ancestor to branch head) into a single commit object
+        let dest_commits: Vec<Commit> =
+            self.get_checkpointed_commits_between_nodes(Some(&common_ancestor), &dest_branch_node)?;
+
+        // Only squash the commits if we have some to squash
+        let mut dest_diffs: Vec<Diff> = Vec::new();
+        if dest_commits.len() > 0 {
+            let dest_squashed_cmt: Commit =
+                self.commit_file
+                    .squash_commits(user.get_user_id(), &dest_commits, false)?;
+            dest_diffs = dest_squashed_cmt.diffs;
+        }
+        Ok((common_ancestor, src_commits, dest_diffs))
     }
 
     /// Checks for a merge conflict between the source branch name and the branch that the user is currently on
@@ -1068,17 +1842,16 @@ impl Database {
 
         // Create the temp branch directory
         // It only creates the parent directories, so we have to make the temp branch path a parent directory
-        std::fs::create_dir_all(&format!("{}", &temp_branch_path)).map_err(|e| {
-            "Database::create_temp_branch_directory() Error: ".to_owned() + &e.to_string()
-        })?;
+        let backend = get_storage_backend(&temp_branch_path);
+        backend
+            .create_dir_all(&temp_branch_path)
+            .map_err(|e| "Database::create_temp_branch_directory() Error: ".to_owned() + &e)?;
 
         // Copy the current branch directory <db_name>-<branch_name>
         // to the temp branch directory <db_name>-<branch_name>-<user_id>
-        let mut options = fs_extra::dir::CopyOptions::new();
-        options.content_only = true; // Only copy the files not the directory
-        fs_extra::dir::copy(curr_branch_path, temp_branch_path, &options).map_err(|e| {
-            "Database::create_temp_branch_directory() Error: ".to_owned() + &e.to_string()
-        })?;
+        backend
+            .copy_dir_contents(&curr_branch_path, &temp_branch_path)
+            .map_err(|e| "Database::create_temp_branch_directory() Error: ".to_owned() + &e)?;
 
         // Update the user to indicate that they are on the temp branch
         user.set_is_on_temp_commit(true);
@@ -1096,9 +1869,9 @@ impl Database {
         let temp_branch_path: String = self.get_temp_db_dir_path(user);
 
         // Remove the temp branch directory <db_name>-<branch_name>-<user_id>
-        std::fs::remove_dir_all(temp_branch_path).map_err(|e| {
-            "Database::delete_temp_branch_directory() Error: ".to_owned() + &e.to_string()
-        })?;
+        get_storage_backend(&temp_branch_path)
+            .remove_dir_all(&temp_branch_path)
+            .map_err(|e| "Database::delete_temp_branch_directory() Error: ".to_owned() + &e)?;
 
         // Update the user to indicate that they are on the temp branch
         user.set_is_on_temp_commit(false);
@@ -1255,7 +2028,8 @@ impl Database {
 
         let mut diffs: Vec<Vec<Diff>> = Vec::new();
 
-        let commits: Vec<Commit> = self.get_commits_between_nodes(node1, node2)?;
+        // The diffs are applied in order, so a checkpoint can stand in for the commits it covers
+        let commits: Vec<Commit> = self.get_checkpointed_commits_between_nodes(node1, node2)?;
 
         // Iterate through the commits and get the diffs
         for commit in commits {
@@ -1333,6 +2107,171 @@ impl Database {
         Ok(commits)
     }
 
+    /// Same as get_commits_between_nodes, but a run of commits that a checkpoint covers is read
+    /// as the checkpoint's single squashed commit, which is given the hash of the newest commit
+    /// in the run. This should only be used when the commits are squashed or applied in order.
+    pub fn get_checkpointed_commits_between_nodes(
+        &self,
+        node1: Option<&BranchNode>,
+        node2: &BranchNode,
+    ) -> Result<Vec<Commit>, String> {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        let checkpoints: HashMap<String, (Option<String>, String)> = self.get_checkpoints()?;
+        let node1_hash: Option<&String> = node1.map(|node| &node.commit_hash);
+
+        // Get the hashes of the commits from node2 back to node1, newest first.
+        // Only the branch nodes are read here, not the commits.
+        let mut hashes: Vec<String> = Vec::new();
+        let mut curr_node: Option<BranchNode> = Some(node2.clone());
+        loop {
+            match curr_node {
+                Some(curr_node_value) => {
+                    if Some(&curr_node_value.commit_hash) == node1_hash {
+                        // We have reached node1, so break
+                        break;
+                    }
+                    hashes.push(curr_node_value.commit_hash.clone());
+                    curr_node = self.branches.get_prev_branch_node(&curr_node_value)?;
+                }
+                None if node1.is_some() => {
+                    return Err("There is no common ancestor".to_string());
+                }
+                None => break,
+            }
+        }
+
+        let mut commits: Vec<Commit> = Vec::new();
+        let mut idx: usize = 0;
+        while idx < hashes.len() {
+            // A checkpoint can only be used if all the commits it covers are wanted,
+            // which means its base is node1 or a commit that comes after node1
+            let checkpoint: Option<(usize, &String)> =
+                checkpoints
+                    .get(&hashes[idx])
+                    .and_then(|(base, checkpoint_hash)| {
+                        let end: Option<usize> = match base {
+                            Some(base) if Some(base) == node1_hash => Some(hashes.len()),
+                            Some(base) => hashes[idx..]
+                                .iter()
+                                .position(|hash| hash == base)
+                                .map(|pos| idx + pos),
+                            None if node1.is_none() => Some(hashes.len()),
+                            None => None,
+                        };
+                        end.map(|end| (end, checkpoint_hash))
+                    });
+            match checkpoint {
+                Some((end, checkpoint_hash)) => {
+                    let mut commit: Commit = self.commit_file.fetch_commit(checkpoint_hash)?;
+                    commit.hash = hashes[idx].clone();
+                    commits.push(commit);
+                    idx = end;
+                }
+                None => {
+                    commits.push(self.commit_file.fetch_commit(&hashes[idx])?);
+                    idx += 1;
+                }
+            }
+        }
+
+        // Now commits contain all the commits between node1 and node2
+        commits.reverse();
+
+        Ok(commits)
+    }
+
+    /// Checkpoints the head of the given branch. A checkpoint squashes the branch's commits since
+    /// its last checkpoint (or since the origin, if it has none) into a single commit, which is
+    /// stored in the commit file but isn't on any branch. Merging or building the branch later
+    /// reads the checkpoint instead of each of those commits. Returns the checkpoint commit.
+    pub fn create_checkpoint(&mut self, branch_name: &String) -> Result<Commit, String> {
+        // Make sure to lock the database before doing anything
+        let mutex: Arc<ReentrantMutex<()>> = self.mutex.clone();
+        let _lock: ReentrantMutexGuard<()> = mutex.lock();
+
+        let node: BranchNode = self
+            .branch_heads
+            .get_branch_node_from_head(branch_name, &self.branches)?;
+        let checkpoint: Commit = self.create_checkpoint_at_node(&node)?;
+        self.branch_heads
+            .reset_commits_since_checkpoint(branch_name)?;
+        Ok(checkpoint)
+    }
+
+    /// Gets every checkpoint, by the hash of the commit it was made at.
+    /// Each one has the hash of the commit it starts after (None for the origin),
+    /// and the hash of its squashed commit.
+    fn get_checkpoints(&self) -> Result<HashMap<String, (Option<String>, String)>, String> {
+        self.metadata
+            .get_values_with_prefix(CHECKPOINT_METADATA_KEY_PREFIX)?
+            .into_iter()
+            .map(|(commit_hash, value)| match value.split_once(':') {
+                Some((base, checkpoint_hash)) => {
+                    let base: Option<String> = match base {
+                        "" => None,
+                        _ => Some(base.to_string()),
+                    };
+                    Ok((commit_hash, (base, checkpoint_hash.to_string())))
+                }
+                None => Err(format!("Invalid checkpoint for commit {}", commit_hash)),
+            })
+            .collect()
+    }
+
+    /// Squashes the commits since the last checkpoint before the given node into a checkpoint
+    fn create_checkpoint_at_node(&mut self, node: &BranchNode) -> Result<Commit, String> {
+        let checkpoints: HashMap<String, (Option<String>, String)> = self.get_checkpoints()?;
+        if checkpoints.contains_key(&node.commit_hash) {
+            return Err(format!(
+                "Commit {} has already been checkpointed",
+                node.commit_hash
+            ));
+        }
+
+        // Checkpoints start where the last one left off, so together they go back to the origin
+        let mut base_node: Option<BranchNode> = self.branches.get_prev_branch_node(node)?;
+        while let Some(base_node_value) = &base_node {
+            if checkpoints.contains_key(&base_node_value.commit_hash) {
+                break;
+            }
+            base_node = self.branches.get_prev_branch_node(base_node_value)?;
+        }
+
+        let commits: Vec<Commit> = self.get_commits_between_nodes(base_node.as_ref(), node)?;
+        let newest_commit: &Commit = commits.last().ok_or("No commits to checkpoint")?;
+        let mut checkpoint: Commit =
+            self.commit_file
+                .squash_commits(newest_commit.user_id.clone(), &commits, false)?;
+        checkpoint.timestamp = newest_commit.timestamp.clone();
+        checkpoint.message = format!("Checkpoint of {} commits", commits.len());
+        checkpoint.command = format!("GQL checkpoint {}", node.commit_hash);
+        self.commit_file.store_commit(&checkpoint)?;
+
+        let base_hash: String = match &base_node {
+            Some(base_node) => base_node.commit_hash.clone(),
+            None => "".to_string(),
+        };
+        self.metadata.set_value(
+            &format!("{}{}", CHECKPOINT_METADATA_KEY_PREFIX, node.commit_hash),
+            &format!("{}:{}", base_hash, checkpoint.hash),
+        )?;
+        Ok(checkpoint)
+    }
+
+    /// Checkpoints the given node, which is the head of its branch, if there have been
+    /// CHECKPOINT_INTERVAL commits on the branch since its last checkpoint
+    fn checkpoint_if_due(&mut self, node: &BranchNode) -> Result<(), String> {
+        let branch_head: BranchHead = self.branch_heads.get_branch_head(&node.branch_name)?;
+        if (branch_head.commits_since_checkpoint as usize) < CHECKPOINT_INTERVAL {
+            return Ok(());
+        }
+        self.create_checkpoint_at_node(node)?;
+        self.branch_heads
+            .reset_commits_since_checkpoint(&node.branch_name)
+    }
+
     /// Delete branch directories that aren't present in the branches_to_keep.
     /// It does not delete the main branch directory.
     pub fn remove_unneeded_branch_directories(
@@ -1347,8 +2286,8 @@ impl Database {
 
         // Append the main branch directory in branches_to_keep if it isn't there
         let mut branches_to_keep: Vec<String> = branches_to_keep.clone();
-        if !branches_to_keep.contains(&MAIN_BRANCH_NAME.to_string()) {
-            branches_to_keep.push(MAIN_BRANCH_NAME.to_string());
+        if !branches_to_keep.contains(&self.main_branch_name) {
+            branches_to_keep.push(self.main_branch_name.clone());
         }
 
         // Delete all the branch directories that are not in the branches_to_keep
@@ -1357,13 +2296,14 @@ impl Database {
                 let branch_path: String = self.get_branch_path_from_name(&branch_dir);
 
                 // If directory exists
-                if Path::new(&branch_path).is_dir() {
+                let backend = get_storage_backend(&branch_path);
+                if backend.is_dir(&branch_path) {
                     // Delete the branch directory
-                    std::fs::remove_dir_all(branch_path).map_err(|e| {
+                    backend.remove_dir_all(&branch_path).map_err(|e| {
                         "Database::remove_unneeded_branch_directories() ".to_owned()
                             + &branch_dir.clone()
                             + &" Error: ".to_string()
-                            + &e.to_string()
+                            + &e
                     })?;
                 }
             }
@@ -1397,16 +2337,37 @@ impl Database {
         }
     }
 
+    /// Private static method that returns the path to the databases directory for in-memory databases
+    fn get_in_memory_base_path() -> String {
+        format!(
+            "{}{}databases",
+            IN_MEMORY_PATH_PREFIX,
+            std::path::MAIN_SEPARATOR
+        )
+    }
+
     /// Deletes the directories of the database
     fn delete_database_dir(&self) -> Result<(), String> {
         // Make sure to lock the database before doing anything
         let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
 
         // Remove the directory and all files within it
-        std::fs::remove_dir_all(self.get_database_path()).map_err(|e| e.to_string())?;
+        let db_path: String = self.get_database_path();
+        get_storage_backend(&db_path).remove_dir_all(&db_path)?;
+        CatalogFile::open(&self.get_databases_dir_path())?.remove_database(&self.db_name)?;
         Ok(())
     }
 
+    /// Returns the path of the directory that holds this database, along with the others
+    /// listed in the same catalog: <path>
+    fn get_databases_dir_path(&self) -> String {
+        // Make sure to lock the database before doing anything
+        let _lock: ReentrantMutexGuard<()> = self.mutex.lock();
+
+        let db_path: String = self.get_database_path();
+        db_path[..db_path.len() - self.db_name.len() - 1].to_string()
+    }
+
     /// Returns the database's path: <path>/<db_name>
     fn get_database_path(&self) -> String {
         // Make sure to lock the database before doing anything
@@ -1522,6 +2483,22 @@ impl Database {
         user_creds_file_path.push_str(USER_CREDS_FILE_EXTENSION);
         user_creds_file_path
     }
+
+    /// Private static method that appends the metadata file path to the database_path
+    fn append_wal_dir_path(database_path: String) -> String {
+        let mut wal_dir_path = database_path;
+        wal_dir_path.push(std::path::MAIN_SEPARATOR);
+        wal_dir_path.push_str(wal::WAL_DIR_NAME);
+        wal_dir_path
+    }
+
+    fn append_metadata_file_path(database_path: String) -> String {
+        let mut metadata_file_path = database_path;
+        metadata_file_path.push(std::path::MAIN_SEPARATOR);
+        metadata_file_path.push_str(METADATA_FILE_NAME);
+        metadata_file_path.push_str(METADATA_FILE_EXTENSION);
+        metadata_file_path
+    }
 }
 
 #[cfg(test)]
@@ -1529,7 +2506,7 @@ mod tests {
     use super::*;
     use crate::{
         executor::query::tests::to_selectitems,
-        executor::query::{create_table, select},
+        executor::query::{create_table, insert, select},
         fileio::header::Schema,
         util::{
             bench::fcreate_db_instance,
@@ -3934,6 +4911,211 @@ mod tests {
         delete_db_instance().unwrap();
     }
 
+    #[test]
+    #[serial]
+    fn test_in_memory_db() {
+        let db_name: String = "test_in_memory_db".to_string();
+        let branch_name: String = "in_memory_branch".to_string();
+        let table_name: String = "test_table".to_string();
+        let db_disk_path: String = Database::get_database_base_path().unwrap()
+            + std::path::MAIN_SEPARATOR.to_string().as_str()
+            + db_name.clone().as_str();
+
+        // Create the in-memory database
+        create_in_memory_db_instance(&db_name).unwrap();
+        let db_path: String = get_db_instance().unwrap().get_database_path();
+        assert!(db_path.starts_with(IN_MEMORY_PATH_PREFIX));
+
+        // Create a table and insert some rows on the main branch
+        let mut user: User = User::new("test_user".to_string());
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+        ];
+        create_table(&table_name, &schema, get_db_instance().unwrap(), &mut user).unwrap();
+        insert(
+            vec![
+                vec![Value::I32(1), Value::String("John".to_string())],
+                vec![Value::I32(2), Value::String("Jane".to_string())],
+            ],
+            table_name.clone(),
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(
+                &"First Commit".to_string(),
+                &"Create Table; Insert;".to_string(),
+                &mut user,
+                None,
+            )
+            .unwrap();
+
+        // Create a branch, and insert a row on it
+        get_db_instance()
+            .unwrap()
+            .create_branch(&branch_name, &None, &mut user)
+            .unwrap();
+        insert(
+            vec![vec![Value::I32(3), Value::String("Joe".to_string())]],
+            table_name.clone(),
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(
+                &"Second Commit".to_string(),
+                &"Insert;".to_string(),
+                &mut user,
+                None,
+            )
+            .unwrap();
+
+        // Merge the branch back into main
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&MAIN_BRANCH_NAME.to_string(), &mut user)
+            .unwrap();
+        get_db_instance()
+            .unwrap()
+            .merge_branches(
+                &branch_name,
+                &mut user,
+                &"Merge Commit".to_string(),
+                true,
+                MergeConflictResolutionAlgo::NoConflicts,
+                false,
+            )
+            .unwrap();
+
+        // Make sure main has all the rows
+        let table: Table =
+            Table::from_user(&user, get_db_instance().unwrap(), &table_name, None).unwrap();
+        let rows: Vec<Row> = table.into_iter().map(|row_info| row_info.row).collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows.contains(&vec![Value::I32(3), Value::String("Joe".to_string())]));
+
+        // Make sure nothing was written to disk
+        assert_eq!(Path::new(&db_disk_path).exists(), false);
+        assert_eq!(Path::new(&db_path).exists(), false);
+
+        // Delete the database, which removes it from memory
+        delete_db_instance().unwrap();
+        assert_eq!(get_storage_backend(&db_path).exists(&db_path), false);
+    }
+
+    #[test]
+    #[serial]
+    fn test_custom_main_branch_name() {
+        let db_name: String = "test_custom_main_branch_name".to_string();
+        let main_branch_name: String = "trunk".to_string();
+        let branch_name: String = "feature_branch".to_string();
+        let table_name: String = "test_table".to_string();
+
+        // Create the database with a custom main branch name
+        create_db_instance_with_main_branch(&db_name, &main_branch_name).unwrap();
+        assert_eq!(
+            get_db_instance().unwrap().get_main_branch_name(),
+            main_branch_name
+        );
+        assert!(Path::new(
+            &get_db_instance()
+                .unwrap()
+                .get_branch_path_from_name(&main_branch_name)
+        )
+        .exists());
+
+        // New users start on the custom main branch
+        let mut user: User = User::new("test_user".to_string());
+        assert_eq!(user.get_current_branch_name(), main_branch_name);
+
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+        ];
+        create_table(&table_name, &schema, get_db_instance().unwrap(), &mut user).unwrap();
+        insert(
+            vec![vec![Value::I32(1), Value::String("John".to_string())]],
+            table_name.clone(),
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(
+                &"First Commit".to_string(),
+                &"Create Table; Insert;".to_string(),
+                &mut user,
+                None,
+            )
+            .unwrap();
+
+        // Create a branch off of the custom main branch, and commit on it
+        get_db_instance()
+            .unwrap()
+            .create_branch(&branch_name, &None, &mut user)
+            .unwrap();
+        insert(
+            vec![vec![Value::I32(2), Value::String("Jane".to_string())]],
+            table_name.clone(),
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(
+                &"Second Commit".to_string(),
+                &"Insert;".to_string(),
+                &mut user,
+                None,
+            )
+            .unwrap();
+
+        // Merge the branch back into the custom main branch
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&main_branch_name, &mut user)
+            .unwrap();
+        get_db_instance()
+            .unwrap()
+            .merge_branches(
+                &branch_name,
+                &mut user,
+                &"Merge Commit".to_string(),
+                true,
+                MergeConflictResolutionAlgo::NoConflicts,
+                false,
+            )
+            .unwrap();
+
+        let table: Table =
+            Table::from_user(&user, get_db_instance().unwrap(), &table_name, None).unwrap();
+        let rows: Vec<Row> = table.into_iter().map(|row_info| row_info.row).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.contains(&vec![Value::I32(2), Value::String("Jane".to_string())]));
+
+        // The custom main branch is protected, and the default name means nothing here
+        let mut other_user: User = User::new("other_user".to_string());
+        other_user.set_current_branch_name(&branch_name);
+        assert!(del_branch(&other_user, &main_branch_name, true, vec![]).is_err());
+        assert!(get_db_instance()
+            .unwrap()
+            .switch_branch(&MAIN_BRANCH_NAME.to_string(), &mut other_user)
+            .is_err());
+
+        // The main branch name is kept when the database is loaded again
+        let loaded_db: Database = Database::load_db(db_name.clone()).unwrap();
+        assert_eq!(loaded_db.get_main_branch_name(), main_branch_name);
+
+        delete_db_instance().unwrap();
+    }
+
     /// Helper that compares two tables to make sure that they are identical, but in separate directories
     fn compare_tables(
         table1: &Table,
@@ -3963,4 +5145,77 @@ mod tests {
         }
         true
     }
+
+    #[test]
+    #[serial]
+    fn test_table_branch_diff() {
+        fcreate_db_instance("test_table_branch_diff");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| {
+            crate::executor::query::execute_update(
+                &crate::parser::parser::parse(query, true).unwrap(),
+                user,
+                &"".to_string(),
+            )
+            .unwrap();
+        };
+        let row = |id: i32, name: &str| vec![Value::I32(id), Value::String(name.to_string())];
+
+        // Both branches start with the same rows
+        run("CREATE TABLE people (id INT, name VARCHAR(20))", &mut user);
+        run(
+            "INSERT INTO people VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Cal'), (4, 'Dee')",
+            &mut user,
+        );
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Initial".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
+        get_db_instance()
+            .unwrap()
+            .create_branch(&"feature".to_string(), &None, &mut user)
+            .unwrap();
+
+        // Insert, update and delete a row on the feature branch
+        run("INSERT INTO people VALUES (5, 'Eve')", &mut user);
+        run("UPDATE people SET name = 'Bobby' WHERE id = 2", &mut user);
+        run("DELETE FROM people WHERE id = 3", &mut user);
+        get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Changes".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
+
+        let main_branch: String = get_db_instance().unwrap().get_main_branch_name();
+        let diff: TableBranchDiff = get_db_instance()
+            .unwrap()
+            .table_branch_diff(&main_branch, &"feature".to_string(), &"people".to_string())
+            .unwrap();
+        assert_eq!(diff.only_in_a, vec![row(3, "Cal")]);
+        assert_eq!(diff.only_in_b, vec![row(5, "Eve")]);
+        assert_eq!(diff.changed, vec![(row(2, "Bob"), row(2, "Bobby"))]);
+
+        // Comparing the other way around swaps the sides
+        let diff: TableBranchDiff = get_db_instance()
+            .unwrap()
+            .table_branch_diff(&"feature".to_string(), &main_branch, &"people".to_string())
+            .unwrap();
+        assert_eq!(diff.only_in_a, vec![row(5, "Eve")]);
+        assert_eq!(diff.only_in_b, vec![row(3, "Cal")]);
+        assert_eq!(diff.changed, vec![(row(2, "Bobby"), row(2, "Bob"))]);
+
+        // A branch compared with itself has no differences
+        let diff: TableBranchDiff = get_db_instance()
+            .unwrap()
+            .table_branch_diff(&main_branch, &main_branch, &"people".to_string())
+            .unwrap();
+        assert!(diff.only_in_a.is_empty() && diff.only_in_b.is_empty());
+        assert!(diff.changed.is_empty());
+
+        assert!(get_db_instance()
+            .unwrap()
+            .table_branch_diff(&main_branch, &"missing".to_string(), &"people".to_string())
+            .is_err());
+
+        delete_db_instance().unwrap();
+    }
 }
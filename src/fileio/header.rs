@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::pageio::*;
 use crate::btree::indexes::*;
@@ -7,8 +7,18 @@ pub struct Header {
     pub num_pages: u32,
     pub schema: Schema,
     pub index_top_level_pages: HashMap<IndexID, (u32, String)>,
+    pub unique_indexes: HashSet<IndexID>, // The indexes that don't allow duplicate keys
+    pub ttl: Option<u64>, // The number of seconds that rows are kept for, or None to keep them forever
+    pub version: u64,     // Incremented every time the rows of the table are changed
 }
 
+// Set on an index's column count when the index is unique
+const UNIQUE_INDEX_FLAG: u16 = 0x8000;
+// Written after the indexes when the table has a TTL, followed by the TTL in seconds
+const HAS_TTL_FLAG: u8 = 1;
+// The version comes after the flag and the TTL, whether or not the table has a TTL
+const VERSION_OFFSET: usize = 9;
+
 pub type SchemaCol = (String, Column);
 pub type Schema = Vec<SchemaCol>;
 
@@ -54,6 +64,7 @@ pub fn read_header(file: &String) -> Result<Header, String> {
 
     // Read indexes from page
     let mut indexes: HashMap<IndexID, (u32, String)> = HashMap::new();
+    let mut unique_indexes: HashSet<IndexID> = HashSet::new();
     for _ in 0..num_indexes {
         // Read the name of the index
         let index_name: String = read_string(&buf, index_offset, 20)?;
@@ -62,6 +73,8 @@ pub fn read_header(file: &String) -> Result<Header, String> {
         // Read the number of columns that compose this specific index
         let num_cols_in_idx: u16 = read_type::<u16>(&buf, index_offset)?;
         index_offset += 2;
+        let is_unique: bool = num_cols_in_idx & UNIQUE_INDEX_FLAG != 0;
+        let num_cols_in_idx: u16 = num_cols_in_idx & !UNIQUE_INDEX_FLAG;
 
         let mut index_key: IndexID = Vec::new();
         for _ in 0..num_cols_in_idx {
@@ -73,13 +86,26 @@ pub fn read_header(file: &String) -> Result<Header, String> {
 
         let index_pagenum: u32 = read_type(&buf, index_offset)?;
         index_offset += 4;
+        if is_unique {
+            unique_indexes.insert(index_key.clone());
+        }
         indexes.insert(index_key, (index_pagenum, index_name));
     }
 
+    // Read the TTL, which older headers don't have, so they're left with zeroes there
+    let ttl: Option<u64> = match read_type::<u8>(&buf, index_offset)? {
+        HAS_TTL_FLAG => Some(read_type::<u64>(&buf, index_offset + 1)?),
+        _ => None,
+    };
+    let version: u64 = read_type::<u64>(&buf, index_offset + VERSION_OFFSET)?;
+
     Ok(Header {
         num_pages,
         schema,
         index_top_level_pages: indexes,
+        unique_indexes,
+        ttl,
+        version,
     })
 }
 
@@ -104,7 +130,11 @@ pub fn write_header(file: &String, header: &Header) -> Result<(), String> {
         index_offset += 20;
 
         // Write the number of columns that compose this specific index
-        write_type(buf.as_mut(), index_offset, index_cols.len() as u16)?;
+        let mut num_cols_in_idx: u16 = index_cols.len() as u16;
+        if header.unique_indexes.contains(index_cols) {
+            num_cols_in_idx |= UNIQUE_INDEX_FLAG;
+        }
+        write_type(buf.as_mut(), index_offset, num_cols_in_idx)?;
         index_offset += 2;
 
         // Write the column indices that compose this index
@@ -118,6 +148,13 @@ pub fn write_header(file: &String, header: &Header) -> Result<(), String> {
         index_offset += 4;
     }
 
+    // Write the TTL
+    if let Some(ttl) = header.ttl {
+        write_type(buf.as_mut(), index_offset, HAS_TTL_FLAG)?;
+        write_type(buf.as_mut(), index_offset + 1, ttl)?;
+    }
+    write_type(buf.as_mut(), index_offset + VERSION_OFFSET, header.version)?;
+
     write_page(0, &file, buf.as_ref(), PageType::Header)?;
     Ok(())
 }
@@ -160,6 +197,9 @@ mod tests {
             num_pages: 10,
             schema,
             index_top_level_pages: HashMap::new(),
+            unique_indexes: HashSet::new(),
+            ttl: None,
+            version: 0,
         };
         let path = "test.db".to_string();
         create_file(&path).unwrap();
@@ -185,6 +225,9 @@ mod tests {
             num_pages: 245,
             schema,
             index_top_level_pages: HashMap::new(),
+            unique_indexes: HashSet::new(),
+            ttl: None,
+            version: 0,
         };
         let path = "test1.db".to_string();
         create_file(&path).unwrap();
@@ -217,6 +260,9 @@ mod tests {
             num_pages: 10,
             schema,
             index_top_level_pages: indexes,
+            unique_indexes: HashSet::from([vec![1], vec![0, 2]]),
+            ttl: Some(3600),
+            version: 7,
         };
         let path: String = "test2.db".to_string();
         create_file(&path).unwrap();
@@ -225,6 +271,9 @@ mod tests {
         assert_eq!(header.num_pages, header2.num_pages);
         assert_eq!(header.schema, header2.schema);
         assert_eq!(header.index_top_level_pages, header2.index_top_level_pages);
+        assert_eq!(header.unique_indexes, header2.unique_indexes);
+        assert_eq!(header.ttl, header2.ttl);
+        assert_eq!(header.version, header2.version);
         // Clean up
         std::fs::remove_file("test2.db").unwrap();
     }
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    databaseio,
+    header::{write_header, Header},
+    pageio::*,
+    storage::get_storage_backend,
+    tableio::Table,
+};
+use crate::util::{
+    dbtype::{Column, Value},
+    row::{Row, RowInfo},
+};
+
+// Metadata Keys
+pub const MAIN_BRANCH_METADATA_KEY: &str = "main_branch";
+pub const COMMIT_COMPRESSION_METADATA_KEY: &str = "commit_compression";
+pub const WRITE_AHEAD_LOG_METADATA_KEY: &str = "write_ahead_log";
+// Followed by the hash of a checkpointed commit, see Database::create_checkpoint
+pub const CHECKPOINT_METADATA_KEY_PREFIX: &str = "checkpoint_";
+
+/// This holds the settings of a database, such as the ones that are decided when it is created.
+/// Each setting is a single (key, value) row in the `metadata.gql` table.
+#[derive(Clone)]
+pub struct MetadataFile {
+    metadata_table: Table,
+}
+
+impl MetadataFile {
+    /// Creates a new MetadataFile object.
+    /// If create_file is true, the file and table will be created with a header.
+    /// If create_file is false, the file and table will be opened.
+    pub fn new(dir_path: &String, create_file: bool) -> Result<MetadataFile, String> {
+        // Get filepath info
+        let metadata_filename: String = format!(
+            "{}{}",
+            databaseio::METADATA_FILE_NAME.to_string(),
+            databaseio::METADATA_FILE_EXTENSION.to_string()
+        );
+        let mut filepath: String = format!(
+            "{}{}{}",
+            dir_path,
+            std::path::MAIN_SEPARATOR,
+            metadata_filename
+        );
+        // If the directory path is not given, use the current directory
+        if dir_path.len() == 0 {
+            filepath = metadata_filename;
+        }
+
+        if create_file {
+            get_storage_backend(&filepath).create_file(&filepath)?;
+
+            let schema = vec![
+                ("key".to_string(), Column::String(64)),
+                ("value".to_string(), Column::String(256)),
+            ];
+            let header = Header {
+                num_pages: 2,
+                schema,
+                index_top_level_pages: HashMap::new(),
+                unique_indexes: HashSet::new(),
+                ttl: None,
+                version: 0,
+            };
+            write_header(&filepath, &header)?;
+
+            // Write a blank page to the table
+            let page = [0u8; PAGE_SIZE];
+            write_page(1, &filepath, &page, PageType::Data)?;
+        }
+
+        Ok(MetadataFile {
+            metadata_table: Table::new(
+                &dir_path.clone(),
+                &databaseio::METADATA_FILE_NAME.to_string(),
+                Some(&databaseio::METADATA_FILE_EXTENSION.to_string()),
+            )?,
+        })
+    }
+
+    /// Returns the value stored for the given key, or None if it was never set.
+    pub fn get_value(&self, key: &str) -> Result<Option<String>, String> {
+        match self.find_row(key)? {
+            Some(row_info) => match row_info.row.get(1) {
+                Some(Value::String(value)) => Ok(Some(value.clone())),
+                _ => Err(format!("Error: Metadata value for {} not found", key)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the value for the given key, replacing the old value if there is one.
+    pub fn set_value(&mut self, key: &str, value: &String) -> Result<(), String> {
+        let row: Row = vec![Value::String(key.to_string()), Value::String(value.clone())];
+        match self.find_row(key)? {
+            Some(mut row_info) => {
+                row_info.row = row;
+                self.metadata_table.rewrite_rows(vec![row_info])?;
+            }
+            None => {
+                self.metadata_table.insert_rows(vec![row])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns every (key, value) whose key starts with the given prefix, with the prefix removed
+    pub fn get_values_with_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>, String> {
+        let mut values: Vec<(String, String)> = Vec::new();
+        for row_info in self.metadata_table.clone() {
+            match (row_info.row.get(0), row_info.row.get(1)) {
+                (Some(Value::String(key)), Some(Value::String(value))) => {
+                    if let Some(key) = key.strip_prefix(prefix) {
+                        values.push((key.to_string(), value.clone()));
+                    }
+                }
+                _ => return Err("Error: Metadata key not found".to_string()),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Returns the row holding the given key, if there is one
+    fn find_row(&self, key: &str) -> Result<Option<RowInfo>, String> {
+        for row_info in self.metadata_table.clone() {
+            match row_info.row.get(0) {
+                Some(Value::String(row_key)) => {
+                    if row_key == key {
+                        return Ok(Some(row_info));
+                    }
+                }
+                _ => return Err("Error: Metadata key not found".to_string()),
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_metadata_values() {
+        let mut metadata_file: MetadataFile = MetadataFile::new(&"".to_string(), true).unwrap();
+
+        assert_eq!(metadata_file.get_value("missing").unwrap(), None);
+
+        metadata_file
+            .set_value(MAIN_BRANCH_METADATA_KEY, &"trunk".to_string())
+            .unwrap();
+        assert_eq!(
+            metadata_file.get_value(MAIN_BRANCH_METADATA_KEY).unwrap(),
+            Some("trunk".to_string())
+        );
+
+        // Overwriting a value keeps a single row for the key
+        metadata_file
+            .set_value(MAIN_BRANCH_METADATA_KEY, &"master".to_string())
+            .unwrap();
+        assert_eq!(
+            metadata_file.get_value(MAIN_BRANCH_METADATA_KEY).unwrap(),
+            Some("master".to_string())
+        );
+        assert_eq!(metadata_file.metadata_table.clone().count(), 1);
+
+        std::fs::remove_file(format!(
+            "{}{}",
+            databaseio::METADATA_FILE_NAME,
+            databaseio::METADATA_FILE_EXTENSION
+        ))
+        .unwrap();
+    }
+}
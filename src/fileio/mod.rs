@@ -1,7 +1,12 @@
 // Needs to be removed when the server is fully implemented.
 #![allow(dead_code)]
+pub mod catalog;
 pub mod databaseio;
 pub mod header;
+pub mod metadata;
 pub mod pageio;
 pub mod rowio;
+pub mod snapshot;
+pub mod storage;
 pub mod tableio;
+pub mod wal;
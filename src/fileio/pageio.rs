@@ -1,11 +1,16 @@
-use positioned_io::{RandomAccessFile, ReadAt, Size, WriteAt};
+use super::storage::get_storage_backend;
+use super::wal;
+use std::cell::Cell;
 use std::cmp::min;
-use std::fs::{File, OpenOptions};
-use std::io::Error;
 
 pub const PAGE_SIZE: usize = 4096;
 pub type Page = [u8; PAGE_SIZE]; // Array of Size 4KB
 
+thread_local! {
+    // The number of data pages that have been read from table files, see data_pages_read()
+    static DATA_PAGES_READ: Cell<u64> = const { Cell::new(0) };
+}
+
 #[derive(Debug, Clone, PartialEq)]
 #[repr(u8)]
 pub enum PageType {
@@ -33,15 +38,11 @@ impl TryFrom<u8> for PageType {
 }
 
 // Creates file with given name and size of Page Size
-pub fn create_file(path: &String) -> Result<(), Error> {
-    // If the directory to the file does not exist, create it
-    let path_obj = std::path::Path::new(path);
-    let path_to_file_dir = path_obj.parent().unwrap();
-    std::fs::create_dir_all(path_to_file_dir).unwrap();
-
-    let file = File::create(path)?;
-    file.set_len((PAGE_SIZE + PAGE_HEADER_SIZE) as u64)?;
-    Ok(())
+pub fn create_file(path: &String) -> Result<(), String> {
+    wal::log_file_create(path)?;
+    let backend = get_storage_backend(path);
+    backend.create_file(path)?;
+    backend.write_page(path, 0, &[0; PAGE_SIZE + PAGE_HEADER_SIZE])
 }
 
 /* File IO on the Pages */
@@ -50,19 +51,11 @@ pub fn create_file(path: &String) -> Result<(), Error> {
 // For more details, refer to fileio/README.md
 pub fn read_page(page_num: u32, path: &String) -> Result<(Box<Page>, PageType), String> {
     let mut buf: [u8; PAGE_SIZE + PAGE_HEADER_SIZE] = [0; PAGE_SIZE + PAGE_HEADER_SIZE];
-    let f = RandomAccessFile::open(path).map_err(map_error)?;
-    let x = f
-        .read_at(
-            (page_num * (PAGE_SIZE + PAGE_HEADER_SIZE) as u32) as u64,
-            buf.as_mut(),
-        )
-        .map_err(map_error)?;
-    if x != PAGE_SIZE + PAGE_HEADER_SIZE {
-        return Err(format!("Error reading page {}", page_num));
-    }
+    get_storage_backend(path).read_page(path, page_num, buf.as_mut())?;
 
     // Read the page type and data buffer from the buffer read from the file
     let page_type: PageType = PageType::from(buf[0].try_into()?);
+    count_page_read(&page_type);
     let mut data_buf: Box<Page> = Box::new([0; PAGE_SIZE]);
     data_buf[0..PAGE_SIZE].copy_from_slice(&buf[PAGE_HEADER_SIZE..(PAGE_SIZE + PAGE_HEADER_SIZE)]);
 
@@ -72,47 +65,51 @@ pub fn read_page(page_num: u32, path: &String) -> Result<(Box<Page>, PageType),
 // It's memory efficient to just reuse our old buffer (when possible)
 pub fn load_page(page_num: u32, path: &String, page: &mut Page) -> Result<PageType, String> {
     let mut buf: [u8; PAGE_SIZE + PAGE_HEADER_SIZE] = [0; PAGE_SIZE + PAGE_HEADER_SIZE];
-    let f = RandomAccessFile::open(path).map_err(map_error)?;
-    let x = f
-        .read_at(
-            (page_num * (PAGE_SIZE + PAGE_HEADER_SIZE) as u32) as u64,
-            buf.as_mut(),
-        )
-        .map_err(map_error)?;
-    if x != PAGE_SIZE + PAGE_HEADER_SIZE {
-        return Err(format!("Error reading page {}", page_num));
-    }
+    get_storage_backend(path).read_page(path, page_num, buf.as_mut())?;
 
     let page_type: PageType = PageType::from(buf[0].try_into()?);
+    count_page_read(&page_type);
     page[0..PAGE_SIZE].copy_from_slice(&buf[PAGE_HEADER_SIZE..(PAGE_SIZE + PAGE_HEADER_SIZE)]);
 
     Ok(page_type)
 }
 
+/// The number of data pages that have been read by the current thread.
+/// It's used to see whether an operation had to read the rows of a table.
+pub fn data_pages_read() -> u64 {
+    DATA_PAGES_READ.with(|count| count.get())
+}
+
+fn count_page_read(page_type: &PageType) {
+    if *page_type == PageType::Data {
+        DATA_PAGES_READ.with(|count| count.set(count.get() + 1));
+    }
+}
+
 pub fn write_page(
     page_num: u32,
     path: &String,
     page: &Page,
     page_type: PageType,
 ) -> Result<(), String> {
-    || -> Result<(), Error> {
-        let file = OpenOptions::new().write(true).open(path)?;
-        let file_size = file.size()?.expect("File size is not available");
-        let offset = (page_num * (PAGE_SIZE + PAGE_HEADER_SIZE) as u32) as u64;
-        if offset >= file_size {
-            file.set_len(offset + (PAGE_SIZE + PAGE_HEADER_SIZE) as u64)?;
-        }
-        let mut f = RandomAccessFile::try_new(file)?;
+    // Construct the buffer to write
+    let mut buffer: [u8; PAGE_SIZE + PAGE_HEADER_SIZE] = [0; PAGE_HEADER_SIZE + PAGE_SIZE];
+    buffer[0] = page_type as u8;
+    buffer[PAGE_HEADER_SIZE..(PAGE_SIZE + PAGE_HEADER_SIZE)].copy_from_slice(page);
 
-        // Construct the buffer to write
-        let mut buffer: [u8; PAGE_SIZE + PAGE_HEADER_SIZE] = [0; PAGE_HEADER_SIZE + PAGE_SIZE];
-        buffer[0] = page_type as u8;
-        buffer[PAGE_HEADER_SIZE..(PAGE_SIZE + PAGE_HEADER_SIZE)].copy_from_slice(page);
+    wal::log_page_write(page_num, path, &buffer)?;
+    get_storage_backend(path).write_page(path, page_num, &buffer)
+}
 
-        f.write_at(offset, &buffer)?;
-        Ok(())
-    }()
-    .map_err(map_error)
+// Appends a new page of the given type to the end of the file, and returns its page number
+pub fn allocate_page(path: &String, page_type: PageType) -> Result<u32, String> {
+    wal::log_file_length(path)?;
+    let page_num: u32 = get_storage_backend(path).allocate_page(path)?;
+    if page_type != PageType::Header {
+        // The allocated page is zeroed, which is a header page, so we write the page type
+        write_page(page_num, path, &[0; PAGE_SIZE], page_type)?;
+    }
+    Ok(page_num)
 }
 
 /* Making reads and writes on the Pages */
@@ -159,10 +156,6 @@ pub fn check_bounds(offset: usize, size: usize) -> Result<(), String> {
     }
 }
 
-fn map_error(err: Error) -> String {
-    format!("IO Error: {}", err)
-}
-
 #[cfg(test)]
 mod tests {
     use serial_test::serial;
@@ -0,0 +1,338 @@
+use parking_lot::{const_mutex, Mutex, RwLock};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+use super::storage::{get_underlying_storage_backend, is_within_dir, StorageBackend};
+
+/*
+ * Read snapshots keep a query's view of a branch the same from start to finish, even if
+ * another session commits to the branch while the query is running.
+ *
+ * A query pins the branch directory that it reads from. Before a file in a pinned branch
+ * directory is changed, the file is copied for the queries that have the directory pinned,
+ * and from then on their reads of that file go to the copy instead. Only the files that are
+ * changed get copied, so a commit costs as much as the tables it changes. A copy is removed
+ * once the last of the queries that read from it finishes. Queries that start after the
+ * commit read the branch directory as usual.
+ */
+
+// Appended to a branch directory, along with a unique number, to name a copy of one of its files
+pub const SNAPSHOT_FILE_SUFFIX: &str = ".snapshot-";
+
+// Used to give every copy of a file a unique name
+static NEXT_SNAPSHOT_ID: AtomicU64 = AtomicU64::new(0);
+
+// Every query that has a branch directory pinned
+static READERS: Mutex<Vec<Weak<Reader>>> = const_mutex(Vec::new());
+
+// The number of queries that have a branch directory pinned, so that files are only looked up
+// in READERS while there are any
+static NUM_READERS: AtomicUsize = AtomicUsize::new(0);
+
+static SNAPSHOT_BACKEND: SnapshotBackend = SnapshotBackend;
+
+thread_local! {
+    // The reader of the query that is running on this thread, see pin_branch_dir()
+    static PINNED_READER: RefCell<Option<Arc<Reader>>> = const { RefCell::new(None) };
+}
+
+/// A copy of a file in a branch directory, which is removed once no query reads from it
+struct SnapshotFile {
+    path: String,
+}
+
+impl Drop for SnapshotFile {
+    fn drop(&mut self) {
+        // Nothing reads from the copy anymore, so there's nobody to report a failure to
+        let _ = get_underlying_storage_backend(&self.path).remove_file(&self.path);
+    }
+}
+
+/// A query that has a branch directory pinned
+struct Reader {
+    branch_dir: String,
+    // The files of the branch directory that changed since it was pinned, by their path.
+    // Each one is read from its copy, or is None if the file didn't exist when it was pinned.
+    files: RwLock<HashMap<String, Option<Arc<SnapshotFile>>>>,
+}
+
+impl Reader {
+    /// Returns the copy that a read of the path should go to, if the path changed since the
+    /// branch was pinned. The copy is None if the path didn't exist back then.
+    fn get_file(&self, path: &String) -> Option<Option<String>> {
+        self.files
+            .read()
+            .get(path)
+            .map(|file| file.as_ref().map(|file| file.path.clone()))
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        NUM_READERS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Keeps a branch directory pinned on the current thread, until it's dropped
+pub struct ReadSnapshot {
+    previous: Option<Arc<Reader>>,
+}
+
+impl Drop for ReadSnapshot {
+    fn drop(&mut self) {
+        let previous: Option<Arc<Reader>> = self.previous.take();
+        PINNED_READER.with(|pinned| *pinned.borrow_mut() = previous);
+    }
+}
+
+/// Pins the branch directory on the current thread, so that reads from it keep seeing the
+/// branch as it is now, until the returned snapshot is dropped.
+/// If the thread already has the directory pinned, it keeps its older snapshot.
+/// It must be called while nothing is changing the directory.
+pub fn pin_branch_dir(branch_dir: &String) -> ReadSnapshot {
+    let current: Option<Arc<Reader>> = PINNED_READER.with(|pinned| pinned.borrow().clone());
+    if let Some(reader) = &current {
+        if reader.branch_dir == *branch_dir {
+            return ReadSnapshot { previous: current };
+        }
+    }
+
+    NUM_READERS.fetch_add(1, Ordering::SeqCst);
+    let reader: Arc<Reader> = Arc::new(Reader {
+        branch_dir: branch_dir.clone(),
+        files: RwLock::new(HashMap::new()),
+    });
+    {
+        let mut readers = READERS.lock();
+        readers.retain(|reader| reader.strong_count() > 0);
+        readers.push(Arc::downgrade(&reader));
+    }
+    let previous: Option<Arc<Reader>> = PINNED_READER.with(|pinned| pinned.replace(Some(reader)));
+    ReadSnapshot { previous }
+}
+
+/// Copies the file for the queries that have its branch directory pinned, so they don't see
+/// the change that is about to be made to it. Queries that already have a copy keep theirs.
+fn preserve_file(path: &String) -> Result<(), String> {
+    // Holding the lock makes sure that two changes to the file don't both copy it
+    let readers = READERS.lock();
+    let unpreserved: Vec<Arc<Reader>> = readers
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|reader| {
+            is_within_dir(path, &reader.branch_dir) && !reader.files.read().contains_key(path)
+        })
+        .collect();
+    if unpreserved.is_empty() {
+        return Ok(());
+    }
+
+    let backend = get_underlying_storage_backend(path);
+    let snapshot_file: Option<Arc<SnapshotFile>> = if backend.exists(path) {
+        let snapshot_file: Arc<SnapshotFile> = Arc::new(SnapshotFile {
+            path: format!(
+                "{}{}{}",
+                unpreserved[0].branch_dir,
+                SNAPSHOT_FILE_SUFFIX,
+                NEXT_SNAPSHOT_ID.fetch_add(1, Ordering::SeqCst)
+            ),
+        });
+        backend.copy_file(path, &snapshot_file.path)?;
+        Some(snapshot_file)
+    } else {
+        None
+    };
+
+    // This waits for any read of the file that a query is in the middle of
+    for reader in unpreserved {
+        reader
+            .files
+            .write()
+            .insert(path.clone(), snapshot_file.clone());
+    }
+    Ok(())
+}
+
+/// Returns the backend that serves the path, if the path is within a branch directory that a
+/// query has pinned. Reads on the pinned thread may need to go to copies of the files, and
+/// changes from any thread may need to copy the files first.
+pub(super) fn get_snapshot_backend(path: &String) -> Option<&'static dyn StorageBackend> {
+    if NUM_READERS.load(Ordering::SeqCst) == 0 {
+        return None;
+    }
+    let is_pinned: bool = READERS
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .any(|reader| is_in_dir(path, &reader.branch_dir));
+    match is_pinned {
+        true => Some(&SNAPSHOT_BACKEND as &'static dyn StorageBackend),
+        false => None,
+    }
+}
+
+/// Returns true if path is the dir directory or is somewhere within it
+fn is_in_dir(path: &String, dir: &String) -> bool {
+    path == dir || is_within_dir(path, dir)
+}
+
+/// Sends the reads of the changed files in a pinned branch directory to their copies,
+/// and copies the files of a pinned branch directory before they're changed.
+/// Changes always go to the branch directory itself.
+struct SnapshotBackend;
+
+impl SnapshotBackend {
+    /// Runs the read against the path, or against the copy of the path if it changed since the
+    /// current thread pinned its branch directory. The read is given None if the path didn't
+    /// exist when the directory was pinned.
+    fn read<T>(
+        path: &String,
+        read: impl FnOnce(&'static dyn StorageBackend, Option<&String>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let reader: Option<Arc<Reader>> = PINNED_READER
+            .with(|pinned| pinned.borrow().clone())
+            .filter(|reader| is_in_dir(path, &reader.branch_dir));
+        let reader: Arc<Reader> = match reader {
+            Some(reader) => reader,
+            None => return read(get_underlying_storage_backend(path), Some(path)),
+        };
+
+        // Hold the lock so the file can't be changed in the middle of the read
+        let files = reader.files.read();
+        match files.get(path) {
+            Some(Some(file)) => read(get_underlying_storage_backend(&file.path), Some(&file.path)),
+            Some(None) => read(get_underlying_storage_backend(path), None),
+            None => read(get_underlying_storage_backend(path), Some(path)),
+        }
+    }
+
+    /// Runs the change against the path, after copying it for the queries that have it pinned
+    fn change<T>(
+        path: &String,
+        change: impl FnOnce(&'static dyn StorageBackend) -> Result<T, String>,
+    ) -> Result<T, String> {
+        preserve_file(path)?;
+        change(get_underlying_storage_backend(path))
+    }
+
+    fn missing(path: &String) -> String {
+        format!("IO Error: File {} does not exist", path)
+    }
+}
+
+impl StorageBackend for SnapshotBackend {
+    fn create_file(&self, path: &String) -> Result<(), String> {
+        Self::change(path, |backend| backend.create_file(path))
+    }
+
+    fn read_page(&self, path: &String, page_num: u32, buf: &mut [u8]) -> Result<(), String> {
+        Self::read(path, |backend, read_path| match read_path {
+            Some(read_path) => backend.read_page(read_path, page_num, buf),
+            None => Err(Self::missing(path)),
+        })
+    }
+
+    fn write_page(&self, path: &String, page_num: u32, buf: &[u8]) -> Result<(), String> {
+        Self::change(path, |backend| backend.write_page(path, page_num, buf))
+    }
+
+    fn read_file(&self, path: &String) -> Result<Vec<u8>, String> {
+        Self::read(path, |backend, read_path| match read_path {
+            Some(read_path) => backend.read_file(read_path),
+            None => Err(Self::missing(path)),
+        })
+    }
+
+    fn write_file(&self, path: &String, data: &[u8]) -> Result<(), String> {
+        Self::change(path, |backend| backend.write_file(path, data))
+    }
+
+    fn file_len(&self, path: &String) -> Result<u64, String> {
+        Self::read(path, |backend, read_path| match read_path {
+            Some(read_path) => backend.file_len(read_path),
+            None => Err(Self::missing(path)),
+        })
+    }
+
+    fn truncate(&self, path: &String, len: u64) -> Result<(), String> {
+        Self::change(path, |backend| backend.truncate(path, len))
+    }
+
+    // Changes always go to the path itself, so that's the file to sync
+    fn sync_data(&self, path: &String) -> Result<(), String> {
+        get_underlying_storage_backend(path).sync_data(path)
+    }
+
+    fn allocate_page(&self, path: &String) -> Result<u32, String> {
+        Self::change(path, |backend| backend.allocate_page(path))
+    }
+
+    fn exists(&self, path: &String) -> bool {
+        Self::read(path, |backend, read_path| {
+            Ok(read_path.is_some_and(|read_path| backend.exists(read_path)))
+        })
+        .unwrap_or(false)
+    }
+
+    fn is_dir(&self, path: &String) -> bool {
+        get_underlying_storage_backend(path).is_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &String) -> Result<(), String> {
+        get_underlying_storage_backend(path).create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &String) -> Result<(), String> {
+        Self::change(path, |backend| backend.remove_file(path))
+    }
+
+    fn remove_dir_all(&self, path: &String) -> Result<(), String> {
+        get_underlying_storage_backend(path).remove_dir_all(path)
+    }
+
+    fn read_dir(&self, path: &String) -> Result<Vec<String>, String> {
+        let mut entries: Vec<String> = get_underlying_storage_backend(path).read_dir(path)?;
+        let reader: Option<Arc<Reader>> = PINNED_READER
+            .with(|pinned| pinned.borrow().clone())
+            .filter(|reader| is_in_dir(path, &reader.branch_dir));
+        if let Some(reader) = reader {
+            // Files that were added since the directory was pinned are left out,
+            // and the ones that were removed are listed again
+            let files = reader.files.read();
+            entries.retain(|entry| !matches!(files.get(entry), Some(None)));
+            for (file, copy) in files.iter() {
+                let in_path: bool = Path::new(file).parent() == Some(Path::new(path));
+                if in_path && copy.is_some() && !entries.contains(file) {
+                    entries.push(file.clone());
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn copy_dir_contents(&self, src: &String, dest: &String) -> Result<(), String> {
+        get_underlying_storage_backend(src).copy_dir_contents(src, dest)?;
+        let reader: Option<Arc<Reader>> = PINNED_READER
+            .with(|pinned| pinned.borrow().clone())
+            .filter(|reader| is_in_dir(src, &reader.branch_dir));
+        if let Some(reader) = reader {
+            // The files that changed since the directory was pinned are copied as they were
+            for (file, copy) in reader.files.read().iter() {
+                if !is_within_dir(file, src) {
+                    continue;
+                }
+                let dest_file: String = format!("{}{}", dest, &file[src.len()..]);
+                let backend = get_underlying_storage_backend(&dest_file);
+                match copy {
+                    Some(copy) => backend.copy_file(&copy.path, &dest_file)?,
+                    None if backend.exists(&dest_file) => backend.remove_file(&dest_file)?,
+                    None => (),
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,606 @@
+use super::pageio::{PAGE_HEADER_SIZE, PAGE_SIZE};
+use super::snapshot::get_snapshot_backend;
+use parking_lot::{const_mutex, Mutex};
+use positioned_io::{RandomAccessFile, ReadAt, Size, WriteAt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::path::{Component, Path};
+
+// Any path starting with this prefix is stored in memory instead of on disk
+pub const IN_MEMORY_PATH_PREFIX: &str = "memory:";
+
+// Number of bytes a page takes up in a file, including its header
+const FULL_PAGE_SIZE: usize = PAGE_SIZE + PAGE_HEADER_SIZE;
+
+/// The storage backend is the only place that touches the underlying storage.
+/// Everything above it (tables, btrees, commits, branches) works on paths and pages,
+/// so a new backend only has to implement these methods.
+pub trait StorageBackend: Send + Sync {
+    /// Creates an empty file at the given path, truncating it if it already exists.
+    fn create_file(&self, path: &String) -> Result<(), String>;
+
+    /// Reads the page at page_num into buf, which is PAGE_SIZE + PAGE_HEADER_SIZE bytes long.
+    fn read_page(&self, path: &String, page_num: u32, buf: &mut [u8]) -> Result<(), String>;
+
+    /// Writes buf to the page at page_num, growing the file if needed.
+    fn write_page(&self, path: &String, page_num: u32, buf: &[u8]) -> Result<(), String>;
+
+    /// Reads the whole file, for the files that aren't split into pages, like imported CSVs.
+    fn read_file(&self, path: &String) -> Result<Vec<u8>, String>;
+
+    /// Replaces the whole file with data, creating it if needed, like for exported scripts.
+    fn write_file(&self, path: &String, data: &[u8]) -> Result<(), String>;
+
+    /// Returns the length of the file in bytes.
+    fn file_len(&self, path: &String) -> Result<u64, String>;
+
+    /// Shortens the file to len bytes, dropping everything after it.
+    fn truncate(&self, path: &String, len: u64) -> Result<(), String>;
+
+    /// Waits until the writes to the file are stored durably, so they survive a crash.
+    fn sync_data(&self, path: &String) -> Result<(), String>;
+
+    /// Appends a zeroed page to the end of the file and returns its page number.
+    fn allocate_page(&self, path: &String) -> Result<u32, String> {
+        let page_num: u32 = (self.file_len(path)? / FULL_PAGE_SIZE as u64) as u32;
+        self.write_page(path, page_num, &[0; FULL_PAGE_SIZE])?;
+        Ok(page_num)
+    }
+
+    /// Returns true if a file or directory exists at the given path.
+    fn exists(&self, path: &String) -> bool;
+
+    /// Returns true if a directory exists at the given path.
+    fn is_dir(&self, path: &String) -> bool;
+
+    /// Creates a directory and all of its missing parents.
+    fn create_dir_all(&self, path: &String) -> Result<(), String>;
+
+    /// Removes a single file.
+    fn remove_file(&self, path: &String) -> Result<(), String>;
+
+    /// Removes a directory and everything within it.
+    fn remove_dir_all(&self, path: &String) -> Result<(), String>;
+
+    /// Returns the full paths of all the entries directly within a directory.
+    fn read_dir(&self, path: &String) -> Result<Vec<String>, String>;
+
+    /// Copies everything within the src directory into the dest directory.
+    fn copy_dir_contents(&self, src: &String, dest: &String) -> Result<(), String>;
+
+    /// Copies the src file to dest, replacing dest if it already exists.
+    fn copy_file(&self, src: &String, dest: &String) -> Result<(), String> {
+        self.create_file(dest)?;
+        let mut buf: [u8; FULL_PAGE_SIZE] = [0; FULL_PAGE_SIZE];
+        for page_num in 0..(self.file_len(src)? / FULL_PAGE_SIZE as u64) as u32 {
+            self.read_page(src, page_num, &mut buf)?;
+            self.write_page(dest, page_num, &buf)?;
+        }
+        Ok(())
+    }
+}
+
+static FILE_BACKEND: FileBackend = FileBackend;
+static MEMORY_BACKEND: MemoryBackend = MemoryBackend::new();
+
+// Backends registered at runtime, along with the path prefix that they are responsible for
+static REGISTERED_BACKENDS: Mutex<Vec<(String, &'static dyn StorageBackend)>> =
+    const_mutex(Vec::new());
+
+/// Returns the storage backend that is responsible for the given path.
+pub fn get_storage_backend(path: &String) -> &'static dyn StorageBackend {
+    // Reads of a branch that a query on this thread has pinned may need to go to its snapshot
+    if let Some(backend) = get_snapshot_backend(path) {
+        return backend;
+    }
+    get_underlying_storage_backend(path)
+}
+
+/// Returns the backend that actually stores the given path, ignoring any read snapshots.
+pub(super) fn get_underlying_storage_backend(path: &String) -> &'static dyn StorageBackend {
+    let registered_backend: Option<&'static dyn StorageBackend> = REGISTERED_BACKENDS
+        .lock()
+        .iter()
+        .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+        .map(|(_, backend)| *backend);
+    if let Some(backend) = registered_backend {
+        backend
+    } else if is_in_memory_path(path) {
+        &MEMORY_BACKEND
+    } else {
+        &FILE_BACKEND
+    }
+}
+
+/// Registers a storage backend for all the paths starting with path_prefix.
+/// Registering a backend for an existing prefix replaces the old backend.
+pub fn register_storage_backend(path_prefix: &str, backend: &'static dyn StorageBackend) {
+    let mut backends = REGISTERED_BACKENDS.lock();
+    backends.retain(|(prefix, _)| prefix != path_prefix);
+    backends.push((path_prefix.to_string(), backend));
+}
+
+/// Removes the storage backend registered for path_prefix, if there is one.
+pub fn unregister_storage_backend(path_prefix: &str) {
+    REGISTERED_BACKENDS
+        .lock()
+        .retain(|(prefix, _)| prefix != path_prefix);
+}
+
+/// Returns the path of the file at the relative path within dir. The path can't be absolute,
+/// and can't use `..` to go up out of dir, so only the files within dir can be reached.
+pub fn resolve_path_within_dir(dir: &String, path: &String) -> Result<String, String> {
+    let relative_path: &Path = Path::new(path);
+    if path.is_empty() || relative_path.has_root() || relative_path.is_absolute() {
+        return Err(format!("{} must be a path within {}", path, dir));
+    }
+    for component in relative_path.components() {
+        if !matches!(component, Component::Normal(_) | Component::CurDir) {
+            return Err(format!("{} must be a path within {}", path, dir));
+        }
+    }
+    Ok(format!("{}{}{}", dir, std::path::MAIN_SEPARATOR, path))
+}
+
+/// Returns true if the given path is stored in memory.
+pub fn is_in_memory_path(path: &String) -> bool {
+    path.starts_with(IN_MEMORY_PATH_PREFIX)
+}
+
+/*********************************************************************************************/
+/*                                     File System Backend                                    */
+/*********************************************************************************************/
+
+/// Stores everything as files on disk.
+pub struct FileBackend;
+
+impl StorageBackend for FileBackend {
+    fn create_file(&self, path: &String) -> Result<(), String> {
+        // If the directory to the file does not exist, create it
+        if let Some(path_to_file_dir) = Path::new(path).parent() {
+            if !path_to_file_dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(path_to_file_dir).map_err(map_error)?;
+            }
+        }
+        File::create(path).map_err(map_error)?;
+        Ok(())
+    }
+
+    fn read_page(&self, path: &String, page_num: u32, buf: &mut [u8]) -> Result<(), String> {
+        let f = RandomAccessFile::open(path).map_err(map_error)?;
+        let x = f
+            .read_at((page_num as u64) * (FULL_PAGE_SIZE as u64), buf)
+            .map_err(map_error)?;
+        if x != FULL_PAGE_SIZE {
+            return Err(format!("Error reading page {}", page_num));
+        }
+        Ok(())
+    }
+
+    fn write_page(&self, path: &String, page_num: u32, buf: &[u8]) -> Result<(), String> {
+        || -> Result<(), std::io::Error> {
+            let file = OpenOptions::new().write(true).open(path)?;
+            let file_size = file.size()?.expect("File size is not available");
+            let offset = (page_num as u64) * (FULL_PAGE_SIZE as u64);
+            if offset >= file_size {
+                file.set_len(offset + FULL_PAGE_SIZE as u64)?;
+            }
+            let mut f = RandomAccessFile::try_new(file)?;
+            f.write_at(offset, buf)?;
+            Ok(())
+        }()
+        .map_err(map_error)
+    }
+
+    fn read_file(&self, path: &String) -> Result<Vec<u8>, String> {
+        std::fs::read(path).map_err(map_error)
+    }
+
+    fn write_file(&self, path: &String, data: &[u8]) -> Result<(), String> {
+        // If the directory to the file does not exist, create it
+        if let Some(path_to_file_dir) = Path::new(path).parent() {
+            if !path_to_file_dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(path_to_file_dir).map_err(map_error)?;
+            }
+        }
+        std::fs::write(path, data).map_err(map_error)
+    }
+
+    fn file_len(&self, path: &String) -> Result<u64, String> {
+        let metadata = std::fs::metadata(path).map_err(map_error)?;
+        Ok(metadata.len())
+    }
+
+    fn truncate(&self, path: &String, len: u64) -> Result<(), String> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(map_error)?;
+        file.set_len(len).map_err(map_error)
+    }
+
+    fn sync_data(&self, path: &String) -> Result<(), String> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(map_error)?;
+        file.sync_data().map_err(map_error)
+    }
+
+    fn exists(&self, path: &String) -> bool {
+        Path::new(path).exists()
+    }
+
+    fn is_dir(&self, path: &String) -> bool {
+        Path::new(path).is_dir()
+    }
+
+    fn create_dir_all(&self, path: &String) -> Result<(), String> {
+        std::fs::create_dir_all(path).map_err(map_error)
+    }
+
+    fn remove_file(&self, path: &String) -> Result<(), String> {
+        std::fs::remove_file(path).map_err(map_error)
+    }
+
+    fn remove_dir_all(&self, path: &String) -> Result<(), String> {
+        std::fs::remove_dir_all(path).map_err(map_error)
+    }
+
+    fn read_dir(&self, path: &String) -> Result<Vec<String>, String> {
+        let mut entries: Vec<String> = Vec::new();
+        for entry in std::fs::read_dir(path).map_err(map_error)? {
+            let entry = entry.map_err(map_error)?;
+            entries.push(entry.path().to_string_lossy().to_string());
+        }
+        Ok(entries)
+    }
+
+    fn copy_dir_contents(&self, src: &String, dest: &String) -> Result<(), String> {
+        let mut options = fs_extra::dir::CopyOptions::new();
+        options.content_only = true; // Only copy the files not the directory
+        fs_extra::dir::copy(src, dest, &options).map_err(|e| format!("IO Error: {}", e))?;
+        Ok(())
+    }
+
+    fn copy_file(&self, src: &String, dest: &String) -> Result<(), String> {
+        std::fs::copy(src, dest).map_err(map_error)?;
+        Ok(())
+    }
+}
+
+fn map_error(err: std::io::Error) -> String {
+    format!("IO Error: {}", err)
+}
+
+/*********************************************************************************************/
+/*                                      In-Memory Backend                                     */
+/*********************************************************************************************/
+
+/// Stores everything in RAM. Nothing is ever written to disk, and everything is lost
+/// when the process exits.
+pub struct MemoryBackend {
+    files: Mutex<BTreeMap<String, Vec<u8>>>, // Maps a file path to its contents
+    dirs: Mutex<BTreeSet<String>>,           // The paths of all the directories
+}
+
+impl MemoryBackend {
+    pub const fn new() -> MemoryBackend {
+        MemoryBackend {
+            files: const_mutex(BTreeMap::new()),
+            dirs: const_mutex(BTreeSet::new()),
+        }
+    }
+}
+
+/// Returns true if path is somewhere within the dir directory
+pub(super) fn is_within_dir(path: &String, dir: &String) -> bool {
+    path.len() > dir.len()
+        && path.starts_with(dir.as_str())
+        && path[dir.len()..].starts_with(std::path::MAIN_SEPARATOR)
+}
+
+impl StorageBackend for MemoryBackend {
+    fn create_file(&self, path: &String) -> Result<(), String> {
+        self.files.lock().insert(path.clone(), Vec::new());
+        Ok(())
+    }
+
+    fn read_page(&self, path: &String, page_num: u32, buf: &mut [u8]) -> Result<(), String> {
+        let files = self.files.lock();
+        let file: &Vec<u8> = files
+            .get(path)
+            .ok_or(format!("IO Error: File {} does not exist", path))?;
+        let offset: usize = page_num as usize * FULL_PAGE_SIZE;
+        if offset + FULL_PAGE_SIZE > file.len() {
+            return Err(format!("Error reading page {}", page_num));
+        }
+        buf[0..FULL_PAGE_SIZE].copy_from_slice(&file[offset..offset + FULL_PAGE_SIZE]);
+        Ok(())
+    }
+
+    fn write_page(&self, path: &String, page_num: u32, buf: &[u8]) -> Result<(), String> {
+        let mut files = self.files.lock();
+        let file: &mut Vec<u8> = files
+            .get_mut(path)
+            .ok_or(format!("IO Error: File {} does not exist", path))?;
+        let offset: usize = page_num as usize * FULL_PAGE_SIZE;
+        if offset + FULL_PAGE_SIZE > file.len() {
+            file.resize(offset + FULL_PAGE_SIZE, 0);
+        }
+        file[offset..offset + FULL_PAGE_SIZE].copy_from_slice(&buf[0..FULL_PAGE_SIZE]);
+        Ok(())
+    }
+
+    fn read_file(&self, path: &String) -> Result<Vec<u8>, String> {
+        match self.files.lock().get(path) {
+            Some(file) => Ok(file.clone()),
+            None => Err(format!("IO Error: File {} does not exist", path)),
+        }
+    }
+
+    fn write_file(&self, path: &String, data: &[u8]) -> Result<(), String> {
+        self.files.lock().insert(path.clone(), data.to_vec());
+        Ok(())
+    }
+
+    fn file_len(&self, path: &String) -> Result<u64, String> {
+        match self.files.lock().get(path) {
+            Some(file) => Ok(file.len() as u64),
+            None => Err(format!("IO Error: File {} does not exist", path)),
+        }
+    }
+
+    fn truncate(&self, path: &String, len: u64) -> Result<(), String> {
+        match self.files.lock().get_mut(path) {
+            Some(file) => {
+                file.truncate(len as usize);
+                Ok(())
+            }
+            None => Err(format!("IO Error: File {} does not exist", path)),
+        }
+    }
+
+    // Files in memory are lost in a crash anyway, so there's nothing to wait for
+    fn sync_data(&self, path: &String) -> Result<(), String> {
+        match self.files.lock().contains_key(path) {
+            true => Ok(()),
+            false => Err(format!("IO Error: File {} does not exist", path)),
+        }
+    }
+
+    fn exists(&self, path: &String) -> bool {
+        let is_file: bool = self.files.lock().contains_key(path);
+        is_file || self.is_dir(path)
+    }
+
+    fn is_dir(&self, path: &String) -> bool {
+        self.dirs.lock().contains(path)
+    }
+
+    fn create_dir_all(&self, path: &String) -> Result<(), String> {
+        let mut dirs = self.dirs.lock();
+        let mut dir: Option<&Path> = Some(Path::new(path));
+        while let Some(d) = dir {
+            let d_str: String = d.to_string_lossy().to_string();
+            if d_str.is_empty() || d_str == IN_MEMORY_PATH_PREFIX {
+                break;
+            }
+            dirs.insert(d_str);
+            dir = d.parent();
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &String) -> Result<(), String> {
+        match self.files.lock().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(format!("IO Error: File {} does not exist", path)),
+        }
+    }
+
+    fn remove_dir_all(&self, path: &String) -> Result<(), String> {
+        let mut dirs = self.dirs.lock();
+        if !dirs.remove(path) {
+            return Err(format!("IO Error: Directory {} does not exist", path));
+        }
+        dirs.retain(|d| !is_within_dir(d, path));
+        self.files.lock().retain(|f, _| !is_within_dir(f, path));
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &String) -> Result<Vec<String>, String> {
+        if !self.is_dir(path) {
+            return Err(format!("IO Error: Directory {} does not exist", path));
+        }
+
+        // Only keep the entries that are directly within the directory
+        let is_direct_child = |entry: &String| -> bool {
+            is_within_dir(entry, path)
+                && !entry[path.len() + 1..].contains(std::path::MAIN_SEPARATOR)
+        };
+        let mut entries: Vec<String> = self
+            .dirs
+            .lock()
+            .iter()
+            .filter(|d| is_direct_child(d))
+            .cloned()
+            .collect();
+        entries.extend(
+            self.files
+                .lock()
+                .keys()
+                .filter(|f| is_direct_child(f))
+                .cloned(),
+        );
+        Ok(entries)
+    }
+
+    fn copy_dir_contents(&self, src: &String, dest: &String) -> Result<(), String> {
+        if !self.is_dir(src) {
+            return Err(format!("IO Error: Directory {} does not exist", src));
+        }
+
+        let mut dirs = self.dirs.lock();
+        let new_dirs: Vec<String> = dirs
+            .iter()
+            .filter(|d| is_within_dir(d, src))
+            .map(|d| format!("{}{}", dest, &d[src.len()..]))
+            .collect();
+        dirs.extend(new_dirs);
+        dirs.insert(dest.clone());
+
+        let mut files = self.files.lock();
+        let new_files: Vec<(String, Vec<u8>)> = files
+            .iter()
+            .filter(|(f, _)| is_within_dir(f, src))
+            .map(|(f, contents)| (format!("{}{}", dest, &f[src.len()..]), contents.clone()))
+            .collect();
+        files.extend(new_files);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fileio::tableio::{create_table_in_dir, Table},
+        util::dbtype::{Column, Value},
+    };
+    use serial_test::serial;
+
+    /// A backend that stores everything in memory, and keeps track of every page access
+    struct MockBackend {
+        memory: MemoryBackend,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl StorageBackend for MockBackend {
+        fn create_file(&self, path: &String) -> Result<(), String> {
+            self.memory.create_file(path)
+        }
+
+        fn read_page(&self, path: &String, page_num: u32, buf: &mut [u8]) -> Result<(), String> {
+            self.calls.lock().push(format!("read_page({})", page_num));
+            self.memory.read_page(path, page_num, buf)
+        }
+
+        fn write_page(&self, path: &String, page_num: u32, buf: &[u8]) -> Result<(), String> {
+            self.calls.lock().push(format!("write_page({})", page_num));
+            self.memory.write_page(path, page_num, buf)
+        }
+
+        fn read_file(&self, path: &String) -> Result<Vec<u8>, String> {
+            self.memory.read_file(path)
+        }
+
+        fn write_file(&self, path: &String, data: &[u8]) -> Result<(), String> {
+            self.memory.write_file(path, data)
+        }
+
+        fn file_len(&self, path: &String) -> Result<u64, String> {
+            self.memory.file_len(path)
+        }
+
+        fn truncate(&self, path: &String, len: u64) -> Result<(), String> {
+            self.memory.truncate(path, len)
+        }
+
+        fn sync_data(&self, path: &String) -> Result<(), String> {
+            self.memory.sync_data(path)
+        }
+
+        fn exists(&self, path: &String) -> bool {
+            self.memory.exists(path)
+        }
+
+        fn is_dir(&self, path: &String) -> bool {
+            self.memory.is_dir(path)
+        }
+
+        fn create_dir_all(&self, path: &String) -> Result<(), String> {
+            self.memory.create_dir_all(path)
+        }
+
+        fn remove_file(&self, path: &String) -> Result<(), String> {
+            self.memory.remove_file(path)
+        }
+
+        fn remove_dir_all(&self, path: &String) -> Result<(), String> {
+            self.memory.remove_dir_all(path)
+        }
+
+        fn read_dir(&self, path: &String) -> Result<Vec<String>, String> {
+            self.memory.read_dir(path)
+        }
+
+        fn copy_dir_contents(&self, src: &String, dest: &String) -> Result<(), String> {
+            self.memory.copy_dir_contents(src, dest)
+        }
+    }
+
+    static MOCK_BACKEND: MockBackend = MockBackend {
+        memory: MemoryBackend::new(),
+        calls: const_mutex(Vec::new()),
+    };
+
+    #[test]
+    #[serial]
+    fn test_mock_backend_insert() {
+        let table_dir: String = "mock:test_mock_backend_insert".to_string();
+        register_storage_backend("mock:", &MOCK_BACKEND);
+        MOCK_BACKEND.create_dir_all(&table_dir).unwrap();
+
+        let schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+        ];
+        create_table_in_dir(&"test_table".to_string(), &schema, &table_dir).unwrap();
+        let mut table: Table = Table::new(&table_dir, &"test_table".to_string(), None).unwrap();
+
+        // Only keep track of the calls made by the insert
+        MOCK_BACKEND.calls.lock().clear();
+        table
+            .insert_rows(vec![vec![
+                Value::I32(1),
+                Value::String("Iron Man".to_string()),
+            ]])
+            .unwrap();
+
+        // The insert should only touch the first data page, and the header to check and
+        // increment the table's version
+        assert_eq!(
+            *MOCK_BACKEND.calls.lock(),
+            vec![
+                "read_page(0)".to_string(),
+                "read_page(1)".to_string(),
+                "write_page(1)".to_string(),
+                "read_page(0)".to_string(),
+                "write_page(0)".to_string(),
+            ]
+        );
+
+        // Nothing should have been written to disk
+        assert_eq!(Path::new(&table.path).exists(), false);
+
+        MOCK_BACKEND.remove_dir_all(&table_dir).unwrap();
+        unregister_storage_backend("mock:");
+    }
+
+    #[test]
+    #[serial]
+    fn test_memory_backend_allocate_page() {
+        let path: String = format!("{}test_allocate_page", IN_MEMORY_PATH_PREFIX);
+        let backend = get_storage_backend(&path);
+        backend.create_file(&path).unwrap();
+        assert_eq!(backend.file_len(&path).unwrap(), 0);
+
+        assert_eq!(backend.allocate_page(&path).unwrap(), 0);
+        assert_eq!(backend.allocate_page(&path).unwrap(), 1);
+        assert_eq!(backend.file_len(&path).unwrap(), 2 * FULL_PAGE_SIZE as u64);
+
+        backend.remove_file(&path).unwrap();
+        assert_eq!(backend.exists(&path), false);
+    }
+}
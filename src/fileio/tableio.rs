@@ -1,8 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
 
 use itertools::Itertools;
+use parking_lot::{const_mutex, Mutex, ReentrantMutex, ReentrantMutexGuard};
+use sqlparser::ast::Value as SqlValue;
 
-use super::{databaseio::Database, header::*, pageio::*, rowio::*};
+use super::{databaseio::Database, header::*, pageio::*, rowio::*, storage::get_storage_backend};
 use crate::{
     btree::{btree::BTree, indexes::*},
     user::userdata::User,
@@ -12,6 +17,12 @@ use crate::{
 
 pub const TABLE_FILE_EXTENSION: &str = ".db";
 
+// The lock of each table file, by the file's path. A table holds it while it checks its version
+// and writes, so two writers of the same file can't both pass the check.
+// A file's lock is dropped along with the last table that has the file loaded.
+static TABLE_FILE_LOCKS: Mutex<BTreeMap<String, Weak<ReentrantMutex<()>>>> =
+    const_mutex(BTreeMap::new());
+
 #[derive(Clone)]
 pub struct Table {
     pub name: String, // The name of the table without the file extension.
@@ -23,6 +34,10 @@ pub struct Table {
     pub max_pages: u32,
     pub schema_size: usize,
     pub indexes: HashMap<IndexID, (u32, String)>, // Hashmap of index id to (page_num, index_name)
+    pub unique_indexes: HashSet<IndexID>,         // The indexes that don't allow duplicate keys
+    pub ttl: Option<u64>, // The number of seconds that rows are kept for, or None to keep them forever
+    version: Arc<AtomicU64>, // The version in the file's header when this table and its clones last read or wrote the rows
+    file_lock: Arc<ReentrantMutex<()>>, // Shared with the other tables loaded from the file
 }
 
 impl Table {
@@ -53,12 +68,13 @@ impl Table {
     /// Construct a new table from an already existing file.
     pub fn new_from_path(path: String, table_name: String) -> Result<Table, String> {
         // If the file doesn't exist, return an error.
-        if !std::path::Path::new(&path).exists() {
+        if !get_storage_backend(&path).exists(&path) {
             return Err(format!("Table file {} does not exist.", path));
         }
 
         let header: Header = read_header(&path)?;
         let page: Box<Page> = Box::new([0u8; PAGE_SIZE]);
+        let file_lock: Arc<ReentrantMutex<()>> = get_table_file_lock(&path);
         Ok(Table {
             name: table_name,
             schema_size: schema_size(&header.schema),
@@ -69,6 +85,10 @@ impl Table {
             row_num: 0,
             max_pages: header.num_pages,
             indexes: header.index_top_level_pages,
+            unique_indexes: header.unique_indexes,
+            ttl: header.ttl,
+            version: Arc::new(AtomicU64::new(header.version)),
+            file_lock,
         })
     }
 
@@ -87,6 +107,55 @@ impl Table {
     fn get_offset(&self) -> usize {
         self.row_num as usize * self.schema_size
     }
+
+    /// Returns the version of the table file that this table was last read or written at.
+    pub fn get_version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    /// Makes sure nobody else wrote to the table file since this table was loaded, by comparing
+    /// the version in the file's header. If someone did, the rows this table was based on may be
+    /// stale, so the write must be retried. Returns the header, which the write can sync with.
+    fn check_version(&self) -> Result<Header, String> {
+        let header: Header = read_header(&self.path)?;
+        if header.version != self.get_version() {
+            return Err(format!(
+                "Table {} was modified concurrently, please retry",
+                self.name
+            ));
+        }
+        Ok(header)
+    }
+
+    /// Records that this table wrote to the table file by incrementing the version in the file's
+    /// header. Returns the header that was written.
+    fn increment_version(&self) -> Result<Header, String> {
+        let mut header: Header = read_header(&self.path)?;
+        header.version += 1;
+        write_header(&self.path, &header)?;
+        self.version.store(header.version, Ordering::SeqCst);
+        Ok(header)
+    }
+
+    /// Picks up the pages and index roots that were written to the table file since this table
+    /// was loaded. Rebalancing an index writes new pages through the index's own copy of the
+    /// table, so they'd otherwise be handed out again, or the old index root written back.
+    fn sync_with_header(&mut self, header: &Header) {
+        self.max_pages = header.num_pages;
+        self.indexes = header.index_top_level_pages.clone();
+    }
+}
+
+/// Gets the lock shared by the tables loaded from the file at path
+fn get_table_file_lock(path: &String) -> Arc<ReentrantMutex<()>> {
+    let mut locks = TABLE_FILE_LOCKS.lock();
+    if let Some(file_lock) = locks.get(path).and_then(Weak::upgrade) {
+        return file_lock;
+    }
+    locks.retain(|_, file_lock| file_lock.strong_count() > 0);
+    let file_lock: Arc<ReentrantMutex<()>> = Arc::new(ReentrantMutex::new(()));
+    locks.insert(path.clone(), Arc::downgrade(&file_lock));
+    file_lock
 }
 
 /// This allows for table scans (but read-only)
@@ -153,6 +222,7 @@ impl Iterator for Table {
 pub fn create_table(
     table_name: &String,
     schema: &Schema,
+    ttl: Option<u64>,
     table_dir: &String,
 ) -> Result<(Table, TableCreateDiff), String> {
     let mut table_path: String = table_name.clone() + &TABLE_FILE_EXTENSION.to_string();
@@ -162,21 +232,54 @@ pub fn create_table(
     }
 
     // If the file already exists, return an error.
-    if std::path::Path::new(&table_path).exists() {
+    if get_storage_backend(&table_path).exists(&table_path) {
         return Err(format!(
             "Table {} already exists on current branch.",
             table_name
         ));
     }
 
-    create_table_in_dir(table_name, schema, table_dir)
+    let diff: TableCreateDiff = TableCreateDiff {
+        table_name: table_name.clone(),
+        schema: schema.clone(),
+        ttl,
+    };
+    Ok((create_table_from_diff(&diff, table_dir)?, diff))
+}
+
+// Writes a value as a CSV field, in the form that Column::parse reads it back from. Nulls are
+// written as the null token. Fields holding a comma, quote or line break are quoted with their
+// quotes doubled, as are values that read the same as the null token, so they aren't read as null.
+fn csv_field(value: &Value, null: &str) -> String {
+    let field: String = match value.to_sql_value() {
+        SqlValue::Number(x, _) | SqlValue::SingleQuotedString(x) => x,
+        SqlValue::Null => return null.to_string(),
+        x => x.to_string(),
+    };
+    if field == null || field.contains(|c: char| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
 }
+
 /// Create a new table within a given directory named <table_name><TABLE_FILE_EXTENSION>
 pub fn create_table_in_dir(
     table_name: &String,
     schema: &Schema,
     table_dir: &String,
 ) -> Result<(Table, TableCreateDiff), String> {
+    let diff: TableCreateDiff = TableCreateDiff {
+        table_name: table_name.clone(),
+        schema: schema.clone(),
+        ttl: None,
+    };
+    Ok((create_table_from_diff(&diff, table_dir)?, diff))
+}
+
+/// Creates the table that a diff created, with its schema and TTL, within the given directory
+pub fn create_table_from_diff(diff: &TableCreateDiff, table_dir: &String) -> Result<Table, String> {
+    let table_name: &String = &diff.table_name;
     // Construct the path to the table file.
     let filename: String = table_name.clone() + &TABLE_FILE_EXTENSION.to_string();
     let mut table_path = filename.clone();
@@ -184,13 +287,16 @@ pub fn create_table_in_dir(
         table_path = table_dir.clone() + std::path::MAIN_SEPARATOR.to_string().as_str() + &filename;
     }
     // Create the file
-    create_file(&table_path).map_err(|e| e.to_string())?;
+    create_file(&table_path)?;
 
     // Write the header
     let header = Header {
         num_pages: 2,
-        schema: schema.clone(),
+        schema: diff.schema.clone(),
         index_top_level_pages: HashMap::new(),
+        unique_indexes: HashSet::new(),
+        ttl: diff.ttl,
+        version: 0,
     };
     write_header(&table_path, &header)?;
 
@@ -198,14 +304,7 @@ pub fn create_table_in_dir(
     let page = [0u8; PAGE_SIZE];
     write_page(1, &table_path, &page, PageType::Data)?;
 
-    // Return the table and the diff
-    Ok((
-        Table::new(&table_dir.clone(), &table_name.clone(), None)?,
-        TableCreateDiff {
-            table_name: table_name.clone(),
-            schema: schema.clone(),
-        },
-    ))
+    Table::new(&table_dir.clone(), &table_name.clone(), None)
 }
 
 /// Delete a table from the given directory.
@@ -216,8 +315,8 @@ pub fn delete_table_in_dir(
     // Create the path to the table file.
     let filename: String = table_name.clone() + &TABLE_FILE_EXTENSION.to_string();
     let mut table_path = filename.clone();
-    //Extracting Schema to use when commits are being reverted
-    let schema = Table::new(&table_dir.clone(), &table_name.clone(), None)?.schema;
+    //Extracting Schema and TTL to use when commits are being reverted
+    let Table { schema, ttl, .. } = Table::new(&table_dir.clone(), &table_name.clone(), None)?;
     if table_dir.len() > 0 {
         table_path = table_dir.clone() + std::path::MAIN_SEPARATOR.to_string().as_str() + &filename;
     }
@@ -230,16 +329,40 @@ pub fn delete_table_in_dir(
     }
 
     // Delete the table file
-    std::fs::remove_file(&table_path).map_err(|e| e.to_string())?;
+    get_storage_backend(&table_path).remove_file(&table_path)?;
 
     // Return the diff
     Ok(TableRemoveDiff {
         table_name: table_name.clone(),
         schema: schema.clone(),
         rows_removed: rows,
+        ttl,
     })
 }
 
+/// Converts a row from one schema to another, by matching up their columns by name.
+/// Columns that are only in the new schema are null.
+pub fn convert_row(row: &Row, schema: &Schema, new_schema: &Schema) -> Result<Row, String> {
+    new_schema
+        .iter()
+        .map(
+            |(name, column)| match schema.iter().position(|(old_name, _)| old_name == name) {
+                Some(i) => column.coerce_type(row[i].clone()),
+                None => column.coerce_type(Value::Null(column.clone())),
+            },
+        )
+        .collect()
+}
+
+/// The formats that a table's rows can be exported in, see `Table::export_stream`
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportFormat {
+    // A line of comma separated values for each row, which COPY ... FROM can read back.
+    // Nulls are written as the null token, which is an empty field by default.
+    Csv { null: String },
+    Binary, // Each row as the bytes it's stored as in the table's pages, which read_row can read
+}
+
 impl Table {
     /// This function is helpful when doing Updates
     /// It allows us to rewrite a specific row from the table.
@@ -256,6 +379,12 @@ impl Table {
         if rows.len() < 1 {
             return Ok(diff);
         }
+        let _lock: ReentrantMutexGuard<()> = self.file_lock.lock();
+        self.check_version()?;
+        self.check_unique_indexes(
+            &rows.iter().map(|row| row.row.clone()).collect(),
+            &rows.iter().map(|row| row.get_row_location()).collect(),
+        )?;
 
         // To reduce page updates, we sort the rows by page number.
         rows.sort();
@@ -304,12 +433,15 @@ impl Table {
             self.load_btree(index_id)?
                 .update_rows(&diff.old_rows, &diff.rows)?;
         }
+        self.increment_version()?;
 
         Ok(diff)
     }
 
     /// This function is helpful when doing Inserts
     /// It allows us to insert a row into the table, allocating space when needed.
+    /// Rows go into the first free slots, including the ones left by deleted rows,
+    /// so the file only grows once all of its pages are full.
     /// It returns a diff of the rows that were inserted.
     pub fn insert_rows(&mut self, rows: Vec<Row>) -> Result<InsertDiff, String> {
         // Keep track of how the rows have changed.
@@ -323,6 +455,11 @@ impl Table {
         if rows.len() == 0 {
             return Ok(diff);
         }
+        let file_lock: Arc<ReentrantMutex<()>> = self.file_lock.clone();
+        let _lock: ReentrantMutexGuard<()> = file_lock.lock();
+        let header: Header = self.check_version()?;
+        self.sync_with_header(&header);
+        self.check_unique_indexes(&rows, &Vec::new())?;
 
         let mut pagenum = 1;
         let (mut page, page_type) = read_page(pagenum, &self.path)?;
@@ -332,6 +469,15 @@ impl Table {
             while rownum_inserted.is_none() {
                 write_page(pagenum, &self.path, page.as_ref(), page_type.clone())?;
                 pagenum += 1;
+                // Index pages share the file with the rows, so they're skipped over
+                while pagenum <= self.max_pages
+                    && !matches!(
+                        load_page(pagenum, &self.path, page.as_mut()),
+                        Ok(PageType::Data) | Err(_)
+                    )
+                {
+                    pagenum += 1;
+                }
                 if pagenum > self.max_pages
                     || load_page(pagenum, &self.path, page.as_mut()).is_err()
                 {
@@ -344,6 +490,9 @@ impl Table {
                         num_pages: self.max_pages,
                         schema: self.schema.clone(),
                         index_top_level_pages: self.indexes.clone(),
+                        unique_indexes: self.unique_indexes.clone(),
+                        ttl: self.ttl,
+                        version: self.get_version(),
                     };
                     write_header(&self.path, &new_header)?;
                 }
@@ -363,6 +512,8 @@ impl Table {
         for index_id in self.indexes.keys() {
             self.load_btree(index_id)?.insert_rows(&diff.rows)?;
         }
+        let header: Header = self.increment_version()?;
+        self.sync_with_header(&header);
 
         Ok(diff)
     }
@@ -383,6 +534,14 @@ impl Table {
         if rows.len() == 0 {
             return Ok(diff);
         }
+        let file_lock: Arc<ReentrantMutex<()>> = self.file_lock.clone();
+        let _lock: ReentrantMutexGuard<()> = file_lock.lock();
+        let header: Header = self.check_version()?;
+        self.sync_with_header(&header);
+        self.check_unique_indexes(
+            &rows.iter().map(|row| row.row.clone()).collect(),
+            &rows.iter().map(|row| row.get_row_location()).collect(),
+        )?;
 
         // To reduce page updates, we sort the rows by page number.
         rows.sort();
@@ -394,20 +553,17 @@ impl Table {
 
             // Allocate new pages if needed
             while rowinfo.pagenum >= self.max_pages {
-                let new_page = Box::new([0; PAGE_SIZE]);
-                self.max_pages += 1;
-                write_page(
-                    self.max_pages - 1,
-                    &self.path,
-                    new_page.as_ref(),
-                    PageType::Data,
-                )?;
+                let new_pagenum: u32 = allocate_page(&self.path, PageType::Data)?;
+                self.max_pages = new_pagenum + 1;
 
                 // Update the header
                 let new_header: Header = Header {
                     num_pages: self.max_pages,
                     schema: self.schema.clone(),
                     index_top_level_pages: self.indexes.clone(),
+                    unique_indexes: self.unique_indexes.clone(),
+                    ttl: self.ttl,
+                    version: self.get_version(),
                 };
                 write_header(&self.path, &new_header)?;
             }
@@ -477,6 +633,8 @@ impl Table {
             self.load_btree(index_id)?
                 .update_rows(&old_rows, &new_rows)?;
         }
+        let header: Header = self.increment_version()?;
+        self.sync_with_header(&header);
         Ok(diff)
     }
 
@@ -496,6 +654,8 @@ impl Table {
         if rows.len() == 0 {
             return Ok(diff);
         }
+        let _lock: ReentrantMutexGuard<()> = self.file_lock.lock();
+        self.check_version()?;
 
         // Keep track of the row number we are removing
         let mut curr_page = 1;
@@ -536,6 +696,127 @@ impl Table {
         for index_id in self.indexes.keys() {
             self.load_btree(index_id)?.remove_rows(&diff.rows)?;
         }
+        self.increment_version()?;
+        Ok(diff)
+    }
+
+    /// Gets the rows that won't fit where they are if the table had the given schema, as fewer
+    /// rows fit on a page when they're wider. They need to be moved before the schema changes.
+    pub fn get_rows_outside(&self, schema: &Schema) -> Vec<RowInfo> {
+        let size: usize = schema_size(schema);
+        self.clone()
+            .filter(|row_info| check_bounds(row_info.rownum as usize * size, size).is_err())
+            .collect()
+    }
+
+    /// Changes the schema of the table in place, such as to add or drop a column.
+    /// Columns are matched up by name, and the columns that are new to the table are null.
+    /// Every row stays where it is, so the indexes on the columns that are kept don't change.
+    /// That means the rows from get_rows_outside have to be moved first, and the indexes on
+    /// columns that are dropped or change type have to be dropped first.
+    /// It returns a diff of the change, with the rows as they were if any values were dropped.
+    pub fn change_schema(&mut self, new_schema: &Schema) -> Result<SchemaChangeDiff, String> {
+        let mut diff: SchemaChangeDiff = SchemaChangeDiff {
+            table_name: self.name.clone(),
+            schema: self.schema.clone(),
+            new_schema: new_schema.clone(),
+            rows: Vec::new(),
+        };
+        let file_lock: Arc<ReentrantMutex<()>> = self.file_lock.clone();
+        let _lock: ReentrantMutexGuard<()> = file_lock.lock();
+        let header: Header = self.check_version()?;
+        self.sync_with_header(&header);
+
+        // The position of each column in the new schema, if it's kept
+        let positions: Vec<Option<u8>> = self
+            .schema
+            .iter()
+            .map(|(name, column)| {
+                new_schema
+                    .iter()
+                    .position(|(new_name, new_column)| new_name == name && new_column == column)
+                    .map(|i| i as u8)
+            })
+            .collect();
+        let is_dropping: bool = self
+            .schema
+            .iter()
+            .any(|(name, _)| !new_schema.iter().any(|(new_name, _)| new_name == name));
+
+        // The indexes point at the same rows, but their columns may have moved
+        let mut index_ids: HashMap<IndexID, IndexID> = HashMap::new();
+        for (index_id, (_, index_name)) in &self.indexes {
+            let new_index_id: IndexID = index_id
+                .iter()
+                .map(|i| positions[*i as usize])
+                .collect::<Option<IndexID>>()
+                .ok_or(format!(
+                    "Index {} has to be dropped before its columns are changed",
+                    index_name
+                ))?;
+            index_ids.insert(index_id.clone(), new_index_id);
+        }
+
+        // Every page is converted before any are written, so a row that can't be converted
+        // leaves the table as it was
+        let new_size: usize = schema_size(new_schema);
+        let mut pages: Vec<(u32, Box<Page>)> = Vec::new();
+        for pagenum in 1..self.max_pages {
+            let (page, page_type) = read_page(pagenum, &self.path)?;
+            if page_type != PageType::Data {
+                continue;
+            }
+            let mut new_page: Box<Page> = Box::new([0; PAGE_SIZE]);
+            let mut rownum: u16 = 0;
+            while check_bounds(rownum as usize * self.schema_size, self.schema_size).is_ok() {
+                if let Some(row) = read_row(&self.schema, &page, rownum) {
+                    if check_bounds(rownum as usize * new_size, new_size).is_err() {
+                        return Err(format!(
+                            "Row {} on page {} of table {} has to be moved before the schema changes",
+                            rownum, pagenum, self.name
+                        ));
+                    }
+                    let new_row: Row = convert_row(&row, &self.schema, new_schema)?;
+                    write_row(new_schema, new_page.as_mut(), &new_row, rownum)?;
+                    if is_dropping {
+                        diff.rows.push(RowInfo {
+                            row,
+                            pagenum,
+                            rownum,
+                        });
+                    }
+                }
+                rownum += 1;
+            }
+            pages.push((pagenum, new_page));
+        }
+        for (pagenum, page) in pages {
+            write_page(pagenum, &self.path, page.as_ref(), PageType::Data)?;
+        }
+
+        // Update the header
+        self.schema = new_schema.clone();
+        self.schema_size = new_size;
+        self.indexes = self
+            .indexes
+            .drain()
+            .map(|(index_id, index)| (index_ids[&index_id].clone(), index))
+            .collect();
+        self.unique_indexes = self
+            .unique_indexes
+            .drain()
+            .map(|index_id| index_ids[&index_id].clone())
+            .collect();
+        let new_header: Header = Header {
+            num_pages: self.max_pages,
+            schema: self.schema.clone(),
+            index_top_level_pages: self.indexes.clone(),
+            unique_indexes: self.unique_indexes.clone(),
+            ttl: self.ttl,
+            version: self.get_version(),
+        };
+        write_header(&self.path, &new_header)?;
+        self.increment_version()?;
         Ok(diff)
     }
 
@@ -556,6 +837,53 @@ impl Table {
         }
     }
 
+    /// Writes every row of the table to the writer in the given format, returning the number of
+    /// rows written. The table is read one page at a time, and the rows of each page are written
+    /// out before the next page is read, so the whole table is never held in memory.
+    /// Every column is written, including hidden ones such as the TTL column.
+    pub fn export_stream<W: Write>(
+        &self,
+        writer: &mut W,
+        format: ExportFormat,
+    ) -> Result<usize, String> {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut buffer_pagenum: u32 = 0;
+        let mut row_page: Box<Page> = Box::new([0u8; PAGE_SIZE]);
+        let mut num_rows: usize = 0;
+        for rowinfo in self.clone() {
+            if rowinfo.pagenum != buffer_pagenum && !buffer.is_empty() {
+                writer
+                    .write_all(&buffer)
+                    .map_err(|e| format!("IO Error: {}", e))?;
+                buffer.clear();
+            }
+            buffer_pagenum = rowinfo.pagenum;
+
+            match &format {
+                ExportFormat::Csv { null } => {
+                    let fields: Vec<String> = rowinfo
+                        .row
+                        .iter()
+                        .map(|value| csv_field(value, null))
+                        .collect();
+                    buffer.extend_from_slice(fields.join(",").as_bytes());
+                    buffer.push(b'\n');
+                }
+                ExportFormat::Binary => {
+                    row_page.fill(0);
+                    write_row_at_offset(&self.schema, row_page.as_mut(), &rowinfo.row, 0)?;
+                    buffer.extend_from_slice(&row_page[..self.schema_size]);
+                }
+            }
+            num_rows += 1;
+        }
+        writer
+            .write_all(&buffer)
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("IO Error: {}", e))?;
+        Ok(num_rows)
+    }
+
     /// Gets a vector of EmptyRowLocations within the table
     pub fn get_empty_rows(&self) -> Result<Vec<EmptyRowLocation>, String> {
         let mut empty_rows: Vec<EmptyRowLocation> = Vec::new();
@@ -569,7 +897,12 @@ impl Table {
                 num_rows_empty: 0,
             };
 
-            let page: Page = *read_page(pagenum, &self.path)?.0;
+            // Index pages are kept in the same file, but they don't hold any rows
+            let (page, page_type) = read_page(pagenum, &self.path)?;
+            if page_type != PageType::Data {
+                continue;
+            }
+            let page: Page = *page;
             let mut rownum: u16 = 0;
             loop {
                 match is_row_present(&self.schema, &page, rownum) {
@@ -620,11 +953,71 @@ impl Table {
         }
     }
 
+    /// Makes sure that writing the rows doesn't give any unique index a duplicate key.
+    /// The rows at the replaced locations are being overwritten, so their keys don't count.
+    /// Keys with a null in them never count as duplicates.
+    fn check_unique_indexes(
+        &self,
+        rows: &Vec<Row>,
+        replaced: &Vec<RowLocation>,
+    ) -> Result<(), String> {
+        for index_id in &self.unique_indexes {
+            let btree: BTree = self.load_btree(index_id)?;
+            let mut keys: Vec<IndexKey> = Vec::new();
+            for row in rows {
+                let row: Row = row
+                    .iter()
+                    .cloned()
+                    .zip(&self.schema)
+                    .map(|(val, (_, col))| col.coerce_type(val))
+                    .collect::<Result<Row, String>>()?;
+                let key: IndexKey = get_index_key_from_row(&row, index_id);
+                if key.iter().any(|value| matches!(value, Value::Null(_))) {
+                    continue;
+                }
+                let is_taken: bool = btree
+                    .get_rows(&key)?
+                    .iter()
+                    .any(|rowinfo| !replaced.contains(&rowinfo.get_row_location()));
+                if is_taken {
+                    return Err(format!(
+                        "Duplicate key {:?} violates unique index {}",
+                        key, self.indexes[index_id].1
+                    ));
+                }
+                keys.push(key);
+            }
+
+            // The rows being written can't share a key with each other either
+            keys.sort();
+            if let Some(pair) = keys.windows(2).find(|pair| pair[0] == pair[1]) {
+                return Err(format!(
+                    "Duplicate key {:?} violates unique index {}",
+                    pair[0], self.indexes[index_id].1
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the rows that have the given key in the index with the given id
+    pub fn get_rows_with_key(
+        &self,
+        index_id: &IndexID,
+        key: &IndexKey,
+    ) -> Result<Vec<RowInfo>, String> {
+        self.load_btree(index_id)?.get_rows(key)
+    }
+
     /// Loads a btree from the table for the specified index id
     fn load_btree(&self, index_id: &IndexID) -> Result<BTree, String> {
-        if let Some((pagenum, index_name)) = self.indexes.get(index_id) {
+        // Another index may have been rebalanced since this table synced, adding pages or
+        // moving the root, so the btree gets a copy of the table that's up to date
+        let mut table: Table = self.clone();
+        table.sync_with_header(&read_header(&self.path)?);
+        if let Some((pagenum, index_name)) = table.indexes.get(index_id) {
             BTree::load_btree_from_root_page(
-                &self,
+                &table,
                 *pagenum,
                 index_id.clone(),
                 cols_id_to_index_key_type(&index_id, &self.schema),
@@ -678,6 +1071,77 @@ mod tests {
         clean_table(&path);
     }
 
+    #[test]
+    #[serial]
+    fn test_concurrent_writes() {
+        let path = "test_concurrent_writes".to_string();
+        create_table(&path);
+
+        // Two sessions load the table, and then both try to update the same row
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let path = path.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let table = Table::new(&"".to_string(), &path, None).unwrap();
+                    barrier.wait();
+                    table.rewrite_rows(vec![RowInfo {
+                        row: vec![
+                            Value::I32(i),
+                            Value::String("Session".to_string()),
+                            Value::I32(30),
+                        ],
+                        pagenum: 1,
+                        rownum: 0,
+                    }])
+                })
+            })
+            .collect();
+        let results: Vec<Result<UpdateDiff, String>> =
+            handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        // Exactly one of the sessions wins, and the other has to retry
+        assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 1);
+        let err: String = results.into_iter().find_map(|res| res.err()).unwrap();
+        assert_eq!(
+            err,
+            format!("Table {} was modified concurrently, please retry", path)
+        );
+
+        // Retrying with a freshly loaded table works
+        let mut table = Table::new(&"".to_string(), &path, None).unwrap();
+        let version: u64 = table.get_version();
+        let diff: InsertDiff = table
+            .insert_rows(vec![vec![
+                Value::I32(5),
+                Value::String("Retry".to_string()),
+                Value::I32(50),
+            ]])
+            .unwrap();
+        assert_eq!(table.get_version(), version + 1);
+
+        // A clone shares the table's version, so writing through either one doesn't conflict
+        let clone: Table = table.clone();
+        clone
+            .remove_rows(vec![diff.rows[0].get_row_location()])
+            .unwrap();
+        table
+            .insert_rows(vec![vec![
+                Value::I32(6),
+                Value::String("Clone".to_string()),
+                Value::I32(60),
+            ]])
+            .unwrap();
+        assert_eq!(table.get_version(), version + 3);
+
+        // The version is kept in the file's header, so tables loaded later start from it
+        assert_eq!(read_header(&table.path).unwrap().version, version + 3);
+        let table = Table::new(&"".to_string(), &path, None).unwrap();
+        assert_eq!(table.get_version(), version + 3);
+        clean_table(&path);
+    }
+
     #[test]
     #[serial]
     fn test_replaces() {
@@ -772,6 +1236,9 @@ mod tests {
             num_pages: 3,
             schema: schema.clone(),
             index_top_level_pages: HashMap::new(),
+            unique_indexes: HashSet::new(),
+            ttl: None,
+            version: 0,
         };
         write_header(&filepath, &header).unwrap();
         let page = [0u8; PAGE_SIZE];
@@ -1199,6 +1666,92 @@ mod tests {
         clean_table(&path);
     }
 
+    #[test]
+    #[serial]
+    fn test_export_stream() {
+        let path: String = "test_export_stream".to_string();
+        let schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+            (
+                "nickname".to_string(),
+                Column::Nullable(Box::new(Column::String(20))),
+            ),
+        ];
+        let mut table = create_table_in_dir(&path, &schema, &"".to_string())
+            .unwrap()
+            .0;
+        let rows: Vec<Row> = (0..200)
+            .map(|i| {
+                vec![
+                    Value::I32(i),
+                    Value::String(format!("name, \"{}\"", i)),
+                    match i % 2 {
+                        0 => Value::Null(Column::String(20)),
+                        _ => Value::String(format!("nick{}", i)),
+                    },
+                ]
+            })
+            .collect();
+        table.insert_rows(rows.clone()).unwrap();
+        let num_pages: usize = table.clone().map(|row| row.pagenum).unique().count();
+        assert!(num_pages > 1);
+
+        // Keeps each write separately, to check the rows are written a page at a time
+        struct PageWriter {
+            writes: Vec<Vec<u8>>,
+        }
+        impl Write for PageWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.writes.push(buf.to_vec());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // Fields with a comma or quote are quoted, and nulls are left empty
+        let mut writer = PageWriter { writes: Vec::new() };
+        let num_rows: usize = table
+            .export_stream(
+                &mut writer,
+                ExportFormat::Csv {
+                    null: String::new(),
+                },
+            )
+            .unwrap();
+        assert_eq!(num_rows, 200);
+        assert_eq!(writer.writes.len(), num_pages);
+        let expected: String = (0..200)
+            .map(|i| match i % 2 {
+                0 => format!("{},\"name, \"\"{}\"\"\",\n", i, i),
+                _ => format!("{},\"name, \"\"{}\"\"\",nick{}\n", i, i, i),
+            })
+            .collect();
+        assert_eq!(String::from_utf8(writer.writes.concat()).unwrap(), expected);
+
+        // The binary rows read back into the same rows that were inserted
+        let mut writer = PageWriter { writes: Vec::new() };
+        table
+            .export_stream(&mut writer, ExportFormat::Binary)
+            .unwrap();
+        assert_eq!(writer.writes.len(), num_pages);
+        let bytes: Vec<u8> = writer.writes.concat();
+        let exported: Vec<Row> = bytes
+            .chunks(table.schema_size)
+            .map(|chunk| {
+                let mut page = [0u8; PAGE_SIZE];
+                page[..chunk.len()].copy_from_slice(chunk);
+                read_row(&schema, &page, 0).unwrap()
+            })
+            .collect();
+        assert_eq!(exported, rows);
+
+        // Clean up by removing file
+        clean_table(&path);
+    }
+
     fn create_table(path: &String) -> Table {
         // Creates a file table
         let filepath: String = path.clone() + &TABLE_FILE_EXTENSION.to_string();
@@ -1212,6 +1765,9 @@ mod tests {
             num_pages: 3,
             schema: schema.clone(),
             index_top_level_pages: HashMap::new(),
+            unique_indexes: HashSet::new(),
+            ttl: None,
+            version: 0,
         };
         write_header(&filepath, &header).unwrap();
         let row = vec![
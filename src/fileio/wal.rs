@@ -0,0 +1,536 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::pageio::{PAGE_HEADER_SIZE, PAGE_SIZE};
+use super::storage::get_storage_backend;
+
+/*
+ * The write-ahead log makes each logged operation (a statement or a commit) all or nothing.
+ * Every operation gets its own log file in the database's WAL directory. Before a page is
+ * written, a record with the page's old and new contents is appended to the log and synced
+ * to the disk. Once the operation is done, a commit record is appended and synced, and the
+ * log is removed after the files it changed are synced. An operation that fails is rolled
+ * back from its log straight away, the same way as one that was interrupted.
+ *
+ * When the database is loaded, any log that is left over is from an operation that was
+ * interrupted. If the log has a commit record, the new pages are written again to complete
+ * the operation. Otherwise, the old pages are written back and the files are cut back to
+ * their old lengths, which rolls the operation back.
+ */
+
+pub const WAL_DIR_NAME: &str = "wal";
+pub const WAL_FILE_EXTENSION: &str = ".wal";
+
+// Number of bytes a page takes up in a file, including its header
+const FULL_PAGE_SIZE: usize = PAGE_SIZE + PAGE_HEADER_SIZE;
+
+// The kinds of records in the log. Each record starts with a page that describes it.
+const PAGE_RECORD: u8 = 1; // Followed by the old page and the new page, if they are there
+const LENGTH_RECORD: u8 = 2; // The length of a file before the operation first changed it
+const CREATE_RECORD: u8 = 3; // A file was created, emptying it if it was already there
+const COMMIT_RECORD: u8 = 4; // The operation finished, so its writes should be kept
+
+// The length recorded for a file that didn't exist before the operation
+const NO_FILE: u64 = u64::MAX;
+
+// Offsets of the fields in a record's description page
+const KIND_OFFSET: usize = 0;
+const PAGE_NUM_OFFSET: usize = 1;
+const PAGE_FLAGS_OFFSET: usize = 5;
+const LENGTH_OFFSET: usize = 6;
+const PATH_LEN_OFFSET: usize = 14;
+const PATH_OFFSET: usize = 16;
+
+// Set in a page record's flags for each page image that follows it
+const HAS_OLD_PAGE: u8 = 1;
+const HAS_NEW_PAGE: u8 = 2;
+
+// Used to give every log file a unique name
+static NEXT_LOG_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The operation that is being logged on this thread
+struct Operation {
+    log_path: String,
+    next_log_page: u32,
+    files_seen: HashSet<String>, // The files whose old length is already in the log
+}
+
+thread_local! {
+    static OPERATION: RefCell<Option<Operation>> = const { RefCell::new(None) };
+}
+
+/// Stops logging on this thread when dropped, even if the operation panics.
+/// The log file is left behind in that case, so it's replayed like any other crash.
+struct OperationGuard;
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        OPERATION.with(|operation| *operation.borrow_mut() = None);
+    }
+}
+
+/// A single record read back from a log
+#[derive(Debug)]
+enum Record {
+    Page {
+        path: String,
+        page_num: u32,
+        old_page: Option<Vec<u8>>,
+        new_page: Option<Vec<u8>>,
+    },
+    Length {
+        path: String,
+        len: u64,
+    },
+    Create {
+        path: String,
+    },
+    Commit,
+}
+
+/// Runs the operation with all of its page writes going through a log in wal_dir.
+/// An operation that is run within another logged operation becomes part of it.
+pub fn run_logged<T, E: From<String>>(
+    wal_dir: &String,
+    operation: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    if OPERATION.with(|operation| operation.borrow().is_some()) {
+        return operation();
+    }
+
+    let backend = get_storage_backend(wal_dir);
+    backend.create_dir_all(wal_dir)?;
+    let log_path: String = format!(
+        "{}{}{}_{}{}",
+        wal_dir,
+        std::path::MAIN_SEPARATOR,
+        std::process::id(),
+        NEXT_LOG_ID.fetch_add(1, Ordering::SeqCst),
+        WAL_FILE_EXTENSION
+    );
+    backend.create_file(&log_path)?;
+    OPERATION.with(|current| {
+        *current.borrow_mut() = Some(Operation {
+            log_path: log_path.clone(),
+            next_log_page: 0,
+            files_seen: HashSet::new(),
+        })
+    });
+    let _guard: OperationGuard = OperationGuard;
+
+    let result: Result<T, E> = operation();
+    match &result {
+        Ok(_) => {
+            append_record(COMMIT_RECORD, &"".to_string(), 0, 0, None, None)?;
+            sync_log()?;
+            // The log can only go once the pages it would write again are on the disk
+            let files_seen: Vec<String> =
+                OPERATION.with(|operation| match operation.borrow().as_ref() {
+                    Some(operation) => operation.files_seen.iter().cloned().collect(),
+                    None => Vec::new(),
+                });
+            for path in files_seen {
+                let backend = get_storage_backend(&path);
+                if backend.exists(&path) {
+                    backend.sync_data(&path)?;
+                }
+            }
+        }
+        Err(_) => roll_back(&read_records(&log_path)?)?,
+    }
+    backend.remove_file(&log_path)?;
+    result
+}
+
+/// Logs a page that is about to be written, if an operation is being logged on this thread
+pub fn log_page_write(page_num: u32, path: &String, new_page: &[u8]) -> Result<(), String> {
+    if !is_logging() {
+        return Ok(());
+    }
+    append_file_length(path)?;
+
+    // The old page is only there if the file was already long enough
+    let backend = get_storage_backend(path);
+    let mut old_page: Vec<u8> = vec![0; FULL_PAGE_SIZE];
+    let has_old_page: bool = (page_num as u64 + 1) * FULL_PAGE_SIZE as u64
+        <= backend.file_len(path)?
+        && backend.read_page(path, page_num, &mut old_page).is_ok();
+    append_record(
+        PAGE_RECORD,
+        path,
+        page_num,
+        0,
+        has_old_page.then_some(&old_page[..]),
+        Some(new_page),
+    )?;
+    sync_log()
+}
+
+/// Logs a file that is about to be created, if an operation is being logged on this thread
+pub fn log_file_create(path: &String) -> Result<(), String> {
+    if !is_logging() {
+        return Ok(());
+    }
+    append_file_length(path)?;
+    append_record(CREATE_RECORD, path, 0, 0, None, None)?;
+    sync_log()
+}
+
+/// Logs the length of a file before the operation first changes it, so that pages that
+/// are added to the file can be dropped when rolling back
+pub fn log_file_length(path: &String) -> Result<(), String> {
+    if append_file_length(path)? {
+        sync_log()?;
+    }
+    Ok(())
+}
+
+/// Appends the length of the file to the log if this is the first time the operation saw it.
+/// Returns true if anything was appended.
+fn append_file_length(path: &String) -> Result<bool, String> {
+    let is_new: bool = OPERATION.with(|operation| match operation.borrow_mut().as_mut() {
+        Some(operation) => operation.files_seen.insert(path.clone()),
+        None => false,
+    });
+    if !is_new {
+        return Ok(false);
+    }
+
+    let backend = get_storage_backend(path);
+    let len: u64 = match backend.exists(path) {
+        true => backend.file_len(path)?,
+        false => NO_FILE,
+    };
+    append_record(LENGTH_RECORD, path, 0, len, None, None)?;
+
+    // Creating a file over an existing one empties it, so its pages are logged first
+    if len != NO_FILE {
+        let mut page: Vec<u8> = vec![0; FULL_PAGE_SIZE];
+        for page_num in 0..(len / FULL_PAGE_SIZE as u64) as u32 {
+            backend.read_page(path, page_num, &mut page)?;
+            append_record(PAGE_RECORD, path, page_num, 0, Some(&page), None)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Completes or rolls back the operations that were interrupted, and removes their logs
+pub fn replay(wal_dir: &String) -> Result<(), String> {
+    let backend = get_storage_backend(wal_dir);
+    if !backend.is_dir(wal_dir) {
+        return Ok(());
+    }
+    let mut log_paths: Vec<String> = backend.read_dir(wal_dir)?;
+    log_paths.retain(|path| path.ends_with(WAL_FILE_EXTENSION));
+    log_paths.sort();
+
+    for log_path in log_paths {
+        let records: Vec<Record> = read_records(&log_path)?;
+        if records
+            .iter()
+            .any(|record| matches!(record, Record::Commit))
+        {
+            complete(&records)?;
+        } else {
+            roll_back(&records)?;
+        }
+        backend.remove_file(&log_path)?;
+    }
+    Ok(())
+}
+
+/// Writes the new pages of a committed operation again, in the order they were logged
+fn complete(records: &[Record]) -> Result<(), String> {
+    for record in records.iter() {
+        match record {
+            Record::Create { path } => get_storage_backend(path).create_file(path)?,
+            Record::Page {
+                path,
+                page_num,
+                new_page: Some(new_page),
+                ..
+            } => {
+                let backend = get_storage_backend(path);
+                if !backend.exists(path) {
+                    backend.create_file(path)?;
+                }
+                backend.write_page(path, *page_num, new_page)?;
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the old pages of an operation back, newest first, and cuts the files it
+/// changed back to their old lengths
+fn roll_back(records: &[Record]) -> Result<(), String> {
+    for record in records.iter().rev() {
+        match record {
+            Record::Page {
+                path,
+                page_num,
+                old_page: Some(old_page),
+                ..
+            } if get_storage_backend(path).exists(path) => {
+                get_storage_backend(path).write_page(path, *page_num, old_page)?;
+            }
+            Record::Length { path, len } if get_storage_backend(path).exists(path) => {
+                let backend = get_storage_backend(path);
+                match *len {
+                    NO_FILE => backend.remove_file(path)?,
+                    len => backend.truncate(path, len)?,
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn is_logging() -> bool {
+    OPERATION.with(|operation| operation.borrow().is_some())
+}
+
+/// Waits until the records appended to the log of this thread's operation are on the disk
+fn sync_log() -> Result<(), String> {
+    let log_path: Option<String> = OPERATION.with(|operation| {
+        operation
+            .borrow()
+            .as_ref()
+            .map(|operation| operation.log_path.clone())
+    });
+    match log_path {
+        Some(log_path) => get_storage_backend(&log_path).sync_data(&log_path),
+        None => Ok(()),
+    }
+}
+
+/// Appends a record, followed by the given page images, to the log of this thread's operation
+fn append_record(
+    kind: u8,
+    path: &String,
+    page_num: u32,
+    len: u64,
+    old_page: Option<&[u8]>,
+    new_page: Option<&[u8]>,
+) -> Result<(), String> {
+    OPERATION.with(|operation| {
+        let mut operation = operation.borrow_mut();
+        let operation: &mut Operation = match operation.as_mut() {
+            Some(operation) => operation,
+            None => return Ok(()),
+        };
+        if PATH_OFFSET + path.len() > FULL_PAGE_SIZE {
+            return Err(format!("Path {} is too long for the write-ahead log", path));
+        }
+
+        let mut description: Vec<u8> = vec![0; FULL_PAGE_SIZE];
+        description[KIND_OFFSET] = kind;
+        description[PAGE_NUM_OFFSET..PAGE_FLAGS_OFFSET].copy_from_slice(&page_num.to_le_bytes());
+        description[PAGE_FLAGS_OFFSET] =
+            old_page.map_or(0, |_| HAS_OLD_PAGE) | new_page.map_or(0, |_| HAS_NEW_PAGE);
+        description[LENGTH_OFFSET..PATH_LEN_OFFSET].copy_from_slice(&len.to_le_bytes());
+        description[PATH_LEN_OFFSET..PATH_OFFSET]
+            .copy_from_slice(&(path.len() as u16).to_le_bytes());
+        description[PATH_OFFSET..PATH_OFFSET + path.len()].copy_from_slice(path.as_bytes());
+
+        // The images are written before the description, so a record is only
+        // complete once its description is in the log
+        let backend = get_storage_backend(&operation.log_path);
+        let first_page: u32 = operation.next_log_page;
+        let images: Vec<&[u8]> = old_page.into_iter().chain(new_page).collect();
+        for (i, image) in images.iter().enumerate() {
+            backend.write_page(&operation.log_path, first_page + 1 + i as u32, image)?;
+        }
+        backend.write_page(&operation.log_path, first_page, &description)?;
+        operation.next_log_page += 1 + images.len() as u32;
+        Ok(())
+    })
+}
+
+/// Reads all of the complete records in a log, stopping at the first one that isn't
+fn read_records(log_path: &String) -> Result<Vec<Record>, String> {
+    let backend = get_storage_backend(log_path);
+    let num_pages: u32 = (backend.file_len(log_path)? / FULL_PAGE_SIZE as u64) as u32;
+    let read = |page_num: u32| -> Result<Vec<u8>, String> {
+        let mut page: Vec<u8> = vec![0; FULL_PAGE_SIZE];
+        backend.read_page(log_path, page_num, &mut page)?;
+        Ok(page)
+    };
+
+    let mut records: Vec<Record> = Vec::new();
+    let mut page_num: u32 = 0;
+    while page_num < num_pages {
+        let description: Vec<u8> = read(page_num)?;
+        let path_len: usize = u16::from_le_bytes(
+            description[PATH_LEN_OFFSET..PATH_OFFSET]
+                .try_into()
+                .map_err(|_| "Invalid write-ahead log record".to_string())?,
+        ) as usize;
+        if PATH_OFFSET + path_len > FULL_PAGE_SIZE {
+            break;
+        }
+        let path: String =
+            String::from_utf8_lossy(&description[PATH_OFFSET..PATH_OFFSET + path_len]).to_string();
+        let record_page_num: u32 = u32::from_le_bytes(
+            description[PAGE_NUM_OFFSET..PAGE_FLAGS_OFFSET]
+                .try_into()
+                .map_err(|_| "Invalid write-ahead log record".to_string())?,
+        );
+        let len: u64 = u64::from_le_bytes(
+            description[LENGTH_OFFSET..PATH_LEN_OFFSET]
+                .try_into()
+                .map_err(|_| "Invalid write-ahead log record".to_string())?,
+        );
+
+        match description[KIND_OFFSET] {
+            PAGE_RECORD => {
+                let flags: u8 = description[PAGE_FLAGS_OFFSET];
+                let has_old_page: bool = flags & HAS_OLD_PAGE != 0;
+                let has_new_page: bool = flags & HAS_NEW_PAGE != 0;
+                let num_images: u32 = has_old_page as u32 + has_new_page as u32;
+                if page_num + num_images >= num_pages {
+                    break;
+                }
+                let old_page: Option<Vec<u8>> = match has_old_page {
+                    true => Some(read(page_num + 1)?),
+                    false => None,
+                };
+                let new_page: Option<Vec<u8>> = match has_new_page {
+                    true => Some(read(page_num + num_images)?),
+                    false => None,
+                };
+                records.push(Record::Page {
+                    path,
+                    page_num: record_page_num,
+                    old_page,
+                    new_page,
+                });
+                page_num += 1 + num_images;
+            }
+            LENGTH_RECORD => {
+                records.push(Record::Length { path, len });
+                page_num += 1;
+            }
+            CREATE_RECORD => {
+                records.push(Record::Create { path });
+                page_num += 1;
+            }
+            COMMIT_RECORD => {
+                records.push(Record::Commit);
+                page_num += 1;
+            }
+            _ => break,
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{
+        executor::query::execute_update,
+        fileio::{databaseio::delete_db_instance, databaseio::get_db_instance, tableio::Table},
+        parser::parser::parse,
+        user::userdata::User,
+        util::bench::fcreate_db_instance,
+    };
+
+    #[test]
+    #[serial]
+    // Ensures that replaying the log after a crash rolls back an interrupted statement,
+    // and completes a statement that finished without all of its pages being written
+    fn test_wal_replay_after_crash() {
+        fcreate_db_instance("wal_replay");
+        let mut user: User = User::new("test_user".to_string());
+        get_db_instance()
+            .unwrap()
+            .set_write_ahead_log(true)
+            .unwrap();
+        let wal_dir: String = get_db_instance().unwrap().get_wal_dir_path();
+        let count_rows = |user: &User| {
+            Table::from_user(user, get_db_instance().unwrap(), &"t".to_string(), None)
+                .unwrap()
+                .count()
+        };
+
+        for query in [
+            "CREATE TABLE t (id INT, name VARCHAR(100))",
+            "INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, 'c')",
+        ] {
+            execute_update(&parse(query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        // Finished statements don't leave a log behind
+        let backend = get_storage_backend(&wal_dir);
+        assert!(backend.read_dir(&wal_dir).unwrap().is_empty());
+        assert_eq!(count_rows(&user), 3);
+
+        // Crash partway through an insert that spans several pages
+        let values: Vec<String> = (0..200).map(|i| format!("({}, 'row {}')", i, i)).collect();
+        let query: String = format!("INSERT INTO t VALUES {}", values.join(", "));
+        let crashed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            run_logged(&wal_dir, || {
+                execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string())?;
+                panic!("Simulated crash");
+                #[allow(unreachable_code)]
+                Ok::<(), String>(())
+            })
+        }));
+        assert!(crashed.is_err());
+        assert_eq!(count_rows(&user), 203);
+        assert_eq!(backend.read_dir(&wal_dir).unwrap().len(), 1);
+
+        // Replaying the log puts the table back the way it was before the insert
+        replay(&wal_dir).unwrap();
+        assert_eq!(count_rows(&user), 3);
+        assert!(backend.read_dir(&wal_dir).unwrap().is_empty());
+
+        // A statement that fails partway is rolled back straight away, without leaving a log
+        let failed = run_logged(&wal_dir, || {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string())?;
+            Err::<(), String>("Simulated failure".to_string())
+        });
+        assert!(failed.is_err());
+        assert_eq!(count_rows(&user), 3);
+        assert!(backend.read_dir(&wal_dir).unwrap().is_empty());
+
+        // The table can still be written to afterwards
+        execute_update(
+            &parse("INSERT INTO t VALUES (4, 'd')", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(count_rows(&user), 4);
+
+        // Crash after the insert finished, but before its pages made it to the table's file
+        let table_path: String =
+            Table::from_user(&user, get_db_instance().unwrap(), &"t".to_string(), None)
+                .unwrap()
+                .path;
+        let old_len: u64 = backend.file_len(&table_path).unwrap();
+        let crashed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            run_logged(&wal_dir, || {
+                execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string())?;
+                append_record(COMMIT_RECORD, &"".to_string(), 0, 0, None, None)?;
+                panic!("Simulated crash");
+                #[allow(unreachable_code)]
+                Ok::<(), String>(())
+            })
+        }));
+        assert!(crashed.is_err());
+        backend.truncate(&table_path, old_len).unwrap();
+
+        // Replaying the log completes the insert, since it was committed
+        replay(&wal_dir).unwrap();
+        assert_eq!(count_rows(&user), 204);
+        assert!(backend.read_dir(&wal_dir).unwrap().is_empty());
+
+        delete_db_instance().unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 use clap::Parser;
-use server::connection::Connection;
+use server::connection::{Connection, SERVER_DB_NAME};
 use server::server::db_connection::database_connection_server::DatabaseConnectionServer;
 use tonic::transport::Server;
 
@@ -41,6 +41,34 @@ struct Args {
     // Use benchmark Database with 2 tables with specified number of rows in each table
     #[clap(long)]
     bench2: Option<usize>,
+
+    /// Maximum number of concurrent sessions, unlimited if not given
+    #[clap(long)]
+    max_connections: Option<usize>,
+
+    /// Seconds a session can go without a request before it's closed, never if not given
+    #[clap(long)]
+    session_timeout: Option<u64>,
+
+    /// Maximum number of columns a query can return, unlimited if not given
+    #[clap(long)]
+    max_result_columns: Option<usize>,
+
+    /// SQL file to create the database from, run only when the database doesn't exist yet
+    #[clap(long)]
+    init: Option<String>,
+
+    /// Directory that GQL dump writes to and GQL restore reads from, `dumps` if not given
+    #[clap(long)]
+    dump_dir: Option<String>,
+
+    /// Directory that users other than admins import CSV files from, `imports` if not given
+    #[clap(long)]
+    import_dir: Option<String>,
+
+    /// Directory that users other than admins export SQL scripts to, `exports` if not given
+    #[clap(long)]
+    export_dir: Option<String>,
 }
 
 #[tokio::main]
@@ -50,7 +78,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         client::client::main().await?;
     } else {
         let addr = format!("{}:{}", args.ip, args.port).parse().unwrap();
-        let db_service = Connection::default();
+        let mut db_service = match args.max_connections {
+            Some(max_connections) => Connection::with_max_connections(max_connections),
+            None => Connection::default(),
+        };
+        db_service.max_result_columns = args.max_result_columns;
+        db_service.session_timeout = args.session_timeout.map(std::time::Duration::from_secs);
+        if let Some(dump_dir) = args.dump_dir {
+            version_control::dump::set_dump_dir(&dump_dir);
+        }
+        if let Some(import_dir) = args.import_dir {
+            executor::import::set_import_dir(&import_dir);
+        }
+        if let Some(export_dir) = args.export_dir {
+            executor::export::set_export_dir(&export_dir);
+        }
         println!("GQL Server Started on address: {}", addr);
         if args.demo {
             bench::create_demo_db("demo");
@@ -59,6 +101,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         } else if let Some(num_rows) = args.bench2 {
             println!("Creating bench2");
             bench::create_huge_bench_db_2_tables(num_rows, true);
+        } else if let Some(file) = args.init {
+            println!("{}", bench::create_db_from_sql(SERVER_DB_NAME, &file)?);
         }
         Server::builder()
             .add_service(DatabaseConnectionServer::new(db_service))
@@ -0,0 +1,60 @@
+use sqlparser::ast::{Assignment, Expr, Function, OrderByExpr, SelectItem};
+
+/// The clauses that are taken out of a statement before sqlparser parses it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Clauses {
+    pub row_limit: RowLimit, // The ORDER BY and LIMIT of a DELETE or UPDATE
+    pub returning: Option<Vec<SelectItem>>, // None if the write doesn't return anything
+    pub on_conflict: Option<OnConflict>, // The ON CONFLICT of an INSERT
+    pub select: SelectClauses, // The clauses of the outermost SELECT of a query
+}
+
+/// The clauses that are taken out of the outermost SELECT of a query. They're only taken out
+/// of the outermost SELECT, so the executor knows which SELECT they belong to, and they're
+/// matched up with the parts of it by position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SelectClauses {
+    pub table_samples: Vec<TableSample>,
+    pub distinct_on: Option<Vec<Expr>>, // The expressions of the DISTINCT ON, if it has one
+    pub aggregates: Vec<AggregateClauses>,
+}
+
+/// The `TABLESAMPLE` of a table in the FROM of a SELECT
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableSample {
+    pub table: usize, // The position of the table in the FROM, counting the tables joined to it
+    pub percent: f64, // The percentage of the table's rows to keep
+}
+
+/// The clauses of an aggregate call in the selected columns of a SELECT, like the `FILTER` of
+/// `SUM(x) FILTER (WHERE x > 0)`. They belong to every call in the
+/// selected item that's the same as this one, which the parser makes sure have the same clauses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregateClauses {
+    pub item: usize,    // The position of the selected item that the call is in
+    pub call: Function, // The call, without its clauses
+    pub order_by: Option<OrderByExpr>, // The order of the rows it aggregates
+    pub filter: Option<Expr>, // The condition of the rows it aggregates
+}
+
+/// Limits a DELETE or UPDATE to the first rows it matches, in the order of its ORDER BY.
+/// Rows that are tied, or all rows if there's no ORDER BY, are in the order they're stored in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RowLimit {
+    pub order_by: Vec<OrderByExpr>,
+    pub limit: Option<usize>, // None if every matching row is written
+}
+
+/// What an insert does with a row that has the same key as a row in the table
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConflictAction {
+    DoNothing,
+    DoUpdate(Vec<Assignment>), // The columns of the row in the table to set, and their values
+}
+
+/// The `ON CONFLICT` clause of an insert
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnConflict {
+    pub columns: Vec<String>, // The columns of the unique index to check, or empty to check all of them
+    pub action: ConflictAction,
+}
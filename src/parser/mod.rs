@@ -1,2 +1,3 @@
+pub mod clauses;
 pub mod parser;
 pub mod vc_commands;
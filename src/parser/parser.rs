@@ -1,37 +1,1027 @@
-use crate::fileio::databaseio::get_db_instance;
+use crate::fileio::databaseio::{get_db_instance, select_database, DatabaseSelection};
 use crate::user::usercreds::UserCREDs;
 use crate::user::userdata::User;
+use crate::util::error::Error;
 use crate::version_control::command;
 use crate::version_control::commit::Commit;
+use crate::version_control::dump;
 use crate::version_control::merge::MergeConflictResolutionAlgo;
 
 use clap::Parser as ClapParser;
-use sqlparser::ast::Statement;
-use sqlparser::dialect::GenericDialect;
-use sqlparser::parser::Parser;
+use sqlparser::ast::{Expr, Function, OrderByExpr, Statement};
+use sqlparser::dialect::{Dialect, GenericDialect, PostgreSqlDialect};
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::{Token, Tokenizer, Whitespace};
 
+use super::clauses::{
+    AggregateClauses, Clauses, ConflictAction, OnConflict, SelectClauses, TableSample,
+};
 use super::vc_commands::{VersionControl, VersionControlSubCommand};
 
+/// A statement, along with the clauses of it that sqlparser can't parse. Those are taken out of
+/// the statement before it's parsed, and passed along next to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedStatement {
+    pub statement: Statement,
+    pub clauses: Clauses,
+}
+
 /// A parse function, that starts with a string and returns an AST representation of the query.
 /// If an error happens, an Err(msg) is returned.
-pub fn parse(query: &str, _update: bool) -> Result<Vec<Statement>, String> {
+pub fn parse(query: &str, _update: bool) -> Result<Vec<ParsedStatement>, String> {
     if query.len() == 0 {
         return Err("Empty query".to_string());
     }
 
     let dialect = GenericDialect {};
 
-    let ast = Parser::parse_sql(&dialect, query);
+    let tokens: Vec<Token> = Tokenizer::new(&dialect, query)
+        .tokenize()
+        .map_err(|e| ParserError::from(e).to_string())?;
+    let tokens: Vec<Token> = wrap_quantified_subqueries(remove_current_user_parens(tokens));
+
+    // Same as Parser::parse_sql, but with our own tokens. Each statement is parsed on its own,
+    // since `UPDATE ... FROM` is only parsed with the PostgreSQL dialect.
+    let postgres_dialect = PostgreSqlDialect {};
+    let mut ast: Vec<ParsedStatement> = Vec::new();
+    for statement_tokens in tokens.split(|token| token == &Token::SemiColon) {
+        let is_update: bool = matches!(
+            statement_tokens.iter().find(|token| !matches!(token, Token::Whitespace(_))),
+            Some(Token::Word(w)) if w.keyword == Keyword::UPDATE
+        );
+        let statement_dialect: &dyn Dialect = match is_update {
+            true => &postgres_dialect,
+            false => &dialect,
+        };
+        let mut clauses: Clauses = Clauses::default();
+        let statement_tokens: Vec<Token> = take_write_limits(
+            rewrite_table_ttl(rewrite_use(rewrite_alter_table_indexes(statement_tokens))),
+            statement_dialect,
+            &mut clauses,
+        )?;
+        let statement_tokens: Vec<Token> =
+            take_returning(statement_tokens, statement_dialect, &mut clauses)?;
+        let statement_tokens: Vec<Token> =
+            take_on_conflict(statement_tokens, statement_dialect, &mut clauses)?;
+        let statement_tokens: Vec<Token> =
+            take_table_samples(statement_tokens, &mut clauses.select)?;
+        let statement_tokens: Vec<Token> =
+            take_distinct_on(statement_tokens, statement_dialect, &mut clauses.select)?;
+        let statement_tokens: Vec<Token> =
+            take_aggregate_clauses(statement_tokens, statement_dialect, &mut clauses.select)?;
+        let mut parser = Parser::new(statement_tokens, statement_dialect);
+        if parser.peek_token() == Token::EOF {
+            continue;
+        }
+        let statement: Statement = parser.parse_statement().map_err(|e| e.to_string())?;
+        ast.push(ParsedStatement { statement, clauses });
+        if parser.peek_token() != Token::EOF {
+            return parser
+                .expected("end of statement", parser.peek_token())
+                .map_err(|e: ParserError| e.to_string());
+        }
+    }
 
     // println!("AST: {:?}", ast);
-    return ast.map_err(|e| e.to_string());
+    Ok(ast)
+}
+
+/// The parser reads `CURRENT_USER` as a function without parentheses, and fails on the ones
+/// after it. This removes the empty parentheses from `CURRENT_USER()`, so both can be written.
+fn remove_current_user_parens(tokens: Vec<Token>) -> Vec<Token> {
+    let is_whitespace = |token: &Token| matches!(token, Token::Whitespace(_));
+    let mut removed: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i: usize = 0;
+    while i < tokens.len() {
+        removed.push(tokens[i].clone());
+        if matches!(&tokens[i], Token::Word(w) if w.quote_style.is_none() && w.keyword == Keyword::CURRENT_USER)
+        {
+            // The positions of the next two tokens that aren't whitespace
+            let mut next = (i + 1..tokens.len()).filter(|j| !is_whitespace(&tokens[*j]));
+            if let (Some(open), Some(close)) = (next.next(), next.next()) {
+                if tokens[open] == Token::LParen && tokens[close] == Token::RParen {
+                    i = close;
+                }
+            }
+        }
+        i += 1;
+    }
+    removed
+}
+
+/// The parser only accepts a subquery after ANY or ALL if it's wrapped in a second set of
+/// parentheses, like `x > ALL ((SELECT ...))`. This adds the extra parentheses, so that
+/// `x > ALL (SELECT ...)` can be written instead.
+fn wrap_quantified_subqueries(tokens: Vec<Token>) -> Vec<Token> {
+    let is_whitespace = |token: &Token| matches!(token, Token::Whitespace(_));
+    let mut wrapped: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut depth: usize = 0;
+    // The depths of the parentheses that need an extra closing parenthesis
+    let mut wrapped_depths: Vec<usize> = Vec::new();
+    let mut previous: Option<&Token> = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => {
+                depth += 1;
+                wrapped.push(token.clone());
+
+                let after_quantifier: bool = matches!(
+                    previous,
+                    Some(Token::Word(w)) if w.keyword == Keyword::ALL || w.keyword == Keyword::ANY
+                );
+                let before_subquery: bool = matches!(
+                    tokens[i + 1..].iter().find(|token| !is_whitespace(token)),
+                    Some(Token::Word(w)) if w.keyword == Keyword::SELECT || w.keyword == Keyword::WITH
+                );
+                if after_quantifier && before_subquery {
+                    wrapped.push(Token::LParen);
+                    wrapped_depths.push(depth);
+                }
+            }
+            Token::RParen => {
+                if wrapped_depths.last() == Some(&depth) {
+                    wrapped_depths.pop();
+                    wrapped.push(Token::RParen);
+                }
+                wrapped.push(token.clone());
+                depth = depth.saturating_sub(1);
+            }
+            _ => wrapped.push(token.clone()),
+        }
+        if !is_whitespace(token) {
+            previous = Some(token);
+        }
+    }
+    wrapped
+}
+
+/// The part of the outermost SELECT of a query that a token is in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SelectPart {
+    Projection(usize), // The selected item at this position
+    From(usize),       // The table at this position in the FROM, counting the tables joined to it
+    Other,             // Anywhere else, like in a subquery, or in a statement that isn't a query
+}
+
+/// Works out the part of the outermost SELECT that each token of a statement is in, for the
+/// clauses that are taken out of it. A query with a set operation like `UNION` has more than
+/// one SELECT on the outside, so none of its tokens are in the outermost SELECT.
+fn get_select_parts(tokens: &[Token]) -> Vec<SelectPart> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let is_any_word = |token: &Token, words: &[&str]| words.iter().any(|word| is_word(token, word));
+    let next_word =
+        |from: usize| (from..tokens.len()).find(|i| !matches!(tokens[*i], Token::Whitespace(_)));
+
+    let mut parts: Vec<SelectPart> = vec![SelectPart::Other; tokens.len()];
+    if !next_word(0).is_some_and(|i| is_any_word(&tokens[i], &["SELECT", "WITH", "EXPLAIN"])) {
+        return parts;
+    }
+
+    // Whether each of the open parentheses starts a subquery
+    let mut parens: Vec<bool> = Vec::new();
+    let mut part: SelectPart = SelectPart::Other;
+    let mut previous: Option<&Token> = None;
+    for (i, token) in tokens.iter().enumerate() {
+        // The GROUP of an aggregate's WITHIN GROUP is part of the selected item
+        let is_within_group: bool =
+            is_word(token, "GROUP") && previous.is_some_and(|previous| is_word(previous, "WITHIN"));
+        if !matches!(token, Token::Whitespace(_)) {
+            previous = Some(token);
+        }
+        match token {
+            Token::LParen => parens.push(
+                next_word(i + 1).is_some_and(|j| is_any_word(&tokens[j], &["SELECT", "WITH"])),
+            ),
+            Token::RParen => {
+                parens.pop();
+            }
+            _ if !parens.is_empty() => (),
+            _ if is_any_word(token, &["UNION", "EXCEPT", "INTERSECT"]) => {
+                return vec![SelectPart::Other; tokens.len()];
+            }
+            _ if is_word(token, "SELECT") => part = SelectPart::Projection(0),
+            _ if is_within_group => (),
+            _ if is_any_word(
+                token,
+                &[
+                    "WHERE", "GROUP", "HAVING", "ORDER", "LIMIT", "OFFSET", "FETCH",
+                ],
+            ) =>
+            {
+                part = SelectPart::Other
+            }
+            _ => {
+                part = match part {
+                    SelectPart::Projection(_) if is_word(token, "FROM") => SelectPart::From(0),
+                    SelectPart::Projection(item) if token == &Token::Comma => {
+                        SelectPart::Projection(item + 1)
+                    }
+                    SelectPart::From(table) if token == &Token::Comma || is_word(token, "JOIN") => {
+                        SelectPart::From(table + 1)
+                    }
+                    part => part,
+                }
+            }
+        }
+        if !parens.contains(&true) {
+            parts[i] = part;
+        }
+    }
+    parts
+}
+
+/// The parser doesn't know about `TABLESAMPLE`, so this takes it out of the tables of a query
+/// and into its clauses. `SELECT * FROM big b TABLESAMPLE (10 PERCENT)` becomes
+/// `SELECT * FROM big b`. The sampling method (`BERNOULLI` or `SYSTEM`) and the `PERCENT`
+/// keyword are optional, and both sample each row.
+fn take_table_samples(
+    tokens: Vec<Token>,
+    clauses: &mut SelectClauses,
+) -> Result<Vec<Token>, String> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let parts: Vec<SelectPart> = get_select_parts(&tokens);
+    let mut taken: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i: usize = 0;
+    while i < tokens.len() {
+        // TABLESAMPLE comes right after the name or alias of a table
+        let after_table: bool = matches!(
+            taken
+                .iter()
+                .rev()
+                .find(|token| !matches!(token, Token::Whitespace(_))),
+            Some(Token::Word(_))
+        );
+        if !is_word(&tokens[i], "TABLESAMPLE") || !after_table {
+            taken.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+        let table: usize = match parts[i] {
+            SelectPart::From(table) => table,
+            _ => {
+                return Err(
+                    "TABLESAMPLE is only supported on the tables of a query's outermost SELECT"
+                        .to_string(),
+                )
+            }
+        };
+
+        // The tokens after TABLESAMPLE, skipping whitespace, along with their positions
+        let mut rest = tokens
+            .iter()
+            .enumerate()
+            .skip(i + 1)
+            .filter(|(_, token)| !matches!(token, Token::Whitespace(_)))
+            .peekable();
+        rest.next_if(|(_, token)| is_word(token, "BERNOULLI") || is_word(token, "SYSTEM"));
+        let percent: f64 = match (rest.next(), rest.next()) {
+            (Some((_, Token::LParen)), Some((_, Token::Number(n, _)))) => n
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid TABLESAMPLE percent: {}", n))?,
+            _ => return Err("Expected TABLESAMPLE (<percent> PERCENT)".to_string()),
+        };
+        rest.next_if(|(_, token)| is_word(token, "PERCENT"));
+        match rest.next() {
+            Some((end, Token::RParen)) => i = end + 1,
+            _ => return Err("Expected TABLESAMPLE (<percent> PERCENT)".to_string()),
+        }
+        clauses.table_samples.push(TableSample { table, percent });
+    }
+    Ok(taken)
+}
+
+/// The parser doesn't know about `DISTINCT ON`, so this takes its expressions out of the
+/// outermost SELECT of a query and into its clauses.
+/// `SELECT DISTINCT ON (a, b) x, y FROM t` becomes `SELECT x, y FROM t`.
+fn take_distinct_on(
+    tokens: Vec<Token>,
+    dialect: &dyn Dialect,
+    clauses: &mut SelectClauses,
+) -> Result<Vec<Token>, String> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let next_word =
+        |from: usize| (from..tokens.len()).find(|i| !matches!(tokens[*i], Token::Whitespace(_)));
+    let error = || "Expected DISTINCT ON (<expressions>)".to_string();
+
+    let parts: Vec<SelectPart> = get_select_parts(&tokens);
+    let mut taken: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut i: usize = 0;
+    while i < tokens.len() {
+        taken.push(tokens[i].clone());
+        let distinct: Option<usize> = Some(i)
+            .filter(|i| is_word(&tokens[*i], "SELECT"))
+            .and_then(|i| next_word(i + 1))
+            .filter(|j| is_word(&tokens[*j], "DISTINCT"));
+        let on: Option<usize> = distinct
+            .and_then(|j| next_word(j + 1))
+            .filter(|k| is_word(&tokens[*k], "ON"));
+        let on: usize = match on {
+            Some(on) => on,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        if parts[i] != SelectPart::Projection(0) {
+            return Err("DISTINCT ON is only supported in a query's outermost SELECT".to_string());
+        }
+
+        let open: usize = next_word(on + 1)
+            .filter(|j| tokens[*j] == Token::LParen)
+            .ok_or_else(error)?;
+        let close: usize = closing_paren(&tokens, open).ok_or_else(error)?;
+        clauses.distinct_on = Some(parse_clause(&tokens[open + 1..close], dialect, |parser| {
+            parser.parse_comma_separated(Parser::parse_expr)
+        })?);
+        i = close + 1;
+    }
+    Ok(taken)
+}
+
+/// The parser doesn't understand the clauses that some aggregates take, so this takes them out
+/// of the calls in the selected columns of a query's outermost SELECT and into its clauses.
+/// `SELECT PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY x), LAST_VALUE(y ORDER BY z),
+/// SUM(y) FILTER (WHERE y > 0) FROM t` becomes
+/// `SELECT PERCENTILE_CONT(0.5), LAST_VALUE(y), SUM(y) FROM t`.
+fn take_aggregate_clauses(
+    tokens: Vec<Token>,
+    dialect: &dyn Dialect,
+    clauses: &mut SelectClauses,
+) -> Result<Vec<Token>, String> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let is_whitespace = |token: &Token| matches!(token, Token::Whitespace(_));
+    let next_word = |from: usize| (from..tokens.len()).find(|i| !is_whitespace(&tokens[*i]));
+
+    let parts: Vec<SelectPart> = get_select_parts(&tokens);
+    let mut taken: Vec<Token> = Vec::with_capacity(tokens.len());
+    // The calls that have clauses, along with the positions of their names and closing
+    // parentheses in the taken tokens
+    let mut calls: Vec<(usize, usize, AggregateClauses)> = Vec::new();
+    // The positions of the open parentheses that the current token is in
+    let mut opens: Vec<usize> = Vec::new();
+    let mut i: usize = 0;
+    while i < tokens.len() {
+        // An ORDER BY inside the parentheses of a call, rather than a subquery or a window
+        let in_call: bool = opens.last().is_some_and(|open| {
+            let name: Option<usize> = (0..*open).rev().find(|j| !is_whitespace(&tokens[*j]));
+            matches!(name, Some(j) if matches!(&tokens[j], Token::Word(_)) && !is_word(&tokens[j], "OVER"))
+                && !next_word(open + 1).is_some_and(|j| {
+                    is_word(&tokens[j], "SELECT") || is_word(&tokens[j], "WITH")
+                })
+        });
+        let by: Option<usize> = Some(i)
+            .filter(|i| in_call && is_word(&tokens[*i], "ORDER"))
+            .and_then(|i| next_word(i + 1))
+            .filter(|j| is_word(&tokens[*j], "BY"));
+        if let Some(by) = by {
+            let item: usize =
+                match parts[i] {
+                    SelectPart::Projection(item) => item,
+                    _ => return Err(
+                        "An ORDER BY inside a call is only supported in a query's outermost SELECT"
+                            .to_string(),
+                    ),
+                };
+            let close: usize = closing_paren(&tokens, opens.pop().unwrap())
+                .ok_or("Expected a ) after the ORDER BY of a call".to_string())?;
+            let order_by: OrderByExpr = parse_clause(&tokens[by + 1..close], dialect, |parser| {
+                parser.parse_order_by_expr()
+            })?;
+            taken.push(tokens[close].clone());
+            get_call_clauses(&mut calls, &taken, item, dialect, "ORDER BY")?.order_by =
+                Some(order_by);
+            i = close + 1;
+            continue;
+        }
+
+        // FILTER is only a clause right after the closing parenthesis of a call
+        let after_call: bool =
+            taken.iter().rev().find(|token| !is_whitespace(token)) == Some(&Token::RParen);
+        let filter_open: Option<usize> = Some(i)
+            .filter(|i| after_call && is_word(&tokens[*i], "FILTER"))
+            .and_then(|i| next_word(i + 1))
+            .filter(|j| tokens[*j] == Token::LParen);
+        if let Some(open) = filter_open {
+            let item: usize = match parts[i] {
+                SelectPart::Projection(item) => item,
+                _ => {
+                    return Err("FILTER is only supported in a query's outermost SELECT".to_string())
+                }
+            };
+            let error = || "Expected FILTER (WHERE <condition>) after an aggregate".to_string();
+            let close: usize = closing_paren(&tokens, open).ok_or_else(error)?;
+            let filter: Expr = parse_clause(&tokens[open + 1..close], dialect, |parser| {
+                parser.expect_keyword(Keyword::WHERE)?;
+                parser.parse_expr()
+            })?;
+            let call_clauses: &mut AggregateClauses =
+                get_call_clauses(&mut calls, &taken, item, dialect, "FILTER")?;
+            if call_clauses.filter.is_some() {
+                return Err(format!("{} can only have one FILTER", call_clauses.call));
+            }
+            call_clauses.filter = Some(filter);
+            i = close + 1;
+            continue;
+        }
+
+        let group: Option<usize> = Some(i)
+            .filter(|i| is_word(&tokens[*i], "WITHIN"))
+            .and_then(|i| next_word(i + 1))
+            .filter(|j| is_word(&tokens[*j], "GROUP"));
+        let group: usize = match group {
+            Some(group) => group,
+            None => {
+                match tokens[i] {
+                    Token::LParen => opens.push(i),
+                    Token::RParen => {
+                        opens.pop();
+                    }
+                    _ => (),
+                }
+                taken.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+        };
+        let item: usize = match parts[i] {
+            SelectPart::Projection(item) => item,
+            _ => {
+                return Err(
+                    "WITHIN GROUP is only supported in a query's outermost SELECT".to_string(),
+                )
+            }
+        };
+
+        let error = || "Expected WITHIN GROUP (ORDER BY <expression>)".to_string();
+        let open: usize = next_word(group + 1)
+            .filter(|j| tokens[*j] == Token::LParen)
+            .ok_or_else(error)?;
+        let close: usize = closing_paren(&tokens, open).ok_or_else(error)?;
+        let order_by: OrderByExpr = parse_clause(&tokens[open + 1..close], dialect, |parser| {
+            parser.expect_keywords(&[Keyword::ORDER, Keyword::BY])?;
+            parser.parse_order_by_expr()
+        })?;
+        let call_clauses: &mut AggregateClauses =
+            get_call_clauses(&mut calls, &taken, item, dialect, "WITHIN GROUP")?;
+        if call_clauses.order_by.is_some() {
+            return Err(format!("{} can only be ordered once", call_clauses.call));
+        }
+        call_clauses.order_by = Some(order_by);
+        i = close + 1;
+    }
+
+    // The calls are matched up with the ones in the statement by what they are, so the same call
+    // can't be in a selected item more than once unless it has the same clauses each time
+    let taken_parts: Vec<SelectPart> = get_select_parts(&taken);
+    for (name, close, call_clauses) in &calls {
+        let call: Vec<&Token> = taken[*name..=*close]
+            .iter()
+            .filter(|token| !is_whitespace(token))
+            .collect();
+        let uses: usize = (0..taken.len())
+            .filter(|j| {
+                taken_parts[*j] == SelectPart::Projection(call_clauses.item)
+                    && !is_whitespace(&taken[*j])
+                    && taken[*j..]
+                        .iter()
+                        .filter(|token| !is_whitespace(token))
+                        .take(call.len())
+                        .eq(call.iter().copied())
+            })
+            .count();
+        let same_uses: usize = calls
+            .iter()
+            .filter(|(_, _, other)| other == call_clauses)
+            .count();
+        if uses != same_uses {
+            return Err(format!(
+                "{} is used with different clauses in the same selected column",
+                call_clauses.call
+            ));
+        }
+    }
+    clauses
+        .aggregates
+        .extend(calls.into_iter().map(|(_, _, call_clauses)| call_clauses));
+    Ok(taken)
+}
+
+/// Gets the clauses of the call that the taken tokens end with, for the clause that comes after
+/// it. The call is added to the calls if it doesn't have any clauses yet.
+fn get_call_clauses<'a>(
+    calls: &'a mut Vec<(usize, usize, AggregateClauses)>,
+    taken: &[Token],
+    item: usize,
+    dialect: &dyn Dialect,
+    clause: &str,
+) -> Result<&'a mut AggregateClauses, String> {
+    let is_whitespace = |token: &Token| matches!(token, Token::Whitespace(_));
+    let error = || format!("Expected a function call before {}", clause);
+    let close: usize = taken
+        .iter()
+        .rposition(|token| !is_whitespace(token))
+        .filter(|j| taken[*j] == Token::RParen)
+        .ok_or_else(error)?;
+    if let Some(position) = calls.iter().position(|(_, end, _)| *end == close) {
+        return Ok(&mut calls[position].2);
+    }
+
+    let open: usize = opening_paren(taken, close).ok_or_else(error)?;
+    let name: usize = taken[..open]
+        .iter()
+        .rposition(|token| !is_whitespace(token))
+        .filter(|j| matches!(taken[*j], Token::Word(_)))
+        .ok_or_else(error)?;
+    let call: Function =
+        match parse_clause(&taken[name..=close], dialect, |parser| parser.parse_expr())? {
+            Expr::Function(call) => call,
+            _ => return Err(error()),
+        };
+    calls.push((
+        name,
+        close,
+        AggregateClauses {
+            item,
+            call,
+            order_by: None,
+            filter: None,
+        },
+    ));
+    Ok(&mut calls.last_mut().unwrap().2)
+}
+
+/// Gets the position of the parenthesis that opens the one at `close`
+fn opening_paren(tokens: &[Token], close: usize) -> Option<usize> {
+    let mut depth: usize = 0;
+    for i in (0..=close).rev() {
+        match tokens[i] {
+            Token::RParen => depth += 1,
+            Token::LParen if depth == 1 => return Some(i),
+            Token::LParen => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Gets the position of the parenthesis that closes the one at `open`
+fn closing_paren(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth: usize = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen if depth == 1 => return Some(i),
+            Token::RParen => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// The parser has no way to add an index through `ALTER TABLE`, so this rewrites
+/// `ALTER TABLE t ADD [UNIQUE] INDEX idx (cols)` into `CREATE [UNIQUE] INDEX idx ON t (cols)`.
+/// `KEY` can be used in place of `INDEX`, and `ADD UNIQUE idx (cols)` is also accepted.
+/// `ADD UNIQUE (cols)` without an index name is left alone, since the parser understands it.
+fn rewrite_alter_table_indexes(tokens: &[Token]) -> Vec<Token> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let words: Vec<&Token> = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_)))
+        .collect();
+    if words.len() < 6 || !is_word(words[0], "ALTER") || !is_word(words[1], "TABLE") {
+        return tokens.to_vec();
+    }
+    if !matches!(words[2], Token::Word(_)) || !is_word(words[3], "ADD") {
+        return tokens.to_vec();
+    }
+
+    let mut pos: usize = 4;
+    let unique: bool = is_word(words[pos], "UNIQUE");
+    if unique {
+        pos += 1;
+    }
+    let has_index_keyword: bool = is_word(words[pos], "INDEX") || is_word(words[pos], "KEY");
+    if has_index_keyword {
+        pos += 1;
+    }
+    let is_index: bool = (unique || has_index_keyword)
+        && matches!(words.get(pos), Some(Token::Word(_)))
+        && words.get(pos + 1) == Some(&&Token::LParen);
+    if !is_index {
+        return tokens.to_vec();
+    }
+
+    let mut rewritten: Vec<Token> = vec![Token::make_keyword("CREATE")];
+    if unique {
+        rewritten.push(Token::make_keyword("UNIQUE"));
+    }
+    rewritten.extend(vec![
+        Token::make_keyword("INDEX"),
+        words[pos].clone(),
+        Token::make_keyword("ON"),
+        words[2].clone(),
+    ]);
+    rewritten.extend(words[pos + 1..].iter().map(|token| (*token).clone()));
+    rewritten
+}
+
+/// `USE BRANCH <name>` and `USE DATABASE <name>` are session settings, so this rewrites them
+/// into `SET branch = '<name>'` and `SET database = '<name>'`.
+/// Names can have characters like `-` in them, so everything after `BRANCH` or `DATABASE` is the name.
+fn rewrite_use(tokens: Vec<Token>) -> Vec<Token> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let is_whitespace = |token: &Token| matches!(token, Token::Whitespace(_));
+    let mut words = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| !is_whitespace(token));
+    let (setting_pos, setting): (usize, &str) = match (words.next(), words.next()) {
+        (Some((_, use_word)), Some((pos, branch_word)))
+            if is_word(use_word, "USE") && is_word(branch_word, "BRANCH") =>
+        {
+            (pos, "branch")
+        }
+        (Some((_, use_word)), Some((pos, database_word)))
+            if is_word(use_word, "USE") && is_word(database_word, "DATABASE") =>
+        {
+            (pos, "database")
+        }
+        _ => return tokens,
+    };
+
+    let mut name_tokens: Vec<&Token> = tokens[setting_pos + 1..]
+        .iter()
+        .skip_while(|token| is_whitespace(token))
+        .collect();
+    while name_tokens.last().is_some_and(|token| is_whitespace(token)) {
+        name_tokens.pop();
+    }
+    let name: String = match name_tokens.as_slice() {
+        [Token::Word(w)] => w.value.clone(),
+        [Token::SingleQuotedString(x)] => x.clone(),
+        _ => name_tokens.iter().map(|token| token.to_string()).collect(),
+    };
+    vec![
+        Token::make_keyword("SET"),
+        Token::Whitespace(Whitespace::Space),
+        Token::make_word(setting, None),
+        Token::Eq,
+        Token::SingleQuotedString(name),
+    ]
+}
+
+/// The parser only accepts table options in parentheses, so this rewrites the TTL of
+/// `CREATE TABLE t (...) WITH TTL 3600` into `CREATE TABLE t (...) WITH (ttl = 3600)`.
+fn rewrite_table_ttl(tokens: Vec<Token>) -> Vec<Token> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let is_create: bool = matches!(
+        tokens.iter().find(|token| !matches!(token, Token::Whitespace(_))),
+        Some(token) if is_word(token, "CREATE")
+    );
+    if !is_create {
+        return tokens;
+    }
+
+    // The positions of the tokens that aren't whitespace
+    let words: Vec<usize> = (0..tokens.len())
+        .filter(|i| !matches!(tokens[*i], Token::Whitespace(_)))
+        .collect();
+    for w in words.windows(3) {
+        if let [with, ttl, seconds] = w {
+            if is_word(&tokens[*with], "WITH")
+                && is_word(&tokens[*ttl], "TTL")
+                && matches!(tokens[*seconds], Token::Number(..))
+            {
+                let mut rewritten: Vec<Token> = tokens[..=*with].to_vec();
+                rewritten.extend(vec![
+                    Token::LParen,
+                    Token::make_word("ttl", None),
+                    Token::Eq,
+                    tokens[*seconds].clone(),
+                    Token::RParen,
+                ]);
+                rewritten.extend_from_slice(&tokens[*seconds + 1..]);
+                return rewritten;
+            }
+        }
+    }
+    tokens
+}
+
+/// The parser doesn't accept `ORDER BY`, `LIMIT` or `RETURNING` at the end of a `DELETE` or
+/// `UPDATE`, so this takes them out of the statement and into its clauses.
+/// `DELETE FROM t WHERE a > 1 ORDER BY b DESC LIMIT 10 RETURNING a`
+/// becomes `DELETE FROM t WHERE a > 1`.
+fn take_write_limits(
+    tokens: Vec<Token>,
+    dialect: &dyn Dialect,
+    clauses: &mut Clauses,
+) -> Result<Vec<Token>, String> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let is_whitespace = |token: &Token| matches!(token, Token::Whitespace(_));
+    let statement: &str = match tokens.iter().find(|token| !is_whitespace(token)) {
+        Some(token) if is_word(token, "DELETE") => "DELETE",
+        Some(token) if is_word(token, "UPDATE") => "UPDATE",
+        _ => return Ok(tokens),
+    };
+
+    // The positions of the words outside of any parentheses, skipping whitespace
+    let mut depth: usize = 0;
+    let mut words: Vec<usize> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            _ if depth == 0 && !is_whitespace(token) => words.push(i),
+            _ => (),
+        }
+    }
+    let find = |word: &str| words.iter().position(|i| is_word(&tokens[*i], word));
+    let order_by: Option<usize> =
+        find("ORDER").filter(|w| words.get(w + 1).is_some_and(|i| is_word(&tokens[*i], "BY")));
+    let limit: Option<usize> = find("LIMIT");
+    let returning: Option<usize> = find("RETURNING");
+    let clauses_start: usize = match (order_by, limit, returning) {
+        (None, None, None) => return Ok(tokens),
+        (Some(order_by), Some(limit), _) if limit < order_by => {
+            return Err(format!("Expected ORDER BY before LIMIT in {}", statement))
+        }
+        (Some(start), _, Some(returning)) | (None, Some(start), Some(returning))
+            if returning < start =>
+        {
+            return Err(format!("Expected RETURNING at the end of {}", statement))
+        }
+        (Some(start), _, _) | (None, Some(start), _) | (None, None, Some(start)) => start,
+    };
+    // The position that each clause ends at, which is where the next one starts
+    let returning_start: usize = returning.map_or(tokens.len(), |w| words[w]);
+
+    if let Some(order_by) = order_by {
+        let end: usize = limit.map_or(returning_start, |w| words[w]);
+        let order_tokens: &[Token] = &tokens[words[order_by + 1] + 1..end];
+        if order_tokens.iter().all(&is_whitespace) {
+            return Err(format!(
+                "Expected an expression after ORDER BY in {}",
+                statement
+            ));
+        }
+        clauses.row_limit.order_by = parse_clause(order_tokens, dialect, |parser| {
+            parser.parse_comma_separated(Parser::parse_order_by_expr)
+        })?;
+    }
+    if let Some(limit) = limit {
+        let count: &String = match &words[limit + 1..returning.unwrap_or(words.len())] {
+            [count] => match &tokens[*count] {
+                Token::Number(count, _) => count,
+                _ => {
+                    return Err(format!(
+                        "Expected LIMIT <number> at the end of {}",
+                        statement
+                    ))
+                }
+            },
+            _ => {
+                return Err(format!(
+                    "Expected LIMIT <number> at the end of {}",
+                    statement
+                ))
+            }
+        };
+        clauses.row_limit.limit = Some(
+            count
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid LIMIT: {}", count))?,
+        );
+    }
+    if let Some(returning) = returning {
+        let expressions: &[Token] = &tokens[words[returning] + 1..];
+        if expressions.iter().all(&is_whitespace) {
+            return Err(format!(
+                "Expected an expression after RETURNING in {}",
+                statement
+            ));
+        }
+        clauses.returning = Some(parse_clause(expressions, dialect, |parser| {
+            parser.parse_comma_separated(Parser::parse_select_item)
+        })?);
+    }
+    Ok(tokens[..words[clauses_start]].to_vec())
+}
+
+/// Parses the tokens of a clause that was taken out of a statement, all of which have to be used
+fn parse_clause<T>(
+    tokens: &[Token],
+    dialect: &dyn Dialect,
+    parse: impl FnOnce(&mut Parser) -> Result<T, ParserError>,
+) -> Result<T, String> {
+    let mut parser = Parser::new(tokens.to_vec(), dialect);
+    let parsed: T = parse(&mut parser).map_err(|e| e.to_string())?;
+    if parser.peek_token() != Token::EOF {
+        return parser
+            .expected("end of clause", parser.peek_token())
+            .map_err(|e: ParserError| e.to_string());
+    }
+    Ok(parsed)
+}
+
+/// The parser doesn't accept `RETURNING` at the end of an `INSERT`, so this takes the returned
+/// expressions out of the statement and into its clauses.
+/// `INSERT INTO t (a, b) VALUES (1, 2) RETURNING a, b` becomes `INSERT INTO t (a, b) VALUES (1, 2)`.
+fn take_returning(
+    tokens: Vec<Token>,
+    dialect: &dyn Dialect,
+    clauses: &mut Clauses,
+) -> Result<Vec<Token>, String> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let is_whitespace = |token: &Token| matches!(token, Token::Whitespace(_));
+    match tokens.iter().find(|token| !is_whitespace(token)) {
+        Some(token) if is_word(token, "INSERT") => (),
+        _ => return Ok(tokens),
+    }
+
+    // RETURNING has to be outside of any parentheses, so that it isn't part of a subquery
+    let mut depth: usize = 0;
+    let mut returning: Option<usize> = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            _ if depth == 0 && is_word(token, "RETURNING") => returning = Some(i),
+            _ => (),
+        }
+    }
+    let returning: usize = match returning {
+        Some(returning) => returning,
+        None => return Ok(tokens),
+    };
+    let expressions: &[Token] = &tokens[returning + 1..];
+    if expressions.iter().all(&is_whitespace) {
+        return Err("Expected an expression after RETURNING".to_string());
+    }
+
+    clauses.returning = Some(parse_clause(expressions, dialect, |parser| {
+        parser.parse_comma_separated(Parser::parse_select_item)
+    })?);
+    Ok(tokens[..returning].to_vec())
+}
+
+/// The parser doesn't know about `ON CONFLICT`, so this takes it out of an `INSERT` and into
+/// its clauses. Without a conflict target, every unique index of the table is checked.
+/// `INSERT INTO t VALUES (1, 2) ON CONFLICT (a) DO UPDATE SET b = excluded.b`
+/// becomes `INSERT INTO t VALUES (1, 2)`.
+fn take_on_conflict(
+    tokens: Vec<Token>,
+    dialect: &dyn Dialect,
+    clauses: &mut Clauses,
+) -> Result<Vec<Token>, String> {
+    let is_word = |token: &Token, word: &str| matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.to_uppercase() == word);
+    let is_whitespace = |token: &Token| matches!(token, Token::Whitespace(_));
+    match tokens.iter().find(|token| !is_whitespace(token)) {
+        Some(token) if is_word(token, "INSERT") => (),
+        _ => return Ok(tokens),
+    }
+
+    // The positions of the tokens that aren't whitespace, and ON CONFLICT outside of any parentheses
+    let words: Vec<usize> = (0..tokens.len())
+        .filter(|i| !is_whitespace(&tokens[*i]))
+        .collect();
+    let mut depth: usize = 0;
+    let mut on: Option<usize> = None;
+    for (w, i) in words.iter().enumerate() {
+        match &tokens[*i] {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            token if depth == 0 && is_word(token, "ON") => {
+                if words
+                    .get(w + 1)
+                    .is_some_and(|i| is_word(&tokens[*i], "CONFLICT"))
+                {
+                    on = Some(w);
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    let on: usize = match on {
+        Some(on) => on,
+        None => return Ok(tokens),
+    };
+
+    // The columns of the conflict target are optional
+    let mut action: usize = on + 2;
+    let mut columns: Vec<String> = Vec::new();
+    if words
+        .get(action)
+        .is_some_and(|i| tokens[*i] == Token::LParen)
+    {
+        let close: usize = closing_paren(&tokens, words[action])
+            .ok_or("Expected a ) after the columns of ON CONFLICT".to_string())?;
+        columns = parse_clause(&tokens[words[action] + 1..close], dialect, |parser| {
+            parser.parse_comma_separated(Parser::parse_identifier)
+        })?
+        .into_iter()
+        .map(|column| column.value)
+        .collect();
+        action = words.iter().position(|i| *i == close).unwrap() + 1;
+    }
+
+    let word = |w: usize| words.get(w).map(|i| &tokens[*i]);
+    let action: ConflictAction = match (word(action), word(action + 1), word(action + 2)) {
+        (Some(d), Some(nothing), None) if is_word(d, "DO") && is_word(nothing, "NOTHING") => {
+            ConflictAction::DoNothing
+        }
+        (Some(d), Some(update), Some(set))
+            if is_word(d, "DO") && is_word(update, "UPDATE") && is_word(set, "SET") =>
+        {
+            ConflictAction::DoUpdate(parse_clause(
+                &tokens[words[action + 2] + 1..],
+                dialect,
+                |parser| parser.parse_comma_separated(Parser::parse_assignment),
+            )?)
+        }
+        _ => return Err("Expected DO NOTHING or DO UPDATE SET after ON CONFLICT".to_string()),
+    };
+    clauses.on_conflict = Some(OnConflict { columns, action });
+    Ok(tokens[..words[on]].to_vec())
+}
+
+/// Removes the `--` and `/* */` comments from a query, leaving quoted strings untouched.
+/// Line comments keep their newline, and block comments are replaced with a space, so that
+/// the words on either side of a comment stay apart.
+pub fn strip_comments(query: &str) -> String {
+    let mut stripped: String = String::with_capacity(query.len());
+    let mut quote: Option<char> = None;
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            stripped.push(c);
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => {
+                quote = Some(c);
+                stripped.push(c);
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                // Skip to the end of the line, but keep the new line itself
+                while chars.next_if(|next| *next != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev: char = ' ';
+                for next in chars.by_ref() {
+                    if prev == '*' && next == '/' {
+                        break;
+                    }
+                    prev = next;
+                }
+                stripped.push(' ');
+            }
+            _ => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+/// Whether the query has a semicolon that ends a statement.
+/// Semicolons inside of comments and quoted strings don't end a statement.
+pub fn is_statement_complete(query: &str) -> bool {
+    let mut quote: Option<char> = None;
+    for c in strip_comments(query).chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == ';' => return true,
+            None => {}
+        }
+    }
+    false
+}
+
+/// Whether the query is `GQL sessions`. The server runs it itself, since it lists the
+/// sessions that only the server knows about.
+pub fn is_sessions_cmd(query: &str) -> bool {
+    shellwords::split(query)
+        .ok()
+        .and_then(|command| VersionControl::try_parse_from(command).ok())
+        .map_or(false, |parse| {
+            matches!(parse.subcmd, VersionControlSubCommand::Sessions)
+        })
 }
 
 /// This method parses a version control command's query string into the individual components.
 /// Format "GQL <command> <flags> <args>"
-pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Result<String, String> {
-    let command = shellwords::split(query)
-        .map_err(|e| format!("Mismatched quotes while parsing query: {}", e))?;
+pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Result<String, Error> {
+    let _database: DatabaseSelection = select_database(user.get_database_name());
+    let command = shellwords::split(query).map_err(|e| {
+        Error::invalid_argument(format!("Mismatched quotes while parsing query: {}", e))
+    })?;
     let parse = VersionControl::try_parse_from(command);
     match parse {
         Ok(parse) => {
@@ -39,7 +1029,7 @@ pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Resul
                 VersionControlSubCommand::Commit { message } => {
                     // Make sure the user has some changes to commit
                     if user.get_diffs().len() == 0 {
-                        return Err("No changes to commit".to_string());
+                        return Err(Error::failed_precondition("No changes to commit"));
                     }
 
                     let (res_node, res_commit) = get_db_instance()?.create_commit_on_head(
@@ -61,12 +1051,25 @@ pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Resul
                         Ok(log_results.0)
                     }
                 }
-                VersionControlSubCommand::Info { commit: hash } => command::info(&hash),
+                VersionControlSubCommand::Info { commit: hash } => Ok(command::info(&hash)?),
                 VersionControlSubCommand::Status => Ok(user.get_status().0),
                 VersionControlSubCommand::CreateBranch {
                     branch_name,
                     commit,
+                    from,
                 } => {
+                    let commit: Option<String> = match from.as_slice() {
+                        [] => commit,
+                        [keyword, hash] if keyword.eq_ignore_ascii_case("from") => {
+                            Some(hash.clone())
+                        }
+                        _ => {
+                            return Err(Error::invalid_argument(format!(
+                                "Expected `from <commit>` after the branch name, found `{}`",
+                                from.join(" ")
+                            )))
+                        }
+                    };
                     get_db_instance()?
                         .create_branch(&branch_name, &commit, user)
                         .map_err(|e| e.to_string())?;
@@ -76,7 +1079,7 @@ pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Resul
                     if current {
                         Ok(user.get_current_branch_name())
                     } else {
-                        command::list_branches(user)
+                        Ok(command::list_branches(user)?)
                     }
                 }
                 VersionControlSubCommand::SwitchBranch { branch_name } => {
@@ -91,25 +1094,27 @@ pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Resul
                     message,
                     delete_src,
                     strategy,
+                    replay,
                 } => {
                     // Get the strategy from the command string
                     let merge_strategy = match strategy.as_str() {
                         "ours" => MergeConflictResolutionAlgo::UseSource,
                         "theirs" => MergeConflictResolutionAlgo::UseTarget,
                         "clean" => MergeConflictResolutionAlgo::NoConflicts,
-                        _ => Err(
-                            "Invalid strategy: Must be one of 'ours', 'theirs', or 'clean'"
-                                .to_string(),
-                        )?,
+                        _ => Err(Error::invalid_argument(
+                            "Invalid strategy: Must be one of 'ours', 'theirs', or 'clean'",
+                        ))?,
                     };
 
                     if src_branch == dest_branch {
-                        return Err("Cannot merge a branch into itself".to_string());
+                        return Err(Error::invalid_argument("Cannot merge a branch into itself"));
                     }
 
                     // Make sure user does not have any uncommitted changes
                     if user.get_diffs().len() > 0 {
-                        return Err("Cannot merge with uncommitted changes".to_string());
+                        return Err(Error::failed_precondition(
+                            "Cannot merge with uncommitted changes",
+                        ));
                     }
 
                     // Swap user to the destination branch
@@ -117,6 +1122,21 @@ pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Resul
                         .switch_branch(&dest_branch, user)
                         .map_err(|e| e.to_string())?;
 
+                    if replay {
+                        // Replay each source commit onto the destination branch
+                        let commits: Vec<Commit> = get_db_instance()?.replay_branch(
+                            &src_branch,
+                            user,
+                            merge_strategy,
+                            delete_src,
+                        )?;
+                        return Ok(format!(
+                            "Merge Successful, replayed {} commits ending at hash {}",
+                            commits.len(),
+                            commits.last().unwrap().hash
+                        ));
+                    }
+
                     // Merge the source branch into the destination branch
                     let merge_commit: Commit = get_db_instance()?
                         .merge_branches(
@@ -136,7 +1156,7 @@ pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Resul
                     let branch_exist =
                         branch_heads_instance.does_branch_exist(branch_name.clone())?;
                     if !branch_exist {
-                        return Err("Branch does not exist".to_string());
+                        return Err(Error::not_found("Branch does not exist"));
                     }
                     let del_results =
                         command::del_branch(user, &branch_name.clone(), force, all_users)?;
@@ -182,15 +1202,57 @@ pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Resul
                         user_creds_instance.get_all_usernames(),
                     ))
                 }
+                VersionControlSubCommand::Sessions => {
+                    Err("Sessions can only be listed by the server".into())
+                }
+                VersionControlSubCommand::BranchDiff {
+                    branch_a,
+                    branch_b,
+                    table,
+                } => Ok(command::branch_diff(&branch_a, &branch_b, &table)?),
+                VersionControlSubCommand::Stat { table } => Ok(command::table_stats(&table, user)?),
+                VersionControlSubCommand::Vacuum { table } => command::vacuum(&table, user),
+                VersionControlSubCommand::Optimize { table, vacuum } => {
+                    command::optimize(&table, vacuum, user)
+                }
+                VersionControlSubCommand::Verify { table } => Ok(command::verify(&table, user)?),
+                VersionControlSubCommand::Dump { branch_name, file } => {
+                    dump::dump_branch(&branch_name, &file, user)
+                }
+                VersionControlSubCommand::Restore { file } => dump::restore_branch(&file, user),
+                VersionControlSubCommand::CommitCompression { setting } => {
+                    let enabled: bool = match setting.to_lowercase().as_str() {
+                        "on" => true,
+                        "off" => false,
+                        _ => Err(Error::invalid_argument(
+                            "Invalid setting: Must be one of 'on' or 'off'",
+                        ))?,
+                    };
+                    get_db_instance()?.set_commit_compression(enabled)?;
+                    Ok(format!(
+                        "Commit compression turned {}",
+                        setting.to_lowercase()
+                    ))
+                }
+                VersionControlSubCommand::WriteAheadLog { setting } => {
+                    let enabled: bool = match setting.to_lowercase().as_str() {
+                        "on" => true,
+                        "off" => false,
+                        _ => Err(Error::invalid_argument(
+                            "Invalid setting: Must be one of 'on' or 'off'",
+                        ))?,
+                    };
+                    get_db_instance()?.set_write_ahead_log(enabled)?;
+                    Ok(format!("Write-ahead log turned {}", setting.to_lowercase()))
+                }
                 VersionControlSubCommand::PullChanges { merge_algo } => {
                     let merge_strategy = match merge_algo.as_str() {
                         "ours" => MergeConflictResolutionAlgo::UseSource,
                         "theirs" => MergeConflictResolutionAlgo::UseTarget,
                         "clean" => MergeConflictResolutionAlgo::NoConflicts,
-                        _ => Err(
-                            "Invalid strategy: Must be one of 'ours', 'theirs', or 'clean'"
-                                .to_string(),
-                        )?,
+                        _ => Err(Error::invalid_argument(
+                            "Invalid strategy: Must be one of 'ours', 'theirs', or 'clean'",
+                        ))?,
                     };
 
                     command::pull(user, merge_strategy.clone())?;
@@ -198,7 +1260,7 @@ pub fn parse_vc_cmd(query: &str, user: &mut User, all_users: Vec<User>) -> Resul
                 }
             }
         }
-        Err(e) => Err(e.to_string()),
+        Err(e) => Err(Error::invalid_argument(e.to_string())),
     }
 }
 
@@ -506,6 +1568,58 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    #[serial]
+    fn test_strip_comments() {
+        let query = "SELECT a, -- the first column\n/* the; second */ b FROM t WHERE c = '--; /* not a comment */';";
+        assert_eq!(
+            strip_comments(query),
+            "SELECT a, \n  b FROM t WHERE c = '--; /* not a comment */';"
+        );
+
+        // Only a semicolon outside of comments and strings ends the statement
+        assert!(!is_statement_complete("SELECT * FROM t -- not done;"));
+        assert!(!is_statement_complete("SELECT * FROM t /* not; done */"));
+        assert!(!is_statement_complete("SELECT * FROM t WHERE a = 'b;'"));
+        assert!(!is_statement_complete(
+            "SELECT * FROM t /* still ; in a comment"
+        ));
+        assert!(is_statement_complete("SELECT * FROM t /* a comment */;"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_parse_sql_with_comments() {
+        fcreate_db_instance("parse_sql_with_comments");
+        let mut user: User = User::new("test_user".to_string());
+        let script = "-- Create the table; it holds a single row\n\
+            CREATE TABLE commented (id INT, name VARCHAR(20)); /* a block comment; with a semicolon */\n\
+            INSERT INTO commented /* inline */ VALUES (1, 'a -- b'); -- trailing comment";
+        assert!(is_statement_complete(script));
+        crate::executor::query::execute_update(
+            &parse(script, true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        let query = "SELECT id, /* the name */ name\nFROM commented -- every row\nWHERE id = 1;";
+        let (_, results) = crate::executor::query::execute_query(
+            &parse(query, false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![vec![
+                crate::util::dbtype::Value::I64(1),
+                crate::util::dbtype::Value::String("a -- b".to_string())
+            ]]
+        );
+        delete_db_instance().unwrap();
+    }
+
     #[test]
     #[serial]
     fn test_parse_sql_cmd12() {
@@ -513,4 +1627,54 @@ mod tests {
         let result = parse(query, true);
         assert!(result.is_ok());
     }
+
+    #[test]
+    #[serial]
+    fn test_branch_from_commit() {
+        use crate::executor::query::{execute_query, execute_update};
+        use crate::util::dbtype::Value;
+
+        fcreate_db_instance("branch_from_commit_db");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string()).unwrap();
+        };
+        let select = |user: &mut User| {
+            let query = parse("SELECT id, name FROM people", false).unwrap();
+            execute_query(&query, user, &"".to_string()).unwrap().1
+        };
+
+        run("CREATE TABLE people (id INT, name VARCHAR(20))", &mut user);
+        run("INSERT INTO people VALUES (1, 'Ann')", &mut user);
+        parse_vc_cmd("gql commit -m first", &mut user, Vec::new()).unwrap();
+        let first_hash: String = command::log(&user).unwrap().1[0][1].clone();
+
+        run("UPDATE people SET name = 'Bob' WHERE id = 1", &mut user);
+        run("INSERT INTO people VALUES (2, 'Cat')", &mut user);
+        parse_vc_cmd("gql commit -m second", &mut user, Vec::new()).unwrap();
+
+        // The new branch has the rows as they were at the first commit
+        let query: String = format!("gql branch old FROM {}", first_hash);
+        parse_vc_cmd(&query, &mut user, Vec::new()).unwrap();
+        assert_eq!(user.get_current_branch_name(), "old");
+        assert_eq!(
+            select(&mut user),
+            vec![vec![Value::I64(1), Value::String("Ann".to_string())]]
+        );
+
+        // The branch it was created from still has the latest rows
+        parse_vc_cmd("gql switch main", &mut user, Vec::new()).unwrap();
+        assert_eq!(
+            select(&mut user),
+            vec![
+                vec![Value::I64(1), Value::String("Bob".to_string())],
+                vec![Value::I64(2), Value::String("Cat".to_string())],
+            ]
+        );
+
+        // Anything other than `from <commit>` after the branch name is an error
+        let query: String = format!("gql branch other at {}", first_hash);
+        assert!(parse_vc_cmd(&query, &mut user, Vec::new()).is_err());
+        delete_db_instance().unwrap();
+    }
 }
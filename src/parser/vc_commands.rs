@@ -61,6 +61,9 @@ pub enum VersionControlSubCommand {
         /// If the commit is specified, the new branch will be created from the given commit, if the commit exists in the current branch
         #[arg(long, short)]
         commit: Option<String>,
+        /// The commit to create the branch from, written as `from <commit>` after the branch name
+        #[arg(num_args = 2, value_names = ["FROM", "COMMIT"], conflicts_with = "commit")]
+        from: Vec<String>,
     },
     /// Lists all branches, with the current branch marked with an asterisk
     #[clap(aliases = &["list"])]
@@ -90,6 +93,10 @@ pub enum VersionControlSubCommand {
         /// The algorithm to use for resolving merge conflicts (options: "ours", "theirs", "clean")
         #[arg(long, short, default_value = "clean")]
         strategy: String,
+        /// Whether to replay each source commit onto the destination branch, keeping their history,
+        /// instead of squashing them into one merge commit (default is false)
+        #[arg(long, short, default_value = "false")]
+        replay: bool,
     },
     /// Deletes the given branch
     #[clap(aliases = &["delete", "del"])]
@@ -111,6 +118,68 @@ pub enum VersionControlSubCommand {
     },
     /// Returns the current user and all users
     User,
+    /// Lists the sessions connected to the server, with their user and branch (admin only)
+    Sessions,
+    /// Shows the rows of a table that differ between two branches
+    #[clap(aliases = &["branchdiff"])]
+    BranchDiff {
+        /// The name of the first branch
+        branch_a: String,
+        /// The name of the second branch
+        branch_b: String,
+        /// The name of the table to compare
+        table: String,
+    },
+    /// Shows the number of rows in a table, and the row slots left free by deleted rows
+    #[clap(aliases = &["stats"])]
+    Stat {
+        /// The name of the table
+        table: String,
+    },
+    /// Packs the rows of a table together, reclaiming the row slots left free by deleted rows
+    Vacuum {
+        /// The name of the table
+        table: String,
+    },
+    /// Rebuilds the indexes of a table, so their pages are evenly filled again
+    Optimize {
+        /// The name of the table
+        table: String,
+        /// Whether to also vacuum the table (default is false)
+        #[arg(long, short, default_value = "false")]
+        vacuum: bool,
+    },
+    /// Checks the indexes of a table against its rows, and reports the entries that don't match
+    Verify {
+        /// The name of the table
+        table: String,
+    },
+    /// Writes the given branch, along with its commit history, into a single dump file
+    #[clap(aliases = &["export"])]
+    Dump {
+        /// The name of the branch to dump
+        branch_name: String,
+        /// The name of the dump file to write, within the server's dump directory
+        file: String,
+    },
+    /// Recreates the branch stored in the given dump file, along with its commit history
+    #[clap(aliases = &["import"])]
+    Restore {
+        /// The name of the dump file to restore, within the server's dump directory
+        file: String,
+    },
+    /// Turns compression of new commits on or off for the database
+    #[clap(aliases = &["compress"])]
+    CommitCompression {
+        /// Whether new commits are compressed (options: "on", "off")
+        setting: String,
+    },
+    /// Turns the write-ahead log on or off for the database
+    #[clap(aliases = &["wal"])]
+    WriteAheadLog {
+        /// Whether statements and commits are logged before they're written (options: "on", "off")
+        setting: String,
+    },
     /// Updates the user's copy of the database
     /// (i.e. fetches the latest changes from the remote)
     #[clap(aliases = &["pull", "fetch"])]
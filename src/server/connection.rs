@@ -1,18 +1,128 @@
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
 
+use chrono::NaiveDateTime;
 use pwhash::bcrypt;
+use tabled::{builder::Builder, Style};
 
-use crate::fileio::databaseio::{get_db_instance, load_db_instance};
+use crate::executor::query;
+use crate::fileio::databaseio::{
+    get_db_instance, load_db_instance, select_database, DatabaseSelection,
+};
 use crate::user::usercreds::UserCred;
 use crate::user::usercreds::UserPermissions::*;
 use crate::user::userdata::*;
+use crate::util::error::Error;
+
+/// The database that the server opens when the first client connects
+pub const SERVER_DB_NAME: &str = "realdb.db";
+
+// The upper bounds of the latency histogram's buckets, in milliseconds.
+// Anything slower goes into a last bucket with no upper bound.
+pub const LATENCY_BUCKETS_MS: [f64; 12] = [
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
 
 #[derive(Debug, Default)]
 pub struct Connection {
     pub clients: Arc<Mutex<Vec<User>>>,
+    pub max_connections: Option<usize>, // The most sessions that can be open at once, if limited
+    pub max_result_columns: Option<usize>, // The most columns a session's queries can return, if limited
+    pub metrics: Arc<Mutex<Metrics>>,      // What the server has done since it started
+    pub sessions: Arc<Mutex<Vec<Session>>>, // When each connected client joined, and last ran a request
+    pub session_timeout: Option<Duration>, // How long a session can go without a request before it's closed, if limited
+}
+
+/// A client that is connected to the server
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub connected_at: SystemTime,
+    pub last_activity: SystemTime, // When the client last ran a query, update, or command
+}
+
+/// Counters about the queries and commits that the server has run since it started
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    pub total_queries: u64,
+    pub errors: u64,
+    pub latency_buckets: Vec<u64>, // Query counts for each of LATENCY_BUCKETS_MS, then for slower ones
+    pub commits: BTreeMap<String, u64>, // The number of commits made on each branch
+}
+
+impl Metrics {
+    /// Counts a query that took the given time, and whether it failed
+    pub fn record_query(&mut self, elapsed: Duration, failed: bool) {
+        if self.latency_buckets.is_empty() {
+            self.latency_buckets = vec![0; LATENCY_BUCKETS_MS.len() + 1];
+        }
+        let elapsed_ms: f64 = elapsed.as_secs_f64() * 1000.0;
+        let bucket: usize = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed_ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.latency_buckets[bucket] += 1;
+        self.total_queries += 1;
+        if failed {
+            self.errors += 1;
+        }
+    }
+
+    /// Counts a commit made on the given branch
+    pub fn record_commit(&mut self, branch_name: &String) {
+        *self.commits.entry(branch_name.clone()).or_insert(0) += 1;
+    }
+
+    /// The latency that the given fraction of queries ran within, in milliseconds.
+    /// This is the upper bound of the histogram bucket that the percentile falls into,
+    /// or infinity if it falls into the last bucket. It's 0 when no queries have run.
+    pub fn latency_percentile(&self, percentile: f64) -> f64 {
+        if self.total_queries == 0 {
+            return 0.0;
+        }
+        let rank: u64 = ((self.total_queries as f64) * percentile).ceil().max(1.0) as u64;
+        let mut seen: u64 = 0;
+        for (bucket, count) in self.latency_buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return *LATENCY_BUCKETS_MS.get(bucket).unwrap_or(&f64::INFINITY);
+            }
+        }
+        f64::INFINITY
+    }
 }
 
 impl Connection {
+    /// Creates a connection that rejects new clients once `max_connections` are connected.
+    pub fn with_max_connections(max_connections: usize) -> Connection {
+        // Connection implements Drop, so the other fields can't be filled in with ..Default::default()
+        let mut connection: Connection = Connection::default();
+        connection.max_connections = Some(max_connections);
+        connection
+    }
+
+    /* Metrics Methods */
+    /// Counts a query or update that a client ran, and how long it took
+    pub fn record_query(&self, elapsed: Duration, failed: bool) {
+        self.metrics.lock().unwrap().record_query(elapsed, failed);
+    }
+
+    /// Counts a commit that a client made on the given branch
+    pub fn record_commit(&self, branch_name: &String) {
+        self.metrics.lock().unwrap().record_commit(branch_name);
+    }
+
+    /// Gets a copy of the server's metrics
+    pub fn snapshot_metrics(&self) -> Metrics {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    /// The number of clients that are connected right now
+    pub fn get_active_sessions(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
     /* Client Management Methods */
     /// Gets a mutable reference to the client with the given ID.
     pub fn get_client<'a>(&self, id: &String) -> Result<&'a mut User, String> {
@@ -40,9 +150,12 @@ impl Connection {
         password: String,
         create: bool,
     ) -> Result<String, String> {
+        // Sessions that have been idle for too long are closed first, which frees their slots
+        self.close_idle_sessions();
+
         // Generate and add a new unique client ID.
         if get_db_instance().is_err() {
-            load_db_instance(&"realdb.db".to_string())?;
+            load_db_instance(&SERVER_DB_NAME.to_string())?;
         }
         let user_creds_instance = get_db_instance()?.get_user_creds_file_mut();
         if !user_creds_instance.does_user_exist("admin".to_string())? {
@@ -72,12 +185,24 @@ impl Connection {
             }
         }
 
-        for client in self.clients.lock().unwrap().iter() {
+        let mut clients: MutexGuard<Vec<User>> = self.clients.lock().unwrap();
+        for client in clients.iter() {
             if client.get_user_id() == username {
                 return Err(format!("User {} already logged in", username));
             }
         }
+        // A slot is freed when a client disconnects or its session times out
+        if let Some(max_connections) = self.max_connections {
+            if clients.len() >= max_connections {
+                return Err(format!(
+                    "Too many connections: the server allows at most {} sessions",
+                    max_connections
+                ));
+            }
+        }
         let mut user: User = User::new(username.clone());
+        // Sessions start with the server's limit, which they can change with SET
+        user.set_max_result_columns(self.max_result_columns);
 
         if username == "admin" {
             user.set_permissions(&Admin);
@@ -91,18 +216,152 @@ impl Connection {
             user.set_permissions(&Read);
         }
 
-        self.clients.lock().unwrap().push(user.clone());
+        clients.push(user.clone());
+        let now: SystemTime = SystemTime::now();
+        self.sessions.lock().unwrap().push(Session {
+            id: username.clone(),
+            connected_at: now,
+            last_activity: now,
+        });
         Ok(username)
     }
 
+    /// Asks the query that the client with the given ID is running to stop.
+    pub fn cancel_client_query(&self, id: &String) -> Result<(), String> {
+        let clients: MutexGuard<Vec<User>> = self.clients.lock().unwrap();
+        let client: &User = clients
+            .iter()
+            .find(|client| client.get_user_id() == *id)
+            .ok_or("Client not found")?;
+        client.request_cancel();
+        Ok(())
+    }
+
     pub fn remove_client(&self, id: String) -> Result<(), String> {
         self.clients
             .lock()
             .unwrap()
             .retain(|x| &x.get_user_id() != &id);
+        self.sessions.lock().unwrap().retain(|x| x.id != id);
         Ok(())
     }
 
+    /// Ends the session of the client with the given ID. The transaction it left open is rolled
+    /// back and its temp branch is deleted. The client is removed even if that cleanup fails,
+    /// so that the session doesn't linger, and the cleanup's error is returned after.
+    pub fn close_client(&self, id: &String) -> Result<(), String> {
+        // The user is only used in its own scope to prevent issues when removing the client
+        let cleanup: Result<(), String> = {
+            let user: &mut User = self.get_client(id)?;
+            let _database: DatabaseSelection = select_database(user.get_database_name());
+            let mut cleanup: Result<(), String> = Ok(());
+            if user.get_transaction_start().is_some() {
+                cleanup = query::rollback_transaction(user);
+            }
+            if user.is_on_temp_commit() {
+                cleanup = cleanup.and(
+                    get_db_instance()
+                        .and_then(|database| database.delete_temp_branch_directory(user)),
+                );
+            }
+            cleanup
+        };
+        self.remove_client(id.clone())?;
+        cleanup
+    }
+
+    /// Closes the sessions that haven't run a request within the session timeout, if there is one
+    pub fn close_idle_sessions(&self) {
+        if let Some(session_timeout) = self.session_timeout {
+            let idle_ids: Vec<String> = self
+                .sessions
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|session| {
+                    session
+                        .last_activity
+                        .elapsed()
+                        .map_or(false, |idle| idle > session_timeout)
+                })
+                .map(|session| session.id.clone())
+                .collect();
+            for id in idle_ids {
+                // The client is removed even if its cleanup fails, so its slot is freed either way
+                let _ = self.close_client(&id);
+            }
+        }
+    }
+
+    /* Session Registry Methods */
+    /// Marks the session with the given ID as having just run a request
+    pub fn touch_session(&self, id: &String) {
+        if let Some(session) = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|session| session.id == *id)
+        {
+            session.last_activity = SystemTime::now();
+        }
+    }
+
+    /// Gets each connected session along with its client, in the order they connected
+    pub fn get_sessions(&self) -> Vec<(Session, User)> {
+        let clients: Vec<User> = self.get_clients_readonly();
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|session| {
+                clients
+                    .iter()
+                    .find(|client| client.get_user_id() == session.id)
+                    .map(|client| (session.clone(), client.clone()))
+            })
+            .collect()
+    }
+
+    /// This function implements the GQL sessions command, which only admins can run.
+    /// It lists each connected session with its user, branch, and when it connected and last ran a request.
+    pub fn list_sessions(&self, user: &User) -> Result<String, Error> {
+        if user.get_permissions() != Admin {
+            return Err(Error::permission_denied(
+                "You do not have permission to list sessions",
+            ));
+        }
+        let format_time = |time: SystemTime| -> String {
+            time.duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .and_then(|since| NaiveDateTime::from_timestamp_opt(since.as_secs() as i64, 0))
+                .map_or("unknown".to_string(), |time| {
+                    time.format("%Y-%m-%d %H:%M:%S").to_string()
+                })
+        };
+
+        let mut builder = Builder::default();
+        builder.set_columns(vec![
+            "session",
+            "user",
+            "branch",
+            "connected",
+            "last activity",
+        ]);
+        for (session, client) in self.get_sessions() {
+            builder.add_record(vec![
+                session.id.clone(),
+                client.get_user_id(),
+                client.get_current_branch_name(),
+                format_time(session.connected_at),
+                format_time(session.last_activity),
+            ]);
+        }
+        let mut table = builder.build();
+        table.with(Style::rounded());
+        Ok(table.to_string())
+    }
+
     pub fn get_all_branches_clients_are_connected_to(&self) -> Vec<String> {
         let mut branches: Vec<String> = Vec::new();
         for client in self.clients.lock().unwrap().iter() {
@@ -139,7 +398,9 @@ impl Drop for Connection {
 mod tests {
     use super::*;
     use crate::{
+        executor::query,
         fileio::{databaseio::*, header::Schema},
+        parser::parser::parse,
         util::dbtype::Column,
         util::{bench::fcreate_db_instance, dbtype::Value, row::*},
         version_control::diff::{Diff, InsertDiff},
@@ -184,6 +445,136 @@ mod tests {
         delete_db_instance().unwrap();
     }
 
+    #[test]
+    fn test_metrics() {
+        let connection = Connection::default();
+        assert_eq!(connection.snapshot_metrics().latency_percentile(0.5), 0.0);
+
+        for elapsed_ms in [2, 3, 4, 20, 700] {
+            connection.record_query(Duration::from_millis(elapsed_ms), false);
+        }
+        connection.record_query(Duration::from_secs(60), true);
+        connection.record_commit(&"main".to_string());
+        connection.record_commit(&"main".to_string());
+        connection.record_commit(&"feature".to_string());
+
+        let metrics: Metrics = connection.snapshot_metrics();
+        assert_eq!(metrics.total_queries, 6);
+        assert_eq!(metrics.errors, 1);
+        assert_eq!(metrics.latency_buckets.iter().sum::<u64>(), 6);
+        // Half the queries took at most 5ms, but the slowest ones took more than 500ms
+        assert_eq!(metrics.latency_percentile(0.5), 5.0);
+        assert_eq!(metrics.latency_percentile(0.8), 1000.0);
+        assert_eq!(metrics.latency_percentile(0.95), f64::INFINITY);
+        assert_eq!(metrics.commits.get("main"), Some(&2));
+        assert_eq!(metrics.commits.get("feature"), Some(&1));
+    }
+
+    #[test]
+    #[serial]
+    fn test_max_connections() {
+        let connection = Connection::with_max_connections(2);
+        fcreate_db_instance(&"test_max_connections");
+
+        let id1 = connection
+            .new_client("user1".to_string(), "user1".to_string(), true)
+            .unwrap();
+        connection
+            .new_client("user2".to_string(), "user2".to_string(), true)
+            .unwrap();
+
+        // The third session is rejected
+        let result = connection.new_client("admin".to_string(), "admin".to_string(), false);
+        assert_eq!(
+            result,
+            Err("Too many connections: the server allows at most 2 sessions".to_string())
+        );
+        assert_eq!(connection.get_clients_readonly().len(), 2);
+
+        // Closing a session frees a slot
+        connection.remove_client(id1).unwrap();
+        connection
+            .new_client("admin".to_string(), "admin".to_string(), false)
+            .unwrap();
+        assert_eq!(connection.get_clients_readonly().len(), 2);
+
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_session_timeout() {
+        let mut connection = Connection::with_max_connections(1);
+        connection.session_timeout = Some(Duration::from_millis(200));
+        fcreate_db_instance(&"test_session_timeout");
+
+        let id = connection
+            .new_client("user1".to_string(), "user1".to_string(), true)
+            .unwrap();
+        let result = connection.new_client("admin".to_string(), "admin".to_string(), false);
+        assert!(result.is_err());
+
+        // A request keeps the session open
+        std::thread::sleep(Duration::from_millis(120));
+        connection.touch_session(&id);
+        std::thread::sleep(Duration::from_millis(120));
+        let result = connection.new_client("admin".to_string(), "admin".to_string(), false);
+        assert!(result.is_err());
+
+        // Once the session has been idle for too long, it's closed and its slot is freed
+        std::thread::sleep(Duration::from_millis(250));
+        connection
+            .new_client("admin".to_string(), "admin".to_string(), false)
+            .unwrap();
+        assert!(connection.get_client(&id).is_err());
+        assert_eq!(connection.get_sessions().len(), 1);
+
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_max_result_columns() {
+        let mut connection = Connection::default();
+        connection.max_result_columns = Some(2);
+        fcreate_db_instance(&"test_max_result_columns");
+
+        let id = connection
+            .new_client("admin".to_string(), "admin".to_string(), false)
+            .unwrap();
+        let user: &mut User = connection.get_client(&id).unwrap();
+        assert_eq!(user.get_max_result_columns(), Some(2));
+        let run = |query: &str, user: &mut User| {
+            query::execute_query(&parse(query, false).unwrap(), user, &query.to_string())
+        };
+        query::execute_update(
+            &parse("CREATE TABLE wide (a INT, b INT, c INT)", true).unwrap(),
+            user,
+            &"".to_string(),
+        )
+        .unwrap();
+
+        // A projection wider than the limit is rejected, however its columns are selected
+        assert!(run("SELECT a, b FROM wide", user).is_ok());
+        for query in ["SELECT * FROM wide", "SELECT a, b, a + c FROM wide"] {
+            assert_eq!(
+                run(query, user),
+                Err("Query returns 3 columns, but at most 2 are allowed".to_string())
+            );
+        }
+
+        // The session can change its own limit
+        query::execute_update(
+            &parse("SET max_result_columns = none", true).unwrap(),
+            user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(run("SELECT * FROM wide", user).is_ok());
+
+        delete_db_instance().unwrap();
+    }
+
     #[test]
     #[serial]
     fn test_remove_client() {
@@ -204,6 +595,33 @@ mod tests {
         delete_db_instance().unwrap();
     }
 
+    #[test]
+    #[serial]
+    fn test_cancel_client_query() {
+        let connection = Connection::default();
+        // Create a new database instance
+        fcreate_db_instance(&"test_cancel_client_query");
+
+        let id = connection
+            .new_client("admin".to_string(), "admin".to_string(), false)
+            .unwrap();
+        let client: &mut User = connection.get_client(&id).unwrap();
+        assert!(client.check_cancelled().is_ok());
+
+        // Cancelling is seen by the client, and can be cleared for the next query
+        connection.cancel_client_query(&id).unwrap();
+        assert!(client.check_cancelled().is_err());
+        client.clear_cancel();
+        assert!(client.check_cancelled().is_ok());
+
+        assert!(connection
+            .cancel_client_query(&"12345".to_string())
+            .is_err());
+
+        // Delete new database instance
+        delete_db_instance().unwrap();
+    }
+
     #[test]
     #[serial]
     fn test_remove_non_client() {
@@ -5,11 +5,12 @@ use db_connection::*;
 use tonic::{Request, Response, Status};
 
 use crate::executor::query;
-use crate::fileio::databaseio::get_db_instance;
+use crate::fileio::databaseio::{get_db_instance, select_database, DatabaseSelection};
 use crate::parser::parser;
 use crate::server::connection::Connection;
 use crate::user::userdata::*;
 use crate::util::convert::*;
+use crate::util::error::{Error, ErrorKind};
 
 pub mod db_connection {
     tonic::include_proto!("db_connection");
@@ -36,22 +37,8 @@ impl DatabaseConnection for Connection {
     async fn disconnect_db(&self, request: Request<ConnectResult>) -> Result<Response<()>, Status> {
         let connect_res: ConnectResult = request.into_inner();
 
-        // Delete the temp branch directory in it's own scope to prevent issues when removing the client
-        {
-            // Get the user that is disconnecting
-            let user: &mut User = self
-                .get_client(&connect_res.id)
-                .map_err(|e| Status::internal(e))?;
-
-            // If the user is on a temp branch, then we need to delete it.
-            if user.is_on_temp_commit() {
-                get_db_instance()
-                    .map_err(|e| Status::internal(e))?
-                    .delete_temp_branch_directory(user)
-                    .map_err(|e| Status::internal(e))?;
-            }
-        }
-        self.remove_client(connect_res.id)
+        // A transaction that wasn't committed is rolled back when its session ends
+        self.close_client(&connect_res.id)
             .map_err(|e| Status::internal(e))?;
         Ok(Response::new(()))
     }
@@ -60,7 +47,121 @@ impl DatabaseConnection for Connection {
         &self,
         request: Request<QueryRequest>,
     ) -> Result<Response<QueryResult>, Status> {
+        let start_time: Instant = Instant::now();
+        let result = self.execute_query_request(request.into_inner());
+        self.record_query(start_time.elapsed(), result.is_err());
+        result.map(Response::new)
+    }
+
+    async fn run_update(
+        &self,
+        request: Request<QueryRequest>,
+    ) -> Result<Response<UpdateResult>, Status> {
+        let start_time: Instant = Instant::now();
+        let result = self.execute_update_request(request.into_inner());
+        self.record_query(start_time.elapsed(), result.is_err());
+        result.map(Response::new)
+    }
+
+    /// Returns counters about the queries and commits that the server has run since it started.
+    async fn get_metrics(&self, _request: Request<()>) -> Result<Response<MetricsResult>, Status> {
+        Ok(Response::new(to_metrics_result(
+            &self.snapshot_metrics(),
+            self.get_active_sessions(),
+        )))
+    }
+
+    /// Cancels the query that the client is currently running.
+    /// The running query stops at its next row and returns a cancelled status.
+    async fn cancel_query(&self, request: Request<ConnectResult>) -> Result<Response<()>, Status> {
+        let connect_res: ConnectResult = request.into_inner();
+        self.cancel_client_query(&connect_res.id)
+            .map_err(|e| Status::internal(e))?;
+        Ok(Response::new(()))
+    }
+
+    /// This is a method that gets run every time the client runs a version control command.
+    /// It automatically parses the command, executes it, and returns the result.
+    async fn run_version_control_command(
+        &self,
+        request: Request<QueryRequest>,
+    ) -> Result<Response<VersionControlResult>, Status> {
         let request = request.into_inner();
+
+        // Get the user that is running the query
+        let user: &mut User = self.validate_request(&request)?;
+
+        // Version control commands work on the user's diffs, which a transaction still owns
+        if user.get_transaction_start().is_some() {
+            return Err(Status::failed_precondition(
+                "Commit or roll back the open transaction before running version control commands",
+            ));
+        }
+
+        let all_users: Vec<User> = self.get_clients_readonly();
+        let branch_name: String = user.get_current_branch_name();
+
+        /* VC Command Pipeline Begins Here */
+        // Sessions are kept by the server, so it lists them itself
+        let result = if parser::is_sessions_cmd(&request.query) {
+            self.list_sessions(user)
+        } else {
+            parser::parse_vc_cmd(&request.query, user, all_users)
+        };
+
+        // In case a user switched to a new branch, we want the db to remove any directories that are not needed.
+        let _database: DatabaseSelection = select_database(user.get_database_name());
+        let in_use_branch_names: Vec<String> = self.get_all_branches_clients_are_connected_to();
+        get_db_instance()
+            .map_err(|e| Status::internal(e))?
+            .remove_unneeded_branch_directories(&in_use_branch_names)
+            .map_err(|e| Status::internal(e))?;
+
+        // Commits are counted on the branch that they were made on
+        if let Ok(message) = &result {
+            if message.starts_with("Commit created on branch") {
+                self.record_commit(&branch_name);
+            }
+        }
+
+        /* Creating Result */
+        match result {
+            Ok(value) => Ok(Response::new(to_vc_cmd_result(value))),
+            Err(err) => Err(to_status(err)),
+        }
+    }
+}
+
+/// Gives the gRPC status for an error from running a statement or a version control command,
+/// based on its kind
+fn to_status(err: Error) -> Status {
+    match err.kind {
+        ErrorKind::InvalidArgument => Status::invalid_argument(err.message),
+        ErrorKind::NotFound => Status::not_found(err.message),
+        ErrorKind::PermissionDenied => Status::permission_denied(err.message),
+        ErrorKind::FailedPrecondition => Status::failed_precondition(err.message),
+        ErrorKind::Internal => Status::internal(err.message),
+    }
+}
+
+impl Connection {
+    // Checks that a request has a query and comes from a connected client, returning the client
+    fn validate_request<'a>(&self, request: &QueryRequest) -> Result<&'a mut User, Status> {
+        if request.query.trim().is_empty() {
+            return Err(Status::invalid_argument("The query is empty"));
+        }
+        let user: &mut User = self
+            .get_client(&request.id)
+            .map_err(|_| Status::not_found(format!("No session with id {}", request.id)))?;
+        self.touch_session(&request.id);
+        Ok(user)
+    }
+
+    // Runs a query for a client, returning its rows
+    fn execute_query_request(&self, request: QueryRequest) -> Result<QueryResult, Status> {
+        // Get the user that is running the query
+        let user: &mut User = self.validate_request(&request)?;
+
         /* SQL Pipeline Begins Here */
         // Instead of having the result type be checked each time, it's checked once here.
         // Hence, future functions will get a Result<T, String> argument, but accessing the
@@ -69,44 +170,48 @@ impl DatabaseConnection for Connection {
         /* Creating Result */
         match result {
             Ok(tree) => {
-                // Get the user that is running the query
-                let user: &mut User = self
-                    .get_client(&request.id)
-                    .map_err(|e| Status::internal(e))?;
+                // Forget about any cancel request made before this query started
+                user.clear_cancel();
+
                 // Record the current time that the query was run.
                 let start_time: Instant = Instant::now();
 
                 // Execute the query represented by the AST.
-                let data = query::execute_query(&tree, user, &request.query)
-                    .map_err(|e| Status::internal(e))?;
+                let data =
+                    query::execute_query_with_types(&tree, user, &request.query).map_err(|e| {
+                        if user.check_cancelled().is_err() {
+                            Status::cancelled(e)
+                        } else {
+                            to_status(e)
+                        }
+                    })?;
 
                 // Record the time that the query finished running.
                 let duration: Duration = Instant::now() - start_time;
 
-                Ok(Response::new(to_query_result(
+                Ok(to_query_result(
                     data.0,
                     data.1,
+                    data.2,
                     duration.as_secs_f64() as f32,
-                )))
+                ))
             }
-            Err(err) => Err(Status::cancelled(&err)),
+            Err(err) => Err(Status::invalid_argument(err)),
         }
     }
 
-    async fn run_update(
-        &self,
-        request: Request<QueryRequest>,
-    ) -> Result<Response<UpdateResult>, Status> {
-        let request = request.into_inner();
+    // Runs an update for a client, returning its message
+    fn execute_update_request(&self, request: QueryRequest) -> Result<UpdateResult, Status> {
+        // Get the user that is running the query
+        let user: &mut User = self.validate_request(&request)?;
+
         /* SQL Pipeline Begins Here */
         let result = parser::parse(&request.query, true);
         /* Creating Result */
         match result {
             Ok(tree) => {
-                // Get the user that is running the query
-                let user: &mut User = self
-                    .get_client(&request.id)
-                    .map_err(|e| Status::internal(e))?;
+                // The temp branch is made in the database that the user has selected
+                let _database: DatabaseSelection = select_database(user.get_database_name());
 
                 // If the user is not on a temp branch, then we need to create a new one.
                 if user.is_on_temp_commit() == false {
@@ -118,50 +223,14 @@ impl DatabaseConnection for Connection {
                 // Record the current time that the query was run.
                 let start_time: Instant = Instant::now();
 
-                let resp = query::execute_update(&tree, user, &request.query)
-                    .map_err(|e| Status::internal(e))?;
+                let resp = query::execute_update(&tree, user, &request.query).map_err(to_status)?;
 
                 // Record the time that the query finished running.
                 let duration: Duration = Instant::now() - start_time;
 
-                Ok(Response::new(to_update_result(
-                    resp,
-                    duration.as_secs_f64() as f32,
-                )))
+                Ok(to_update_result(resp, duration.as_secs_f64() as f32))
             }
-            Err(err) => Err(Status::cancelled(&err)),
-        }
-    }
-
-    /// This is a method that gets run every time the client runs a version control command.
-    /// It automatically parses the command, executes it, and returns the result.
-    async fn run_version_control_command(
-        &self,
-        request: Request<QueryRequest>,
-    ) -> Result<Response<VersionControlResult>, Status> {
-        let request = request.into_inner();
-
-        // Get the user that is running the query
-        let user: &mut User = self
-            .get_client(&request.id)
-            .map_err(|e| Status::internal(e))?;
-
-        let all_users: Vec<User> = self.get_clients_readonly();
-
-        /* VC Command Pipeline Begins Here */
-        let result = parser::parse_vc_cmd(&request.query, user, all_users);
-
-        // In case a user switched to a new branch, we want the db to remove any directories that are not needed.
-        let in_use_branch_names: Vec<String> = self.get_all_branches_clients_are_connected_to();
-        get_db_instance()
-            .map_err(|e| Status::internal(e))?
-            .remove_unneeded_branch_directories(&in_use_branch_names)
-            .map_err(|e| Status::internal(e))?;
-
-        /* Creating Result */
-        match result {
-            Ok(value) => Ok(Response::new(to_vc_cmd_result(value))),
-            Err(err) => Err(Status::cancelled(&err)),
+            Err(err) => Err(Status::invalid_argument(err)),
         }
     }
 }
@@ -173,6 +242,9 @@ mod tests {
 
     // This import's needed, probably a bug in the language server.
     use super::*;
+    use crate::fileio::databaseio::delete_db_instance;
+    use crate::user::usercreds::UserPermissions::Read;
+    use tonic::Code;
     // Tests to test async functions
     #[tokio::test]
     #[serial]
@@ -342,4 +414,275 @@ mod tests {
         let result = conn.disconnect_db(Request::new(request)).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn get_metrics() {
+        let conn = Connection::default();
+        let result = conn
+            .connect_db(Request::new(LoginRequest {
+                username: "admin".to_string(),
+                password: "admin".to_string(),
+                create: false,
+            }))
+            .await;
+        let id = result.unwrap().into_inner().id;
+        let queries: Vec<(&str, bool)> = vec![
+            ("CREATE TABLE metrics_test (id INT);", true),
+            ("INSERT INTO metrics_test VALUES (1), (2);", true),
+            ("SELECT * FROM metrics_test;", false),
+            ("SELECT * FROM missing_table;", false),
+        ];
+        for (query, is_update) in queries {
+            let request = Request::new(super::QueryRequest {
+                id: id.clone(),
+                query: query.to_string(),
+            });
+            if is_update {
+                conn.run_update(request).await.unwrap();
+            } else {
+                let _ = conn.run_query(request).await;
+            }
+        }
+        let metrics: MetricsResult = conn
+            .get_metrics(Request::new(()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(metrics.total_queries, 4);
+        assert_eq!(metrics.errors, 1);
+        assert_eq!(metrics.latency_buckets.iter().sum::<u64>(), 4);
+        assert!(metrics.latency_p50_ms <= metrics.latency_p95_ms);
+        assert_eq!(metrics.active_sessions, 1);
+        assert!(metrics.commits_per_branch.is_empty());
+
+        let request = ConnectResult { id };
+        let result = conn.disconnect_db(Request::new(request)).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn transaction_across_requests() {
+        let conn = Connection::default();
+        let result = conn
+            .connect_db(Request::new(LoginRequest {
+                username: "admin".to_string(),
+                password: "admin".to_string(),
+                create: false,
+            }))
+            .await;
+        let id = result.unwrap().into_inner().id;
+        let request = |query: &str| {
+            Request::new(super::QueryRequest {
+                id: id.clone(),
+                query: query.to_string(),
+            })
+        };
+        conn.run_update(request("CREATE TABLE transaction_test (id INT);"))
+            .await
+            .unwrap();
+
+        // Each statement is its own request, but they're all part of one transaction
+        for query in [
+            "BEGIN;",
+            "INSERT INTO transaction_test VALUES (1);",
+            "INSERT INTO transaction_test VALUES (2);",
+        ] {
+            conn.run_update(request(query)).await.unwrap();
+        }
+        let count = || async {
+            conn.run_query(request("SELECT * FROM transaction_test;"))
+                .await
+                .unwrap()
+                .into_inner()
+                .row_values
+                .len()
+        };
+        assert_eq!(count().await, 2);
+        conn.run_update(request("ROLLBACK;")).await.unwrap();
+        assert_eq!(count().await, 0);
+
+        for query in [
+            "BEGIN;",
+            "INSERT INTO transaction_test VALUES (3);",
+            "COMMIT;",
+        ] {
+            conn.run_update(request(query)).await.unwrap();
+        }
+        assert_eq!(count().await, 1);
+        assert!(conn.run_update(request("COMMIT;")).await.is_err());
+
+        // Once the transaction has ended, its rows can be committed to the branch
+        conn.run_version_control_command(request("gql commit -m transaction_test"))
+            .await
+            .unwrap();
+
+        // Version control commands wait for the transaction to end
+        conn.run_update(request("BEGIN;")).await.unwrap();
+        conn.run_update(request("INSERT INTO transaction_test VALUES (4);"))
+            .await
+            .unwrap();
+        let status: Status = conn
+            .run_version_control_command(request("gql status"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(conn.get_client(&id).unwrap().get_diffs().len(), 1);
+
+        // Disconnecting without a commit rolls the transaction back
+        let result = conn
+            .disconnect_db(Request::new(ConnectResult { id: id.clone() }))
+            .await;
+        assert!(result.is_ok());
+        assert!(conn.get_client(&id).is_err());
+
+        // Only the committed row is left when the user connects again
+        conn.connect_db(Request::new(LoginRequest {
+            username: "admin".to_string(),
+            password: "admin".to_string(),
+            create: false,
+        }))
+        .await
+        .unwrap();
+        assert_eq!(count().await, 1);
+        let result = conn
+            .disconnect_db(Request::new(ConnectResult { id: id.clone() }))
+            .await;
+        assert!(result.is_ok());
+
+        // The commit is kept in the server's database, which the other tests start over with
+        delete_db_instance().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn malformed_requests() {
+        let conn = Connection::default();
+        let result = conn
+            .connect_db(Request::new(LoginRequest {
+                username: "admin".to_string(),
+                password: "admin".to_string(),
+                create: false,
+            }))
+            .await;
+        let id = result.unwrap().into_inner().id;
+        let request = |id: &str, query: &str| {
+            Request::new(super::QueryRequest {
+                id: id.to_string(),
+                query: query.to_string(),
+            })
+        };
+
+        let status: Status = conn
+            .run_query(request("unknown_session", "SELECT * FROM test;"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+        let status: Status = conn
+            .run_update(request("unknown_session", "CREATE TABLE test (id INT);"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        for query in ["", "   "] {
+            let status: Status = conn.run_query(request(&id, query)).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+            let status: Status = conn.run_update(request(&id, query)).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+        }
+        let status: Status = conn
+            .run_query(request(&id, "ABCD INCORRECT QUERY;"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let status: Status = conn
+            .run_update(request(&id, "DROP TABLE malformed_missing;"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        // Version control commands get the same codes
+        for (query, code) in [
+            ("gql unknown_command", Code::InvalidArgument),
+            ("gql delete malformed_missing", Code::NotFound),
+            ("gql commit -m message", Code::FailedPrecondition),
+        ] {
+            let status: Status = conn
+                .run_version_control_command(request(&id, query))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), code);
+        }
+
+        // A read only session can't create tables
+        conn.get_client(&id).unwrap().set_permissions(&Read);
+        let status: Status = conn
+            .run_update(request(&id, "CREATE TABLE malformed_test (id INT);"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        let result = conn.disconnect_db(Request::new(ConnectResult { id })).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn list_sessions() {
+        let conn = Connection::default();
+        let connect = |username: &str, create: bool| {
+            conn.connect_db(Request::new(LoginRequest {
+                username: username.to_string(),
+                password: username.to_string(),
+                create,
+            }))
+        };
+        let admin_id = connect("admin", false).await.unwrap().into_inner().id;
+        let user_id = connect("sessions_user", true)
+            .await
+            .unwrap()
+            .into_inner()
+            .id;
+        let request = |id: &String, query: &str| {
+            Request::new(super::QueryRequest {
+                id: id.clone(),
+                query: query.to_string(),
+            })
+        };
+
+        // The second session moves onto its own branch
+        conn.run_version_control_command(request(&user_id, "gql branch sessions_branch"))
+            .await
+            .unwrap();
+
+        let sessions: String = conn
+            .run_version_control_command(request(&admin_id, "gql sessions"))
+            .await
+            .unwrap()
+            .into_inner()
+            .message;
+        let lines: Vec<&str> = sessions.lines().collect();
+        let admin_line: &str = lines.iter().find(|line| line.contains("admin")).unwrap();
+        assert!(admin_line.contains("main"));
+        let user_line: &str = lines
+            .iter()
+            .find(|line| line.contains("sessions_user"))
+            .unwrap();
+        assert!(user_line.contains("sessions_branch"));
+
+        // Only admins can list the sessions
+        let status: Status = conn
+            .run_version_control_command(request(&user_id, "gql sessions"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert!(status.message().contains("do not have permission"));
+
+        for id in [admin_id, user_id] {
+            let result = conn.disconnect_db(Request::new(ConnectResult { id })).await;
+            assert!(result.is_ok());
+        }
+        assert!(conn.get_sessions().is_empty());
+    }
 }
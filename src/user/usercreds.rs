@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     fileio::{
         databaseio,
         header::{write_header, Header},
         pageio::*,
+        storage::get_storage_backend,
         tableio::Table,
     },
     util::{
@@ -77,7 +78,7 @@ impl UserCREDs {
         }
 
         if create_file {
-            std::fs::File::create(filepath.clone()).map_err(|e| e.to_string())?;
+            get_storage_backend(&filepath).create_file(&filepath)?;
 
             let schema = vec![
                 ("username".to_string(), Column::String(32)),
@@ -89,6 +90,9 @@ impl UserCREDs {
                 num_pages: 2,
                 schema,
                 index_top_level_pages: HashMap::new(),
+                unique_indexes: HashSet::new(),
+                ttl: None,
+                version: 0,
             };
             write_header(&filepath, &header)?;
 
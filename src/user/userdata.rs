@@ -1,5 +1,14 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
 use crate::{
     fileio::databaseio::*,
+    util::dbtype::{Collation, StringOverflowPolicy},
     version_control::{
         branches::{BranchNode, Branches},
         diff::Diff,
@@ -8,6 +17,58 @@ use crate::{
 
 use super::usercreds::UserPermissions;
 
+thread_local! {
+    // The (user id, branch name) of the session whose statement is running on this thread
+    static SELECTED_SESSION: RefCell<Option<(String, String)>> = const { RefCell::new(None) };
+    // The generator that RANDOM() takes its numbers from, for the statement running on this thread
+    static SELECTED_RNG: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Keeps a session selected on the current thread, until it's dropped
+pub struct SessionSelection {
+    previous: Option<(String, String)>,
+    previous_rng: Option<StdRng>,
+}
+
+impl Drop for SessionSelection {
+    fn drop(&mut self) {
+        let previous: Option<(String, String)> = self.previous.take();
+        SELECTED_SESSION.with(|selected| *selected.borrow_mut() = previous);
+        let previous_rng: Option<StdRng> = self.previous_rng.take();
+        SELECTED_RNG.with(|selected| *selected.borrow_mut() = previous_rng);
+    }
+}
+
+/// Selects the user whose id and branch CURRENT_USER() and CURRENT_BRANCH() return on this
+/// thread, until the returned selection is dropped.
+/// RANDOM() starts over from the user's sample seed if they set one, so a statement that's run
+/// again gives the same numbers.
+pub fn select_session(user: &User) -> SessionSelection {
+    let session: (String, String) = (user.get_user_id(), user.get_current_branch_name());
+    let previous: Option<(String, String)> =
+        SELECTED_SESSION.with(|selected| selected.replace(Some(session)));
+    let rng: StdRng = match user.get_sample_seed() {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let previous_rng: Option<StdRng> = SELECTED_RNG.with(|selected| selected.replace(Some(rng)));
+    SessionSelection {
+        previous,
+        previous_rng,
+    }
+}
+
+/// Gets the (user id, branch name) of the session selected on this thread, if there is one
+pub fn get_selected_session() -> Option<(String, String)> {
+    SELECTED_SESSION.with(|selected| selected.borrow().clone())
+}
+
+/// Gets the next random number from 0 up to 1 for the session selected on this thread,
+/// if there is one
+pub fn next_selected_random() -> Option<f32> {
+    SELECTED_RNG.with(|selected| selected.borrow_mut().as_mut().map(|rng| rng.gen::<f32>()))
+}
+
 /*#[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum UserPermissions {
     Read,
@@ -26,19 +87,50 @@ pub struct User {
     commands: Vec<String>, // The commands that the user has executed that are in an uncommitted state
     branch_head: Option<BranchNode>, // The commit id of the head of the branch that the user is currently on
     user_permissions: UserPermissions, // The user's abiltiy to read / write to a database
+    cancel_requested: Arc<AtomicBool>, // Whether the user asked to cancel their running query (shared between clones)
+    string_overflow_policy: StringOverflowPolicy, // What happens when the user writes a string that's too long for its column
+    sample_seed: Option<u64>, // The seed used by TABLESAMPLE and RANDOM(), so samples can be repeated. Random if None
+    prefer_index_scans: bool, // Whether to scan an index whenever one fits part of a where clause
+    collation: Collation,     // How strings are compared when rows are sorted or grouped
+    safe_updates: bool,       // Whether updates and deletes need a where clause
+    alter_dry_run: bool, // Whether ALTER TABLE only checks the change against the rows, without making it
+    restrict_column_drops: bool, // Whether dropping a column that's indexed needs CASCADE to drop its indexes
+    database_name: Option<String>, // The database picked with USE DATABASE, or None for the default one
+    cte_tables: Vec<(String, String)>, // The (name, directory) of each table from the query's WITH clauses
+    transaction_start: Option<(usize, usize)>, // The number of diffs and commands when the open transaction began
+    max_result_columns: Option<usize>, // The most columns a query can return, or None if unlimited
+    max_merge_conflicts: Option<usize>, // The most conflicts a clean merge reports, or None for all of them
 }
 
 impl User {
     /// Create a new user with the given id, which defaults to the main branch
     pub fn new(user_id: String) -> Self {
+        // Use the main branch of the open database, if there is one
+        let branch_name: String = match get_db_instance() {
+            Ok(db) => db.get_main_branch_name(),
+            Err(_) => MAIN_BRANCH_NAME.to_string(),
+        };
         Self {
             user_id: user_id,
-            branch_name: MAIN_BRANCH_NAME.to_string(),
+            branch_name: branch_name,
             is_on_temp_commit: false,
             diffs: Vec::new(),
             commands: Vec::new(),
             branch_head: None,
             user_permissions: UserPermissions::ReadAndWrite,
+            cancel_requested: Arc::new(AtomicBool::new(false)),
+            string_overflow_policy: StringOverflowPolicy::Error,
+            sample_seed: None,
+            prefer_index_scans: false,
+            collation: Collation::Binary,
+            safe_updates: false,
+            alter_dry_run: false,
+            restrict_column_drops: false,
+            database_name: None,
+            cte_tables: Vec::new(),
+            transaction_start: None,
+            max_result_columns: None,
+            max_merge_conflicts: Some(1),
         }
     }
 
@@ -57,6 +149,37 @@ impl User {
         self.branch_name = new_branch_name.clone();
     }
 
+    /// Get the name of the database that the user's statements run against.
+    /// None means the default database that the server was started with.
+    pub fn get_database_name(&self) -> Option<String> {
+        self.database_name.clone()
+    }
+
+    /// Set the database that the user's statements run against, None being the default one
+    pub fn set_database_name(&mut self, database_name: Option<String>) {
+        self.database_name = database_name;
+    }
+
+    /// Get the (name, directory) of every table defined by the WITH clauses of the
+    /// user's running query. Later tables shadow earlier ones with the same name.
+    pub fn get_cte_tables(&self) -> Vec<(String, String)> {
+        self.cte_tables.clone()
+    }
+
+    /// Get the directory of the WITH table with the given name, if the user's query defines one
+    pub fn get_cte_table_dir(&self, table_name: &String) -> Option<String> {
+        self.cte_tables
+            .iter()
+            .rev()
+            .find(|(name, _)| name == table_name)
+            .map(|(_, dir)| dir.clone())
+    }
+
+    /// Replaces the tables defined by the WITH clauses of the user's running query
+    pub fn set_cte_tables(&mut self, cte_tables: &Vec<(String, String)>) {
+        self.cte_tables = cte_tables.clone();
+    }
+
     /// Get the list of diffs that the user has made
     pub fn get_diffs(&self) -> Vec<Diff> {
         self.diffs.clone()
@@ -97,6 +220,128 @@ impl User {
         self.user_permissions = permissions.clone();
     }
 
+    /// Get what happens when the user writes a string that's too long for its column
+    pub fn get_string_overflow_policy(&self) -> StringOverflowPolicy {
+        self.string_overflow_policy.clone()
+    }
+
+    /// Set what happens when the user writes a string that's too long for its column
+    pub fn set_string_overflow_policy(&mut self, policy: &StringOverflowPolicy) {
+        self.string_overflow_policy = policy.clone();
+    }
+
+    /// Get the seed used when sampling tables, if the user set one
+    pub fn get_sample_seed(&self) -> Option<u64> {
+        self.sample_seed
+    }
+
+    /// Set the seed used when sampling tables, or None to sample randomly
+    pub fn set_sample_seed(&mut self, seed: Option<u64>) {
+        self.sample_seed = seed;
+    }
+
+    /// Whether the user prefers index scans over full table scans
+    pub fn get_prefer_index_scans(&self) -> bool {
+        self.prefer_index_scans
+    }
+
+    /// Set whether the user prefers index scans over full table scans
+    pub fn set_prefer_index_scans(&mut self, prefer_index_scans: bool) {
+        self.prefer_index_scans = prefer_index_scans;
+    }
+
+    /// Get how strings are compared when the user's queries sort or group rows
+    pub fn get_collation(&self) -> Collation {
+        self.collation.clone()
+    }
+
+    /// Set how strings are compared when the user's queries sort or group rows
+    pub fn set_collation(&mut self, collation: &Collation) {
+        self.collation = collation.clone();
+    }
+
+    /// Whether the user's updates and deletes are rejected when they have no where clause
+    pub fn get_safe_updates(&self) -> bool {
+        self.safe_updates
+    }
+
+    /// Set whether the user's updates and deletes are rejected when they have no where clause
+    pub fn set_safe_updates(&mut self, safe_updates: bool) {
+        self.safe_updates = safe_updates;
+    }
+
+    /// Whether the user's ALTER TABLE statements are only checked, without changing the table
+    pub fn get_alter_dry_run(&self) -> bool {
+        self.alter_dry_run
+    }
+
+    /// Set whether the user's ALTER TABLE statements are only checked, without changing the table
+    pub fn set_alter_dry_run(&mut self, alter_dry_run: bool) {
+        self.alter_dry_run = alter_dry_run;
+    }
+
+    /// Whether the user's DROP COLUMN is rejected when an index includes the column, unless it
+    /// has CASCADE. Otherwise, those indexes are dropped along with the column.
+    pub fn get_restrict_column_drops(&self) -> bool {
+        self.restrict_column_drops
+    }
+
+    /// Set whether the user's DROP COLUMN is rejected when an index includes the column,
+    /// unless it has CASCADE
+    pub fn set_restrict_column_drops(&mut self, restrict_column_drops: bool) {
+        self.restrict_column_drops = restrict_column_drops;
+    }
+
+    /// The number of diffs and commands the user had when their open transaction began,
+    /// or None if they don't have a transaction open
+    pub fn get_transaction_start(&self) -> Option<(usize, usize)> {
+        self.transaction_start
+    }
+
+    /// Set where the user's open transaction began, or None to close it
+    pub fn set_transaction_start(&mut self, transaction_start: Option<(usize, usize)>) {
+        self.transaction_start = transaction_start;
+    }
+
+    /// The most columns that the user's queries can return, or None if there's no limit
+    pub fn get_max_result_columns(&self) -> Option<usize> {
+        self.max_result_columns
+    }
+
+    /// Set the most columns that the user's queries can return, or None to remove the limit
+    pub fn set_max_result_columns(&mut self, max_result_columns: Option<usize>) {
+        self.max_result_columns = max_result_columns;
+    }
+
+    /// The most conflicts that the user's clean merges find before they stop, or None to find them all
+    pub fn get_max_merge_conflicts(&self) -> Option<usize> {
+        self.max_merge_conflicts
+    }
+
+    /// Set the most conflicts that the user's clean merges find before they stop, or None to find them all
+    pub fn set_max_merge_conflicts(&mut self, max_merge_conflicts: Option<usize>) {
+        self.max_merge_conflicts = max_merge_conflicts;
+    }
+
+    /// Asks for the query this user is running to stop at its next checkpoint.
+    /// Clones of this user share the request, so it can be made from another thread.
+    pub fn request_cancel(&self) {
+        self.cancel_requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Clears any cancel request, which should be done before the user runs a new query
+    pub fn clear_cancel(&self) {
+        self.cancel_requested.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns an error if the user asked to cancel their running query
+    pub fn check_cancelled(&self) -> Result<(), String> {
+        if self.cancel_requested.load(Ordering::SeqCst) {
+            return Err("Query cancelled".to_string());
+        }
+        Ok(())
+    }
+
     /// Returns the id of the commit that was head when the user was last updated
     pub fn get_user_branch_head(&self) -> Option<BranchNode> {
         self.branch_head.clone()
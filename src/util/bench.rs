@@ -9,11 +9,15 @@ use crate::util::{
 };
 use crate::version_control::diff::{Diff, InsertDiff};
 use crate::{
-    executor::query::create_table,
-    fileio::databaseio::{create_db_instance, get_db_instance, Database},
-    user::userdata::User,
+    executor::query::{create_table, execute_query, execute_update},
+    fileio::databaseio::{
+        create_db_instance, delete_db_instance, get_db_instance, load_db_instance, Database,
+    },
+    parser::parser::{parse, ParsedStatement},
+    user::{usercreds::UserPermissions, userdata::User},
     util::row::RowLocation,
 };
+use sqlparser::ast::Statement;
 
 use super::dbtype::parse_time;
 
@@ -513,6 +517,55 @@ pub fn create_huge_bench_db(num_rows: usize, random_values: bool) -> User {
     user
 }
 
+/// Creates a database from a SQL file, by running its statements as the admin user and committing
+/// the result. If the database already exists, it's loaded as it is, so the file only runs on the
+/// first launch. If a statement fails, the half-created database is deleted and the error returned.
+pub fn create_db_from_sql(name: &str, file_path: &str) -> Result<String, String> {
+    if Database::load_db(name.to_string()).is_ok() {
+        load_db_instance(&name.to_string())?;
+        return Ok(format!("Database {} is already initialized", name));
+    }
+    let script: String = std::fs::read_to_string(file_path)
+        .map_err(|e| format!("Could not read {}: {}", file_path, e))?;
+    create_db_instance(&name.to_string())?;
+    let result: Result<String, String> = run_init_script(&script, file_path);
+    if result.is_err() {
+        delete_db_instance()?;
+    }
+    result
+}
+
+// Runs each statement of the script, and commits the changes they made
+fn run_init_script(script: &str, file_path: &str) -> Result<String, String> {
+    let mut user: User = User::new("admin".to_string());
+    user.set_permissions(&UserPermissions::Admin);
+    let statements: Vec<ParsedStatement> = parse(script, true)?;
+    for (i, parsed) in statements.iter().enumerate() {
+        let ast: Vec<ParsedStatement> = vec![parsed.clone()];
+        let statement: &Statement = &parsed.statement;
+        let result: Result<(), String> = match statement {
+            Statement::Query(_) => {
+                execute_query(&ast, &mut user, &statement.to_string()).map(|_| ())
+            }
+            _ => execute_update(&ast, &mut user, &statement.to_string())
+                .map(|_| ())
+                .map_err(String::from),
+        };
+        result.map_err(|e| format!("Statement {} of {} failed: {}", i + 1, file_path, e))?;
+    }
+    get_db_instance()?.create_commit_on_head(
+        &format!("Initialized from {}", file_path),
+        &script.to_string(),
+        &mut user,
+        None,
+    )?;
+    Ok(format!(
+        "Database initialized with {} statements from {}",
+        statements.len(),
+        file_path
+    ))
+}
+
 /// Creates a huge bench database with 2 tables that each have <num_rows> rows
 pub fn create_huge_bench_db_2_tables(
     num_rows: usize,
@@ -592,6 +645,61 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    #[serial]
+    fn test_create_db_from_sql() {
+        let sql_path: &str = "test_create_db_from_sql.sql";
+        std::fs::write(
+            sql_path,
+            "CREATE TABLE teams (id INT, name VARCHAR(20));\n\
+             INSERT INTO teams VALUES (1, 'Red'), (2, 'Blue');\n\
+             SELECT * FROM teams;\n\
+             CREATE TABLE players (id INT, team_id INT);\n",
+        )
+        .unwrap();
+        if let Ok(db) = Database::load_db("test_init_db".to_string()) {
+            db.delete_database().unwrap();
+        }
+
+        let result: String = create_db_from_sql("test_init_db", sql_path).unwrap();
+        assert_eq!(
+            result,
+            format!("Database initialized with 4 statements from {}", sql_path)
+        );
+        let user: User = User::new("test_user".to_string());
+        let db: &mut Database = get_db_instance().unwrap();
+        let mut tables: Vec<String> = db.get_tables(&user).unwrap();
+        tables.sort();
+        assert_eq!(tables, vec!["players".to_string(), "teams".to_string()]);
+        let rows: Vec<Row> = Table::from_user(&user, db, &"teams".to_string(), None)
+            .unwrap()
+            .map(|row_info| row_info.row)
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::I64(1), Value::String("Red".to_string())],
+                vec![Value::I64(2), Value::String("Blue".to_string())],
+            ]
+        );
+
+        // The file only runs when the database is first created
+        let result: String = create_db_from_sql("test_init_db", sql_path).unwrap();
+        assert_eq!(result, "Database test_init_db is already initialized");
+        delete_db_instance().unwrap();
+
+        // A failing statement stops the startup, and doesn't leave a half-made database behind
+        std::fs::write(
+            sql_path,
+            "CREATE TABLE teams (id INT);\nINSERT INTO missing VALUES (1);\n",
+        )
+        .unwrap();
+        let err: String = create_db_from_sql("test_init_db", sql_path).unwrap_err();
+        assert!(err.starts_with(&format!("Statement 2 of {} failed", sql_path)));
+        assert!(Database::load_db("test_init_db".to_string()).is_err());
+        std::fs::remove_file(sql_path).unwrap();
+    }
+
     #[test]
     #[serial]
     fn test_bench() {
@@ -0,0 +1,87 @@
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+// Replaces the system clock when set, so tests can control the time
+static CLOCK: RwLock<Option<Box<dyn Clock>>> = RwLock::new(None);
+
+/// Where the database gets the current time from
+pub trait Clock: Send + Sync {
+    /// The current time, as the number of milliseconds since the epoch
+    fn now_millis(&self) -> u128;
+}
+
+/// The clock used by default, which reads the system time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis()
+    }
+}
+
+/// Reads the time from the given clock, or from the system clock if None
+pub fn set_clock(clock: Option<Box<dyn Clock>>) {
+    *CLOCK.write().unwrap_or_else(|e| e.into_inner()) = clock;
+}
+
+/// The current time, as the number of milliseconds since the epoch.
+/// It reads the clock that was set if there is one, otherwise the system clock.
+pub fn now_millis() -> u128 {
+    match CLOCK.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(clock) => clock.now_millis(),
+        None => SystemClock.now_millis(),
+    }
+}
+
+/// A clock that tests move by hand. It replaces the clock while it's alive, and the system clock
+/// is put back when it's dropped, even if the test panics.
+#[cfg(test)]
+pub struct TestClock {
+    millis: Arc<AtomicU64>,
+}
+
+#[cfg(test)]
+struct SharedClock(Arc<AtomicU64>);
+
+#[cfg(test)]
+impl Clock for SharedClock {
+    fn now_millis(&self) -> u128 {
+        self.0.load(Ordering::SeqCst) as u128
+    }
+}
+
+#[cfg(test)]
+impl TestClock {
+    /// Replaces the clock with one that starts at the given time
+    pub fn start_at(millis: u64) -> TestClock {
+        let millis: Arc<AtomicU64> = Arc::new(AtomicU64::new(millis));
+        set_clock(Some(Box::new(SharedClock(millis.clone()))));
+        TestClock { millis }
+    }
+
+    /// Sets the time, as the number of milliseconds since the epoch
+    pub fn set_millis(&self, millis: u64) {
+        self.millis.store(millis, Ordering::SeqCst);
+    }
+
+    /// Moves the time forward by the given number of seconds
+    pub fn advance_seconds(&self, seconds: u64) {
+        self.millis.fetch_add(seconds * 1000, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestClock {
+    fn drop(&mut self) {
+        set_clock(None);
+    }
+}
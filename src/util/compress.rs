@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+// Matches shorter than this are cheaper to store as literals
+const MIN_MATCH: usize = 3;
+// The length of a match is stored in a single byte, as (length - MIN_MATCH)
+const MAX_MATCH: usize = MIN_MATCH + u8::MAX as usize;
+// The distance back to a match is stored as a u16
+const MAX_DISTANCE: usize = u16::MAX as usize;
+
+/// Compresses the given bytes with a simple LZ77 style scheme.
+/// The output is a series of groups, each starting with a flag byte that says whether
+/// each of the (up to) 8 items after it is a literal byte, or a match of earlier bytes.
+/// A match is stored as a 2 byte distance back into the output, followed by its length.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::new();
+    // The last position that each 3 byte sequence was seen at
+    let mut last_seen: HashMap<[u8; MIN_MATCH], usize> = HashMap::new();
+
+    let mut flag_pos: usize = 0;
+    let mut num_items: u8 = 8;
+    let mut pos: usize = 0;
+    while pos < data.len() {
+        // Start a new group every 8 items
+        if num_items == 8 {
+            flag_pos = output.len();
+            output.push(0);
+            num_items = 0;
+        }
+
+        let mut match_len: usize = 0;
+        let mut match_distance: usize = 0;
+        if pos + MIN_MATCH <= data.len() {
+            let key: [u8; MIN_MATCH] = [data[pos], data[pos + 1], data[pos + 2]];
+            if let Some(&start) = last_seen.get(&key) {
+                if pos - start <= MAX_DISTANCE {
+                    let max_len: usize = MAX_MATCH.min(data.len() - pos);
+                    while match_len < max_len && data[start + match_len] == data[pos + match_len] {
+                        match_len += 1;
+                    }
+                    match_distance = pos - start;
+                }
+            }
+            last_seen.insert(key, pos);
+        }
+
+        if match_len >= MIN_MATCH {
+            output[flag_pos] |= 1 << num_items;
+            output.extend_from_slice(&(match_distance as u16).to_le_bytes());
+            output.push((match_len - MIN_MATCH) as u8);
+            // Remember the sequences within the match, so later data can refer to them
+            for i in pos + 1..(pos + match_len).min(data.len() - MIN_MATCH + 1) {
+                last_seen.insert([data[i], data[i + 1], data[i + 2]], i);
+            }
+            pos += match_len;
+        } else {
+            output.push(data[pos]);
+            pos += 1;
+        }
+        num_items += 1;
+    }
+    output
+}
+
+/// Decompresses bytes that were compressed with `compress`.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut pos: usize = 0;
+    while pos < data.len() {
+        let flags: u8 = data[pos];
+        pos += 1;
+        for i in 0..8 {
+            if pos >= data.len() {
+                break;
+            }
+            if flags & (1 << i) == 0 {
+                output.push(data[pos]);
+                pos += 1;
+                continue;
+            }
+
+            if pos + 3 > data.len() {
+                return Err("Compressed data is truncated".to_string());
+            }
+            let distance: usize = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+            let length: usize = data[pos + 2] as usize + MIN_MATCH;
+            pos += 3;
+            if distance == 0 || distance > output.len() {
+                return Err("Compressed data is corrupted".to_string());
+            }
+            // Copy byte by byte, since a match can overlap the bytes it produces
+            let start: usize = output.len() - distance;
+            for j in 0..length {
+                output.push(output[start + j]);
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_round_trip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            b"a".to_vec(),
+            b"abcabcabcabcabcabcabcabcabcabc".to_vec(),
+            vec![0u8; 10000],
+            (0..20000u32).map(|i| (i * 7919 % 251) as u8).collect(),
+            "The quick brown fox jumps over the lazy dog. "
+                .repeat(100)
+                .into_bytes(),
+        ];
+        for input in inputs {
+            let compressed: Vec<u8> = compress(&input);
+            assert_eq!(decompress(&compressed).unwrap(), input);
+        }
+
+        // Repetitive data gets a lot smaller
+        let zeros: Vec<u8> = vec![0u8; 10000];
+        assert!(compress(&zeros).len() < zeros.len() / 10);
+    }
+
+    #[test]
+    fn test_decompress_corrupted() {
+        // A match that points back before the start of the output
+        assert!(decompress(&[1, 5, 0, 0]).is_err());
+        // A match that is cut off
+        assert!(decompress(&[1, 5]).is_err());
+    }
+}
@@ -1,5 +1,6 @@
-use super::dbtype::{Column, Value};
+use super::dbtype::{format_time_of_day, Column, Value};
 use super::row::Row;
+use crate::server::connection::Metrics;
 use crate::server::server::db_connection::cell_value::CellType::*;
 use crate::server::server::db_connection::*;
 
@@ -8,14 +9,31 @@ pub fn to_connect_result(id: String) -> ConnectResult {
     ConnectResult { id }
 }
 
-pub fn to_query_result(schema: Vec<String>, row_values: Vec<Row>, time_taken: f32) -> QueryResult {
+pub fn to_query_result(
+    schema: Vec<String>,
+    column_types: Vec<Column>,
+    row_values: Vec<Row>,
+    time_taken: f32,
+) -> QueryResult {
     QueryResult {
         column_names: schema.into_iter().map(|x| x).collect(),
         row_values: row_values.into_iter().map(to_row_value).collect(),
         time_taken,
+        column_types: column_types
+            .iter()
+            .map(|x| x.encode_type() as u32)
+            .collect(),
     }
 }
 
+/// Decodes the column types of a QueryResult.
+pub fn from_column_types(column_types: &Vec<u32>) -> Vec<Column> {
+    column_types
+        .iter()
+        .map(|x| Column::decode_type(*x as u16))
+        .collect()
+}
+
 pub fn to_update_result(message: String, time_taken: f32) -> UpdateResult {
     UpdateResult {
         message,
@@ -23,6 +41,20 @@ pub fn to_update_result(message: String, time_taken: f32) -> UpdateResult {
     }
 }
 
+/// Converts the server's metrics into a MetricsResult that is suitable to be
+/// returned to the client.
+pub fn to_metrics_result(metrics: &Metrics, active_sessions: usize) -> MetricsResult {
+    MetricsResult {
+        total_queries: metrics.total_queries,
+        errors: metrics.errors,
+        latency_p50_ms: metrics.latency_percentile(0.5),
+        latency_p95_ms: metrics.latency_percentile(0.95),
+        latency_buckets: metrics.latency_buckets.clone(),
+        active_sessions: active_sessions as u64,
+        commits_per_branch: metrics.commits.clone().into_iter().collect(),
+    }
+}
+
 /// Converts the parameters into a VersionControlResult that is suitable to be
 /// returned to the client.
 pub fn to_vc_cmd_result(message: String) -> VersionControlResult {
@@ -37,7 +69,7 @@ pub fn to_row_value(row_values: Vec<Value>) -> RowValue {
 
 pub fn to_value(value: Value) -> CellValue {
     match value {
-        Value::String(s) => CellValue {
+        Value::String(s) | Value::Char(s) => CellValue {
             cell_type: Some(ColString { 0: s }),
         },
         Value::I32(i) => CellValue {
@@ -49,6 +81,12 @@ pub fn to_value(value: Value) -> CellValue {
         Value::Timestamp(t) => CellValue {
             cell_type: Some(ColTime { 0: t }),
         },
+        // Times of day are sent as their HH:MM:SS text
+        Value::Time(t) => CellValue {
+            cell_type: Some(ColString {
+                0: format_time_of_day(t),
+            }),
+        },
         Value::I64(i) => CellValue {
             cell_type: Some(ColI64 { 0: i }),
         },
@@ -109,9 +147,16 @@ mod tests {
                 Value::String("d".to_string()),
             ],
         ];
-        let result = to_query_result(column_names.clone(), row_values.clone(), 5.6 as f32);
+        let column_types = vec![Column::String(1), Column::Nullable(Box::new(Column::I64))];
+        let result = to_query_result(
+            column_names.clone(),
+            column_types.clone(),
+            row_values.clone(),
+            5.6 as f32,
+        );
         assert_eq!(result.column_names[0], "a");
         assert_eq!(result.column_names[1], "b");
+        assert_eq!(from_column_types(&result.column_types), column_types);
         assert_eq!(
             result.row_values,
             vec![
@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, NaiveTime, Timelike};
 use core::mem::size_of;
 use prost_types::Timestamp;
 use serde::Serialize;
@@ -12,21 +12,61 @@ use crate::fileio::pageio::{read_string, read_type, write_string, write_type, Pa
 #[derive(Debug, Clone)]
 pub enum Value {
     String(String),
+    Char(String), // Always as long as its column, padded with spaces
     I32(i32),
     Float(f32),
     Timestamp(Timestamp),
+    Time(u32), // The number of seconds since midnight
     I64(i64),
     Double(f64),
     Bool(bool),
     Null(Column),
 }
 
+/// What happens when a string is longer than the width of the column it's stored in
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringOverflowPolicy {
+    Error,    // The write fails, naming the column and the lengths
+    Truncate, // The string is cut down to fit the column
+}
+
+/// How strings are compared when rows are sorted or grouped
+#[derive(Debug, Clone, PartialEq)]
+pub enum Collation {
+    Binary,          // By their bytes, so every uppercase letter comes before the lowercase ones
+    CaseInsensitive, // Ignoring the case of ASCII letters, so 'Apple' sorts before 'banana'
+}
+
+impl Collation {
+    /// Compares two values, using the collation if they're both strings
+    pub fn compare(&self, left: &Value, right: &Value) -> Ordering {
+        match self {
+            Collation::Binary => left.cmp(right),
+            Collation::CaseInsensitive => self.key(left.clone()).cmp(&self.key(right.clone())),
+        }
+    }
+
+    /// The value that the given value is equal to under the collation, so values that the
+    /// collation treats as the same can be grouped together
+    pub fn key(&self, value: Value) -> Value {
+        match (self, value) {
+            (Collation::CaseInsensitive, Value::String(x)) => Value::String(x.to_ascii_lowercase()),
+            (Collation::CaseInsensitive, Value::Char(x)) => Value::Char(x.to_ascii_lowercase()),
+            (_, value) => value,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub enum Column {
     // Strings have a given length value (in bytes).
     String(u16),
+    // Fixed length strings, which are padded with spaces to their length (in bytes).
+    // Trailing spaces are ignored when comparing them.
+    Char(u16),
     I32,
     Timestamp,
+    Time, // A time of day, without a date
     I64,
     Float,
     Double,
@@ -48,9 +88,10 @@ impl Column {
             DataType::Double => Column::Double,
             DataType::Boolean => Column::Bool,
             DataType::Timestamp => Column::Timestamp,
-            DataType::Char(Some(size)) => Column::String(size as u16),
+            DataType::Time => Column::Time,
+            DataType::Char(Some(size)) => Column::Char(size as u16),
             DataType::Varchar(Some(size)) => Column::String(size as u16),
-            DataType::Char(None) => Column::String(1),
+            DataType::Char(None) => Column::Char(1),
             DataType::Varchar(None) => Column::String(1),
             _ => Err(format!("Unsupported data type: {}", data_type.data_type))?,
         };
@@ -70,9 +111,10 @@ impl Column {
             DataType::Double => Column::Double,
             DataType::Boolean => Column::Bool,
             DataType::Timestamp => Column::Timestamp,
-            DataType::Char(Some(size)) => Column::String(*size as u16),
+            DataType::Time => Column::Time,
+            DataType::Char(Some(size)) => Column::Char(*size as u16),
             DataType::Varchar(Some(size)) => Column::String(*size as u16),
-            DataType::Char(None) => Column::String(1),
+            DataType::Char(None) => Column::Char(1),
             DataType::Varchar(None) => Column::String(1),
             _ => Err(format!("Unsupported data type: {}", data_type))?,
         };
@@ -91,8 +133,11 @@ impl Column {
                 3 => Column::Double,
                 4 => Column::Bool,
                 5 => Column::Timestamp,
+                6 => Column::Time,
                 // If the second most significant bit is set, then the column is a string.
                 x if x & (1 << 14) != 0 => Column::String((x & !(1 << 14)) as u16),
+                // Otherwise, if the third most significant bit is set, it's a fixed length string.
+                x if x & (1 << 13) != 0 => Column::Char((x & !(1 << 13)) as u16),
                 _ => panic!("Invalid column type"),
             }
         }
@@ -106,7 +151,9 @@ impl Column {
             Column::Double => 3,
             Column::Bool => 4,
             Column::Timestamp => 5,
+            Column::Time => 6,
             Column::String(x) => (1 << 14) | (*x as u16),
+            Column::Char(x) => (1 << 13) | (*x as u16),
             Column::Nullable(x) => (1 << 15) | x.encode_type(),
         }
     }
@@ -144,10 +191,18 @@ impl Column {
                 let val: Timestamp = read_type(page, offset)?;
                 Ok(Value::Timestamp(val))
             }
+            Column::Time => {
+                let val: u32 = read_type(page, offset)?;
+                Ok(Value::Time(val))
+            }
             Column::String(len) => {
                 let val = read_string(page, offset, *len as usize)?;
                 Ok(Value::String(val))
             }
+            Column::Char(len) => {
+                let val = read_string(page, offset, *len as usize)?;
+                Ok(Value::Char(pad_char(&val, *len)))
+            }
             Column::Nullable(x) => {
                 // Check if the value is null.
                 let val: u8 = read_type(page, offset)?;
@@ -168,9 +223,11 @@ impl Column {
             (Column::Double, Value::Double(x)) => write_type(page, offset, *x),
             (Column::Bool, Value::Bool(x)) => write_type(page, offset, *x),
             (Column::Timestamp, Value::Timestamp(x)) => write_type(page, offset, x.clone()),
+            (Column::Time, Value::Time(x)) => write_type(page, offset, *x),
             (Column::String(size), Value::String(x)) => {
                 write_string(page, offset, &x, *size as usize)
             }
+            (Column::Char(size), Value::Char(x)) => write_string(page, offset, &x, *size as usize),
             // Null cases
             (Column::Nullable(_), Value::Null(_)) => write_type(page, offset, 0u8),
             (Column::Nullable(x), y) => {
@@ -197,8 +254,14 @@ impl Column {
             (Column::Double, Value::Double(_)) => Ok(value),
             (Column::Bool, Value::Bool(_)) => Ok(value),
             (Column::Timestamp, Value::Timestamp(_)) => Ok(value),
+            (Column::Time, Value::Time(_)) => Ok(value),
             (Column::String(_), Value::String(_)) => Ok(value),
+            (Column::Char(size), Value::Char(x)) => Ok(Value::Char(pad_char(x, *size))),
             // Type conversions
+            (Column::Char(size), Value::String(x)) => Ok(Value::Char(pad_char(x, *size))),
+            (Column::String(_), Value::Char(x)) => {
+                Ok(Value::String(x.trim_end_matches(' ').to_string()))
+            }
             (Column::I32, Value::I64(x)) => Ok(Value::I32(*x as i32)),
             (Column::I64, Value::I32(x)) => Ok(Value::I64(*x as i64)),
             (Column::Float, Value::Double(x)) => Ok(Value::Float(*x as f32)),
@@ -215,6 +278,7 @@ impl Column {
             (Column::Double, Value::I64(x)) => Ok(Value::Double(*x as f64)),
             // Time stamps
             (Column::Timestamp, Value::String(x)) => Ok(Value::Timestamp(parse_time(x)?)),
+            (Column::Time, Value::String(x)) => Ok(Value::Time(parse_time_of_day(x)?)),
             // Null cases
             (Column::Nullable(x), Value::Null(_)) => Ok(Value::Null(*x.clone())),
             (Column::Nullable(x), _) => x.coerce_type(value),
@@ -233,7 +297,8 @@ impl Column {
             Column::Double => size_of::<f64>(),
             Column::Bool => size_of::<bool>(),
             Column::Timestamp => size_of::<Timestamp>(),
-            Column::String(x) => (*x as usize) * size_of::<u8>(),
+            Column::Time => size_of::<u32>(),
+            Column::String(x) | Column::Char(x) => (*x as usize) * size_of::<u8>(),
             // Add a single byte overhead for the null flag.
             Column::Nullable(x) => size_of::<u8>() + x.size(),
         }
@@ -250,11 +315,13 @@ impl Column {
                     .map_err(|_x| format!("Could not parse value {str} into type Float"))?,
             ),
             Column::String(_) => Value::String(str.clone()),
+            Column::Char(size) => Value::Char(pad_char(str, *size)),
             Column::Bool => Value::Bool(
                 str.parse()
                     .map_err(|_x| format!("Could not parse value {str} into type Bool"))?,
             ),
             Column::Timestamp => Value::Timestamp(parse_time(str)?),
+            Column::Time => Value::Time(parse_time_of_day(str)?),
             Column::I64 => Value::I64(
                 str.parse()
                     .map_err(|_x| format!("Could not parse value {str} into type Int64"))?,
@@ -296,11 +363,51 @@ impl Column {
             Column::Timestamp => {
                 Value::Timestamp(parse_time(&"1970-01-01 00:00:00".to_string()).unwrap())
             }
+            Column::Time => Value::Time(0),
             Column::String(_) => Value::String(String::new()),
+            Column::Char(size) => Value::Char(pad_char("", *size)),
             Column::Nullable(x) => Value::Null(*x.clone()),
         }
     }
 
+    /// Makes sure a string fits within the width of this column, following the given policy.
+    /// Widths are in bytes, so truncating keeps as many whole characters as fit in the column.
+    /// Values that aren't strings are returned as they are.
+    pub fn enforce_length(
+        &self,
+        value: Value,
+        column_name: &String,
+        policy: &StringOverflowPolicy,
+    ) -> Result<Value, String> {
+        match (self, value) {
+            (Column::String(size), Value::String(x)) if x.len() > *size as usize => match policy {
+                StringOverflowPolicy::Error => Err(format!(
+                    "Value for column {} is too long ({} bytes), the maximum is {} bytes",
+                    column_name,
+                    x.len(),
+                    size
+                )),
+                StringOverflowPolicy::Truncate => {
+                    let mut end: usize = *size as usize;
+                    while !x.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    Ok(Value::String(x[..end].to_string()))
+                }
+            },
+            // Trailing spaces never count towards the length of a fixed length string
+            (Column::Char(size), Value::String(x) | Value::Char(x)) => {
+                let trimmed = Value::String(x.trim_end_matches(' ').to_string());
+                match Column::String(*size).enforce_length(trimmed, column_name, policy)? {
+                    Value::String(x) => Ok(Value::Char(pad_char(&x, *size))),
+                    value => Ok(value),
+                }
+            }
+            (Column::Nullable(x), value) => x.enforce_length(value, column_name, policy),
+            (_, value) => Ok(value),
+        }
+    }
+
     pub fn match_type(&self, other: &Column) -> bool {
         self.coerce_type(other.get_default_value()).is_ok()
     }
@@ -311,6 +418,13 @@ impl Column {
             _ => Column::Nullable(Box::new(self)),
         }
     }
+
+    pub fn as_non_nullable(self) -> Column {
+        match self {
+            Column::Nullable(x) => *x,
+            _ => self,
+        }
+    }
 }
 
 impl Value {
@@ -333,7 +447,9 @@ impl Value {
             Value::Double(_) => Column::Double,
             Value::Bool(_) => Column::Bool,
             Value::Timestamp(_) => Column::Timestamp,
+            Value::Time(_) => Column::Time,
             Value::String(_) => Column::String(0),
+            Value::Char(x) => Column::Char(x.len() as u16),
             Value::Null(x) => Column::Nullable(Box::new(x.clone())),
         }
     }
@@ -341,6 +457,26 @@ impl Value {
     pub fn is_null(&self) -> bool {
         matches!(self, Value::Null(_))
     }
+
+    /// Converts the value back into a literal, so it can be placed into a parsed expression.
+    pub fn to_sql_value(&self) -> SqlValue {
+        match self {
+            Value::I32(x) => SqlValue::Number(x.to_string(), false),
+            Value::I64(x) => SqlValue::Number(x.to_string(), false),
+            Value::Float(x) => SqlValue::Number(x.to_string(), false),
+            Value::Double(x) => SqlValue::Number(x.to_string(), false),
+            Value::Bool(x) => SqlValue::Boolean(*x),
+            Value::Timestamp(x) => SqlValue::SingleQuotedString(
+                NaiveDateTime::from_timestamp_opt(x.seconds, x.nanos as u32)
+                    .unwrap_or(NaiveDateTime::MAX)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+            ),
+            Value::Time(x) => SqlValue::SingleQuotedString(format_time_of_day(*x)),
+            Value::String(x) | Value::Char(x) => SqlValue::SingleQuotedString(x.clone()),
+            Value::Null(_) => SqlValue::Null,
+        }
+    }
 }
 
 impl ToString for Column {
@@ -352,7 +488,9 @@ impl ToString for Column {
             Column::Double => "Double".to_string(),
             Column::Bool => "Bool".to_string(),
             Column::Timestamp => "Timestamp".to_string(),
+            Column::Time => "Time".to_string(),
             Column::String(x) => format!("String({})", x),
+            Column::Char(x) => format!("Char({})", x),
             Column::Nullable(x) => format!("Nullable({})", x.to_string()),
         }
     }
@@ -367,7 +505,9 @@ impl ToString for Value {
             Value::Double(x) => format!("Double({})", x),
             Value::Bool(x) => format!("Bool({})", x),
             Value::Timestamp(x) => format!("Timestamp({})", x),
+            Value::Time(x) => format!("Time({})", format_time_of_day(*x)),
             Value::String(x) => format!("String({})", x),
+            Value::Char(x) => format!("Char({})", x),
             Value::Null(_) => "Null()".to_string(),
         }
     }
@@ -390,7 +530,12 @@ impl PartialOrd for Value {
                     }
                 })
             }
-            (Value::String(x), Value::String(y)) => x.partial_cmp(y),
+            (Value::Time(x), Value::Time(y)) => x.partial_cmp(y),
+            // Strings are compared as if the shorter one was padded with spaces, so that fixed
+            // length strings are equal to the strings they were padded from
+            (Value::String(x) | Value::Char(x), Value::String(y) | Value::Char(y)) => {
+                x.trim_end_matches(' ').partial_cmp(y.trim_end_matches(' '))
+            }
             // Type coercions
             (Value::I64(x), Value::I32(y)) => x.partial_cmp(&(*y as i64)),
             (Value::I64(x), Value::Double(y)) => (*x as f64).partial_cmp(y),
@@ -444,13 +589,18 @@ impl Hash for Value {
                 x.seconds.hash(state);
                 x.nanos.hash(state);
             }
-            Value::String(x) => {
+            // Hashed without their padding, as strings that only differ in it are equal
+            Value::String(x) | Value::Char(x) => {
                 state.write_u8(6);
-                x.hash(state);
+                x.trim_end_matches(' ').hash(state);
             }
             Value::Null(_) => {
                 state.write_u8(7);
             }
+            Value::Time(x) => {
+                state.write_u8(8);
+                x.hash(state);
+            }
         }
     }
 }
@@ -472,6 +622,12 @@ impl PartialEq for Value {
     }
 }
 
+/// Pads a fixed length string with spaces to `size` bytes. Longer strings are left as they are,
+/// so that `enforce_length` can decide what to do with them.
+pub fn pad_char(value: &str, size: u16) -> String {
+    value.to_string() + &" ".repeat((size as usize).saturating_sub(value.len()))
+}
+
 pub fn parse_time(str: &String) -> Result<Timestamp, String> {
     let time = NaiveDateTime::parse_from_str(str, "%Y-%m-%d %H:%M:%S");
     if let Ok(x) = time {
@@ -483,3 +639,21 @@ pub fn parse_time(str: &String) -> Result<Timestamp, String> {
         Err(format!("Could not parse value {str} into type time"))
     }
 }
+
+/// Parses a time of day in the format HH:MM:SS into the number of seconds since midnight
+pub fn parse_time_of_day(str: &String) -> Result<u32, String> {
+    match NaiveTime::parse_from_str(str, "%H:%M:%S") {
+        Ok(x) => Ok(x.num_seconds_from_midnight()),
+        Err(_) => Err(format!("Could not parse value {str} into type Time")),
+    }
+}
+
+/// Formats a number of seconds since midnight as HH:MM:SS
+pub fn format_time_of_day(seconds: u32) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+
+/// What kind of failure an error is, so that callers like the server can tell failures apart
+/// without reading their messages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorKind {
+    InvalidArgument,    // The statement or command can't be run as it's written
+    NotFound,           // A table, column or branch that it names doesn't exist
+    PermissionDenied,   // The user's permissions don't allow it
+    FailedPrecondition, // The session isn't in a state that allows it, like having no changes to commit
+    Internal,           // Anything else that went wrong while running it
+}
+
+/// An error from running a statement or a version control command, along with its kind.
+/// Errors from the functions that only give a message are Internal ones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Error {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::InvalidArgument, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn permission_denied(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::PermissionDenied, message)
+    }
+
+    pub fn failed_precondition(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::FailedPrecondition, message)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::new(ErrorKind::Internal, message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::new(ErrorKind::Internal, message)
+    }
+}
+
+// Lets functions that only give a message call the ones that give an Error with ?
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.message
+    }
+}
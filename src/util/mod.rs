@@ -1,4 +1,7 @@
 pub mod bench;
+pub mod clock;
+pub mod compress;
 pub mod convert;
 pub mod dbtype;
+pub mod error;
 pub mod row;
@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::fileio::{header::*, pageio::*, tableio::*, *};
+use crate::fileio::{header::*, pageio::*, storage::get_storage_backend, tableio::*, *};
 use crate::util::{dbtype::*, row::*};
 use crate::version_control::branches::*;
 
@@ -11,6 +11,7 @@ pub struct BranchHead {
     pub branch_name: String, // The name of the branch that this head points to.
     pub pagenum: i32,        // The page number in `branches.gql` where the branch node is located.
     pub rownum: i32,         // The row number in `branches.gql` where the branch node is located.
+    pub commits_since_checkpoint: i32, // The number of commits made on the branch since it was last checkpointed.
 }
 
 /// This is designed to represent the branch_heads.gql file for a database.
@@ -54,17 +55,15 @@ impl BranchHEADs {
         }
 
         if create_file {
-            std::fs::File::create(filepath.clone()).map_err(|e| e.to_string())?;
+            get_storage_backend(&filepath).create_file(&filepath)?;
 
-            let schema = vec![
-                ("branch_name".to_string(), Column::String(60)),
-                ("page_num".to_string(), Column::I32),
-                ("row_num".to_string(), Column::I32),
-            ];
             let header = Header {
                 num_pages: 2,
-                schema,
+                schema: Self::get_schema(),
                 index_top_level_pages: HashMap::new(),
+                unique_indexes: HashSet::new(),
+                ttl: None,
+                version: 0,
             };
             write_header(&filepath, &header)?;
 
@@ -73,16 +72,31 @@ impl BranchHEADs {
             write_page(1, &filepath, &page, PageType::Data)?;
         }
 
+        let mut branch_heads_table: Table = Table::new(
+            &dir_path.clone(),
+            &databaseio::BRANCH_HEADS_FILE_NAME.to_string(),
+            Some(&databaseio::BRANCH_HEADS_FILE_EXTENSION.to_string()),
+        )?;
+        // Files made before the checkpoint count was kept get the column, with no counts yet
+        if branch_heads_table.schema.len() < Self::get_schema().len() {
+            branch_heads_table.change_schema(&Self::get_schema())?;
+        }
+
         Ok(BranchHEADs {
             filepath: filepath.clone(),
-            branch_heads_table: Table::new(
-                &dir_path.clone(),
-                &databaseio::BRANCH_HEADS_FILE_NAME.to_string(),
-                Some(&databaseio::BRANCH_HEADS_FILE_EXTENSION.to_string()),
-            )?,
+            branch_heads_table,
         })
     }
 
+    fn get_schema() -> Schema {
+        vec![
+            ("branch_name".to_string(), Column::String(60)),
+            ("page_num".to_string(), Column::I32),
+            ("row_num".to_string(), Column::I32),
+            ("commits_since_checkpoint".to_string(), Column::I32),
+        ]
+    }
+
     // Immutable getter access to filepath.
     pub fn filepath(&self) -> &str {
         &self.filepath
@@ -156,6 +170,7 @@ impl BranchHEADs {
             let branch_name: String;
             let page_num: i32;
             let row_num: i32;
+            let commits_since_checkpoint: i32;
 
             // Get the branch name
             match row.get(0) {
@@ -175,10 +190,18 @@ impl BranchHEADs {
                 _ => return Err("Error: Row number not found".to_string()),
             }
 
+            // Get the number of commits since the last checkpoint
+            match row.get(3) {
+                Some(Value::I32(count)) => commits_since_checkpoint = count.clone(),
+                Some(Value::Null(_)) => commits_since_checkpoint = 0,
+                _ => return Err("Error: Commits since checkpoint not found".to_string()),
+            }
+
             let branch_head: BranchHead = BranchHead {
                 branch_name: branch_name,
                 pagenum: page_num,
                 rownum: row_num,
+                commits_since_checkpoint,
             };
 
             branch_heads.push(branch_head);
@@ -204,6 +227,7 @@ impl BranchHEADs {
                 Value::String(branch_head.branch_name.clone()),
                 Value::I32(branch_head.pagenum),
                 Value::I32(branch_head.rownum),
+                Value::I32(branch_head.commits_since_checkpoint),
             ],
         ];
         self.branch_heads_table.insert_rows(rows)?;
@@ -234,6 +258,7 @@ impl BranchHEADs {
                         Value::String(branch_head.branch_name.clone()),
                         Value::I32(branch_head.pagenum),
                         Value::I32(branch_head.rownum),
+                        Value::I32(branch_head.commits_since_checkpoint),
                     ],
                     pagenum: row_info.pagenum,
                     rownum: row_info.rownum,
@@ -263,15 +288,23 @@ impl BranchHEADs {
         // Get the branch head
         let mut branch_head: BranchHead = self.get_branch_head(branch_name)?;
 
-        // Update the branch head
+        // Update the branch head, which is one more commit past its last checkpoint
         branch_head.pagenum = new_branch_node_loc.pagenum as i32;
         branch_head.rownum = new_branch_node_loc.rownum as i32;
+        branch_head.commits_since_checkpoint += 1;
 
         // Update the branch head in the branch heads file
         self.update_branch_head(&branch_head)?;
         Ok(())
     }
 
+    /// Starts counting the commits on the given branch since its last checkpoint over again
+    pub fn reset_commits_since_checkpoint(&mut self, branch_name: &String) -> Result<(), String> {
+        let mut branch_head: BranchHead = self.get_branch_head(branch_name)?;
+        branch_head.commits_since_checkpoint = 0;
+        self.update_branch_head(&branch_head)
+    }
+
     /// Deletes a branch head from the branch heads file
     /// Returns an error if the branch name is not present in the branch heads file
     pub fn delete_branch_head(&mut self, branch_name: &String) -> Result<(), String> {
@@ -318,6 +351,7 @@ mod tests {
             branch_name: "main".to_string(),
             pagenum: 1,
             rownum: 1,
+            commits_since_checkpoint: 0,
         };
 
         branch_heads.create_branch_head(&branch_head).unwrap();
@@ -346,12 +380,14 @@ mod tests {
             branch_name: "main".to_string(),
             pagenum: 1,
             rownum: 1,
+            commits_since_checkpoint: 0,
         };
 
         let branch_head2 = BranchHead {
             branch_name: "test".to_string(),
             pagenum: 2,
             rownum: 2,
+            commits_since_checkpoint: 0,
         };
 
         branch_heads.create_branch_head(&branch_head1).unwrap();
@@ -391,12 +427,14 @@ mod tests {
             branch_name: "main".to_string(),
             pagenum: 1,
             rownum: 1,
+            commits_since_checkpoint: 0,
         };
 
         let branch_head2 = BranchHead {
             branch_name: "test".to_string(),
             pagenum: 2,
             rownum: 2,
+            commits_since_checkpoint: 0,
         };
 
         branch_heads.create_branch_head(&branch_head1).unwrap();
@@ -406,6 +444,7 @@ mod tests {
             branch_name: "test".to_string(),
             pagenum: 5,
             rownum: 16,
+            commits_since_checkpoint: 0,
         };
 
         let branch_head_list = branch_heads.get_all_branch_heads().unwrap();
@@ -447,12 +486,14 @@ mod tests {
             branch_name: "main".to_string(),
             pagenum: 1,
             rownum: 1,
+            commits_since_checkpoint: 0,
         };
 
         let branch_head2 = BranchHead {
             branch_name: "test".to_string(),
             pagenum: 2,
             rownum: 2,
+            commits_since_checkpoint: 0,
         };
 
         branch_heads.create_branch_head(&branch_head1).unwrap();
@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::{branch_heads::*, diff::*};
 use crate::fileio::{
     databaseio::{self, get_db_instance},
     header::*,
     pageio::*,
+    storage::get_storage_backend,
     tableio::*,
 };
 use crate::util::{dbtype::*, row::*};
@@ -112,7 +113,7 @@ impl Branches {
         }
 
         if create_file {
-            std::fs::File::create(filepath.clone()).map_err(|e| e.to_string())?;
+            get_storage_backend(&filepath).create_file(&filepath)?;
 
             let schema = vec![
                 ("branch_name".to_string(), Column::String(60)),
@@ -128,6 +129,9 @@ impl Branches {
                 num_pages: 2,
                 schema,
                 index_top_level_pages: HashMap::new(),
+                unique_indexes: HashSet::new(),
+                ttl: None,
+                version: 0,
             };
             write_header(&filepath, &header)?;
 
@@ -271,6 +275,7 @@ impl Branches {
                             branch_name: branch_name.clone(),
                             pagenum: row.pagenum as i32,
                             rownum: row.rownum as i32,
+                            commits_since_checkpoint: 1,
                         })?;
                         prev_row[7] = Value::Bool(true);
                     }
@@ -285,6 +290,7 @@ impl Branches {
                         branch_name: branch_name.clone(),
                         pagenum: row.pagenum as i32,
                         rownum: row.rownum as i32,
+                        commits_since_checkpoint: 1,
                     })?;
                 }
 
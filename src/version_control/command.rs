@@ -1,12 +1,15 @@
+use crate::executor::query::{optimize_table, vacuum_table};
 use crate::fileio::pageio::PageType;
+use crate::util::error::Error;
 use crate::{fileio::databaseio::*, user::userdata::User};
 
 use crate::{
     fileio::{
         header::read_schema,
         pageio::{read_page, Page},
+        storage::get_storage_backend,
     },
-    util::dbtype::Column,
+    util::{dbtype::Column, row::Row},
 };
 
 use itertools::Itertools;
@@ -15,7 +18,6 @@ use serde_json;
 use tabled::{builder::Builder, Style};
 
 use std::collections::HashMap;
-use std::fs;
 
 use super::branch_heads::BranchHEADs;
 use super::diff::*;
@@ -153,8 +155,8 @@ pub fn del_branch(
     all_users: Vec<User>,
 ) -> Result<String, String> {
     // Check if the branch is the master branch. If so, return an error
-    // MAIN_BRANCH_NAME is the name of the master branch
-    if branch_name == MAIN_BRANCH_NAME {
+    // The name of the master branch is stored in the database's metadata
+    if *branch_name == get_db_instance()?.get_main_branch_name() {
         return Err("ERROR: Cannot delete the master branch".to_string());
     }
 
@@ -394,7 +396,7 @@ pub fn discard(user: &mut User) -> Result<(), String> {
 
     if user.is_on_temp_commit() {
         //Deleting the temp copy of the branch
-        fs::remove_dir_all(branch_path).map_err(|e| e.to_string())?;
+        get_storage_backend(&branch_path).remove_dir_all(&branch_path)?;
         user.set_diffs(&Vec::new());
     }
 
@@ -421,6 +423,10 @@ pub fn info(hash: &String) -> Result<String, String> {
     log_string = format!("{}\nChanges Made:", log_string);
     for diffs in commit.diffs {
         log_string = format!("{}\n{}", log_string, diffs.to_string());
+        // Updates also show the columns that changed in each row
+        if let Diff::Update(diff) = &diffs {
+            log_string = format!("{}\n{}", log_string, diff.format_changes());
+        }
     }
     log_string = format!("{}\n----------------------------------------\n", log_string);
 
@@ -505,6 +511,125 @@ pub fn schema_table(user: &User) -> Result<(String, String), String> {
     Ok((log_string, json))
 }
 
+/// This function outputs the rows of a table that differ between two branches
+pub fn branch_diff(
+    branch_a: &String,
+    branch_b: &String,
+    table_name: &String,
+) -> Result<String, String> {
+    let diff: TableBranchDiff =
+        get_db_instance()?.table_branch_diff(branch_a, branch_b, table_name)?;
+    let summary: String = format!(
+        "Table {}: {} rows only in {}, {} rows only in {}, {} rows changed",
+        table_name,
+        diff.only_in_a.len(),
+        branch_a,
+        diff.only_in_b.len(),
+        branch_b,
+        diff.changed.len()
+    );
+    if diff.only_in_a.is_empty() && diff.only_in_b.is_empty() && diff.changed.is_empty() {
+        return Ok(summary);
+    }
+
+    // Each row is labelled with how it differs
+    let mut builder = Builder::default();
+    let mut columns: Vec<String> = vec!["change".to_string()];
+    columns.extend(diff.schema.iter().map(|(name, _)| name.clone()));
+    builder.set_columns(columns);
+    let to_record = |label: String, row: &Row| -> Vec<String> {
+        let mut record: Vec<String> = vec![label];
+        record.extend(row.iter().map(|value| value.to_sql_value().to_string()));
+        record
+    };
+    for row in &diff.only_in_a {
+        builder.add_record(to_record(format!("only in {}", branch_a), row));
+    }
+    for row in &diff.only_in_b {
+        builder.add_record(to_record(format!("only in {}", branch_b), row));
+    }
+    for (row_a, row_b) in &diff.changed {
+        builder.add_record(to_record(format!("changed on {}", branch_a), row_a));
+        builder.add_record(to_record(format!("changed on {}", branch_b), row_b));
+    }
+
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    Ok(format!("{}\n{}", summary, table))
+}
+
+/// This function implements the GQL stat command.
+/// It shows how many rows a table has, and how many row slots deleted rows have left free.
+pub fn table_stats(table_name: &String, user: &User) -> Result<String, String> {
+    let stats: TableStats = get_db_instance()?.get_table_stats(table_name, user)?;
+    let mut builder = Builder::default();
+    builder.set_columns(vec!["rows", "free slots", "spare slots", "pages"]);
+    builder.add_record(vec![
+        stats.num_rows.to_string(),
+        stats.free_slots.to_string(),
+        stats.spare_slots.to_string(),
+        stats.num_pages.to_string(),
+    ]);
+    let mut table = builder.build();
+    table.with(Style::rounded());
+
+    let mut result: String = format!("Table: {}\n{}", table_name, table);
+    if stats.free_slots > 0 {
+        result.push_str(&format!(
+            "\nRun GQL vacuum {} to reclaim the free slots",
+            table_name
+        ));
+    }
+    Ok(result)
+}
+
+/// This function implements the GQL vacuum command.
+/// It packs the rows of a table together, so that no free slots are left between them.
+pub fn vacuum(table_name: &String, user: &mut User) -> Result<String, Error> {
+    vacuum_table(table_name, get_db_instance()?, user)
+}
+
+/// This function implements the GQL optimize command.
+/// It rebuilds the indexes of a table, and optionally vacuums the table too.
+pub fn optimize(table_name: &String, vacuum: bool, user: &mut User) -> Result<String, Error> {
+    optimize_table(table_name, vacuum, get_db_instance()?, user)
+}
+
+/// This function implements the GQL verify command.
+/// It lists the index entries of a table that don't match its rows, and the rows with no entry.
+pub fn verify(table_name: &String, user: &User) -> Result<String, String> {
+    let problems: Vec<IndexProblem> = get_db_instance()?.verify_indexes(table_name, user)?;
+    if problems.is_empty() {
+        return Ok(format!(
+            "The indexes of table {} match its rows",
+            table_name
+        ));
+    }
+    let mut builder = Builder::default();
+    builder.set_columns(vec!["index", "page", "row", "key", "problem"]);
+    for problem in &problems {
+        builder.add_record(vec![
+            problem.index_name.clone(),
+            problem.location.pagenum.to_string(),
+            problem.location.rownum.to_string(),
+            problem
+                .key
+                .iter()
+                .map(|value| value.to_sql_value().to_string())
+                .join(", "),
+            problem.problem.clone(),
+        ]);
+    }
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    Ok(format!(
+        "Table {} has {} index problems\n{}",
+        table_name,
+        problems.len(),
+        table
+    ))
+}
+
 /// This function is used to update the user's copy of the db
 /// to the latest commit if the user is behind
 /// Takes in user object and Returns Success or Error
@@ -547,8 +672,8 @@ pub fn pull(
 
         // Source Diffs are the changes that the user
         // missed out on while making their own changes
-        let source_commits: Vec<Commit> =
-            get_db_instance()?.get_commits_between_nodes(Some(&user_curr_node), &current_node)?;
+        let source_commits: Vec<Commit> = get_db_instance()?
+            .get_checkpointed_commits_between_nodes(Some(&user_curr_node), &current_node)?;
         // Only need to squash the commits if there are some
         let mut source_diffs: Vec<Diff> = Vec::new();
         if source_commits.len() > 0 {
@@ -750,17 +875,21 @@ mod tests {
     use serial_test::serial;
 
     use crate::{
-        executor::query::{create_table, insert},
+        btree::{
+            btree::BTree,
+            indexes::{cols_id_to_index_key_type, set_node_capacity},
+        },
+        executor::query::{count_index_pages, create_table, execute_query, execute_update, insert},
         fileio::{
             databaseio::{delete_db_instance, Database},
             header::Schema,
             tableio::Table,
         },
-        parser::parser::parse_vc_cmd,
+        parser::parser::{parse, parse_vc_cmd},
         util::{
             bench::{create_demo_db, fcreate_db_instance},
             dbtype::*,
-            row::Row,
+            row::{Row, RowInfo},
         },
         version_control::{commit::Commit, diff::Diff},
     };
@@ -2027,4 +2156,264 @@ mod tests {
         delete_db_instance().unwrap();
         assert!(result.is_ok());
     }
+
+    // Checks that deleting rows leaves free slots, and that vacuuming the table reclaims them
+    #[test]
+    #[serial]
+    fn test_stat_and_vacuum() {
+        fcreate_db_instance("gql_stat_vacuum_test");
+        let mut user: User = User::new("test_user".to_string());
+        let all_users: Vec<User> = vec![user.clone()];
+        let values: Vec<String> = (0..1000).map(|i| format!("({}, {})", i, i * 2)).collect();
+        for query in [
+            "CREATE TABLE numbers (id INT, doubled INT)".to_string(),
+            format!("INSERT INTO numbers VALUES {}", values.join(", ")),
+            "CREATE INDEX numbers_id ON numbers (id)".to_string(),
+        ] {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let stats = |user: &User| {
+            get_db_instance()
+                .unwrap()
+                .get_table_stats(&"numbers".to_string(), user)
+                .unwrap()
+        };
+        let full: TableStats = stats(&user);
+        assert_eq!(full.num_rows, 1000);
+        assert_eq!(full.free_slots, 0);
+        assert!(full.num_pages > 1);
+
+        // The deleted rows leave free slots behind, that the stat command reports
+        execute_update(
+            &parse("DELETE FROM numbers WHERE id < 500", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let deleted: TableStats = stats(&user);
+        assert_eq!(deleted.num_rows, 500);
+        assert_eq!(deleted.free_slots, 500);
+        assert_eq!(deleted.num_pages, full.num_pages);
+        let result: String =
+            parse_vc_cmd("GQL stat numbers", &mut user, all_users.clone()).unwrap();
+        assert!(result.contains("Run GQL vacuum numbers"));
+
+        // Vacuuming packs the rows together, and keeps the table's rows and indexes
+        let result: String =
+            parse_vc_cmd("GQL vacuum numbers", &mut user, all_users.clone()).unwrap();
+        assert_eq!(result, "Table numbers vacuumed: 500 rows kept".to_string());
+        let vacuumed: TableStats = stats(&user);
+        assert_eq!(vacuumed.num_rows, 500);
+        assert_eq!(vacuumed.free_slots, 0);
+        assert!(vacuumed.num_pages < full.num_pages);
+        let (_, rows) = execute_query(
+            &parse("SELECT doubled FROM numbers WHERE id = 700", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert_eq!(rows, vec![vec![Value::I64(1400)]]);
+        let table: Table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"numbers".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(table.indexes.len(), 1);
+
+        assert!(parse_vc_cmd("GQL stat missing", &mut user, all_users).is_err());
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    // Checks that optimizing a table shrinks the indexes left sparse by deletes, and keeps them correct
+    fn test_optimize() {
+        fcreate_db_instance("gql_optimize_test");
+        let mut user: User = User::new("test_user".to_string());
+        let all_users: Vec<User> = vec![user.clone()];
+        // Small index pages, so that a few hundred rows make a deep tree
+        set_node_capacity(Some(8)).unwrap();
+        let values: Vec<String> = (0..200).map(|i| format!("({}, {})", i, i * 2)).collect();
+        for query in [
+            "CREATE TABLE numbers (id INT, doubled INT)".to_string(),
+            format!("INSERT INTO numbers VALUES {}", values.join(", ")),
+            "CREATE INDEX numbers_id ON numbers (id)".to_string(),
+            "CREATE UNIQUE INDEX numbers_doubled ON numbers (doubled)".to_string(),
+            format!(
+                "INSERT INTO numbers VALUES {}",
+                (200..300).map(|i| format!("({}, {})", i, i * 2)).join(", ")
+            ),
+            // Most rows are deleted, but every leaf page keeps some of its keys
+            "DELETE FROM numbers WHERE id % 4 <> 0".to_string(),
+        ] {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let index_pages = |user: &User| -> usize {
+            let database: &Database = get_db_instance().unwrap();
+            let table: Table =
+                Table::from_user(user, database, &"numbers".to_string(), None).unwrap();
+            count_index_pages(&table).unwrap()
+        };
+        let lookup = |user: &mut User, id: i64| -> Vec<Row> {
+            let query: String = format!(
+                "SELECT doubled FROM numbers WHERE id = {} AND doubled = {}",
+                id,
+                id * 2
+            );
+            execute_query(&parse(&query, false).unwrap(), user, &"".to_string())
+                .unwrap()
+                .1
+        };
+        let churned: usize = index_pages(&user);
+
+        let result: String =
+            parse_vc_cmd("GQL optimize numbers", &mut user, all_users.clone()).unwrap();
+        let optimized: usize = index_pages(&user);
+        assert!(optimized < churned);
+        assert_eq!(
+            result,
+            format!(
+                "Table numbers optimized: 2 indexes rebuilt, index pages went from {} to {}",
+                churned, optimized
+            )
+        );
+        for id in [0, 100, 196, 240, 296] {
+            assert_eq!(lookup(&mut user, id), vec![vec![Value::I64(id * 2)]]);
+        }
+        for id in [1, 150, 299] {
+            assert_eq!(lookup(&mut user, id), Vec::<Row>::new());
+        }
+        let result: String =
+            parse_vc_cmd("GQL verify numbers", &mut user, all_users.clone()).unwrap();
+        assert_eq!(
+            result,
+            "The indexes of table numbers match its rows".to_string()
+        );
+
+        // The unique index is still unique once it's rebuilt
+        assert!(execute_update(
+            &parse("INSERT INTO numbers VALUES (1000, 200)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .is_err());
+
+        // Vacuuming as well packs the rows together, and the indexes are rebuilt with the table
+        let result: String = parse_vc_cmd(
+            "GQL optimize numbers --vacuum",
+            &mut user,
+            all_users.clone(),
+        )
+        .unwrap();
+        assert!(result.starts_with("Table numbers vacuumed: 75 rows kept\n"));
+        assert!(result.ends_with(&format!(
+            "index pages went from {} to {}",
+            optimized, optimized
+        )));
+        let stats: TableStats = get_db_instance()
+            .unwrap()
+            .get_table_stats(&"numbers".to_string(), &user)
+            .unwrap();
+        assert_eq!(stats.free_slots, 0);
+        assert_eq!(lookup(&mut user, 240), vec![vec![Value::I64(480)]]);
+
+        // The rebuilt indexes are kept when the changes are committed
+        parse_vc_cmd("GQL commit -m optimized", &mut user, all_users.clone()).unwrap();
+        assert_eq!(index_pages(&user), optimized);
+        assert_eq!(lookup(&mut user, 296), vec![vec![Value::I64(592)]]);
+
+        assert!(parse_vc_cmd("GQL optimize missing", &mut user, all_users).is_err());
+        set_node_capacity(None).unwrap();
+        delete_db_instance().unwrap();
+    }
+
+    // Checks that index entries that don't match the table's rows are reported
+    #[test]
+    #[serial]
+    fn test_verify_indexes() {
+        fcreate_db_instance("gql_verify_indexes_test");
+        let mut user: User = User::new("test_user".to_string());
+        let all_users: Vec<User> = vec![user.clone()];
+        let values: Vec<String> = (0..100).map(|i| format!("({}, {})", i, i * 2)).collect();
+        for query in [
+            "CREATE TABLE numbers (id INT, doubled INT)".to_string(),
+            format!("INSERT INTO numbers VALUES {}", values.join(", ")),
+            "CREATE INDEX numbers_id ON numbers (id)".to_string(),
+        ] {
+            execute_update(&parse(&query, true).unwrap(), &mut user, &"".to_string()).unwrap();
+        }
+        let result: String =
+            parse_vc_cmd("GQL verify numbers", &mut user, all_users.clone()).unwrap();
+        assert_eq!(
+            result,
+            "The indexes of table numbers match its rows".to_string()
+        );
+
+        // Corrupt the index, so that an entry is lost, one points nowhere, and one has the wrong key
+        let table: Table = Table::from_user(
+            &user,
+            get_db_instance().unwrap(),
+            &"numbers".to_string(),
+            None,
+        )
+        .unwrap();
+        let (index_id, (pagenum, index_name)) = table.indexes.iter().next().unwrap();
+        let mut btree: BTree = BTree::load_btree_from_root_page(
+            &table,
+            *pagenum,
+            index_id.clone(),
+            cols_id_to_index_key_type(index_id, &table.schema),
+            index_name.clone(),
+        )
+        .unwrap();
+        let lost: Vec<RowInfo> = btree.get_rows(&vec![Value::I64(7)]).unwrap();
+        btree.remove_rows(&lost).unwrap();
+        let wrong_key: RowInfo = btree.get_rows(&vec![Value::I64(8)]).unwrap()[0].clone();
+        btree
+            .insert_rows(&vec![
+                RowInfo {
+                    row: vec![Value::I64(5000), Value::I64(0)],
+                    pagenum: 900,
+                    rownum: 3,
+                },
+                RowInfo {
+                    row: vec![Value::I64(123456), Value::I64(0)],
+                    pagenum: wrong_key.pagenum,
+                    rownum: wrong_key.rownum,
+                },
+            ])
+            .unwrap();
+
+        // The index no longer finds the row, but verifying it reports each problem
+        let (_, rows) = execute_query(
+            &parse("SELECT doubled FROM numbers WHERE id = 7", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        assert!(rows.is_empty());
+        let problems: Vec<IndexProblem> = get_db_instance()
+            .unwrap()
+            .verify_indexes(&"numbers".to_string(), &user)
+            .unwrap();
+        let found = |key: i64, problem: &str| {
+            problems.iter().any(|found| {
+                found.index_name == "numbers_id"
+                    && found.key == vec![Value::I64(key)]
+                    && found.problem == problem
+            })
+        };
+        assert_eq!(problems.len(), 3);
+        assert!(found(7, "row has no entry"));
+        assert!(found(5000, "points to a missing row"));
+        assert!(found(123456, "points to a row with the key 8"));
+        let result: String =
+            parse_vc_cmd("GQL verify numbers", &mut user, all_users.clone()).unwrap();
+        assert!(result.starts_with("Table numbers has 3 index problems"));
+
+        assert!(parse_vc_cmd("GQL verify missing", &mut user, all_users).is_err());
+        delete_db_instance().unwrap();
+    }
 }
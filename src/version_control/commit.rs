@@ -11,16 +11,40 @@ use crate::{
         databaseio,
         header::{write_header, Header, Schema},
         pageio::*,
+        storage::{get_storage_backend, IN_MEMORY_PATH_PREFIX},
         tableio::Table,
     },
     util::{
+        clock,
+        compress::{compress, decompress},
         dbtype::{Column, Value},
         row::{Row, RowInfo},
     },
 };
 use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+// The first byte of every commit in the deltas file, which says how its diffs are stored
+const UNCOMPRESSED_COMMIT_MARKER: u8 = 1;
+const COMPRESSED_COMMIT_MARKER: u8 = 2;
+// Set on the length of an index id in an index diff when the index is unique
+const UNIQUE_INDEX_DIFF_FLAG: u8 = 0x80;
+// Set on the type of a table diff when the table has a TTL, which is written after its schema
+const TTL_DIFF_FLAG: u32 = 0x100;
+
+// Used to give every scratch file a unique name
+static SCRATCH_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+// The number of commits that have been read from the deltas file, see commits_read()
+#[cfg(test)]
+static COMMITS_READ: AtomicU64 = AtomicU64::new(0);
+
+/// The number of commits that have been read from the deltas file since the process started.
+/// It's used by tests to see how many commits an operation has to go through.
+#[cfg(test)]
+pub fn commits_read() -> u64 {
+    COMMITS_READ.load(AtomicOrdering::SeqCst)
+}
 
 // Commit Header: A struct with a commit hash, a page number, and a row number.
 pub struct CommitHeader {
@@ -120,7 +144,7 @@ impl CommitFile {
 
         if create_file {
             // Header File
-            std::fs::File::create(header_path.clone()).map_err(|e| e.to_string())?;
+            get_storage_backend(&header_path).create_file(&header_path)?;
 
             let schema = vec![
                 ("commit_hash".to_string(), Column::String(32)),
@@ -130,6 +154,9 @@ impl CommitFile {
                 num_pages: 2,
                 schema,
                 index_top_level_pages: HashMap::new(),
+                unique_indexes: HashSet::new(),
+                ttl: None,
+                version: 0,
             };
             write_header(&header_path, &header)?;
 
@@ -138,7 +165,7 @@ impl CommitFile {
             write_page(1, &header_path, &page, PageType::Data)?;
 
             // Delta File
-            std::fs::File::create(delta_path.clone()).map_err(|e| e.to_string())?;
+            get_storage_backend(&delta_path).create_file(&delta_path)?;
         }
 
         Ok(CommitFile {
@@ -149,6 +176,7 @@ impl CommitFile {
                 &databaseio::COMMIT_HEADERS_FILE_NAME.to_string(),
                 Some(&databaseio::COMMIT_HEADERS_FILE_EXTENSION.to_string()),
             )?,
+            compress_commits: false,
         })
     }
 
@@ -158,6 +186,8 @@ impl CommitFile {
     pub fn fetch_commit(&self, commit_hash: &String) -> Result<Commit, String> {
         let header = self.find_header(commit_hash.clone())?;
         if let Some(header) = header {
+            #[cfg(test)]
+            COMMITS_READ.fetch_add(1, AtomicOrdering::SeqCst);
             self.read_commit(header.pagenum)
         } else {
             Err("Commit not found".to_string())
@@ -172,11 +202,7 @@ impl CommitFile {
         diffs: Vec<Diff>,
         write_to_file: bool,
     ) -> Result<Commit, String> {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis()
-            .to_string();
+        let timestamp = clock::now_millis().to_string();
         let hash = Commit::create_hash();
         let commit = Commit::new(user_id, hash, timestamp, message, command, diffs);
         if write_to_file {
@@ -185,8 +211,8 @@ impl CommitFile {
         Ok(commit)
     }
 
-    // Returns the commit header for a given commit hash.
-    fn store_commit(&mut self, commit: &Commit) -> Result<(), String> {
+    /// Writes the given commit to the end of the deltas file, and adds a header for it.
+    pub fn store_commit(&mut self, commit: &Commit) -> Result<(), String> {
         // Always needs to be written at the end. Traverse pages until we find a page marked as free.
         let mut pagenum = 1;
         let mut page = self.sread_page(pagenum)?;
@@ -255,7 +281,7 @@ impl CommitFile {
         let pagenum = &mut pagenum;
         let offset = &mut 0;
         let byte: u8 = self.sread_type(page, pagenum, offset)?;
-        if byte != 1 {
+        if byte != UNCOMPRESSED_COMMIT_MARKER && byte != COMPRESSED_COMMIT_MARKER {
             return Err("Invalid commit".to_string());
         }
         let user_id = self.sread_string(page, pagenum, offset, 32)?;
@@ -264,11 +290,41 @@ impl CommitFile {
         let message = self.sdread_string(page, pagenum, offset)?;
         let command = self.sdread_string(page, pagenum, offset)?;
 
-        // Parsing the diffs
+        let diffs: Vec<Diff> = if byte == COMPRESSED_COMMIT_MARKER {
+            let raw_size: u32 = self.sread_type(page, pagenum, offset)?;
+            let compressed_size: u32 = self.sread_type(page, pagenum, offset)?;
+            let compressed: Vec<u8> = self.sread_bytes(page, pagenum, offset, compressed_size)?;
+            let raw: Vec<u8> = decompress(&compressed)?;
+            if raw.len() != raw_size as usize {
+                return Err("Invalid commit: Compressed diffs are corrupted".to_string());
+            }
+            self.diffs_from_bytes(&raw)?
+        } else {
+            self.read_diffs(page, pagenum, offset)?
+        };
+        Ok(Commit::new(
+            user_id,
+            commit_hash,
+            timestamp,
+            message,
+            command,
+            diffs,
+        ))
+    }
+
+    /// Reads the diffs of a commit, starting at the given position in the file.
+    fn read_diffs(
+        &self,
+        page: &mut Page,
+        pagenum: &mut u32,
+        offset: &mut u32,
+    ) -> Result<Vec<Diff>, String> {
         let num_diffs: u32 = self.sread_type(page, pagenum, offset)?;
         let mut diffs: Vec<Diff> = Vec::new();
         for _ in 0..num_diffs {
-            let difftype: i32 = self.sread_type::<u32>(page, pagenum, offset)? as i32;
+            let difftype: u32 = self.sread_type(page, pagenum, offset)?;
+            let has_ttl: bool = difftype & TTL_DIFF_FLAG != 0;
+            let difftype: i32 = (difftype & !TTL_DIFF_FLAG) as i32;
             let table_name = self.sdread_string(page, pagenum, offset)?;
             let diff: Diff = match difftype {
                 INSERT_TYPE | UPDATE_TYPE | REMOVE_TYPE => {
@@ -322,12 +378,24 @@ impl CommitFile {
                 TABLE_CREATE_TYPE => {
                     // Create Table
                     let schema = self.sread_schema(page, pagenum, offset)?;
-                    Diff::TableCreate(TableCreateDiff { table_name, schema })
+                    let ttl: Option<u64> = match has_ttl {
+                        true => Some(self.sread_type(page, pagenum, offset)?),
+                        false => None,
+                    };
+                    Diff::TableCreate(TableCreateDiff {
+                        table_name,
+                        schema,
+                        ttl,
+                    })
                 }
                 TABLE_REMOVE_TYPE => {
                     // Remove Table
                     let num_rows: u32 = self.sread_type(page, pagenum, offset)?;
                     let schema = self.sread_schema(page, pagenum, offset)?;
+                    let ttl: Option<u64> = match has_ttl {
+                        true => Some(self.sread_type(page, pagenum, offset)?),
+                        false => None,
+                    };
                     let mut rows: Vec<RowInfo> = Vec::new();
                     for _ in 0..num_rows {
                         let row = self.sread_row(page, pagenum, offset, &schema)?;
@@ -342,6 +410,7 @@ impl CommitFile {
                         table_name,
                         schema,
                         rows_removed: rows,
+                        ttl,
                     })
                 }
                 INDEX_CREATE_TYPE => {
@@ -350,19 +419,24 @@ impl CommitFile {
                     let schema: Schema = self.sread_schema(page, pagenum, offset)?;
 
                     let mut indexes: Vec<(String, IndexID)> = Vec::new();
+                    let mut unique_indexes: Vec<IndexID> = Vec::new();
                     for _ in 0..num_indexes {
                         let index_name: String = self.sdread_string(page, pagenum, offset)?;
                         let mut index_id: IndexID = Vec::new();
                         let index_id_len: u8 = self.sread_type::<u8>(page, pagenum, offset)?;
-                        for _ in 0..index_id_len {
+                        for _ in 0..(index_id_len & !UNIQUE_INDEX_DIFF_FLAG) {
                             index_id.push(self.sread_type::<u8>(page, pagenum, offset)?);
                         }
+                        if index_id_len & UNIQUE_INDEX_DIFF_FLAG != 0 {
+                            unique_indexes.push(index_id.clone());
+                        }
                         indexes.push((index_name, index_id));
                     }
                     Diff::IndexCreate(IndexCreateDiff {
                         table_name,
                         schema,
                         indexes,
+                        unique_indexes,
                     })
                 }
                 INDEX_REMOVE_TYPE => {
@@ -371,49 +445,106 @@ impl CommitFile {
                     let schema: Schema = self.sread_schema(page, pagenum, offset)?;
 
                     let mut indexes: Vec<(String, IndexID)> = Vec::new();
+                    let mut unique_indexes: Vec<IndexID> = Vec::new();
                     for _ in 0..num_indexes {
                         let index_name: String = self.sdread_string(page, pagenum, offset)?;
                         let mut index_id: IndexID = Vec::new();
                         let index_id_len: u8 = self.sread_type::<u8>(page, pagenum, offset)?;
-                        for _ in 0..index_id_len {
+                        for _ in 0..(index_id_len & !UNIQUE_INDEX_DIFF_FLAG) {
                             index_id.push(self.sread_type::<u8>(page, pagenum, offset)?);
                         }
+                        if index_id_len & UNIQUE_INDEX_DIFF_FLAG != 0 {
+                            unique_indexes.push(index_id.clone());
+                        }
                         indexes.push((index_name, index_id));
                     }
                     Diff::IndexRemove(IndexRemoveDiff {
                         table_name,
                         schema,
                         indexes,
+                        unique_indexes,
+                    })
+                }
+                SCHEMA_CHANGE_TYPE => {
+                    // Change Schema
+                    let num_rows: u32 = self.sread_type(page, pagenum, offset)?;
+                    let schema: Schema = self.sread_schema(page, pagenum, offset)?;
+                    let new_schema: Schema = self.sread_schema(page, pagenum, offset)?;
+                    let mut rows: Vec<RowInfo> = Vec::new();
+                    for _ in 0..num_rows {
+                        let row = self.sread_row(page, pagenum, offset, &schema)?;
+                        let row_info = RowInfo {
+                            row,
+                            pagenum: self.sread_type(page, pagenum, offset)?,
+                            rownum: self.sread_type(page, pagenum, offset)?,
+                        };
+                        rows.push(row_info);
+                    }
+                    Diff::SchemaChange(SchemaChangeDiff {
+                        table_name,
+                        schema,
+                        new_schema,
+                        rows,
                     })
                 }
                 _ => return Err("Invalid diff type".to_string()),
             };
             diffs.push(diff);
         }
-        Ok(Commit::new(
-            user_id,
-            commit_hash,
-            timestamp,
-            message,
-            command,
-            diffs,
-        ))
+        Ok(diffs)
     }
 
-    fn write_commit(&self, commit: &Commit, mut pagenum: u32) -> Result<(), String> {
+    /// Writes the commit to the deltas file, starting at the given page.
+    /// Returns the page number of the last page that the commit was written to.
+    pub fn write_commit(&self, commit: &Commit, mut pagenum: u32) -> Result<u32, String> {
         let page = &mut self.sread_page(pagenum)?;
         let pagenum = &mut pagenum;
         let offset = &mut 0;
-        self.swrite_type(page, pagenum, offset, 1u8)?;
+        let marker: u8 = if self.compress_commits {
+            COMPRESSED_COMMIT_MARKER
+        } else {
+            UNCOMPRESSED_COMMIT_MARKER
+        };
+        self.swrite_type(page, pagenum, offset, marker)?;
         self.swrite_string(page, pagenum, offset, &commit.user_id, 32)?;
         self.swrite_string(page, pagenum, offset, &commit.hash, 32)?;
         self.swrite_string(page, pagenum, offset, &commit.timestamp, 128)?;
         self.sdwrite_string(page, pagenum, offset, &commit.message)?;
         self.sdwrite_string(page, pagenum, offset, &commit.command)?;
-        // Parsing the diffs
-        self.swrite_type(page, pagenum, offset, commit.diffs.len() as u32)?;
-        for diff in &commit.diffs {
-            self.swrite_type(page, pagenum, offset, diff.get_type() as u32)?;
+
+        if self.compress_commits {
+            let raw: Vec<u8> = self.diffs_to_bytes(&commit.diffs)?;
+            let compressed: Vec<u8> = compress(&raw);
+            self.swrite_type(page, pagenum, offset, raw.len() as u32)?;
+            self.swrite_type(page, pagenum, offset, compressed.len() as u32)?;
+            self.swrite_bytes(page, pagenum, offset, &compressed)?;
+        } else {
+            self.write_diffs(page, pagenum, offset, &commit.diffs)?;
+        }
+        write_page(*pagenum, &self.delta_path, page, PageType::Data)?;
+        Ok(*pagenum)
+    }
+
+    /// Writes the diffs of a commit, starting at the given position in the file.
+    fn write_diffs(
+        &self,
+        page: &mut Page,
+        pagenum: &mut u32,
+        offset: &mut u32,
+        diffs: &Vec<Diff>,
+    ) -> Result<(), String> {
+        self.swrite_type(page, pagenum, offset, diffs.len() as u32)?;
+        for diff in diffs {
+            let ttl: Option<u64> = match diff {
+                Diff::TableCreate(create) => create.ttl,
+                Diff::TableRemove(remove) => remove.ttl,
+                _ => None,
+            };
+            let difftype: u32 = match ttl {
+                Some(_) => diff.get_type() as u32 | TTL_DIFF_FLAG,
+                None => diff.get_type() as u32,
+            };
+            self.swrite_type(page, pagenum, offset, difftype)?;
             self.sdwrite_string(page, pagenum, offset, &diff.get_table_name())?;
             match diff {
                 Diff::Insert(insert) => {
@@ -448,10 +579,16 @@ impl CommitFile {
                 }
                 Diff::TableCreate(create) => {
                     self.swrite_schema(page, pagenum, offset, &create.schema)?;
+                    if let Some(ttl) = create.ttl {
+                        self.swrite_type(page, pagenum, offset, ttl)?;
+                    }
                 }
                 Diff::TableRemove(remove) => {
                     self.swrite_type(page, pagenum, offset, remove.rows_removed.len() as u32)?;
                     self.swrite_schema(page, pagenum, offset, &remove.schema)?;
+                    if let Some(ttl) = remove.ttl {
+                        self.swrite_type(page, pagenum, offset, ttl)?;
+                    }
                     for row in &remove.rows_removed {
                         self.swrite_row(page, pagenum, offset, &row.row, &remove.schema)?;
                         self.swrite_type(page, pagenum, offset, row.pagenum)?;
@@ -464,7 +601,11 @@ impl CommitFile {
 
                     for (index_name, index_id) in &create.indexes {
                         self.sdwrite_string(page, pagenum, offset, index_name)?;
-                        self.swrite_type::<u8>(page, pagenum, offset, index_id.len() as u8)?;
+                        let mut index_id_len: u8 = index_id.len() as u8;
+                        if create.unique_indexes.contains(index_id) {
+                            index_id_len |= UNIQUE_INDEX_DIFF_FLAG;
+                        }
+                        self.swrite_type::<u8>(page, pagenum, offset, index_id_len)?;
                         for index in index_id {
                             self.swrite_type::<u8>(page, pagenum, offset, *index as u8)?;
                         }
@@ -476,18 +617,88 @@ impl CommitFile {
 
                     for (index_name, index_id) in &remove.indexes {
                         self.sdwrite_string(page, pagenum, offset, index_name)?;
-                        self.swrite_type::<u8>(page, pagenum, offset, index_id.len() as u8)?;
+                        let mut index_id_len: u8 = index_id.len() as u8;
+                        if remove.unique_indexes.contains(index_id) {
+                            index_id_len |= UNIQUE_INDEX_DIFF_FLAG;
+                        }
+                        self.swrite_type::<u8>(page, pagenum, offset, index_id_len)?;
                         for index in index_id {
                             self.swrite_type::<u8>(page, pagenum, offset, *index as u8)?;
                         }
                     }
                 }
+                Diff::SchemaChange(change) => {
+                    self.swrite_type(page, pagenum, offset, change.rows.len() as u32)?;
+                    self.swrite_schema(page, pagenum, offset, &change.schema)?;
+                    self.swrite_schema(page, pagenum, offset, &change.new_schema)?;
+                    for row in &change.rows {
+                        self.swrite_row(page, pagenum, offset, &row.row, &change.schema)?;
+                        self.swrite_type(page, pagenum, offset, row.pagenum)?;
+                        self.swrite_type(page, pagenum, offset, row.rownum)?;
+                    }
+                }
             }
         }
-        write_page(*pagenum, &self.delta_path, page, PageType::Data)?;
         Ok(())
     }
 
+    /// Serializes the diffs into bytes, in the same format they have in the deltas file.
+    /// The diffs are written to a scratch in-memory file, since they are written page by page.
+    fn diffs_to_bytes(&self, diffs: &Vec<Diff>) -> Result<Vec<u8>, String> {
+        let scratch: CommitFile = self.scratch_file()?;
+        let page = &mut [0u8; PAGE_SIZE];
+        let pagenum = &mut 0;
+        let offset = &mut 0;
+        let result: Result<(), String> = scratch
+            .write_diffs(page, pagenum, offset, diffs)
+            .and_then(|_| write_page(*pagenum, &scratch.delta_path, page, PageType::Data));
+
+        // Everything before the current position holds the diffs
+        let bytes: Result<Vec<u8>, String> = result.and_then(|_| {
+            let mut bytes: Vec<u8> = Vec::new();
+            for i in 0..=*pagenum {
+                bytes.extend_from_slice(&read_page(i, &scratch.delta_path)?.0[..]);
+            }
+            bytes.truncate(*pagenum as usize * PAGE_SIZE + *offset as usize);
+            Ok(bytes)
+        });
+        get_storage_backend(&scratch.delta_path).remove_file(&scratch.delta_path)?;
+        bytes
+    }
+
+    /// Parses diffs that were serialized with `diffs_to_bytes`.
+    fn diffs_from_bytes(&self, bytes: &[u8]) -> Result<Vec<Diff>, String> {
+        let scratch: CommitFile = self.scratch_file()?;
+        let mut result: Result<(), String> = Ok(());
+        for (i, chunk) in bytes.chunks(PAGE_SIZE).enumerate() {
+            let page = &mut [0u8; PAGE_SIZE];
+            page[..chunk.len()].copy_from_slice(chunk);
+            result = result
+                .and_then(|_| write_page(i as u32, &scratch.delta_path, page, PageType::Data));
+        }
+        let diffs: Result<Vec<Diff>, String> = result.and_then(|_| {
+            let page = &mut scratch.sread_page(0)?;
+            scratch.read_diffs(page, &mut 0, &mut 0)
+        });
+        get_storage_backend(&scratch.delta_path).remove_file(&scratch.delta_path)?;
+        diffs
+    }
+
+    /// Creates an empty in-memory file that (de)compressed diffs can be written to.
+    fn scratch_file(&self) -> Result<CommitFile, String> {
+        let delta_path: String = format!(
+            "{}commit_scratch_{}",
+            IN_MEMORY_PATH_PREFIX,
+            SCRATCH_FILE_COUNTER.fetch_add(1, AtomicOrdering::SeqCst)
+        );
+        get_storage_backend(&delta_path).create_file(&delta_path)?;
+        Ok(CommitFile {
+            delta_path,
+            compress_commits: false,
+            ..self.clone()
+        })
+    }
+
     pub fn get_hashes(&self) -> Result<Vec<String>, String> {
         let mut hashes: Vec<String> = Vec::new();
         for row in self.header_table.clone().into_iter() {
@@ -847,6 +1058,21 @@ impl CommitFile {
                     Diff::IndexRemove(remove) => {
                         add_diff(&mut map, diff.clone(), remove.table_name.clone());
                     }
+                    // Two schema changes become one, from the first schema to the last
+                    Diff::SchemaChange(change) => {
+                        let mut change: SchemaChangeDiff = change.clone();
+                        if let Some(Diff::SchemaChange(existing)) =
+                            get_diff(&map, &change.table_name, SCHEMA_CHANGE_TYPE)
+                        {
+                            change.schema = existing.schema.clone();
+                            change.rows = existing.rows.clone();
+                        }
+                        add_diff(
+                            &mut map,
+                            Diff::SchemaChange(change.clone()),
+                            change.table_name.clone(),
+                        );
+                    }
                 }
             }
         }
@@ -898,7 +1124,8 @@ mod tests {
         executor::query::{create_table, insert},
         fileio::databaseio::{delete_db_instance, get_db_instance, Database},
         user::userdata::User,
-        util::{bench::fcreate_db_instance, row::RowLocation},
+        util::{bench::fcreate_db_instance, clock::TestClock, row::RowLocation},
+        version_control::merge::MergeConflictResolutionAlgo,
     };
 
     use super::*;
@@ -921,6 +1148,7 @@ mod tests {
             vec![Diff::TableCreate(TableCreateDiff {
                 table_name: "test_table".to_string(),
                 schema: schema.clone(),
+                ttl: Some(3600),
             })],
         );
         delta.write_commit(&commit, 0).unwrap();
@@ -950,11 +1178,13 @@ mod tests {
                 Diff::TableCreate(TableCreateDiff {
                     table_name: "test_table".to_string(),
                     schema: schema.clone(),
+                    ttl: None,
                 }),
                 Diff::TableRemove(TableRemoveDiff {
                     table_name: "test_table".to_string(),
                     schema: schema.clone(),
                     rows_removed: vec![],
+                    ttl: None,
                 }),
             ],
         );
@@ -1046,6 +1276,7 @@ mod tests {
             vec![Diff::TableCreate(TableCreateDiff {
                 table_name: "test_table".to_string(),
                 schema: schema.clone(),
+                ttl: None,
             })],
         );
         let commit2 = Commit::new(
@@ -1139,6 +1370,7 @@ mod tests {
             vec![Diff::TableCreate(TableCreateDiff {
                 table_name: "test_table".to_string(),
                 schema: schema.clone(),
+                ttl: None,
             })],
         );
         let commit2 = Commit::new(
@@ -1230,6 +1462,7 @@ mod tests {
             vec![Diff::TableCreate(TableCreateDiff {
                 table_name: "test_table".to_string(),
                 schema: schema.clone(),
+                ttl: None,
             })],
         );
         let commit2 = Commit::new(
@@ -1503,6 +1736,7 @@ mod tests {
             vec![Diff::TableCreate(TableCreateDiff {
                 table_name: "test_table".to_string(),
                 schema: schema.clone(),
+                ttl: None,
             })],
         );
         let commit2 = Commit::new(
@@ -1537,4 +1771,165 @@ mod tests {
         std::fs::remove_file(delta.delta_path).unwrap();
         std::fs::remove_file(delta.header_path).unwrap();
     }
+
+    #[test]
+    #[serial]
+    fn test_commit_clock() {
+        fcreate_db_instance("test_commit_clock");
+        let mut user: User = User::new("test_user".to_string());
+        let schema: Schema = vec![("id".to_string(), Column::I32)];
+
+        // The clock only moves when it's set, so each commit's timestamp is known ahead of time
+        let clock: TestClock = TestClock::start_at(0);
+        let times: Vec<u64> = vec![1_000, 2_000, 3_000];
+        let mut commits: Vec<Commit> = Vec::new();
+        for (i, time) in times.iter().enumerate() {
+            let (_, table_diff) = create_table(
+                &format!("table{}", i),
+                &schema,
+                get_db_instance().unwrap(),
+                &mut user,
+            )
+            .unwrap();
+            user.append_diff(&Diff::TableCreate(table_diff));
+
+            clock.set_millis(*time);
+            let (_, commit) = get_db_instance()
+                .unwrap()
+                .create_commit_on_head(&format!("Commit {}", i), &"".to_string(), &mut user, None)
+                .unwrap();
+            commits.push(commit);
+        }
+        drop(clock);
+
+        for (commit, time) in commits.iter().zip(&times) {
+            assert_eq!(commit.timestamp, time.to_string());
+        }
+
+        // The log lists the newest commit first, which is the one with the latest timestamp
+        let (_, log, _) = crate::version_control::command::log(&user).unwrap();
+        let log_times: Vec<String> = log.iter().map(|entry| entry[2].clone()).collect();
+        assert_eq!(log_times, vec!["3000", "2000", "1000"]);
+
+        // Once the test clock is dropped, commits go back to the system time
+        let (_, table_diff) = create_table(
+            &"table3".to_string(),
+            &schema,
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
+        user.append_diff(&Diff::TableCreate(table_diff));
+        let (_, commit) = get_db_instance()
+            .unwrap()
+            .create_commit_on_head(&"Commit 3".to_string(), &"".to_string(), &mut user, None)
+            .unwrap();
+        assert!(commit.timestamp.parse::<u128>().unwrap() > 3_000);
+
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_compressed_commits() {
+        fcreate_db_instance("test_compressed_commits");
+        let mut user: User = User::new("test_user".to_string());
+        let table_name: String = "test_table".to_string();
+        let schema: Schema = vec![
+            ("id".to_string(), Column::I32),
+            ("name".to_string(), Column::String(50)),
+        ];
+
+        // The first commit is written before compression is turned on
+        create_table(&table_name, &schema, get_db_instance().unwrap(), &mut user).unwrap();
+        let (_, uncompressed_commit) = get_db_instance()
+            .unwrap()
+            .create_commit_on_head(
+                &"Create Table".to_string(),
+                &"Create Table;".to_string(),
+                &mut user,
+                None,
+            )
+            .unwrap();
+
+        // Make a commit on a new branch, with enough rows to span several pages
+        get_db_instance()
+            .unwrap()
+            .set_commit_compression(true)
+            .unwrap();
+        let branch_name: String = "compressed_branch".to_string();
+        get_db_instance()
+            .unwrap()
+            .create_branch(&branch_name, &None, &mut user)
+            .unwrap();
+        let rows: Vec<Row> = (0..500)
+            .map(|i| vec![Value::I32(i), Value::String(format!("Name {}", i))])
+            .collect();
+        insert(
+            rows.clone(),
+            table_name.clone(),
+            get_db_instance().unwrap(),
+            &mut user,
+        )
+        .unwrap();
+        let diffs: Vec<Diff> = user.get_diffs();
+        let (_, compressed_commit) = get_db_instance()
+            .unwrap()
+            .create_commit_on_head(
+                &"Insert".to_string(),
+                &"Insert;".to_string(),
+                &mut user,
+                None,
+            )
+            .unwrap();
+
+        // The compressed commit round-trips to the same diffs, and takes up less space
+        let commit_file: CommitFile = get_db_instance().unwrap().get_commit_file_mut().clone();
+        let fetched: Commit = commit_file.fetch_commit(&compressed_commit.hash).unwrap();
+        assert_eq!(fetched, compressed_commit);
+        assert_eq!(fetched.diffs, diffs);
+        let header: CommitHeader = commit_file
+            .find_header(compressed_commit.hash.clone())
+            .unwrap()
+            .unwrap();
+        let page = read_page(header.pagenum, &commit_file.delta_path)
+            .unwrap()
+            .0;
+        assert_eq!(read_type::<u8>(&page, 0).unwrap(), COMPRESSED_COMMIT_MARKER);
+        let raw: Vec<u8> = commit_file.diffs_to_bytes(&diffs).unwrap();
+        assert!(raw.len() > PAGE_SIZE);
+        assert!(compress(&raw).len() < raw.len() / 2);
+
+        // The commit made before compression was turned on can still be read
+        assert_eq!(
+            commit_file.fetch_commit(&uncompressed_commit.hash).unwrap(),
+            uncompressed_commit
+        );
+
+        // Merging the branch uses the compressed commit
+        get_db_instance()
+            .unwrap()
+            .switch_branch(
+                &get_db_instance().unwrap().get_main_branch_name(),
+                &mut user,
+            )
+            .unwrap();
+        get_db_instance()
+            .unwrap()
+            .merge_branches(
+                &branch_name,
+                &mut user,
+                &"Merge Commit".to_string(),
+                true,
+                MergeConflictResolutionAlgo::NoConflicts,
+                false,
+            )
+            .unwrap();
+        let table: Table =
+            Table::from_user(&user, get_db_instance().unwrap(), &table_name, None).unwrap();
+        let merged_rows: Vec<Row> = table.into_iter().map(|row_info| row_info.row).collect();
+        assert_eq!(merged_rows, rows);
+
+        delete_db_instance().unwrap();
+    }
 }
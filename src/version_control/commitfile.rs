@@ -12,6 +12,7 @@ pub struct CommitFile {
     pub header_path: String,
     pub delta_path: String,
     pub header_table: Table,
+    pub compress_commits: bool, // Whether new commits are compressed before they are written
 }
 
 impl CommitFile {
@@ -130,6 +131,56 @@ impl CommitFile {
         Ok(())
     }
 
+    // Safe read - a run of raw bytes, which can span any number of pages
+    pub fn sread_bytes(
+        &self,
+        page: &mut Page,
+        pagenum: &mut u32,
+        offset: &mut u32,
+        size: u32,
+    ) -> Result<Vec<u8>, String> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(size as usize);
+        while bytes.len() < size as usize {
+            // If the page is full, read the next page and reset the offset
+            if *offset as usize >= PAGE_SIZE {
+                *offset = 0;
+                *pagenum = *pagenum + 1;
+                *page = *read_page(*pagenum, &self.delta_path)?.0;
+            }
+            let chunk_size: usize = (size as usize - bytes.len()).min(PAGE_SIZE - *offset as usize);
+            let start: usize = *offset as usize;
+            bytes.extend_from_slice(&page[start..start + chunk_size]);
+            *offset = *offset + chunk_size as u32;
+        }
+        Ok(bytes)
+    }
+
+    // Safe write - a run of raw bytes, which can span any number of pages
+    pub fn swrite_bytes(
+        &self,
+        page: &mut Page,
+        pagenum: &mut u32,
+        offset: &mut u32,
+        bytes: &[u8],
+    ) -> Result<(), String> {
+        let mut written: usize = 0;
+        while written < bytes.len() {
+            // If the page is full, write it out and move on to the next page
+            if *offset as usize >= PAGE_SIZE {
+                write_page(*pagenum, &self.delta_path, page, PageType::Data)?;
+                *offset = 0;
+                *pagenum = *pagenum + 1;
+                *page = *self.sread_page(*pagenum)?;
+            }
+            let chunk_size: usize = (bytes.len() - written).min(PAGE_SIZE - *offset as usize);
+            let start: usize = *offset as usize;
+            page[start..start + chunk_size].copy_from_slice(&bytes[written..written + chunk_size]);
+            written += chunk_size;
+            *offset = *offset + chunk_size as u32;
+        }
+        Ok(())
+    }
+
     pub fn sread_row(
         &self,
         page: &mut Page,
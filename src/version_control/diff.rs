@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use crate::{
     btree::{btree::BTree, indexes::IndexID},
     fileio::{header::*, tableio::*},
-    util::row::*,
+    util::{dbtype::Value, row::*},
 };
 
 /* Constants */
@@ -15,6 +15,10 @@ pub const TABLE_CREATE_TYPE: i32 = 3;
 pub const TABLE_REMOVE_TYPE: i32 = 4;
 pub const INDEX_CREATE_TYPE: i32 = 5;
 pub const INDEX_REMOVE_TYPE: i32 = 6;
+pub const SCHEMA_CHANGE_TYPE: i32 = 7;
+
+/// A column whose value was changed by an update, as (column name, old value, new value)
+pub type ColumnChange = (String, Value, Value);
 
 /***************************************************************************************************/
 /*                                         Diff Structs                                            */
@@ -29,6 +33,7 @@ pub enum Diff {
     TableRemove(TableRemoveDiff),
     IndexCreate(IndexCreateDiff),
     IndexRemove(IndexRemoveDiff),
+    SchemaChange(SchemaChangeDiff),
 }
 
 impl ToString for Diff {
@@ -71,6 +76,10 @@ impl ToString for Diff {
                 diff.table_name,
                 diff.schema
             ),
+            Diff::SchemaChange(diff) => format!(
+                "\nCHANGED SCHEMA\nChanged Table {} from Schemas {:?} to Schemas {:?}",
+                diff.table_name, diff.schema, diff.new_schema
+            ),
         }
     }
 }
@@ -85,6 +94,7 @@ impl Diff {
             Diff::TableRemove(diff) => diff.table_name.clone(),
             Diff::IndexCreate(diff) => diff.table_name.clone(),
             Diff::IndexRemove(diff) => diff.table_name.clone(),
+            Diff::SchemaChange(diff) => diff.table_name.clone(),
         }
     }
 
@@ -97,6 +107,7 @@ impl Diff {
             Diff::TableRemove(diff) => diff.schema.clone(),
             Diff::IndexCreate(diff) => diff.schema.clone(),
             Diff::IndexRemove(diff) => diff.schema.clone(),
+            Diff::SchemaChange(diff) => diff.new_schema.clone(),
         }
     }
 
@@ -109,6 +120,7 @@ impl Diff {
             Diff::TableRemove(_) => Err("Cannot get rows from a TableRemoveDiff".to_string()),
             Diff::IndexCreate(_) => Err("Cannot get rows from a IndexCreateDiff".to_string()),
             Diff::IndexRemove(_) => Err("Cannot get rows from a IndexRemoveDiff".to_string()),
+            Diff::SchemaChange(diff) => Ok(diff.rows.clone()),
         }
     }
 
@@ -121,6 +133,7 @@ impl Diff {
             Diff::TableRemove(_) => TableRemoveDiff::get_type(),
             Diff::IndexCreate(_) => IndexCreateDiff::get_type(),
             Diff::IndexRemove(_) => IndexRemoveDiff::get_type(),
+            Diff::SchemaChange(_) => SchemaChangeDiff::get_type(),
         }
     }
 
@@ -133,6 +146,7 @@ impl Diff {
             Diff::TableRemove(_) => false,
             Diff::IndexCreate(_) => false,
             Diff::IndexRemove(_) => false,
+            Diff::SchemaChange(_) => false,
         }
     }
 }
@@ -163,6 +177,7 @@ pub struct RemoveDiff {
 pub struct TableCreateDiff {
     pub table_name: String, // The name of the table that was created.
     pub schema: Schema,
+    pub ttl: Option<u64>, // The number of seconds that the table keeps its rows for, if it expires them
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -170,6 +185,7 @@ pub struct TableRemoveDiff {
     pub table_name: String, // The name of the table that was removed.
     pub schema: Schema,
     pub rows_removed: Vec<RowInfo>, // The rows that were removed from the table.
+    pub ttl: Option<u64>, // The number of seconds that the table kept its rows for, if it expired them
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -177,6 +193,7 @@ pub struct IndexCreateDiff {
     pub table_name: String, // The name of the table that had an index created.
     pub schema: Schema,
     pub indexes: Vec<(String, IndexID)>, // The name of the index and the index id.
+    pub unique_indexes: Vec<IndexID>,    // The ids of the indexes that are unique.
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -184,6 +201,15 @@ pub struct IndexRemoveDiff {
     pub table_name: String, // The name of the table that had an index created.
     pub schema: Schema,
     pub indexes: Vec<(String, IndexID)>, // The name of the index and the index id.
+    pub unique_indexes: Vec<IndexID>,    // The ids of the indexes that are unique.
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaChangeDiff {
+    pub table_name: String, // The name of the table that had its columns changed.
+    pub schema: Schema,     // The schema of the table before the change
+    pub new_schema: Schema, // The schema of the table after the change
+    pub rows: Vec<RowInfo>, // The rows before the change, if it dropped any of their values.
 }
 
 /// This represents a set of diffs that would result from squashing a series of diffs together.
@@ -203,6 +229,7 @@ pub struct TableSquashDiff {
     pub table_remove_diff: Option<TableRemoveDiff>,
     pub index_create_diff: IndexCreateDiff,
     pub index_remove_diff: IndexRemoveDiff,
+    pub schema_change_diff: Option<SchemaChangeDiff>,
 }
 
 /***************************************************************************************************/
@@ -214,6 +241,56 @@ impl UpdateDiff {
     pub fn get_type() -> i32 {
         UPDATE_TYPE
     }
+
+    /// Pairs each updated row with its old row, and gets the columns whose value changed.
+    /// Rows that didn't change are left out.
+    pub fn changed_columns(&self) -> Vec<(RowLocation, Vec<ColumnChange>)> {
+        let old_rows: HashMap<(u32, u16), &Row> = self
+            .old_rows
+            .iter()
+            .map(|rowinfo| ((rowinfo.pagenum, rowinfo.rownum), &rowinfo.row))
+            .collect();
+        self.rows
+            .iter()
+            .filter_map(|rowinfo| {
+                let old_row: &Row = old_rows.get(&(rowinfo.pagenum, rowinfo.rownum))?;
+                let changes: Vec<ColumnChange> = self
+                    .schema
+                    .iter()
+                    .zip(old_row.iter().zip(rowinfo.row.iter()))
+                    .filter(|(_, (old, new))| old != new)
+                    .map(|((name, _), (old, new))| (name.clone(), old.clone(), new.clone()))
+                    .collect();
+                match changes.is_empty() {
+                    true => None,
+                    false => Some((rowinfo.get_row_location(), changes)),
+                }
+            })
+            .collect()
+    }
+
+    /// Renders the update as one line per row, showing only the columns that changed,
+    /// like `Row (page 1, row 2): name: 'John' -> 'Jim'`
+    pub fn format_changes(&self) -> String {
+        self.changed_columns()
+            .iter()
+            .map(|(location, changes)| {
+                let changes: Vec<String> = changes
+                    .iter()
+                    .map(|(name, old, new)| {
+                        format!("{}: {} -> {}", name, old.to_sql_value(), new.to_sql_value())
+                    })
+                    .collect();
+                format!(
+                    "Row (page {}, row {}): {}",
+                    location.pagenum,
+                    location.rownum,
+                    changes.join(", ")
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
 }
 
 impl InsertDiff {
@@ -258,6 +335,13 @@ impl IndexRemoveDiff {
     }
 }
 
+impl SchemaChangeDiff {
+    /// Gets the type for the diff.
+    pub fn get_type() -> i32 {
+        SCHEMA_CHANGE_TYPE
+    }
+}
+
 impl SquashDiffs {
     /// Creates a new empty squash diff object
     pub fn new() -> SquashDiffs {
@@ -293,12 +377,15 @@ impl TableSquashDiff {
                 table_name: table_name.clone(),
                 schema: schema.clone(),
                 indexes: Vec::new(),
+                unique_indexes: Vec::new(),
             },
             index_remove_diff: IndexRemoveDiff {
                 table_name: table_name.clone(),
                 schema: schema.clone(),
                 indexes: Vec::new(),
+                unique_indexes: Vec::new(),
             },
+            schema_change_diff: None,
         }
     }
 }
@@ -345,6 +432,12 @@ impl PartialOrd for IndexRemoveDiff {
     }
 }
 
+impl PartialOrd for SchemaChangeDiff {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.table_name.cmp(&other.table_name))
+    }
+}
+
 /// This method takes in a directory along with the diffs that are to be applied to it and applies them.
 /// There are a couple assumptions:
 /// 1. The table_dir exists and is where the table files are/will be stored.
@@ -370,11 +463,7 @@ pub fn construct_tables_from_diffs(table_dir: &String, diffs: &Vec<Diff>) -> Res
                 table.remove_rows(row_locations_removed)?;
             }
             Diff::TableCreate(table_create_diff) => {
-                create_table_in_dir(
-                    &table_create_diff.table_name,
-                    &table_create_diff.schema,
-                    table_dir,
-                )?;
+                create_table_from_diff(table_create_diff, table_dir)?;
             }
             Diff::TableRemove(table_remove_diff) => {
                 delete_table_in_dir(&table_remove_diff.table_name, table_dir)?;
@@ -392,6 +481,7 @@ pub fn construct_tables_from_diffs(table_dir: &String, diffs: &Vec<Diff>) -> Res
                         None,
                         columns,
                         index_name.clone(),
+                        index_create_diff.unique_indexes.contains(index_id),
                     )?;
                 }
             }
@@ -405,6 +495,10 @@ pub fn construct_tables_from_diffs(table_dir: &String, diffs: &Vec<Diff>) -> Res
                     )?;
                 }
             }
+            Diff::SchemaChange(schema_change_diff) => {
+                let mut table = Table::new(table_dir, &schema_change_diff.table_name, None)?;
+                table.change_schema(&schema_change_diff.new_schema)?;
+            }
         }
     }
     Ok(())
@@ -441,13 +535,13 @@ pub fn revert_tables_from_diffs(table_dir: &String, diffs: &Vec<Diff>) -> Result
                 delete_table_in_dir(&table_create_diff.table_name, table_dir)?;
             }
             Diff::TableRemove(table_remove_diff) => {
-                create_table_in_dir(
-                    &table_remove_diff.table_name,
-                    &table_remove_diff.schema,
-                    table_dir,
-                )?;
+                let create_diff: TableCreateDiff = TableCreateDiff {
+                    table_name: table_remove_diff.table_name.clone(),
+                    schema: table_remove_diff.schema.clone(),
+                    ttl: table_remove_diff.ttl,
+                };
                 // We need to insert the rows back into the table
-                let mut table = Table::new(table_dir, &table_remove_diff.table_name, None)?;
+                let mut table = create_table_from_diff(&create_diff, table_dir)?;
                 table.write_rows(table_remove_diff.rows_removed.clone())?;
             }
             Diff::IndexCreate(index_create_diff) => {
@@ -473,9 +567,16 @@ pub fn revert_tables_from_diffs(table_dir: &String, diffs: &Vec<Diff>) -> Result
                         None,
                         columns,
                         index_name.clone(),
+                        index_remove_diff.unique_indexes.contains(index_id),
                     )?;
                 }
             }
+            Diff::SchemaChange(schema_change_diff) => {
+                let mut table = Table::new(table_dir, &schema_change_diff.table_name, None)?;
+                table.change_schema(&schema_change_diff.schema)?;
+                // Put back the values of the columns that were dropped
+                table.rewrite_rows(schema_change_diff.rows.clone())?;
+            }
         }
     }
     Ok(())
@@ -522,6 +623,7 @@ pub fn reverse_diffs(diffs: &Vec<Diff>) -> Result<Vec<Diff>, String> {
                     table_name: table_create_diff.table_name.clone(),
                     schema: table_create_diff.schema.clone(),
                     rows_removed: Vec::new(),
+                    ttl: table_create_diff.ttl,
                 });
                 inverted_diffs.push(curr_diff);
             }
@@ -529,6 +631,7 @@ pub fn reverse_diffs(diffs: &Vec<Diff>) -> Result<Vec<Diff>, String> {
                 let curr_diff: Diff = Diff::TableCreate(TableCreateDiff {
                     table_name: table_remove_diff.table_name.clone(),
                     schema: table_remove_diff.schema.clone(),
+                    ttl: table_remove_diff.ttl,
                 });
                 inverted_diffs.push(curr_diff);
             }
@@ -537,6 +640,7 @@ pub fn reverse_diffs(diffs: &Vec<Diff>) -> Result<Vec<Diff>, String> {
                     table_name: index_create_diff.table_name.clone(),
                     schema: index_create_diff.schema.clone(),
                     indexes: index_create_diff.indexes.clone(),
+                    unique_indexes: index_create_diff.unique_indexes.clone(),
                 }));
             }
             Diff::IndexRemove(index_remove_diff) => {
@@ -544,8 +648,41 @@ pub fn reverse_diffs(diffs: &Vec<Diff>) -> Result<Vec<Diff>, String> {
                     table_name: index_remove_diff.table_name.clone(),
                     schema: index_remove_diff.schema.clone(),
                     indexes: index_remove_diff.indexes.clone(),
+                    unique_indexes: index_remove_diff.unique_indexes.clone(),
                 }));
             }
+            // The columns are changed back, and then the values of the columns that were dropped
+            // are put back, as they come back null
+            Diff::SchemaChange(schema_change_diff) => {
+                inverted_diffs.push(Diff::SchemaChange(SchemaChangeDiff {
+                    table_name: schema_change_diff.table_name.clone(),
+                    schema: schema_change_diff.new_schema.clone(),
+                    new_schema: schema_change_diff.schema.clone(),
+                    rows: Vec::new(),
+                }));
+                if !schema_change_diff.rows.is_empty() {
+                    let old_rows: Vec<RowInfo> = schema_change_diff
+                        .rows
+                        .iter()
+                        .map(|row_info| {
+                            let schema: &Schema = &schema_change_diff.schema;
+                            let new_schema: &Schema = &schema_change_diff.new_schema;
+                            let row: Row = convert_row(&row_info.row, schema, new_schema)?;
+                            Ok(RowInfo {
+                                row: convert_row(&row, new_schema, schema)?,
+                                pagenum: row_info.pagenum,
+                                rownum: row_info.rownum,
+                            })
+                        })
+                        .collect::<Result<Vec<RowInfo>, String>>()?;
+                    inverted_diffs.push(Diff::Update(UpdateDiff {
+                        table_name: schema_change_diff.table_name.clone(),
+                        schema: schema_change_diff.schema.clone(),
+                        rows: schema_change_diff.rows.clone(),
+                        old_rows,
+                    }));
+                }
+            }
         }
     }
     Ok(inverted_diffs)
@@ -773,6 +910,76 @@ mod tests {
         std::fs::remove_dir_all(&dir_to_build_in).unwrap();
     }
 
+    #[test]
+    fn test_update_diff_changed_columns() {
+        let row = |pagenum: u32, rownum: u16, values: Vec<Value>| RowInfo {
+            row: values,
+            pagenum,
+            rownum,
+        };
+        let diff: UpdateDiff = UpdateDiff {
+            table_name: "test_table".to_string(),
+            schema: vec![
+                ("id".to_string(), Column::I32),
+                ("name".to_string(), Column::String(50)),
+                ("age".to_string(), Column::I32),
+            ],
+            rows: vec![
+                row(
+                    1,
+                    4,
+                    vec![
+                        Value::I32(2),
+                        Value::String("Jane".to_string()),
+                        Value::I32(22),
+                    ],
+                ),
+                row(
+                    1,
+                    0,
+                    vec![
+                        Value::I32(1),
+                        Value::String("John".to_string()),
+                        Value::I32(20),
+                    ],
+                ),
+            ],
+            old_rows: vec![
+                row(
+                    1,
+                    0,
+                    vec![
+                        Value::I32(1),
+                        Value::String("John".to_string()),
+                        Value::I32(20),
+                    ],
+                ),
+                row(
+                    1,
+                    4,
+                    vec![
+                        Value::I32(2),
+                        Value::String("Jane".to_string()),
+                        Value::I32(21),
+                    ],
+                ),
+            ],
+        };
+
+        // Only the age of the second row changed
+        assert_eq!(
+            diff.changed_columns(),
+            vec![(
+                RowLocation {
+                    pagenum: 1,
+                    rownum: 4
+                },
+                vec![("age".to_string(), Value::I32(21), Value::I32(22))]
+            )]
+        );
+        assert_eq!(diff.format_changes(), "Row (page 1, row 4): age: 21 -> 22");
+    }
+
     #[test]
     #[serial]
     fn test_update_diff() {
@@ -1494,6 +1701,12 @@ mod tests {
             return false;
         }
 
+        // The versions count the writes that each table went through, which differ even when
+        // the tables ended up with the same rows, so they're left out of the comparison
+        let mut header2: Header = read_header(&table2.path).unwrap();
+        header2.version = read_header(&table1.path).unwrap().version;
+        write_header(&table2.path, &header2).unwrap();
+
         if !diff(&table1.path, &table2.path) {
             return false;
         }
@@ -0,0 +1,353 @@
+use super::{branches::BranchNode, commit::Commit, commitfile::CommitFile};
+use crate::fileio::{
+    databaseio::get_db_instance,
+    pageio::*,
+    storage::{get_storage_backend, resolve_path_within_dir},
+};
+use crate::user::{usercreds::UserPermissions::Admin, userdata::User};
+use crate::util::error::Error;
+use parking_lot::{const_mutex, Mutex};
+
+// Written at the start of every dump file, so other files aren't restored by mistake
+const DUMP_FILE_MAGIC: &str = "GQL BRANCH DUMP";
+const DUMP_FILE_MAGIC_SIZE: u32 = 16;
+
+// The directory that dump files are written to and restored from, unless the server sets another
+pub const DEFAULT_DUMP_DIR: &str = "dumps";
+
+// The directory set with --dump-dir, or None to use the default one
+static DUMP_DIR: Mutex<Option<String>> = const_mutex(None);
+
+/// Sets the directory that dump files are written to and restored from.
+pub fn set_dump_dir(dir: &String) {
+    *DUMP_DIR.lock() = Some(dir.clone());
+}
+
+/// Returns the path of the dump file with the given name. Dump files can only be in the dump
+/// directory, so the name can't be an absolute path or go up out of it with `..`.
+pub fn get_dump_path(file_name: &String) -> Result<String, String> {
+    let dump_dir: String = DUMP_DIR
+        .lock()
+        .clone()
+        .unwrap_or(DEFAULT_DUMP_DIR.to_string());
+    resolve_path_within_dir(&dump_dir, file_name)
+}
+
+/// A branch dump holds the whole commit history of a branch in a single file.
+/// The tables, schemas and indexes of the branch are recreated from the diffs of the commits.
+///
+/// Page 0 of the file holds the dump's information: the magic string, the branch name,
+/// the number of commits and the page where the list of commit pages starts.
+/// The commits are written from page 1 onwards, in the same format as the deltas file,
+/// and are followed by the list of the pages that each commit starts on.
+#[derive(Clone, Debug)]
+pub struct BranchDump {
+    pub branch_name: String,
+    pub commits: Vec<Commit>, // The commits in the order they were made
+}
+
+/// This function implements the GQL dump command, which only admins can run.
+/// It writes the history of the given branch into the dump file with the given name.
+pub fn dump_branch(branch_name: &String, file_name: &String, user: &User) -> Result<String, Error> {
+    if user.get_permissions() != Admin {
+        return Err(Error::permission_denied(
+            "You do not have permission to dump branches",
+        ));
+    }
+    let file_path: String = get_dump_path(file_name)?;
+    let branch_heads = get_db_instance()?.get_branch_heads_file_mut();
+    if !branch_heads.does_branch_exist(branch_name.clone())? {
+        return Err(Error::not_found(format!(
+            "Branch {} does not exist",
+            branch_name
+        )));
+    }
+    let branch_node: BranchNode = branch_heads
+        .get_branch_node_from_head(branch_name, get_db_instance()?.get_branch_file())?;
+
+    // The branch nodes go from the head back to the first commit, so reverse them
+    let branch_nodes: Vec<BranchNode> = get_db_instance()?
+        .get_branch_file()
+        .traverse_branch_nodes(&branch_node)?;
+    let commits: Vec<Commit> = branch_nodes
+        .iter()
+        .rev()
+        .map(|node| {
+            get_db_instance()?
+                .get_commit_file_mut()
+                .fetch_commit(&node.commit_hash)
+        })
+        .collect::<Result<Vec<Commit>, String>>()?;
+
+    let dump: BranchDump = BranchDump {
+        branch_name: branch_name.clone(),
+        commits,
+    };
+    write_dump_file(&file_path, &dump)?;
+    Ok(format!(
+        "Dumped {} commits from branch {} to {}",
+        dump.commits.len(),
+        branch_name,
+        file_name
+    ))
+}
+
+/// This function implements the GQL restore command, which only admins can run.
+/// It recreates the branch stored in the dump file with the given name, along with its commit history.
+pub fn restore_branch(file_name: &String, user: &User) -> Result<String, Error> {
+    if user.get_permissions() != Admin {
+        return Err(Error::permission_denied(
+            "You do not have permission to restore branches",
+        ));
+    }
+    let dump: BranchDump = read_dump_file(&get_dump_path(file_name)?)?;
+    get_db_instance()?.restore_branch_commits(&dump.branch_name, &dump.commits)?;
+    Ok(format!(
+        "Restored {} commits to branch {}",
+        dump.commits.len(),
+        dump.branch_name
+    ))
+}
+
+/// Writes the dump into a new file at the given path, replacing any file that's there.
+pub fn write_dump_file(file_path: &String, dump: &BranchDump) -> Result<(), String> {
+    get_storage_backend(file_path).create_file(file_path)?;
+    let dump_file: CommitFile = open_dump_file(file_path)?;
+
+    // Write the commits, each one starting on a new page
+    let mut commit_pagenums: Vec<u32> = Vec::new();
+    let mut pagenum: u32 = 1;
+    for commit in &dump.commits {
+        commit_pagenums.push(pagenum);
+        pagenum = dump_file.write_commit(commit, pagenum)? + 1;
+    }
+
+    // Write the list of pages that the commits start on
+    let list_pagenum: u32 = pagenum;
+    let page = &mut [0u8; PAGE_SIZE];
+    let offset = &mut 0;
+    let pagenum = &mut pagenum;
+    for commit_pagenum in &commit_pagenums {
+        dump_file.swrite_type(page, pagenum, offset, *commit_pagenum)?;
+    }
+    write_page(*pagenum, file_path, page, PageType::Data)?;
+
+    // Write the information about the dump
+    let page = &mut [0u8; PAGE_SIZE];
+    let offset = &mut 0;
+    let pagenum = &mut 0;
+    dump_file.swrite_string(page, pagenum, offset, DUMP_FILE_MAGIC, DUMP_FILE_MAGIC_SIZE)?;
+    dump_file.sdwrite_string(page, pagenum, offset, &dump.branch_name)?;
+    dump_file.swrite_type(page, pagenum, offset, commit_pagenums.len() as u32)?;
+    dump_file.swrite_type(page, pagenum, offset, list_pagenum)?;
+    write_page(*pagenum, file_path, page, PageType::Data)
+}
+
+/// Reads the dump stored in the file at the given path.
+pub fn read_dump_file(file_path: &String) -> Result<BranchDump, String> {
+    let dump_file: CommitFile = open_dump_file(file_path)?;
+
+    let page = &mut read_page(0, file_path)?.0;
+    let offset = &mut 0;
+    let pagenum = &mut 0;
+    let magic: String = dump_file.sread_string(page, pagenum, offset, DUMP_FILE_MAGIC_SIZE)?;
+    if magic != DUMP_FILE_MAGIC {
+        return Err(format!("{} is not a branch dump", file_path));
+    }
+    let branch_name: String = dump_file.sdread_string(page, pagenum, offset)?;
+    let num_commits: u32 = dump_file.sread_type(page, pagenum, offset)?;
+    let list_pagenum: u32 = dump_file.sread_type(page, pagenum, offset)?;
+
+    let page = &mut read_page(list_pagenum, file_path)?.0;
+    let offset = &mut 0;
+    let pagenum = &mut list_pagenum.clone();
+    let mut commits: Vec<Commit> = Vec::new();
+    for _ in 0..num_commits {
+        let commit_pagenum: u32 = dump_file.sread_type(page, pagenum, offset)?;
+        commits.push(dump_file.read_commit(commit_pagenum)?);
+    }
+    Ok(BranchDump {
+        branch_name,
+        commits,
+    })
+}
+
+// The commits are written in the same format as the deltas file, so we reuse the
+// database's commit file, but have it read and write the dump file instead.
+fn open_dump_file(file_path: &String) -> Result<CommitFile, String> {
+    if !get_storage_backend(file_path).exists(file_path) {
+        return Err(format!("Dump file {} does not exist", file_path));
+    }
+    Ok(CommitFile {
+        delta_path: file_path.clone(),
+        ..get_db_instance()?.get_commit_file_mut().clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::query::{execute_query, execute_update},
+        fileio::databaseio::delete_db_instance,
+        parser::parser::{parse, parse_vc_cmd},
+        util::{
+            bench::{create_demo_db, fcreate_db_instance},
+            row::Row,
+        },
+        version_control::command,
+    };
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_dump_and_restore_branch() {
+        let dump_name: String = "test_dump_and_restore_branch.gql".to_string();
+        let queries: Vec<&str> = vec!["SELECT * FROM personal_info", "SELECT * FROM locations"];
+
+        // Dump the main branch of the demo database
+        let mut user: User = create_demo_db("dump_branch");
+        user.set_permissions(&Admin);
+        let main_branch: String = get_db_instance().unwrap().get_main_branch_name();
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&main_branch, &mut user)
+            .unwrap();
+        let expected_results: Vec<Vec<Row>> = queries
+            .iter()
+            .map(|query| execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()))
+            .map(|result| result.unwrap().1)
+            .collect();
+        let (_, expected_log, _) = command::log(&user).unwrap();
+        assert!(expected_log.len() > 0);
+        parse_vc_cmd(
+            &format!("gql dump {} {}", main_branch, dump_name),
+            &mut user,
+            vec![],
+        )
+        .unwrap();
+        delete_db_instance().unwrap();
+
+        // Restore it into a fresh database
+        fcreate_db_instance("dump_branch_restore");
+        let mut user: User = User::new("test_user".to_string());
+        user.set_permissions(&Admin);
+        let message: String =
+            parse_vc_cmd(&format!("gql restore {}", dump_name), &mut user, vec![]).unwrap();
+        assert_eq!(
+            message,
+            format!(
+                "Restored {} commits to branch {}",
+                expected_log.len(),
+                user.get_current_branch_name()
+            )
+        );
+
+        let results: Vec<Vec<Row>> = queries
+            .iter()
+            .map(|query| execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string()))
+            .map(|result| result.unwrap().1)
+            .collect();
+        assert_eq!(results, expected_results);
+        let (_, log, _) = command::log(&user).unwrap();
+        assert_eq!(log, expected_log);
+
+        // The branch can't be restored a second time
+        assert!(restore_branch(&dump_name, &user).is_err());
+
+        std::fs::remove_file(get_dump_path(&dump_name).unwrap()).unwrap();
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_restore_branch_off_main() {
+        let main_dump_name: String = "test_restore_branch_off_main_main.gql".to_string();
+        let feature_dump_name: String = "test_restore_branch_off_main_feature.gql".to_string();
+        let feature_branch: String = "feature".to_string();
+        let query: &str = "SELECT * FROM locations";
+
+        // Make a commit on a branch created from main, then dump both branches
+        let mut user: User = create_demo_db("restore_branch_off_main");
+        user.set_permissions(&Admin);
+        let main_branch: String = get_db_instance().unwrap().get_main_branch_name();
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&main_branch, &mut user)
+            .unwrap();
+        parse_vc_cmd(&format!("gql branch {}", feature_branch), &mut user, vec![]).unwrap();
+        execute_update(
+            &parse("INSERT INTO locations VALUES (100, 'Attic', true)", true).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        parse_vc_cmd("gql commit -m \"Add the attic\"", &mut user, vec![]).unwrap();
+        let expected_results: Vec<Row> =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string())
+                .unwrap()
+                .1;
+        let (_, expected_log, _) = command::log(&user).unwrap();
+        dump_branch(&feature_branch, &feature_dump_name, &user).unwrap();
+        dump_branch(&main_branch, &main_dump_name, &user).unwrap();
+        delete_db_instance().unwrap();
+
+        // Restore main into a fresh database, and then the branch on top of it
+        fcreate_db_instance("restore_branch_off_main_restore");
+        let mut user: User = User::new("test_user".to_string());
+        user.set_permissions(&Admin);
+        restore_branch(&main_dump_name, &user).unwrap();
+        restore_branch(&feature_dump_name, &user).unwrap();
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&feature_branch, &mut user)
+            .unwrap();
+        let results: Vec<Row> =
+            execute_query(&parse(query, false).unwrap(), &mut user, &"".to_string())
+                .unwrap()
+                .1;
+        assert_eq!(results, expected_results);
+        let (_, log, _) = command::log(&user).unwrap();
+        assert_eq!(log, expected_log);
+
+        // The first commit of the branch is still branched off of main
+        let branches = get_db_instance().unwrap().get_branch_file();
+        let head_node: BranchNode = get_db_instance()
+            .unwrap()
+            .get_branch_heads_file_mut()
+            .get_branch_node_from_head(&feature_branch, branches)
+            .unwrap();
+        let branch_nodes: Vec<BranchNode> = branches.traverse_branch_nodes(&head_node).unwrap();
+        let first_node: &BranchNode = branch_nodes
+            .iter()
+            .rev()
+            .find(|node| node.branch_name == feature_branch)
+            .unwrap();
+        let parent_node: BranchNode = branches.get_prev_branch_node(first_node).unwrap().unwrap();
+        assert_eq!(parent_node.branch_name, main_branch);
+
+        std::fs::remove_file(get_dump_path(&main_dump_name).unwrap()).unwrap();
+        std::fs::remove_file(get_dump_path(&feature_dump_name).unwrap()).unwrap();
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_dump_restrictions() {
+        let mut user: User = create_demo_db("dump_restrictions");
+        let main_branch: String = get_db_instance().unwrap().get_main_branch_name();
+        let dump_name: String = "test_dump_restrictions.gql".to_string();
+
+        // Only admins can dump and restore branches
+        assert!(dump_branch(&main_branch, &dump_name, &user).is_err());
+        assert!(restore_branch(&dump_name, &user).is_err());
+
+        // Dump files have to be within the dump directory
+        user.set_permissions(&Admin);
+        for file_name in ["", "/tmp/dump.gql", "../dump.gql", "nested/../../dump.gql"] {
+            assert!(dump_branch(&main_branch, &file_name.to_string(), &user).is_err());
+            assert!(restore_branch(&file_name.to_string(), &user).is_err());
+        }
+        delete_db_instance().unwrap();
+    }
+}
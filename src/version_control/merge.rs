@@ -1,10 +1,22 @@
 use crate::{
-    fileio::{databaseio::get_db_instance, tableio::Table},
-    util::row::{EmptyRowLocation, RowInfo, RowLocation},
+    fileio::{
+        databaseio::get_db_instance,
+        storage::{get_storage_backend, IN_MEMORY_PATH_PREFIX},
+        tableio::{create_table_from_diff, delete_table_in_dir, Table},
+    },
+    util::row::{EmptyRowLocation, Row, RowInfo, RowLocation},
 };
 
 use super::diff::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Whether clean merges are checked with verify_merge_diffs before they're applied.
+/// It's on while testing, so that every merge in the tests is checked.
+pub const VERIFY_MERGES: bool = cfg!(test);
+
+// Used to give every merge check its own directory
+static VERIFY_MERGE_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum MergeConflictResolutionAlgo {
@@ -13,6 +25,72 @@ pub enum MergeConflictResolutionAlgo {
     UseSource,   // Uses the source's version of any conflicting cases
 }
 
+/// A change in the source that conflicts with a change in the target
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeConflict {
+    pub table_name: String,
+    pub location: Option<RowLocation>, // The row that both branches changed, if the conflict is about a row
+    pub description: String,
+}
+
+impl std::fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Merge Conflict: {}", self.description)
+    }
+}
+
+/// The conflicts found by a clean merge. The merge keeps looking for conflicts until it has
+/// found max_conflicts of them, so they can all be reported together.
+pub struct MergeConflicts {
+    pub conflicts: Vec<MergeConflict>,
+    max_conflicts: Option<usize>, // None to find every conflict
+}
+
+impl MergeConflicts {
+    pub fn new(max_conflicts: Option<usize>) -> Self {
+        Self {
+            conflicts: Vec::new(),
+            max_conflicts,
+        }
+    }
+
+    /// Adds a conflict that was found.
+    /// Returns an error with all the conflicts once there are max_conflicts of them.
+    pub fn report(
+        &mut self,
+        table_name: &String,
+        location: Option<RowLocation>,
+        description: String,
+    ) -> Result<(), String> {
+        self.conflicts.push(MergeConflict {
+            table_name: table_name.clone(),
+            location,
+            description,
+        });
+        match self.max_conflicts {
+            Some(max_conflicts) if self.conflicts.len() >= max_conflicts => self.finish(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an error with all the conflicts that were found, if there are any
+    pub fn finish(&self) -> Result<(), String> {
+        match self.conflicts.as_slice() {
+            [] => Ok(()),
+            [conflict] => Err(conflict.to_string()),
+            conflicts => Err(format!(
+                "Found {} merge conflicts:\n{}",
+                conflicts.len(),
+                conflicts
+                    .iter()
+                    .map(|conflict| conflict.to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            )),
+        }
+    }
+}
+
 /// Merges a single diff to merge into the list of diffs to merge into using a merge conflict algorithm
 /// Returns a new list of diffs that would be the result of applying source_diffs into target_diffs
 pub fn create_merge_diffs(
@@ -20,19 +98,40 @@ pub fn create_merge_diffs(
     target_diffs: &Vec<Diff>,  // The target diffs to merge the source diff into
     target_table_dir: &String, // The directory where the target branch tables are stored
     conflict_res_algo: MergeConflictResolutionAlgo, // The merge conflict resolution algorithm to use
+) -> Result<Vec<Diff>, String> {
+    create_merge_diffs_with_insert_map(
+        source_diffs,
+        target_diffs,
+        target_table_dir,
+        conflict_res_algo,
+        &mut HashMap::new(),
+        Some(1),
+    )
+}
+
+/// Same as create_merge_diffs, but the locations that source rows were inserted at in the target
+/// are kept in the given map. Merging several source commits one after the other with the same map
+/// lets later commits update and remove the rows that earlier commits inserted.
+pub fn create_merge_diffs_with_insert_map(
+    source_diffs: &Vec<Diff>,  // The source diffs to merge into the target diffs
+    target_diffs: &Vec<Diff>,  // The target diffs to merge the source diff into
+    target_table_dir: &String, // The directory where the target branch tables are stored
+    conflict_res_algo: MergeConflictResolutionAlgo, // The merge conflict resolution algorithm to use
+    insert_map: &mut HashMap<(u32, u16), (u32, u16)>, // Maps source locations to target locations
+    max_conflicts: Option<usize>, // The most conflicts a clean merge finds before it fails, or None for all
 ) -> Result<Vec<Diff>, String> {
     // We assume target_diffs_on_the_table only contains one diff of each type for that table
     verify_only_one_type_of_diff_per_table(target_diffs)?;
 
+    // The conflicts found so far, if the merge is a clean one
+    let mut conflicts: MergeConflicts = MergeConflicts::new(max_conflicts);
+
     // Keep track of anything we need to do before applying the merge diffs
     let mut prev_merge_diffs: Vec<Diff> = Vec::new();
 
     // The result of the merge without any conflicts resolved
     let mut result_diffs: SquashDiffs = SquashDiffs::new();
 
-    // Maps (pagenum, rownum) in source to (pagenum, rownum) in the target
-    let mut insert_map: HashMap<(u32, u16), (u32, u16)> = HashMap::new();
-
     for source_diff in source_diffs {
         // Get all the diffs that affect the same table as the source_diff
         let target_diffs_on_the_table: Vec<Diff> = target_diffs
@@ -83,12 +182,15 @@ pub fn create_merge_diffs(
                     // This is a merge conflict if our algorithm is NoConflicts
                     if create_table_diff_option.is_none() {
                         if conflict_res_algo == MergeConflictResolutionAlgo::NoConflicts {
-                            return Err(
+                            conflicts.report(
+                                &insert_source_diff.table_name,
+                                None,
                                 format!(
-                                    "Merge Conflict: Table {} has been deleted in target branch, but has rows inserted in the source branch", 
+                                    "Table {} has been deleted in target branch, but has rows inserted in the source branch",
                                     insert_source_diff.table_name
-                                )
-                            );
+                                ),
+                            )?;
+                            continue;
                         } else if conflict_res_algo == MergeConflictResolutionAlgo::UseSource {
                             // Get the table remove diff from target_diffs_on_the_table if it exists
                             let remove_table_diff_target_option = target_diffs_on_the_table
@@ -105,6 +207,7 @@ pub fn create_merge_diffs(
                                     TableCreateDiff {
                                         table_name: remove_table_diff_target.table_name.clone(),
                                         schema: remove_table_diff_target.schema.clone(),
+                                        ttl: remove_table_diff_target.ttl,
                                     };
                                 prev_merge_diffs
                                     .push(Diff::TableCreate(create_table_target_premerge));
@@ -221,12 +324,15 @@ pub fn create_merge_diffs(
                     // This is a merge conflict if our algorithm is NoConflicts
                     if create_table_diff_option.is_none() {
                         if conflict_res_algo == MergeConflictResolutionAlgo::NoConflicts {
-                            return Err(
+                            conflicts.report(
+                                &update_source_diff.table_name,
+                                None,
                                 format!(
-                                    "Merge Conflict: Table {} has been deleted in target branch, but is updated in the source branch", 
+                                    "Table {} has been deleted in target branch, but is updated in the source branch",
                                     update_source_diff.table_name
-                                )
-                            );
+                                ),
+                            )?;
+                            continue;
                         } else if conflict_res_algo == MergeConflictResolutionAlgo::UseSource {
                             // Get the table remove diff from target_diffs_on_the_table if it exists
                             let remove_table_diff_target_option = target_diffs_on_the_table
@@ -243,6 +349,7 @@ pub fn create_merge_diffs(
                                     TableCreateDiff {
                                         table_name: remove_table_diff_target.table_name.clone(),
                                         schema: remove_table_diff_target.schema.clone(),
+                                        ttl: remove_table_diff_target.ttl,
                                     };
                                 prev_merge_diffs
                                     .push(Diff::TableCreate(create_table_target_premerge));
@@ -266,8 +373,13 @@ pub fn create_merge_diffs(
                     }
                 }
 
-                // We need to map the rows in update_source_diff to the rows in the target
-                for row in update_source_diff.rows.iter_mut() {
+                // We need to map the rows in update_source_diff to the rows in the target,
+                // along with the old versions of them
+                for row in update_source_diff
+                    .rows
+                    .iter_mut()
+                    .chain(update_source_diff.old_rows.iter_mut())
+                {
                     // If it is mapped to the target, use the mapped row location
                     if let Some((target_pagenum, target_rownum)) =
                         insert_map.get(&(row.pagenum, row.rownum))
@@ -354,12 +466,15 @@ pub fn create_merge_diffs(
                     // This is a merge conflict if our algorithm is NoConflicts
                     if create_table_diff_option.is_none() {
                         if conflict_res_algo == MergeConflictResolutionAlgo::NoConflicts {
-                            return Err(
+                            conflicts.report(
+                                &remove_source_diff.table_name,
+                                None,
                                 format!(
-                                    "Table {} has been deleted in target branch, but has rows removed from it in the source branch", 
+                                    "Table {} has been deleted in target branch, but has rows removed from it in the source branch",
                                     remove_source_diff.table_name
-                                )
-                            );
+                                ),
+                            )?;
+                            continue;
                         } else if conflict_res_algo == MergeConflictResolutionAlgo::UseSource {
                             // Get the table remove diff from target_diffs_on_the_table if it exists
                             let remove_table_diff_target_option = target_diffs_on_the_table
@@ -376,6 +491,7 @@ pub fn create_merge_diffs(
                                     TableCreateDiff {
                                         table_name: remove_table_diff_target.table_name.clone(),
                                         schema: remove_table_diff_target.schema.clone(),
+                                        ttl: remove_table_diff_target.ttl,
                                     };
                                 prev_merge_diffs
                                     .push(Diff::TableCreate(create_table_target_premerge));
@@ -532,7 +648,7 @@ pub fn create_merge_diffs(
                 }
 
                 // Add the new index creation to the result_diffs
-                result_diffs
+                let index_create_diff: &mut IndexCreateDiff = &mut result_diffs
                     .table_diffs
                     .entry(index_create_source_diff.table_name.clone())
                     .or_insert_with(|| {
@@ -541,9 +657,13 @@ pub fn create_merge_diffs(
                             &index_create_source_diff.schema,
                         )
                     })
-                    .index_create_diff
+                    .index_create_diff;
+                index_create_diff
                     .indexes
                     .extend(index_create_source_diff.indexes);
+                index_create_diff
+                    .unique_indexes
+                    .extend(index_create_source_diff.unique_indexes);
             }
             Diff::IndexRemove(mut index_remove_source_diff) => {
                 // Get the index_remove diff from target_diffs_on_the_table if it exists
@@ -570,7 +690,7 @@ pub fn create_merge_diffs(
                 }
 
                 // Add the new index creation to the result_diffs
-                result_diffs
+                let index_remove_diff: &mut IndexRemoveDiff = &mut result_diffs
                     .table_diffs
                     .entry(index_remove_source_diff.table_name.clone())
                     .or_insert_with(|| {
@@ -579,9 +699,56 @@ pub fn create_merge_diffs(
                             &index_remove_source_diff.schema,
                         )
                     })
-                    .index_remove_diff
+                    .index_remove_diff;
+                index_remove_diff
                     .indexes
                     .extend(index_remove_source_diff.indexes);
+                index_remove_diff
+                    .unique_indexes
+                    .extend(index_remove_source_diff.unique_indexes);
+            }
+            Diff::SchemaChange(schema_change_source_diff) => {
+                // Get the schema change diff from target_diffs_on_the_table if it exists
+                let schema_change_diff_target_option =
+                    target_diffs_on_the_table
+                        .iter()
+                        .find_map(|diff| match diff {
+                            Diff::SchemaChange(schema_change_diff) => Some(schema_change_diff),
+                            _ => None,
+                        });
+
+                if let Some(schema_change_diff_target) = schema_change_diff_target_option {
+                    // If the target made the same change, there's nothing left to do
+                    if schema_change_diff_target.schema == schema_change_source_diff.schema
+                        && schema_change_diff_target.new_schema
+                            == schema_change_source_diff.new_schema
+                    {
+                        continue;
+                    }
+                    // The rows of the table can't be in two different schemas at once
+                    conflicts.report(
+                        &schema_change_source_diff.table_name,
+                        None,
+                        format!(
+                            "Table {} changed to schema {:?} in source, but was changed to schema {:?} in target",
+                            schema_change_source_diff.table_name,
+                            schema_change_source_diff.new_schema,
+                            schema_change_diff_target.new_schema
+                        ),
+                    )?;
+                    continue;
+                }
+
+                result_diffs
+                    .table_diffs
+                    .entry(schema_change_source_diff.table_name.clone())
+                    .or_insert_with(|| {
+                        TableSquashDiff::new(
+                            &schema_change_source_diff.table_name,
+                            &schema_change_source_diff.schema,
+                        )
+                    })
+                    .schema_change_diff = Some(schema_change_source_diff.clone());
             }
         }
     }
@@ -592,7 +759,9 @@ pub fn create_merge_diffs(
         target_diffs,
         target_table_dir,
         conflict_res_algo,
+        &mut conflicts,
     )?;
+    conflicts.finish()?;
 
     // Assemble the final diffs into a chronological list of diffs
     prev_merge_diffs.append(&mut prereq_diffs);
@@ -609,6 +778,7 @@ fn handle_merge_conflicts(
     target_diffs: &Vec<Diff>,                 // The target diffs to merge the source diff into
     target_table_dir: &String, // The directory where the target branch tables are located.
     conflict_res_algo: MergeConflictResolutionAlgo, // The merge conflict resolution algorithm to use
+    conflicts: &mut MergeConflicts, // Where the conflicts are kept, if the merge is a clean one
 ) -> Result<(Vec<Diff>, Vec<Diff>), String> {
     // Keep track of the diffs that need to be applied to the target before the source diffs can be applied
     let mut prereq_diffs: Vec<Diff> = Vec::new();
@@ -647,10 +817,18 @@ fn handle_merge_conflicts(
                     if res_insert_row.get_row_location() == target_insert_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Inserted row at location {:?} in table {} in source, but row was also inserted at the same location in the target", 
-                                                   res_insert_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_insert_row.get_row_location()),
+                                    format!(
+                                        "Inserted row at location {:?} in table {} in source, but row was also inserted at the same location in the target",
+                                        res_insert_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_insert_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Overwrite the target row with the source row by keeping the source row
@@ -679,10 +857,18 @@ fn handle_merge_conflicts(
                     if res_insert_row.get_row_location() == target_update_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Inserted row at location {:?} in table {} in source, but row was also updated at the same location in the target", 
-                                                   res_insert_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_insert_row.get_row_location()),
+                                    format!(
+                                        "Inserted row at location {:?} in table {} in source, but row was also updated at the same location in the target",
+                                        res_insert_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_insert_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Overwrite the target row with the source row by keeping the source row
@@ -711,10 +897,18 @@ fn handle_merge_conflicts(
                     if res_insert_row.get_row_location() == target_remove_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Inserted row at location {:?} in table {} in source, but row was also removed at the same location in the target", 
-                                                   res_insert_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_insert_row.get_row_location()),
+                                    format!(
+                                        "Inserted row at location {:?} in table {} in source, but row was also removed at the same location in the target",
+                                        res_insert_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_insert_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Overwrite the target row with the source row by keeping the source row
@@ -741,10 +935,18 @@ fn handle_merge_conflicts(
             if let Some(_target_table_remove_diff) = target_table_remove_diff_opt {
                 match conflict_res_algo {
                     MergeConflictResolutionAlgo::NoConflicts => {
-                        // We don't want to handle merge conflicts, so just throw error
-                        return Err(format!("Merge Conflict: Inserted row at location {:?} in table {} in source, but table was also removed in the target", 
-                                           res_insert_row.get_row_location(),
-                                           res_table_name));
+                        // Keep looking for more conflicts, past this row
+                        conflicts.report(
+                            &res_table_name,
+                            Some(res_insert_row.get_row_location()),
+                            format!(
+                                "Inserted row at location {:?} in table {} in source, but table was also removed in the target",
+                                res_insert_row.get_row_location(),
+                                res_table_name
+                            ),
+                        )?;
+                        idx += 1;
+                        continue 'result_insert_loop;
                     }
                     MergeConflictResolutionAlgo::UseSource => {
                         // Keep the source's row changes by removing the table remove diff from the target
@@ -781,10 +983,18 @@ fn handle_merge_conflicts(
                     if res_update_row.get_row_location() == target_insert_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Updated row at location {:?} in table {} in source, but row was also inserted at the same location in the target", 
-                                                   res_update_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_update_row.get_row_location()),
+                                    format!(
+                                        "Updated row at location {:?} in table {} in source, but row was also inserted at the same location in the target",
+                                        res_update_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_update_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Overwrite the target row with the source row by keeping the source row
@@ -813,10 +1023,18 @@ fn handle_merge_conflicts(
                     if res_update_row.get_row_location() == target_update_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Updated row at location {:?} in table {} in source, but row was also updated at the same location in the target", 
-                                                   res_update_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_update_row.get_row_location()),
+                                    format!(
+                                        "Updated row at location {:?} in table {} in source, but row was also updated at the same location in the target",
+                                        res_update_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_update_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Overwrite the target row with the source row by keeping the source row
@@ -845,10 +1063,18 @@ fn handle_merge_conflicts(
                     if res_update_row.get_row_location() == target_remove_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Updated row at location {:?} in table {} in source, but row was also removed at the same location in the target", 
-                                                   res_update_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_update_row.get_row_location()),
+                                    format!(
+                                        "Updated row at location {:?} in table {} in source, but row was also removed at the same location in the target",
+                                        res_update_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_update_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Overwrite the target row with the source row by changing the source row from an update to an insert
@@ -887,10 +1113,18 @@ fn handle_merge_conflicts(
                     if res_remove_row.get_row_location() == target_insert_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Removed row at location {:?} in table {} in source, but row was also inserted at the same location in the target", 
-                                                   res_remove_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_remove_row.get_row_location()),
+                                    format!(
+                                        "Removed row at location {:?} in table {} in source, but row was also inserted at the same location in the target",
+                                        res_remove_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_remove_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Remove the row from the target by keeping the source's remove row
@@ -919,10 +1153,18 @@ fn handle_merge_conflicts(
                     if res_remove_row.get_row_location() == target_update_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Removed row at location {:?} in table {} in source, but row was also updated at the same location in the target", 
-                                                   res_remove_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_remove_row.get_row_location()),
+                                    format!(
+                                        "Removed row at location {:?} in table {} in source, but row was also updated at the same location in the target",
+                                        res_remove_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_remove_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Remove the row from the target by keeping the source's remove row
@@ -951,10 +1193,18 @@ fn handle_merge_conflicts(
                     if res_remove_row.get_row_location() == target_remove_row.get_row_location() {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Removed row at location {:?} in table {} in source, but row was also removed at the same location in the target", 
-                                                   res_remove_row.get_row_location(),
-                                                   res_table_name));
+                                // Keep looking for more conflicts, past this row
+                                conflicts.report(
+                                    &res_table_name,
+                                    Some(res_remove_row.get_row_location()),
+                                    format!(
+                                        "Removed row at location {:?} in table {} in source, but row was also removed at the same location in the target",
+                                        res_remove_row.get_row_location(),
+                                        res_table_name
+                                    ),
+                                )?;
+                                idx += 1;
+                                continue 'result_remove_loop;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Remove the row from the target by keeping the source's remove row
@@ -990,8 +1240,11 @@ fn handle_merge_conflicts(
                     if target_table_create_diff.schema != res_table_create_diff.schema {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Table {} created in source, but table was also created in target with different schema", res_table_name));
+                                conflicts.report(
+                                    &res_table_name,
+                                    None,
+                                    format!("Table {} created in source, but table was also created in target with different schema", res_table_name),
+                                )?;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Remove the table from the target by keeping the source's table create
@@ -1007,6 +1260,7 @@ fn handle_merge_conflicts(
                                     table_name: target_table_create_diff.table_name.clone(),
                                     schema: target_table_create_diff.schema.clone(),
                                     rows_removed: table_rows,
+                                    ttl: target_table_create_diff.ttl,
                                 });
 
                                 prereq_diffs.push(table_remove_diff);
@@ -1041,10 +1295,16 @@ fn handle_merge_conflicts(
                     if target_inserted_rows.len() > 0 {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Removed table {} in source, but rows were also inserted into the same table in the target", 
-                                                    res_table_remove_diff.table_name,
-                                                    ));
+                                conflicts.report(
+                                    &res_table_name,
+                                    None,
+                                    format!(
+                                        "Removed table {} in source, but rows were also inserted into the same table in the target",
+                                        res_table_remove_diff.table_name
+                                    ),
+                                )?;
+                                // The other changes to the table would conflict with the removal as well
+                                res_table_remove_diff_exists = false;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Remove the rows from the target by using a prerequisite remove row diff
@@ -1082,10 +1342,16 @@ fn handle_merge_conflicts(
                     if target_updated_rows.len() > 0 {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Removed table {} in source, but rows were also updated in the same table in the target", 
-                                                    res_table_remove_diff.table_name,
-                                                    ));
+                                conflicts.report(
+                                    &res_table_name,
+                                    None,
+                                    format!(
+                                        "Removed table {} in source, but rows were also updated in the same table in the target",
+                                        res_table_remove_diff.table_name
+                                    ),
+                                )?;
+                                // The other changes to the table would conflict with the removal as well
+                                res_table_remove_diff_exists = false;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Remove the rows from the target by using a prerequisite remove row diff
@@ -1123,10 +1389,16 @@ fn handle_merge_conflicts(
                     if target_removed_rows.len() > 0 {
                         match conflict_res_algo {
                             MergeConflictResolutionAlgo::NoConflicts => {
-                                // We don't want to handle merge conflicts, so just throw error
-                                return Err(format!("Merge Conflict: Removed table {} in source, but rows were also removed from the same table in the target", 
-                                                    res_table_remove_diff.table_name,
-                                                    ));
+                                conflicts.report(
+                                    &res_table_name,
+                                    None,
+                                    format!(
+                                        "Removed table {} in source, but rows were also removed from the same table in the target",
+                                        res_table_remove_diff.table_name
+                                    ),
+                                )?;
+                                // The other changes to the table would conflict with the removal as well
+                                res_table_remove_diff_exists = false;
                             }
                             MergeConflictResolutionAlgo::UseSource => {
                                 // Remove the rows from the target by using a prerequisite insert row diff
@@ -1193,6 +1465,12 @@ fn handle_merge_conflicts(
         if table_diff.remove_diff.rows.len() > 0 {
             res_diffs.push(Diff::Remove(table_diff.remove_diff.clone()));
         }
+        // The schema is changed after rows are removed, and before the new rows are inserted
+        if table_diff.schema_change_diff.is_some() {
+            res_diffs.push(Diff::SchemaChange(
+                table_diff.schema_change_diff.clone().unwrap(),
+            ));
+        }
         if table_diff.insert_diff.rows.len() > 0 {
             res_diffs.push(Diff::Insert(table_diff.insert_diff.clone()));
         }
@@ -1204,6 +1482,157 @@ fn handle_merge_conflicts(
     Ok((prereq_diffs, res_diffs))
 }
 
+/// Checks that the merged diffs give the same tables as applying the source diffs to the target
+/// branch one at a time. The merged diffs place rows by their location, while the expected tables
+/// are found by matching rows on their values, so the two are worked out independently.
+/// Only clean merges can be checked, as resolving conflicts changes what the result should be.
+/// Nothing in the target directory is changed, and an error describes any difference found.
+pub fn verify_merge_diffs(
+    source_diffs: &Vec<Diff>, // The source diffs that were merged into the target
+    merged_diffs: &Vec<Diff>, // The diffs that create_merge_diffs gave for the merge
+    target_table_dir: &String, // The directory where the target branch tables are stored
+) -> Result<(), String> {
+    let check_dir: String = format!(
+        "{}merge_check{}{}",
+        IN_MEMORY_PATH_PREFIX,
+        std::path::MAIN_SEPARATOR,
+        VERIFY_MERGE_COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+    let merged_dir: String = format!("{}{}merged", check_dir, std::path::MAIN_SEPARATOR);
+    let expected_dir: String = format!("{}{}expected", check_dir, std::path::MAIN_SEPARATOR);
+
+    let result: Result<(), String> = (|| {
+        // Only the tables that the merge touches can change, so only those are copied
+        let table_names: HashSet<String> = source_diffs
+            .iter()
+            .chain(merged_diffs.iter())
+            .map(|diff| diff.get_table_name())
+            .collect();
+        for table_name in &table_names {
+            copy_table(table_name, target_table_dir, &merged_dir)?;
+            copy_table(table_name, target_table_dir, &expected_dir)?;
+        }
+
+        construct_tables_from_diffs(&merged_dir, merged_diffs)?;
+        // Squashed diffs aren't in the order they were made, so tables are created first,
+        // and rows are changed before any are inserted, so there's never a copy to choose from
+        let mut ordered_source_diffs: Vec<&Diff> = source_diffs.iter().collect();
+        ordered_source_diffs.sort_by_key(|diff| match diff {
+            Diff::TableCreate(_) => 0,
+            Diff::Update(_) => 1,
+            Diff::Remove(_) => 2,
+            Diff::SchemaChange(_) => 3,
+            Diff::Insert(_) => 4,
+            Diff::IndexCreate(_) | Diff::IndexRemove(_) => 5,
+            Diff::TableRemove(_) => 6,
+        });
+        for diff in ordered_source_diffs {
+            apply_diff_by_value(diff, &expected_dir)?;
+        }
+
+        for table_name in &table_names {
+            let merged: Option<Vec<Row>> = get_sorted_rows(table_name, &merged_dir);
+            let expected: Option<Vec<Row>> = get_sorted_rows(table_name, &expected_dir);
+            if merged != expected {
+                return Err(format!(
+                    "Merge Verification Error: Table {} is {:?} after the merge, but should be {:?}",
+                    table_name, merged, expected
+                ));
+            }
+        }
+        Ok(())
+    })();
+
+    let backend = get_storage_backend(&check_dir);
+    if backend.exists(&check_dir) {
+        backend.remove_dir_all(&check_dir)?;
+    }
+    result
+}
+
+// Copies a table and its rows, at the same locations, into another directory.
+// If the table doesn't exist, nothing is copied.
+fn copy_table(table_name: &String, src_dir: &String, dest_dir: &String) -> Result<(), String> {
+    let table: Table = match Table::new(src_dir, table_name, None) {
+        Ok(table) => table,
+        Err(_) => return Ok(()),
+    };
+    let create_diff: TableCreateDiff = TableCreateDiff {
+        table_name: table_name.clone(),
+        schema: table.schema.clone(),
+        ttl: table.ttl,
+    };
+    let mut copy: Table = create_table_from_diff(&create_diff, dest_dir)?;
+    let rows: Vec<RowInfo> = table.into_iter().collect();
+    copy.write_rows(rows)?;
+    Ok(())
+}
+
+// Applies a diff by finding the rows it changes by their values, rather than their locations
+fn apply_diff_by_value(diff: &Diff, table_dir: &String) -> Result<(), String> {
+    let find_row = |table: &Table, row: &Row| -> Result<RowLocation, String> {
+        table
+            .clone()
+            .into_iter()
+            .find(|row_info| &row_info.row == row)
+            .map(|row_info| row_info.get_row_location())
+            .ok_or(format!(
+                "Merge Verification Error: Row {:?} is not in table {}",
+                row, table.name
+            ))
+    };
+    match diff {
+        Diff::TableCreate(create_diff) => {
+            if Table::new(table_dir, &create_diff.table_name, None).is_err() {
+                create_table_from_diff(create_diff, table_dir)?;
+            }
+        }
+        Diff::TableRemove(remove_diff) => {
+            delete_table_in_dir(&remove_diff.table_name, table_dir)?;
+        }
+        Diff::Insert(insert_diff) => {
+            let mut table: Table = Table::new(table_dir, &insert_diff.table_name, None)?;
+            table.insert_rows(insert_diff.rows.iter().map(|x| x.row.clone()).collect())?;
+        }
+        Diff::Update(update_diff) => {
+            let table: Table = Table::new(table_dir, &update_diff.table_name, None)?;
+            for (new_row, old_row) in update_diff.rows.iter().zip(&update_diff.old_rows) {
+                let location: RowLocation = find_row(&table, &old_row.row)?;
+                table.rewrite_rows(vec![RowInfo {
+                    row: new_row.row.clone(),
+                    pagenum: location.pagenum,
+                    rownum: location.rownum,
+                }])?;
+            }
+        }
+        Diff::Remove(remove_diff) => {
+            let table: Table = Table::new(table_dir, &remove_diff.table_name, None)?;
+            for removed in &remove_diff.rows {
+                // Both branches can remove the same row, in which case it's already gone
+                if let Ok(location) = find_row(&table, &removed.row) {
+                    table.remove_rows(vec![location])?;
+                }
+            }
+        }
+        Diff::SchemaChange(schema_change_diff) => {
+            let mut table: Table = Table::new(table_dir, &schema_change_diff.table_name, None)?;
+            table.change_schema(&schema_change_diff.new_schema)?;
+        }
+        // Indexes don't change the rows of a table
+        Diff::IndexCreate(_) | Diff::IndexRemove(_) => {}
+    }
+    Ok(())
+}
+
+// The rows of a table in a directory, sorted so that tables can be compared regardless of
+// where their rows are. Returns None if the table doesn't exist.
+fn get_sorted_rows(table_name: &String, table_dir: &String) -> Option<Vec<Row>> {
+    let table: Table = Table::new(table_dir, table_name, None).ok()?;
+    let mut rows: Vec<Row> = table.into_iter().map(|row_info| row_info.row).collect();
+    rows.sort();
+    Some(rows)
+}
+
 /// Verifies that for each table within the diffs, there is at most 1 of each type of diff
 fn verify_only_one_type_of_diff_per_table(diffs: &Vec<Diff>) -> Result<(), String> {
     // Get all the diffs organized into a hashmap of table name to a list of diffs for that table
@@ -1224,6 +1653,7 @@ fn verify_only_one_type_of_diff_per_table(diffs: &Vec<Diff>) -> Result<(), Strin
             let mut contains_update: bool = false;
             let mut contains_index_create: bool = false;
             let mut contains_index_remove: bool = false;
+            let mut contains_schema_change: bool = false;
             for diff in diffs_of_same_table {
                 match diff {
                     Diff::TableCreate(_) => {
@@ -1289,6 +1719,15 @@ fn verify_only_one_type_of_diff_per_table(diffs: &Vec<Diff>) -> Result<(), Strin
                         }
                         contains_index_remove = true;
                     }
+                    Diff::SchemaChange(_) => {
+                        if contains_schema_change {
+                            return Err(format!(
+                                "Multiple schema change diffs for table {}",
+                                diff.get_table_name()
+                            ));
+                        }
+                        contains_schema_change = true;
+                    }
                 }
             }
         }
@@ -1304,19 +1743,24 @@ mod tests {
     use serial_test::serial;
 
     use crate::{
-        executor::query::create_table,
+        executor::query::{create_table, execute_query, execute_update},
         fileio::{
             databaseio::{delete_db_instance, get_db_instance, MAIN_BRANCH_NAME},
             header::Schema,
             tableio::{create_table_in_dir, delete_table_in_dir},
         },
+        parser::parser::parse,
         user::userdata::User,
         util::{
             bench::{create_demo_db, fcreate_db_instance},
             dbtype::*,
             row::Row,
         },
-        version_control::{commit::Commit, diff::Diff},
+        version_control::{
+            command::log,
+            commit::{commits_read, Commit},
+            diff::Diff,
+        },
     };
 
     use super::*;
@@ -2003,6 +2447,84 @@ mod tests {
         delete_test_db();
     }
 
+    #[test]
+    #[serial]
+    fn test_max_merge_conflicts() {
+        // Tests that a clean merge reports several conflicts at once, up to the most it was asked for
+
+        // Create the database
+        let (
+            _user,
+            _src_branch,
+            _target_branch,
+            src_branch_dir,
+            target_branch_dir,
+            table_name1,
+            _table_name2,
+        ) = setup_test_db();
+
+        // Update every row in both the source branch and the target branch
+        let src_table1: Table = Table::new(&src_branch_dir, &table_name1, None).unwrap();
+        let target_table1: Table = Table::new(&target_branch_dir, &table_name1, None).unwrap();
+        let update_rows = |name: &str| -> Vec<RowInfo> {
+            (0..3)
+                .map(|rownum| RowInfo {
+                    pagenum: 1,
+                    rownum,
+                    row: vec![Value::I32(rownum as i32), Value::String(name.to_string())],
+                })
+                .collect()
+        };
+        let src_diffs: Vec<Diff> = vec![Diff::Update(
+            src_table1.rewrite_rows(update_rows("Source")).unwrap(),
+        )];
+        let target_diffs: Vec<Diff> = vec![Diff::Update(
+            target_table1.rewrite_rows(update_rows("Target")).unwrap(),
+        )];
+
+        let merge = |max_conflicts: Option<usize>| -> String {
+            create_merge_diffs_with_insert_map(
+                &src_diffs,
+                &target_diffs,
+                &target_branch_dir,
+                MergeConflictResolutionAlgo::NoConflicts,
+                &mut HashMap::new(),
+                max_conflicts,
+            )
+            .unwrap_err()
+        };
+
+        // By default, the merge stops at the first conflict
+        let error: String = create_merge_diffs(
+            &src_diffs,
+            &target_diffs,
+            &target_branch_dir,
+            MergeConflictResolutionAlgo::NoConflicts,
+        )
+        .unwrap_err();
+        assert_eq!(error, merge(Some(1)));
+        assert!(error.starts_with("Merge Conflict: Updated row at location"));
+        assert_eq!(error.lines().count(), 1);
+
+        // Every conflicting row is reported together
+        let error: String = merge(None);
+        assert!(error.starts_with("Found 3 merge conflicts:"));
+        for rownum in 0..3 {
+            assert!(error.contains(&format!(
+                "Merge Conflict: Updated row at location {:?}",
+                RowLocation { pagenum: 1, rownum }
+            )));
+        }
+
+        // The merge stops once it has found as many conflicts as it was asked for
+        let error: String = merge(Some(2));
+        assert!(error.starts_with("Found 2 merge conflicts:"));
+        assert_eq!(error.lines().count(), 3);
+
+        // Clean up the database
+        delete_test_db();
+    }
+
     #[test]
     #[serial]
     fn test_basic_remove_merge() {
@@ -4451,4 +4973,308 @@ mod tests {
     fn delete_test_db() {
         delete_db_instance().unwrap();
     }
+
+    #[test]
+    #[serial]
+    fn test_replay_merge() {
+        fcreate_db_instance("test_replay_merge");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string()).unwrap();
+        };
+        let commit = |message: &str, user: &mut User| {
+            get_db_instance()
+                .unwrap()
+                .create_commit_on_head(&message.to_string(), &"".to_string(), user, None)
+                .unwrap();
+        };
+        run("CREATE TABLE people (id INT, name VARCHAR(10))", &mut user);
+        run("INSERT INTO people VALUES (1, 'a')", &mut user);
+        commit("Initial", &mut user);
+
+        // Each commit on the branch builds on the one before it
+        get_db_instance()
+            .unwrap()
+            .create_branch(&"feature".to_string(), &None, &mut user)
+            .unwrap();
+        run("INSERT INTO people VALUES (2, 'b')", &mut user);
+        commit("Add b", &mut user);
+        run("UPDATE people SET name = 'bb' WHERE id = 2", &mut user);
+        commit("Rename b", &mut user);
+        run("INSERT INTO people VALUES (3, 'c')", &mut user);
+        commit("Add c", &mut user);
+
+        // Main inserts into the same slot the branch first inserted into
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&MAIN_BRANCH_NAME.to_string(), &mut user)
+            .unwrap();
+        run("INSERT INTO people VALUES (4, 'd')", &mut user);
+        commit("Add d", &mut user);
+
+        let commits: Vec<Commit> = get_db_instance()
+            .unwrap()
+            .replay_branch(
+                &"feature".to_string(),
+                &mut user,
+                MergeConflictResolutionAlgo::NoConflicts,
+                false,
+            )
+            .unwrap();
+        assert_eq!(
+            commits
+                .iter()
+                .map(|commit| commit.message.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["Created Branch feature", "Add b", "Rename b", "Add c"]
+        );
+
+        // The replayed commits are on main, after its own commits
+        let (_, log, _) = log(&user).unwrap();
+        assert_eq!(
+            log.iter()
+                .map(|entry| entry[3].as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "Add c",
+                "Rename b",
+                "Add b",
+                "Created Branch feature",
+                "Add d",
+                "Initial"
+            ]
+        );
+
+        let (_, rows) = execute_query(
+            &parse("SELECT id, name FROM people ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let names: Vec<Row> = vec![(1, "a"), (2, "bb"), (3, "c"), (4, "d")]
+            .into_iter()
+            .map(|(id, name)| vec![Value::I64(id), Value::String(name.to_string())])
+            .collect();
+        assert_eq!(rows, names);
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_verify_merge_diffs() {
+        fcreate_db_instance("test_verify_merge_diffs");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string()).unwrap();
+        };
+        let commit = |message: &str, user: &mut User| {
+            get_db_instance()
+                .unwrap()
+                .create_commit_on_head(&message.to_string(), &"".to_string(), user, None)
+                .unwrap()
+                .1
+        };
+        run("CREATE TABLE people (id INT, name VARCHAR(10))", &mut user);
+        for id in 1..=6 {
+            run(
+                &format!("INSERT INTO people VALUES ({}, 'p{}')", id, id),
+                &mut user,
+            );
+        }
+        commit("Initial", &mut user);
+
+        // Both branches insert, update and remove different rows of the same table
+        get_db_instance()
+            .unwrap()
+            .create_branch(&"feature".to_string(), &None, &mut user)
+            .unwrap();
+        run("UPDATE people SET name = 'feature' WHERE id = 2", &mut user);
+        run("INSERT INTO people VALUES (7, 'p7')", &mut user);
+        run("INSERT INTO people VALUES (8, 'p8')", &mut user);
+        let mut source_diffs: Vec<Diff> = commit("Feature changes", &mut user).diffs;
+        run("DELETE FROM people WHERE id = 3", &mut user);
+        source_diffs.append(&mut commit("Feature removal", &mut user).diffs);
+
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&MAIN_BRANCH_NAME.to_string(), &mut user)
+            .unwrap();
+        run("UPDATE people SET name = 'main' WHERE id = 4", &mut user);
+        run("DELETE FROM people WHERE id = 5", &mut user);
+        commit("Main changes", &mut user);
+        run("INSERT INTO people VALUES (9, 'p9')", &mut user);
+        commit("Main insert", &mut user);
+
+        // The merged diffs give the same rows as applying the feature's diffs one at a time
+        let merged_diffs: Vec<Diff> = get_db_instance()
+            .unwrap()
+            .merge_branches(
+                &"feature".to_string(),
+                &mut user,
+                &"Test Merge".to_string(),
+                false,
+                MergeConflictResolutionAlgo::NoConflicts,
+                false,
+            )
+            .unwrap()
+            .diffs;
+        let main_dir: String = get_db_instance()
+            .unwrap()
+            .get_current_working_branch_path(&user);
+        verify_merge_diffs(&source_diffs, &merged_diffs, &main_dir).unwrap();
+
+        // Leaving out the update makes the merge diverge, which is caught
+        let without_update: Vec<Diff> = merged_diffs
+            .iter()
+            .filter(|diff| !matches!(diff, Diff::Update(_)))
+            .cloned()
+            .collect();
+        let error: String =
+            verify_merge_diffs(&source_diffs, &without_update, &main_dir).unwrap_err();
+        assert!(error.starts_with("Merge Verification Error: Table people"));
+
+        // Checking the merge doesn't change the target branch, and the merge still goes through
+        get_db_instance()
+            .unwrap()
+            .merge_branches(
+                &"feature".to_string(),
+                &mut user,
+                &"Merge".to_string(),
+                true,
+                MergeConflictResolutionAlgo::NoConflicts,
+                false,
+            )
+            .unwrap();
+        let (_, rows) = execute_query(
+            &parse("SELECT id, name FROM people ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let expected: Vec<Row> = vec![
+            (1, "p1"),
+            (2, "feature"),
+            (4, "main"),
+            (6, "p6"),
+            (7, "p7"),
+            (8, "p8"),
+            (9, "p9"),
+        ]
+        .into_iter()
+        .map(|(id, name)| vec![Value::I64(id), Value::String(name.to_string())])
+        .collect();
+        assert_eq!(rows, expected);
+
+        delete_db_instance().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_merge_after_checkpoint() {
+        fcreate_db_instance("test_merge_after_checkpoint");
+        let mut user: User = User::new("test_user".to_string());
+        let run = |query: &str, user: &mut User| {
+            execute_update(&parse(query, true).unwrap(), user, &"".to_string()).unwrap();
+        };
+        let commit = |message: &str, user: &mut User| {
+            get_db_instance()
+                .unwrap()
+                .create_commit_on_head(&message.to_string(), &"".to_string(), user, None)
+                .unwrap();
+        };
+        // Merges without committing, returning the number of commits that were read
+        let count_merge_reads = |user: &mut User| -> u64 {
+            let before: u64 = commits_read();
+            get_db_instance()
+                .unwrap()
+                .merge_branches(
+                    &"feature".to_string(),
+                    user,
+                    &"Test Merge".to_string(),
+                    false,
+                    MergeConflictResolutionAlgo::NoConflicts,
+                    false,
+                )
+                .unwrap();
+            commits_read() - before
+        };
+        run("CREATE TABLE people (id INT, name VARCHAR(10))", &mut user);
+        run("INSERT INTO people VALUES (1, 'p1')", &mut user);
+        commit("Initial", &mut user);
+        let main_branch: String = MAIN_BRANCH_NAME.to_string();
+        get_db_instance()
+            .unwrap()
+            .create_checkpoint(&main_branch)
+            .unwrap();
+        // A commit is only checkpointed once
+        assert!(get_db_instance()
+            .unwrap()
+            .create_checkpoint(&main_branch)
+            .is_err());
+
+        get_db_instance()
+            .unwrap()
+            .create_branch(&"feature".to_string(), &None, &mut user)
+            .unwrap();
+        run("INSERT INTO people VALUES (2, 'p2')", &mut user);
+        commit("Feature insert", &mut user);
+
+        // Main has a commit for each of its rows since the branch was made
+        get_db_instance()
+            .unwrap()
+            .switch_branch(&main_branch, &mut user)
+            .unwrap();
+        for id in 3..=8 {
+            run(
+                &format!("INSERT INTO people VALUES ({}, 'p{}')", id, id),
+                &mut user,
+            );
+            commit(&format!("Main insert {}", id), &mut user);
+        }
+        let commits_since_checkpoint = || -> i32 {
+            get_db_instance()
+                .unwrap()
+                .get_branch_heads_file_mut()
+                .get_branch_head(&MAIN_BRANCH_NAME.to_string())
+                .unwrap()
+                .commits_since_checkpoint
+        };
+        assert_eq!(commits_since_checkpoint(), 6);
+        let reads_without_checkpoint: u64 = count_merge_reads(&mut user);
+
+        // The new checkpoint starts from the one on the first commit, and covers main's 6 commits
+        let checkpoint: Commit = get_db_instance()
+            .unwrap()
+            .create_checkpoint(&main_branch)
+            .unwrap();
+        assert_eq!(checkpoint.message, "Checkpoint of 6 commits");
+        assert_eq!(commits_since_checkpoint(), 0);
+        let reads_with_checkpoint: u64 = count_merge_reads(&mut user);
+        assert_eq!(reads_without_checkpoint - reads_with_checkpoint, 5);
+
+        // The merge gives the same rows as it would have without the checkpoint
+        get_db_instance()
+            .unwrap()
+            .merge_branches(
+                &"feature".to_string(),
+                &mut user,
+                &"Merge".to_string(),
+                true,
+                MergeConflictResolutionAlgo::NoConflicts,
+                false,
+            )
+            .unwrap();
+        let (_, rows) = execute_query(
+            &parse("SELECT id, name FROM people ORDER BY id", false).unwrap(),
+            &mut user,
+            &"".to_string(),
+        )
+        .unwrap();
+        let expected: Vec<Row> = (1..=8)
+            .map(|id| vec![Value::I64(id), Value::String(format!("p{}", id))])
+            .collect();
+        assert_eq!(rows, expected);
+
+        delete_db_instance().unwrap();
+    }
 }
@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::fileio::{header::*, pageio::*, tableio::*, *};
+use crate::fileio::{header::*, pageio::*, storage::get_storage_backend, tableio::*, *};
 use crate::util::{dbtype::*, row::*};
 
 /// This represents a merged branch. It is a single row in the `merged_branches.gql` table.
@@ -42,7 +42,7 @@ impl MergedBranchesFile {
         }
 
         if create_file {
-            std::fs::File::create(filepath.clone()).map_err(|e| e.to_string())?;
+            get_storage_backend(&filepath).create_file(&filepath)?;
 
             let schema = vec![
                 ("branch_name".to_string(), Column::String(60)),
@@ -53,6 +53,9 @@ impl MergedBranchesFile {
                 num_pages: 2,
                 schema,
                 index_top_level_pages: HashMap::new(),
+                unique_indexes: HashSet::new(),
+                ttl: None,
+                version: 0,
             };
             write_header(&filepath, &header)?;
 
@@ -6,5 +6,6 @@ pub mod command;
 pub mod commit;
 pub mod commitfile;
 pub mod diff;
+pub mod dump;
 pub mod merge;
 pub mod merged_branches;